@@ -0,0 +1,74 @@
+//! Formatting of 100k records in the plain, colored, JSON and CSV outputs, as batch runs
+//! print them
+
+use std::{
+    hint::black_box,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use hickory_client::rr::{
+    rdata::{A, AAAA, CNAME, MX, TXT},
+    Name, RData, Record,
+};
+use resoy::format::{self, OutputConfig, RecordFormatter, TableFormatter};
+
+const RECORDS: u32 = 100_000;
+
+/// Records of a mix of types under as many names, as the answers of a batch run
+fn fixture() -> Vec<Record> {
+    (0..RECORDS)
+        .map(|index| {
+            let name = Name::from_str(&format!("host{}.example.com.", index / 4)).unwrap();
+            let data = match index % 5 {
+                0 => RData::A(A(Ipv4Addr::from(0xc000_0200 + index))),
+                1 => RData::AAAA(AAAA(Ipv6Addr::from(0x2001_0db8_u128 << 96 | index as u128))),
+                2 => RData::CNAME(CNAME(Name::from_str("www.example.com.").unwrap())),
+                3 => RData::MX(MX::new(10, Name::from_str("mail.example.com.").unwrap())),
+                _ => RData::TXT(TXT::new(vec![format!("v=spf1 include:_spf{} -all", index)])),
+            };
+            Record::from_rdata(name, 300 + index % 3600, data)
+        })
+        .collect()
+}
+
+/// Every record into one reused line, as the text output writes them
+fn text(
+    config: &OutputConfig,
+    records: &[&Record],
+) -> usize {
+    let widths = config.column_widths(records);
+    let mut line = String::new();
+    let mut written = 0;
+    for record in records {
+        line.clear();
+        RecordFormatter::new(record, config)
+            .with_widths(widths)
+            .write(&mut line);
+        written += black_box(&line).len();
+    }
+    written
+}
+
+fn formatting(c: &mut Criterion) {
+    let records = fixture();
+    let records: Vec<&Record> = records.iter().collect();
+    let mut group = c.benchmark_group("format");
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.sample_size(10);
+    let plain = OutputConfig::new(true, false);
+    group.bench_function("plain", |b| b.iter(|| text(&plain, &records)));
+    let colored = OutputConfig::new(true, true);
+    group.bench_function("colored", |b| b.iter(|| text(&colored, &records)));
+    group.bench_function("json", |b| {
+        b.iter(|| serde_json::to_string_pretty(&format::json_records(&records)).unwrap())
+    });
+    group.bench_function("csv", |b| {
+        b.iter(|| TableFormatter::Csv.format(&records, &OutputConfig::new(true, false)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, formatting);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+//! Responses as a server could send them, through the parsing of resoy and every output mode
+//! of their records
+
+#![no_main]
+
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use hickory_client::{
+    op::{DnsResponse, Message},
+    rr::{Name, Record, RecordType},
+};
+use libfuzzer_sys::fuzz_target;
+use resoy::{
+    env,
+    format::{self, OutputConfig, RecordFormatter, TableFormatter, Template},
+    salvage, schema,
+    widerow::Row,
+};
+
+const TEMPLATE: &str = "{color}{name}\t{type} {class} {ttl}/{ttl_human} {data} @{server}{reset}";
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = Message::from_vec(data) {
+        output(&message, data);
+    }
+    // What salvaging still makes of the responses that fail to parse
+    if let Ok((message, _)) = salvage::parse_lenient(data) {
+        output(&message, data);
+    }
+});
+
+fn output(
+    message: &Message,
+    bytes: &[u8],
+) {
+    let records: Vec<&Record> = message
+        .answers()
+        .iter()
+        .chain(message.name_servers())
+        .chain(message.additionals())
+        .collect();
+
+    for (format_seconds, ansi) in [(false, false), (true, true)] {
+        let config = OutputConfig::new(format_seconds, ansi)
+            .with_ascii_names(ansi)
+            .with_txt_concat(format_seconds);
+        let widths = config.column_widths(&records);
+        for record in &records {
+            RecordFormatter::new(record, &config)
+                .with_widths(widths)
+                .format();
+        }
+        let template = Template::parse(TEMPLATE, "192.0.2.53").unwrap();
+        let config = config.with_template(Some(template));
+        for record in &records {
+            RecordFormatter::new(record, &config).format();
+        }
+        TableFormatter::Csv.format(&records, &config);
+        TableFormatter::Tsv.format(&records, &config);
+        format::format_short(&records, ansi, format_seconds);
+    }
+
+    let json = schema::lookup(format::json_records(&records), Vec::new());
+    serde_json::to_string_pretty(&json).unwrap();
+    env::assignments("DNS", &[RecordType::A, RecordType::TXT], &records);
+
+    format::format_flags(message);
+    format::format_nsid(message);
+    if let Some(edns) = message.extensions() {
+        format::format_edns(edns);
+    }
+
+    let name = message
+        .queries()
+        .first()
+        .map(|query| query.name().clone())
+        .unwrap_or_else(|| Name::from_str("fuzz.example.").unwrap());
+    let row = Row {
+        timestamp: SystemTime::UNIX_EPOCH,
+        name: &name,
+        record_type: RecordType::A,
+        result: Ok(DnsResponse::new(message.clone(), bytes.to_vec())),
+        server: "127.0.0.1",
+        latency: Duration::from_millis(1),
+    };
+    row.fields();
+}
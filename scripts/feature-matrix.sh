@@ -0,0 +1,12 @@
+#!/bin/sh
+# Build, lint and test resoy under every combination of its cargo features, so that none of
+# them breaks the others. Extra arguments are passed to every cargo invocation.
+set -eu
+
+for features in "" doh tls dnssec tsig doh,tls doh,tls,dnssec doh,tls,dnssec,tsig minimal; do
+    echo "== features: ${features:-none}" >&2
+    cargo clippy --no-default-features --features "$features" --all-targets "$@" -- -D warnings
+    cargo test --no-default-features --features "$features" "$@"
+    # clap checks the consistency of the options of debug builds when they are parsed
+    cargo run --quiet --no-default-features --features "$features" "$@" -- --help >/dev/null
+done
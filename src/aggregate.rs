@@ -0,0 +1,349 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    net::IpAddr,
+    path::Path,
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+use hickory_client::rr::{Name, RData, Record};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    asn::{self, Origin},
+    format::escape_csv,
+    schema::SCHEMA,
+    AppError,
+};
+
+/// Labels under which names are registered in country code domains, as there is no public
+/// suffix list to take them from: a name under one of them is registered a label below
+const SECOND_LEVEL_SUFFIXES: &[&str] = &[
+    "ac.uk", "co.uk", "gov.uk", "org.uk", "com.au", "net.au", "org.au", "co.nz", "co.jp", "ne.jp",
+    "or.jp", "com.br", "com.cn", "com.hk", "co.in", "co.kr", "com.mx", "com.sg", "com.tr", "co.za",
+];
+
+/// What the answers of `--aggregate` are counted by
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+pub enum AggregateBy {
+    /// the data of the records
+    Data,
+    /// the autonomous system announcing the addresses of A and AAAA records
+    Asn,
+    /// the registrable domain of the names that records point at, e.g. of CNAME, MX or NS
+    RegistrableDomain,
+}
+
+/// Registrable domain of `name`: its last two labels, or three under a country code domain
+/// of `SECOND_LEVEL_SUFFIXES`
+pub fn registrable_domain(name: &Name) -> String {
+    let ascii = name.to_ascii().trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = ascii.split('.').collect();
+    let suffix = labels[labels.len().saturating_sub(2)..].join(".");
+    let keep = match SECOND_LEVEL_SUFFIXES.contains(&suffix.as_str()) {
+        true => 3,
+        false => 2,
+    };
+    labels[labels.len().saturating_sub(keep)..].join(".")
+}
+
+/// Name a record points at, for the types that point at one
+fn target(record: &Record) -> Option<&Name> {
+    match record.data()? {
+        RData::CNAME(cname) => Some(&cname.0),
+        RData::NS(ns) => Some(&ns.0),
+        RData::PTR(ptr) => Some(&ptr.0),
+        RData::MX(mx) => Some(mx.exchange()),
+        RData::SRV(srv) => Some(srv.target()),
+        _ => None,
+    }
+}
+
+/// Value `record` is counted under, `None` for records that `by` doesn't count, `origins`
+/// being those of `asn::annotate`
+fn value(
+    by: AggregateBy,
+    record: &Record,
+    origins: &HashMap<IpAddr, Origin>,
+) -> Option<String> {
+    match by {
+        AggregateBy::Data => Some(record.data()?.to_string()),
+        AggregateBy::Asn => {
+            let address = asn::address_of(record)?;
+            Some(match origins.get(&address) {
+                Some(Origin {
+                    asn,
+                    network: Some(network),
+                    ..
+                }) => format!("AS{} {}", asn, network),
+                Some(origin) => format!("AS{}", origin.asn),
+                None => String::from("unknown"),
+            })
+        },
+        AggregateBy::RegistrableDomain => target(record).map(registrable_domain),
+    }
+}
+
+/// How many names were answered with a value
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Row {
+    #[serde(rename = "type")]
+    pub record_type: String,
+    pub value: String,
+    pub names: usize,
+}
+
+/// Counts of the values the names of a batch were answered with, a counter per distinct value
+/// so that it grows with them rather than with the input
+pub struct Aggregate {
+    by: AggregateBy,
+    counts: Mutex<Counts>,
+}
+
+#[derive(Default)]
+struct Counts {
+    names: usize,
+    /// how many names got every value, by type and value
+    values: HashMap<(String, String), usize>,
+}
+
+impl Aggregate {
+    pub fn new(by: AggregateBy) -> Self {
+        Self {
+            by,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    /// Count the values of `answers` of a name, every value once however many of its records
+    /// have it
+    pub fn add(
+        &self,
+        answers: &[&Record],
+        origins: &HashMap<IpAddr, Origin>,
+    ) {
+        let values: HashSet<(String, String)> = answers
+            .iter()
+            .filter_map(|record| {
+                Some((
+                    record.record_type().to_string(),
+                    value(self.by, record, origins)?,
+                ))
+            })
+            .collect();
+        let mut counts = self.counts.lock().unwrap();
+        counts.names += 1;
+        for value in values {
+            *counts.values.entry(value).or_default() += 1;
+        }
+    }
+
+    /// How many names were counted
+    pub fn names(&self) -> usize {
+        self.counts.lock().unwrap().names
+    }
+
+    /// Every value with how many names got it, by type and then from the most common down
+    pub fn rows(&self) -> Vec<Row> {
+        let counts = self.counts.lock().unwrap();
+        let mut rows: Vec<Row> = counts
+            .values
+            .iter()
+            .map(|((record_type, value), names)| Row {
+                record_type: record_type.clone(),
+                value: value.clone(),
+                names: *names,
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            (&a.record_type, b.names, &a.value).cmp(&(&b.record_type, a.names, &b.value))
+        });
+        rows
+    }
+
+    /// The table as the text output prints it after the names
+    pub fn lines(&self) -> Vec<String> {
+        let rows = self.rows();
+        let type_width = rows
+            .iter()
+            .map(|row| row.record_type.len())
+            .max()
+            .unwrap_or(0);
+        let count_width = rows
+            .iter()
+            .map(|row| row.names.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let by = self.by.to_possible_value().unwrap();
+        let mut lines = vec![format!(";; {} names by {}", self.names(), by.get_name())];
+        lines.extend(rows.iter().map(|row| {
+            format!(
+                "{:<type_width$} {:>count_width$} names  {}",
+                row.record_type,
+                row.names,
+                row.value,
+                type_width = type_width,
+                count_width = count_width
+            )
+        }));
+        lines
+    }
+
+    /// The table as JSON, in the `aggregate` schema
+    pub fn json(&self) -> Value {
+        let by = self.by.to_possible_value().unwrap();
+        json!({
+            "schema": SCHEMA,
+            "aggregate": {
+                "by": by.get_name(),
+                "names": self.names(),
+                "values": self.rows(),
+            },
+        })
+    }
+
+    /// The table as CSV, a `type,value,names` row per value under a header
+    pub fn csv(&self) -> String {
+        let mut csv = String::from("type,value,names\n");
+        for row in self.rows() {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                escape_csv(&row.record_type),
+                escape_csv(&row.value),
+                row.names
+            ));
+        }
+        csv
+    }
+
+    /// Write the table into `path`, as JSON when it ends with `.json` and as CSV otherwise
+    pub fn write(
+        &self,
+        path: &Path,
+    ) -> Result<(), AppError> {
+        let content = match path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            true => format!("{}\n", serde_json::to_string_pretty(&self.json()).unwrap()),
+            false => self.csv(),
+        };
+        fs::write(path, content)
+            .map_err(|error| AppError::AggregateError(path.to_owned(), error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::rr::rdata::{A, CNAME, MX};
+
+    use super::*;
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn address(
+        owner: &str,
+        last: u8,
+    ) -> Record {
+        Record::from_rdata(name(owner), 300, RData::A(A::new(203, 0, 113, last)))
+    }
+
+    #[test]
+    fn names_are_counted_once_per_value() {
+        let aggregate = Aggregate::new(AggregateBy::Data);
+        for host in 0..5 {
+            let owner = format!("host{}.example.com.", host);
+            let last = if host < 4 { 10 } else { 11 };
+            // A name answered twice with the same address counts once
+            let records = [address(&owner, last), address(&owner, last)];
+            let answers: Vec<&Record> = records.iter().collect();
+            aggregate.add(&answers, &HashMap::new());
+        }
+        let mx = Record::from_rdata(
+            name("example.com."),
+            300,
+            RData::MX(MX::new(10, name("mail.example.com."))),
+        );
+        aggregate.add(&[&mx], &HashMap::new());
+        assert_eq!(
+            aggregate.lines(),
+            [
+                ";; 6 names by data",
+                "A  4 names  203.0.113.10",
+                "A  1 names  203.0.113.11",
+                "MX 1 names  10 mail.example.com.",
+            ]
+        );
+        assert_eq!(
+            aggregate.csv(),
+            "type,value,names\nA,203.0.113.10,4\nA,203.0.113.11,1\nMX,10 mail.example.com.,1\n"
+        );
+        assert_eq!(aggregate.json()["aggregate"]["values"][0]["names"], 4);
+    }
+
+    #[test]
+    fn addresses_are_counted_by_their_autonomous_system() {
+        let aggregate = Aggregate::new(AggregateBy::Asn);
+        let origin = Origin {
+            asn: 64500,
+            prefix: String::from("203.0.113.0/24"),
+            country: String::from("ZZ"),
+            network: Some(String::from("EXAMPLE-NET")),
+        };
+        let origins = HashMap::from([(IpAddr::from([203, 0, 113, 10]), origin)]);
+        let records = [address("a.example.", 10), address("b.example.", 99)];
+        for record in &records {
+            aggregate.add(&[record], &origins);
+        }
+        let rows: Vec<(String, usize)> = aggregate
+            .rows()
+            .into_iter()
+            .map(|row| (row.value, row.names))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (String::from("AS64500 EXAMPLE-NET"), 1),
+                (String::from("unknown"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn targets_are_counted_by_their_registrable_domain() {
+        assert_eq!(
+            registrable_domain(&name("d1.cdn.Example.NET.")),
+            "example.net"
+        );
+        assert_eq!(registrable_domain(&name("www.shop.co.uk.")), "shop.co.uk");
+        assert_eq!(registrable_domain(&name("com.")), "com");
+        let aggregate = Aggregate::new(AggregateBy::RegistrableDomain);
+        for host in ["a", "b"] {
+            let cname = Record::from_rdata(
+                name(&format!("{}.example.com.", host)),
+                300,
+                RData::CNAME(CNAME(name(&format!("{}.edge.cdn.example.net.", host)))),
+            );
+            // Addresses have no name to count
+            let records = [cname, address("a.edge.cdn.example.net.", 1)];
+            let answers: Vec<&Record> = records.iter().collect();
+            aggregate.add(&answers, &HashMap::new());
+        }
+        assert_eq!(
+            aggregate.rows(),
+            [Row {
+                record_type: String::from("CNAME"),
+                value: String::from("example.net"),
+                names: 2,
+            }]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use hickory_client::{
+    op::{Message, ResponseCode},
+    rr::{DNSClass, Name, RData, Record, RecordType},
+};
+use serde_json::{json, Value};
+
+use crate::clock::{Clock, SystemClock};
+
+/// How long SERVFAIL answers are kept, they come with no TTL of their own
+const SERVFAIL_TTL: u32 = 30;
+
+/// The answers are split into up to this many independently locked shards, so that the
+/// handlers of concurrent queries rarely wait for each other
+const SHARDS: usize = 16;
+
+/// Answers of a shard at the least, so that small caches drop exactly the answers used least
+/// recently rather than those of whichever shard is full
+const MIN_PER_SHARD: usize = 64;
+
+/// Negative outcomes that `--no-cache-rcode` can keep out of the cache
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+#[clap(rename_all = "kebab_case")]
+pub enum Negative {
+    /// the name does not exist, kept as long as the SOA of the answer tells
+    Nxdomain,
+    /// the name exists without records of the type, kept as long as the SOA of the answer
+    /// tells
+    Nodata,
+    /// the server failed, kept for 30 seconds
+    Servfail,
+}
+
+/// Question of a query, and its DO and CD bits, which change the answer: with signatures, and
+/// with the records that fail validation
+type Key = (Name, RecordType, DNSClass, bool, bool);
+
+struct Entry {
+    message: Message,
+    stored: Instant,
+    lifetime: Duration,
+    /// when the answer was last used, as a position in the `order` of its shard
+    used: u64,
+}
+
+/// Answers of one shard, and their keys from the least recently used on
+#[derive(Default)]
+struct Shard {
+    entries: HashMap<Key, Entry>,
+    order: BTreeMap<u64, Key>,
+}
+
+impl Shard {
+    fn remove(
+        &mut self,
+        key: &Key,
+    ) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.used);
+        Some(entry)
+    }
+
+    /// Drop the answer used least recently
+    fn evict(&mut self) -> bool {
+        let Some((_, key)) = self.order.pop_first() else {
+            return false;
+        };
+        self.entries.remove(&key);
+        true
+    }
+}
+
+/// Answers of the proxy kept for as long as their TTLs tell, dropping those used least
+/// recently once there are too many
+pub struct AnswerCache {
+    shards: Vec<Mutex<Shard>>,
+    per_shard: usize,
+    /// source of the positions of the answers in the order of their use
+    uses: AtomicU64,
+    clock: Arc<dyn Clock>,
+    min_ttl: u32,
+    uncached: Vec<Negative>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl AnswerCache {
+    pub fn new(
+        capacity: usize,
+        min_ttl: u32,
+        uncached: Vec<Negative>,
+    ) -> Self {
+        let capacity = capacity.max(1);
+        let shards = (capacity / MIN_PER_SHARD).clamp(1, SHARDS);
+        Self {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            per_shard: capacity.div_ceil(shards),
+            uses: AtomicU64::new(0),
+            clock: Arc::new(SystemClock),
+            min_ttl,
+            uncached,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Tell the age of the answers with `clock` rather than the time of the system
+    pub fn with_clock(
+        mut self,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn shard(
+        &self,
+        key: &Key,
+    ) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// The cached answer to `request`, its TTLs lowered by the time it was kept
+    pub fn get(
+        &self,
+        request: &Message,
+    ) -> Option<Message> {
+        let key = key(request)?;
+        let now = self.clock.now();
+        let mut shard = self.shard(&key).lock().unwrap();
+        let fresh = shard
+            .entries
+            .get(&key)
+            .is_some_and(|entry| now.saturating_duration_since(entry.stored) < entry.lifetime);
+        if !fresh {
+            shard.remove(&key);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let mut entry = shard.remove(&key)?;
+        entry.used = self.uses.fetch_add(1, Ordering::Relaxed);
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        let age = now.saturating_duration_since(entry.stored).as_secs() as u32;
+        let mut message = entry.message.clone();
+        map_ttls(&mut message, |ttl| ttl.saturating_sub(age));
+        shard.order.insert(entry.used, key.clone());
+        shard.entries.insert(key, entry);
+        Some(message)
+    }
+
+    /// Keep the answer of the server to `request`, unless it is truncated or its outcome is
+    /// not to be cached
+    pub fn insert(
+        &self,
+        request: &Message,
+        mut message: Message,
+    ) {
+        let Some(key) = key(request) else {
+            return;
+        };
+        if message.truncated() {
+            return;
+        }
+        map_ttls(&mut message, |ttl| ttl.max(self.min_ttl));
+        let Some(ttl) = self.lifetime(&message) else {
+            return;
+        };
+        if ttl == 0 {
+            return;
+        }
+
+        let mut shard = self.shard(&key).lock().unwrap();
+        if shard.remove(&key).is_none() && shard.entries.len() >= self.per_shard && shard.evict() {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        let used = self.uses.fetch_add(1, Ordering::Relaxed);
+        shard.order.insert(used, key.clone());
+        shard.entries.insert(
+            key,
+            Entry {
+                message,
+                stored: self.clock.now(),
+                lifetime: Duration::from_secs(ttl.into()),
+                used,
+            },
+        );
+    }
+
+    /// Seconds to keep `message` for, `None` for those not to be cached
+    fn lifetime(
+        &self,
+        message: &Message,
+    ) -> Option<u32> {
+        let negative = match message.response_code() {
+            ResponseCode::NoError if !message.answers().is_empty() => {
+                return message.answers().iter().map(Record::ttl).min();
+            },
+            ResponseCode::NoError => Negative::Nodata,
+            ResponseCode::NXDomain => Negative::Nxdomain,
+            ResponseCode::ServFail => Negative::Servfail,
+            _ => return None,
+        };
+        if self.uncached.contains(&negative) {
+            return None;
+        }
+        match negative {
+            Negative::Servfail => Some(SERVFAIL_TTL),
+            // RFC 2308, negative answers are kept for the lower of the TTL and the minimum
+            _ => message
+                .name_servers()
+                .iter()
+                .find_map(|record| match record.data() {
+                    Some(RData::SOA(soa)) => Some(record.ttl().min(soa.minimum())),
+                    _ => None,
+                })
+                .map(|ttl| ttl.max(self.min_ttl)),
+        }
+    }
+
+    /// The counters and the number of answers kept
+    pub fn to_json(&self) -> Value {
+        json!({
+            "entries": self
+                .shards
+                .iter()
+                .map(|shard| shard.lock().unwrap().entries.len())
+                .sum::<usize>(),
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed),
+            "evictions": self.evictions.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Start the counters over, keeping the answers
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+    }
+}
+
+fn key(request: &Message) -> Option<Key> {
+    let query = request.queries().first()?;
+    let dnssec_ok = request
+        .extensions()
+        .as_ref()
+        .is_some_and(|edns| edns.dnssec_ok());
+    Some((
+        query.name().to_lowercase(),
+        query.query_type(),
+        query.query_class(),
+        dnssec_ok,
+        request.checking_disabled(),
+    ))
+}
+
+/// Change the TTLs of the records of every section of `message` with `map`
+fn map_ttls(
+    message: &mut Message,
+    map: impl Fn(u32) -> u32,
+) {
+    let map = |records: &mut Vec<Record>| {
+        for record in records {
+            record.set_ttl(map(record.ttl()));
+        }
+    };
+    map(message.answers_mut());
+    map(message.name_servers_mut());
+    map(message.additionals_mut());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::{
+        op::{Edns, MessageType, Query},
+        rr::rdata::{A, SOA},
+    };
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn request(name: &str) -> Message {
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Query)
+            .add_query(Query::query(Name::from_str(name).unwrap(), RecordType::A));
+        message
+    }
+
+    fn answer(request: &Message) -> Message {
+        let mut message = request.clone();
+        message
+            .set_message_type(MessageType::Response)
+            .add_answer(Record::from_rdata(
+                request.queries()[0].name().clone(),
+                300,
+                RData::A(A::new(192, 0, 2, 1)),
+            ));
+        message
+    }
+
+    /// Answer of `response_code` without records, with an SOA of `ttl` and `minimum`
+    fn negative(
+        request: &Message,
+        response_code: ResponseCode,
+        ttl: u32,
+        minimum: u32,
+    ) -> Message {
+        let mut message = request.clone();
+        let zone = Name::from_str("example.com.").unwrap();
+        let soa = SOA::new(zone.clone(), zone.clone(), 1, 7200, 3600, 1209600, minimum);
+        message
+            .set_message_type(MessageType::Response)
+            .set_response_code(response_code)
+            .add_name_server(Record::from_rdata(zone, ttl, RData::SOA(soa)));
+        message
+    }
+
+    fn clocked(
+        capacity: usize,
+        min_ttl: u32,
+        uncached: Vec<Negative>,
+    ) -> (AnswerCache, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let cache = AnswerCache::new(capacity, min_ttl, uncached).with_clock(clock.clone());
+        (cache, clock)
+    }
+
+    fn counter(
+        cache: &AnswerCache,
+        name: &str,
+    ) -> u64 {
+        cache.to_json()[name].as_u64().unwrap()
+    }
+
+    #[test]
+    fn answers_are_kept_apart_by_the_do_and_cd_bits_of_the_request() {
+        let (cache, _) = clocked(10, 0, Vec::new());
+        let plain = request("example.com.");
+        cache.insert(&plain, answer(&plain));
+
+        let mut checking_disabled = plain.clone();
+        checking_disabled.set_checking_disabled(true);
+        let mut dnssec_ok = plain.clone();
+        let mut edns = Edns::new();
+        edns.set_dnssec_ok(true);
+        dnssec_ok.set_edns(edns);
+
+        assert!(cache.get(&checking_disabled).is_none());
+        assert!(cache.get(&dnssec_ok).is_none());
+        assert!(cache.get(&request("EXAMPLE.com.")).is_some());
+    }
+
+    #[test]
+    fn answers_expire_with_their_lowest_ttl_and_are_served_with_it_lowered() {
+        let (cache, clock) = clocked(10, 0, Vec::new());
+        let request = request("example.com.");
+        let mut answer = answer(&request);
+        answer.add_answer(Record::from_rdata(
+            request.queries()[0].name().clone(),
+            60,
+            RData::A(A::new(192, 0, 2, 2)),
+        ));
+        cache.insert(&request, answer);
+
+        clock.advance(Duration::from_secs(59));
+        let cached = cache.get(&request).unwrap();
+        let ttls: Vec<u32> = cached.answers().iter().map(Record::ttl).collect();
+        assert_eq!(ttls, [241, 1]);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&request).is_none());
+        assert_eq!(counter(&cache, "entries"), 0);
+        assert_eq!((counter(&cache, "hits"), counter(&cache, "misses")), (1, 1));
+    }
+
+    #[test]
+    fn negative_answers_are_kept_for_the_lower_of_the_soa_ttl_and_minimum() {
+        let (cache, clock) = clocked(10, 0, Vec::new());
+        let nxdomain = request("nope.example.com.");
+        cache.insert(
+            &nxdomain,
+            negative(&nxdomain, ResponseCode::NXDomain, 3600, 120),
+        );
+        let nodata = request("example.com.");
+        cache.insert(&nodata, negative(&nodata, ResponseCode::NoError, 30, 300));
+
+        clock.advance(Duration::from_secs(29));
+        assert!(cache.get(&nodata).is_some());
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&nodata).is_none());
+        clock.advance(Duration::from_secs(89));
+        assert!(cache.get(&nxdomain).is_some());
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&nxdomain).is_none());
+    }
+
+    #[test]
+    fn servfail_is_kept_for_thirty_seconds_unless_it_is_not_to_be_cached() {
+        let (cache, clock) = clocked(10, 0, Vec::new());
+        let request = request("example.com.");
+        let mut servfail = request.clone();
+        servfail
+            .set_message_type(MessageType::Response)
+            .set_response_code(ResponseCode::ServFail);
+        cache.insert(&request, servfail.clone());
+        clock.advance(Duration::from_secs(29));
+        assert!(cache.get(&request).is_some());
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&request).is_none());
+
+        let (cache, _) = clocked(10, 0, vec![Negative::Servfail]);
+        cache.insert(&request, servfail);
+        assert!(cache.get(&request).is_none());
+    }
+
+    #[test]
+    fn the_minimum_ttl_keeps_answers_longer_and_raises_their_ttls() {
+        let (cache, clock) = clocked(10, 600, Vec::new());
+        let request = request("example.com.");
+        cache.insert(&request, answer(&request));
+        clock.advance(Duration::from_secs(599));
+        assert_eq!(cache.get(&request).unwrap().answers()[0].ttl(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&request).is_none());
+    }
+
+    #[test]
+    fn truncated_answers_and_those_without_ttl_are_not_kept() {
+        let (cache, _) = clocked(10, 0, Vec::new());
+        let request = request("example.com.");
+        let mut truncated = answer(&request);
+        truncated.set_truncated(true);
+        cache.insert(&request, truncated);
+        let mut expired = answer(&request);
+        expired.answers_mut()[0].set_ttl(0);
+        cache.insert(&request, expired);
+        assert!(cache.get(&request).is_none());
+    }
+
+    #[test]
+    fn the_answers_used_least_recently_are_dropped_first() {
+        let (cache, _) = clocked(2, 0, Vec::new());
+        let [a, b, c] = ["a.example.", "b.example.", "c.example."].map(request);
+        cache.insert(&a, answer(&a));
+        cache.insert(&b, answer(&b));
+        assert!(cache.get(&a).is_some());
+        cache.insert(&c, answer(&c));
+
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&c).is_some());
+        assert_eq!(counter(&cache, "evictions"), 1);
+
+        // Answering a question again replaces its answer rather than dropping another one
+        cache.insert(&c, answer(&c));
+        assert_eq!(counter(&cache, "entries"), 2);
+        assert_eq!(counter(&cache, "evictions"), 1);
+    }
+
+    #[test]
+    fn large_caches_keep_about_as_many_answers_as_asked_over_their_shards() {
+        let (cache, _) = clocked(1000, 0, Vec::new());
+        for index in 0..5000 {
+            let request = request(&format!("host{}.example.", index));
+            cache.insert(&request, answer(&request));
+        }
+        let entries = counter(&cache, "entries");
+        assert!((900..=1008).contains(&entries), "{}", entries);
+        assert_eq!(counter(&cache, "evictions"), 5000 - entries);
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashMap, net::IpAddr, str::FromStr, thread, time::Duration};
+
+use hickory_client::rr::{DNSClass, Name, RData, Record, RecordType};
+use serde::Serialize;
+
+use crate::DnsClient;
+
+/// Timeout of every lookup of `--asn`
+pub const ASN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Origin of the route covering an address, as served by the Team Cymru DNS interface
+#[derive(Clone, Serialize)]
+pub struct Origin {
+    pub asn: u32,
+    pub prefix: String,
+    pub country: String,
+    /// name of the autonomous system, if it could be looked up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+}
+
+impl Origin {
+    /// Suffix annotating an address in plain output
+    pub fn annotation(&self) -> String {
+        match &self.network {
+            Some(network) => format!("  [AS{} {}]", self.asn, network),
+            None => format!("  [AS{} {} {}]", self.asn, self.prefix, self.country),
+        }
+    }
+}
+
+/// Address of an A or AAAA record
+pub fn address_of(record: &Record) -> Option<IpAddr> {
+    match record.data() {
+        Some(RData::A(address)) => Some(IpAddr::V4(address.0)),
+        Some(RData::AAAA(address)) => Some(IpAddr::V6(address.0)),
+        _ => None,
+    }
+}
+
+/// Routes are announced no more specific than a /24 or a /48, so one lookup covers them all
+fn prefix_key(address: IpAddr) -> (IpAddr, u8) {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, c, _] = address.octets();
+            (IpAddr::from([a, b, c, 0]), 24)
+        },
+        IpAddr::V6(address) => {
+            let bits = u128::from(address) & !(u128::MAX >> 48);
+            (IpAddr::from(bits.to_be_bytes()), 48)
+        },
+    }
+}
+
+fn origin_query(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, c, d] = address.octets();
+            format!("{}.{}.{}.{}.origin.asn.cymru.com.", d, c, b, a)
+        },
+        IpAddr::V6(address) => {
+            let nibbles: Vec<String> = format!("{:032x}", u128::from(address))
+                .chars()
+                .rev()
+                .map(String::from)
+                .collect();
+            format!("{}.origin6.asn.cymru.com.", nibbles.join("."))
+        },
+    }
+}
+
+/// Fields of the first TXT answer, split at `|`
+fn query_fields(
+    client: &DnsClient,
+    name: &str,
+) -> Option<Vec<String>> {
+    let name = Name::from_str(name).ok()?;
+    let response = client.query(&name, DNSClass::IN, RecordType::TXT).ok()?;
+    response
+        .answers()
+        .iter()
+        .find_map(|record| match record.data() {
+            Some(RData::TXT(txt)) => {
+                let text: String = txt
+                    .txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data))
+                    .collect();
+                Some(
+                    text.split('|')
+                        .map(|field| field.trim().to_owned())
+                        .collect(),
+                )
+            },
+            _ => None,
+        })
+}
+
+/// Query `lookup` for every key concurrently
+fn query_all<K: Copy + Send + Sync + std::hash::Hash + Eq, V: Send>(
+    keys: Vec<K>,
+    lookup: impl Fn(K) -> Option<V> + Sync,
+) -> HashMap<K, V> {
+    let lookup = &lookup;
+    thread::scope(|scope| {
+        let handles: Vec<_> = keys
+            .into_iter()
+            .map(|key| scope.spawn(move || (key, lookup(key))))
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| match handle.join() {
+                Ok((key, Some(value))) => Some((key, value)),
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+/// Origins of the addresses in `answers`, one lookup per prefix and per autonomous system.
+///
+/// Addresses whose lookups failed are left out, so they are printed bare.
+pub fn annotate(
+    client: &DnsClient,
+    answers: &[&Record],
+) -> HashMap<IpAddr, Origin> {
+    let addresses: Vec<IpAddr> = answers
+        .iter()
+        .filter_map(|record| address_of(record))
+        .collect();
+
+    // The first address of every prefix is looked up for all of them
+    let mut representatives: HashMap<(IpAddr, u8), IpAddr> = HashMap::new();
+    for address in &addresses {
+        representatives
+            .entry(prefix_key(*address))
+            .or_insert(*address);
+    }
+    let origins = query_all(representatives.values().copied().collect(), |address| {
+        let fields = query_fields(client, &origin_query(address))?;
+        Some(Origin {
+            asn: fields.first()?.split_whitespace().next()?.parse().ok()?,
+            prefix: fields.get(1)?.clone(),
+            country: fields.get(2)?.clone(),
+            network: None,
+        })
+    });
+
+    let mut asns: Vec<u32> = origins.values().map(|origin| origin.asn).collect();
+    asns.sort_unstable();
+    asns.dedup();
+    let networks = query_all(asns, |asn| {
+        let fields = query_fields(client, &format!("AS{}.asn.cymru.com.", asn))?;
+        fields.get(4).cloned()
+    });
+
+    addresses
+        .into_iter()
+        .filter_map(|address| {
+            let representative = representatives.get(&prefix_key(address))?;
+            let mut origin = origins.get(representative)?.clone();
+            origin.network = networks.get(&origin.asn).cloned();
+            Some((address, origin))
+        })
+        .collect()
+}
@@ -0,0 +1,500 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use clap::ValueEnum;
+use hickory_client::rr::{Name, RData, Record};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{outln, schema, AppError};
+
+/// TXT records longer than this are flagged as oversized
+const OVERSIZED_LENGTH: usize = 512;
+
+/// Minimum length of a token to be considered a blob
+const BLOB_MIN_LENGTH: usize = 32;
+
+/// Minimum Shannon entropy in bits per character of a blob
+const BLOB_MIN_ENTROPY: f64 = 4.0;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditFormat {
+    Plain,
+    Jsonl,
+}
+
+/// (label, owner name pattern, value pattern) of the built-in classification rules
+const BUILTIN_RULES: &[(&str, &str, &str)] = &[
+    ("spf", "", r"^v=spf1(\s|$)"),
+    ("dmarc", "", r"^v=DMARC1\s*;"),
+    ("dkim", "", r"^v=DKIM1\s*;"),
+    ("dkim", r"\._domainkey\.", r"(^|;)\s*p="),
+    ("bimi", "", r"^v=BIMI1\s*;"),
+    ("mta-sts", "", r"^v=STSv1\s*;"),
+    ("tls-rpt", "", r"^v=TLSRPTv1\s*;"),
+    (
+        "acme-challenge",
+        r"^_acme-challenge\.",
+        r"^[A-Za-z0-9_-]{43}$",
+    ),
+    (
+        "google-site-verification",
+        "",
+        r"^google-site-verification=",
+    ),
+    ("ms-verification", "", r"^MS=ms[0-9]+$"),
+    (
+        "facebook-domain-verification",
+        "",
+        r"^facebook-domain-verification=",
+    ),
+    (
+        "apple-domain-verification",
+        "",
+        r"^apple-domain-verification=",
+    ),
+    (
+        "atlassian-domain-verification",
+        "",
+        r"^atlassian-domain-verification=",
+    ),
+    ("docusign", "", r"^docusign="),
+    (
+        "globalsign-domain-verification",
+        "",
+        r"^_?globalsign-domain-verification=",
+    ),
+    ("stripe-verification", "", r"^stripe-verification="),
+];
+
+struct Rule {
+    label: String,
+    name: Option<Regex>,
+    value: Regex,
+}
+
+/// Classification rules for `--audit-txt`
+pub struct Auditor {
+    rules: Vec<Rule>,
+}
+
+/// Classifications of a single TXT record
+#[derive(Serialize)]
+pub struct Finding {
+    pub name: String,
+    pub text: String,
+    pub length: usize,
+    pub classes: Vec<String>,
+    /// decoded length of a base64-looking high-entropy blob
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_length: Option<usize>,
+}
+
+impl Auditor {
+    /// Built-in rules, followed by the rules of `rules_file` if given.
+    ///
+    /// Every non-empty line of the file not starting with `#` is `label = regex`, matched
+    /// against the concatenated text of the record.
+    pub fn new(rules_file: Option<&Path>) -> Result<Self, AppError> {
+        let mut rules: Vec<Rule> = BUILTIN_RULES
+            .iter()
+            .map(|(label, name, value)| Rule {
+                label: label.to_string(),
+                name: (!name.is_empty()).then(|| Regex::new(name).unwrap()),
+                value: Regex::new(value).unwrap(),
+            })
+            .collect();
+
+        if let Some(path) = rules_file {
+            let error = |message: String| AppError::AuditRulesError(path.to_owned(), message);
+            let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+            for (index, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (label, pattern) = line.split_once('=').ok_or_else(|| {
+                    error(format!("line {}: expected `label = regex`", index + 1))
+                })?;
+                let value = Regex::new(pattern.trim())
+                    .map_err(|e| error(format!("line {}: {}", index + 1, e)))?;
+                rules.push(Rule {
+                    label: label.trim().to_owned(),
+                    name: None,
+                    value,
+                });
+            }
+        }
+
+        Ok(Self {
+            rules,
+        })
+    }
+
+    pub fn audit(
+        &self,
+        record: &Record,
+    ) -> Option<Finding> {
+        let Some(RData::TXT(txt)) = record.data() else {
+            return None;
+        };
+        let text: String = txt
+            .txt_data()
+            .iter()
+            .map(|data| String::from_utf8_lossy(data))
+            .collect();
+        let name = record.name().to_ascii();
+
+        let mut classes: Vec<String> = Vec::new();
+        for rule in &self.rules {
+            let name_matches = rule.name.as_ref().is_none_or(|re| re.is_match(&name));
+            if name_matches && rule.value.is_match(&text) && !classes.contains(&rule.label) {
+                classes.push(rule.label.clone());
+            }
+        }
+
+        let blob_length = find_blob(&text);
+        if blob_length.is_some() {
+            classes.push(String::from("high-entropy-blob"));
+        }
+        if text.len() > OVERSIZED_LENGTH {
+            classes.push(String::from("oversized"));
+        }
+        if classes.is_empty() {
+            classes.push(String::from("unclassified"));
+        }
+
+        Some(Finding {
+            name,
+            length: text.len(),
+            text,
+            classes,
+            blob_length,
+        })
+    }
+}
+
+/// Decoded length of the longest base64-looking token with high entropy
+fn find_blob(text: &str) -> Option<usize> {
+    text.split(|c: char| c.is_whitespace() || c == ';')
+        .map(|token| token.trim_end_matches('='))
+        // Values of tags such as the public key in `p=...` of DKIM records
+        .map(|token| token.split_once('=').map_or(token, |(_, value)| value))
+        .filter(|token| {
+            token.len() >= BLOB_MIN_LENGTH
+                && token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_'))
+                && entropy(token) >= BLOB_MIN_ENTROPY
+        })
+        .map(|token| token.len() * 3 / 4)
+        .max()
+}
+
+fn entropy(token: &str) -> f64 {
+    let mut counts: BTreeMap<char, usize> = BTreeMap::new();
+    for c in token.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let length = token.chars().count() as f64;
+    counts
+        .values()
+        .map(|count| {
+            let p = *count as f64 / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Print the findings and a summary of the classes per domain
+pub fn report(
+    name: &Name,
+    findings: &[Finding],
+    format: AuditFormat,
+) {
+    let mut summary: BTreeMap<&str, usize> = BTreeMap::new();
+    for finding in findings {
+        for class in &finding.classes {
+            *summary.entry(class).or_default() += 1;
+        }
+    }
+
+    match format {
+        AuditFormat::Plain => {
+            outln!();
+            outln!("TXT audit of {}:", name);
+            for finding in findings {
+                let blob = finding
+                    .blob_length
+                    .map(|length| format!(", blob of {} bytes", length))
+                    .unwrap_or_default();
+                outln!(
+                    "  [{}] {} ({} bytes{}): {}",
+                    finding.classes.join(", "),
+                    finding.name,
+                    finding.length,
+                    blob,
+                    finding.text
+                );
+            }
+            let summary: Vec<String> = summary
+                .iter()
+                .map(|(class, count)| format!("{} {}", count, class))
+                .collect();
+            outln!(
+                "  {} TXT records: {}",
+                findings.len(),
+                if summary.is_empty() {
+                    String::from("none")
+                } else {
+                    summary.join(", ")
+                }
+            );
+        },
+        AuditFormat::Jsonl => {
+            for finding in findings {
+                outln!("{}", schema::versioned(finding));
+            }
+            outln!(
+                "{}",
+                json!({
+                    "schema": schema::SCHEMA,
+                    "summary": name.to_ascii(),
+                    "records": findings.len(),
+                    "classes": summary,
+                })
+            );
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::rr::rdata::TXT;
+
+    use super::*;
+    use crate::schema::{validate, Output};
+
+    fn txt(
+        name: &str,
+        strings: &[&str],
+    ) -> Record {
+        let strings = strings.iter().map(|s| s.to_string()).collect();
+        Record::from_rdata(
+            Name::from_str(name).unwrap(),
+            300,
+            RData::TXT(TXT::new(strings)),
+        )
+    }
+
+    fn classes(
+        auditor: &Auditor,
+        name: &str,
+        text: &str,
+    ) -> Vec<String> {
+        auditor.audit(&txt(name, &[text])).unwrap().classes
+    }
+
+    /// A file of `content` in the temporary directory, named after the test
+    fn rules_file(
+        test: &str,
+        content: &str,
+    ) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("resoy-{}-{}.rules", test, std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn every_builtin_rule_recognizes_its_format() {
+        let auditor = Auditor::new(None).unwrap();
+        let cases = [
+            ("example.com.", "v=spf1 include:_spf.google.com ~all", "spf"),
+            ("_dmarc.example.com.", "v=DMARC1; p=reject", "dmarc"),
+            (
+                "s1._domainkey.example.com.",
+                "v=DKIM1; k=rsa; p=MIGf",
+                "dkim",
+            ),
+            ("s1._domainkey.example.com.", "k=rsa; p=MIGf", "dkim"),
+            (
+                "default._bimi.example.com.",
+                "v=BIMI1; l=https://example.com/logo.svg",
+                "bimi",
+            ),
+            ("_mta-sts.example.com.", "v=STSv1; id=20240101", "mta-sts"),
+            (
+                "_smtp._tls.example.com.",
+                "v=TLSRPTv1; rua=mailto:tls@example.com",
+                "tls-rpt",
+            ),
+            (
+                "_acme-challenge.example.com.",
+                "gfj9Xq-ACME_ch4llenge-t0ken-of-43-character",
+                "acme-challenge",
+            ),
+            (
+                "example.com.",
+                "google-site-verification=abc",
+                "google-site-verification",
+            ),
+            ("example.com.", "MS=ms12345678", "ms-verification"),
+            (
+                "example.com.",
+                "facebook-domain-verification=abc",
+                "facebook-domain-verification",
+            ),
+            (
+                "example.com.",
+                "apple-domain-verification=abc",
+                "apple-domain-verification",
+            ),
+            (
+                "example.com.",
+                "atlassian-domain-verification=abc",
+                "atlassian-domain-verification",
+            ),
+            (
+                "example.com.",
+                "docusign=1b0a6754-49b1-4db5-8540-d2c12664b289",
+                "docusign",
+            ),
+            (
+                "example.com.",
+                "_globalsign-domain-verification=abc",
+                "globalsign-domain-verification",
+            ),
+            (
+                "example.com.",
+                "stripe-verification=abc",
+                "stripe-verification",
+            ),
+        ];
+        for (name, text, label) in cases {
+            assert_eq!(classes(&auditor, name, text)[0], label, "{} {}", name, text);
+        }
+        // Every rule of the table has a case
+        for (label, ..) in BUILTIN_RULES {
+            assert!(cases.iter().any(|case| case.2 == *label), "{}", label);
+        }
+    }
+
+    #[test]
+    fn rules_of_an_owner_only_match_under_it() {
+        let auditor = Auditor::new(None).unwrap();
+        let token = "gfj9Xq-ACME_ch4llenge-t0ken-of-43-character";
+        assert_eq!(
+            classes(&auditor, "_acme-challenge.example.com.", token)[0],
+            "acme-challenge"
+        );
+        assert_eq!(
+            classes(&auditor, "example.com.", token),
+            ["high-entropy-blob"]
+        );
+        assert_eq!(
+            classes(&auditor, "example.com.", "k=rsa; p=MIGf"),
+            ["unclassified"]
+        );
+        assert_eq!(
+            classes(&auditor, "example.com.", "MS=ms12345678 extra"),
+            ["unclassified"]
+        );
+    }
+
+    #[test]
+    fn blobs_oversized_and_unclassified_records_are_told() {
+        let auditor = Auditor::new(None).unwrap();
+        let key = "MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQC1LfAzvTRo/8Z3Yw==";
+        let finding = auditor
+            .audit(&txt(
+                "s1._domainkey.example.com.",
+                &[&format!("v=DKIM1; p={}", key)],
+            ))
+            .unwrap();
+        assert_eq!(finding.classes, ["dkim", "high-entropy-blob"]);
+        // 54 characters without the padding
+        assert_eq!(finding.blob_length, Some(40));
+        // Repeated characters have little entropy
+        assert_eq!(find_blob(&"ab".repeat(32)), None);
+        assert_eq!(find_blob("short"), None);
+
+        let long = "x".repeat(300);
+        let finding = auditor
+            .audit(&txt("example.com.", &[&long, &long]))
+            .unwrap();
+        assert_eq!(
+            (finding.length, finding.classes),
+            (600, vec![String::from("oversized")])
+        );
+        assert_eq!(
+            classes(&auditor, "example.com.", "hello world"),
+            ["unclassified"]
+        );
+        assert!(auditor
+            .audit(&Record::from_rdata(
+                Name::from_str("example.com.").unwrap(),
+                300,
+                RData::A(hickory_client::rr::rdata::A::new(192, 0, 2, 1)),
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn rules_files_add_rules_after_the_builtin_ones() {
+        let path = rules_file(
+            "added",
+            "# tokens of our own\n\ninternal = ^corp-token=[0-9a-f]+$\n  spf = ^v=spf1 \n",
+        );
+        let auditor = Auditor::new(Some(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            classes(&auditor, "example.com.", "corp-token=c0ffee"),
+            ["internal"]
+        );
+        // A label is given once, whichever rules match
+        assert_eq!(classes(&auditor, "example.com.", "v=spf1 -all"), ["spf"]);
+    }
+
+    #[test]
+    fn rules_files_are_refused_at_the_line_at_fault() {
+        let error = |test: &str, content: &str| {
+            let path = rules_file(test, content);
+            let result = Auditor::new(Some(&path));
+            fs::remove_file(&path).unwrap();
+            match result {
+                Err(AppError::AuditRulesError(at, message)) => {
+                    assert_eq!(at, path);
+                    message
+                },
+                _ => panic!("{:?} was accepted", content),
+            }
+        };
+        assert_eq!(
+            error("no-label", "# comment\nno label here\n"),
+            "line 2: expected `label = regex`"
+        );
+        assert!(error("bad-regex", "broken = (unclosed\n").starts_with("line 1: "));
+        assert!(matches!(
+            Auditor::new(Some(Path::new("/nonexistent/resoy.rules"))),
+            Err(AppError::AuditRulesError(..))
+        ));
+    }
+
+    #[test]
+    fn the_jsonl_lines_match_their_schema() {
+        let record = Record::from_rdata(
+            Name::from_str("example.com.").unwrap(),
+            300,
+            RData::TXT(TXT::new(vec![String::from("v=spf1 -all")])),
+        );
+        let finding = Auditor::new(None).unwrap().audit(&record).unwrap();
+        let line = schema::versioned(&finding);
+        validate(&schema::schema(Output::Audit), &line).unwrap();
+        assert_eq!(line["classes"][0], "spf");
+    }
+}
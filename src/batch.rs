@@ -0,0 +1,166 @@
+use std::{
+    fs::File,
+    io::{stdin, BufRead, BufReader},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client::Session,
+    concurrency::{self, Limiter},
+    output,
+    verbosity::{self, Verbosity},
+    AppError,
+};
+
+/// Names resolved at the same time by default, so that long lists don't hammer the server
+pub const DEFAULT_CONCURRENCY: usize = 20;
+
+/// The file of `--file`, stdin for `-`
+pub fn open(path: &Path) -> Result<Box<dyn BufRead + Send>, AppError> {
+    if path == Path::new("-") {
+        return Ok(Box::new(BufReader::new(stdin())));
+    }
+    let file = File::open(path)
+        .map_err(|error| AppError::NamesFileError(path.to_owned(), error.to_string()))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// Counters of a run, which the snapshots of SIGUSR2 and the summary at its end both read
+#[derive(Default)]
+pub struct Progress {
+    resolved: AtomicUsize,
+    failed: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+impl Progress {
+    /// Names resolved so far, the failed ones included
+    pub fn resolved(&self) -> usize {
+        self.resolved.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// The counters after `elapsed`, e.g. `4812 names resolved, 3 failed, 20 in flight,
+    /// 160 names/s`
+    pub fn summary(
+        &self,
+        elapsed: Duration,
+    ) -> String {
+        format!(
+            "{} names resolved, {} failed, {} in flight, {:.0} names/s",
+            self.resolved(),
+            self.failed(),
+            self.in_flight(),
+            self.resolved() as f64 / elapsed.as_secs_f64().max(0.001)
+        )
+    }
+}
+
+/// Next name of the input and its line number, skipping blank lines and `#` comments
+pub(crate) fn next_name(
+    input: &mut dyn BufRead,
+    line_number: &mut usize,
+) -> Option<(usize, String)> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match input.read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(_) => *line_number += 1,
+            Err(error) => {
+                eprintln!("Error: Cannot read names: {}", error);
+                return None;
+            },
+        }
+        let line = String::from_utf8_lossy(&line);
+        let name = line.split('#').next().unwrap_or_default().trim();
+        if !name.is_empty() {
+            return Some((*line_number, name.to_owned()));
+        }
+    }
+}
+
+/// Resolve every name of `input` with `resolve`, as many at the same time as `limiter` lets
+/// through, printing what it returns for a name as soon as it does.
+///
+/// Names are read as they are needed, so that a pipe can feed them. Unless the verbosity is
+/// quiet, failures are reported on stderr with the line of the name, from verbose on the
+/// changes of the limit, and with debug every name with its time. The names are counted into
+/// `progress`, and no more are read once `session` is stopped. Returns how many names there
+/// were and the failures.
+pub fn run<F>(
+    input: Box<dyn BufRead + Send>,
+    limiter: &Limiter,
+    progress: &Progress,
+    session: &Session,
+    resolve: F,
+) -> (usize, Vec<AppError>)
+where
+    F: Fn(&str) -> (String, Result<(), AppError>) + Sync,
+{
+    let input = Mutex::new((input, 0));
+    let failures: Mutex<Vec<AppError>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..limiter.workers() {
+            scope.spawn(|| loop {
+                limiter.acquire();
+                if session.stopped().is_some() {
+                    limiter.release(false);
+                    break;
+                }
+                let next = {
+                    let mut input = input.lock().unwrap();
+                    let (reader, line_number) = &mut *input;
+                    next_name(reader.as_mut(), line_number)
+                };
+                let Some((line_number, name)) = next else {
+                    limiter.release(false);
+                    break;
+                };
+                progress.in_flight.fetch_add(1, Ordering::Relaxed);
+                let started = Instant::now();
+                let (text, result) = resolve(&name);
+                progress.in_flight.fetch_sub(1, Ordering::Relaxed);
+                let overloaded = result.as_ref().is_err_and(concurrency::overloaded);
+                let decision = limiter.release(overloaded);
+                if let (Some(decision), true) = (decision, verbosity::at_least(Verbosity::Verbose))
+                {
+                    eprintln!("; {}", decision);
+                }
+                if verbosity::at_least(Verbosity::Debug) {
+                    eprintln!(
+                        "; line {}: {} in {}ms",
+                        line_number,
+                        name,
+                        started.elapsed().as_millis()
+                    );
+                }
+                // Written at once, so that the output of names never interleaves
+                let _ = output::record(|out| out.write_all(text.as_bytes()));
+                let mut failures = failures.lock().unwrap();
+                progress.resolved.fetch_add(1, Ordering::Relaxed);
+                if let Err(error) = result {
+                    if verbosity::at_least(Verbosity::Normal) {
+                        eprintln!("Error: line {}: {:?}", line_number, error);
+                    }
+                    progress.failed.fetch_add(1, Ordering::Relaxed);
+                    failures.push(error);
+                }
+            });
+        }
+    });
+    (progress.resolved(), failures.into_inner().unwrap())
+}
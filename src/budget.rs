@@ -0,0 +1,167 @@
+use std::{fmt::Display, sync::Mutex};
+
+/// Exit status of runs that ran out of `--max-queries`
+pub const BUDGET_EXIT_CODE: i32 = 3;
+
+/// Features whose queries are counted but never refused nor count against the budget, as
+/// what they find never changes the outcome of the run
+const EXEMPT: &[&str] = &["hints"];
+
+/// Why a query was not sent: the budget of `--max-queries` was spent
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exhausted {
+    pub max: usize,
+    /// feature of the query that was not sent
+    pub feature: &'static str,
+    /// queries sent by every feature
+    pub breakdown: String,
+}
+
+impl Display for Exhausted {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "Query budget of {} exhausted by {} ({})",
+            self.max, self.feature, self.breakdown
+        )
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// in the order the features first sent a query
+    spent: Vec<(&'static str, usize)>,
+    max: Option<usize>,
+    /// the first query that was not sent
+    exhausted: Option<Exhausted>,
+}
+
+/// Queries a run sent, by the feature that sent them
+#[derive(Default)]
+pub struct Budget {
+    state: Mutex<State>,
+}
+
+impl Budget {
+    /// Allow at most `max` queries in the run
+    pub fn limit(
+        &self,
+        max: usize,
+    ) {
+        self.state.lock().unwrap().max = Some(max);
+    }
+
+    /// Count a query of `feature` that is about to go out over the network, or refuse it
+    /// once the budget of `--max-queries` is spent, unless the feature is exempt from it like
+    /// hints.
+    ///
+    /// The first refusal is kept, for the run to end with it.
+    pub fn spend(
+        &self,
+        feature: &'static str,
+    ) -> Result<(), Exhausted> {
+        let mut state = self.state.lock().unwrap();
+        let total: usize = state
+            .spent
+            .iter()
+            .filter(|(name, _)| !EXEMPT.contains(name))
+            .map(|(_, spent)| spent)
+            .sum();
+        let exempt = EXEMPT.contains(&feature);
+        if let Some(max) = state.max.filter(|max| !exempt && total >= *max) {
+            let exhausted = Exhausted {
+                max,
+                feature,
+                breakdown: breakdown(&state.spent),
+            };
+            return Err(state.exhausted.get_or_insert(exhausted).clone());
+        }
+        match state.spent.iter_mut().find(|(name, _)| *name == feature) {
+            Some((_, spent)) => *spent += 1,
+            None => state.spent.push((feature, 1)),
+        }
+        Ok(())
+    }
+
+    /// The first query refused once the budget was spent, if any
+    pub fn exhausted(&self) -> Option<Exhausted> {
+        self.state.lock().unwrap().exhausted.clone()
+    }
+
+    /// Total of the queries sent, followed by how many each feature sent
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let total: usize = state.spent.iter().map(|(_, spent)| spent).sum();
+        format!("{} queries sent ({})", total, breakdown(&state.spent))
+    }
+}
+
+fn breakdown(spent: &[(&'static str, usize)]) -> String {
+    match spent.is_empty() {
+        true => String::from("no queries"),
+        false => spent
+            .iter()
+            .map(|(feature, spent)| format!("{} {}", feature, spent))
+            .collect::<Vec<String>>()
+            .join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_counted_per_feature() {
+        let budget = Budget::default();
+        assert_eq!(budget.summary(), "0 queries sent (no queries)");
+        budget.spend("lookup").unwrap();
+        budget.spend("lookup").unwrap();
+        budget.spend("hints").unwrap();
+        budget.spend("lookup").unwrap();
+        assert_eq!(budget.summary(), "4 queries sent (lookup 3, hints 1)");
+    }
+
+    #[test]
+    fn budgets_of_separate_runs_are_independent() {
+        let first = Budget::default();
+        let second = Budget::default();
+        first.limit(1);
+        first.spend("lookup").unwrap();
+        second.spend("lookup").unwrap();
+        second.spend("lookup").unwrap();
+        assert_eq!(first.summary(), "1 queries sent (lookup 1)");
+        assert_eq!(second.summary(), "2 queries sent (lookup 2)");
+    }
+
+    #[test]
+    fn spent_budgets_refuse_queries_and_keep_the_first_refusal() {
+        let budget = Budget::default();
+        budget.limit(2);
+        budget.spend("lookup").unwrap();
+        budget.spend("asn").unwrap();
+        assert_eq!(budget.exhausted(), None);
+        let refused = budget.spend("dangling").unwrap_err();
+        assert_eq!(
+            refused.to_string(),
+            "Query budget of 2 exhausted by dangling (lookup 1, asn 1)"
+        );
+        assert_eq!(budget.spend("lookup").unwrap_err().feature, "dangling");
+        assert_eq!(budget.exhausted(), Some(refused));
+        assert_eq!(budget.summary(), "2 queries sent (lookup 1, asn 1)");
+    }
+
+    #[test]
+    fn hints_are_never_refused_and_leave_the_budget_to_the_others() {
+        let budget = Budget::default();
+        budget.limit(1);
+        budget.spend("hints").unwrap();
+        budget.spend("lookup").unwrap();
+        budget.spend("hints").unwrap();
+        assert!(budget.spend("lookup").is_err());
+        assert_eq!(budget.summary(), "3 queries sent (hints 2, lookup 1)");
+    }
+}
@@ -0,0 +1,1003 @@
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use clap::ValueEnum;
+use hickory_client::{
+    client::{Client, SyncClient},
+    error::{ClientError, ClientErrorKind, ClientResult},
+    op::{DnsResponse, Edns, Message, MessageType, OpCode, Query, ResponseCode},
+    rr::{
+        rdata::opt::{EdnsCode, EdnsOption},
+        DNSClass, Name, Record, RecordType,
+    },
+    tcp::TcpClientConnection,
+    udp::UdpClientConnection,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "tls")]
+use crate::tls::DotClient;
+use crate::{
+    budget::Budget,
+    connections::Connections,
+    dedup::Questions,
+    mock, pcap,
+    salvage::{self, Lenient},
+    server_addr, source,
+    tcp::TcpClient,
+    tcp_fallback::TcpFallback,
+    tsig::Signer,
+    AppError, ResolveOptions,
+};
+#[cfg(feature = "doh")]
+use crate::{doh::DohClient, doh_json::DohJsonClient};
+
+#[derive(ValueEnum, Clone, Debug, Copy, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionType {
+    Udp,
+    Tcp,
+    /// JSON API of DNS-over-HTTPS resolvers
+    #[cfg(feature = "doh")]
+    DohJson,
+    /// DNS-over-TLS
+    #[cfg(feature = "tls")]
+    Tls,
+    /// DNS-over-HTTPS, with a URL as the server
+    #[cfg(feature = "doh")]
+    Https,
+}
+
+impl Display for ConnectionType {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let s = match self {
+            Self::Udp => "udp",
+            Self::Tcp => "tcp",
+            #[cfg(feature = "doh")]
+            Self::DohJson => "doh-json",
+            #[cfg(feature = "tls")]
+            Self::Tls => "tls",
+            #[cfg(feature = "doh")]
+            Self::Https => "https",
+        };
+        write!(f, "{}", s)?;
+        Ok(())
+    }
+}
+
+/// Timeout of the queries that no option sets one for
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a run gets after its deadline to end with what it printed, before the process is
+/// ended instead
+pub const DEADLINE_GRACE: Duration = Duration::from_secs(1);
+
+/// Payload size advertised with EDNS, the one agreed for DNS flag day 2020
+const EDNS_PAYLOAD: u16 = 1232;
+
+/// EDNS of the queries of `DnsClient::query_with`
+#[derive(Clone, Copy)]
+enum QueryEdns {
+    /// the payload size only, as hickory sends it by default
+    Default,
+    /// with the DO bit set, asking for signatures
+    DnssecOk,
+    /// none, for servers that don't take EDNS
+    Without,
+}
+
+/// What the clients of a run share: the budget of its queries, its deadline, the questions
+/// they asked and how many responses they salvaged
+#[derive(Default)]
+pub struct Session {
+    budget: Budget,
+    /// when `--deadline` passes, and how long it was
+    deadline: OnceLock<(Instant, Duration)>,
+    questions: Questions,
+    salvaged: AtomicUsize,
+}
+
+impl Session {
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    pub fn questions(&self) -> &Questions {
+        &self.questions
+    }
+
+    /// Count a response whose answers were salvaged
+    pub fn add_salvaged(&self) {
+        self.salvaged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn salvaged(&self) -> usize {
+        self.salvaged.load(Ordering::Relaxed)
+    }
+
+    /// End the run `deadline` after `started`: the queries in flight then time out, and those
+    /// after are refused
+    pub fn set_deadline(
+        &self,
+        started: Instant,
+        deadline: Duration,
+    ) {
+        let _ = self.deadline.set((started + deadline, deadline));
+    }
+
+    /// Time left until the deadline, `None` without one
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .get()
+            .map(|(at, _)| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Why the run ends before it is done, once the budget of `--max-queries` is spent or the
+    /// deadline passed, as its queries are refused from then on
+    pub fn stopped(&self) -> Option<AppError> {
+        if let Some(exhausted) = self.budget.exhausted() {
+            return Some(AppError::BudgetExhausted(exhausted));
+        }
+        match (self.deadline.get(), self.remaining()) {
+            (Some((_, deadline)), Some(Duration::ZERO)) => {
+                Some(AppError::DeadlineExceeded(*deadline))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Query of the question, with the header bits of `options`
+fn query_message(
+    name: &Name,
+    query_class: DNSClass,
+    query_type: RecordType,
+    edns: QueryEdns,
+    options: &ResolveOptions,
+) -> Message {
+    let mut query = Query::query(name.clone(), query_type);
+    query.set_query_class(query_class);
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(options.recursion_desired())
+        .add_query(query);
+    let dnssec_ok = match edns {
+        QueryEdns::Default => false,
+        QueryEdns::DnssecOk => true,
+        QueryEdns::Without => return message,
+    };
+    let mut edns = Edns::new();
+    edns.set_dnssec_ok(dnssec_ok).set_max_payload(EDNS_PAYLOAD);
+    if options.nsid() {
+        edns.options_mut()
+            .insert(EdnsOption::Unknown(EdnsCode::NSID.into(), Vec::new()));
+    }
+    message.set_edns(edns);
+    message
+}
+
+/// The response of a sent message, which the clients of hickory return as a stream.
+///
+/// The stream ends without any response when the timeout passes, which is a timeout like any
+/// other, to be retried and salvaged.
+fn first_response(responses: Vec<ClientResult<DnsResponse>>) -> ClientResult<DnsResponse> {
+    responses
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| Err(ClientError::from(ClientErrorKind::Timeout)))
+}
+
+/// Client of a server over UDP, and what it takes to send its queries on sockets of our own
+struct UdpTransport {
+    client: SyncClient<UdpClientConnection>,
+    addr: SocketAddr,
+    /// local address of `--source` and `--source-port`
+    bind_addr: Option<SocketAddr>,
+    signer: Option<Signer>,
+    fallback: TcpFallback,
+}
+
+/// Connection of a client to its server
+enum Transport {
+    Tcp(TcpClient),
+    Udp(UdpTransport),
+    #[cfg(feature = "doh")]
+    DohJson(DohJsonClient),
+    #[cfg(feature = "tls")]
+    Tls(DotClient),
+    #[cfg(feature = "doh")]
+    Https(DohClient),
+    Mock(Arc<mock::Fixture>),
+}
+
+/// Client of the server of a run, over any of the connections.
+///
+/// A client is `Send` and `Sync`, and its clones are cheap and share its connections, so that
+/// one client may be queried from any number of threads at once: queries over UDP go out on
+/// sockets of their own, those over TCP and TLS take turns on the one connection of the client,
+/// and those over HTTPS share the connections the agent keeps alive. A connection that fails
+/// is opened again by the next query, whichever clone sends it. The budget of queries and the
+/// answers shared between the questions asked twice are those of the session of the options.
+#[derive(Clone)]
+pub struct DnsClient {
+    transport: Arc<Transport>,
+    options: ResolveOptions,
+}
+
+impl DnsClient {
+    /// Connect to the server of `options`, or answer from its mock answers if it has any
+    pub fn new(options: &ResolveOptions) -> Result<Self, AppError> {
+        Ok(Self {
+            transport: Arc::new(Transport::new(options)?),
+            options: options.clone(),
+        })
+    }
+
+    pub fn options(&self) -> &ResolveOptions {
+        &self.options
+    }
+
+    /// The same client, over the same connections, counting its queries as sent by `feature`
+    pub fn with_feature(
+        &self,
+        feature: &'static str,
+    ) -> Self {
+        Self {
+            transport: self.transport.clone(),
+            options: self.options.clone().with_feature(feature),
+        }
+    }
+}
+
+impl Transport {
+    fn new(options: &ResolveOptions) -> Result<Self, AppError> {
+        if let Some(fixture) = options.mock() {
+            return Ok(Self::Mock(fixture.clone()));
+        }
+        let connection_type = options.connection();
+        let raw_addr = options.server();
+        let timeout = options.timeout();
+        let socket_addr = || server_addr::resolve_server(connection_type, raw_addr, timeout);
+        let (source_ip, source_port) = options.source();
+        let udp_bind_addr = |addr| source::udp_bind_addr(addr, source_ip, source_port);
+        let tcp_bind_addr = |addr| source::tcp_bind_addr(addr, source_ip, source_port);
+
+        let signer = match options.tsig() {
+            Some(key) => Some(Signer::new(key).map_err(AppError::TsigError)?),
+            None => None,
+        };
+        if signer.is_some() && !matches!(connection_type, ConnectionType::Udp | ConnectionType::Tcp)
+        {
+            #[cfg(feature = "tls")]
+            let signed = matches!(connection_type, ConnectionType::Tls);
+            #[cfg(not(feature = "tls"))]
+            let signed = false;
+            if !signed {
+                return Err(AppError::TsigError(format!(
+                    "queries over {} cannot be signed, only those over udp, tcp and tls",
+                    connection_type
+                )));
+            }
+        }
+
+        Ok(match connection_type {
+            ConnectionType::Udp => {
+                let addr = socket_addr()?;
+                let bind_addr = udp_bind_addr(addr)?;
+                let connection =
+                    UdpClientConnection::with_bind_addr_and_timeout(addr, bind_addr, timeout)
+                        .map_err(|_| {
+                            AppError::DNSServerUnreachable(connection_type, raw_addr.to_owned())
+                        })?;
+                #[cfg(feature = "tsig")]
+                let client = match &signer {
+                    Some(signer) => SyncClient::with_tsigner(connection, signer.tsigner().clone()),
+                    None => SyncClient::new(connection),
+                };
+                #[cfg(not(feature = "tsig"))]
+                let client = SyncClient::new(connection);
+                let fallback =
+                    TcpClient::new(addr, tcp_bind_addr(addr)?, timeout).with_signer(signer.clone());
+                Self::Udp(UdpTransport {
+                    client,
+                    addr,
+                    bind_addr,
+                    signer,
+                    fallback: TcpFallback::new(fallback, options.tcp_fallback()),
+                })
+            },
+            ConnectionType::Tcp => {
+                let addr = socket_addr()?;
+                let bind_addr = tcp_bind_addr(addr)?;
+                Self::Tcp(TcpClient::new(addr, bind_addr, timeout).with_signer(signer))
+            },
+            #[cfg(feature = "doh")]
+            ConnectionType::DohJson => Self::DohJson(
+                DohJsonClient::new(raw_addr, timeout)
+                    .ok_or_else(|| AppError::InvalidDnsServer(raw_addr.to_owned()))?,
+            ),
+            #[cfg(feature = "doh")]
+            ConnectionType::Https => Self::Https(
+                DohClient::new(raw_addr, timeout)
+                    .ok_or_else(|| AppError::InvalidDnsServer(raw_addr.to_owned()))?,
+            ),
+            #[cfg(feature = "tls")]
+            ConnectionType::Tls => {
+                let addr = socket_addr()?;
+                let host = server_addr::split_hostname(connection_type, raw_addr)?;
+                let host = host.map(|(host, _)| host);
+                Self::Tls(
+                    DotClient::new(addr, host, options, signer).map_err(|error| {
+                        AppError::DNSServerUnreachable(
+                            connection_type,
+                            format!("{}: {}", raw_addr, error),
+                        )
+                    })?,
+                )
+            },
+        })
+    }
+}
+
+impl DnsClient {
+    /// Records of a transfer of the zone `zone`, which always goes over TCP
+    pub fn zone_transfer(
+        &self,
+        zone: &Name,
+    ) -> Result<Vec<Record>, String> {
+        self.spend().map_err(|stopped| stopped.to_string())?;
+        // A transfer comes in several responses, which the client of hickory reads until the
+        // server closes the connection
+        let (addr, bind_addr, timeout) = match &*self.transport {
+            Transport::Mock(fixture) => return fixture.zone_transfer(zone),
+            Transport::Tcp(client) => (client.addr(), client.bind_addr(), client.timeout()),
+            Transport::Udp(udp) => (udp.addr, udp.fallback.bind_addr(), udp.fallback.timeout()),
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(String::from(
+                    "zone transfers need a udp or tcp server, try --zone-file",
+                ))
+            },
+        };
+        let connection = TcpClientConnection::with_bind_addr_and_timeout(addr, bind_addr, timeout)
+            .map_err(|error| error.to_string())?;
+        let responses: Vec<ClientResult<DnsResponse>> = SyncClient::new(connection)
+            .zone_transfer(zone, None)
+            .map_err(|error| error.to_string())?
+            .collect();
+
+        let mut records: Vec<Record> = Vec::new();
+        for response in responses {
+            let response = match response {
+                Ok(response) => response,
+                Err(error) if !records.is_empty() => {
+                    return Err(format!(
+                        "malformed transfer of {}, it ended without the closing SOA record after \
+                         {} records: {}",
+                        zone,
+                        records.len(),
+                        error
+                    ))
+                },
+                Err(error) => return Err(error.to_string()),
+            };
+            match response.response_code() {
+                ResponseCode::NoError => {},
+                code @ (ResponseCode::Refused | ResponseCode::NotAuth) => {
+                    return Err(format!(
+                        "the server refused to transfer {} ({})",
+                        zone,
+                        format!("{:?}", code).to_uppercase()
+                    ))
+                },
+                code => return Err(format!("{:?}", code).to_uppercase()),
+            }
+            records.extend(response.answers().iter().cloned());
+        }
+        // The transfer starts with the SOA record of the zone and ends with it again
+        if records.first().map(Record::record_type) != Some(RecordType::SOA) {
+            return Err(format!(
+                "malformed transfer of {}, it does not start with its SOA record",
+                zone
+            ));
+        }
+        if records.len() < 2 || records.last().map(Record::record_type) != Some(RecordType::SOA) {
+            return Err(format!(
+                "malformed transfer of {}, it ended without the closing SOA record after {} \
+                 records",
+                zone,
+                records.len()
+            ));
+        }
+        records.pop();
+        #[cfg(feature = "tsig")]
+        crate::tsig::raw_dnssec_records(&mut records);
+        Ok(records)
+    }
+
+    /// Server the client asks, `None` for mock answers
+    pub fn server(&self) -> Option<String> {
+        match &*self.transport {
+            Transport::Tcp(client) => Some(format!("tcp://{}", client.addr())),
+            Transport::Udp(udp) => Some(format!("udp://{}", udp.addr)),
+            #[cfg(feature = "doh")]
+            Transport::DohJson(client) => Some(client.url().to_owned()),
+            #[cfg(feature = "tls")]
+            Transport::Tls(client) => Some(format!("tls://{}", client.addr())),
+            #[cfg(feature = "doh")]
+            Transport::Https(client) => Some(client.url().to_owned()),
+            Transport::Mock(_) => None,
+        }
+    }
+
+    /// Connections the client opened and reused so far, `None` for those that keep none open
+    pub fn connections(&self) -> Option<Connections> {
+        match &*self.transport {
+            #[cfg(feature = "doh")]
+            Transport::DohJson(client) => Some(client.connections()),
+            #[cfg(feature = "tls")]
+            Transport::Tls(client) => Some(client.connections()),
+            #[cfg(feature = "doh")]
+            Transport::Https(client) => Some(client.connections()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Answer the question, sharing the answer with every other client of the run that asks
+    /// the same server the same question while its query is in flight
+    pub fn query(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+    ) -> ClientResult<DnsResponse> {
+        match self.server() {
+            Some(server) => {
+                let questions = self.options.session().questions();
+                questions.query(server, name, query_class, query_type, || {
+                    self.query_uncached(name, query_class, query_type)
+                })
+            },
+            None => self.query_uncached(name, query_class, query_type),
+        }
+    }
+
+    /// Like `query`, sending the query even when the question was already asked
+    pub fn query_uncached(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+    ) -> ClientResult<DnsResponse> {
+        let edns = match self.options.dnssec_ok() {
+            true => QueryEdns::DnssecOk,
+            false => QueryEdns::Default,
+        };
+        self.query_with(name, query_class, query_type, edns)
+    }
+
+    /// Like `query_uncached`, with the DO bit set so that the server answers with signatures
+    /// as well
+    pub fn query_dnssec(
+        &self,
+        name: &Name,
+        query_type: RecordType,
+    ) -> ClientResult<DnsResponse> {
+        self.query_with(name, DNSClass::IN, query_type, QueryEdns::DnssecOk)
+    }
+
+    /// Forward `request`, a query as another client sent it, with its header bits and EDNS as
+    /// they are, every transport giving it an ID of its own
+    pub fn forward(
+        &self,
+        request: &Message,
+    ) -> ClientResult<DnsResponse> {
+        let Some(query) = request.queries().first() else {
+            return Err(ClientError::from("no question to forward"));
+        };
+        let message = || request.clone();
+        self.exchange(
+            query.name(),
+            query.query_class(),
+            query.query_type(),
+            &message,
+        )
+    }
+
+    /// Send the query with `edns`
+    fn query_with(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+        edns: QueryEdns,
+    ) -> ClientResult<DnsResponse> {
+        let message = || query_message(name, query_class, query_type, edns, &self.options);
+        let response = self.exchange(name, query_class, query_type, &message);
+
+        // Servers that don't know EDNS answer FORMERR or NOTIMP, RFC 6891
+        let edns_refused = matches!(
+            &response,
+            Ok(response) if matches!(
+                response.response_code(),
+                ResponseCode::FormErr | ResponseCode::NotImp
+            )
+        );
+        if let (QueryEdns::DnssecOk, true, true) = (edns, edns_refused, self.sends_edns()) {
+            eprintln!(
+                "the server does not take EDNS for {} {}, asking again without it",
+                name, query_type
+            );
+            return self.query_with(name, query_class, query_type, QueryEdns::Without);
+        }
+        response
+    }
+
+    /// Send the query that `message` builds for the question, retrying it on timeouts,
+    /// salvaging its response and asking again over TCP when it comes truncated
+    fn exchange(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+        message: &dyn Fn() -> Message,
+    ) -> ClientResult<DnsResponse> {
+        // Sent again on timeouts and the response codes of the policy only, as any other
+        // response, even an error, is the answer of the server
+        let policy = self.options.policy();
+        let lenient = !self.options.strict_parsing();
+        let mut attempt = 0;
+        let (sent, response, corruption) = loop {
+            attempt += 1;
+            self.spend()
+                .map_err(|stopped| ClientError::from(stopped.to_string()))?;
+            let sent = SystemTime::now();
+            let mut corruption = None;
+            let mut salvaged = |response: ClientResult<Lenient>| {
+                response.map(|(response, lost)| {
+                    corruption = lost;
+                    response
+                })
+            };
+            let timeout = self.attempt_timeout();
+            let response = match &*self.transport {
+                Transport::Tcp(client) => salvaged(client.send_within(message(), lenient, timeout)),
+                // hickory drops the responses it cannot parse and waits for another until the
+                // timeout, so the attempts after a timeout go out on sockets of our own, which
+                // keep what arrives, as do those cut short by the deadline
+                Transport::Udp(udp) if (lenient && attempt > 1) || timeout < policy.timeout() => {
+                    salvaged(salvage::send_udp(
+                        udp.addr,
+                        udp.bind_addr,
+                        message(),
+                        udp.signer.as_ref(),
+                        timeout,
+                        lenient,
+                    ))
+                },
+                Transport::Udp(udp) => first_response(udp.client.send(message())),
+                // The JSON API has no signatures to answer with, nor a way to clear RD
+                #[cfg(feature = "doh")]
+                Transport::DohJson(client) => client.query(name, query_class, query_type),
+                #[cfg(feature = "tls")]
+                Transport::Tls(client) => client.send(message()),
+                #[cfg(feature = "doh")]
+                Transport::Https(client) => client.send(message()),
+                Transport::Mock(fixture) => {
+                    fixture.query_within(name, query_class, query_type, timeout)
+                },
+            };
+            // Waiting past the deadline only for the next attempt to be refused is no use
+            let remaining = self.options.session().remaining();
+            match policy.retry_after(attempt, &response) {
+                Some(wait) if remaining.is_none_or(|remaining| wait < remaining) => {
+                    thread::sleep(wait)
+                },
+                _ => break (sent, response, corruption),
+            }
+        };
+        let server = match &*self.transport {
+            Transport::Tcp(client) => Some(client.addr()),
+            Transport::Udp(udp) => Some(udp.addr),
+            _ => None,
+        };
+        if let (Some(server), Some(corruption)) = (server, &corruption) {
+            salvage::warn(server, name, query_type, corruption, self.options.session());
+        }
+        #[cfg(feature = "tsig")]
+        let response = response.map(crate::tsig::raw_dnssec);
+        self.capture(name, query_class, query_type, sent, &response);
+
+        let Transport::Udp(udp) = &*self.transport else {
+            return response;
+        };
+        let Some(client) = udp.fallback.client(&response, name, query_type) else {
+            return response;
+        };
+        self.spend()
+            .map_err(|stopped| ClientError::from(stopped.to_string()))?;
+        let sent = SystemTime::now();
+        let response = match lenient {
+            true => client
+                .send_lenient(message())
+                .map(|(response, corruption)| {
+                    if let Some(corruption) = corruption {
+                        let session = self.options.session();
+                        salvage::warn(udp.addr, name, query_type, &corruption, session);
+                    }
+                    response
+                }),
+            false => client.send(message()),
+        };
+        #[cfg(feature = "tsig")]
+        let response = response.map(crate::tsig::raw_dnssec);
+        capture(
+            pcap::Transport::Tcp(udp.addr),
+            name,
+            query_class,
+            query_type,
+            sent,
+            &response,
+        );
+        response
+    }
+
+    /// Whether queries with the DO bit go out with EDNS, rather than through an API or mock
+    /// answers without it
+    fn sends_edns(&self) -> bool {
+        match &*self.transport {
+            Transport::Tcp(..) | Transport::Udp(..) => true,
+            #[cfg(feature = "tls")]
+            Transport::Tls(_) => true,
+            #[cfg(feature = "doh")]
+            Transport::Https(_) => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Count a query against `--max-queries`, unless it is answered by mock answers, failing
+    /// once the budget is spent or the deadline passed
+    fn spend(&self) -> Result<(), AppError> {
+        let session = self.options.session();
+        if let Some(AppError::DeadlineExceeded(deadline)) = session.stopped() {
+            return Err(AppError::DeadlineExceeded(deadline));
+        }
+        match *self.transport {
+            Transport::Mock(_) => Ok(()),
+            _ => session
+                .budget()
+                .spend(self.options.feature())
+                .map_err(AppError::BudgetExhausted),
+        }
+    }
+
+    /// Timeout of the next attempt of a query, cut short by the deadline of the session
+    fn attempt_timeout(&self) -> Duration {
+        let timeout = self.options.policy().timeout();
+        match self.options.session().remaining() {
+            // Sockets take no timeout of zero, and the query is refused once it passed anyway
+            Some(remaining) => timeout.min(remaining).max(Duration::from_millis(1)),
+            None => timeout,
+        }
+    }
+
+    /// Record the exchange into the file of `--pcap`, if any
+    fn capture(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+        sent: SystemTime,
+        response: &ClientResult<DnsResponse>,
+    ) {
+        let transport = match &*self.transport {
+            Transport::Tcp(client) => pcap::Transport::Tcp(client.addr()),
+            Transport::Udp(udp) => pcap::Transport::Udp(udp.addr),
+            #[cfg(feature = "doh")]
+            Transport::DohJson(_) => pcap::Transport::DohJson,
+            #[cfg(feature = "tls")]
+            Transport::Tls(client) => pcap::Transport::Tls(client.addr()),
+            #[cfg(feature = "doh")]
+            Transport::Https(_) => pcap::Transport::Https,
+            // Mock answers never travelled, so there is nothing to capture
+            Transport::Mock(_) => return,
+        };
+        capture(transport, name, query_class, query_type, sent, response);
+    }
+}
+
+/// Record an exchange that travelled over `transport` into the file of `--pcap`, if any
+fn capture(
+    transport: pcap::Transport,
+    name: &Name,
+    query_class: DNSClass,
+    query_type: RecordType,
+    sent: SystemTime,
+    response: &ClientResult<DnsResponse>,
+) {
+    if let (true, Ok(response)) = (pcap::is_capturing(), response) {
+        let received = SystemTime::now();
+        pcap::record(
+            transport,
+            name,
+            query_class,
+            query_type,
+            sent,
+            received,
+            response,
+        );
+    }
+}
+
+/// The error of a query for `name` and `record_type` to the server of `options` that failed
+/// after `elapsed`, or the end of the budget of the session once it refuses the queries
+pub fn query_failure(
+    name: &Name,
+    record_type: RecordType,
+    options: &ResolveOptions,
+    error: ClientError,
+    elapsed: Duration,
+) -> AppError {
+    if let Some(stopped) = options.session().stopped() {
+        return stopped;
+    }
+    match salvage::timed_out(&error) {
+        true => AppError::QueryTimeout(
+            format!("{}/{}", name, record_type),
+            options.server().to_owned(),
+            options.attempts(),
+            elapsed,
+        ),
+        false => AppError::QueryError(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        str::FromStr,
+        thread,
+        time::Instant,
+    };
+
+    use hickory_client::{
+        rr::{RData, Record},
+        serialize::{binary::BinDecodable, txt::RDataParser},
+    };
+
+    use super::*;
+    use crate::QueryPolicy;
+
+    fn record(
+        name: &str,
+        record_type: RecordType,
+        data: &str,
+    ) -> Record {
+        let data = RData::try_from_str(record_type, data).unwrap();
+        Record::from_rdata(Name::from_str(name).unwrap(), 300, data)
+    }
+
+    fn soa() -> Record {
+        record(
+            "example.com.",
+            RecordType::SOA,
+            "ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 600",
+        )
+    }
+
+    /// Server answering one transfer with a message of `records` for every entry of
+    /// `messages`, then closing the connection
+    fn server(
+        response_code: ResponseCode,
+        messages: Vec<Vec<Record>>,
+    ) -> DnsClient {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut length = [0; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut query = vec![0; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut query).unwrap();
+            let query = Message::from_bytes(&query).unwrap();
+            for records in messages {
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_response_code(response_code)
+                    .add_queries(query.queries().to_vec())
+                    .add_answers(records);
+                let response = response.to_vec().unwrap();
+                let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+                framed.extend_from_slice(&response);
+                stream.write_all(&framed).unwrap();
+            }
+        });
+        let options = ResolveOptions::new(ConnectionType::Tcp, &addr.to_string())
+            .with_timeout(Duration::from_secs(5));
+        DnsClient::new(&options).unwrap()
+    }
+
+    fn zone() -> Name {
+        Name::from_str("example.com.").unwrap()
+    }
+
+    #[test]
+    fn transfers_are_read_over_every_message_up_to_the_closing_soa() {
+        let client = server(
+            ResponseCode::NoError,
+            vec![
+                vec![
+                    soa(),
+                    record("example.com.", RecordType::NS, "ns1.example.com."),
+                ],
+                vec![
+                    record("ns1.example.com.", RecordType::A, "192.0.2.53"),
+                    soa(),
+                ],
+            ],
+        );
+        let records = client.zone_transfer(&zone()).unwrap();
+        let types: Vec<RecordType> = records.iter().map(Record::record_type).collect();
+        assert_eq!(types, [RecordType::SOA, RecordType::NS, RecordType::A]);
+    }
+
+    #[test]
+    fn refused_transfers_are_told_apart() {
+        for code in [ResponseCode::Refused, ResponseCode::NotAuth] {
+            let error = server(code, vec![Vec::new()])
+                .zone_transfer(&zone())
+                .unwrap_err();
+            assert_eq!(
+                error,
+                format!(
+                    "the server refused to transfer example.com. ({})",
+                    format!("{:?}", code).to_uppercase()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn transfers_without_the_closing_soa_are_malformed() {
+        let client = server(
+            ResponseCode::NoError,
+            vec![vec![
+                soa(),
+                record("example.com.", RecordType::NS, "ns1.example.com."),
+            ]],
+        );
+        let error = client.zone_transfer(&zone()).unwrap_err();
+        assert!(
+            error.starts_with(
+                "malformed transfer of example.com., it ended without the closing SOA"
+            ),
+            "{}",
+            error
+        );
+        let client = server(
+            ResponseCode::NoError,
+            vec![vec![record(
+                "example.com.",
+                RecordType::NS,
+                "ns1.example.com.",
+            )]],
+        );
+        let error = client.zone_transfer(&zone()).unwrap_err();
+        assert!(
+            error.ends_with("it does not start with its SOA record"),
+            "{}",
+            error
+        );
+    }
+
+    /// Client answering from the answers of `fixture`, sending the queries as `policy` says
+    fn mock(
+        fixture: &str,
+        policy: QueryPolicy,
+    ) -> DnsClient {
+        let fixture = mock::Fixture::from_toml(fixture).unwrap();
+        let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+            .with_policy(policy)
+            .with_mock(Some(Arc::new(fixture)));
+        DnsClient::new(&options).unwrap()
+    }
+
+    fn ask(client: &DnsClient) -> ClientResult<DnsResponse> {
+        let name = Name::from_str("example.com.").unwrap();
+        client.query_uncached(&name, DNSClass::IN, RecordType::A)
+    }
+
+    const FLAKY: &str = r#"
+        [[answer]]
+        name = "example.com"
+        type = "A"
+        failures = ["timeout", "SERVFAIL"]
+        records = ["192.0.2.1"]
+    "#;
+
+    #[test]
+    fn timeouts_and_the_rcodes_of_the_policy_are_sent_again() {
+        let policy = QueryPolicy::default()
+            .with_timeout(Duration::from_millis(20))
+            .with_retry_on(&[ResponseCode::ServFail]);
+        let response = ask(&mock(FLAKY, policy.clone())).unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert_eq!(response.answers().len(), 1);
+
+        // The last response is the answer when every attempt failed
+        let response = ask(&mock(FLAKY, policy.with_attempts(2))).unwrap();
+        assert_eq!(response.response_code(), ResponseCode::ServFail);
+
+        // By default SERVFAIL is the answer of the server, and timeouts only are sent again
+        let policy = QueryPolicy::default().with_timeout(Duration::from_millis(20));
+        let response = ask(&mock(FLAKY, policy.clone())).unwrap();
+        assert_eq!(response.response_code(), ResponseCode::ServFail);
+        let error = ask(&mock(FLAKY, policy.with_attempts(1))).unwrap_err();
+        assert!(salvage::timed_out(&error), "{}", error);
+    }
+
+    #[test]
+    fn answers_later_than_the_timeout_time_out_on_every_attempt() {
+        const SLOW: &str = r#"
+            [[answer]]
+            name = "example.com"
+            type = "A"
+            delay = "200ms"
+            records = ["192.0.2.1"]
+        "#;
+        let policy = QueryPolicy::default()
+            .with_attempts(2)
+            .with_timeout(Duration::from_millis(30));
+        let started = Instant::now();
+        let error = ask(&mock(SLOW, policy.clone())).unwrap_err();
+        assert!(salvage::timed_out(&error), "{}", error);
+        // Both attempts waited the whole timeout, and nothing longer
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(60), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+
+        let policy = policy.with_timeout(Duration::from_secs(1));
+        let response = ask(&mock(SLOW, policy)).unwrap();
+        assert_eq!(response.answers().len(), 1);
+    }
+
+    #[test]
+    fn the_backoff_doubles_between_attempts() {
+        const TIMEOUTS: &str = r#"
+            [[answer]]
+            name = "example.com"
+            type = "A"
+            failures = ["timeout", "timeout"]
+            records = ["192.0.2.1"]
+        "#;
+        let policy = QueryPolicy::default()
+            .with_timeout(Duration::from_millis(10))
+            .with_backoff(Duration::from_millis(50));
+        let started = Instant::now();
+        let response = ask(&mock(TIMEOUTS, policy)).unwrap();
+        assert_eq!(response.answers().len(), 1);
+        // Two timeouts of 10ms, then waits of 50ms and 100ms
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(170), "{:?}", elapsed);
+    }
+}
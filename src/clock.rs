@@ -0,0 +1,53 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Source of the current time of what expires or adapts over time, so that it can be tested
+/// without waiting
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The time of the system, that of every run
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when told to, for tests and simulations
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+impl ManualClock {
+    /// Clock starting at the current time of the system
+    pub fn new() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Move the clock `by` forward
+    pub fn advance(
+        &self,
+        by: Duration,
+    ) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
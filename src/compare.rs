@@ -0,0 +1,306 @@
+use std::{fmt::Display, thread, time::Instant};
+
+use hickory_client::{
+    op::DnsResponse,
+    rr::{Name, Record, RecordType},
+};
+use serde_json::{json, Value};
+
+use crate::{client::query_failure, format, AppError, DnsClient, ResolveOptions};
+
+/// Answers of one of the servers of `--server`
+pub struct Answers {
+    pub server: String,
+    /// in the order the types were asked for
+    pub responses: Vec<(RecordType, DnsResponse)>,
+    /// the error of the connection, or of every query that failed
+    pub failures: Vec<AppError>,
+}
+
+impl Answers {
+    /// Records of the answers of every type, in the order the types were asked for
+    pub fn records(&self) -> Vec<&Record> {
+        self.responses
+            .iter()
+            .flat_map(|(_, response)| response.answers())
+            .collect()
+    }
+}
+
+/// Ask every one of `servers` for the records of `name` at the same time, the types one after
+/// another per server, as `options` say otherwise.
+///
+/// A server that cannot be reached gets its error instead of answers, and the others are
+/// still asked.
+pub fn query_all(
+    options: &ResolveOptions,
+    servers: &[String],
+    name: &Name,
+    record_types: &[RecordType],
+) -> Vec<Answers> {
+    let ask = |server: &String| {
+        let mut answers = Answers {
+            server: server.clone(),
+            responses: Vec::with_capacity(record_types.len()),
+            failures: Vec::new(),
+        };
+        let options = options.clone().with_server(server);
+        let client = match DnsClient::new(&options) {
+            Ok(client) => client,
+            Err(error) => {
+                answers.failures.push(error);
+                return answers;
+            },
+        };
+        for record_type in record_types {
+            let started = Instant::now();
+            match client.query(name, options.class(), *record_type) {
+                Ok(response) => answers.responses.push((*record_type, response)),
+                Err(error) => answers.failures.push(query_failure(
+                    name,
+                    *record_type,
+                    &options,
+                    error,
+                    started.elapsed(),
+                )),
+            }
+        }
+        answers
+    };
+    thread::scope(|scope| {
+        let handles: Vec<_> = servers
+            .iter()
+            .map(|server| {
+                let ask = &ask;
+                scope.spawn(move || ask(server))
+            })
+            .collect();
+        // In the order the servers were given, whichever answered first
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// A record that some of the compared servers answered with and the others did not
+struct Difference<'r> {
+    record: &'r Record,
+    /// the servers that answered with it
+    servers: Vec<&'r str>,
+}
+
+/// What the servers of `--server` disagree on, as printed by `--diff`
+pub struct Comparison<'r> {
+    /// servers whose every query got a response
+    compared: Vec<&'r str>,
+    /// servers left out, as their answers are incomplete
+    skipped: Vec<&'r str>,
+    differences: Vec<Difference<'r>>,
+}
+
+/// Compare the records the servers answered with, by name, type and data, whatever their TTLs.
+///
+/// Records differing in their data only are two differences, one per data. Servers that failed
+/// any query are left out of the comparison, as the records of their failed queries would all
+/// look missing.
+pub fn compare(answers: &[Answers]) -> Comparison<'_> {
+    let (complete, incomplete): (Vec<&Answers>, Vec<&Answers>) = answers
+        .iter()
+        .partition(|answers| answers.failures.is_empty());
+    let mut differences: Vec<Difference> = Vec::new();
+    for answers in &complete {
+        for record in answers.records() {
+            match differences
+                .iter_mut()
+                .find(|difference| format::same_record(difference.record, record))
+            {
+                Some(difference) => {
+                    if !difference.servers.contains(&answers.server.as_str()) {
+                        difference.servers.push(&answers.server);
+                    }
+                },
+                None => differences.push(Difference {
+                    record,
+                    servers: vec![&answers.server],
+                }),
+            }
+        }
+    }
+    // Records of every server are no difference
+    differences.retain(|difference| difference.servers.len() < complete.len());
+    Comparison {
+        compared: complete
+            .iter()
+            .map(|answers| answers.server.as_str())
+            .collect(),
+        skipped: incomplete
+            .iter()
+            .map(|answers| answers.server.as_str())
+            .collect(),
+        differences,
+    }
+}
+
+impl Comparison<'_> {
+    /// The differences as JSON objects of `name`, `type`, `data` and the `servers` that
+    /// answered with the record
+    pub fn json(&self) -> Value {
+        self.differences
+            .iter()
+            .map(|difference| {
+                json!({
+                    "name": difference.record.name().to_string(),
+                    "type": difference.record.record_type().to_string(),
+                    "data": difference.record.data().map(format::data_text),
+                    "servers": difference.servers,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Display for Comparison<'_> {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match (self.compared.len(), self.differences.is_empty()) {
+            (0 | 1, _) => write!(
+                f,
+                "Nothing to compare, {} of the servers answered every query",
+                self.compared.len()
+            )?,
+            (compared, true) => write!(f, "The {} servers answered the same records", compared)?,
+            (compared, false) => {
+                write!(f, "Differences between the {} servers:", compared)?;
+                for difference in &self.differences {
+                    let record_type: &str = difference.record.record_type().into();
+                    let data = difference.record.data().map(format::data_text);
+                    write!(
+                        f,
+                        "\n  {:>5} {} {}  only from {}",
+                        record_type,
+                        difference.record.name(),
+                        data.unwrap_or_default(),
+                        difference.servers.join(", ")
+                    )?;
+                }
+            },
+        }
+        if !self.skipped.is_empty() {
+            write!(
+                f,
+                "\n  not compared, as queries failed: {}",
+                self.skipped.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::{op::Message, rr::RData, serialize::txt::RDataParser};
+
+    use super::*;
+
+    fn record(
+        record_type: RecordType,
+        ttl: u32,
+        data: &str,
+    ) -> Record {
+        let data = RData::try_from_str(record_type, data).unwrap();
+        Record::from_rdata(Name::from_str("example.com.").unwrap(), ttl, data)
+    }
+
+    fn answers(
+        server: &str,
+        records: Vec<Record>,
+    ) -> Answers {
+        let mut message = Message::new();
+        message.add_answers(records);
+        Answers {
+            server: String::from(server),
+            responses: vec![(RecordType::A, DnsResponse::from_message(message).unwrap())],
+            failures: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn records_are_compared_by_name_type_and_data_whatever_their_ttls() {
+        let answers = [
+            answers(
+                "192.0.2.53",
+                vec![
+                    record(RecordType::A, 300, "192.0.2.1"),
+                    record(RecordType::A, 300, "192.0.2.2"),
+                ],
+            ),
+            answers(
+                "198.51.100.53",
+                vec![
+                    record(RecordType::A, 12, "192.0.2.1"),
+                    record(RecordType::A, 300, "192.0.2.3"),
+                ],
+            ),
+            answers(
+                "203.0.113.53",
+                vec![
+                    record(RecordType::A, 300, "192.0.2.1"),
+                    record(RecordType::A, 300, "192.0.2.3"),
+                    record(RecordType::TXT, 300, "\"192.0.2.1\""),
+                ],
+            ),
+        ];
+        let comparison = compare(&answers);
+        assert_eq!(
+            comparison.to_string(),
+            [
+                "Differences between the 3 servers:",
+                "      A example.com. 192.0.2.2  only from 192.0.2.53",
+                "      A example.com. 192.0.2.3  only from 198.51.100.53, 203.0.113.53",
+                "    TXT example.com. 192.0.2.1  only from 203.0.113.53",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            comparison.json()[1],
+            json!({
+                "name": "example.com.",
+                "type": "A",
+                "data": "192.0.2.3",
+                "servers": ["198.51.100.53", "203.0.113.53"],
+            })
+        );
+    }
+
+    #[test]
+    fn servers_that_failed_are_left_out() {
+        let mut failed = answers("203.0.113.53", Vec::new());
+        failed
+            .failures
+            .push(AppError::NxDomain(String::from("example.com.")));
+        let answers = [
+            answers("192.0.2.53", vec![record(RecordType::A, 300, "192.0.2.1")]),
+            answers(
+                "198.51.100.53",
+                vec![record(RecordType::A, 60, "192.0.2.1")],
+            ),
+            failed,
+        ];
+        assert_eq!(
+            compare(&answers).to_string(),
+            "The 2 servers answered the same records\n  \
+             not compared, as queries failed: 203.0.113.53"
+        );
+        assert_eq!(compare(&answers).json(), json!([]));
+        assert_eq!(
+            compare(&answers[1..]).to_string(),
+            "Nothing to compare, 1 of the servers answered every query\n  \
+             not compared, as queries failed: 203.0.113.53"
+        );
+    }
+}
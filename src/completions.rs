@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::Cli;
+
+/// Write the completion script of `shell` for resoy into `out`, from the options of `Cli`,
+/// record types and connections completing to the values resoy knows
+pub fn generate(
+    shell: Shell,
+    out: &mut dyn Write,
+) {
+    let mut command = Cli::command();
+    let name = command.get_name().to_owned();
+    clap_complete::generate(shell, &mut command, name, out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The options depend on the features, the snapshot is that of the default build
+    #[cfg(all(feature = "doh", feature = "tls", feature = "dnssec"))]
+    #[test]
+    fn bash_script_matches_the_snapshot() {
+        // Written again by running the tests with `UPDATE_SNAPSHOTS=1`
+        const SNAPSHOT: &str = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/snapshots/completions.bash"
+        );
+        let mut script = Vec::new();
+        generate(Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(SNAPSHOT, &script).unwrap();
+        }
+        let expected = std::fs::read_to_string(SNAPSHOT).unwrap();
+        assert!(
+            script == expected,
+            "the bash completion changed, check it and run the tests with UPDATE_SNAPSHOTS=1"
+        );
+    }
+
+    #[test]
+    fn record_types_and_connections_complete_to_their_values() {
+        let mut script = Vec::new();
+        generate(Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("AAAA"));
+        assert!(script.contains("HTTPS"));
+        assert!(script.contains("udp tcp"));
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{clock::Clock, AppError};
+
+/// Highest number of names of `--file` resolved at the same time by default, whatever
+/// `--concurrency` says
+pub const DEFAULT_MAX: usize = 256;
+
+/// Names resolved at the same time when `--concurrency auto` starts, before the answers
+/// tell how many the server keeps up with
+pub const START: usize = 4;
+
+/// Shortest time over which the failures are counted before the limit changes, so that a
+/// burst of fast answers doesn't raise it many times over
+pub const WINDOW: Duration = Duration::from_millis(250);
+
+/// Share of the queries of a window that may time out or fail with SERVFAIL without the
+/// limit being lowered, as some always do on the internet
+pub const FAILURE_RATE: f64 = 0.05;
+
+/// How many names of `--file` are resolved at the same time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Concurrency {
+    Fixed(usize),
+    /// as many as the server answers without timing out or failing with SERVFAIL
+    Auto,
+}
+
+impl FromStr for Concurrency {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        match value.parse::<usize>() {
+            Ok(0) | Err(_) => Err(format!("invalid concurrency {:?}, e.g. 20 or auto", value)),
+            Ok(concurrency) => Ok(Self::Fixed(concurrency)),
+        }
+    }
+}
+
+impl Display for Concurrency {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Fixed(concurrency) => write!(f, "{}", concurrency),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl Serialize for Concurrency {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Fixed(concurrency) => serializer.serialize_u64(*concurrency as u64),
+            Self::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Concurrency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Number(usize),
+            Text(String),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::Number(concurrency) => Ok(Self::Fixed(concurrency.max(1))),
+            Value::Text(concurrency) => concurrency.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+/// Whether `error` tells of a server that gets more queries than it can take: a timeout, or
+/// SERVFAIL as resolvers answer when their own queries upstream pile up
+pub fn overloaded(error: &AppError) -> bool {
+    match error {
+        AppError::QueryTimeout(..) => true,
+        AppError::ResponseError(code, ..) => code == "SERVFAIL",
+        _ => false,
+    }
+}
+
+/// Why `--concurrency auto` changed its limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// no query of the window failed too often, while the limit was still doubled
+    SlowStart,
+    /// no query of the window failed too often
+    Increase,
+    /// too many queries of the window failed
+    Decrease,
+}
+
+/// A change of the limit of `--concurrency auto`, as `--verbose` prints it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decision {
+    /// time since the controller started
+    pub at: Duration,
+    pub from: usize,
+    pub to: usize,
+    /// queries of the window that timed out or failed with SERVFAIL
+    pub failures: usize,
+    /// queries of the window
+    pub queries: usize,
+    pub reason: Reason,
+}
+
+impl Display for Decision {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let reason = match self.reason {
+            Reason::SlowStart => ", doubling until the first failures",
+            Reason::Increase => "",
+            Reason::Decrease => ", too many",
+        };
+        write!(
+            f,
+            "concurrency {} -> {} at {}ms: {} of {} queries timed out or failed with \
+             SERVFAIL{}",
+            self.from,
+            self.to,
+            self.at.as_millis(),
+            self.failures,
+            self.queries,
+            reason
+        )
+    }
+}
+
+/// Controller of `--concurrency auto`: additive increase, multiplicative decrease.
+///
+/// The limit starts at `START` and doubles after every window in which few enough queries
+/// failed, until the first window with too many failures halves it. From then on it grows
+/// by one per window, and halves again whenever too many fail. A window lasts `WINDOW` at
+/// least, and as many queries as the limit, so that every query in flight when the limit
+/// changed counts in the next decision. The limit stays between 1 and `max`.
+pub struct Aimd {
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    max: usize,
+    limit: usize,
+    /// highest limit of the run
+    highest: usize,
+    slow_start: bool,
+    window_started: Instant,
+    queries: usize,
+    failures: usize,
+}
+
+impl Aimd {
+    pub fn new(
+        max: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let now = clock.now();
+        let limit = START.min(max.max(1));
+        Self {
+            clock,
+            started: now,
+            max: max.max(1),
+            limit,
+            highest: limit,
+            slow_start: true,
+            window_started: now,
+            queries: 0,
+            failures: 0,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn highest(&self) -> usize {
+        self.highest
+    }
+
+    /// Count a query that was answered, or that `overloaded` the server, returning the
+    /// change of the limit it brought when it ended a window
+    pub fn record(
+        &mut self,
+        overloaded: bool,
+    ) -> Option<Decision> {
+        self.queries += 1;
+        self.failures += usize::from(overloaded);
+        let now = self.clock.now();
+        if self.queries < self.limit || now.duration_since(self.window_started) < WINDOW {
+            return None;
+        }
+        let failed = self.failures as f64 > self.queries as f64 * FAILURE_RATE;
+        let (to, reason) = match (failed, self.slow_start) {
+            (true, _) => ((self.limit / 2).max(1), Reason::Decrease),
+            (false, true) => ((self.limit * 2).min(self.max), Reason::SlowStart),
+            (false, false) => ((self.limit + 1).min(self.max), Reason::Increase),
+        };
+        let decision = Decision {
+            at: now.duration_since(self.started),
+            from: self.limit,
+            to,
+            failures: self.failures,
+            queries: self.queries,
+            reason,
+        };
+        self.slow_start &= !failed;
+        self.limit = to;
+        self.highest = self.highest.max(to);
+        self.window_started = now;
+        self.queries = 0;
+        self.failures = 0;
+        (decision.from != decision.to).then_some(decision)
+    }
+}
+
+/// Gate of the names of `--file` resolved at the same time, up to a fixed number or the
+/// limit of an `Aimd` controller
+pub struct Limiter {
+    aimd: Option<Mutex<Aimd>>,
+    /// names being resolved, and the limit of them
+    in_flight: Mutex<(usize, usize)>,
+    released: Condvar,
+    /// names that can be resolved at the same time at most, with the limit at its highest
+    workers: usize,
+}
+
+impl Limiter {
+    pub fn fixed(concurrency: usize) -> Self {
+        Self {
+            aimd: None,
+            in_flight: Mutex::new((0, concurrency.max(1))),
+            released: Condvar::new(),
+            workers: concurrency.max(1),
+        }
+    }
+
+    pub fn adaptive(aimd: Aimd) -> Self {
+        let (limit, workers) = (aimd.limit(), aimd.max);
+        Self {
+            aimd: Some(Mutex::new(aimd)),
+            in_flight: Mutex::new((0, limit)),
+            released: Condvar::new(),
+            workers,
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Wait until another name may be resolved
+    pub fn acquire(&self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while in_flight.0 >= in_flight.1 {
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+        in_flight.0 += 1;
+    }
+
+    /// End the resolution of a name that `overloaded` the server or not, returning how the
+    /// limit changed for it
+    pub fn release(
+        &self,
+        overloaded: bool,
+    ) -> Option<Decision> {
+        let decision = self
+            .aimd
+            .as_ref()
+            .and_then(|aimd| aimd.lock().unwrap().record(overloaded));
+        let mut in_flight = self.in_flight.lock().unwrap();
+        in_flight.0 -= 1;
+        if let Some(decision) = decision {
+            in_flight.1 = decision.to;
+        }
+        self.released.notify_all();
+        decision
+    }
+
+    /// The limit at the end of the run and the highest one, those of the controller
+    pub fn limits(&self) -> Option<(usize, usize)> {
+        let aimd = self.aimd.as_ref()?.lock().unwrap();
+        Some((aimd.limit(), aimd.highest()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Run `rounds` rounds of as many queries as the limit against a server that answers
+    /// `capacity` of them at the same time and times out on the others, each round taking
+    /// `WINDOW`, returning the limit of every round and the decisions
+    fn simulate(
+        capacity: usize,
+        max: usize,
+        rounds: usize,
+    ) -> (Vec<usize>, Vec<Decision>) {
+        let clock = Arc::new(ManualClock::new());
+        let mut aimd = Aimd::new(max, clock.clone());
+        let (mut limits, mut decisions) = (Vec::new(), Vec::new());
+        for _ in 0..rounds {
+            let limit = aimd.limit();
+            limits.push(limit);
+            clock.advance(WINDOW);
+            for query in 0..limit {
+                decisions.extend(aimd.record(query >= capacity));
+            }
+        }
+        (limits, decisions)
+    }
+
+    #[test]
+    fn the_limit_doubles_then_settles_under_the_capacity() {
+        let (limits, decisions) = simulate(40, DEFAULT_MAX, 30);
+        assert_eq!(limits[..6], [4, 8, 16, 32, 64, 32]);
+        assert_eq!(decisions[0].reason, Reason::SlowStart);
+        assert_eq!(decisions[4].reason, Reason::Decrease);
+        assert_eq!(decisions[4].failures, 24);
+        // Additive increase up to the capacity, halving past it
+        assert_eq!(
+            limits[6..18],
+            [33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 21]
+        );
+        assert!(limits.iter().all(|limit| *limit <= 64));
+        assert_eq!(
+            decisions[4].to_string(),
+            "concurrency 64 -> 32 at 1250ms: 24 of 64 queries timed out or failed with \
+             SERVFAIL, too many"
+        );
+    }
+
+    #[test]
+    fn the_limit_never_exceeds_the_ceiling() {
+        let (limits, decisions) = simulate(usize::MAX, 10, 20);
+        assert_eq!(limits[..4], [4, 8, 10, 10]);
+        assert!(limits.iter().all(|limit| *limit <= 10));
+        // The limit stays as it is at the ceiling, which nothing prints
+        assert_eq!(decisions.len(), 2);
+        let (limits, _) = simulate(usize::MAX, 2, 5);
+        assert_eq!(limits, [2; 5]);
+    }
+
+    #[test]
+    fn windows_last_long_enough_to_count() {
+        let clock = Arc::new(ManualClock::new());
+        let mut aimd = Aimd::new(DEFAULT_MAX, clock.clone());
+        // Queries answered faster than the window don't change the limit
+        for _ in 0..100 {
+            assert_eq!(aimd.record(false), None);
+        }
+        clock.advance(WINDOW);
+        assert_eq!(aimd.record(false).map(|decision| decision.to), Some(8));
+        // A server failing everything keeps one query in flight
+        for _ in 0..10 {
+            clock.advance(WINDOW);
+            for _ in 0..aimd.limit() {
+                aimd.record(true);
+            }
+        }
+        assert_eq!(aimd.limit(), 1);
+        assert_eq!(aimd.highest(), 8);
+    }
+
+    #[test]
+    fn the_limiter_holds_names_past_the_limit() {
+        let limiter = Limiter::fixed(2);
+        limiter.acquire();
+        limiter.acquire();
+        assert_eq!(*limiter.in_flight.lock().unwrap(), (2, 2));
+        assert_eq!(limiter.release(true), None);
+        limiter.acquire();
+        assert_eq!(limiter.limits(), None);
+
+        let clock = Arc::new(ManualClock::new());
+        let limiter = Limiter::adaptive(Aimd::new(DEFAULT_MAX, clock.clone()));
+        clock.advance(WINDOW);
+        for _ in 0..START {
+            limiter.acquire();
+        }
+        let decisions: Vec<Decision> = (0..START).filter_map(|_| limiter.release(false)).collect();
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(*limiter.in_flight.lock().unwrap(), (0, 8));
+        assert_eq!(limiter.limits(), Some((8, 8)));
+    }
+
+    #[test]
+    fn concurrency_is_a_number_or_auto() {
+        assert_eq!("20".parse(), Ok(Concurrency::Fixed(20)));
+        assert_eq!("AUTO".parse(), Ok(Concurrency::Auto));
+        assert!("0".parse::<Concurrency>().is_err());
+        assert!("many".parse::<Concurrency>().is_err());
+        assert_eq!(Concurrency::Auto.to_string(), "auto");
+    }
+}
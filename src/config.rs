@@ -0,0 +1,88 @@
+use clap::{parser::ValueSource, ArgMatches};
+use serde_json::Value;
+
+use crate::{output, Cli};
+
+/// Where the effective value of a setting came from
+#[derive(Clone, Copy)]
+pub enum Source {
+    Default,
+    Flag,
+    Manifest,
+}
+
+impl Source {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Flag => "flag",
+            Self::Manifest => "manifest",
+        }
+    }
+}
+
+/// Effective value of a setting and its origin
+pub struct Setting {
+    pub key: String,
+    pub value: Value,
+    pub source: Source,
+}
+
+/// The merged configuration of a run, as printed by `--show-config`
+pub struct EffectiveConfig {
+    pub settings: Vec<Setting>,
+}
+
+impl EffectiveConfig {
+    /// Settings of `cli` after merging, `recorded` being the keys `--from-manifest` changed
+    pub fn new(
+        cli: &Cli,
+        matches: &ArgMatches,
+        recorded: &[String],
+    ) -> Self {
+        let Value::Object(config) = serde_json::to_value(cli).unwrap() else {
+            unreachable!("Cli serializes to an object")
+        };
+        let settings = config
+            .into_iter()
+            .map(|(key, value)| {
+                let source = if recorded.contains(&key) {
+                    Source::Manifest
+                } else if matches.value_source(&key) == Some(ValueSource::CommandLine) {
+                    Source::Flag
+                } else {
+                    Source::Default
+                };
+                Setting {
+                    key,
+                    value,
+                    source,
+                }
+            })
+            .collect();
+        Self {
+            settings,
+        }
+    }
+
+    pub fn print(&self) {
+        let width = self
+            .settings
+            .iter()
+            .map(|setting| setting.key.len())
+            .max()
+            .unwrap_or_default();
+        let _ = output::record(|out| {
+            for setting in &self.settings {
+                writeln!(
+                    out,
+                    "{:<width$}  {:<8} {}",
+                    setting.key,
+                    setting.source.as_str(),
+                    setting.value,
+                )?;
+            }
+            Ok(())
+        });
+    }
+}
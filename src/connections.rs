@@ -0,0 +1,108 @@
+use std::{fmt::Display, ops::Sub, sync::Mutex, time::Duration};
+
+/// Connections that a client over TLS or HTTPS opened and reused, as `--watch` reports them
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Connections {
+    /// connections opened, the first one included
+    pub opened: usize,
+    /// of those, the TLS sessions resumed from an earlier connection
+    pub resumed: usize,
+    /// queries sent over a connection that was already open
+    pub reused: usize,
+    /// time spent opening connections, which the time of the queries leaves out
+    pub handshakes: Duration,
+}
+
+impl Sub for Connections {
+    type Output = Self;
+
+    fn sub(
+        self,
+        earlier: Self,
+    ) -> Self {
+        Self {
+            opened: self.opened - earlier.opened,
+            resumed: self.resumed - earlier.resumed,
+            reused: self.reused - earlier.reused,
+            handshakes: self.handshakes.saturating_sub(earlier.handshakes),
+        }
+    }
+}
+
+impl Display for Connections {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "{} connections opened in {}ms, {} queries over one already open",
+            self.opened,
+            self.handshakes.as_millis(),
+            self.reused
+        )?;
+        if self.resumed > 0 {
+            write!(f, ", {} TLS sessions resumed", self.resumed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts of the connections of a client, shared with what opens them
+#[derive(Default)]
+pub struct ConnectionCounter {
+    connections: Mutex<Connections>,
+}
+
+impl ConnectionCounter {
+    /// Count a connection that took `handshake` to open
+    pub fn opened(
+        &self,
+        handshake: Duration,
+        resumed: bool,
+    ) {
+        let mut connections = self.connections.lock().unwrap();
+        connections.opened += 1;
+        connections.resumed += usize::from(resumed);
+        connections.handshakes += handshake;
+    }
+
+    /// Count a query sent over a connection that was already open
+    pub fn reused(&self) {
+        self.connections.lock().unwrap().reused += 1;
+    }
+
+    pub fn get(&self) -> Connections {
+        *self.connections.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_are_counted_and_compared() {
+        let counter = ConnectionCounter::default();
+        counter.opened(Duration::from_millis(120), false);
+        let first = counter.get();
+        counter.reused();
+        counter.reused();
+        counter.opened(Duration::from_millis(40), true);
+        let delta = counter.get() - first;
+        assert_eq!(
+            delta,
+            Connections {
+                opened: 1,
+                resumed: 1,
+                reused: 2,
+                handshakes: Duration::from_millis(40),
+            }
+        );
+        assert_eq!(
+            counter.get().to_string(),
+            "2 connections opened in 160ms, 2 queries over one already open, 1 TLS sessions \
+             resumed"
+        );
+    }
+}
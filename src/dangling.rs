@@ -0,0 +1,368 @@
+use std::{collections::HashMap, fmt::Display, net::IpAddr, str::FromStr, thread, time::Duration};
+
+use hickory_client::{
+    op::{DnsResponse, ResponseCode},
+    rr::{DNSClass, Name, RData, Record, RecordType},
+};
+
+use crate::{format, DnsClient};
+
+/// Timeout of every follow-up query of `--check-dangling`
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Types of the checked name whose answers can dangle
+const CHECKED_TYPES: [RecordType; 5] = [
+    RecordType::CNAME,
+    RecordType::MX,
+    RecordType::NS,
+    RecordType::A,
+    RecordType::AAAA,
+];
+
+/// Suffixes of hosting providers whose unclaimed names can be registered by anyone
+const PROVIDER_SUFFIXES: &[&str] = &[
+    "azurewebsites.net",
+    "cloudapp.net",
+    "trafficmanager.net",
+    "blob.core.windows.net",
+    "s3.amazonaws.com",
+    "elasticbeanstalk.com",
+    "cloudfront.net",
+    "herokuapp.com",
+    "herokudns.com",
+    "github.io",
+    "gitlab.io",
+    "netlify.app",
+    "vercel.app",
+    "pages.dev",
+    "fastly.net",
+    "ghost.io",
+    "myshopify.com",
+    "surge.sh",
+    "readthedocs.io",
+    "zendesk.com",
+];
+
+/// A block of addresses given with `--our-cidrs`
+#[derive(Clone, Copy)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let address = IpAddr::from_str(address).map_err(|_| ())?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| ())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(());
+        }
+        Ok(Self {
+            address,
+            prefix,
+        })
+    }
+}
+
+impl Cidr {
+    fn contains(
+        &self,
+        address: IpAddr,
+    ) -> bool {
+        match (self.address, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Result of a query, as far as the checks are concerned
+#[derive(Clone)]
+enum Lookup {
+    Answered(ResponseCode, Vec<Record>),
+    Failed(String),
+}
+
+impl Lookup {
+    fn from_response(response: &DnsResponse) -> Self {
+        Self::Answered(response.response_code(), response.answers().to_vec())
+    }
+
+    fn has_address(&self) -> bool {
+        matches!(self, Self::Answered(_, answers) if answers
+            .iter()
+            .any(|record| matches!(record.record_type(), RecordType::A | RecordType::AAAA)))
+    }
+
+    fn is_nxdomain(&self) -> bool {
+        matches!(self, Self::Answered(ResponseCode::NXDomain, _))
+    }
+}
+
+/// Queries made during a check, shared by all records pointing at the same name
+struct Lookups<'a> {
+    client: &'a DnsClient,
+    cache: HashMap<(Name, RecordType), Lookup>,
+}
+
+impl<'a> Lookups<'a> {
+    /// Query every key that is not cached yet, concurrently
+    fn fetch(
+        &mut self,
+        keys: &[(Name, RecordType)],
+    ) {
+        let mut missing: Vec<&(Name, RecordType)> = Vec::new();
+        for key in keys {
+            if !self.cache.contains_key(key) && !missing.contains(&key) {
+                missing.push(key);
+            }
+        }
+
+        let client = self.client;
+        let fetched: Vec<((Name, RecordType), Lookup)> = thread::scope(|scope| {
+            let handles: Vec<_> = missing
+                .into_iter()
+                .map(|(name, record_type)| {
+                    scope.spawn(move || {
+                        let lookup = match client.query(name, DNSClass::IN, *record_type) {
+                            Ok(response) => Lookup::from_response(&response),
+                            Err(error) => Lookup::Failed(error.to_string()),
+                        };
+                        ((name.clone(), *record_type), lookup)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+        self.cache.extend(fetched);
+    }
+
+    fn get(
+        &self,
+        name: &Name,
+        record_type: RecordType,
+    ) -> Option<&Lookup> {
+        self.cache.get(&(name.clone(), record_type))
+    }
+
+    /// Evidence lines of the queries made for `name`
+    fn evidence(
+        &self,
+        name: &Name,
+        record_types: &[RecordType],
+    ) -> Vec<String> {
+        record_types
+            .iter()
+            .filter_map(|record_type| {
+                let lookup = self.get(name, *record_type)?;
+                Some(match lookup {
+                    Lookup::Answered(response_code, answers) if answers.is_empty() => format!(
+                        "{} {}: {}, no records",
+                        name,
+                        record_type,
+                        format!("{:?}", response_code).to_uppercase()
+                    ),
+                    Lookup::Answered(response_code, answers) => {
+                        let answers: Vec<String> = answers
+                            .iter()
+                            .map(|record| {
+                                let data = record.data().map(format::data_text);
+                                format!("{} {}", record.record_type(), data.unwrap_or_default())
+                            })
+                            .collect();
+                        format!(
+                            "{} {}: {}, {}",
+                            name,
+                            record_type,
+                            format!("{:?}", response_code).to_uppercase(),
+                            answers.join(", ")
+                        )
+                    },
+                    Lookup::Failed(error) => format!("{} {}: {}", name, record_type, error),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A record pointing at something that is likely no longer ours
+pub struct Finding {
+    pub record: Record,
+    pub reason: String,
+    pub evidence: Vec<String>,
+}
+
+impl Display for Finding {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let data = self
+            .record
+            .data()
+            .map(format::data_text)
+            .unwrap_or_default();
+        write!(
+            f,
+            "  {} {} {}: {}",
+            self.record.name(),
+            self.record.record_type(),
+            data,
+            self.reason
+        )?;
+        for line in &self.evidence {
+            write!(f, "\n    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn provider_of(name: &Name) -> Option<&'static str> {
+    let ascii = name.to_ascii().trim_end_matches('.').to_lowercase();
+    PROVIDER_SUFFIXES
+        .iter()
+        .copied()
+        .find(|suffix| ascii == *suffix || ascii.ends_with(&format!(".{}", suffix)))
+}
+
+/// Look for CNAME, MX and NS records of `name` pointing at names that don't exist or resolve,
+/// and for addresses outside of `our_cidrs` if any are given.
+///
+/// `responses` are reused, other queries are made concurrently and at most once per run.
+pub fn check(
+    client: &DnsClient,
+    name: &Name,
+    responses: &[(RecordType, DnsResponse)],
+    our_cidrs: &[Cidr],
+) -> Vec<Finding> {
+    let mut lookups = Lookups {
+        client,
+        cache: responses
+            .iter()
+            .map(|(record_type, response)| {
+                (
+                    (name.clone(), *record_type),
+                    Lookup::from_response(response),
+                )
+            })
+            .collect(),
+    };
+    let keys: Vec<(Name, RecordType)> = CHECKED_TYPES
+        .iter()
+        .map(|record_type| (name.clone(), *record_type))
+        .collect();
+    lookups.fetch(&keys);
+
+    let mut records: Vec<Record> = Vec::new();
+    for (_, record_type) in &keys {
+        if let Some(Lookup::Answered(_, answers)) = lookups.get(name, *record_type) {
+            for record in answers {
+                if !records.contains(record) {
+                    records.push(record.clone());
+                }
+            }
+        }
+    }
+
+    let address_types = [RecordType::A, RecordType::AAAA];
+    let targets: Vec<(Name, RecordType)> = records
+        .iter()
+        .filter_map(|record| match record.data() {
+            Some(RData::CNAME(target)) => Some(target.0.clone()),
+            Some(RData::MX(mx)) if !mx.exchange().is_root() => Some(mx.exchange().clone()),
+            Some(RData::NS(target)) => Some(target.0.clone()),
+            _ => None,
+        })
+        .flat_map(|target| address_types.map(|record_type| (target.clone(), record_type)))
+        .collect();
+    lookups.fetch(&targets);
+
+    let resolves = |target: &Name| {
+        address_types.iter().any(|record_type| {
+            lookups
+                .get(target, *record_type)
+                .is_some_and(Lookup::has_address)
+        })
+    };
+    let does_not_exist = |target: &Name| {
+        address_types.iter().any(|record_type| {
+            lookups
+                .get(target, *record_type)
+                .is_some_and(Lookup::is_nxdomain)
+        })
+    };
+
+    let mut findings: Vec<Finding> = Vec::new();
+    for record in records {
+        let reason = match record.data() {
+            Some(RData::CNAME(target)) if does_not_exist(target) => {
+                let reason = match provider_of(target) {
+                    Some(provider) => format!(
+                        "the target does not exist at {}, the name may be claimed by anyone",
+                        provider
+                    ),
+                    None => String::from("the target does not exist"),
+                };
+                Some((reason, Some(target.0.clone())))
+            },
+            Some(RData::MX(mx)) if !mx.exchange().is_root() && !resolves(mx.exchange()) => Some((
+                String::from("the mail server does not resolve"),
+                Some(mx.exchange().clone()),
+            )),
+            Some(RData::NS(target)) if !resolves(target) => Some((
+                String::from("the name server does not resolve"),
+                Some(target.0.clone()),
+            )),
+            Some(RData::A(address)) if !is_ours(IpAddr::V4(address.0), our_cidrs) => {
+                Some((String::from("the address is not in --our-cidrs"), None))
+            },
+            Some(RData::AAAA(address)) if !is_ours(IpAddr::V6(address.0), our_cidrs) => {
+                Some((String::from("the address is not in --our-cidrs"), None))
+            },
+            _ => None,
+        };
+        if let Some((reason, target)) = reason {
+            let evidence = target
+                .map(|target| lookups.evidence(&target, &address_types))
+                .unwrap_or_default();
+            findings.push(Finding {
+                record,
+                reason,
+                evidence,
+            });
+        }
+    }
+    findings
+}
+
+/// Every address is ours when no blocks are given
+fn is_ours(
+    address: IpAddr,
+    our_cidrs: &[Cidr],
+) -> bool {
+    our_cidrs.is_empty() || our_cidrs.iter().any(|cidr| cidr.contains(address))
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use hickory_client::{
+    error::{ClientError, ClientResult},
+    op::DnsResponse,
+    rr::{DNSClass, Name, RecordType},
+};
+
+/// Server, name, class and type of a question
+type Key = (String, Name, DNSClass, RecordType);
+
+/// Answer to a question, filled in once the query that asked it returns
+#[derive(Default)]
+struct Slot {
+    result: Mutex<Option<Result<DnsResponse, String>>>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// questions in flight
+    slots: HashMap<Key, Arc<Slot>>,
+    shared: usize,
+}
+
+/// Questions in flight in a run, shared by every feature and every client of it
+#[derive(Default)]
+pub struct Questions {
+    state: Mutex<State>,
+}
+
+/// The query of a question in flight, which takes it out of the questions in flight once it
+/// returns, and hands its waiters an error if it panicked rather than leaving them waiting
+struct Flight<'q> {
+    questions: &'q Questions,
+    key: Key,
+    slot: Arc<Slot>,
+}
+
+impl Flight<'_> {
+    fn land(
+        self,
+        result: &ClientResult<DnsResponse>,
+    ) {
+        let shared = match result {
+            Ok(response) => Ok(response.clone()),
+            Err(error) => Err(error.to_string()),
+        };
+        *self.slot.result.lock().unwrap() = Some(shared);
+    }
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        self.questions.state.lock().unwrap().slots.remove(&self.key);
+        let mut result = self
+            .slot
+            .result
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if result.is_none() {
+            *result = Some(Err(String::from("the query of the question panicked")));
+        }
+        self.slot.ready.notify_all();
+    }
+}
+
+impl Questions {
+    /// Answer the question with `send`, unless the same server is being asked it already:
+    /// then the answer of that query is shared, once it returns.
+    ///
+    /// Only queries in flight are shared, so questions asked again later are sent again, and
+    /// the questions of a run take no memory once their queries returned.
+    pub fn query(
+        &self,
+        server: String,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+        send: impl FnOnce() -> ClientResult<DnsResponse>,
+    ) -> ClientResult<DnsResponse> {
+        let key = (server, name.to_lowercase(), query_class, query_type);
+        let (slot, asking) = {
+            let mut state = self.state.lock().unwrap();
+            match state.slots.get(&key) {
+                Some(slot) => {
+                    let slot = slot.clone();
+                    state.shared += 1;
+                    (slot, false)
+                },
+                None => {
+                    let slot = Arc::new(Slot::default());
+                    state.slots.insert(key.clone(), slot.clone());
+                    (slot, true)
+                },
+            }
+        };
+
+        if asking {
+            let flight = Flight {
+                questions: self,
+                key,
+                slot,
+            };
+            let result = send();
+            flight.land(&result);
+            return result;
+        }
+
+        let mut result = slot.result.lock().unwrap();
+        while result.is_none() {
+            result = slot.ready.wait(result).unwrap();
+        }
+        match result.as_ref() {
+            Some(Ok(response)) => Ok(response.clone()),
+            Some(Err(error)) => Err(ClientError::from(error.clone())),
+            None => unreachable!(),
+        }
+    }
+
+    /// How many questions were answered by the query of another one in flight
+    pub fn shared(&self) -> usize {
+        self.state.lock().unwrap().shared
+    }
+
+    /// How many questions are in flight
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().slots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Barrier,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use hickory_client::{error::ClientErrorKind, op::Message};
+
+    use super::*;
+
+    fn example() -> Name {
+        Name::from_str("example.com.").unwrap()
+    }
+
+    fn answer() -> ClientResult<DnsResponse> {
+        Ok(DnsResponse::from_message(Message::new()).unwrap())
+    }
+
+    fn ask(
+        questions: &Questions,
+        send: impl FnOnce() -> ClientResult<DnsResponse>,
+    ) -> ClientResult<DnsResponse> {
+        let server = String::from("udp://192.0.2.53:53");
+        questions.query(server, &example(), DNSClass::IN, RecordType::A, send)
+    }
+
+    #[test]
+    fn questions_in_flight_share_one_query() {
+        let questions = Questions::default();
+        let sent = AtomicUsize::new(0);
+        let asking = Barrier::new(8);
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    asking.wait();
+                    ask(&questions, || {
+                        sent.fetch_add(1, Ordering::SeqCst);
+                        // Long enough for the others to ask meanwhile
+                        thread::sleep(Duration::from_millis(200));
+                        answer()
+                    })
+                    .unwrap();
+                });
+            }
+        });
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+        assert_eq!(questions.shared(), 7);
+        assert_eq!(questions.in_flight(), 0);
+    }
+
+    #[test]
+    fn questions_asked_after_their_query_returned_are_sent_again() {
+        let questions = Questions::default();
+        let sent = AtomicUsize::new(0);
+        for _ in 0..3 {
+            ask(&questions, || {
+                sent.fetch_add(1, Ordering::SeqCst);
+                answer()
+            })
+            .unwrap();
+        }
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+        assert_eq!(questions.shared(), 0);
+        assert_eq!(questions.in_flight(), 0);
+    }
+
+    #[test]
+    fn other_questions_are_not_shared() {
+        let questions = Questions::default();
+        let server = || String::from("udp://192.0.2.53:53");
+        let other_server = String::from("udp://198.51.100.53:53");
+        let slow = || {
+            thread::sleep(Duration::from_millis(100));
+            answer()
+        };
+        thread::scope(|scope| {
+            scope
+                .spawn(|| questions.query(server(), &example(), DNSClass::IN, RecordType::A, slow));
+            scope.spawn(|| {
+                questions.query(server(), &example(), DNSClass::IN, RecordType::AAAA, slow)
+            });
+            scope.spawn(|| {
+                questions.query(other_server, &example(), DNSClass::IN, RecordType::A, slow)
+            });
+        });
+        assert_eq!(questions.shared(), 0);
+    }
+
+    #[test]
+    fn waiters_get_an_error_when_the_query_fails_or_panics() {
+        let questions = Questions::default();
+        let started = Barrier::new(2);
+        thread::scope(|scope| {
+            let asking = scope.spawn(|| {
+                panic::catch_unwind(AssertUnwindSafe(|| {
+                    ask(&questions, || {
+                        started.wait();
+                        thread::sleep(Duration::from_millis(200));
+                        panic!("lost the connection");
+                    })
+                }))
+            });
+            started.wait();
+            let waiting = ask(&questions, answer);
+            let error = waiting.unwrap_err();
+            assert!(error.to_string().contains("panicked"), "{}", error);
+            assert!(asking.join().unwrap().is_err());
+        });
+        assert_eq!(questions.in_flight(), 0);
+
+        // Failures reach the waiters as they are
+        let error = ask(&questions, || {
+            Err(ClientError::from(ClientErrorKind::Timeout))
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+        assert_eq!(questions.in_flight(), 0);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{collections::HashSet, fmt::Display, io::BufRead, path::PathBuf, str::FromStr};
+
+use clap::Args;
+use hickory_client::rr::{Name, RecordType};
+
+use crate::{batch, output, parse, AppError};
+
+/// A query of a dnsperf file, a line of a name and a type such as `example.com A`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Query {
+    /// the name fully qualified in lower case, the same query however it was written
+    pub name: Name,
+    pub record_type: RecordType,
+}
+
+impl Display for Query {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        // dnsperf reads the names as they go on the wire, the A-labels of internationalized
+        // ones included, and types without a name as in RFC 3597
+        let record_type = match self.record_type {
+            RecordType::Unknown(code) => format!("TYPE{}", code),
+            record_type => record_type.to_string(),
+        };
+        write!(f, "{} {}", self.name.to_ascii(), record_type)
+    }
+}
+
+/// Parse a line of a dnsperf file, a name or an address and a record type separated by
+/// whitespace, e.g. `example.com MX` or `example.com TYPE65280`
+pub fn parse_line(line: &str) -> Result<Query, AppError> {
+    let invalid = |reason: &str| AppError::InvalidQueryLine(line.to_owned(), reason.to_owned());
+    let mut fields = line.split_whitespace();
+    let (Some(name), Some(record_type)) = (fields.next(), fields.next()) else {
+        return Err(invalid("it has no record type after its name"));
+    };
+    if fields.next().is_some() {
+        return Err(invalid("it has more than a name and a record type"));
+    }
+    let name = query_name(name)?;
+    let record_type = parse_record_type(record_type)?;
+    Ok(Query {
+        name,
+        record_type,
+    })
+}
+
+/// The name of `argument` as queries go out for it, the reverse name of addresses
+fn query_name(argument: &str) -> Result<Name, AppError> {
+    let mut name = parse::parse_domain_name(Some(argument))?.to_lowercase();
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+/// A record type by its name in any case, or as TYPE<N> for any type
+fn parse_record_type(value: &str) -> Result<RecordType, AppError> {
+    let upper = value.to_ascii_uppercase();
+    if let Some(code) = upper.strip_prefix("TYPE") {
+        if let Ok(code) = code.parse::<u16>() {
+            return Ok(RecordType::from(code));
+        }
+    }
+    RecordType::from_str(&upper).map_err(|_| AppError::UnknownRecordType(value.to_owned()))
+}
+
+/// Queries of an input, and the lines of it that don't parse
+#[derive(Default)]
+pub struct Corpus {
+    /// queries in the order of their lines, without those asked before
+    pub queries: Vec<Query>,
+    /// lines read, blank and comment lines left out
+    pub lines: usize,
+    /// lines that don't parse, by their number
+    pub failures: Vec<(usize, AppError)>,
+}
+
+/// Read the queries of the lines of `input`.
+///
+/// Every line is a name of the batch input of `--file`, asked for every type of
+/// `record_types` as `--file` asks it, `all` for the common ones, or with `dnsperf` a query
+/// of a dnsperf file. Blank lines and everything after a `#` are ignored either way.
+pub fn read_queries(
+    input: &mut dyn BufRead,
+    record_types: &[String],
+    dnsperf: bool,
+) -> Corpus {
+    let mut corpus = Corpus::default();
+    let mut seen = HashSet::new();
+    let mut line_number = 0;
+    while let Some((line, argument)) = batch::next_name(input, &mut line_number) {
+        corpus.lines += 1;
+        let parsed = match dnsperf {
+            true => parse_line(&argument).map(|query| vec![query]),
+            false => names_queries(&argument, record_types),
+        };
+        match parsed {
+            Ok(parsed) => {
+                for query in parsed {
+                    if seen.insert(query.clone()) {
+                        corpus.queries.push(query);
+                    }
+                }
+            },
+            Err(error) => corpus.failures.push((line, error)),
+        }
+    }
+    corpus
+}
+
+/// Queries of `argument` for every type of `record_types`, as `--file` sends them
+fn names_queries(
+    argument: &str,
+    record_types: &[String],
+) -> Result<Vec<Query>, AppError> {
+    let name = query_name(argument)?;
+    let record_types = parse::parse_record_types(record_types, Some(argument))?;
+    Ok(record_types
+        .into_iter()
+        .map(|record_type| Query {
+            name: name.clone(),
+            record_type,
+        })
+        .collect())
+}
+
+/// Put `queries` in an order that only depends on `seed`, so that a load test can be run
+/// again with the same one
+pub fn shuffle(
+    queries: &mut [Query],
+    seed: u64,
+) {
+    // SplitMix64, the same sequence on every platform and release unlike what a crate may
+    // change its mind about
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    // Fisher-Yates, the remainder of the modulo being a bias too small to matter here
+    for i in (1..queries.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        queries.swap(i, j);
+    }
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// batch input to export, one name per line as for --file, or - for stdin
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// record types to ask every name for, all for the common ones [default: A, or PTR for
+    /// addresses]
+    #[arg(value_name = "TYPE")]
+    record_types: Vec<String>,
+
+    /// read the input as a dnsperf file of a name and a type per line instead, e.g. to
+    /// deduplicate or shuffle one
+    #[arg(long, conflicts_with = "record_types")]
+    import_dnsperf: bool,
+
+    /// shuffle the queries, in the same order for the same --seed
+    #[arg(long)]
+    shuffle: bool,
+
+    /// seed of --shuffle, another one giving another order of the same queries
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "shuffle")]
+    seed: u64,
+}
+
+impl ExportArgs {
+    /// Print the queries of the input as a dnsperf file, one `name type` line per query.
+    ///
+    /// Lines that don't parse are reported on stderr with their number while the others are
+    /// still exported, and the run fails when there were some.
+    pub fn run(&self) -> Result<(), AppError> {
+        let mut input = batch::open(&self.file)?;
+        let Corpus {
+            mut queries,
+            lines,
+            failures,
+        } = read_queries(input.as_mut(), &self.record_types, self.import_dnsperf);
+        for (line, error) in &failures {
+            eprintln!("Error: line {}: {}", line, error);
+        }
+        if self.shuffle {
+            shuffle(&mut queries, self.seed);
+        }
+        let _ = output::record(|out| {
+            for query in &queries {
+                writeln!(out, "{}", query)?;
+            }
+            Ok(())
+        });
+        match failures.len() {
+            0 => Ok(()),
+            failed => Err(AppError::NamesFailed(failed, lines, 1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exported(
+        input: &str,
+        record_types: &[&str],
+        dnsperf: bool,
+    ) -> (Vec<String>, Vec<usize>) {
+        let record_types: Vec<String> = record_types.iter().map(|t| t.to_string()).collect();
+        let corpus = read_queries(&mut input.as_bytes(), &record_types, dnsperf);
+        (
+            corpus.queries.iter().map(Query::to_string).collect(),
+            corpus.failures.iter().map(|(line, _)| *line).collect(),
+        )
+    }
+
+    #[test]
+    fn batch_input_is_expanded_and_deduplicated() {
+        let input = "# hosts\nexample.com\n\nwww.example.com # the site\nEXAMPLE.com.\n192.0.2.1\n";
+        let (queries, failures) = exported(input, &["A", "mx", "a"], false);
+        assert_eq!(
+            queries,
+            [
+                "example.com. A",
+                "example.com. MX",
+                "www.example.com. A",
+                "www.example.com. MX",
+                "1.2.0.192.in-addr.arpa. A",
+                "1.2.0.192.in-addr.arpa. MX",
+            ]
+        );
+        assert!(failures.is_empty());
+        // Addresses are asked for their PTR records by default, as with --file
+        let (queries, _) = exported("192.0.2.1\nmünchen.de\n", &[], false);
+        assert_eq!(
+            queries,
+            ["1.2.0.192.in-addr.arpa. PTR", "xn--mnchen-3ya.de. A"]
+        );
+        let (queries, _) = exported("example.com\n", &["all"], false);
+        assert_eq!(queries.len(), parse::COMMON_RECORD_TYPES.len());
+    }
+
+    #[test]
+    fn dnsperf_lines_are_read_back() {
+        let input = "example.com A\nexample.com\ta\nexample.com TYPE65280\nbad..name A\n\
+                     example.com\nexample.com A extra\nexample.com NOTATYPE\n";
+        let (queries, failures) = exported(input, &[], true);
+        assert_eq!(queries, ["example.com. A", "example.com. TYPE65280"]);
+        assert_eq!(failures, [4, 5, 6, 7]);
+        assert_eq!(
+            parse_line("example.com").unwrap_err().to_string(),
+            "Cannot read the query \"example.com\": it has no record type after its name"
+        );
+        // What is exported reads back the same
+        let (again, _) = exported(&(queries.join("\n") + "\n"), &[], true);
+        assert_eq!(again, queries);
+    }
+
+    #[test]
+    fn shuffles_depend_on_their_seed_only() {
+        let (queries, _) = exported(
+            &(0..50)
+                .map(|i| format!("host{}.example.com\n", i))
+                .collect::<String>(),
+            &[],
+            false,
+        );
+        let names: Vec<Query> = queries.iter().map(|q| parse_line(q).unwrap()).collect();
+        let shuffled = |seed| {
+            let mut names = names.clone();
+            shuffle(&mut names, seed);
+            names
+        };
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+        assert_ne!(shuffled(7), names);
+        let mut sorted = shuffled(7);
+        sorted.sort_by_key(|query| query.name.to_string());
+        let mut expected = names.clone();
+        expected.sort_by_key(|query| query.name.to_string());
+        assert_eq!(sorted, expected);
+    }
+}
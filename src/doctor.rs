@@ -0,0 +1,313 @@
+#[cfg(unix)]
+use std::fs;
+use std::{
+    io::{stdout, IsTerminal},
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use hickory_client::rr::{DNSClass, Name, RecordType};
+use serde::Serialize;
+
+#[cfg(unix)]
+use crate::resolv_conf::{nameservers, RESOLV_CONF};
+use crate::{
+    outln, output, resolv_conf, schema, AppError, ConnectionType, DnsClient, ResolveOptions,
+};
+
+/// Timeout of every network check, so the doctor never hangs
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Address used to find out whether there is an IPv6 route, nothing is sent to it
+const IPV6_PROBE: &str = "[2606:4700:4700::1111]:53";
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// dns server to check, the first name server of the system by default
+    #[arg(short, long)]
+    server: Option<String>,
+
+    /// print the checks as JSON
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remedy: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(
+        name: &'static str,
+        detail: String,
+    ) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail,
+            remedy: None,
+        }
+    }
+
+    fn with_problem(
+        name: &'static str,
+        status: Status,
+        detail: String,
+        remedy: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail,
+            remedy: Some(remedy),
+        }
+    }
+}
+
+/// Output of `--json`
+fn to_json(
+    checks: &[Check],
+    failures: usize,
+) -> serde_json::Value {
+    serde_json::json!({
+        "schema": schema::SCHEMA,
+        "checks": checks,
+        "failures": failures,
+    })
+}
+
+#[cfg(windows)]
+fn check_adapters() -> Check {
+    let name = "name servers";
+    match resolv_conf::system_servers().len() {
+        0 => Check::with_problem(
+            name,
+            Status::Warn,
+            String::from("no network adapter that is up has a name server"),
+            "configure the DNS servers of the adapter, or always pass --server",
+        ),
+        servers => {
+            let plural = if servers == 1 { "" } else { "s" };
+            Check::pass(
+                name,
+                format!(
+                    "the network adapters list {} name server{}",
+                    servers, plural
+                ),
+            )
+        },
+    }
+}
+
+#[cfg(unix)]
+fn check_resolv_conf() -> Check {
+    let name = "resolv.conf";
+    match fs::read_to_string(RESOLV_CONF) {
+        Ok(content) => {
+            let servers = nameservers(&content).len();
+            if servers > 0 {
+                let plural = if servers == 1 { "" } else { "s" };
+                Check::pass(
+                    name,
+                    format!("/etc/resolv.conf lists {} name server{}", servers, plural),
+                )
+            } else {
+                Check::with_problem(
+                    name,
+                    Status::Warn,
+                    String::from("/etc/resolv.conf lists no name servers"),
+                    "add a nameserver line, or always pass --server",
+                )
+            }
+        },
+        Err(error) => Check::with_problem(
+            name,
+            Status::Warn,
+            format!("cannot read /etc/resolv.conf: {}", error),
+            "check the permissions of /etc/resolv.conf, or always pass --server",
+        ),
+    }
+}
+
+fn check_server(
+    server: &str,
+    connection_type: ConnectionType,
+) -> Check {
+    let name = match connection_type {
+        ConnectionType::Udp => "server over udp",
+        ConnectionType::Tcp => "server over tcp",
+        #[cfg(feature = "doh")]
+        ConnectionType::DohJson => "server over doh-json",
+        #[cfg(feature = "tls")]
+        ConnectionType::Tls => "server over tls",
+        #[cfg(feature = "doh")]
+        ConnectionType::Https => "server over https",
+    };
+    let remedy = match connection_type {
+        ConnectionType::Udp => "allow outbound UDP port 53, or pick another server with --server",
+        ConnectionType::Tcp => "allow outbound TCP port 53, or pick another server with --server",
+        #[cfg(feature = "doh")]
+        ConnectionType::DohJson | ConnectionType::Https => {
+            "check the URL of the endpoint passed with --server"
+        },
+        #[cfg(feature = "tls")]
+        ConnectionType::Tls => {
+            "allow outbound TCP port 853, or check the certificate name with --tls-hostname"
+        },
+    };
+
+    let started = Instant::now();
+    let options = ResolveOptions::new(connection_type, server).with_timeout(CHECK_TIMEOUT);
+    let result = DnsClient::new(&options).and_then(|client| {
+        client
+            .query(&Name::root(), DNSClass::IN, RecordType::NS)
+            .map_err(AppError::QueryError)
+    });
+    match result {
+        Ok(response) => Check::pass(
+            name,
+            format!(
+                "{} answered with {} in {} ms",
+                server,
+                response.response_code(),
+                started.elapsed().as_millis()
+            ),
+        ),
+        Err(error) => Check::with_problem(
+            name,
+            Status::Fail,
+            format!("{} did not answer: {:?}", server, error),
+            remedy,
+        ),
+    }
+}
+
+fn check_ipv6() -> Check {
+    let name = "ipv6";
+    // Connecting a UDP socket only picks a route, so this is instant and sends nothing
+    let route = UdpSocket::bind("[::]:0").and_then(|socket| {
+        socket.connect(IPV6_PROBE)?;
+        socket.local_addr()
+    });
+    match route {
+        Ok(address) => Check::pass(name, format!("routed from {}", address.ip())),
+        Err(error) => Check::with_problem(
+            name,
+            Status::Warn,
+            format!("no IPv6 route: {}", error),
+            "IPv6 servers cannot be used, pass an IPv4 address with --server",
+        ),
+    }
+}
+
+fn check_colors() -> Check {
+    let name = "colors";
+    let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    if !stdout().is_terminal() {
+        Check::pass(
+            name,
+            String::from("stdout is not a terminal, colors are off unless --color always"),
+        )
+    } else if no_color {
+        Check::pass(
+            name,
+            String::from("NO_COLOR is set, colors are off unless --color always"),
+        )
+    } else if dumb {
+        Check::with_problem(
+            name,
+            Status::Warn,
+            String::from("TERM is dumb"),
+            "pass --no-ansi, the terminal does not support colors",
+        )
+    } else {
+        Check::pass(name, String::from("stdout is a terminal"))
+    }
+}
+
+impl DoctorArgs {
+    /// Run the self-diagnostic and fail when any of the checks failed
+    pub fn run(&self) -> Result<(), AppError> {
+        let server = match &self.server {
+            Some(server) => server.clone(),
+            None => resolv_conf::default_server(),
+        };
+        let mut checks: Vec<Check> = vec![
+            check_server(&server, ConnectionType::Udp),
+            check_server(&server, ConnectionType::Tcp),
+            check_ipv6(),
+            check_colors(),
+        ];
+        #[cfg(unix)]
+        checks.insert(0, check_resolv_conf());
+        #[cfg(windows)]
+        checks.insert(0, check_adapters());
+
+        let failures = checks
+            .iter()
+            .filter(|check| check.status == Status::Fail)
+            .count();
+
+        if self.json {
+            let json = to_json(&checks, failures);
+            outln!("{}", serde_json::to_string_pretty(&json).unwrap());
+        } else {
+            let _ = output::record(|out| {
+                for check in &checks {
+                    let status = match check.status {
+                        Status::Pass => "pass",
+                        Status::Warn => "warn",
+                        Status::Fail => "FAIL",
+                    };
+                    writeln!(out, "[{}] {}: {}", status, check.name, check.detail)?;
+                    if let Some(remedy) = check.remedy {
+                        writeln!(out, "       {}", remedy)?;
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        match failures {
+            0 => Ok(()),
+            failures => Err(AppError::DoctorFailed(failures)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{validate, Output};
+
+    #[test]
+    fn the_json_output_matches_its_schema() {
+        let checks = [
+            Check::pass("ipv6", String::from("an IPv6 route exists")),
+            Check::with_problem(
+                "udp",
+                Status::Fail,
+                String::from("no answer from 192.0.2.53"),
+                "check the firewall",
+            ),
+        ];
+        let json = to_json(&checks, 1);
+        validate(&schema::schema(Output::Doctor), &json).unwrap();
+        assert_eq!(json["checks"][1]["status"], "fail");
+    }
+}
@@ -0,0 +1,87 @@
+use std::{io::Read, time::Duration};
+
+use hickory_client::{
+    error::{ClientError, ClientResult},
+    op::{DnsResponse, Message},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+
+use crate::{
+    connections::Connections,
+    doh_json::{endpoint_url, HttpAgent},
+};
+
+/// Media type of DNS messages carried over HTTPS, RFC 8484
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// Client of DNS-over-HTTPS resolvers speaking the wire format of RFC 8484
+pub struct DohClient {
+    url: String,
+    agent: HttpAgent,
+}
+
+/// Failure of HTTP itself, told apart from the DNS errors of the answers
+fn transport_error(error: impl std::fmt::Display) -> ClientError {
+    ClientError::from(format!("DoH transport failed: {}", error))
+}
+
+impl DohClient {
+    /// `raw_addr` is either the full URL of the endpoint or the address of a resolver serving
+    /// it at `/dns-query`
+    pub fn new(
+        raw_addr: &str,
+        timeout: Duration,
+    ) -> Option<Self> {
+        let url = endpoint_url(raw_addr)?;
+        Some(Self {
+            url,
+            agent: HttpAgent::new(timeout),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn connections(&self) -> Connections {
+        self.agent.connections()
+    }
+
+    pub fn send(
+        &self,
+        mut message: Message,
+    ) -> ClientResult<DnsResponse> {
+        // An id of 0 keeps the request cacheable by HTTP caches, as RFC 8484 recommends
+        message.set_id(0);
+        let body = message.to_bytes()?;
+        let bytes = self.agent.request(|agent| {
+            let response = agent
+                .post(&self.url)
+                .set("content-type", DNS_MESSAGE)
+                .set("accept", DNS_MESSAGE)
+                .send_bytes(&body)
+                .map_err(|error| match error {
+                    ureq::Error::Status(status, response) => transport_error(format!(
+                        "HTTP status {} {}",
+                        status,
+                        response.status_text()
+                    )),
+                    ureq::Error::Transport(error) => transport_error(error),
+                })?;
+            if response.content_type() != DNS_MESSAGE {
+                return Err(transport_error(format!(
+                    "unexpected content type {:?}",
+                    response.content_type()
+                )));
+            }
+            let mut bytes = Vec::new();
+            response
+                .into_reader()
+                .read_to_end(&mut bytes)
+                .map_err(transport_error)?;
+            Ok(bytes)
+        })?;
+        // Kept with the bytes of the server, which tell the size of the response
+        Ok(DnsResponse::new(Message::from_bytes(&bytes)?, bytes))
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    error::{ClientError, ClientResult},
+    op::{DnsResponse, Message, MessageType, Query, ResponseCode},
+    rr::{DNSClass, Name, RData, Record, RecordType},
+    serialize::txt::RDataParser,
+};
+use serde::Deserialize;
+
+use crate::connections::{ConnectionCounter, Connections};
+
+/// Client of the JSON API of DNS-over-HTTPS resolvers (`application/dns-json`)
+pub struct DohJsonClient {
+    url: String,
+    agent: HttpAgent,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct JsonResponse {
+    status: u16,
+    #[serde(rename = "TC", default)]
+    truncated: bool,
+    #[serde(rename = "RD", default)]
+    recursion_desired: bool,
+    #[serde(rename = "RA", default)]
+    recursion_available: bool,
+    #[serde(rename = "AD", default)]
+    authentic_data: bool,
+    #[serde(rename = "CD", default)]
+    checking_disabled: bool,
+    #[serde(default)]
+    answer: Vec<JsonRecord>,
+    #[serde(default)]
+    authority: Vec<JsonRecord>,
+    #[serde(default)]
+    additional: Vec<JsonRecord>,
+}
+
+#[derive(Deserialize)]
+struct JsonRecord {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL", default)]
+    ttl: u32,
+    data: String,
+}
+
+impl JsonRecord {
+    fn into_record(self) -> ClientResult<Record> {
+        let name = Name::from_str(&self.name)
+            .map_err(|_| ClientError::from(format!("invalid name {:?}", self.name)))?;
+        let record_type = RecordType::from(self.record_type);
+        let data = RData::try_from_str(record_type, &self.data).map_err(|_| {
+            ClientError::from(format!("cannot parse {} data {:?}", record_type, self.data))
+        })?;
+        Ok(Record::from_rdata(name, self.ttl, data))
+    }
+}
+
+/// URL of the endpoint at `raw_addr`, which is either the full URL or the address of a
+/// resolver serving DNS-over-HTTPS at `/dns-query`
+pub fn endpoint_url(raw_addr: &str) -> Option<String> {
+    let url = if raw_addr.starts_with("https://") || raw_addr.starts_with("http://") {
+        raw_addr.to_owned()
+    } else {
+        let host = raw_addr.strip_suffix(":53").unwrap_or(raw_addr);
+        format!("https://{}/dns-query", host)
+    };
+    url::Url::parse(&url).ok()?;
+    Some(url)
+}
+
+/// HTTP agent of the DoH clients, keeping its connections alive between requests and
+/// counting those it opens
+pub struct HttpAgent {
+    agent: ureq::Agent,
+    /// connections the agent opened, as it looks up the address of the server for every one
+    resolved: Arc<AtomicUsize>,
+    counter: ConnectionCounter,
+}
+
+impl HttpAgent {
+    pub fn new(timeout: Duration) -> Self {
+        let resolved = Arc::new(AtomicUsize::new(0));
+        let lookups = resolved.clone();
+        let agent = ureq::AgentBuilder::new()
+            .timeout(timeout)
+            .resolver(move |netloc: &str| -> io::Result<Vec<SocketAddr>> {
+                lookups.fetch_add(1, Ordering::Relaxed);
+                netloc.to_socket_addrs().map(Iterator::collect)
+            })
+            .build();
+        Self {
+            agent,
+            resolved,
+            counter: ConnectionCounter::default(),
+        }
+    }
+
+    /// Send a request with `send`, counting the connection it opened or reused.
+    ///
+    /// ureq doesn't tell the handshake of a request apart from the rest of it, so the whole
+    /// of a request that opened a connection counts as handshake.
+    pub fn request<T>(
+        &self,
+        send: impl FnOnce(&ureq::Agent) -> T,
+    ) -> T {
+        let resolved = self.resolved.load(Ordering::Relaxed);
+        let started = Instant::now();
+        let result = send(&self.agent);
+        if self.resolved.load(Ordering::Relaxed) > resolved {
+            self.counter.opened(started.elapsed(), false);
+        } else {
+            self.counter.reused();
+        }
+        result
+    }
+
+    pub fn connections(&self) -> Connections {
+        self.counter.get()
+    }
+}
+
+impl DohJsonClient {
+    /// `raw_addr` is either the full URL of the endpoint or the address of a resolver serving
+    /// the API at `/dns-query`
+    pub fn new(
+        raw_addr: &str,
+        timeout: Duration,
+    ) -> Option<Self> {
+        let url = endpoint_url(raw_addr)?;
+        Some(Self {
+            url,
+            agent: HttpAgent::new(timeout),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn connections(&self) -> Connections {
+        self.agent.connections()
+    }
+
+    pub fn query(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+    ) -> ClientResult<DnsResponse> {
+        let json: JsonResponse = self.agent.request(|agent| {
+            let response = agent
+                .get(&self.url)
+                .set("accept", "application/dns-json")
+                .query("name", &name.to_ascii())
+                .query("type", &query_type.to_string())
+                .call()
+                .map_err(|error| {
+                    ClientError::from(format!("DoH JSON request failed: {}", error))
+                })?;
+            // Read to the end, so that the connection goes back to the agent
+            serde_json::from_reader(response.into_reader())
+                .map_err(|error| ClientError::from(format!("invalid DoH JSON response: {}", error)))
+        })?;
+
+        let mut query = Query::query(name.clone(), query_type);
+        query.set_query_class(query_class);
+
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Response)
+            .set_response_code(<ResponseCode as From<u16>>::from(json.status))
+            .set_truncated(json.truncated)
+            .set_recursion_desired(json.recursion_desired)
+            .set_recursion_available(json.recursion_available)
+            .set_authentic_data(json.authentic_data)
+            .set_checking_disabled(json.checking_disabled)
+            .add_query(query);
+        for record in json.answer {
+            message.add_answer(record.into_record()?);
+        }
+        for record in json.authority {
+            message.add_name_server(record.into_record()?);
+        }
+        for record in json.additional {
+            message.add_additional(record.into_record()?);
+        }
+
+        Ok(DnsResponse::from_message(message)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    /// Answer `requests` requests over each connection, then close it
+    fn serve(
+        listener: TcpListener,
+        requests: usize,
+    ) {
+        let body = r#"{"Status": 0, "Answer": [{"name": "example.com.", "type": 1, "TTL": 300, "data": "192.0.2.1"}]}"#;
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            for _ in 0..requests {
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                write!(
+                    writer,
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/dns-json\r\ncontent-length: \
+                     {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn queries_go_over_the_connection_kept_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+        thread::spawn(move || serve(listener, 2));
+
+        let client = DohJsonClient::new(&url, Duration::from_secs(5)).unwrap();
+        let name = Name::from_str("example.com.").unwrap();
+        for _ in 0..3 {
+            let response = client.query(&name, DNSClass::IN, RecordType::A).unwrap();
+            assert_eq!(response.answers().len(), 1);
+        }
+        let connections = client.connections();
+        // The server closed the first connection after two requests
+        assert_eq!((connections.opened, connections.reused), (2, 1));
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use hickory_client::{
+    op::DnsResponse,
+    rr::{Name, RData, RecordType},
+};
+use serde_json::{json, Value};
+
+use crate::format;
+
+/// Addresses of one family and the CNAME chain that led to them
+struct Family {
+    chain: Vec<Name>,
+    addresses: Vec<String>,
+}
+
+impl Family {
+    /// Follow the CNAME chain of `name` through the answers
+    fn from_response(
+        name: &Name,
+        record_type: RecordType,
+        response: &DnsResponse,
+    ) -> Self {
+        let mut chain = vec![name.clone()];
+        while let Some(target) = response.answers().iter().find_map(|record| {
+            match (record.name() == chain.last().unwrap(), record.data()) {
+                (true, Some(RData::CNAME(target))) => Some(target.0.clone()),
+                _ => None,
+            }
+        }) {
+            if chain.contains(&target) {
+                break;
+            }
+            chain.push(target);
+        }
+
+        let owner = chain.last().unwrap();
+        let addresses = response
+            .answers()
+            .iter()
+            .filter(|record| record.name() == owner && record.record_type() == record_type)
+            .filter_map(|record| record.data().map(format::data_text))
+            .collect();
+        Self {
+            chain,
+            addresses,
+        }
+    }
+}
+
+/// Addresses of both families for one owner name, as printed by `--dual-stack`
+pub struct Pair {
+    chain: Vec<Name>,
+    v4: Option<Vec<String>>,
+    v6: Option<Vec<String>>,
+}
+
+/// Group the A and AAAA answers of `name` per owner name.
+///
+/// The families usually share an owner, but a CNAME chain may lead them to different names,
+/// in which case every owner gets a pair of its own.
+pub fn pair(
+    name: &Name,
+    responses: &[(RecordType, DnsResponse)],
+) -> Vec<Pair> {
+    let family = |record_type: RecordType| {
+        responses
+            .iter()
+            .find(|(queried, _)| *queried == record_type)
+            .map(|(_, response)| Family::from_response(name, record_type, response))
+            .filter(|family| !family.addresses.is_empty())
+    };
+
+    match (family(RecordType::A), family(RecordType::AAAA)) {
+        (Some(v4), Some(v6)) if v4.chain.last() != v6.chain.last() => vec![
+            Pair {
+                chain: v4.chain,
+                v4: Some(v4.addresses),
+                v6: None,
+            },
+            Pair {
+                chain: v6.chain,
+                v4: None,
+                v6: Some(v6.addresses),
+            },
+        ],
+        (v4, v6) => {
+            let chain = v4
+                .as_ref()
+                .or(v6.as_ref())
+                .map(|family| family.chain.clone())
+                .unwrap_or_else(|| vec![name.clone()]);
+            vec![Pair {
+                chain,
+                v4: v4.map(|family| family.addresses),
+                v6: v6.map(|family| family.addresses),
+            }]
+        },
+    }
+}
+
+impl Pair {
+    /// Pair as an entry of the `dual_stack` array of `--json`, the families without addresses
+    /// being null and listed in `missing`
+    pub fn to_json(&self) -> Value {
+        let chain: Vec<String> = self.chain.iter().map(ToString::to_string).collect();
+        let missing: Vec<&str> = [("A", &self.v4), ("AAAA", &self.v6)]
+            .into_iter()
+            .filter(|(_, addresses)| addresses.is_none())
+            .map(|(record_type, _)| record_type)
+            .collect();
+        json!({
+            "name": chain.last(),
+            "chain": chain,
+            "a": self.v4,
+            "aaaa": self.v6,
+            "missing": missing,
+        })
+    }
+}
+
+impl Display for Pair {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let chain: Vec<String> = self.chain.iter().map(ToString::to_string).collect();
+        write!(f, "{}", chain.join(" -> "))?;
+        for (record_type, addresses) in [("A", &self.v4), ("AAAA", &self.v6)] {
+            match addresses {
+                Some(addresses) => {
+                    for address in addresses {
+                        write!(f, "\n  {:<5} {}", record_type, address)?;
+                    }
+                },
+                None => write!(f, "\n  (no {})", record_type)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+use hickory_client::rr::{RData, Record, RecordType};
+
+use crate::{format, outln, output};
+
+/// Characters that stand for themselves in a POSIX shell word, even right after the `=`
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | ':' | '/' | '@' | '%' | '+' | ',' | '-')
+}
+
+/// Quote `value` for a POSIX shell.
+///
+/// Values made of safe characters only are left bare. Anything else is put between single
+/// quotes, within which the shell expands nothing: no `$`, no backticks, no backslashes and no
+/// newlines. A single quote itself closes the quotes, is escaped and opens them again. NUL
+/// bytes cannot be held by shell variables at all, so they become U+FFFD.
+fn quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_safe) {
+        return value.to_owned();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        match c {
+            '\'' => quoted.push_str("'\\''"),
+            '\0' => quoted.push('\u{FFFD}'),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Part of a variable name for `record_type`, anything but letters, digits and `_` being `_`,
+/// types without a name being spelled as in RFC 3597, e.g. TYPE65280
+fn type_key(record_type: RecordType) -> String {
+    let name = match record_type {
+        RecordType::Unknown(code) => format!("TYPE{}", code),
+        record_type => record_type.to_string(),
+    };
+    name.to_ascii_uppercase()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+/// Whether `prefix` makes valid variable names, for `--env-prefix`
+pub fn parse_prefix(prefix: &str) -> Result<String, String> {
+    let valid = prefix.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match valid {
+        true => Ok(prefix.to_owned()),
+        false => Err(String::from(
+            "must start with a letter or _ and contain only letters, digits and _",
+        )),
+    }
+}
+
+/// Suffixes and values of the variables of one record, a bare value being under no suffix
+fn fields(data: &RData) -> Vec<(&'static str, String)> {
+    match data {
+        RData::MX(mx) => vec![
+            ("PREF", mx.preference().to_string()),
+            ("HOST", mx.exchange().to_string()),
+        ],
+        RData::SRV(srv) => vec![
+            ("PRIORITY", srv.priority().to_string()),
+            ("WEIGHT", srv.weight().to_string()),
+            ("PORT", srv.port().to_string()),
+            ("TARGET", srv.target().to_string()),
+        ],
+        RData::SOA(soa) => vec![
+            ("MNAME", soa.mname().to_string()),
+            ("RNAME", soa.rname().to_string()),
+            ("SERIAL", soa.serial().to_string()),
+            ("REFRESH", soa.refresh().to_string()),
+            ("RETRY", soa.retry().to_string()),
+            ("EXPIRE", soa.expire().to_string()),
+            ("MINIMUM", soa.minimum().to_string()),
+        ],
+        RData::CAA(caa) => vec![
+            ("FLAGS", u8::from(caa.issuer_critical()).to_string()),
+            ("TAG", caa.tag().as_str().to_owned()),
+            ("VALUE", format::display_text(caa.value())),
+        ],
+        // The strings of the record joined, the way the value is meant to be read
+        RData::TXT(txt) => vec![(
+            "",
+            txt.txt_data()
+                .iter()
+                .map(|data| String::from_utf8_lossy(data))
+                .collect(),
+        )],
+        data => vec![("", format::data_text(data))],
+    }
+}
+
+/// Prefix of the variables of the name at `index` of several, e.g. `DNS_0`, so that the
+/// variables of each name are apart
+pub fn name_prefix(
+    prefix: &str,
+    index: usize,
+) -> String {
+    format!("{}_{}", prefix, index)
+}
+
+/// Print the name that the variables under `prefix`, from `name_prefix`, are about
+pub fn print_name(
+    prefix: &str,
+    name: &str,
+) {
+    outln!("{}_NAME={}", prefix, quote(name));
+}
+
+/// Print how many names were resolved, each with its own `name_prefix`
+pub fn print_count(
+    prefix: &str,
+    count: usize,
+) {
+    outln!("{}_COUNT={}", prefix, count);
+}
+
+/// Print the answers as shell variable assignments, see `--env-prefix` for the rules
+pub fn print(
+    prefix: &str,
+    record_types: &[RecordType],
+    answers: &[&Record],
+) {
+    let _ = output::record(|out| {
+        for assignment in assignments(prefix, record_types, answers) {
+            writeln!(out, "{}", assignment)?;
+        }
+        Ok(())
+    });
+}
+
+/// Assignments of the variables of the answers, one per line
+pub fn assignments(
+    prefix: &str,
+    record_types: &[RecordType],
+    answers: &[&Record],
+) -> Vec<String> {
+    // The queried types come first, so that their counts are there even without answers
+    let mut types: Vec<RecordType> = record_types.to_vec();
+    for record in answers {
+        if !types.contains(&record.record_type()) {
+            types.push(record.record_type());
+        }
+    }
+
+    let mut assignments = Vec::new();
+    for record_type in types {
+        let key = format!("{}_{}", prefix, type_key(record_type));
+        let records = answers
+            .iter()
+            .filter(|record| record.record_type() == record_type)
+            .filter_map(|record| record.data());
+        let mut count = 0;
+        for (index, data) in records.enumerate() {
+            for (suffix, value) in fields(data) {
+                let name = match suffix {
+                    "" => format!("{}_{}", key, index),
+                    suffix => format!("{}_{}_{}", key, index, suffix),
+                };
+                assignments.push(format!("{}={}", name, quote(&value)));
+            }
+            count += 1;
+        }
+        assignments.push(format!("{}_COUNT={}", key, count));
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::rr::{
+        rdata::{A, MX, TXT},
+        Name,
+    };
+
+    use super::*;
+
+    fn txt(strings: &[&[u8]]) -> Record {
+        let data = TXT::from_bytes(strings.to_vec());
+        Record::from_rdata(
+            Name::from_str("example.com.").unwrap(),
+            300,
+            RData::TXT(data),
+        )
+    }
+
+    /// Values of the variables after the shell ran `assignments`, newlines in them escaped
+    #[cfg(unix)]
+    fn evaluated(
+        assignments: &[String],
+        names: &[&str],
+    ) -> Vec<String> {
+        let script = names
+            .iter()
+            .map(|name| format!("printf '%s\\0' \"${}\"", name))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{}\n{}", assignments.join("\n"), script))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn safe_values_are_left_bare() {
+        assert_eq!(quote("192.0.2.1"), "192.0.2.1");
+        assert_eq!(quote("2001:db8::1"), "2001:db8::1");
+        assert_eq!(quote("mail.example.com."), "mail.example.com.");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn values_that_a_shell_would_expand_are_single_quoted() {
+        assert_eq!(quote("$(reboot)"), "'$(reboot)'");
+        assert_eq!(quote("`reboot`"), "'`reboot`'");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("one\ntwo"), "'one\ntwo'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote("a\0b"), "'a\u{FFFD}b'");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hostile_txt_records_come_out_of_the_shell_as_they_were() {
+        let values: [&[u8]; 6] = [
+            b"v=spf1 -all; echo 'pwned'",
+            b"$(touch /tmp/resoy-env-test)",
+            b"`touch /tmp/resoy-env-test`",
+            b"line\nX=injected",
+            b"\\'\"${HOME}",
+            b"';exit 1;'",
+        ];
+        let records: Vec<Record> = values.iter().map(|value| txt(&[value])).collect();
+        let answers: Vec<&Record> = records.iter().collect();
+        let assignments = assignments("DNS", &[RecordType::TXT], &answers);
+        let names: Vec<String> = (0..values.len())
+            .map(|index| format!("DNS_TXT_{}", index))
+            .collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let expected: Vec<String> = values
+            .iter()
+            .map(|value| String::from_utf8(value.to_vec()).unwrap())
+            .collect();
+        assert_eq!(evaluated(&assignments, &names), expected);
+        assert_eq!(evaluated(&assignments, &["DNS_TXT_COUNT", "X"]), ["6", ""]);
+    }
+
+    #[test]
+    fn nul_bytes_and_invalid_utf8_of_txt_records_are_replaced() {
+        let record = txt(&[b"a\0b", b"\xff\xfe"]);
+        let assignments = assignments("DNS", &[RecordType::TXT], &[&record]);
+        assert_eq!(assignments[0], "DNS_TXT_0='a\u{FFFD}b\u{FFFD}\u{FFFD}'");
+    }
+
+    #[test]
+    fn records_with_fields_get_a_variable_per_field() {
+        let name = Name::from_str("example.com.").unwrap();
+        let mx = Record::from_rdata(
+            name.clone(),
+            300,
+            RData::MX(MX::new(10, Name::from_str("mail.example.com.").unwrap())),
+        );
+        let a = Record::from_rdata(name, 300, RData::A(A::new(192, 0, 2, 1)));
+        let assignments = assignments("DNS", &[RecordType::AAAA, RecordType::MX], &[&mx, &a]);
+        assert_eq!(
+            assignments,
+            [
+                "DNS_AAAA_COUNT=0",
+                "DNS_MX_0_PREF=10",
+                "DNS_MX_0_HOST=mail.example.com.",
+                "DNS_MX_COUNT=1",
+                "DNS_A_0=192.0.2.1",
+                "DNS_A_COUNT=1",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_names_become_valid_variable_names() {
+        assert_eq!(type_key(RecordType::Unknown(65280)), "TYPE65280");
+        assert_eq!(type_key(RecordType::ANAME), "ANAME");
+    }
+
+    #[test]
+    fn prefixes_that_are_not_shell_identifiers_are_refused() {
+        assert_eq!(parse_prefix("DNS").unwrap(), "DNS");
+        assert_eq!(parse_prefix("_dns_2").unwrap(), "_dns_2");
+        for prefix in ["", "2DNS", "DNS-A", "DNS A", "$DNS", "DNS;", "ÉDNS"] {
+            assert!(parse_prefix(prefix).is_err(), "{:?}", prefix);
+        }
+    }
+}
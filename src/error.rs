@@ -0,0 +1,364 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use hickory_client::error::{ClientError, ClientErrorKind};
+
+use crate::{
+    budget::{Exhausted, BUDGET_EXIT_CODE},
+    format::OutputFormat,
+    ConnectionType,
+};
+
+/// Why a query or a run failed, printed as the message of the run
+pub enum AppError {
+    InvalidDomainName(String, String),
+    UnknownRecordType(String),
+    UnknownClass(String),
+    UnknownSortKey(String),
+    InvalidTemplate(String, String),
+    InvalidDnsServer(String),
+    InvalidCidr(String),
+    DNSServerUnreachable(ConnectionType, String),
+    QueryError(ClientError),
+    ManifestError(PathBuf, String),
+    AuditRulesError(PathBuf, String),
+    DoctorFailed(usize),
+    ServiceUnreachable(String),
+    PinFileError(PathBuf, String),
+    PcapError(PathBuf, String),
+    InvalidVantage(String, String),
+    MockAnswersError(PathBuf, String),
+    ZoneError(String, String),
+    RolloverError(String),
+    GraphError(PathBuf, String),
+    AggregateError(PathBuf, String),
+    UnknownInterface(String, Vec<String>),
+    NxDomain(String),
+    ResponseError(String, String, String),
+    QueryTimeout(String, String, usize, Duration),
+    ListenError(SocketAddr, String),
+    BindError(SocketAddr, String),
+    UnsupportedSource(ConnectionType),
+    NamesFailed(usize, usize, i32),
+    ServersFailed(usize, usize, i32),
+    UnsupportedServersOption(&'static str),
+    UnsupportedServersOutput(OutputFormat),
+    NamesFileError(PathBuf, String),
+    InvalidQueryLine(String, String),
+    UnsupportedBatchOutput(OutputFormat),
+    RulesError(PathBuf, String),
+    TsigError(String),
+    TraceError(String, String),
+    FollowError(String, String),
+    #[cfg(feature = "dnssec")]
+    InvalidTrustAnchor(String, String),
+    Bogus(usize),
+    InvalidExpectation(String, String),
+    ExpectationsUnmet(usize),
+    BudgetExhausted(Exhausted),
+    DeadlineExceeded(Duration),
+}
+
+impl AppError {
+    /// Exit status of runs failing with the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::NxDomain(_) => NXDOMAIN_EXIT_CODE,
+            Self::ResponseError(..) => RESPONSE_ERROR_EXIT_CODE,
+            Self::Bogus(_) => BOGUS_EXIT_CODE,
+            Self::ExpectationsUnmet(_) => EXPECTATION_EXIT_CODE,
+            Self::BudgetExhausted(_) => BUDGET_EXIT_CODE,
+            Self::DeadlineExceeded(_) => DEADLINE_EXIT_CODE,
+            Self::NamesFailed(_, _, exit_code) => *exit_code,
+            Self::ServersFailed(_, _, exit_code) => *exit_code,
+            Self::QueryTimeout(..) | Self::DNSServerUnreachable(..) => TRANSPORT_EXIT_CODE,
+            Self::QueryError(error) if matches!(error.kind(), ClientErrorKind::Io(_)) => {
+                TRANSPORT_EXIT_CODE
+            },
+            _ => 1,
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::InvalidDomainName(domain, hint) => {
+                write!(f, "Invalid name {:?}: {}", domain, hint)
+            },
+            Self::UnknownRecordType(record_type) => {
+                write!(f, "Cannot parse record type: {:?}", record_type)
+            },
+            Self::UnknownClass(class) => write!(f, "Cannot parse class: {:?}", class),
+            Self::UnknownSortKey(key) => write!(
+                f,
+                "Cannot parse sort key: {:?}, use type, name, ttl or data, with a - in front \
+                 for descending",
+                key
+            ),
+            Self::InvalidTemplate(template, error) => {
+                write!(f, "Cannot parse template {:?}: {}", template, error)
+            },
+            Self::InvalidDnsServer(host) => {
+                write!(f, "Cannot parse DNS server address: {:?}", host)
+            },
+            Self::InvalidCidr(cidr) => {
+                write!(f, "Cannot parse address block: {:?}", cidr)
+            },
+            Self::DNSServerUnreachable(connection_type, host) => {
+                write!(
+                    f,
+                    "Cannot establish {} connection with {:?}",
+                    connection_type, host
+                )
+            },
+            Self::QueryError(client_error) => match client_error.kind() {
+                // hickory only tells "io error", e.g. for refused connections
+                ClientErrorKind::Io(error) => write!(f, "Cannot send DNS query: {}", error),
+                _ => write!(f, "Cannot send DNS query: {}", client_error),
+            },
+            Self::ManifestError(path, error) => {
+                write!(f, "Cannot use manifest {:?}: {}", path, error)
+            },
+            Self::AuditRulesError(path, error) => {
+                write!(f, "Cannot load audit rules {:?}: {}", path, error)
+            },
+            Self::DoctorFailed(failures) => {
+                write!(f, "{} doctor checks failed", failures)
+            },
+            Self::ServiceUnreachable(name) => {
+                write!(f, "No advertised endpoint of {} is reachable", name)
+            },
+            Self::PinFileError(path, error) => {
+                write!(f, "Cannot use pin file {:?}: {}", path, error)
+            },
+            Self::GraphError(path, error) => {
+                write!(f, "Cannot write graph {:?}: {}", path, error)
+            },
+            Self::AggregateError(path, error) => {
+                write!(f, "Cannot write aggregate {:?}: {}", path, error)
+            },
+            Self::PcapError(path, error) => {
+                write!(f, "Cannot write capture {:?}: {}", path, error)
+            },
+            Self::InvalidVantage(vantage, error) => {
+                write!(f, "Cannot use vantage {:?}: {}", vantage, error)
+            },
+            Self::MockAnswersError(path, error) => {
+                write!(f, "Cannot use mock answers {:?}: {}", path, error)
+            },
+            Self::ZoneError(source, error) => {
+                write!(f, "Cannot read zone from {}: {}", source, error)
+            },
+            Self::RolloverError(error) => {
+                write!(f, "Cannot tell the rollover status: {}", error)
+            },
+            Self::NxDomain(name) => write!(f, "NXDOMAIN for {}", name),
+            Self::ListenError(addr, error) => write!(f, "Cannot listen on {}: {}", addr, error),
+            Self::BindError(addr, error) => {
+                write!(f, "Cannot send queries from {}: {}", addr, error)
+            },
+            Self::UnsupportedSource(connection_type) => write!(
+                f,
+                "--source and --source-port apply to udp and tcp queries, not to {} ones",
+                connection_type
+            ),
+            Self::NamesFailed(failed, total, _) => {
+                write!(f, "{} of {} names failed", failed, total)
+            },
+            Self::ServersFailed(failed, total, _) => {
+                write!(f, "{} of {} servers failed", failed, total)
+            },
+            Self::UnsupportedServersOption(option) => write!(
+                f,
+                "Several servers can only be compared on the records of one name, not with --{}",
+                option
+            ),
+            Self::UnsupportedServersOutput(format) => write!(
+                f,
+                "--output {} cannot tell the records of several servers apart, use text, short \
+                 or json",
+                format
+            ),
+            Self::NamesFileError(path, error) => {
+                write!(f, "Cannot read names from {:?}: {}", path, error)
+            },
+            Self::InvalidQueryLine(line, reason) => {
+                write!(f, "Cannot read the query {:?}: {}", line, reason)
+            },
+            Self::TraceError(question, error) => {
+                write!(f, "Cannot trace {}: {}", question, error)
+            },
+            Self::FollowError(question, error) => {
+                write!(f, "Cannot follow the CNAMEs of {}: {}", question, error)
+            },
+            #[cfg(feature = "dnssec")]
+            Self::InvalidTrustAnchor(anchor, error) => {
+                write!(f, "Cannot parse trust anchor {:?}: {}", anchor, error)
+            },
+            Self::Bogus(count) => write!(f, "{} records failed DNSSEC validation", count),
+            Self::InvalidExpectation(expectation, error) => {
+                write!(f, "Cannot parse expectation {:?}: {}", expectation, error)
+            },
+            Self::ExpectationsUnmet(count) => match count {
+                1 => write!(f, "1 expectation was not met"),
+                count => write!(f, "{} expectations were not met", count),
+            },
+            Self::BudgetExhausted(exhausted) => write!(f, "{}", exhausted),
+            Self::DeadlineExceeded(deadline) => write!(f, "Deadline of {:?} exceeded", deadline),
+            Self::TsigError(error) => write!(f, "Cannot sign the queries with TSIG: {}", error),
+            Self::RulesError(path, error) => {
+                write!(f, "Cannot use rules {:?}: {}", path, error)
+            },
+            Self::UnsupportedBatchOutput(format) => write!(
+                f,
+                "--output {} cannot stream the records of --file, use text, short or json",
+                format
+            ),
+            Self::QueryTimeout(question, server, attempts, elapsed) => {
+                let plural = if *attempts == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "No response from {} for {} after {} attempt{} in {:.1?}, see --timeout and \
+                     --retries",
+                    server, question, attempts, plural, elapsed
+                )
+            },
+            Self::ResponseError(code, server, question) => {
+                write!(f, "{} from {} for {}", code, server, question)
+            },
+            Self::UnknownInterface(name, interfaces) => match interfaces.is_empty() {
+                true => write!(f, "Unknown interface {:?}, use its index as the zone", name),
+                false => write!(
+                    f,
+                    "Unknown interface {:?}, available: {}",
+                    name,
+                    interfaces.join(", ")
+                ),
+            },
+        }
+    }
+}
+
+// The message of the error stands for it, as it is what a run failing with it prints
+impl Debug for AppError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for AppError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::QueryError(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Exit status of runs that exceeded `--deadline`, the same as timeout(1) uses
+pub const DEADLINE_EXIT_CODE: i32 = 124;
+
+/// Exit status of runs for names that don't exist
+pub const NXDOMAIN_EXIT_CODE: i32 = 4;
+
+/// Exit status of runs whose responses all came with another error code, e.g. SERVFAIL
+pub const RESPONSE_ERROR_EXIT_CODE: i32 = 5;
+
+/// Exit status of runs with records that failed `--validate`
+pub const BOGUS_EXIT_CODE: i32 = 6;
+
+/// Exit status of runs whose server couldn't be reached or didn't answer in time
+pub const TRANSPORT_EXIT_CODE: i32 = 7;
+
+/// Exit status of runs whose answers did not meet an `--expect`
+pub const EXPECTATION_EXIT_CODE: i32 = 8;
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn outcomes_of_the_responses_have_their_own_exit_status() {
+        assert_eq!(
+            AppError::NxDomain(String::from("example.com.")).exit_code(),
+            NXDOMAIN_EXIT_CODE
+        );
+        let servfail = AppError::ResponseError(
+            String::from("SERVFAIL"),
+            String::from("192.0.2.53"),
+            String::from("example.com./A"),
+        );
+        assert_eq!(servfail.exit_code(), RESPONSE_ERROR_EXIT_CODE);
+        assert_eq!(AppError::Bogus(1).exit_code(), BOGUS_EXIT_CODE);
+        assert_eq!(
+            AppError::ExpectationsUnmet(1).exit_code(),
+            EXPECTATION_EXIT_CODE
+        );
+    }
+
+    #[test]
+    fn transport_failures_are_told_apart_from_usage_errors() {
+        let timeout = AppError::QueryTimeout(
+            String::from("example.com./A"),
+            String::from("192.0.2.53"),
+            3,
+            Duration::from_secs(15),
+        );
+        assert_eq!(timeout.exit_code(), TRANSPORT_EXIT_CODE);
+        let unreachable =
+            AppError::DNSServerUnreachable(ConnectionType::Tcp, String::from("192.0.2.53"));
+        assert_eq!(unreachable.exit_code(), TRANSPORT_EXIT_CODE);
+        let refused = ClientError::from(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert_eq!(
+            AppError::QueryError(refused).exit_code(),
+            TRANSPORT_EXIT_CODE
+        );
+
+        assert_eq!(
+            AppError::QueryError(ClientError::from("no response")).exit_code(),
+            1
+        );
+        assert_eq!(
+            AppError::UnknownRecordType(String::from("AAAAA")).exit_code(),
+            1
+        );
+        assert_eq!(
+            AppError::InvalidDnsServer(String::from("[::1")).exit_code(),
+            1
+        );
+        let codes = [
+            NXDOMAIN_EXIT_CODE,
+            RESPONSE_ERROR_EXIT_CODE,
+            BOGUS_EXIT_CODE,
+            TRANSPORT_EXIT_CODE,
+            DEADLINE_EXIT_CODE,
+        ];
+        assert!(!codes.contains(&1) && !codes.contains(&2));
+    }
+
+    #[test]
+    fn failures_of_several_names_or_servers_keep_the_status_of_the_first() {
+        assert_eq!(
+            AppError::NamesFailed(2, 3, NXDOMAIN_EXIT_CODE).exit_code(),
+            NXDOMAIN_EXIT_CODE
+        );
+        assert_eq!(
+            AppError::ServersFailed(1, 2, TRANSPORT_EXIT_CODE).exit_code(),
+            TRANSPORT_EXIT_CODE
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::output;
+
+/// Entry of the `resoy examples` cookbook
+pub struct Example {
+    pub title: &'static str,
+    /// arguments passed to resoy, without the binary name
+    pub args: &'static [&'static str],
+    /// also shown in the `--help` epilog
+    pub in_help: bool,
+}
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        title: "Query several record types at once",
+        args: &["example.com", "A", "AAAA", "MX"],
+        in_help: true,
+    },
+    Example {
+        title: "Compare the records of several hosts",
+        args: &["-n", "web1.example.com,web2.example.com", "A", "AAAA"],
+        in_help: false,
+    },
+    Example {
+        title: "Resolve the hosts listed in a file, printing name and address",
+        args: &["-f", "hosts.txt", "--short"],
+        in_help: false,
+    },
+    Example {
+        title: "Write the records of a huge list of hosts out by blocks, the fastest way",
+        args: &["-f", "hosts.txt", "--short", "--flush", "block"],
+        in_help: false,
+    },
+    Example {
+        title: "Turn a file of hosts into a shuffled dnsperf query file for a load test",
+        args: &[
+            "export-queries",
+            "hosts.txt",
+            "A",
+            "AAAA",
+            "--shuffle",
+            "--seed",
+            "1",
+        ],
+        in_help: false,
+    },
+    Example {
+        title: "Look up the common record types of a domain",
+        args: &["example.com", "all"],
+        in_help: false,
+    },
+    Example {
+        title: "Ask a specific server",
+        args: &["--server", "9.9.9.9:53", "example.com"],
+        in_help: true,
+    },
+    Example {
+        title: "Ask a server the way dig does",
+        args: &["example.com", "@8.8.8.8", "MX"],
+        in_help: false,
+    },
+    Example {
+        title: "Follow the delegations of a name from the root servers down",
+        args: &["--trace", "www.example.com"],
+        in_help: false,
+    },
+    Example {
+        title: "Time every delegation and every name server of the zones on the way",
+        args: &["--trace", "--timing", "--trace-all-ns", "www.example.com"],
+        in_help: false,
+    },
+    #[cfg(feature = "dnssec")]
+    Example {
+        title: "Check the DNSSEC signatures of the answers from the root keys down",
+        args: &["--validate", "example.com"],
+        in_help: false,
+    },
+    Example {
+        title: "Follow the CNAMEs of a name to its addresses",
+        args: &["--follow", "www.example.com", "A"],
+        in_help: false,
+    },
+    Example {
+        title: "Wait until a record flips during a migration",
+        args: &["--watch", "30", "--until-changed", "www.example.com", "A"],
+        in_help: false,
+    },
+    Example {
+        title: "Use TCP instead of UDP",
+        args: &["--connection", "tcp", "example.com", "NS"],
+        in_help: false,
+    },
+    #[cfg(feature = "doh")]
+    Example {
+        title: "Query the JSON API of a DNS-over-HTTPS resolver",
+        args: &[
+            "--connection",
+            "doh-json",
+            "--server",
+            "https://cloudflare-dns.com/dns-query",
+            "example.com",
+        ],
+        in_help: false,
+    },
+    Example {
+        title: "Print the records as JSON, e.g. for jq",
+        args: &["--json", "example.com", "MX"],
+        in_help: false,
+    },
+    Example {
+        title: "Print shell variables of the records, e.g. for eval",
+        args: &[
+            "--output",
+            "env",
+            "--env-prefix",
+            "DNS",
+            "example.com",
+            "A",
+            "MX",
+        ],
+        in_help: false,
+    },
+    #[cfg(feature = "doh")]
+    Example {
+        title: "Query a DNS-over-HTTPS resolver in the wire format",
+        args: &[
+            "--connection",
+            "https",
+            "--server",
+            "https://dns.google/dns-query",
+            "example.com",
+        ],
+        in_help: false,
+    },
+    #[cfg(feature = "tls")]
+    Example {
+        title: "Query a DNS-over-TLS resolver",
+        args: &[
+            "--connection",
+            "tls",
+            "--server",
+            "1.1.1.1:853",
+            "--tls-hostname",
+            "cloudflare-dns.com",
+            "example.com",
+        ],
+        in_help: false,
+    },
+    Example {
+        title: "Reverse lookup of an IPv4 address",
+        args: &["1.1.1.1"],
+        in_help: true,
+    },
+    Example {
+        title: "Reverse lookup of an IPv6 address",
+        args: &["2606:4700:4700::1111"],
+        in_help: false,
+    },
+    Example {
+        title: "Inspect the DMARC policy of a domain",
+        args: &["--explain", "_dmarc.example.com", "TXT"],
+        in_help: true,
+    },
+    Example {
+        title: "Check SPF and site verification tokens",
+        args: &["example.com", "TXT"],
+        in_help: false,
+    },
+    Example {
+        title: "Audit the TXT records of a domain as JSON lines",
+        args: &["--audit-txt", "jsonl", "example.com"],
+        in_help: false,
+    },
+    Example {
+        title: "Look for dangling records outside of our address space",
+        args: &[
+            "--check-dangling",
+            "--our-cidrs",
+            "93.184.0.0/16",
+            "example.com",
+            "MX",
+        ],
+        in_help: false,
+    },
+    Example {
+        title: "Plain output for scripts",
+        args: &["--no-ansi", "--seconds", "example.com", "A", "AAAA"],
+        in_help: false,
+    },
+    Example {
+        title: "Check that the XMPP service of a domain is up",
+        args: &["--probe-targets", "_xmpp-client._tcp.example.com", "SRV"],
+        in_help: false,
+    },
+    Example {
+        title: "Feed canned answers to a script instead of asking a server",
+        args: &["--mock-answers", "fixture.toml", "example.com", "A", "MX"],
+        in_help: false,
+    },
+    Example {
+        title: "Find out whether a resolver filters answers",
+        args: &["--server", "1.1.1.3:53", "--check-filtering", "example.com"],
+        in_help: false,
+    },
+    Example {
+        title: "Diagnose connectivity problems",
+        args: &["doctor"],
+        in_help: false,
+    },
+    #[cfg(feature = "tls")]
+    Example {
+        title: "Give DNS-over-TLS to local tools that only speak plain DNS",
+        args: &[
+            "proxy",
+            "--listen",
+            "127.0.0.1:5353",
+            "--server",
+            "9.9.9.9",
+            "--connection",
+            "tls",
+        ],
+        in_help: false,
+    },
+    Example {
+        title: "Describe a record type",
+        args: &["types", "CAA"],
+        in_help: false,
+    },
+];
+
+impl Example {
+    /// Copy-pasteable shell command line
+    pub fn command_line(&self) -> String {
+        let mut line = String::from("resoy");
+        for arg in self.args {
+            line.push(' ');
+            if arg.contains([' ', '"', '\'', '$', ';', '*', '?']) {
+                line.push_str(&format!("'{}'", arg.replace('\'', r"'\''")));
+            } else {
+                line.push_str(arg);
+            }
+        }
+        line
+    }
+}
+
+/// Epilog of `--help` with a few of the examples
+pub fn after_help() -> String {
+    let mut help = String::from("Examples:\n");
+    for example in EXAMPLES.iter().filter(|example| example.in_help) {
+        help.push_str(&format!(
+            "  # {}\n  {}\n",
+            example.title,
+            example.command_line()
+        ));
+    }
+    help.push_str("\nRun `resoy examples` for more.");
+    help
+}
+
+/// Print the whole cookbook
+pub fn run() {
+    let _ = output::record(|out| {
+        for example in EXAMPLES {
+            writeln!(out, "# {}\n{}\n", example.title, example.command_line())?;
+        }
+        Ok(())
+    });
+}
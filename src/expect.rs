@@ -0,0 +1,478 @@
+//! Expectations of `--expect`, which a run checks its answers against.
+//!
+//! ```text
+//! expectation = type "=" value
+//!             | "soa." field "=" value
+//! field       = "mname" | "rname" | "serial" | "refresh" | "retry" | "expire" | "minimum"
+//! ```
+//!
+//! The type is any record type, in any case, and the value is everything after the first `=`.
+//! How it is compared depends on the type:
+//!
+//! - A and AAAA: an address, equal to the address of the record however it is written, e.g.
+//!   `2001:db8::1` for `2001:DB8:0:0:0:0:0:1`.
+//! - MX: `PREFERENCE:HOST`, `PREFERENCE HOST` or only `HOST`, which any preference matches.
+//! - TXT: the text of the record, its strings decoded and concatenated, without quotes.
+//! - CNAME, NS, PTR and the `mname` and `rname` of SOA records: a name, its case and final
+//!   dot left aside.
+//! - The serial of SOA records: a number.
+//! - The refresh, retry, expire and minimum of SOA records: a TTL, in seconds or with a unit,
+//!   e.g. `3600` or `1h`.
+//! - Other types, and SOA records as a whole: the data of the record as resoy prints it, word
+//!   by word, its case and the dots ending its names left aside, and the timers of SOA records
+//!   in seconds or with a unit.
+//!
+//! An expectation is met when an answer of its type matches it.
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use hickory_client::rr::{rdata::SOA, Name, RData, Record, RecordType};
+
+use crate::{asn, format, ttl};
+
+/// Fields of SOA records that an expectation may target
+const SOA_FIELDS: [&str; 7] = [
+    "mname", "rname", "serial", "refresh", "retry", "expire", "minimum",
+];
+
+/// What an answer must hold to meet an expectation
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Matcher {
+    Address(IpAddr),
+    Mx(Option<u16>, Name),
+    Name(Name),
+    Text(String),
+    /// a field of `SOA_FIELDS` and its value
+    SoaName(&'static str, Name),
+    SoaNumber(&'static str, i64),
+    /// words of the data
+    Data(Vec<String>),
+}
+
+/// An expectation of `--expect`, e.g. `MX=10:mail.example.com` or `soa.mname=ns1.example.com`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expectation {
+    /// as it was given
+    text: String,
+    record_type: RecordType,
+    matcher: Matcher,
+}
+
+/// Name of `value`, whose final dot is optional
+fn parse_name(value: &str) -> Result<Name, String> {
+    Name::from_str(value).map_err(|_| format!("{:?} is no valid name", value))
+}
+
+/// Lower case words of `data`, without the dots ending its names
+fn words(data: &str) -> Vec<String> {
+    data.split_whitespace()
+        .map(|word| word.trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+fn same_name(
+    name: &Name,
+    other: &Name,
+) -> bool {
+    let text = |name: &Name| name.to_ascii().trim_end_matches('.').to_lowercase();
+    text(name) == text(other)
+}
+
+/// The strings of a TXT record concatenated, bytes that are no UTF-8 replaced
+fn decoded_text(data: &RData) -> Option<String> {
+    match data {
+        RData::TXT(txt) => {
+            let bytes: Vec<u8> = txt.txt_data().iter().flatten().copied().collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        },
+        _ => None,
+    }
+}
+
+/// Value of `field` of `soa`, as text
+fn soa_field(
+    soa: &SOA,
+    field: &str,
+) -> String {
+    match field {
+        "mname" => soa.mname().to_string(),
+        "rname" => soa.rname().to_string(),
+        "serial" => soa.serial().to_string(),
+        "refresh" => soa.refresh().to_string(),
+        "retry" => soa.retry().to_string(),
+        "expire" => soa.expire().to_string(),
+        _ => soa.minimum().to_string(),
+    }
+}
+
+/// Name a record of a name matcher points at
+fn target(data: &RData) -> Option<&Name> {
+    match data {
+        RData::CNAME(cname) => Some(&cname.0),
+        RData::NS(ns) => Some(&ns.0),
+        RData::PTR(ptr) => Some(&ptr.0),
+        _ => None,
+    }
+}
+
+impl FromStr for Expectation {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let Some((subject, value)) = text.split_once('=') else {
+            return Err(String::from(
+                "expected TYPE=VALUE, e.g. MX=10:mail.example.com, or soa.FIELD=VALUE",
+            ));
+        };
+        let (type_name, field) = match subject.split_once('.') {
+            Some((type_name, field)) => (type_name.trim(), Some(field.trim())),
+            None => (subject.trim(), None),
+        };
+        // hickory only takes the names of the types in upper case
+        let record_type = RecordType::from_str(&type_name.to_ascii_uppercase())
+            .map_err(|_| format!("{:?} is no record type", type_name))?;
+        if value.trim().is_empty() {
+            return Err(format!("no value is expected of {}", subject));
+        }
+        let matcher = match (record_type, field) {
+            (RecordType::SOA, Some(field)) => {
+                let field = SOA_FIELDS
+                    .into_iter()
+                    .find(|known| known.eq_ignore_ascii_case(field))
+                    .ok_or_else(|| {
+                        format!(
+                            "SOA records have no field {:?}, use {}",
+                            field,
+                            SOA_FIELDS.join(", ")
+                        )
+                    })?;
+                let value = value.trim();
+                match field {
+                    "mname" | "rname" => Matcher::SoaName(field, parse_name(value)?),
+                    "serial" => Matcher::SoaNumber(
+                        field,
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| {
+                                format!("the {} of SOA records is a number, not {:?}", field, value)
+                            })?
+                            .into(),
+                    ),
+                    _ => Matcher::SoaNumber(
+                        field,
+                        ttl::parse(value)
+                            .map_err(|error| format!("the {} of SOA records: {}", field, error))?
+                            .into(),
+                    ),
+                }
+            },
+            (_, Some(field)) => {
+                return Err(format!(
+                    "only SOA records have fields to expect, {} records have no {:?}",
+                    record_type, field
+                ))
+            },
+            (RecordType::A, None) => Matcher::Address(
+                Ipv4Addr::from_str(value.trim())
+                    .map_err(|_| format!("{:?} is no IPv4 address", value))?
+                    .into(),
+            ),
+            (RecordType::AAAA, None) => Matcher::Address(
+                Ipv6Addr::from_str(value.trim())
+                    .map_err(|_| format!("{:?} is no IPv6 address", value))?
+                    .into(),
+            ),
+            (RecordType::MX, None) => {
+                let value = value.trim();
+                match value.split_once(|c: char| c == ':' || c.is_whitespace()) {
+                    Some((preference, host)) => {
+                        let preference = preference.parse().map_err(|_| {
+                            format!(
+                                "the preference {:?} of MX records is a number from 0 to 65535",
+                                preference
+                            )
+                        })?;
+                        Matcher::Mx(Some(preference), parse_name(host.trim())?)
+                    },
+                    None => Matcher::Mx(None, parse_name(value)?),
+                }
+            },
+            (RecordType::TXT, None) => Matcher::Text(value.to_owned()),
+            (RecordType::CNAME | RecordType::NS | RecordType::PTR, None) => {
+                Matcher::Name(parse_name(value.trim())?)
+            },
+            (RecordType::SOA, None) => Matcher::Data(words(&ttl::soa_in_seconds(value))),
+            (_, None) => Matcher::Data(words(value)),
+        };
+        Ok(Self {
+            text: text.to_owned(),
+            record_type,
+            matcher,
+        })
+    }
+}
+
+impl Display for Expectation {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Expectation {
+    /// Type of the records that can meet the expectation, which the run queries
+    pub fn record_type(&self) -> RecordType {
+        self.record_type
+    }
+
+    /// Whether `record` meets the expectation
+    pub fn matches(
+        &self,
+        record: &Record,
+    ) -> bool {
+        if record.record_type() != self.record_type {
+            return false;
+        }
+        let Some(data) = record.data() else {
+            return false;
+        };
+        match (&self.matcher, data) {
+            (Matcher::Address(address), _) => asn::address_of(record) == Some(*address),
+            (Matcher::Mx(preference, host), RData::MX(mx)) => {
+                preference.is_none_or(|preference| preference == mx.preference())
+                    && same_name(host, mx.exchange())
+            },
+            (Matcher::Name(name), data) => {
+                target(data).is_some_and(|target| same_name(name, target))
+            },
+            (Matcher::Text(text), data) => decoded_text(data).as_ref() == Some(text),
+            (Matcher::SoaName(field, name), RData::SOA(soa)) => {
+                let value = match *field {
+                    "mname" => soa.mname(),
+                    _ => soa.rname(),
+                };
+                same_name(name, value)
+            },
+            (Matcher::SoaNumber(field, number), RData::SOA(soa)) => {
+                soa_field(soa, field) == number.to_string()
+            },
+            (Matcher::Data(expected), data) => words(&format::data_text(data)) == *expected,
+            _ => false,
+        }
+    }
+
+    /// What the records of the type of the expectation among `answers` hold, as it compares
+    /// them, for the message of an expectation that none of them met
+    pub fn found(
+        &self,
+        answers: &[&Record],
+    ) -> Vec<String> {
+        answers
+            .iter()
+            .filter(|record| record.record_type() == self.record_type)
+            .filter_map(|record| record.data())
+            .map(|data| match (&self.matcher, data) {
+                (Matcher::Text(_), data) => format!("{:?}", decoded_text(data).unwrap_or_default()),
+                (Matcher::SoaName(field, _) | Matcher::SoaNumber(field, _), RData::SOA(soa)) => {
+                    soa_field(soa, field)
+                },
+                (_, data) => format::data_text(data),
+            })
+            .collect()
+    }
+}
+
+/// Print every expectation of `expectations` that no record of `answers` meets on stderr, with
+/// what the answers hold instead, and return how many there are
+pub fn report(
+    expectations: &[Expectation],
+    answers: &[&Record],
+) -> usize {
+    let unmet: Vec<&Expectation> = expectations
+        .iter()
+        .filter(|expectation| !answers.iter().any(|record| expectation.matches(record)))
+        .collect();
+    for expectation in &unmet {
+        let found = expectation.found(answers);
+        match found.is_empty() {
+            true => eprintln!(
+                "Error: Expected {}, got no {} records",
+                expectation, expectation.record_type
+            ),
+            false => eprintln!("Error: Expected {}, got {}", expectation, found.join(", ")),
+        }
+    }
+    unmet.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_client::rr::rdata::{A, AAAA, CNAME, MX, TXT};
+
+    use super::*;
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn record(data: RData) -> Record {
+        Record::from_rdata(name("example.com."), 300, data)
+    }
+
+    fn expect(text: &str) -> Expectation {
+        Expectation::from_str(text).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        Expectation::from_str(text).unwrap_err()
+    }
+
+    #[test]
+    fn addresses_are_compared_as_addresses() {
+        let v4 = record(RData::A(A::new(192, 0, 2, 1)));
+        let v6 = record(RData::AAAA(AAAA::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)));
+        assert!(expect("A=192.0.2.1").matches(&v4));
+        assert!(!expect("a=192.0.2.10").matches(&v4));
+        assert!(expect("AAAA=2001:DB8:0:0::1").matches(&v6));
+        // Records of another type never meet it
+        assert!(!expect("AAAA=2001:db8::1").matches(&v4));
+        assert_eq!(error("A=2001:db8::1"), "\"2001:db8::1\" is no IPv4 address");
+        assert_eq!(error("AAAA=192.0.2.1"), "\"192.0.2.1\" is no IPv6 address");
+    }
+
+    #[test]
+    fn mail_exchanges_match_with_or_without_their_preference() {
+        let mx = record(RData::MX(MX::new(10, name("mail.example.com."))));
+        assert!(expect("MX=10:mail.example.com").matches(&mx));
+        assert!(expect("MX=10  Mail.Example.COM.").matches(&mx));
+        assert!(expect("mx=mail.example.com").matches(&mx));
+        assert!(!expect("MX=20:mail.example.com").matches(&mx));
+        assert!(!expect("MX=backup.example.com").matches(&mx));
+        assert_eq!(
+            error("MX=high:mail.example.com"),
+            "the preference \"high\" of MX records is a number from 0 to 65535"
+        );
+        assert_eq!(
+            error("MX=10:mail..example"),
+            "\"mail..example\" is no valid name"
+        );
+    }
+
+    #[test]
+    fn texts_are_decoded_and_concatenated() {
+        let strings = vec![
+            String::from("v=spf1 include:_spf.example.com "),
+            String::from("-all"),
+        ];
+        let txt = record(RData::TXT(TXT::new(strings)));
+        assert!(expect("TXT=v=spf1 include:_spf.example.com -all").matches(&txt));
+        // Quotes are those of the output, not of the text
+        assert!(!expect("TXT=\"v=spf1 include:_spf.example.com -all\"").matches(&txt));
+        assert!(!expect("TXT=v=spf1 -all").matches(&txt));
+        assert_eq!(
+            expect("TXT=v=spf1 -all").found(&[&txt]),
+            ["\"v=spf1 include:_spf.example.com -all\""]
+        );
+    }
+
+    #[test]
+    fn fields_of_soa_records_are_compared_alone() {
+        let soa = record(RData::SOA(SOA::new(
+            name("ns1.example.com."),
+            name("hostmaster.example.com."),
+            2026101401,
+            7200,
+            3600,
+            1209600,
+            600,
+        )));
+        assert!(expect("soa.mname=ns1.example.com").matches(&soa));
+        assert!(expect("SOA.rname=HOSTMASTER.example.com.").matches(&soa));
+        assert!(expect("soa.minimum=600").matches(&soa));
+        assert!(!expect("soa.refresh=3600").matches(&soa));
+        // Timers are TTLs, compared in seconds whatever their unit
+        for refresh in ["7200", "7200s", "120m", "2h"] {
+            assert!(expect(&format!("soa.refresh={}", refresh)).matches(&soa));
+        }
+        assert!(expect("soa.expire=14d").matches(&soa));
+        assert!(!expect("soa.retry=2h").matches(&soa));
+        assert!(
+            expect("SOA=ns1.example.com hostmaster.example.com 2026101401 2h 1h 14d 10m")
+                .matches(&soa)
+        );
+        assert_eq!(expect("soa.serial=1").found(&[&soa]), ["2026101401"]);
+        // The record as a whole is compared word by word
+        assert!(expect(
+            "SOA=ns1.example.com hostmaster.example.com 2026101401 7200 3600 1209600 600"
+        )
+        .matches(&soa));
+        assert_eq!(
+            error("soa.owner=ns1.example.com"),
+            "SOA records have no field \"owner\", use mname, rname, serial, refresh, retry, \
+             expire, minimum"
+        );
+        assert_eq!(
+            error("soa.serial=yesterday"),
+            "the serial of SOA records is a number, not \"yesterday\""
+        );
+        assert_eq!(
+            error("soa.serial=1h"),
+            "the serial of SOA records is a number, not \"1h\""
+        );
+        assert_eq!(
+            error("soa.minimum=1.5s"),
+            "the minimum of SOA records: ttl \"1.5s\" is not a whole number of seconds"
+        );
+        assert_eq!(
+            error("mx.preference=10"),
+            "only SOA records have fields to expect, MX records have no \"preference\""
+        );
+    }
+
+    #[test]
+    fn names_and_other_data_ignore_case_and_final_dots() {
+        let cname = record(RData::CNAME(CNAME(name("edge.example.net."))));
+        assert!(expect("CNAME=Edge.Example.net").matches(&cname));
+        assert!(!expect("CNAME=example.net").matches(&cname));
+        let srv = record(RData::SRV(hickory_client::rr::rdata::SRV::new(
+            10,
+            5,
+            443,
+            name("host.example.com."),
+        )));
+        assert!(expect("SRV=10  5 443 HOST.example.com").matches(&srv));
+        assert!(!expect("SRV=10 5 8443 host.example.com").matches(&srv));
+    }
+
+    #[test]
+    fn expectations_need_a_type_and_a_value() {
+        assert_eq!(
+            error("192.0.2.1"),
+            "expected TYPE=VALUE, e.g. MX=10:mail.example.com, or soa.FIELD=VALUE"
+        );
+        assert_eq!(error("ADDRESS=192.0.2.1"), "\"ADDRESS\" is no record type");
+        assert_eq!(error("MX= "), "no value is expected of MX");
+    }
+
+    #[test]
+    fn unmet_expectations_are_counted() {
+        let answers = [
+            record(RData::A(A::new(192, 0, 2, 1))),
+            record(RData::MX(MX::new(10, name("mail.example.com.")))),
+        ];
+        let answers: Vec<&Record> = answers.iter().collect();
+        let expectations = [
+            expect("A=192.0.2.1"),
+            expect("MX=20:mail.example.com"),
+            expect("TXT=v=spf1 -all"),
+        ];
+        assert_eq!(report(&expectations, &answers), 2);
+        assert_eq!(report(&expectations[..1], &answers), 0);
+    }
+}
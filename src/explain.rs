@@ -0,0 +1,241 @@
+//! Plain-English interpretation of query results for `--explain`.
+//!
+//! All user-facing wording lives in this module so it can be translated later.
+
+use hickory_client::{
+    op::ResponseCode,
+    rr::{
+        rdata::{caa::Value, CAA, MX, SOA, SRV, TXT},
+        Name, RData, Record, RecordType,
+    },
+};
+
+use crate::format::format_ttl;
+
+/// Outcome of a single (name, type) question
+pub struct Outcome<'a> {
+    pub name: &'a Name,
+    pub record_type: RecordType,
+    pub response_code: ResponseCode,
+    pub answers: &'a [Record],
+}
+
+pub fn explain(outcomes: &[Outcome]) -> Vec<String> {
+    let mut lines = vec![String::from("Explanation:")];
+    let mut described: Vec<RecordType> = Vec::new();
+
+    for outcome in outcomes {
+        if let Some(line) = explain_outcome(outcome) {
+            lines.push(format!("  {}", line));
+            continue;
+        }
+
+        for record in outcome.answers {
+            let record_type = record.record_type();
+            if !described.contains(&record_type) {
+                described.push(record_type);
+                if let Some(description) = describe_type(record_type) {
+                    lines.push(format!("  {}: {}", record_type, description));
+                }
+            }
+            lines.push(format!(
+                "    - {} {}",
+                explain_record(record),
+                explain_ttl(record.ttl())
+            ));
+        }
+    }
+
+    lines
+}
+
+/// One-line description of what a record type is used for
+pub fn describe_type(record_type: RecordType) -> Option<&'static str> {
+    use RecordType::*;
+    Some(match record_type {
+        A => "an IPv4 address of the host",
+        AAAA => "an IPv6 address of the host",
+        CNAME => "an alias; the name is another name for the target",
+        MX => "a mail server accepting email for the domain",
+        NS => "a name server that is authoritative for the zone",
+        SOA => "the start of authority, holding administrative data of the zone",
+        TXT => "free-form text, often used for email policies and ownership proofs",
+        CAA => "which certificate authorities may issue certificates for the domain",
+        SRV => "the host and port providing a specific service",
+        PTR => "the name an address maps back to (reverse lookup)",
+        _ => return None,
+    })
+}
+
+fn explain_outcome(outcome: &Outcome) -> Option<String> {
+    match outcome.response_code {
+        ResponseCode::NoError if outcome.answers.is_empty() => Some(format!(
+            "{} exists, but has no {} records (NODATA).",
+            outcome.name, outcome.record_type
+        )),
+        ResponseCode::NoError => None,
+        ResponseCode::NXDomain => Some(format!(
+            "{} does not exist (NXDOMAIN): no records of any type are published for it.",
+            outcome.name
+        )),
+        ResponseCode::ServFail => Some(format!(
+            "The server failed to answer {} for {} (SERVFAIL); the domain may be misconfigured or \
+             fail DNSSEC validation.",
+            outcome.record_type, outcome.name
+        )),
+        ResponseCode::Refused => Some(format!(
+            "The server refused to answer {} for {} (REFUSED); it may not serve this zone or \
+             allow recursion for you.",
+            outcome.record_type, outcome.name
+        )),
+        code => Some(format!(
+            "The server answered {} for {} with {}.",
+            outcome.record_type, outcome.name, code
+        )),
+    }
+}
+
+fn explain_record(record: &Record) -> String {
+    let name = record.name();
+    match record.data() {
+        Some(RData::A(address)) => format!("{} points to the IPv4 address {}.", name, address),
+        Some(RData::AAAA(address)) => format!("{} points to the IPv6 address {}.", name, address),
+        Some(RData::CNAME(target)) => {
+            format!(
+                "{} is an alias; lookups continue at {} instead.",
+                name, target.0
+            )
+        },
+        Some(RData::MX(mx)) => explain_mx(name, mx),
+        Some(RData::NS(ns)) => format!(
+            "The zone {} is served by the name server {}, which is authoritative for it.",
+            name, ns.0
+        ),
+        Some(RData::SOA(soa)) => explain_soa(name, soa),
+        Some(RData::TXT(txt)) => explain_txt(name, txt),
+        Some(RData::CAA(caa)) => explain_caa(name, caa),
+        Some(RData::SRV(srv)) => explain_srv(name, srv),
+        Some(RData::PTR(target)) => format!(
+            "{} maps back to the name {}, as used for reverse lookups.",
+            name, target.0
+        ),
+        Some(data) => format!("{} has {} data {}.", name, record.record_type(), data),
+        None => format!("{} has an empty {} record.", name, record.record_type()),
+    }
+}
+
+fn explain_mx(
+    name: &Name,
+    mx: &MX,
+) -> String {
+    if mx.exchange().is_root() {
+        return format!("{} explicitly accepts no email (null MX).", name);
+    }
+    format!(
+        "Mail for {} is delivered to {} with priority {} (lower is tried first).",
+        name,
+        mx.exchange(),
+        mx.preference()
+    )
+}
+
+fn explain_soa(
+    name: &Name,
+    soa: &SOA,
+) -> String {
+    format!(
+        "The zone {} has primary server {}, contact {}, serial {}; negative answers are cached \
+         for {}.",
+        name,
+        soa.mname(),
+        soa.rname(),
+        soa.serial(),
+        format_ttl(soa.minimum())
+    )
+}
+
+fn explain_txt(
+    name: &Name,
+    txt: &TXT,
+) -> String {
+    let text: String = txt
+        .txt_data()
+        .iter()
+        .map(|data| String::from_utf8_lossy(data))
+        .collect();
+
+    if text.starts_with("v=spf1") {
+        format!(
+            "{} publishes an SPF policy listing which servers may send its email.",
+            name
+        )
+    } else if text.starts_with("v=DMARC1") {
+        let policy = text
+            .split(';')
+            .filter_map(|tag| tag.trim().strip_prefix("p="))
+            .next()
+            .unwrap_or("none");
+        format!(
+            "{} publishes a DMARC policy: receivers should apply \"{}\" to mail failing \
+             SPF/DKIM checks.",
+            name, policy
+        )
+    } else if text.starts_with("v=DKIM1") || name.to_ascii().contains("._domainkey.") {
+        format!(
+            "{} publishes a DKIM public key used to verify email signatures.",
+            name
+        )
+    } else {
+        format!("{} carries the text \"{}\".", name, text)
+    }
+}
+
+fn explain_caa(
+    name: &Name,
+    caa: &CAA,
+) -> String {
+    match caa.value() {
+        Value::Issuer(Some(issuer), _) if caa.tag().is_issuewild() => {
+            format!("{} allows {} to issue wildcard certificates.", name, issuer)
+        },
+        Value::Issuer(Some(issuer), _) => {
+            format!("{} allows {} to issue certificates.", name, issuer)
+        },
+        Value::Issuer(None, _) => format!(
+            "{} forbids all certificate authorities from issuing {}.",
+            name,
+            caa.tag().as_str()
+        ),
+        Value::Url(url) => format!(
+            "{} asks certificate authorities to report policy violations to {}.",
+            name, url
+        ),
+        Value::Unknown(_) => format!(
+            "{} has a CAA property {} unknown to resoy.",
+            name,
+            caa.tag().as_str()
+        ),
+    }
+}
+
+fn explain_srv(
+    name: &Name,
+    srv: &SRV,
+) -> String {
+    format!(
+        "The service {} is provided by {} on port {} (priority {}, weight {}).",
+        name,
+        srv.target(),
+        srv.port(),
+        srv.priority(),
+        srv.weight()
+    )
+}
+
+fn explain_ttl(ttl: u32) -> String {
+    if ttl == 0 {
+        String::from("Resolvers must not cache it.")
+    } else {
+        format!("Resolvers may cache it for up to {}.", format_ttl(ttl))
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use hickory_client::{
+    op::ResponseCode,
+    rr::{DNSClass, Name, Record, RecordType},
+};
+
+use crate::{asn::address_of, DnsClient};
+
+/// Timeout of the canary queries of `--check-filtering`
+pub const FILTERING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Block page addresses that filtering resolvers answer with, and who runs them
+const BLOCK_PAGE_ADDRESSES: [(&str, &str); 6] = [
+    ("146.112.61.104", "Cisco Umbrella"),
+    ("146.112.61.105", "Cisco Umbrella"),
+    ("146.112.61.106", "Cisco Umbrella"),
+    ("146.112.61.107", "Cisco Umbrella"),
+    ("146.112.61.108", "Cisco Umbrella"),
+    ("146.112.61.110", "Cisco Umbrella"),
+];
+
+/// Why an address looks like the answer of a filtering resolver
+pub fn suspect(address: IpAddr) -> Option<&'static str> {
+    if address == IpAddr::V4(Ipv4Addr::UNSPECIFIED) || address == IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    {
+        return Some("sinkhole");
+    }
+    BLOCK_PAGE_ADDRESSES
+        .iter()
+        .find(|(block_page, _)| IpAddr::from_str(block_page).is_ok_and(|page| page == address))
+        .map(|(_, provider)| *provider)
+}
+
+/// Suffix annotating a suspicious address in plain output
+pub fn annotation(record: &Record) -> Option<String> {
+    let reason = suspect(address_of(record)?)?;
+    Some(format!("  [filtered? {}]", reason))
+}
+
+/// How the resolver answered a canary
+enum Answer {
+    Resolved(Vec<IpAddr>),
+    /// a sinkhole or block page address, a refusal or a name said not to exist
+    Filtered(String),
+    Failed(String),
+}
+
+struct Canary {
+    name: Name,
+    answer: Answer,
+}
+
+impl Display for Canary {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match &self.answer {
+            Answer::Resolved(addresses) => {
+                let addresses: Vec<String> = addresses.iter().map(ToString::to_string).collect();
+                write!(f, "{}: {}", self.name, addresses.join(", "))
+            },
+            Answer::Filtered(reason) => write!(f, "{}: filtered, {}", self.name, reason),
+            Answer::Failed(error) => write!(f, "{}: query failed: {}", self.name, error),
+        }
+    }
+}
+
+fn ask(
+    client: &DnsClient,
+    name: &Name,
+) -> Answer {
+    let response = match client.query(name, DNSClass::IN, RecordType::A) {
+        Ok(response) => response,
+        Err(error) => return Answer::Failed(error.to_string()),
+    };
+    match response.response_code() {
+        ResponseCode::NoError => {},
+        code @ (ResponseCode::NXDomain | ResponseCode::Refused) => {
+            return Answer::Filtered(format!("{:?}", code).to_uppercase())
+        },
+        code => return Answer::Failed(format!("{:?}", code).to_uppercase()),
+    }
+    let addresses: Vec<IpAddr> = response.answers().iter().filter_map(address_of).collect();
+    match addresses
+        .iter()
+        .find_map(|address| suspect(*address).map(|reason| (address, reason)))
+    {
+        Some((address, reason)) => Answer::Filtered(format!("{} ({})", address, reason)),
+        None => Answer::Resolved(addresses),
+    }
+}
+
+/// Outcome of `--check-filtering`: a benign canary that must resolve, and canaries that
+/// filtering resolvers block
+pub struct Check {
+    benign: Canary,
+    blocked: Vec<Canary>,
+}
+
+impl Check {
+    pub fn run(
+        client: &DnsClient,
+        benign: &Name,
+        blocked: &[Name],
+    ) -> Self {
+        thread::scope(|scope| {
+            let benign_answer = scope.spawn(|| ask(client, benign));
+            let handles: Vec<_> = blocked
+                .iter()
+                .map(|name| scope.spawn(move || ask(client, name)))
+                .collect();
+            let blocked = handles
+                .into_iter()
+                .zip(blocked)
+                .map(|(handle, name)| Canary {
+                    name: name.clone(),
+                    answer: handle
+                        .join()
+                        .unwrap_or_else(|_| Answer::Failed(String::from("lookup panicked"))),
+                })
+                .collect();
+            Self {
+                benign: Canary {
+                    name: benign.clone(),
+                    answer: benign_answer
+                        .join()
+                        .unwrap_or_else(|_| Answer::Failed(String::from("lookup panicked"))),
+                },
+                blocked,
+            }
+        })
+    }
+
+    fn verdict(&self) -> String {
+        if !matches!(self.benign.answer, Answer::Resolved(_)) {
+            return format!(
+                "inconclusive, the benign canary {} does not resolve normally",
+                self.benign.name
+            );
+        }
+        let filtered = self
+            .blocked
+            .iter()
+            .filter(|canary| matches!(canary.answer, Answer::Filtered(_)))
+            .count();
+        let resolved = self
+            .blocked
+            .iter()
+            .filter(|canary| matches!(canary.answer, Answer::Resolved(_)))
+            .count();
+        match (filtered, resolved) {
+            (0, 0) => String::from("inconclusive, no blocked canary could be looked up"),
+            (0, _) => String::from("this resolver does not filter"),
+            (filtered, _) => format!(
+                "this resolver filters, {} of {} blocked canaries were withheld",
+                filtered,
+                self.blocked.len()
+            ),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        writeln!(f, "  benign:  {}", self.benign)?;
+        for canary in &self.blocked {
+            writeln!(f, "  blocked: {}", canary)?;
+        }
+        write!(f, "  {}", self.verdict())
+    }
+}
@@ -0,0 +1,120 @@
+use hickory_client::{
+    op::DnsResponse,
+    rr::{DNSClass, Name, RData, Record, RecordType},
+};
+
+use crate::{AppError, DnsClient};
+
+/// CNAMEs followed at most from the queried name, with `--follow`
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+/// The response with its answers in the order of the CNAME chain from `name` on, from the
+/// queried name to the records of the last target, the answers outside of the chain coming
+/// last.
+///
+/// Targets that the response has no records of are asked for with `client`, as servers only
+/// answer for the zones they have, and the response code becomes that of the last of these
+/// queries, e.g. NXDOMAIN for a target that doesn't exist. Chains of more than `max_depth`
+/// CNAMEs and chains that come back to a name fail.
+pub fn follow(
+    client: &DnsClient,
+    name: &Name,
+    class: DNSClass,
+    record_type: RecordType,
+    response: DnsResponse,
+    max_depth: usize,
+) -> Result<DnsResponse, AppError> {
+    let error =
+        |message: String| AppError::FollowError(format!("{} {}", name, record_type), message);
+    // The CNAMEs themselves are the records asked for
+    if matches!(record_type, RecordType::CNAME | RecordType::ANY) {
+        return Ok(response);
+    }
+    let mut message = response.into_message();
+    let mut pool = message.take_answers();
+    let mut chain: Vec<Record> = Vec::new();
+    let mut path = vec![name.clone()];
+    let mut owner = name.clone();
+    loop {
+        // Signatures and NSEC records come along with the records of the owner
+        let (records, rest): (Vec<Record>, Vec<Record>) =
+            pool.into_iter().partition(|record| record.name() == &owner);
+        pool = rest;
+        let target = records.iter().find_map(|record| match record.data() {
+            Some(RData::CNAME(target)) => Some(target.0.clone()),
+            _ => None,
+        });
+        let found = !records.is_empty();
+        chain.extend(records);
+        let target = match (found, target) {
+            (_, Some(target)) => target,
+            // The records of the last name end the chain
+            (true, None) => break,
+            // The first name is the one the response is about
+            (false, None) if path.len() == 1 => break,
+            (false, None) => {
+                let follow_up = client
+                    .query(&owner, class, record_type)
+                    .map_err(|failure| error(format!("cannot ask for {}: {}", owner, failure)))?;
+                message.set_response_code(follow_up.response_code());
+                let answers = follow_up.into_message().take_answers();
+                // A target without records, or one the server won't tell about, ends the chain
+                if !answers.iter().any(|record| record.name() == &owner) {
+                    break;
+                }
+                pool.extend(answers);
+                continue;
+            },
+        };
+        if path.contains(&target) {
+            path.push(target);
+            return Err(error(format!("the CNAMEs loop: {}", join(&path))));
+        }
+        if path.len() > max_depth {
+            return Err(error(format!(
+                "more than {} CNAMEs: {} -> {} ...",
+                max_depth,
+                join(&path),
+                target
+            )));
+        }
+        path.push(target.clone());
+        owner = target;
+    }
+    chain.extend(pool);
+    message.insert_answers(chain);
+    DnsResponse::from_message(message).map_err(|failure| error(failure.to_string()))
+}
+
+/// How many CNAMEs lead from `name` to the owner of each of `records`, as `follow` orders
+/// them, `None` for the records outside of the chain
+pub fn steps<'r>(
+    name: &'r Name,
+    records: &'r [Record],
+) -> Vec<Option<usize>> {
+    let mut owner = name;
+    let mut step = 0;
+    let mut target: Option<&Name> = None;
+    records
+        .iter()
+        .map(|record| {
+            if target == Some(record.name()) {
+                step += 1;
+                owner = record.name();
+                target = None;
+            }
+            if record.name() != owner {
+                return None;
+            }
+            if let Some(RData::CNAME(next)) = record.data() {
+                target = Some(&next.0);
+            }
+            Some(step)
+        })
+        .collect()
+}
+
+fn join(path: &[Name]) -> String {
+    let names: Vec<String> = path.iter().map(Name::to_string).collect();
+    names.join(" -> ")
+}
@@ -1,12 +1,166 @@
-#[cfg(not(windows))]
-extern crate termion;
-use hickory_client::rr::{Record, RecordType};
-#[cfg(not(windows))]
-use termion::color::{AnsiValue, Fg, Reset};
+use std::{
+    cmp,
+    fmt::{Display, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+
+use hickory_client::{
+    op::{Edns, Message, MessageType},
+    rr::{
+        rdata::{
+            opt::{EdnsCode, EdnsOption},
+            TXT,
+        },
+        DNSClass, Name, RData, Record, RecordType,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(ValueEnum, Clone, Debug, Copy, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Text,
+    /// one TSV row per queried name and type, for column stores
+    Widerow,
+    /// a JSON object of the records, with structured data, and of the hints about them
+    Json,
+    /// shell variable assignments to eval or source, see --env-prefix
+    Env,
+    /// only the data of the records, one per line
+    Short,
+    /// one CSV row per record under a header, with the TTL in seconds
+    Csv,
+    /// one TSV row per record under a header, with the TTL in seconds
+    Tsv,
+}
+
+impl Display for OutputFormat {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let s = match self {
+            Self::Text => "text",
+            Self::Widerow => "widerow",
+            Self::Json => "json",
+            Self::Env => "env",
+            Self::Short => "short",
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+        };
+        write!(f, "{}", s)?;
+        Ok(())
+    }
+}
+
+/// Upper bound of the formatted data of a single record, longer data is cut
+const MAX_PAYLOAD_LENGTH: usize = 4096;
+
+/// Escape sequences of the few attributes used, which Windows Terminal understands like the
+/// terminals of other systems
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[m";
+const INVERT: &str = "\x1b[7m";
+const NO_INVERT: &str = "\x1b[27m";
+const DEFAULT_FOREGROUND: &str = "\x1b[39m";
+
+/// Palette index of the color of alerts
+const RED: u8 = 1;
+
+/// Palette index of the color of owner names
+const NAME_COLOR: u8 = 75;
+
+/// Palette indexes of the colors of the addresses and numbers in the data of records
+const ADDRESS_COLOR: u8 = 214;
+const NUMBER_COLOR: u8 = 141;
+
+/// Escape sequence of the foreground color at `index` of the 256-color palette
+fn foreground(index: u8) -> String {
+    format!("\x1b[38;5;{}m", index)
+}
+
+/// Color of every record type, as an index of the 256-color palette and its xterm name.
+///
+/// Colors are part of the interface: a type keeps its color across runs and releases.
+const PALETTE: &[(RecordType, u8, &str)] = &[
+    (RecordType::A, 1, "maroon"),
+    (RecordType::AAAA, 2, "green"),
+    (RecordType::ANAME, 3, "olive"),
+    (RecordType::ANY, 4, "navy"),
+    (RecordType::AXFR, 5, "purple"),
+    (RecordType::CAA, 6, "teal"),
+    (RecordType::CDS, 7, "silver"),
+    (RecordType::CDNSKEY, 8, "grey"),
+    (RecordType::CSYNC, 9, "red"),
+    (RecordType::DNSKEY, 10, "lime"),
+    (RecordType::DS, 11, "yellow"),
+    (RecordType::HINFO, 12, "blue"),
+    (RecordType::HTTPS, 13, "fuchsia"),
+    (RecordType::IXFR, 14, "aqua"),
+    (RecordType::KEY, 15, "white"),
+    (RecordType::MX, 38, "deep sky blue 2"),
+    (RecordType::NAPTR, 17, "navy blue"),
+    (RecordType::NS, 18, "dark blue"),
+    (RecordType::NSEC, 19, "blue 3"),
+    (RecordType::NSEC3, 20, "blue 3"),
+    (RecordType::NSEC3PARAM, 21, "blue 1"),
+    (RecordType::NULL, 22, "dark green"),
+    (RecordType::OPENPGPKEY, 23, "deep sky blue 4"),
+    (RecordType::OPT, 24, "deep sky blue 4"),
+    (RecordType::PTR, 25, "deep sky blue 4"),
+    (RecordType::RRSIG, 26, "dodger blue 3"),
+    (RecordType::SIG, 27, "dodger blue 2"),
+    (RecordType::SOA, 28, "green 4"),
+    (RecordType::SRV, 29, "spring green 4"),
+    (RecordType::SSHFP, 30, "turquoise 4"),
+    (RecordType::SVCB, 31, "deep sky blue 3"),
+    (RecordType::TLSA, 32, "deep sky blue 3"),
+    (RecordType::TSIG, 33, "dodger blue 1"),
+    (RecordType::TXT, 34, "green 3"),
+    (RecordType::ZERO, 36, "dark cyan"),
+];
+
+/// Color of unknown record types
+const UNKNOWN_COLOR: (u8, &str) = (35, "spring green 3");
+
+/// Color of the types missing from the palette
+const OTHER_COLOR: (u8, &str) = (37, "light sea green");
+
+/// Palette index and name of the color of `record_type`
+fn palette_entry(record_type: RecordType) -> (u8, &'static str) {
+    if let RecordType::Unknown(_) = record_type {
+        return UNKNOWN_COLOR;
+    }
+    PALETTE
+        .iter()
+        .find(|(entry, _, _)| *entry == record_type)
+        .map(|(_, color, name)| (*color, *name))
+        .unwrap_or(OTHER_COLOR)
+}
 
 pub struct OutputConfig {
     format_seconds: bool,
+    /// TTLs in the largest unit dividing them, e.g. `2h`, winning over `format_seconds`
+    ttl_units: bool,
     ansi: bool,
+    /// layout of `--format` replacing that of the text output
+    template: Option<Template>,
+    /// names in their xn-- form rather than in Unicode
+    ascii_names: bool,
+    /// the strings of TXT records joined into one
+    txt_concat: bool,
+    /// whether the header of the CSV, TSV and widerow tables was printed already, shared by
+    /// the configurations of a run so that the records of several names make one table
+    table_header: Arc<AtomicBool>,
 }
 
 impl OutputConfig {
@@ -16,149 +170,1635 @@ impl OutputConfig {
     ) -> Self {
         Self {
             format_seconds,
+            ttl_units: false,
             ansi,
+            template: None,
+            ascii_names: false,
+            txt_concat: false,
+            table_header: Arc::default(),
+        }
+    }
+
+    /// Lay every record out with `template` instead
+    pub fn with_template(
+        mut self,
+        template: Option<Template>,
+    ) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Print TTLs with a unit, e.g. `2h` or `90m`, as the inputs taking TTLs read them
+    pub fn with_ttl_units(
+        mut self,
+        ttl_units: bool,
+    ) -> Self {
+        self.ttl_units = ttl_units;
+        self
+    }
+
+    /// Print internationalized names in their xn-- form, as they are sent over the wire
+    pub fn with_ascii_names(
+        mut self,
+        ascii_names: bool,
+    ) -> Self {
+        self.ascii_names = ascii_names;
+        self
+    }
+
+    pub fn ascii_names(&self) -> bool {
+        self.ascii_names
+    }
+
+    /// Print the strings of TXT records as one, as DKIM keys split over several are meant
+    pub fn with_txt_concat(
+        mut self,
+        txt_concat: bool,
+    ) -> Self {
+        self.txt_concat = txt_concat;
+        self
+    }
+
+    pub fn txt_concat(&self) -> bool {
+        self.txt_concat
+    }
+
+    /// Print the header of the tables only once over every configuration sharing `printed`
+    pub fn with_table_header(
+        mut self,
+        printed: Arc<AtomicBool>,
+    ) -> Self {
+        self.table_header = printed;
+        self
+    }
+
+    /// Whether the header of the table is still to be printed, which it is from now on
+    pub fn starts_table(&self) -> bool {
+        !self.table_header.swap(true, Ordering::Relaxed)
+    }
+
+    /// Header of a group of records, bold when colors are enabled
+    pub fn format_header(
+        &self,
+        header: &str,
+    ) -> String {
+        if self.ansi {
+            format!("{}{}{}", BOLD, header, RESET)
+        } else {
+            header.to_owned()
+        }
+    }
+
+    /// Text that must not go unnoticed, red when colors are enabled
+    pub fn format_alert(
+        &self,
+        text: &str,
+    ) -> String {
+        if self.ansi {
+            format!("{}{}{}", foreground(RED), text, DEFAULT_FOREGROUND)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// Line of a record that changed, in reverse video when colors are enabled, which the
+    /// colors of the line don't undo, and marked as changed otherwise
+    pub fn format_changed(
+        &self,
+        line: &str,
+    ) -> String {
+        if self.ansi {
+            format!("{}{}{}", INVERT, line, NO_INVERT)
+        } else {
+            format!("{}  [changed]", line)
+        }
+    }
+
+    /// One line naming the color of each of `record_types`, `None` when colors are disabled
+    pub fn format_legend(
+        &self,
+        record_types: &[RecordType],
+    ) -> Option<String> {
+        if !self.ansi || record_types.is_empty() {
+            return None;
+        }
+        let entries: Vec<String> = record_types
+            .iter()
+            .map(|record_type| {
+                let (color, name) = palette_entry(*record_type);
+                format!(
+                    "{}{}{} {}",
+                    foreground(color),
+                    record_type,
+                    DEFAULT_FOREGROUND,
+                    name
+                )
+            })
+            .collect();
+        Some(format!("legend: {}", entries.join(", ")))
+    }
+}
+
+/// Key records are grouped by with `--group-by`
+#[derive(Clone, Copy)]
+pub enum GroupKey {
+    Name,
+    Type,
+}
+
+/// Group `records` under headers, names in order of appearance and types in canonical order
+pub fn group_records(
+    records: Vec<&Record>,
+    key: GroupKey,
+) -> Vec<(String, Vec<&Record>)> {
+    let mut groups: Vec<(String, Vec<&Record>)> = Vec::new();
+    let mut sorted = records;
+    if let GroupKey::Type = key {
+        sorted.sort_by_key(|record| u16::from(record.record_type()));
+    }
+    for record in sorted {
+        let header = match key {
+            GroupKey::Name => record.name().to_string(),
+            GroupKey::Type => record.record_type().to_string(),
+        };
+        match groups.iter_mut().find(|(existing, _)| *existing == header) {
+            Some((_, members)) => members.push(record),
+            None => groups.push((header, vec![record])),
+        }
+    }
+    groups
+}
+
+/// `groups` of `group_records` with an empty group for `name`, or for every one of `queried`
+/// that no record is of, in the same order, for `--group-by` with `--verbose`
+pub fn with_empty_groups<'r>(
+    mut groups: Vec<(String, Vec<&'r Record>)>,
+    key: GroupKey,
+    name: &Name,
+    queried: &[RecordType],
+) -> Vec<(String, Vec<&'r Record>)> {
+    let mut headers: Vec<String> = match key {
+        GroupKey::Name => {
+            let mut name = name.clone();
+            name.set_fqdn(true);
+            vec![name.to_string()]
+        },
+        GroupKey::Type => queried.iter().map(ToString::to_string).collect(),
+    };
+    headers.retain(|header| !groups.iter().any(|(existing, _)| existing == header));
+    let empty = headers.into_iter().map(|header| (header, Vec::new()));
+    match key {
+        // The name asked for comes before the targets of its CNAMEs
+        GroupKey::Name => {
+            groups.splice(0..0, empty);
+        },
+        GroupKey::Type => {
+            groups.extend(empty);
+            groups.sort_by_key(|(header, _)| {
+                RecordType::from_str(header).map_or(u16::MAX, u16::from)
+            });
+        },
+    }
+    groups
+}
+
+/// Field records are sorted by with `--sort`
+#[derive(Clone, Copy)]
+pub enum SortField {
+    Type,
+    Name,
+    Ttl,
+    Data,
+}
+
+/// Order of `--sort`, a field with `-` in front for descending
+#[derive(Clone, Copy)]
+pub struct SortKey {
+    field: SortField,
+    descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = ();
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let (descending, field) = match key.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, key),
+        };
+        let field = match field.to_lowercase().as_str() {
+            "type" => SortField::Type,
+            "name" => SortField::Name,
+            "ttl" => SortField::Ttl,
+            "data" => SortField::Data,
+            _ => return Err(()),
+        };
+        Ok(Self {
+            field,
+            descending,
+        })
+    }
+}
+
+/// Whether two records hold the same data, whatever their TTLs, which count down in caches
+pub fn same_record(
+    a: &Record,
+    b: &Record,
+) -> bool {
+    a.name() == b.name()
+        && a.record_type() == b.record_type()
+        && a.dns_class() == b.dns_class()
+        && a.data() == b.data()
+}
+
+/// Drop the records of `records` that are the same as one before, keeping the one with the
+/// lowest TTL in the place of the first
+pub fn dedup_records(records: &mut Vec<&Record>) {
+    let mut kept: Vec<&Record> = Vec::with_capacity(records.len());
+    for record in records.drain(..) {
+        match kept.iter_mut().find(|other| same_record(other, record)) {
+            Some(other) if record.ttl() < other.ttl() => *other = record,
+            Some(_) => {},
+            None => kept.push(record),
         }
     }
+    *records = kept;
+}
+
+/// Sort `records` by `key`, records that compare equal keeping the order they came in, and
+/// all of them without a key
+pub fn sort_records(
+    records: &mut [&Record],
+    key: Option<SortKey>,
+) {
+    let Some(key) = key else {
+        return;
+    };
+    records.sort_by(|a, b| {
+        let order = match key.field {
+            SortField::Type => u16::from(a.record_type()).cmp(&u16::from(b.record_type())),
+            SortField::Name => a.name().cmp(b.name()),
+            SortField::Ttl => a.ttl().cmp(&b.ttl()),
+            SortField::Data => compare_data(a, b),
+        };
+        match key.descending {
+            true => order.reverse(),
+            false => order,
+        }
+    });
 }
+
+/// Addresses by their value, MX by preference, SRV by priority and weight, other data and
+/// data of different types by type and text
+fn compare_data(
+    a: &Record,
+    b: &Record,
+) -> cmp::Ordering {
+    match (a.data(), b.data()) {
+        (Some(RData::A(x)), Some(RData::A(y))) => x.0.cmp(&y.0),
+        (Some(RData::AAAA(x)), Some(RData::AAAA(y))) => x.0.cmp(&y.0),
+        (Some(RData::MX(x)), Some(RData::MX(y))) => x
+            .preference()
+            .cmp(&y.preference())
+            .then_with(|| x.exchange().cmp(y.exchange())),
+        (Some(RData::SRV(x)), Some(RData::SRV(y))) => (x.priority(), x.weight(), x.port())
+            .cmp(&(y.priority(), y.weight(), y.port()))
+            .then_with(|| x.target().cmp(y.target())),
+        (x, y) => u16::from(a.record_type())
+            .cmp(&u16::from(b.record_type()))
+            .then_with(|| {
+                let text = |data: Option<&RData>| data.map(data_text);
+                text(x).cmp(&text(y))
+            }),
+    }
+}
+
+/// Section of a response that a record came in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Answer,
+    Authority,
+    Additional,
+}
+
+impl Section {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Answer => "answer",
+            Self::Authority => "authority",
+            Self::Additional => "additional",
+        }
+    }
+}
+
+/// Records of the authority and additional sections of `response`, tagged with their section,
+/// without the OPT pseudo-record
+pub fn other_sections(response: &Message) -> Vec<(&Record, Section)> {
+    let authority = response
+        .name_servers()
+        .iter()
+        .map(|record| (record, Section::Authority));
+    let additional = response
+        .additionals()
+        .iter()
+        .filter(|record| record.record_type() != RecordType::OPT)
+        .map(|record| (record, Section::Additional));
+    authority.chain(additional).collect()
+}
+
+/// A record of `--output json`
+#[derive(Serialize)]
+struct JsonRecord {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    class: String,
+    /// always in seconds
+    ttl: u32,
+    data: Value,
+    section: &'static str,
+}
+
+/// Fields of the record data where they are worth taking apart, its text otherwise
+fn json_data(data: &RData) -> Value {
+    match data {
+        RData::A(address) => json!(address.to_string()),
+        RData::AAAA(address) => json!(address.to_string()),
+        RData::CNAME(name) => json!(name.to_string()),
+        RData::NS(name) => json!(name.to_string()),
+        RData::PTR(name) => json!(name.to_string()),
+        RData::MX(mx) => json!({
+            "preference": mx.preference(),
+            "exchange": mx.exchange().to_string(),
+        }),
+        RData::SRV(srv) => json!({
+            "priority": srv.priority(),
+            "weight": srv.weight(),
+            "port": srv.port(),
+            "target": srv.target().to_string(),
+        }),
+        RData::SOA(soa) => json!({
+            "mname": soa.mname().to_string(),
+            "rname": soa.rname().to_string(),
+            "serial": soa.serial(),
+            "refresh": soa.refresh(),
+            "retry": soa.retry(),
+            "expire": soa.expire(),
+            "minimum": soa.minimum(),
+        }),
+        // Every string of the record, as splitting them up may matter, e.g. for DKIM
+        RData::TXT(txt) => json!(txt
+            .txt_data()
+            .iter()
+            .map(|data| String::from_utf8_lossy(data).into_owned())
+            .collect::<Vec<String>>()),
+        RData::CAA(caa) => json!({
+            "issuer_critical": caa.issuer_critical(),
+            "tag": caa.tag().as_str(),
+            "value": display_text(caa.value()),
+        }),
+        data => json!(data_text(data)),
+    }
+}
+
+/// `records` as a JSON array, one object per record
+pub fn json_records(records: &[&Record]) -> Value {
+    let records: Vec<(&Record, Section)> = records
+        .iter()
+        .map(|record| (*record, Section::Answer))
+        .collect();
+    json_sections(&records)
+}
+
+/// Like `json_records`, for records of any section
+pub fn json_sections(records: &[(&Record, Section)]) -> Value {
+    let records: Vec<JsonRecord> = records
+        .iter()
+        .map(|(record, section)| JsonRecord {
+            name: record.name().to_string(),
+            record_type: record.record_type().to_string(),
+            class: record.dns_class().to_string(),
+            ttl: record.ttl(),
+            data: record.data().map(json_data).unwrap_or(Value::Null),
+            section: section.as_str(),
+        })
+        .collect();
+    serde_json::to_value(records).unwrap_or_default()
+}
+
+/// The header bits set in `message`, like the flags line of dig
+pub fn format_flags(message: &Message) -> String {
+    let flags = [
+        ("qr", message.message_type() == MessageType::Response),
+        ("aa", message.authoritative()),
+        ("tc", message.truncated()),
+        ("rd", message.recursion_desired()),
+        ("ra", message.recursion_available()),
+        ("ad", message.authentic_data()),
+        ("cd", message.checking_disabled()),
+    ];
+    let set: Vec<&str> = flags
+        .iter()
+        .filter(|(_, set)| *set)
+        .map(|(flag, _)| *flag)
+        .collect();
+    format!("; flags: {}", set.join(" "))
+}
+
+/// Summary of the OPT pseudo-record of a response, e.g. `; EDNS: version 0, udp 1232, do`
+pub fn format_edns(edns: &Edns) -> String {
+    let mut summary = format!(
+        "; EDNS: version {}, udp {}",
+        edns.version(),
+        edns.max_payload()
+    );
+    if edns.dnssec_ok() {
+        summary.push_str(", do");
+    }
+    if let Some(nsid) = nsid(edns) {
+        let _ = write!(summary, ", nsid {}", nsid);
+    }
+    summary
+}
+
+/// NSID the server sent with `message`, as text when it is printable and in hex otherwise
+pub fn format_nsid(message: &Message) -> Option<String> {
+    nsid(message.extensions().as_ref()?)
+}
+
+fn nsid(edns: &Edns) -> Option<String> {
+    let Some(EdnsOption::Unknown(_, nsid)) = edns.option(EdnsCode::NSID) else {
+        return None;
+    };
+    if nsid.is_empty() {
+        return None;
+    }
+    match std::str::from_utf8(nsid) {
+        Ok(text) if !text.chars().any(char::is_control) => Some(text.to_owned()),
+        _ => Some(nsid.iter().map(|byte| format!("{:02x}", byte)).collect()),
+    }
+}
+
+/// Humanize a TTL, e.g. `3735` becomes `1h02m15s`
+pub fn format_ttl(seconds: u32) -> String {
+    let mut formatted = String::new();
+    write_ttl(&mut formatted, seconds);
+    formatted
+}
+
+/// RFC 3339 timestamp in UTC with millisecond precision
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (hour, minute, second) = (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Append the humanized `seconds` to `out`, see `format_ttl`
+fn write_ttl(
+    out: &mut String,
+    seconds: u32,
+) {
+    // Writing into a String cannot fail
+    let _ = if seconds < 60 {
+        write!(out, "{}s", seconds)
+    } else if seconds < 60 * 60 {
+        write!(out, "{}m{:02}s", seconds / 60, seconds % 60)
+    } else if seconds < 60 * 60 * 24 {
+        write!(
+            out,
+            "{}h{:02}m{:02}s",
+            seconds / 3600,
+            (seconds % 3600) / 60,
+            seconds % 60
+        )
+    } else {
+        write!(
+            out,
+            "{}d{}h{:02}m{:02}s",
+            seconds / 86400,
+            (seconds % 86400) / 3600,
+            (seconds % 3600) / 60,
+            seconds % 60
+        )
+    };
+}
+
+/// Pad what was appended to `out` since `start` on the left to `width` characters
+fn pad_left(
+    out: &mut String,
+    start: usize,
+    width: usize,
+) {
+    let written = out[start..].chars().count();
+    if written < width {
+        out.insert_str(start, &" ".repeat(width - written));
+    }
+}
+
+/// Append the spaces that pad `written` characters on the right to `width` characters
+fn pad_right(
+    out: &mut String,
+    written: usize,
+    width: usize,
+) {
+    out.push_str(&" ".repeat(width.saturating_sub(written)));
+}
+
+/// Append `name` to `out`, in its xn-- form with `ascii_names`
+fn write_name(
+    out: &mut String,
+    name: &Name,
+    ascii_names: bool,
+) {
+    match ascii_names {
+        true => out.push_str(&name.to_ascii()),
+        false => {
+            let _ = write!(out, "{}", name);
+        },
+    }
+}
+
+/// The data with the names in it in their xn-- form
+fn ascii_payload(data: &RData) -> String {
+    match data {
+        RData::CNAME(name) => name.0.to_ascii(),
+        RData::NS(name) => name.0.to_ascii(),
+        RData::PTR(name) => name.0.to_ascii(),
+        RData::MX(mx) => format!("{} {}", mx.preference(), mx.exchange().to_ascii()),
+        RData::SRV(srv) => format!(
+            "{} {} {} {}",
+            srv.priority(),
+            srv.weight(),
+            srv.port(),
+            srv.target().to_ascii()
+        ),
+        RData::SOA(soa) => format!(
+            "{} {} {} {} {} {} {}",
+            soa.mname().to_ascii(),
+            soa.rname().to_ascii(),
+            soa.serial(),
+            soa.refresh(),
+            soa.retry(),
+            soa.expire(),
+            soa.minimum()
+        ),
+        _ => data_text(data),
+    }
+}
+
+/// Append the strings of `txt` to `out` like dig, each of them quoted, or all of them in a
+/// single quoted string with `concat`.
+///
+/// Quotes and backslashes are escaped with a backslash, the bytes other than printable ASCII
+/// as `\DDD` in decimal, so that the data can be pasted into a zone file whatever it holds.
+fn write_txt(
+    out: &mut String,
+    txt: &TXT,
+    concat: bool,
+) {
+    let strings = txt.txt_data();
+    if concat {
+        out.push('"');
+    }
+    for (index, string) in strings.iter().enumerate() {
+        if !concat {
+            if index > 0 {
+                out.push(' ');
+            }
+            out.push('"');
+        }
+        for &byte in string.iter() {
+            match byte {
+                b'"' | b'\\' => {
+                    out.push('\\');
+                    out.push(char::from(byte));
+                },
+                b' '..=b'~' => out.push(char::from(byte)),
+                _ => {
+                    let _ = write!(out, "\\{:03}", byte);
+                },
+            }
+        }
+        if !concat {
+            out.push('"');
+        }
+    }
+    if concat {
+        out.push('"');
+    }
+}
+
+/// Text of `data` as in zone files, which stays empty rather than panicking for the data that
+/// hickory fails to write out, like OPT records parsed outside of the additional section
+pub fn data_text(data: &RData) -> String {
+    display_text(data)
+}
+
+/// Text of `value`, whatever of it was written before its formatting failed
+pub fn display_text(value: &dyn Display) -> String {
+    let mut text = String::new();
+    let _ = write!(text, "{}", value);
+    text
+}
+
+/// Append the data of `record` to `out`, cut to `MAX_PAYLOAD_LENGTH` bytes, as record data
+/// comes from untrusted servers
+fn write_payload(
+    out: &mut String,
+    record: &Record,
+    ascii_names: bool,
+    txt_concat: bool,
+) {
+    let Some(data) = record.data() else {
+        return;
+    };
+    let start = out.len();
+    match (data, ascii_names) {
+        (RData::TXT(txt), _) => write_txt(out, txt, txt_concat),
+        (data, true) => out.push_str(&ascii_payload(data)),
+        (data, false) => {
+            let _ = write!(out, "{}", data);
+        },
+    }
+    let length = out.len() - start;
+    if length <= MAX_PAYLOAD_LENGTH {
+        return;
+    }
+    let mut end = start + MAX_PAYLOAD_LENGTH;
+    while !out.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = out.len() - end;
+    out.truncate(end);
+    let _ = write!(out, "... ({} more bytes)", cut);
+}
+
+/// Append the data of `record` to `out` like `write_payload`, with the addresses and the
+/// numbers of MX and SRV records in colors of their own
+fn write_colored_payload(
+    out: &mut String,
+    record: &Record,
+    ascii_names: bool,
+    txt_concat: bool,
+) {
+    match record.data() {
+        Some(RData::A(address)) => {
+            let _ = write!(out, "{}{}", foreground(ADDRESS_COLOR), address);
+        },
+        Some(RData::AAAA(address)) => {
+            let _ = write!(out, "{}{}", foreground(ADDRESS_COLOR), address);
+        },
+        Some(RData::MX(mx)) => {
+            let _ = write!(
+                out,
+                "{}{}{} ",
+                foreground(NUMBER_COLOR),
+                mx.preference(),
+                DEFAULT_FOREGROUND
+            );
+            write_name(out, mx.exchange(), ascii_names);
+            return;
+        },
+        Some(RData::SRV(srv)) => {
+            let _ = write!(
+                out,
+                "{}{} {} {}{} ",
+                foreground(NUMBER_COLOR),
+                srv.priority(),
+                srv.weight(),
+                srv.port(),
+                DEFAULT_FOREGROUND
+            );
+            write_name(out, srv.target(), ascii_names);
+            return;
+        },
+        _ => return write_payload(out, record, ascii_names, txt_concat),
+    }
+    out.push_str(DEFAULT_FOREGROUND);
+}
+
+/// The data of `records` one per line, like `dig +short`, records without data having no line
+pub fn format_short(
+    records: &[&Record],
+    ascii_names: bool,
+    txt_concat: bool,
+) -> Vec<String> {
+    records
+        .iter()
+        .filter(|record| record.data().is_some())
+        .map(|record| {
+            let mut line = String::new();
+            write_payload(&mut line, record, ascii_names, txt_concat);
+            line
+        })
+        .collect()
+}
+
+/// Columns of `--output csv` and `--output tsv`, in order
+const TABLE_COLUMNS: [&str; 6] = ["name", "type", "class", "ttl", "data", "section"];
+
+/// Fields of `record` as columns of a table, the TTL in seconds and the data whole, the
+/// strings of TXT records quoted as in the text output
+pub fn record_fields(
+    record: &Record,
+    txt_concat: bool,
+) -> [String; 5] {
+    let data = match record.data() {
+        Some(RData::TXT(txt)) => {
+            let mut data = String::new();
+            write_txt(&mut data, txt, txt_concat);
+            data
+        },
+        data => data.map(data_text).unwrap_or_default(),
+    };
+    [
+        record.name().to_string(),
+        record.record_type().to_string(),
+        record.dns_class().to_string(),
+        record.ttl().to_string(),
+        data,
+    ]
+}
+
+/// Escape a field the way ClickHouse and BigQuery read TSV
+pub fn escape_tsv(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quote a field that holds a comma, a quote or a line break, doubling its quotes, as RFC 4180
+/// has it
+pub fn escape_csv(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+/// Formats records as the rows of a CSV or TSV table, one per record under a header
+#[derive(Clone, Copy)]
+pub enum TableFormatter {
+    Csv,
+    Tsv,
+}
+
+impl TableFormatter {
+    /// Rows of `records`, after the header unless `config` printed it already, so that the
+    /// records of several names make one table
+    pub fn format(
+        self,
+        records: &[&Record],
+        config: &OutputConfig,
+    ) -> Vec<String> {
+        let records: Vec<(&Record, Section)> = records
+            .iter()
+            .map(|record| (*record, Section::Answer))
+            .collect();
+        self.format_sections(&records, config)
+    }
+
+    /// Like `format`, for records of any section
+    pub fn format_sections(
+        self,
+        records: &[(&Record, Section)],
+        config: &OutputConfig,
+    ) -> Vec<String> {
+        let mut rows = Vec::with_capacity(records.len() + 1);
+        if config.starts_table() {
+            rows.push(self.row(&TABLE_COLUMNS.map(String::from)));
+        }
+        for (record, section) in records {
+            let [name, record_type, class, ttl, data] = record_fields(record, config.txt_concat);
+            let fields = [
+                name,
+                record_type,
+                class,
+                ttl,
+                data,
+                section.as_str().to_owned(),
+            ];
+            rows.push(self.row(&fields));
+        }
+        rows
+    }
+
+    fn row(
+        self,
+        fields: &[String],
+    ) -> String {
+        let (escape, delimiter): (fn(&str) -> String, &str) = match self {
+            Self::Csv => (escape_csv, ","),
+            Self::Tsv => (escape_tsv, "\t"),
+        };
+        let fields: Vec<String> = fields.iter().map(|field| escape(field)).collect();
+        fields.join(delimiter)
+    }
+}
+
+/// Part of a `--format` template
+enum Piece {
+    Text(String),
+    Name,
+    Type,
+    Class,
+    Ttl,
+    TtlHuman,
+    Data,
+    Server,
+    Color,
+    Reset,
+}
+
+/// Placeholders of templates
+const PLACEHOLDERS: [&str; 9] = [
+    "name",
+    "type",
+    "class",
+    "ttl",
+    "ttl_human",
+    "data",
+    "server",
+    "color",
+    "reset",
+];
+
+fn placeholder(name: &str) -> Option<Piece> {
+    Some(match name {
+        "name" => Piece::Name,
+        "type" => Piece::Type,
+        "class" => Piece::Class,
+        "ttl" => Piece::Ttl,
+        "ttl_human" => Piece::TtlHuman,
+        "data" => Piece::Data,
+        "server" => Piece::Server,
+        "color" => Piece::Color,
+        "reset" => Piece::Reset,
+        _ => return None,
+    })
+}
+
+/// Layout of the line of every record given with `--format`, compiled once
+pub struct Template {
+    pieces: Vec<Piece>,
+    server: String,
+}
+
+impl Template {
+    /// Compile `template`, in which `{{` and `}}` stand for braces and `\t`, `\n` and `\\`
+    /// for a tab, a line break and a backslash, with `server` for its `{server}`
+    pub fn parse(
+        template: &str,
+        server: &str,
+    ) -> Result<Self, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err(String::from("unclosed {, write {{ for a brace"));
+                    };
+                    let name = &rest[..end];
+                    let piece = placeholder(name).ok_or_else(|| {
+                        format!(
+                            "unknown placeholder {{{}}}, use {}",
+                            name,
+                            PLACEHOLDERS.join(", ")
+                        )
+                    })?;
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(piece);
+                    chars = rest[end + 1..].chars();
+                },
+                '}' => return Err(String::from("unmatched }, write }} for a brace")),
+                '\\' if chars.as_str().starts_with(['t', 'n', '\\']) => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    _ => text.push('\\'),
+                },
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Self {
+            pieces,
+            server: server.to_owned(),
+        })
+    }
+
+    /// Append the line of `record` to `out`, with escape sequences for `{color}` and
+    /// `{reset}` only when `ansi`, names in their xn-- form with `ascii_names` and the strings
+    /// of TXT records joined with `txt_concat`
+    fn render(
+        &self,
+        out: &mut String,
+        record: &Record,
+        ansi: bool,
+        ascii_names: bool,
+        txt_concat: bool,
+    ) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Name => write_name(out, record.name(), ascii_names),
+                Piece::Type => {
+                    let _ = write!(out, "{}", record.record_type());
+                },
+                Piece::Class => {
+                    let _ = write!(out, "{}", record.dns_class());
+                },
+                Piece::Ttl => {
+                    let _ = write!(out, "{}", record.ttl());
+                },
+                Piece::TtlHuman => write_ttl(out, record.ttl()),
+                Piece::Data => write_payload(out, record, ascii_names, txt_concat),
+                Piece::Server => out.push_str(&self.server),
+                Piece::Color if ansi => {
+                    out.push_str(&foreground(palette_entry(record.record_type()).0))
+                },
+                Piece::Reset if ansi => out.push_str(RESET),
+                Piece::Color | Piece::Reset => {},
+            }
+        }
+    }
+}
+
+/// Widths of the columns of the lines of a set of records, so that the lines line up
+#[derive(Clone, Copy)]
+pub struct ColumnWidths {
+    record_type: usize,
+    name: usize,
+    ttl: usize,
+}
+
+impl Default for ColumnWidths {
+    /// The widths of a single record, where the names are not padded
+    fn default() -> Self {
+        Self {
+            record_type: 5,
+            name: 0,
+            ttl: 12,
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Widths of the widest type, name and TTL of `records`, as written by `RecordFormatter`,
+    /// and at least those of a single record
+    pub fn column_widths(
+        &self,
+        records: &[&Record],
+    ) -> ColumnWidths {
+        let mut widths = ColumnWidths::default();
+        let mut cell = String::new();
+        for record in records {
+            let record_type: &str = record.record_type().into();
+            widths.record_type = widths.record_type.max(record_type.len());
+            cell.clear();
+            write_name(&mut cell, record.name(), self.ascii_names);
+            widths.name = widths.name.max(cell.chars().count());
+            cell.clear();
+            self.write_record_ttl(&mut cell, record.ttl());
+            widths.ttl = widths.ttl.max(cell.len());
+        }
+        widths
+    }
+
+    fn write_record_ttl(
+        &self,
+        out: &mut String,
+        ttl: u32,
+    ) {
+        match (self.ttl_units, self.format_seconds) {
+            (true, _) => out.push_str(&crate::ttl::format_units(ttl)),
+            (false, true) => write_ttl(out, ttl),
+            (false, false) => {
+                let _ = write!(out, "{}", ttl);
+            },
+        }
+    }
+}
+
+/// Formats one record per line, writing into a buffer that can be reused across records
 pub struct RecordFormatter<'a> {
-    result: Record,
+    record: &'a Record,
     config: &'a OutputConfig,
+    widths: ColumnWidths,
 }
 
 impl<'a> RecordFormatter<'a> {
     pub fn new(
-        result: Record,
+        record: &'a Record,
         config: &'a OutputConfig,
     ) -> Self {
         Self {
-            result,
+            record,
             config,
+            widths: ColumnWidths::default(),
         }
     }
 
-    #[cfg(not(windows))]
+    /// Pad the columns to `widths`, those of all the records printed with this one
+    pub fn with_widths(
+        mut self,
+        widths: ColumnWidths,
+    ) -> Self {
+        self.widths = widths;
+        self
+    }
+
     pub fn format(&self) -> String {
+        let mut line = String::new();
+        self.write(&mut line);
+        line
+    }
+
+    /// Append the line of the record to `out`
+    pub fn write(
+        &self,
+        out: &mut String,
+    ) {
+        if let Some(template) = &self.config.template {
+            return template.render(
+                out,
+                self.record,
+                self.config.ansi,
+                self.config.ascii_names,
+                self.config.txt_concat,
+            );
+        }
         if !self.config.ansi {
-            format!(
-                "{:>5} {} {:>12} {}",
-                self.rormat_record_type(),
-                self.format_name(),
-                self.format_duration(),
-                self.format_payload(),
-            )
-        } else {
-            let color = Fg(self.get_record_color());
-            let reset_color = Fg(Reset);
+            return self.write_plain(out);
+        }
+        let record_type: &str = self.record.record_type().into();
+        let _ = write!(
+            out,
+            "{}{:>width$}{} {}",
+            foreground(palette_entry(self.record.record_type()).0),
+            record_type,
+            DEFAULT_FOREGROUND,
+            foreground(NAME_COLOR),
+            width = self.widths.record_type,
+        );
+        let start = out.len();
+        write_name(out, self.record.name(), self.config.ascii_names);
+        let written = out[start..].chars().count();
+        out.push_str(DEFAULT_FOREGROUND);
+        pad_right(out, written, self.widths.name);
+        out.push(' ');
+        self.write_rest(out);
+    }
 
-            let name_color = Fg(AnsiValue(75));
+    fn write_plain(
+        &self,
+        out: &mut String,
+    ) {
+        let record_type: &str = self.record.record_type().into();
+        let _ = write!(
+            out,
+            "{:>width$} ",
+            record_type,
+            width = self.widths.record_type
+        );
+        let start = out.len();
+        write_name(out, self.record.name(), self.config.ascii_names);
+        let written = out[start..].chars().count();
+        pad_right(out, written, self.widths.name);
+        out.push(' ');
+        self.write_rest(out);
+    }
 
-            format!(
-                "{color}{:>5}{reset_color} {name_color}{}{reset_color} {:>12} {}",
-                self.rormat_record_type(),
-                self.format_name(),
-                self.format_duration(),
-                self.format_payload(),
-            )
+    /// The TTL, the class and the data, of which only the addresses and numbers are colored
+    fn write_rest(
+        &self,
+        out: &mut String,
+    ) {
+        let start = out.len();
+        self.config.write_record_ttl(out, self.record.ttl());
+        pad_left(out, start, self.widths.ttl);
+        out.push(' ');
+        // Only classes other than IN are shown, nearly every record is IN
+        if self.record.dns_class() != DNSClass::IN {
+            let _ = write!(out, "{} ", self.record.dns_class());
+        }
+        let (ascii_names, txt_concat) = (self.config.ascii_names, self.config.txt_concat);
+        match self.config.ansi {
+            true => write_colored_payload(out, self.record, ascii_names, txt_concat),
+            false => write_payload(out, self.record, ascii_names, txt_concat),
         }
     }
+}
 
-    #[cfg(windows)]
-    pub fn format(&self) -> String {
-        format!(
-            "{:>5} {} {:>12} {}",
-            self.rormat_record_type(),
-            self.format_name(),
-            self.format_duration(),
-            self.format_payload(),
-        )
+#[cfg(test)]
+mod tests {
+    use hickory_client::serialize::txt::RDataParser;
+
+    use super::*;
+
+    fn record(
+        name: &str,
+        record_type: RecordType,
+        ttl: u32,
+        data: &str,
+    ) -> Record {
+        let data = RData::try_from_str(record_type, data).unwrap();
+        Record::from_rdata(Name::from_str(name).unwrap(), ttl, data)
     }
 
-    fn rormat_record_type(&self) -> String {
-        self.result.record_type().to_string()
+    #[test]
+    fn empty_groups_take_the_place_of_their_name_or_type() {
+        let records = [
+            record("www.example.com.", RecordType::CNAME, 300, "example.com."),
+            record("example.com.", RecordType::A, 300, "192.0.2.1"),
+        ];
+        let records: Vec<&Record> = records.iter().collect();
+        let queried = [RecordType::A, RecordType::MX, RecordType::CNAME];
+        let headers = |key: GroupKey, name: &str| -> Vec<(String, usize)> {
+            let name = Name::from_str(name).unwrap();
+            let groups = group_records(records.clone(), key);
+            with_empty_groups(groups, key, &name, &queried)
+                .into_iter()
+                .map(|(header, members)| (header, members.len()))
+                .collect()
+        };
+
+        let types = headers(GroupKey::Type, "www.example.com");
+        assert_eq!(
+            types,
+            [("A".into(), 1), ("CNAME".into(), 1), ("MX".into(), 0)]
+        );
+        let names = headers(GroupKey::Name, "www.example.com");
+        assert_eq!(
+            names,
+            [("www.example.com.".into(), 1), ("example.com.".into(), 1)]
+        );
+        let names = headers(GroupKey::Name, "example.net");
+        assert_eq!(names[0], ("example.net.".into(), 0));
     }
 
-    fn format_name(&self) -> String {
-        self.result.name().to_string()
+    fn lines(
+        config: &OutputConfig,
+        records: &[Record],
+    ) -> Vec<String> {
+        let records: Vec<&Record> = records.iter().collect();
+        let widths = config.column_widths(&records);
+        records
+            .iter()
+            .map(|record| {
+                RecordFormatter::new(record, config)
+                    .with_widths(widths)
+                    .format()
+            })
+            .collect()
     }
 
-    fn format_payload(&self) -> String {
-        if let Some(result_data) = self.result.data() {
-            result_data.to_string()
-        } else {
-            String::default()
+    /// `text` without its escape sequences
+    fn strip_ansi(text: &str) -> String {
+        let mut out = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    // Every sequence of the formatter is a CSI one ending in a letter
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                },
+                c => out.push(c),
+            }
         }
+        out
     }
 
-    fn format_duration(&self) -> String {
-        let seconds = self.result.ttl();
+    #[test]
+    fn colored_records_only_add_escape_sequences() {
+        let records = [
+            record("example.com.", RecordType::A, 300, "192.0.2.1"),
+            record("example.com.", RecordType::MX, 3600, "10 mail.example.com."),
+            record("example.com.", RecordType::TXT, 60, "\"v=spf1 -all\""),
+        ];
+        for seconds in [false, true] {
+            let plain = lines(&OutputConfig::new(!seconds, false), &records);
+            let colored = lines(&OutputConfig::new(!seconds, true), &records);
+            for (plain, colored) in plain.iter().zip(&colored) {
+                assert!(!plain.contains('\x1b'), "{:?}", plain);
+                assert!(colored.contains('\x1b'), "{:?}", colored);
+                assert_eq!(&strip_ansi(colored), plain);
+            }
+        }
+    }
 
-        if !self.config.format_seconds {
-            return format!("{}", seconds);
+    #[test]
+    fn ttl_units_print_the_ttls_as_the_inputs_read_them() {
+        let records = [
+            record("example.com.", RecordType::A, 7200, "192.0.2.1"),
+            record("example.com.", RecordType::A, 5400, "192.0.2.2"),
+            record("example.com.", RecordType::A, 45, "192.0.2.3"),
+        ];
+        for format_seconds in [false, true] {
+            let config = OutputConfig::new(format_seconds, false).with_ttl_units(true);
+            let ttls: Vec<String> = lines(&config, &records)
+                .iter()
+                .map(|line| line.split_whitespace().nth(2).unwrap().to_owned())
+                .collect();
+            assert_eq!(ttls, ["2h", "90m", "45s"]);
         }
+    }
 
-        if seconds < 60 {
-            format!("{}s", seconds)
-        } else if seconds < 60 * 60 {
-            format!("{}m{:02}s", seconds / 60, seconds % 60)
-        } else if seconds < 60 * 60 * 24 {
-            format!(
-                "{}h{:02}m{:02}s",
-                seconds / 3600,
-                (seconds % 3600) / 60,
-                seconds % 60
-            )
-        } else {
+    #[test]
+    fn internationalized_names_are_printed_in_unicode_or_their_xn_form() {
+        let records = [
+            record(
+                "xn--mnchen-3ya.de.",
+                RecordType::CNAME,
+                30,
+                "xn--r8jz45g.xn--zckzah.",
+            ),
+            record(
+                "xn--mnchen-3ya.de.",
+                RecordType::MX,
+                30,
+                "10 xn--53h.example.",
+            ),
+        ];
+        let unicode = lines(&OutputConfig::new(true, false), &records);
+        assert!(unicode[0].contains("münchen.de."), "{:?}", unicode);
+        assert!(unicode[0].ends_with(" 例え.テスト."), "{:?}", unicode);
+        assert!(unicode[1].ends_with(" 10 ☕.example."), "{:?}", unicode);
+        let ascii = lines(
+            &OutputConfig::new(true, false).with_ascii_names(true),
+            &records,
+        );
+        assert!(ascii[0].contains("xn--mnchen-3ya.de."), "{:?}", ascii);
+        assert!(
+            ascii[0].ends_with(" xn--r8jz45g.xn--zckzah."),
+            "{:?}",
+            ascii
+        );
+        assert!(ascii[1].ends_with(" 10 xn--53h.example."), "{:?}", ascii);
+    }
+
+    #[test]
+    fn single_record_keeps_the_fixed_layout() {
+        let config = OutputConfig::new(true, false);
+        let records = [record("example.com.", RecordType::A, 30, "192.0.2.1")];
+        assert_eq!(
+            lines(&config, &records),
+            ["    A example.com.          30s 192.0.2.1"]
+        );
+        assert_eq!(
+            lines(&config, &records),
+            [RecordFormatter::new(&records[0], &config).format()]
+        );
+    }
+
+    #[test]
+    fn columns_line_up_over_the_records() {
+        let config = OutputConfig::new(true, false);
+        let records = [
+            record("example.com.", RecordType::A, 2315, "93.184.215.14"),
+            record(
+                "www.example.com.",
+                RecordType::NS,
+                80411,
+                "a.iana-servers.net.",
+            ),
+            record("example.com.", RecordType::OPENPGPKEY, 1, "AAAA"),
+        ];
+        assert_eq!(
+            lines(&config, &records),
+            [
+                "         A example.com.           38m35s 93.184.215.14",
+                "        NS www.example.com.    22h20m11s a.iana-servers.net.",
+                "OPENPGPKEY example.com.               1s AAAA",
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_ttls_widen_their_column() {
+        let config = OutputConfig::new(true, false);
+        let records = [
+            record("example.com.", RecordType::A, u32::MAX, "192.0.2.1"),
+            record("example.com.", RecordType::A, 1, "192.0.2.2"),
+        ];
+        let lines = lines(&config, &records);
+        let data = |line: &str| line.find("192.0.2.").unwrap();
+        assert_eq!(data(&lines[0]), data(&lines[1]));
+        assert!(lines[0].len() > "    A example.com.             s 192.0.2.1".len());
+    }
+
+    #[test]
+    fn seconds_are_aligned_to_the_right() {
+        let config = OutputConfig::new(false, false);
+        let records = [
+            record("example.com.", RecordType::A, 5, "192.0.2.1"),
+            record("example.com.", RecordType::AAAA, 86400, "2001:db8::1"),
+        ];
+        assert_eq!(
+            lines(&config, &records),
+            [
+                "    A example.com.            5 192.0.2.1",
+                " AAAA example.com.        86400 2001:db8::1",
+            ]
+        );
+    }
+
+    fn txt_record(strings: &[&[u8]]) -> Record {
+        let txt = TXT::from_bytes(strings.to_vec());
+        Record::from_rdata(
+            Name::from_str("example.com.").unwrap(),
+            300,
+            RData::TXT(txt),
+        )
+    }
+
+    #[test]
+    fn table_fields_quote_every_string_of_txt_records() {
+        let record = txt_record(&[b"v=spf1 include:_spf.example.com", b"~all"]);
+        assert_eq!(
+            record_fields(&record, false)[4],
+            r#""v=spf1 include:_spf.example.com" "~all""#
+        );
+        assert_eq!(
+            record_fields(&record, true)[4],
+            r#""v=spf1 include:_spf.example.com~all""#
+        );
+    }
+
+    #[test]
+    fn table_fields_escape_quotes_and_raw_bytes_of_txt_records() {
+        let record = txt_record(&[br#"say "hi"\"#, b"\x00\xff\n"]);
+        let data = &record_fields(&record, false)[4];
+        assert_eq!(data, r#""say \"hi\"\\" "\000\255\010""#);
+        assert_eq!(
+            TableFormatter::Csv.row(&record_fields(&record, false)),
+            r#"example.com.,TXT,IN,300,"""say \""hi\""\\"" ""\000\255\010""""#
+        );
+        assert_eq!(
+            TableFormatter::Tsv.row(&record_fields(&record, false)),
             format!(
-                "{}d{}h{:02}m{:02}s",
-                seconds / 86400,
-                (seconds % 86400) / 3600,
-                (seconds % 3600) / 60,
-                seconds % 60
+                "example.com.\tTXT\tIN\t300\t{}",
+                r#""say \\"hi\\"\\\\" "\\000\\255\\010""#
             )
-        }
+        );
     }
 
-    #[cfg(not(windows))]
-    fn get_record_color(&self) -> AnsiValue {
-        use RecordType::*;
-        match self.result.record_type() {
-            A => AnsiValue(1),           // Red
-            AAAA => AnsiValue(2),        // Green
-            ANAME => AnsiValue(3),       // Yellow
-            ANY => AnsiValue(4),         // Blue
-            AXFR => AnsiValue(5),        // Magenta
-            CAA => AnsiValue(6),         // Cyan
-            CDS => AnsiValue(7),         // Light gray
-            CDNSKEY => AnsiValue(8),     // Dark gray
-            CSYNC => AnsiValue(9),       // Light red
-            DNSKEY => AnsiValue(10),     // Light green
-            DS => AnsiValue(11),         // Light yellow
-            HINFO => AnsiValue(12),      // Light blue
-            HTTPS => AnsiValue(13),      // Light magenta
-            IXFR => AnsiValue(14),       // Light cyan
-            KEY => AnsiValue(15),        // White
-            MX => AnsiValue(38),         // Bright red
-            NAPTR => AnsiValue(17),      // Bright green
-            NS => AnsiValue(18),         // Bright yellow
-            NSEC => AnsiValue(19),       // Bright blue
-            NSEC3 => AnsiValue(20),      // Bright magenta
-            NSEC3PARAM => AnsiValue(21), // Bright cyan
-            NULL => AnsiValue(22),       // Bright white
-            OPENPGPKEY => AnsiValue(23), // Default color
-            OPT => AnsiValue(24),        // Default color
-            PTR => AnsiValue(25),        // Default color
-            RRSIG => AnsiValue(26),      // Default color
-            SIG => AnsiValue(27),        // Default color
-            SOA => AnsiValue(28),        // Default color
-            SRV => AnsiValue(29),        // Default color
-            SSHFP => AnsiValue(30),      // Default color
-            SVCB => AnsiValue(31),       // Default color
-            TLSA => AnsiValue(32),       // Default color
-            TSIG => AnsiValue(33),       // Default color
-            TXT => AnsiValue(34),        // Default color
-            Unknown(_) => AnsiValue(35), // Default color
-            ZERO => AnsiValue(36),       // Default color
-            _ => AnsiValue(37),
-        }
+    #[test]
+    fn csv_fields_with_commas_quotes_and_line_breaks_are_quoted() {
+        assert_eq!(escape_csv("example.com."), "example.com.");
+        assert_eq!(escape_csv("a,b"), r#""a,b""#);
+        assert_eq!(escape_csv(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(escape_csv("two\nlines"), "\"two\nlines\"");
+        assert_eq!(escape_csv("cr\r"), "\"cr\r\"");
+        assert_eq!(escape_csv(""), "");
+    }
+
+    #[test]
+    fn csv_rows_keep_the_columns_of_records_with_commas() {
+        let txt = txt_record(&[b"a,b", b"c"]);
+        let row = TableFormatter::Csv.row(&record_fields(&txt, false));
+        assert_eq!(row, r#"example.com.,TXT,IN,300,"""a,b"" ""c""""#);
+        let hinfo = record("example.com.", RecordType::HINFO, 60, "\"x86, arm\" linux");
+        let row = TableFormatter::Csv.row(&record_fields(&hinfo, false));
+        assert!(row.starts_with("example.com.,HINFO,IN,60,\""), "{}", row);
+        assert!(row.ends_with('"'), "{}", row);
+    }
+
+    #[test]
+    fn tsv_fields_escape_tabs_line_breaks_and_backslashes() {
+        assert_eq!(escape_tsv("a\tb"), "a\\tb");
+        assert_eq!(escape_tsv("a\nb\r"), "a\\nb\\r");
+        assert_eq!(escape_tsv("a\\b"), "a\\\\b");
+        assert_eq!(escape_tsv("a,\"b\""), "a,\"b\"");
+    }
+
+    #[test]
+    fn tables_print_their_header_once_per_shared_state() {
+        let a = record("example.com.", RecordType::A, 300, "192.0.2.1");
+        let printed = Arc::new(AtomicBool::new(false));
+        let first = OutputConfig::new(true, false).with_table_header(printed.clone());
+        let second = OutputConfig::new(true, false).with_table_header(printed);
+        let rows = TableFormatter::Csv.format(&[&a], &first);
+        assert_eq!(
+            rows,
+            [
+                "name,type,class,ttl,data,section",
+                "example.com.,A,IN,300,192.0.2.1,answer"
+            ]
+        );
+        assert_eq!(TableFormatter::Csv.format(&[&a], &second).len(), 1);
+        // Another run starts its own table
+        let other = OutputConfig::new(true, false);
+        assert_eq!(TableFormatter::Tsv.format(&[&a], &other).len(), 2);
+    }
+
+    #[test]
+    fn records_are_tagged_with_their_section() {
+        let mut response = Message::new();
+        response.add_answer(record("example.com.", RecordType::A, 300, "192.0.2.1"));
+        response.add_name_server(record(
+            "example.com.",
+            RecordType::NS,
+            300,
+            "ns1.example.com.",
+        ));
+        response.add_additional(record("ns1.example.com.", RecordType::A, 300, "192.0.2.53"));
+        response.set_edns(hickory_client::op::Edns::new());
+        let others = other_sections(&response);
+        let sections: Vec<Section> = others.iter().map(|(_, section)| *section).collect();
+        assert_eq!(sections, [Section::Authority, Section::Additional]);
+
+        let mut records = vec![(&response.answers()[0], Section::Answer)];
+        records.extend(others);
+        let rows = TableFormatter::Tsv.format_sections(&records, &OutputConfig::new(true, false));
+        assert_eq!(
+            rows[1..],
+            [
+                "example.com.\tA\tIN\t300\t192.0.2.1\tanswer",
+                "example.com.\tNS\tIN\t300\tns1.example.com.\tauthority",
+                "ns1.example.com.\tA\tIN\t300\t192.0.2.53\tadditional",
+            ]
+        );
+        let json = json_sections(&records);
+        let tags: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| record["section"].as_str().unwrap())
+            .collect();
+        assert_eq!(tags, ["answer", "authority", "additional"]);
+    }
+
+    fn sorted(
+        records: &[Record],
+        key: &str,
+    ) -> Vec<String> {
+        let mut records: Vec<&Record> = records.iter().collect();
+        sort_records(&mut records, Some(key.parse().unwrap()));
+        records
+            .iter()
+            .map(|record| {
+                format!(
+                    "{} {}",
+                    record.record_type(),
+                    data_text(record.data().unwrap())
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn addresses_are_sorted_by_their_value() {
+        let records = [
+            record("example.com.", RecordType::A, 300, "192.0.2.10"),
+            record("example.com.", RecordType::A, 300, "192.0.2.9"),
+            record("example.com.", RecordType::AAAA, 300, "2001:db8::10"),
+            record("example.com.", RecordType::AAAA, 300, "2001:db8::9"),
+            record("example.com.", RecordType::A, 300, "10.0.0.1"),
+        ];
+        assert_eq!(
+            sorted(&records, "data"),
+            [
+                "A 10.0.0.1",
+                "A 192.0.2.9",
+                "A 192.0.2.10",
+                "AAAA 2001:db8::9",
+                "AAAA 2001:db8::10",
+            ]
+        );
+        assert_eq!(sorted(&records, "-data")[0], "AAAA 2001:db8::10");
+    }
+
+    #[test]
+    fn mixed_types_are_sorted_by_type_then_data() {
+        let records = [
+            record("example.com.", RecordType::TXT, 60, "\"b\""),
+            record("example.com.", RecordType::MX, 300, "20 mx1.example.com."),
+            record("example.com.", RecordType::A, 30, "192.0.2.1"),
+            record("example.com.", RecordType::MX, 3600, "5 mx2.example.com."),
+            record("example.com.", RecordType::TXT, 60, "\"a\""),
+        ];
+        assert_eq!(
+            sorted(&records, "data"),
+            [
+                "A 192.0.2.1",
+                "MX 5 mx2.example.com.",
+                "MX 20 mx1.example.com.",
+                "TXT a",
+                "TXT b",
+            ]
+        );
+        // Records of the same type keep the order they came in
+        assert_eq!(
+            sorted(&records, "type"),
+            [
+                "A 192.0.2.1",
+                "MX 20 mx1.example.com.",
+                "MX 5 mx2.example.com.",
+                "TXT b",
+                "TXT a",
+            ]
+        );
+        assert_eq!(sorted(&records, "-TTL")[0], "MX 5 mx2.example.com.");
+        assert_eq!(sorted(&records, "ttl")[0], "A 192.0.2.1");
+        assert!("size".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn the_cname_chain_repeated_across_responses_is_kept_once() {
+        let cname = |ttl| record("www.example.com.", RecordType::CNAME, ttl, "example.com.");
+        // The chain as it comes back in the A, AAAA and CNAME responses
+        let records = [
+            cname(300),
+            record("example.com.", RecordType::A, 300, "192.0.2.1"),
+            cname(120),
+            record("example.com.", RecordType::AAAA, 300, "2001:db8::1"),
+            cname(200),
+        ];
+        let mut deduped: Vec<&Record> = records.iter().collect();
+        dedup_records(&mut deduped);
+        assert_eq!(
+            deduped
+                .iter()
+                .map(|record| (record.record_type(), record.ttl()))
+                .collect::<Vec<_>>(),
+            [
+                (RecordType::CNAME, 120),
+                (RecordType::A, 300),
+                (RecordType::AAAA, 300),
+            ]
+        );
+        // The same data under another name or type is not a repeat
+        let other = [
+            record("example.com.", RecordType::A, 300, "192.0.2.1"),
+            record("example.net.", RecordType::A, 300, "192.0.2.1"),
+            record("example.com.", RecordType::TXT, 300, "\"192.0.2.1\""),
+        ];
+        let mut deduped: Vec<&Record> = other.iter().collect();
+        dedup_records(&mut deduped);
+        assert_eq!(deduped.len(), 3);
+    }
+
+    #[test]
+    fn data_that_hickory_cannot_write_out_is_left_empty() {
+        let opt = RData::OPT(hickory_client::rr::rdata::OPT::default());
+        let record = Record::from_rdata(Name::root(), 0, opt);
+        let config = OutputConfig::new(true, false);
+        assert_eq!(record_fields(&record, false)[4], "");
+        assert_eq!(json_records(&[&record])[0]["data"], "");
+        RecordFormatter::new(&record, &config).format();
     }
 }
@@ -0,0 +1,392 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fmt::Write as _,
+    fs,
+    net::IpAddr,
+    path::Path,
+    time::Duration,
+};
+
+use hickory_client::{
+    op::{DnsResponse, ResponseCode},
+    rr::{DNSClass, Name, RData, Record, RecordType},
+};
+
+use crate::{format::format_ttl, AppError, DnsClient};
+
+/// Timeout of every lookup of the targets that `--graph` follows
+pub const GRAPH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Most targets looked up, bounding runs on long chains and loops
+const MAX_LOOKUPS: usize = 32;
+
+/// Types that the targets of the answers are looked up for
+const ADDRESS_TYPES: [RecordType; 2] = [RecordType::A, RecordType::AAAA];
+
+enum Node {
+    Name(String),
+    Address(IpAddr),
+    /// a lookup that failed, e.g. with NXDOMAIN or a timeout
+    Error(String),
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    label: String,
+}
+
+/// Names and addresses that the answers of a run lead to
+pub struct Graph {
+    server: String,
+    /// by their DOT identifiers
+    nodes: BTreeMap<String, Node>,
+    edges: Vec<Edge>,
+}
+
+/// Name that the record points to, for the types resolved further
+fn target_of(record: &Record) -> Option<Name> {
+    let target = match record.data() {
+        Some(RData::CNAME(target)) => target.0.clone(),
+        Some(RData::NS(target)) => target.0.clone(),
+        Some(RData::PTR(target)) => target.0.clone(),
+        Some(RData::MX(mx)) => mx.exchange().clone(),
+        Some(RData::SRV(srv)) => srv.target().clone(),
+        Some(RData::HTTPS(https)) => https.0.target_name().clone(),
+        Some(RData::SVCB(svcb)) => svcb.target_name().clone(),
+        _ => return None,
+    };
+    // A target of "." stands for no service, or for the owner with HTTPS and SVCB
+    match target.is_root() {
+        true => None,
+        false => Some(target),
+    }
+}
+
+fn address(record: &Record) -> Option<IpAddr> {
+    match record.data() {
+        Some(RData::A(address)) => Some(IpAddr::V4(address.0)),
+        Some(RData::AAAA(address)) => Some(IpAddr::V6(address.0)),
+        _ => None,
+    }
+}
+
+/// Escape `value` for a DOT string
+fn escape(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"")
+}
+
+/// Quote `value` as a DOT string
+fn quote(value: &str) -> String {
+    format!("\"{}\"", escape(value))
+}
+
+impl Graph {
+    fn new(server: &str) -> Self {
+        Self {
+            server: server.to_owned(),
+            nodes: BTreeMap::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    fn name(
+        &mut self,
+        name: &Name,
+    ) -> String {
+        let name = name.to_lowercase().to_ascii();
+        let id = format!("name:{}", name);
+        self.nodes.entry(id.clone()).or_insert(Node::Name(name));
+        id
+    }
+
+    fn add_record(
+        &mut self,
+        record: &Record,
+    ) {
+        let to = match (target_of(record), address(record)) {
+            (Some(target), _) => self.name(&target),
+            (_, Some(address)) => {
+                let id = format!("address:{}", address);
+                self.nodes
+                    .entry(id.clone())
+                    .or_insert(Node::Address(address));
+                id
+            },
+            _ => return,
+        };
+        let from = self.name(record.name());
+        let label = format!("{} {}", record.record_type(), format_ttl(record.ttl()));
+        self.add_edge(from, to, label);
+    }
+
+    fn add_error(
+        &mut self,
+        name: &Name,
+        record_type: RecordType,
+        error: String,
+    ) {
+        let from = self.name(name);
+        let id = format!("error:{}:{}", from, record_type);
+        self.nodes.insert(id.clone(), Node::Error(error));
+        self.add_edge(from, id, record_type.to_string());
+    }
+
+    fn add_edge(
+        &mut self,
+        from: String,
+        to: String,
+        label: String,
+    ) {
+        let duplicate = self
+            .edges
+            .iter()
+            .any(|edge| edge.from == from && edge.to == to && edge.label == label);
+        if !duplicate {
+            self.edges.push(Edge {
+                from,
+                to,
+                label,
+            });
+        }
+    }
+
+    /// Answers of one query, or the error when it has none
+    fn add_response(
+        &mut self,
+        name: &Name,
+        record_type: RecordType,
+        response: &DnsResponse,
+    ) {
+        match response.response_code() {
+            ResponseCode::NoError => {
+                for record in response.answers() {
+                    self.add_record(record);
+                }
+            },
+            code => self.add_error(name, record_type, format!("{:?}", code).to_uppercase()),
+        }
+    }
+
+    /// Graph of the answers of the run, with the targets they name followed to their
+    /// addresses unless the answers already lead on from them
+    pub fn build(
+        client: &DnsClient,
+        server: &str,
+        name: &Name,
+        responses: &[(RecordType, DnsResponse)],
+    ) -> Self {
+        let mut graph = Self::new(server);
+        for (record_type, response) in responses {
+            graph.add_response(name, *record_type, response);
+        }
+
+        let answers: Vec<&Record> = responses
+            .iter()
+            .flat_map(|(_, response)| response.answers())
+            .collect();
+        // Owners that the answers already lead on from
+        let mut seen: HashSet<Name> = answers
+            .iter()
+            .filter(|record| address(record).is_some() || target_of(record).is_some())
+            .map(|record| record.name().to_lowercase())
+            .collect();
+        let mut pending: VecDeque<Name> = answers
+            .iter()
+            .filter_map(|record| target_of(record))
+            .collect();
+
+        let mut lookups = 0;
+        while let Some(target) = pending.pop_front() {
+            if !seen.insert(target.to_lowercase()) {
+                continue;
+            }
+            if lookups == MAX_LOOKUPS {
+                break;
+            }
+            lookups += 1;
+            for record_type in ADDRESS_TYPES {
+                match client.query(&target, DNSClass::IN, record_type) {
+                    Ok(response) => {
+                        graph.add_response(&target, record_type, &response);
+                        pending.extend(response.answers().iter().filter_map(target_of));
+                    },
+                    Err(error) => graph.add_error(&target, record_type, error.to_string()),
+                }
+            }
+        }
+        graph
+    }
+
+    /// Render as a Graphviz DOT graph
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph resoy {\n  rankdir=LR;\n");
+        for (id, node) in &self.nodes {
+            let attributes = match node {
+                Node::Name(name) => format!("label={}, shape=ellipse", quote(name)),
+                Node::Address(address) => {
+                    format!("label={}, shape=box", quote(&address.to_string()))
+                },
+                Node::Error(error) => format!(
+                    "label={}, shape=octagon, color=red, fontcolor=red",
+                    quote(error)
+                ),
+            };
+            let _ = writeln!(dot, "  {} [{}];", quote(id), attributes);
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                dot,
+                "  {} -> {} [label=\"{}\\n@{}\"];",
+                quote(&edge.from),
+                quote(&edge.to),
+                escape(&edge.label),
+                escape(&self.server)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn write(
+        &self,
+        path: &Path,
+    ) -> Result<(), AppError> {
+        fs::write(path, self.to_dot())
+            .map_err(|e| AppError::GraphError(path.to_owned(), e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use super::*;
+    use crate::{mock::Fixture, ConnectionType, ResolveOptions};
+
+    /// A name behind a CNAME and a mail exchanger that doesn't exist
+    const FIXTURE: &str = r#"
+        [[answer]]
+        name = "example.com"
+        type = "SOA"
+        records = ["ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 600"]
+
+        [[answer]]
+        name = "www.example.com"
+        type = "CNAME"
+        ttl = 3600
+        records = ["web.example.com."]
+
+        [[answer]]
+        name = "web.example.com"
+        type = "A"
+        ttl = 60
+        records = ["192.0.2.1"]
+
+        [[answer]]
+        name = "web.example.com"
+        type = "AAAA"
+        records = ["2001:db8::1"]
+
+        [[answer]]
+        name = "www.example.com"
+        type = "MX"
+        records = ["10 mail.example.net."]
+    "#;
+
+    fn graph() -> Graph {
+        let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+            .with_mock(Some(Arc::new(Fixture::from_toml(FIXTURE).unwrap())));
+        let client = DnsClient::new(&options).unwrap();
+        let name = Name::from_str("www.example.com.").unwrap();
+        let responses: Vec<(RecordType, DnsResponse)> = [RecordType::CNAME, RecordType::MX]
+            .into_iter()
+            .map(|record_type| {
+                let response = client.query(&name, DNSClass::IN, record_type).unwrap();
+                (record_type, response)
+            })
+            .collect();
+        Graph::build(&client, "192.0.2.53", &name, &responses)
+    }
+
+    fn edges(graph: &Graph) -> Vec<(&str, &str, &str)> {
+        graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.label.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn targets_are_followed_to_their_addresses() {
+        let graph = graph();
+        assert_eq!(
+            edges(&graph),
+            [
+                (
+                    "name:www.example.com.",
+                    "name:web.example.com.",
+                    "CNAME 1h00m00s"
+                ),
+                (
+                    "name:www.example.com.",
+                    "name:mail.example.net.",
+                    "MX 5m00s"
+                ),
+                ("name:web.example.com.", "address:192.0.2.1", "A 1m00s"),
+                ("name:web.example.com.", "address:2001:db8::1", "AAAA 5m00s"),
+                (
+                    "name:mail.example.net.",
+                    "error:name:mail.example.net.:A",
+                    "A"
+                ),
+                (
+                    "name:mail.example.net.",
+                    "error:name:mail.example.net.:AAAA",
+                    "AAAA"
+                ),
+            ]
+        );
+        let ids: Vec<&str> = graph.nodes.keys().map(String::as_str).collect();
+        assert_eq!(
+            ids,
+            [
+                "address:192.0.2.1",
+                "address:2001:db8::1",
+                "error:name:mail.example.net.:A",
+                "error:name:mail.example.net.:AAAA",
+                "name:mail.example.net.",
+                "name:web.example.com.",
+                "name:www.example.com.",
+            ]
+        );
+        assert!(matches!(
+            &graph.nodes["error:name:mail.example.net.:A"],
+            Node::Error(error) if error == "NXDOMAIN"
+        ));
+    }
+
+    #[test]
+    fn the_dot_graph_draws_errors_distinctly_and_tells_the_server() {
+        let dot = graph().to_dot();
+        assert!(dot.starts_with("digraph resoy {\n  rankdir=LR;\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  \"address:192.0.2.1\" [label=\"192.0.2.1\", shape=box];\n"));
+        assert!(dot.contains(
+            "  \"name:web.example.com.\" [label=\"web.example.com.\", shape=ellipse];\n"
+        ));
+        assert!(dot.contains(
+            "  \"error:name:mail.example.net.:AAAA\" [label=\"NXDOMAIN\", shape=octagon, \
+             color=red, fontcolor=red];\n"
+        ));
+        assert!(dot.contains(
+            "  \"name:www.example.com.\" -> \"name:web.example.com.\" \
+             [label=\"CNAME 1h00m00s\\n@192.0.2.53\"];\n"
+        ));
+    }
+
+    #[test]
+    fn dot_strings_are_escaped() {
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}
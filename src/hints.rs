@@ -0,0 +1,157 @@
+use std::{fmt::Display, thread, time::Duration};
+
+use hickory_client::{
+    op::{DnsResponse, ResponseCode},
+    rr::{DNSClass, Name, RecordType},
+};
+
+use crate::DnsClient;
+
+/// Timeout of the single attempt of every extra query made to find hints
+pub const HINT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Types checked when a name has no records of the requested type
+const LIKELY_TYPES: [RecordType; 5] = [
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::CNAME,
+    RecordType::MX,
+    RecordType::TXT,
+];
+
+/// Other record types found for a name that answered NODATA
+pub struct Hint {
+    pub name: Name,
+    pub missing: RecordType,
+    pub existing: Vec<RecordType>,
+}
+
+impl Display for Hint {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let existing: Vec<String> = self.existing.iter().map(ToString::to_string).collect();
+        let listed = match existing.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+            None => String::new(),
+        };
+        write!(
+            f,
+            "no {} record for {}, but {} {} for this name",
+            self.missing,
+            self.name,
+            listed,
+            if existing.len() == 1 {
+                "exists"
+            } else {
+                "exist"
+            }
+        )
+    }
+}
+
+fn is_nodata(
+    record_type: RecordType,
+    response: &DnsResponse,
+) -> bool {
+    response.response_code() == ResponseCode::NoError
+        && !response
+            .answers()
+            .iter()
+            .any(|record| record.record_type() == record_type)
+}
+
+/// Whether the server declined to answer ANY with its records, answering with a lone HINFO
+/// record instead as RFC 8482 allows
+pub fn is_minimal_any(responses: &[(RecordType, DnsResponse)]) -> bool {
+    responses.iter().any(|(record_type, response)| {
+        *record_type == RecordType::ANY
+            && !response.answers().is_empty()
+            && response
+                .answers()
+                .iter()
+                .all(|record| record.record_type() == RecordType::HINFO)
+    })
+}
+
+/// Look up likely record types concurrently for every NODATA response.
+///
+/// Failed or timed out lookups are treated as absent, so hints never turn into errors.
+pub fn find_hints(
+    client: &DnsClient,
+    name: &Name,
+    responses: &[(RecordType, DnsResponse)],
+) -> Vec<Hint> {
+    let missing: Vec<RecordType> = responses
+        .iter()
+        .filter(|(record_type, response)| is_nodata(*record_type, response))
+        .map(|(record_type, _)| *record_type)
+        .collect();
+    if missing.is_empty() {
+        return Vec::new();
+    }
+
+    // Types whose answers are already known don't need another query
+    let mut known: Vec<RecordType> = Vec::new();
+    for (_, response) in responses {
+        for record in response.answers() {
+            if !known.contains(&record.record_type()) {
+                known.push(record.record_type());
+            }
+        }
+    }
+    let candidates: Vec<RecordType> = LIKELY_TYPES
+        .into_iter()
+        .filter(|record_type| {
+            !known.contains(record_type)
+                && !responses.iter().any(|(queried, _)| queried == record_type)
+        })
+        .collect();
+
+    let found: Vec<RecordType> = thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .iter()
+            .map(|record_type| {
+                scope.spawn(move || {
+                    client
+                        .query(name, DNSClass::IN, *record_type)
+                        .map(|response| {
+                            response
+                                .answers()
+                                .iter()
+                                .map(|record| record.record_type())
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    });
+
+    let existing: Vec<RecordType> = LIKELY_TYPES
+        .into_iter()
+        .filter(|record_type| known.contains(record_type) || found.contains(record_type))
+        .collect();
+
+    missing
+        .into_iter()
+        .filter_map(|missing| {
+            let existing: Vec<RecordType> = existing
+                .iter()
+                .copied()
+                .filter(|record_type| *record_type != missing)
+                .collect();
+            (!existing.is_empty()).then(|| Hint {
+                name: name.clone(),
+                missing,
+                existing,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,87 @@
+//! Resolution of DNS names over UDP, TCP, DNS-over-TLS and DNS-over-HTTPS, and the
+//! formatting of the answers, as the resoy command-line tool does them.
+//!
+//! `resolve` answers the questions of a name for several record types at once, while
+//! `DnsClient` sends single queries. How the queries go out, e.g. with the DO bit, is set by
+//! the `ResolveOptions` they are given, how they are sent again by the `QueryPolicy` of
+//! those, and the clients built from the same options share the budget of their queries and
+//! the answers to questions asked twice.
+
+pub mod aggregate;
+pub mod answer_cache;
+pub mod asn;
+pub mod audit;
+pub mod batch;
+pub mod budget;
+pub mod client;
+pub mod clock;
+pub mod compare;
+pub mod concurrency;
+pub mod connections;
+pub mod dangling;
+pub mod dedup;
+pub mod dnsperf;
+pub mod doctor;
+#[cfg(feature = "doh")]
+pub mod doh;
+#[cfg(feature = "doh")]
+pub mod doh_json;
+pub mod dual_stack;
+pub mod env;
+pub mod error;
+pub mod expect;
+pub mod explain;
+pub mod filtering;
+pub mod follow;
+pub mod format;
+pub mod graph;
+pub mod hints;
+pub mod lookup;
+pub mod mock;
+pub mod output;
+pub mod parse;
+pub mod pcap;
+pub mod pins;
+pub mod policy;
+pub mod probe;
+pub mod proxy;
+#[cfg(feature = "doh")]
+pub mod rdap;
+pub mod readiness;
+pub mod reputation;
+pub mod resolv_conf;
+pub mod resolve;
+pub mod rollover;
+pub mod routes;
+pub mod salvage;
+pub mod schema;
+pub mod server_addr;
+pub mod sha256;
+pub mod source;
+pub mod stats;
+pub mod stats_http;
+pub mod suggest;
+pub mod survey;
+pub mod tcp;
+pub mod tcp_fallback;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod trace;
+#[cfg(feature = "dnssec")]
+pub mod trust_anchors;
+pub mod tsig;
+pub mod ttl;
+pub mod ttl_rewrite;
+pub mod types;
+#[cfg(feature = "doh")]
+pub mod update;
+pub mod validate;
+pub mod verbosity;
+pub mod via;
+pub mod watch;
+pub mod widerow;
+
+pub use client::{ConnectionType, DnsClient};
+pub use error::AppError;
+pub use policy::QueryPolicy;
+pub use resolve::{resolve, ResolveOptions};
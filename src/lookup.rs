@@ -0,0 +1,468 @@
+//! The answers of a lookup, with what the features of a run add to every record of them.
+
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use hickory_client::{
+    op::DnsResponse,
+    rr::{Name, Record, RecordType},
+};
+use serde_json::{json, Value};
+
+use crate::{
+    asn::{self, Origin},
+    expect::{self, Expectation},
+    filtering, follow,
+    format::{self, ColumnWidths, GroupKey, OutputConfig, RecordFormatter},
+    outln,
+    validate::Verdicts,
+    AppError, DnsClient,
+};
+
+/// Responses to the queries of a name, and the annotations of their answers
+pub struct Lookup<'r> {
+    name: &'r Name,
+    responses: &'r [(RecordType, DnsResponse)],
+    verdicts: Verdicts<'r>,
+    /// how many CNAMEs lead to every answer, with --follow
+    chain_steps: Vec<(&'r Record, Option<usize>)>,
+    /// origins of the addresses, with --asn
+    origins: HashMap<IpAddr, Origin>,
+    /// types asked for, whose groups are shown when empty with --verbose
+    empty_groups: Option<Vec<RecordType>>,
+    dnssec: bool,
+    nsid: bool,
+    filtering: bool,
+}
+
+impl<'r> Lookup<'r> {
+    pub fn new(
+        name: &'r Name,
+        responses: &'r [(RecordType, DnsResponse)],
+    ) -> Self {
+        Self {
+            name,
+            responses,
+            verdicts: Verdicts::default(),
+            chain_steps: Vec::new(),
+            origins: HashMap::new(),
+            empty_groups: None,
+            dnssec: false,
+            nsid: false,
+            filtering: false,
+        }
+    }
+
+    /// Verdicts of `--validate` on the answers
+    pub fn with_verdicts(
+        mut self,
+        verdicts: Verdicts<'r>,
+    ) -> Self {
+        self.verdicts = verdicts;
+        self
+    }
+
+    /// Count the CNAMEs that lead to every answer, for the responses of `--follow`
+    pub fn with_chains(mut self) -> Self {
+        self.chain_steps = self
+            .responses
+            .iter()
+            .flat_map(|(_, response)| {
+                let answers = response.answers();
+                answers.iter().zip(follow::steps(self.name, answers))
+            })
+            .collect();
+        self
+    }
+
+    /// Look up the origins of the addresses of the answers with `client`, for `--asn`
+    pub fn with_origins(
+        mut self,
+        client: &DnsClient,
+    ) -> Self {
+        self.origins = asn::annotate(client, &self.answers());
+        self
+    }
+
+    /// Show the groups of the name and of the types of `queried` that have no records, for
+    /// `--group-by` with `--verbose`
+    pub fn with_empty_groups(
+        mut self,
+        queried: &[RecordType],
+    ) -> Self {
+        self.empty_groups = Some(queried.to_vec());
+        self
+    }
+
+    /// Tell whether the server validated every record, for `--dnssec`
+    pub fn with_dnssec(
+        mut self,
+        dnssec: bool,
+    ) -> Self {
+        self.dnssec = dnssec;
+        self
+    }
+
+    /// Tell the NSID of the response every record came in, for `--nsid`
+    pub fn with_nsid(
+        mut self,
+        nsid: bool,
+    ) -> Self {
+        self.nsid = nsid;
+        self
+    }
+
+    /// Flag the addresses that look like the answers of a filtering resolver
+    pub fn with_filtering(
+        mut self,
+        filtering: bool,
+    ) -> Self {
+        self.filtering = filtering;
+        self
+    }
+
+    /// Answers of every response, in the order of the types
+    pub fn answers(&self) -> Vec<&'r Record> {
+        self.responses
+            .iter()
+            .flat_map(|(_, response)| response.answers())
+            .collect()
+    }
+
+    /// Response that `record` came in
+    fn response_of(
+        &self,
+        record: &Record,
+    ) -> Option<&'r DnsResponse> {
+        self.responses
+            .iter()
+            .map(|(_, response)| response)
+            .find(|response| {
+                response
+                    .answers()
+                    .iter()
+                    .any(|answer| std::ptr::eq(answer, record))
+            })
+    }
+
+    /// Whether the server validated the response `record` came in
+    pub fn authenticated(
+        &self,
+        record: &Record,
+    ) -> bool {
+        self.response_of(record)
+            .is_some_and(|response| response.authentic_data())
+    }
+
+    /// How many CNAMEs lead to `record`, with `with_chains`
+    pub fn chain_step(
+        &self,
+        record: &Record,
+    ) -> Option<usize> {
+        self.chain_steps
+            .iter()
+            .find(|(answer, _)| std::ptr::eq(*answer, record))
+            .and_then(|(_, step)| *step)
+    }
+
+    /// Origin of the address of `record`, with `with_origins`
+    pub fn origin(
+        &self,
+        record: &Record,
+    ) -> Option<&Origin> {
+        self.origins.get(&asn::address_of(record)?)
+    }
+
+    /// Fail `codes` with the bogus records, then with the expectations no answer meets, which
+    /// are reported on stderr
+    pub fn validated(
+        &self,
+        codes: Result<(), AppError>,
+        expectations: &[Expectation],
+    ) -> Result<(), AppError> {
+        let codes = match self.verdicts.bogus() {
+            0 => codes,
+            bogus => codes.and(Err(AppError::Bogus(bogus))),
+        };
+        if codes.is_err() || expectations.is_empty() {
+            return codes;
+        }
+        match expect::report(expectations, &self.answers()) {
+            0 => codes,
+            unmet => Err(AppError::ExpectationsUnmet(unmet)),
+        }
+    }
+
+    /// JSON records of `answers` with their annotations, followed by the records of the other
+    /// sections with `full`, each tagged with its own
+    pub fn json_records(
+        &self,
+        answers: &[&Record],
+        full: bool,
+    ) -> Value {
+        let mut records = format::json_records(answers);
+        if let Value::Array(records) = &mut records {
+            for (record, answer) in records.iter_mut().zip(answers) {
+                if self.dnssec {
+                    record["authenticated"] = Value::Bool(self.authenticated(answer));
+                }
+                // Every record has the NSID of the response it came in
+                let nsid = self
+                    .response_of(answer)
+                    .and_then(|response| format::format_nsid(response));
+                if let (true, Some(nsid)) = (self.nsid, nsid) {
+                    record["nsid"] = Value::from(nsid);
+                }
+                if let Some(step) = self.chain_step(answer) {
+                    record["chain"] = Value::from(step);
+                }
+                if let Some(origin) = self.origin(answer) {
+                    record["asn"] = serde_json::to_value(origin).unwrap_or_default();
+                }
+                if let Some(verdict) = self.verdicts.get(answer) {
+                    record["validation"] = Value::from(verdict.label());
+                    if let Some(anchor) = verdict.anchor() {
+                        record["validation_anchor"] = Value::from(anchor);
+                    }
+                    if let Some(reason) = verdict.reason() {
+                        record["validation_reason"] = Value::from(reason);
+                    }
+                }
+            }
+            if full {
+                for (_, response) in self.responses {
+                    let others = format::other_sections(response);
+                    if let Value::Array(others) = format::json_sections(&others) {
+                        records.extend(others);
+                    }
+                }
+            }
+        }
+        records
+    }
+
+    /// Group `records` by `key`, with the empty groups of `with_empty_groups`
+    fn groups(
+        &self,
+        records: &[&'r Record],
+        key: GroupKey,
+    ) -> Vec<(String, Vec<&'r Record>)> {
+        let groups = format::group_records(records.to_vec(), key);
+        match &self.empty_groups {
+            Some(queried) => format::with_empty_groups(groups, key, self.name, queried),
+            None => groups,
+        }
+    }
+
+    /// The JSON records of `answers` nested per name then per type, or per type then per name,
+    /// for `--group-by`, in arrays that keep the order of the groups
+    pub fn json_groups(
+        &self,
+        answers: &[&'r Record],
+        key: GroupKey,
+    ) -> Value {
+        let records = self.json_records(answers, false);
+        let json_of = |record: &Record| {
+            answers
+                .iter()
+                .position(|answer| std::ptr::eq(*answer, record))
+                .map(|index| records[index].clone())
+        };
+        let (outer, inner, members) = match key {
+            GroupKey::Name => ("name", GroupKey::Type, "types"),
+            GroupKey::Type => ("type", GroupKey::Name, "names"),
+        };
+        let inner_name = match inner {
+            GroupKey::Name => "name",
+            GroupKey::Type => "type",
+        };
+        let mut asked = self.name.clone();
+        asked.set_fqdn(true);
+        let asked = asked.to_string();
+        let groups: Vec<Value> = self
+            .groups(answers, key)
+            .into_iter()
+            .map(|(header, records)| {
+                let mut subgroups = format::group_records(records, inner);
+                // Only the name asked for was queried for every type
+                if let (Some(queried), GroupKey::Name) = (&self.empty_groups, key) {
+                    if header == asked {
+                        subgroups = format::with_empty_groups(subgroups, inner, self.name, queried);
+                    }
+                }
+                let subgroups: Vec<Value> = subgroups
+                    .into_iter()
+                    .map(|(subheader, records)| {
+                        let records: Vec<Value> = records.into_iter().filter_map(json_of).collect();
+                        json!({ inner_name: subheader, "records": records })
+                    })
+                    .collect();
+                json!({ outer: header, members: subgroups })
+            })
+            .collect();
+        Value::from(groups)
+    }
+
+    /// Write `record` into `line`, in the columns of `widths`, with its annotations
+    pub fn write_record(
+        &self,
+        line: &mut String,
+        record: &Record,
+        widths: ColumnWidths,
+        output_config: &OutputConfig,
+    ) {
+        line.clear();
+        RecordFormatter::new(record, output_config)
+            .with_widths(widths)
+            .write(line);
+        if let Some(origin) = self.origin(record) {
+            line.push_str(&origin.annotation());
+        }
+        if self.filtering {
+            if let Some(filtered) = filtering::annotation(record) {
+                line.push_str(&filtered);
+            }
+        }
+        if self.dnssec && self.authenticated(record) {
+            line.push_str("  [ad]");
+        }
+        if let Some(verdict) = self.verdicts.get(record) {
+            line.push_str(&verdict.annotation(output_config));
+        }
+    }
+
+    /// Print `printed` as lines, under the headers of their groups with `group_by`, or with
+    /// the first record of every CNAME target connected to the CNAME before it with
+    /// `with_chains`
+    pub fn print_records(
+        &self,
+        printed: &[&'r Record],
+        group_by: Option<GroupKey>,
+        output_config: &OutputConfig,
+    ) {
+        // The columns line up across all the printed records, whatever their groups
+        let widths = output_config.column_widths(printed);
+        // Every line is written into the same buffer
+        let mut line = String::new();
+        match group_by {
+            Some(group_by) => {
+                for (header, records) in self.groups(printed, group_by) {
+                    outln!("{}", output_config.format_header(&header));
+                    if records.is_empty() {
+                        outln!("  (no records)")
+                    }
+                    for record in records {
+                        self.write_record(&mut line, record, widths, output_config);
+                        outln!("  {}", line)
+                    }
+                }
+            },
+            None if !self.chain_steps.is_empty() => {
+                let mut previous = None;
+                for record in printed {
+                    let step = self.chain_step(record);
+                    self.write_record(&mut line, record, widths, output_config);
+                    let arrow = match step {
+                        Some(step) if step > 0 && previous != Some(step) => "-> ",
+                        _ => "   ",
+                    };
+                    previous = step;
+                    outln!("{}{}", arrow, line)
+                }
+            },
+            None => {
+                for record in printed {
+                    self.write_record(&mut line, record, widths, output_config);
+                    outln!("{}", line)
+                }
+            },
+        }
+    }
+
+    /// Print the header, the authority and the additional section of every response, for
+    /// `--full`
+    pub fn print_sections(
+        &self,
+        output_config: &OutputConfig,
+    ) {
+        let mut line = String::new();
+        for (record_type, response) in self.responses {
+            // OPT is no record, its fields are summarized instead
+            let others = format::other_sections(response);
+            let header = format!(";; HEADER of {}", record_type);
+            outln!("{}", output_config.format_header(&header));
+            outln!("{}", format::format_flags(response));
+            for section in [format::Section::Authority, format::Section::Additional] {
+                let records: Vec<&Record> = others
+                    .iter()
+                    .filter(|(_, other)| *other == section)
+                    .map(|(record, _)| *record)
+                    .collect();
+                let edns = match section {
+                    format::Section::Additional => response.extensions().as_ref(),
+                    _ => None,
+                };
+                if records.is_empty() && edns.is_none() {
+                    continue;
+                }
+                let header = format!(";; {} of {}", section.as_str().to_uppercase(), record_type);
+                outln!("{}", output_config.format_header(&header));
+                if let Some(edns) = edns {
+                    outln!("{}", format::format_edns(edns));
+                }
+                // Only the records of the answers go untagged
+                let widths = output_config.column_widths(&records);
+                for record in records {
+                    self.write_record(&mut line, record, widths, output_config);
+                    outln!("{}  [{}]", line, section.as_str())
+                }
+            }
+        }
+    }
+
+    /// Print the NSIDs of the responses, once when the same node answered every type
+    pub fn print_nsids(&self) {
+        let nsids: Vec<(RecordType, String)> = self
+            .responses
+            .iter()
+            .filter_map(|(record_type, response)| {
+                Some((*record_type, format::format_nsid(response)?))
+            })
+            .collect();
+        let same = nsids.windows(2).all(|pair| pair[0].1 == pair[1].1);
+        match (same, nsids.first()) {
+            (true, Some((_, nsid))) => outln!("; NSID: {}", nsid),
+            _ => {
+                for (record_type, nsid) in &nsids {
+                    outln!("; NSID of {}: {}", record_type, nsid)
+                }
+            },
+        }
+    }
+
+    /// Print the response code, the round trip time of `latencies` and the size of every
+    /// response from `server`, and the time all of them took with `elapsed`
+    pub fn print_footer(
+        &self,
+        server: &str,
+        latencies: &[Duration],
+        elapsed: Duration,
+    ) {
+        for ((record_type, response), latency) in self.responses.iter().zip(latencies) {
+            outln!(
+                ";; {}: {} from {} in {}ms, {} bytes",
+                record_type,
+                format!("{:?}", response.response_code()).to_uppercase(),
+                server,
+                latency.as_millis(),
+                response.as_buffer().len()
+            );
+        }
+        if self.responses.len() > 1 {
+            outln!(
+                ";; {} queries in {}ms",
+                self.responses.len(),
+                elapsed.as_millis()
+            );
+        }
+    }
+}
@@ -1,192 +1,2470 @@
-mod format;
+mod completions;
+mod config;
+mod examples;
+mod manifest;
 
 use std::{
-    collections::HashSet,
-    fmt::{Debug, Display},
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    ffi::OsString,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    io::{stdout, IsTerminal},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    process,
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use clap::{Parser, ValueEnum};
-use format::{OutputConfig, RecordFormatter};
+use clap::{
+    parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
 use hickory_client::{
-    client::{Client, SyncClient},
-    error::{ClientError, ClientResult},
-    op::DnsResponse,
+    op::{DnsResponse, ResponseCode},
     rr::{DNSClass, Name, Record, RecordType},
-    tcp::TcpClientConnection,
-    udp::UdpClientConnection,
 };
+#[cfg(feature = "dnssec")]
+use resoy::trust_anchors;
+use resoy::{
+    aggregate::{Aggregate, AggregateBy},
+    asn, audit, batch,
+    client::{query_failure, Session, DEADLINE_GRACE},
+    clock::SystemClock,
+    compare,
+    concurrency::{self, Aimd, Concurrency, Limiter},
+    dangling, dnsperf, doctor, dual_stack, env,
+    error::DEADLINE_EXIT_CODE,
+    expect,
+    explain::{self, Outcome},
+    filtering, follow,
+    format::{self, GroupKey, OutputConfig, OutputFormat, RecordFormatter, TableFormatter},
+    graph, hints,
+    lookup::Lookup,
+    mock, outln,
+    output::{self, Flush},
+    parse::{self, invalid_name, parse_domain_name, parse_duration},
+    pcap, pins, policy, probe, proxy, readiness, reputation, resolv_conf,
+    resolve::{self, response_codes},
+    rollover, schema, suggest, survey, trace, types, validate,
+    verbosity::{self, Verbosity},
+    via, watch, widerow, AppError, ConnectionType, DnsClient, QueryPolicy, ResolveOptions,
+};
+#[cfg(feature = "doh")]
+use resoy::{rdap, update};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 /// Simple dns resolve tool
-#[derive(Parser)]
-#[command(version, about)]
+#[derive(Parser, Serialize, Deserialize)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    after_help = examples::after_help()
+)]
 struct Cli {
-    /// domain name to resolve
-    name: String,
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+
+    /// domain name to resolve, or an IP address to look up the PTR records of
+    #[arg(required_unless_present_any = Cli::standalone_args())]
+    name: Option<String>,
+
+    /// domain name to resolve, may be repeated or separated by comma, the records of every name
+    /// being printed under a header, and every positional argument being a record type
+    #[arg(
+        short = 'n',
+        long = "name",
+        value_name = "NAME",
+        value_delimiter = ',',
+        conflicts_with_all = ["pin_file", "survey", "from_manifest"]
+    )]
+    names: Vec<String>,
+
+    /// read the names to resolve from the file, one per line, or from stdin with -, the
+    /// records of each being printed as soon as it is resolved
+    ///
+    /// Blank lines and everything after a # are ignored, and every positional argument is a
+    /// record type. The records are printed as text, as short with the name in front of the
+    /// data, or as json with one object per name and line. Names that cannot be parsed or
+    /// resolved are reported on stderr with their line while the others are still resolved.
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["names", "pin_file", "survey", "from_manifest"]
+    )]
+    file: Option<PathBuf>,
+
+    /// read --file as a dnsperf query file, a name and a record type per line, every line
+    /// being asked for its own type rather than those of the positional arguments
+    #[arg(long, requires = "file")]
+    import_dnsperf: bool,
+
+    /// resolve up to this many names of --file at the same time, or with auto as many as the
+    /// server answers without timing out or failing with SERVFAIL
+    ///
+    /// auto starts with 4 names and doubles them until too many fail, then adds one at a time
+    /// and halves them whenever too many fail, printing every change with --verbose and the
+    /// last and highest number of names with the rate at the end.
+    #[arg(long, value_name = "N", default_value_t = Concurrency::Fixed(batch::DEFAULT_CONCURRENCY))]
+    concurrency: Concurrency,
 
-    /// record types to check separated by space
-    #[arg(default_values_t=vec![String::from("A")], value_delimiter = ' ', num_args=1..)]
+    /// never resolve more names of --file at the same time than this, auto or not
+    #[arg(long, value_name = "N", default_value_t = concurrency::DEFAULT_MAX)]
+    concurrency_max: usize,
+
+    /// after the names of --file, print how many of them got every distinct answer, per record
+    /// type and from the most common down, as a last line with --json
+    #[arg(long, requires = "file")]
+    aggregate: bool,
+
+    /// count the answers of --aggregate by their data, by the autonomous system of their
+    /// addresses, or by the registrable domain of the names they point at, e.g. of CNAMEs
+    #[arg(long, value_name = "BY", value_enum, default_value_t = AggregateBy::Data, requires = "aggregate")]
+    aggregate_by: AggregateBy,
+
+    /// write the table of --aggregate into this file too, as json when it ends with .json and
+    /// as csv otherwise
+    #[arg(long, value_name = "FILE", requires = "aggregate")]
+    aggregate_output: Option<PathBuf>,
+
+    /// record types to check separated by space, all for the common ones [default: A, or PTR for
+    /// addresses]
+    #[arg(
+        value_delimiter = ' ',
+        num_args=1..,
+        value_parser = types::RecordTypeParser::new(true),
+        hide_possible_values = true
+    )]
     record_types: Vec<String>,
 
-    /// dns server to use
-    #[arg(short, long, default_value_t = String::from("1.1.1.1:53"))]
+    /// class of the queries, e.g. CH for version.bind TXT
+    #[arg(long, value_name = "CLASS", default_value_t = String::from("IN"))]
+    class: String,
+
+    /// dns server to use, an address or a hostname with port 53 by default, 853 with tls,
+    /// link-local ones with their interface as in [fe80::1%eth0]:53, the first name server of
+    /// the system by default
+    ///
+    /// May be repeated or separated by comma to ask every server at the same time, the records
+    /// of each being printed under its address, see --diff. A positional argument starting
+    /// with @, as in `resoy example.com @8.8.8.8 MX`, gives the server like dig does, --server
+    /// winning over it when both are given.
+    #[arg(
+        short = 's',
+        long = "server",
+        id = "server",
+        value_name = "SERVER",
+        value_delimiter = ','
+    )]
+    servers: Vec<String>,
+
+    /// the first of the servers, that every query goes to unless several are given
+    #[arg(skip)]
     server: String,
 
-    /// disable ansi-colored output
+    /// after the records of several servers, print those that only some of them answered with
+    #[arg(long, default_value_t = false)]
+    diff: bool,
+
+    /// with several servers, or those of the system when none is given, ask only the first
+    /// that answers instead of comparing them, those that failed or were slow lately last
+    ///
+    /// Every server tried is remembered for 15 minutes in resoy/servers.json under the cache
+    /// directory, which resoy servers --status prints.
+    #[arg(long, conflicts_with = "diff")]
+    failover: bool,
+
+    /// neither read nor write the state of the servers of --failover, trying them in the
+    /// order they were given
+    #[arg(long, requires = "failover")]
+    no_state: bool,
+
+    /// local address the udp and tcp queries leave from, of the family of the server
+    #[arg(long, value_name = "IP")]
+    source: Option<IpAddr>,
+
+    /// local port the udp and tcp queries leave from, a random one by default
+    #[arg(long, value_name = "PORT")]
+    source_port: Option<u16>,
+
+    /// print which color stands for which of the printed record types
+    #[arg(long, default_value_t = false)]
+    legend: bool,
+
+    /// disable ansi-colored output, whatever --color says
     #[arg(long, default_value_t = false)]
     no_ansi: bool,
 
+    /// when to color the text output: auto colors it when stdout is a terminal and the
+    /// NO_COLOR environment variable is unset or empty, always also when piping into less -R
+    #[arg(long, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// how to print internationalized names in the text and short output: auto prints them in Unicode
+    /// when stdout is a terminal and in their xn-- form otherwise
+    #[arg(long, value_name = "FORM", default_value_t = IdnOutput::Auto)]
+    idn_out: IdnOutput,
+
+    /// print the strings of TXT records of the text, short, csv and tsv output as one, e.g. for
+    /// DKIM keys
+    #[arg(long, default_value_t = false)]
+    txt_concat: bool,
+
     /// do not format ttl
     #[arg(long, default_value_t = false)]
     seconds: bool,
 
+    /// print every ttl in the largest unit dividing it, e.g. 2h or 90m, as --mock-answers,
+    /// pin files and --expect read them
+    #[arg(long, default_value_t = false, conflicts_with = "seconds")]
+    ttl_units: bool,
+
     // Connection type
     #[arg(long, short, default_value_t=ConnectionType::Udp)]
     connection: ConnectionType,
+
+    /// name to verify the certificate of a tls server against, by default its address
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "NAME")]
+    tls_hostname: Option<String>,
+
+    /// do not verify the certificate of a tls server
+    #[cfg(feature = "tls")]
+    #[arg(long, default_value_t = false)]
+    insecure: bool,
+
+    /// annotate addresses with the autonomous system announcing them, in an asn object of
+    /// the records of --json
+    #[arg(long, default_value_t = false)]
+    asn: bool,
+
+    /// also query from this host over ssh, e.g. ssh://user@host, may be repeated
+    #[arg(long, value_name = "URL")]
+    via: Vec<String>,
+
+    /// print how the queries of --via are run, and how many queries each feature sent
+    #[arg(long, default_value_t = false)]
+    verbose: bool,
+
+    /// do not annotate addresses that look like the answers of a filtering resolver
+    #[arg(long, default_value_t = false, conflicts_with = "check_filtering")]
+    no_filtering_detection: bool,
+
+    /// query canary names to tell whether the server filters answers
+    #[arg(long, default_value_t = false)]
+    check_filtering: bool,
+
+    /// name that must resolve normally, for --check-filtering
+    #[arg(long, value_name = "NAME", default_value_t = String::from("example.com"))]
+    benign_canary: String,
+
+    /// names that filtering resolvers block separated by comma, for --check-filtering
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        default_values_t = [String::from("malware.testcategory.com"), String::from("internetbadguys.com")]
+    )]
+    blocked_canaries: Vec<String>,
+
+    /// connect to the targets of SRV and HTTPS answers and fail when none is reachable
+    #[arg(long, default_value_t = false)]
+    probe_targets: bool,
+
+    /// tell which view of a split horizon the server serves, from the expected answers in the
+    /// file
+    #[arg(long, value_name = "FILE")]
+    pin_file: Option<PathBuf>,
+
+    /// ask the server this many times over UDP for the identity of its anycast instance
+    #[arg(long, value_name = "QUERIES", conflicts_with_all = ["name", "pin_file"])]
+    survey: Option<usize>,
+
+    /// end the survey once this many queries in a row found no new instance
+    #[arg(long, value_name = "QUERIES", requires = "survey")]
+    survey_until_stable: Option<usize>,
+
+    /// report what will bite after signing the zone of the name, from a zone transfer
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "plain")]
+    dnssec_readiness: Option<readiness::ReadinessFormat>,
+
+    /// read the zone for --dnssec-readiness from this file instead of transferring it
+    #[arg(long, value_name = "FILE", requires = "dnssec_readiness")]
+    zone_file: Option<PathBuf>,
+
+    /// transfer the zone of the name with AXFR and print all its records, always over tcp
+    ///
+    /// Only udp and tcp servers can transfer zones, and most only do for their secondaries.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["trace", "dnssec_readiness", "rollover_status"])]
+    axfr: bool,
+
+    /// resolve the name iteratively from the root servers down, like dig +trace, printing
+    /// every delegation with the server that answered it
+    ///
+    /// Every name server is asked over UDP, or over TCP with --connection tcp. The server of
+    /// --server is only asked for the addresses of name servers that come without glue.
+    #[arg(long, default_value_t = false)]
+    trace: bool,
+
+    /// start --trace at this server instead of the root servers, e.g. the root of a private
+    /// namespace
+    #[arg(long, value_name = "ADDR", requires = "trace")]
+    trace_from: Option<SocketAddr>,
+
+    /// print the time of every step of --trace, and what took the most of the total
+    #[arg(long, default_value_t = false, requires = "trace")]
+    timing: bool,
+
+    /// ask every name server of every zone of --trace, printing the time of each and the
+    /// spread between the fastest and the slowest
+    #[arg(long, default_value_t = false, requires = "trace")]
+    trace_all_ns: bool,
+
+    /// tell which stage the key rollovers of the zone of the name are in
+    #[arg(long, default_value_t = false)]
+    rollover_status: bool,
+
+    /// tag of the key being introduced, for --rollover-status
+    #[arg(long, value_name = "TAG", requires = "rollover_status")]
+    new_key: Option<u16>,
+
+    /// ask again every this many seconds until Ctrl-C, e.g. 30 or 5m, printing the records of
+    /// every round under a timestamp, with those that changed since the round before
+    /// highlighted
+    ///
+    /// Records are compared without their TTLs. A query that fails is reported in its round
+    /// and the next one connects to the server again.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_duration,
+        conflicts_with_all = ["names", "file", "json", "trace", "axfr"]
+    )]
+    watch: Option<Duration>,
+
+    /// end --watch with status 0 as soon as the records of a type differ from those first seen
+    #[arg(long, default_value_t = false, requires = "watch")]
+    until_changed: bool,
+
+    /// look up the registration of the domain over RDAP after the answers, an rdap object of
+    /// --json
+    #[cfg(feature = "doh")]
+    #[arg(long, default_value_t = false)]
+    rdap: bool,
+
+    /// output format
+    #[arg(long, short, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// same as --output json
+    #[arg(long, default_value_t = false, conflicts_with = "output")]
+    json: bool,
+
+    /// same as --output short, like dig +short
+    #[arg(long, default_value_t = false, conflicts_with_all = ["output", "json"])]
+    short: bool,
+
+    /// when the output is written out, line by line on a terminal, record by record for
+    /// --watch and --file into a pipe, and by blocks of 64 KiB for the others by default
+    #[arg(long, value_enum, value_name = "WHEN")]
+    flush: Option<Flush>,
+
+    /// lay out the line of every record of the text output with this template, e.g.
+    /// "{name}\t{type}\t{ttl}\t{data}"
+    ///
+    /// The placeholders are {name}, {type}, {class}, {ttl} in seconds, {ttl_human} as in the
+    /// text output, {data}, {server} and {color} and {reset}, which color what lies between
+    /// them like the type of the record when colors are enabled. {{ and }} stand for braces, \t,
+    /// \n and \\ for a tab, a line break and a backslash. Only the lines of the records are
+    /// printed, without the footer.
+    #[arg(
+        long = "format",
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["output", "json", "short"]
+    )]
+    template: Option<String>,
+
+    /// prefix of the variables of --output env
+    ///
+    /// Every record of the answers becomes PREFIX_TYPE_INDEX=VALUE, indexes counting from 0 per
+    /// type in the order of the answers, and PREFIX_TYPE_COUNT holds how many there are. The
+    /// count of every queried type is printed, 0 when it has no records. Records with several
+    /// fields get one variable per field instead: MX as _PREF and _HOST, SRV as _PRIORITY,
+    /// _WEIGHT, _PORT and _TARGET, SOA as _MNAME, _RNAME, _SERIAL, _REFRESH, _RETRY, _EXPIRE
+    /// and _MINIMUM, CAA as _FLAGS, _TAG and _VALUE. The strings of a TXT record are joined.
+    /// Characters of type names other than letters and digits become _, and types without a
+    /// name are TYPE followed by their number. With several --name, the variables of each get
+    /// PREFIX_N as their prefix, N counting the names from 0, PREFIX_N_NAME holds the name and
+    /// PREFIX_COUNT how many there are.
+    ///
+    /// Values of letters, digits and . _ : / @ % + , - only are printed bare. Any other value
+    /// is put in single quotes, within which a shell expands nothing, a ' in it being written
+    /// as '\''. NUL bytes, which shell variables cannot hold, become U+FFFD.
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        default_value_t = String::from("DNS"),
+        value_parser = env::parse_prefix
+    )]
+    env_prefix: String,
+
+    /// group the records under a header per name or per type
+    #[arg(long, value_name = "KEY")]
+    group_by: Option<GroupBy>,
+
+    /// sort the records by type, name, ttl or data, descending with a - in front, e.g.
+    /// --sort=-ttl, instead of printing them as received
+    ///
+    /// Addresses are sorted by their value, MX records by preference and SRV records by
+    /// priority, weight and port. Records that compare equal keep the order they came in.
+    #[arg(
+        long,
+        value_name = "KEY",
+        allow_hyphen_values = true,
+        conflicts_with = "follow"
+    )]
+    sort: Option<String>,
+
+    /// print every record of every response, even those that several responses share, e.g.
+    /// the CNAME of the name when asking for several types
+    #[arg(long, default_value_t = false)]
+    no_dedup: bool,
+
+    /// also print the authority and additional sections of the responses, like dig
+    #[arg(long, default_value_t = false)]
+    full: bool,
+
+    /// set the DO bit, so that signed zones come with their RRSIG records, and mark the
+    /// answers the server validated, with [ad] or "authenticated" in json
+    #[arg(long, default_value_t = false)]
+    dnssec: bool,
+
+    /// clear the RD bit, so that the server answers from its own zones only, see RA with --full
+    #[arg(long, default_value_t = false)]
+    norecurse: bool,
+
+    /// do not print the response code, server, round trip time and size of every response
+    /// after the records
+    #[arg(long, default_value_t = false)]
+    quiet: bool,
+
+    /// ask the server for its NSID, which tells the node of an anycast service that answered
+    #[arg(long, default_value_t = false)]
+    nsid: bool,
+
+    /// check the signatures of the answers from the root keys down, marking every record
+    /// secure, insecure or bogus, and exit with 6 when one is bogus
+    #[cfg(feature = "dnssec")]
+    #[arg(long, default_value_t = false)]
+    validate: bool,
+
+    /// start the checks of --validate from this key instead of the root keys, given as a DS
+    /// record like "example. 12345 13 2 <digest>" or a file of anchors, may be repeated
+    #[cfg(feature = "dnssec")]
+    #[arg(long, value_name = "DS|FILE", requires = "validate")]
+    trust_anchor: Vec<String>,
+
+    /// keys of --trust-anchor, read before any query is sent
+    #[cfg(feature = "dnssec")]
+    #[arg(skip)]
+    #[serde(skip)]
+    trust_anchors: Vec<validate::TrustAnchor>,
+
+    /// query A and AAAA together and print the addresses of both families per name
+    #[arg(long, default_value_t = false, conflicts_with = "record_types")]
+    dual_stack: bool,
+
+    /// print the CNAMEs of the answers in the order they lead from the name to the last
+    /// target, asking for the targets that the answers have no records of
+    ///
+    /// The text output marks every target of the chain with ->, and the records of --json
+    /// tell how many CNAMEs lead to them in "chain". CNAMEs that loop back to a name, or more
+    /// of them than --follow-depth, fail the query.
+    #[arg(long, default_value_t = false)]
+    follow: bool,
+
+    /// follow this many CNAMEs at most with --follow
+    #[arg(long, value_name = "N", default_value_t = follow::DEFAULT_MAX_DEPTH, requires = "follow")]
+    follow_depth: usize,
+
+    /// explain the answers in plain English
+    #[arg(long, default_value_t = false)]
+    explain: bool,
+
+    /// do not look up other record types when a name has no records of the requested type
+    #[arg(long, default_value_t = false)]
+    no_hints: bool,
+
+    /// suggest corrections when the name does not exist
+    #[arg(long, default_value_t = false)]
+    suggest: bool,
+
+    /// classify TXT records, e.g. verification tokens and high-entropy blobs
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "plain")]
+    audit_txt: Option<audit::AuditFormat>,
+
+    /// file with additional `label = regex` rules for --audit-txt
+    #[arg(long, value_name = "FILE", requires = "audit_txt")]
+    audit_rules: Option<PathBuf>,
+
+    /// flag records pointing at names that no longer exist or addresses that are not ours
+    #[arg(long, default_value_t = false)]
+    check_dangling: bool,
+
+    /// address blocks of ours separated by comma, for --check-dangling
+    #[arg(
+        long,
+        value_name = "CIDRS",
+        value_delimiter = ',',
+        requires = "check_dangling"
+    )]
+    our_cidrs: Vec<String>,
+
+    /// fail unless an answer matches, e.g. A=192.0.2.1, MX=10:mail.example.com or
+    /// soa.mname=ns1.example.com; may be repeated
+    #[arg(
+        long,
+        value_name = "TYPE=VALUE",
+        conflicts_with_all = ["file", "pin_file", "survey", "axfr", "trace", "watch"]
+    )]
+    expect: Vec<String>,
+
+    /// sleep a random duration up to this long before starting, e.g. 30s
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    splay: Option<Duration>,
+
+    /// wait this long between successive queries, e.g. 50ms
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    spacing: Option<Duration>,
+
+    /// wait this long for the response to a query, e.g. 2s
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_duration)]
+    timeout: Duration,
+
+    /// send a query that timed out this many more times before giving up on it
+    #[arg(long, value_name = "N", default_value_t = resolve::DEFAULT_RETRIES)]
+    retries: usize,
+
+    /// wait this long before sending a query again, twice as long before every later retry,
+    /// e.g. 200ms
+    #[arg(long, value_name = "DURATION", default_value = "0s", value_parser = parse_duration)]
+    backoff: Duration,
+
+    /// send a query again, as when it timed out, when its response comes with one of these
+    /// rcodes separated by comma, e.g. SERVFAIL,REFUSED
+    #[arg(
+        long,
+        value_name = "RCODES",
+        value_delimiter = ',',
+        value_parser = policy::parse_retry_code
+    )]
+    #[serde(with = "policy::retry_codes")]
+    retry_on: Vec<ResponseCode>,
+
+    /// show truncated UDP responses as they are instead of asking again over TCP
+    #[arg(long, default_value_t = false)]
+    no_tcp_fallback: bool,
+
+    /// give up on the whole run after this long, e.g. 20s
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    deadline: Option<Duration>,
+
+    /// fail on responses that cannot be parsed as a whole instead of salvaging their answers
+    #[arg(long, default_value_t = false)]
+    strict_parsing: bool,
+
+    /// abort the run once it has sent this many queries
+    #[arg(long, value_name = "N")]
+    max_queries: Option<usize>,
+
+    /// write a manifest of this run to the file
+    #[arg(long, value_name = "FILE")]
+    #[serde(skip)]
+    manifest: Option<PathBuf>,
+
+    /// write the names and addresses that the answers lead to as a Graphviz DOT graph
+    #[arg(long, value_name = "FILE")]
+    #[serde(skip)]
+    graph: Option<PathBuf>,
+
+    /// answer queries from the records of a TOML or JSON fixture instead of the network
+    #[arg(long, value_name = "FILE")]
+    mock_answers: Option<PathBuf>,
+
+    /// record every query and response of the run into a pcapng file
+    #[arg(long, value_name = "FILE")]
+    #[serde(skip)]
+    pcap: Option<PathBuf>,
+
+    /// print the effective configuration and where every value came from, then exit
+    #[arg(long, default_value_t = false)]
+    #[serde(skip)]
+    show_config: bool,
+
+    /// tell whether a newer release of resoy is out, then exit
+    #[cfg(feature = "doh")]
+    #[arg(long, default_value_t = false)]
+    #[serde(skip)]
+    check_update: bool,
+
+    /// skip the weekly check for a newer release that RESOY_UPDATE_CHECK=weekly enables
+    #[cfg(feature = "doh")]
+    #[arg(long, default_value_t = false, conflicts_with = "check_update")]
+    #[serde(skip)]
+    offline: bool,
+
+    /// repeat the run recorded in a manifest
+    #[arg(long, value_name = "FILE", conflicts_with = "name")]
+    #[serde(skip)]
+    from_manifest: Option<PathBuf>,
+
+    /// print the completion script of the shell, then exit
+    #[arg(long, value_name = "SHELL", hide = true)]
+    #[serde(skip)]
+    completions: Option<clap_complete::Shell>,
+
+    /// budget and questions shared by the clients of the run
+    #[arg(skip)]
+    #[serde(skip)]
+    session: Arc<Session>,
+
+    /// answers of --mock-answers, read before any query is sent
+    #[arg(skip)]
+    #[serde(skip)]
+    mock: Option<Arc<mock::Fixture>>,
+
+    /// whether the header of the csv and tsv output was printed already
+    #[arg(skip)]
+    #[serde(skip)]
+    table_header: Arc<AtomicBool>,
 }
 
 impl Cli {
-    fn parse_record_types(&self) -> Result<Vec<RecordType>, AppError> {
+    /// Options that make a run without a name
+    fn standalone_args() -> Vec<&'static str> {
+        let mut args = vec![
+            "names",
+            "file",
+            "from_manifest",
+            "show_config",
+            "pin_file",
+            "survey",
+            "completions",
+        ];
+        if cfg!(feature = "doh") {
+            args.push("check_update");
+        }
+        args
+    }
+
+    /// Sort the positional arguments into the name, the record types and the dig-style
+    /// `@server` arguments, which may come anywhere among them. `--server` wins over them.
+    fn take_positional(
+        &mut self,
+        matches: &ArgMatches,
+    ) {
+        let positional = self
+            .name
+            .take()
+            .into_iter()
+            .chain(self.record_types.drain(..));
+        let (dig_servers, mut positional) = parse::split_servers(positional.collect());
+        if !positional.is_empty() {
+            self.name = Some(positional.remove(0));
+        }
+        self.record_types = positional;
+        let dig_server = !dig_servers.is_empty()
+            && matches.value_source("server") != Some(ValueSource::CommandLine);
+        if dig_server {
+            self.servers = dig_servers;
+        }
+        self.server = self.servers.first().cloned().unwrap_or_default();
+        // With --name, the first positional argument is no name but a record type as well
+        if !self.names.is_empty() || self.file.is_some() {
+            if let Some(record_type) = self.name.take() {
+                self.record_types.insert(0, record_type);
+            }
+        }
+    }
+
+    /// Record types in the order they were given, without repetitions, `all` and `common`
+    /// standing for the common types
+    fn parse_record_types(
+        &self,
+        name: Option<&str>,
+    ) -> Result<Vec<RecordType>, AppError> {
+        parse::parse_record_types(&self.record_types, name)
+    }
+
+    fn parse_sort(&self) -> Result<Option<format::SortKey>, AppError> {
+        let Some(key) = &self.sort else {
+            return Ok(None);
+        };
+        key.parse()
+            .map(Some)
+            .map_err(|_| AppError::UnknownSortKey(key.clone()))
+    }
+
+    fn parse_class(&self) -> Result<DNSClass, AppError> {
+        DNSClass::from_str(&self.class.to_uppercase())
+            .map_err(|_| AppError::UnknownClass(self.class.clone()))
+    }
+
+    /// Whether `all` or `common` is among the record types
+    fn asks_common_types(&self) -> bool {
         self.record_types
             .iter()
-            .collect::<HashSet<_>>()
-            .into_iter()
+            .any(|value| value.eq_ignore_ascii_case("all") || value.eq_ignore_ascii_case("common"))
+    }
+
+    fn parse_our_cidrs(&self) -> Result<Vec<dangling::Cidr>, AppError> {
+        self.our_cidrs
+            .iter()
+            .map(|value| {
+                dangling::Cidr::from_str(value).map_err(|_| AppError::InvalidCidr(value.clone()))
+            })
+            .collect()
+    }
+
+    fn parse_expectations(&self) -> Result<Vec<expect::Expectation>, AppError> {
+        self.expect
+            .iter()
+            .map(|value| {
+                expect::Expectation::from_str(value)
+                    .map_err(|error| AppError::InvalidExpectation(value.clone(), error))
+            })
+            .collect()
+    }
+
+    fn parse_vantages(&self) -> Result<Vec<via::Vantage>, AppError> {
+        self.via
+            .iter()
             .map(|value| {
-                RecordType::from_str(value).map_err(|_| AppError::UnknownRecordType(value.clone()))
+                via::Vantage::from_str(value)
+                    .map_err(|error| AppError::InvalidVantage(value.clone(), error))
             })
             .collect()
     }
 
-    fn parse_domain_name(&self) -> Result<Name, AppError> {
-        Name::from_str(&self.name).map_err(|_| AppError::InvalidDomainName(self.name.clone()))
+    fn output_format(&self) -> OutputFormat {
+        match (self.json, self.short) {
+            (true, _) => OutputFormat::Json,
+            (_, true) => OutputFormat::Short,
+            _ => self.output,
+        }
+    }
+
+    /// Keys `--validate` starts from, the root keys unless others are given, and warnings
+    /// about the keys of the files of `--trust-anchor` left out
+    #[cfg(feature = "dnssec")]
+    fn parse_trust_anchors(&self) -> Result<(Vec<validate::TrustAnchor>, Vec<String>), AppError> {
+        if self.trust_anchor.is_empty() {
+            return Ok((validate::root_anchors(), Vec::new()));
+        }
+        let (mut anchors, mut warnings) = (Vec::new(), Vec::new());
+        for value in &self.trust_anchor {
+            let invalid = |error| AppError::InvalidTrustAnchor(value.clone(), error);
+            let path = Path::new(value);
+            if !path.is_file() {
+                anchors.push(validate::parse_trust_anchor(value).map_err(invalid)?);
+                continue;
+            }
+            let file = trust_anchors::read(path, SystemTime::now()).map_err(invalid)?;
+            anchors.extend(file.anchors);
+            warnings.extend(
+                file.warnings
+                    .into_iter()
+                    .map(|warning| format!("{}: {}", value, warning)),
+            );
+        }
+        Ok((anchors, warnings))
+    }
+
+    #[cfg(feature = "dnssec")]
+    fn validates(&self) -> bool {
+        self.validate
+    }
+
+    #[cfg(not(feature = "dnssec"))]
+    fn validates(&self) -> bool {
+        false
+    }
+
+    fn parse_template(&self) -> Result<Option<format::Template>, AppError> {
+        self.parse_template_of(&self.server)
+    }
+
+    /// The template of `--format`, with `server` for its `{server}`
+    fn parse_template_of(
+        &self,
+        server: &str,
+    ) -> Result<Option<format::Template>, AppError> {
+        let Some(template) = &self.template else {
+            return Ok(None);
+        };
+        format::Template::parse(template, server)
+            .map(Some)
+            .map_err(|error| AppError::InvalidTemplate(template.clone(), error))
+    }
+
+    /// How the clients of the run ask the server
+    fn resolve_options(&self) -> ResolveOptions {
+        let policy = QueryPolicy::default()
+            .with_attempts(self.retries.saturating_add(1))
+            .with_timeout(self.timeout)
+            .with_backoff(self.backoff)
+            .with_retry_on(&self.retry_on)
+            .with_tcp_fallback(!self.no_tcp_fallback);
+        let options = ResolveOptions::new(self.connection, &self.server)
+            .with_policy(policy)
+            .with_dnssec_ok(self.dnssec || self.validates())
+            .with_recursion_desired(!self.norecurse)
+            .with_nsid(self.nsid)
+            .with_source(self.source, self.source_port)
+            .with_strict_parsing(self.strict_parsing)
+            .with_mock(self.mock.clone())
+            .with_session(self.session.clone());
+        #[cfg(feature = "tls")]
+        let options = options.with_tls(self.tls_hostname.clone(), self.insecure);
+        options
     }
 
     fn parse_output_config(&self) -> OutputConfig {
-        OutputConfig::new(!self.seconds, !self.no_ansi)
+        self.parse_output_config_of(&self.server)
+    }
+
+    /// The output configuration of the records of `server`, among those of `--server`
+    fn parse_output_config_of(
+        &self,
+        server: &str,
+    ) -> OutputConfig {
+        let ansi = self.ansi(stdout().is_terminal(), std::env::var_os("NO_COLOR"));
+        // Validated before any query is sent
+        let template = self.parse_template_of(server).unwrap_or_default();
+        OutputConfig::new(!self.seconds, ansi)
+            .with_ttl_units(self.ttl_units)
+            .with_template(template)
+            .with_ascii_names(self.ascii_names())
+            .with_txt_concat(self.txt_concat)
+            .with_table_header(self.table_header.clone())
+    }
+
+    /// Whether the records get colors, `--no-ansi` winning over `--color`, which wins over
+    /// NO_COLOR and the terminal
+    fn ansi(
+        &self,
+        terminal: bool,
+        no_color: Option<OsString>,
+    ) -> bool {
+        // Machine formats never carry escape sequences
+        !self.no_ansi
+            && matches!(self.output_format(), OutputFormat::Text)
+            && self.color.enabled(terminal, no_color)
     }
+
+    /// Whether to print names in their xn-- form
+    fn ascii_names(&self) -> bool {
+        match self.idn_out {
+            IdnOutput::Auto => !stdout().is_terminal(),
+            IdnOutput::Unicode => false,
+            IdnOutput::Ascii => true,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// list the record types resoy can query
+    Types(types::TypesArgs),
+    /// print a cookbook of example invocations
+    Examples,
+    /// check that resoy can reach a dns server from here
+    Doctor(doctor::DoctorArgs),
+    /// accept plain dns queries locally and forward them to a server, until interrupted
+    Proxy(Box<proxy::ProxyArgs>),
+    /// print the json schemas of the json outputs
+    Schema(schema::SchemaArgs),
+    /// print the queries of a --file input as a dnsperf query file, deduplicated
+    ExportQueries(dnsperf::ExportArgs),
+    /// print what runs of --failover remember of the servers they asked
+    Servers(reputation::ServersArgs),
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+enum GroupBy {
+    Name,
+    Type,
 }
 
-#[derive(ValueEnum, Clone, Debug, Copy)]
+#[derive(ValueEnum, Clone, Debug, Copy, Serialize, Deserialize)]
 #[clap(rename_all = "kebab_case")]
-enum ConnectionType {
-    Udp,
-    Tcp,
+#[serde(rename_all = "kebab-case")]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
 }
 
-impl Display for ConnectionType {
+impl ColorChoice {
+    /// Whether to color the output, written to a terminal or not, with `no_color` the value
+    /// of NO_COLOR, which turns colors off unless it is empty, see https://no-color.org
+    fn enabled(
+        self,
+        terminal: bool,
+        no_color: Option<OsString>,
+    ) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => terminal && no_color.is_none_or(|value| value.is_empty()),
+        }
+    }
+}
+
+impl Display for ColorChoice {
     fn fmt(
         &self,
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
         let s = match self {
-            Self::Udp => "udp",
-            Self::Tcp => "tcp",
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
         };
         write!(f, "{}", s)?;
         Ok(())
     }
 }
 
-enum AppError {
-    InvalidDomainName(String),
-    UnknownRecordType(String),
-    InvalidDnsServer(String),
-    DNSServerUnreachable(ConnectionType, String),
-    QueryError(ClientError),
+#[derive(ValueEnum, Clone, Debug, Copy, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+enum IdnOutput {
+    Auto,
+    /// the U-labels, e.g. münchen.de
+    Unicode,
+    /// the A-labels, e.g. xn--mnchen-3ya.de
+    Ascii,
 }
 
-impl Debug for AppError {
+impl Display for IdnOutput {
     fn fmt(
         &self,
         f: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
-        match self {
-            Self::InvalidDomainName(domain) => write!(f, "Invalid name: {:?}", domain),
-            Self::UnknownRecordType(record_type) => {
-                write!(f, "Cannot parse record type: {:?}", record_type)
+        let s = match self {
+            Self::Auto => "auto",
+            Self::Unicode => "unicode",
+            Self::Ascii => "ascii",
+        };
+        write!(f, "{}", s)?;
+        Ok(())
+    }
+}
+
+impl From<GroupBy> for GroupKey {
+    fn from(group_by: GroupBy) -> Self {
+        match group_by {
+            GroupBy::Name => Self::Name,
+            GroupBy::Type => Self::Type,
+        }
+    }
+}
+
+fn main() -> Result<(), AppError> {
+    // Whatever was printed is written out however the run ends, a panic included
+    let _flushed = output::FlushGuard;
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    if let Some(shell) = cli.completions {
+        completions::generate(shell, &mut stdout());
+        return Ok(());
+    }
+    cli.take_positional(&matches);
+
+    if let Some(command) = &cli.command {
+        return match command {
+            Command::Types(args) => args.run(),
+            Command::Examples => {
+                examples::run();
+                Ok(())
+            },
+            Command::Doctor(args) => args.run(),
+            Command::Proxy(args) => args.run(),
+            Command::Schema(args) => args.run(),
+            Command::ExportQueries(args) => args.run(),
+            Command::Servers(args) => args.run(),
+        };
+    }
+
+    #[cfg(feature = "doh")]
+    if cli.check_update {
+        update::check(cli.verbose);
+        return Ok(());
+    }
+
+    let streaming = cli.watch.is_some() || cli.file.is_some();
+    output::set(cli.flush.unwrap_or_else(|| Flush::default_for(streaming)));
+
+    let started = Instant::now();
+    let show_config = cli.show_config;
+    let mut recorded: Vec<String> = Vec::new();
+    if let Some(path) = &cli.from_manifest {
+        let manifest = manifest::Manifest::read(path)?;
+        recorded = manifest.recorded_keys(&cli);
+        if let Some(warning) = manifest.input_changed() {
+            eprintln!("WARNING: {}", warning);
+        }
+        cli = manifest.apply(cli)?;
+    }
+    // Once the deadline passes, the queries in flight time out and the others are refused, so
+    // that the run ends with what it printed. What no timeout cuts short, e.g. a read of a
+    // hung network filesystem, ends with the whole process a moment later.
+    if let Some(deadline) = cli.deadline {
+        cli.session.set_deadline(started, deadline);
+        thread::spawn(move || {
+            let end = started + deadline + DEADLINE_GRACE;
+            thread::sleep(end.saturating_duration_since(Instant::now()));
+            output::flush();
+            eprintln!("Error: {}", AppError::DeadlineExceeded(deadline));
+            process::exit(DEADLINE_EXIT_CODE);
+        });
+    }
+    // Older manifests record the first server only
+    if cli.servers.is_empty() && !cli.server.is_empty() {
+        cli.servers.push(cli.server.clone());
+    }
+
+    // Read only now, so that --help, --completions and the subcommands don't read it. The name
+    // servers of the system rarely speak anything but plain DNS.
+    let default_server = cli.servers.is_empty();
+    let plain = matches!(cli.connection, ConnectionType::Udp | ConnectionType::Tcp);
+    if default_server {
+        cli.server = match plain {
+            true => resolv_conf::default_server(),
+            false => String::from(resolv_conf::FALLBACK_SERVER),
+        };
+        cli.servers = vec![cli.server.clone()];
+        let system = resolv_conf::system_servers();
+        if cli.failover && plain && !system.is_empty() {
+            cli.servers = system;
+        }
+    }
+
+    if show_config {
+        config::EffectiveConfig::new(&cli, &matches, &recorded).print();
+        return Ok(());
+    }
+    cli.parse_template()?;
+
+    if let Some(path) = &cli.pcap {
+        pcap::start(path)?;
+    }
+    if let Some(path) = &cli.mock_answers {
+        cli.mock = Some(Arc::new(mock::Fixture::read(path)?));
+    }
+    #[cfg(feature = "dnssec")]
+    if cli.validate {
+        let (anchors, warnings) = cli.parse_trust_anchors()?;
+        for warning in warnings {
+            eprintln!("WARNING: trust anchor {}", warning);
+        }
+        cli.trust_anchors = anchors;
+    }
+    if (cli.source.is_some() || cli.source_port.is_some()) && !plain {
+        return Err(AppError::UnsupportedSource(cli.connection));
+    }
+    if let Some(max) = cli.max_queries {
+        cli.session.budget().limit(max);
+    }
+
+    if cli.verbose && default_server {
+        let source = match (plain, resolv_conf::default_server_source()) {
+            (true, Some(source)) => format!("the first name server of {}", source),
+            _ => String::from("the fallback server"),
+        };
+        eprintln!("Querying {}, {}", cli.server, source);
+    }
+    let started_at = SystemTime::now();
+    if let Some(bound) = cli.splay {
+        thread::sleep(splay(bound));
+    }
+    // The run fails like any other when no server of --failover answers
+    let mut failed_over = Ok(());
+    if cli.failover && cli.servers.len() > 1 {
+        let tried = cli.servers.len();
+        match fail_over(&cli) {
+            Ok(server) => {
+                cli.server = server;
+                cli.servers = vec![cli.server.clone()];
+                if cli.verbose {
+                    eprintln!(
+                        "Querying {}, the first of {} servers to answer",
+                        cli.server, tried
+                    );
+                }
             },
-            Self::InvalidDnsServer(host) => {
-                write!(f, "Cannot parse DNS server address: {:?}", host)
+            Err(error) => failed_over = Err(error),
+        }
+    }
+    let result = failed_over.and_then(|()| match (&cli.file, cli.names.is_empty()) {
+        _ if cli.servers.len() > 1 => resolve_servers(&cli),
+        (Some(path), _) => resolve_file(&cli, path),
+        (None, true) => resolve(&cli, cli.name.as_deref(), None, &cli.env_prefix),
+        (None, false) => resolve_names(&cli),
+    });
+    if cli.verbose {
+        eprintln!("{}", session_summary(&cli));
+    }
+    // Every query is refused once the budget is spent, whatever failed first
+    let result = match cli.session.stopped() {
+        Some(stopped) => Err(stopped),
+        None => result,
+    };
+    if let Some(path) = &cli.manifest {
+        manifest::Manifest::new(&cli, started_at, &result).write(path)?;
+    }
+    #[cfg(feature = "doh")]
+    if !cli.offline && cli.session.stopped().is_none() && update::passive_enabled() {
+        output::flush();
+        update::check_weekly(cli.verbose);
+    }
+    // Printed the way returning the error would, but with the exit status of the error
+    if let Err(error) = &result {
+        output::flush();
+        eprintln!("Error: {}", error);
+        process::exit(error.exit_code());
+    }
+    Ok(())
+}
+
+/// The queries the run sent, and the answers it shared and salvaged, as the summary of
+/// `--verbose` and the snapshots of SIGUSR2 print them
+fn session_summary(cli: &Cli) -> String {
+    let stopped = match cli.session.stopped() {
+        Some(AppError::DeadlineExceeded(_)) => "deadline exceeded: ",
+        Some(_) => "query budget exhausted: ",
+        None => "",
+    };
+    format!(
+        "{}{}, {} questions answered by the same one in flight, {} corrupt responses salvaged",
+        stopped,
+        cli.session.budget().summary(),
+        cli.session.questions().shared(),
+        cli.session.salvaged()
+    )
+}
+
+/// `records` in the order they are printed, without the duplicates that the responses to
+/// several types share, e.g. the CNAME of the name, unless --no-dedup, and sorted with --sort
+fn arranged<'r>(
+    cli: &Cli,
+    mut records: Vec<&'r Record>,
+) -> Vec<&'r Record> {
+    if !cli.no_dedup {
+        format::dedup_records(&mut records);
+    }
+    // Validated before any query is sent
+    format::sort_records(&mut records, cli.parse_sort().unwrap_or_default());
+    records
+}
+
+/// Random duration up to `bound`, so that runs started at the same moment spread out
+fn splay(bound: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    bound.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Resolve every name of `--name` in turn, the records of each under a header, or nested per
+/// name with `--json`.
+///
+/// A name that fails is reported and the others are still resolved, the run failing with the
+/// exit status of the first that failed.
+fn resolve_names(cli: &Cli) -> Result<(), AppError> {
+    let output_config = cli.parse_output_config();
+    let format = cli.output_format();
+    let mut nested: Vec<Value> = Vec::new();
+    let mut failed: Vec<AppError> = Vec::new();
+    for (index, name) in cli.names.iter().enumerate() {
+        if cli.session.stopped().is_some() {
+            break;
+        }
+        match format {
+            OutputFormat::Json => {},
+            // Every row already tells its name
+            OutputFormat::Widerow | OutputFormat::Csv | OutputFormat::Tsv => {},
+            OutputFormat::Env => outln!("# {}", name),
+            OutputFormat::Text | OutputFormat::Short => {
+                if index > 0 {
+                    outln!();
+                }
+                outln!("{}", output_config.format_header(name));
             },
-            Self::DNSServerUnreachable(connection_type, host) => {
-                write!(
-                    f,
-                    "Cannot establish {} connection with {:?}",
-                    connection_type, host
-                )
+        }
+        // Every name of several has variables of its own
+        let env_prefix = match cli.names.len() {
+            1 => cli.env_prefix.clone(),
+            _ => env::name_prefix(&cli.env_prefix, index),
+        };
+        if let (OutputFormat::Env, true) = (format, cli.names.len() > 1) {
+            env::print_name(&env_prefix, name);
+        }
+        let mut answers: Vec<Value> = Vec::new();
+        let json = matches!(format, OutputFormat::Json).then_some(&mut answers);
+        let result = resolve(cli, Some(name), json, &env_prefix);
+
+        let mut entry = json!({ "name": name });
+        if let Some(Value::Object(output)) = answers.pop() {
+            for (key, value) in output {
+                if key != "schema" {
+                    entry[key] = value;
+                }
+            }
+        }
+        if let Err(error) = result {
+            output::flush();
+            eprintln!("Error: {}", error);
+            entry["error"] = Value::String(error.to_string());
+            failed.push(error);
+        }
+        nested.push(entry);
+    }
+    if let OutputFormat::Json = format {
+        outln!(
+            "{}",
+            serde_json::to_string_pretty(&schema::names(nested)).unwrap_or_default()
+        );
+    }
+    if let (OutputFormat::Env, true) = (format, cli.names.len() > 1) {
+        env::print_count(&cli.env_prefix, cli.names.len());
+    }
+    match failed.first() {
+        Some(first) => Err(AppError::NamesFailed(
+            failed.len(),
+            cli.names.len(),
+            first.exit_code(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Ask every server of `--server` for the records of the name at the same time, and print
+/// those of each under its address, nested per server with `--json`, followed by the records
+/// that only some of them answered with for `--diff`.
+///
+/// A server that fails is reported and the others are still shown, the run failing with the
+/// exit status of the first that failed.
+fn resolve_servers(cli: &Cli) -> Result<(), AppError> {
+    let options = [
+        (!cli.names.is_empty(), "name"),
+        (cli.file.is_some(), "file"),
+        (cli.survey.is_some(), "survey"),
+        (cli.pin_file.is_some(), "pin-file"),
+        (cli.dnssec_readiness.is_some(), "dnssec-readiness"),
+        (cli.axfr, "axfr"),
+        (cli.rollover_status, "rollover-status"),
+        (cli.trace, "trace"),
+        (cli.watch.is_some(), "watch"),
+    ];
+    if let Some((_, option)) = options.iter().find(|(given, _)| *given) {
+        return Err(AppError::UnsupportedServersOption(option));
+    }
+    let format = cli.output_format();
+    if !matches!(
+        format,
+        OutputFormat::Text | OutputFormat::Short | OutputFormat::Json
+    ) {
+        return Err(AppError::UnsupportedServersOutput(format));
+    }
+    let argument = cli.name.as_deref();
+    let name = parse_domain_name(argument)?;
+    let record_types = match cli.dual_stack {
+        true => vec![RecordType::A, RecordType::AAAA],
+        false => cli.parse_record_types(argument)?,
+    };
+    let class = cli.parse_class()?;
+    cli.parse_sort()?;
+
+    // A server given twice is asked once
+    let mut servers: Vec<String> = Vec::with_capacity(cli.servers.len());
+    for server in &cli.servers {
+        if !servers.contains(server) {
+            servers.push(server.clone());
+        }
+    }
+    let options = cli
+        .resolve_options()
+        .with_class(class)
+        .with_feature("servers");
+    let answers = compare::query_all(&options, &servers, &name, &record_types);
+    let mut nested: Vec<Value> = Vec::new();
+    let mut failed: Vec<i32> = Vec::new();
+    let mut line = String::new();
+    for (index, server_answers) in answers.iter().enumerate() {
+        let server = &server_answers.server;
+        let output_config = cli.parse_output_config_of(server);
+        let records = arranged(cli, server_answers.records());
+        let mut entry = json!({ "server": server });
+        match format {
+            OutputFormat::Json => entry["records"] = format::json_records(&records),
+            OutputFormat::Short => {
+                if index > 0 {
+                    outln!();
+                }
+                outln!("{}", output_config.format_header(server));
+                for line in format::format_short(&records, cli.ascii_names(), cli.txt_concat) {
+                    outln!("{}", line)
+                }
             },
-            Self::QueryError(client_error) => {
-                write!(f, "Cannot send DNS query: {}", client_error)
+            _ => {
+                if index > 0 {
+                    outln!();
+                }
+                outln!("{}", output_config.format_header(server));
+                let widths = output_config.column_widths(&records);
+                for record in &records {
+                    line.clear();
+                    RecordFormatter::new(record, &output_config)
+                        .with_widths(widths)
+                        .write(&mut line);
+                    outln!("{}", line)
+                }
             },
         }
+
+        // As with a single server, the server only fails when no type got a response
+        output::flush();
+        let failures = &server_answers.failures;
+        let outcome = match (server_answers.responses.is_empty(), failures.split_first()) {
+            (true, Some((first, others))) => {
+                for error in others {
+                    eprintln!("Error: {}", error);
+                }
+                Err(first)
+            },
+            _ => {
+                for error in failures {
+                    eprintln!("Error: {}", error);
+                }
+                Ok(response_codes(&name, server, &server_answers.responses))
+            },
+        };
+        let error = match outcome {
+            Err(error) => Some((error.to_string(), error.exit_code())),
+            Ok(Err(error)) => Some((error.to_string(), error.exit_code())),
+            Ok(Ok(())) => None,
+        };
+        if let Some((error, exit_code)) = error {
+            eprintln!("Error: {}", error);
+            entry["error"] = Value::String(error);
+            failed.push(exit_code);
+        }
+        nested.push(entry);
+    }
+    let comparison = compare::compare(&answers);
+    match (format, cli.diff) {
+        (OutputFormat::Json, diff) => {
+            let output = schema::servers(nested, diff.then(|| comparison.json()));
+            outln!(
+                "{}",
+                serde_json::to_string_pretty(&output).unwrap_or_default()
+            )
+        },
+        (_, true) => {
+            outln!();
+            outln!("{}", comparison)
+        },
+        (_, false) => {},
+    }
+    match failed.first() {
+        Some(exit_code) => Err(AppError::ServersFailed(
+            failed.len(),
+            answers.len(),
+            *exit_code,
+        )),
+        None => Ok(()),
     }
 }
 
-fn main() -> Result<(), AppError> {
-    let cli = Cli::parse();
+/// The server of `--failover`: the first of the servers to answer the first question of the
+/// run, or the root NS one for `--file`, asking them in the order of `reputation::State::order`
+/// and recording how each did into the state
+fn fail_over(cli: &Cli) -> Result<String, AppError> {
+    let path = match cli.no_state {
+        true => None,
+        false => reputation::State::path(),
+    };
+    let now = SystemTime::now();
+    let state = path
+        .as_deref()
+        .map(reputation::State::read)
+        .unwrap_or_default();
+    let argument = cli
+        .name
+        .as_deref()
+        .or(cli.names.first().map(String::as_str));
+    let (name, record_type) = match argument {
+        Some(argument) => (
+            parse_domain_name(Some(argument))?,
+            cli.parse_record_types(Some(argument))?[0],
+        ),
+        None => (Name::root(), RecordType::NS),
+    };
+    let class = cli.parse_class()?;
+
+    let mut outcomes: Vec<(String, Option<Duration>)> = Vec::new();
+    let mut result = Err(AppError::ServersFailed(0, 0, 1));
+    for server in state.order(cli.connection, &cli.servers, now) {
+        let key = reputation::key(cli.connection, server);
+        let options = cli.resolve_options().with_server(server);
+        let started = Instant::now();
+        let response = DnsClient::new(&options).and_then(|client| {
+            client.query(&name, class, record_type).map_err(|error| {
+                query_failure(&name, record_type, &options, error, started.elapsed())
+            })
+        });
+        let error = match response {
+            Ok(response)
+                if !matches!(
+                    response.response_code(),
+                    ResponseCode::ServFail | ResponseCode::Refused
+                ) =>
+            {
+                outcomes.push((key, Some(started.elapsed())));
+                result = Ok(server.clone());
+                break;
+            },
+            Ok(response) => AppError::ResponseError(
+                format!("{:?}", response.response_code()).to_uppercase(),
+                server.clone(),
+                format!("{}/{}", name, record_type),
+            ),
+            Err(error) => error,
+        };
+        if cli.verbose {
+            eprintln!("{} failed: {}, trying the next server", server, error);
+        }
+        outcomes.push((key, None));
+        result = Err(error);
+    }
+    if let Some(path) = &path {
+        if let Err(error) = reputation::State::update(path, now, &outcomes) {
+            eprintln!(
+                "Cannot keep the state of the servers in {:?}: {}",
+                path, error
+            );
+        }
+    }
+    result
+}
 
-    let client = DnsClient::new(cli.connection, &cli.server)?;
-    let name = cli.parse_domain_name()?;
-    let record_types = cli.parse_record_types()?;
+/// Resolve the names of `--file` as they are read, see `batch::run`.
+///
+/// The run fails with the exit status of the first name that failed, as with `--name`.
+fn resolve_file(
+    cli: &Cli,
+    path: &Path,
+) -> Result<(), AppError> {
+    let format = cli.output_format();
+    if !matches!(
+        format,
+        OutputFormat::Text | OutputFormat::Short | OutputFormat::Json
+    ) {
+        return Err(AppError::UnsupportedBatchOutput(format));
+    }
+    let input = batch::open(path)?;
+    let client = DnsClient::new(&cli.resolve_options())?;
     let output_config = cli.parse_output_config();
 
-    let mut results: Vec<Record> = Vec::with_capacity(record_types.len());
+    let limiter = match cli.concurrency {
+        Concurrency::Fixed(concurrency) => Limiter::fixed(concurrency.min(cli.concurrency_max)),
+        Concurrency::Auto => {
+            Limiter::adaptive(Aimd::new(cli.concurrency_max, Arc::new(SystemClock)))
+        },
+    };
+    let aggregate = match cli.aggregate {
+        true => Some(BatchAggregate {
+            counts: Aggregate::new(cli.aggregate_by),
+            asn_client: match cli.aggregate_by {
+                AggregateBy::Asn => Some(DnsClient::new(
+                    &cli.resolve_options()
+                        .with_timeout(asn::ASN_TIMEOUT)
+                        .with_feature("asn"),
+                )?),
+                _ => None,
+            },
+        }),
+        false => None,
+    };
+    verbosity::set(Verbosity::of(cli.quiet, cli.verbose));
+    // Keys typed into the terminal are no names when the names come from a file
+    verbosity::listen(path != Path::new("-"));
+    let progress = batch::Progress::default();
+    let started = Instant::now();
+    let snapshot = || {
+        let mut snapshot = format!(
+            "; after {:.1}s: {}",
+            started.elapsed().as_secs_f64(),
+            progress.summary(started.elapsed())
+        );
+        if let Some((limit, _)) = limiter.limits() {
+            snapshot.push_str(&format!(", concurrency {}", limit));
+        }
+        if let Some(server) = client.server() {
+            snapshot.push_str(&format!("; {}", server));
+        }
+        snapshot.push_str(&format!(": {}", session_summary(cli)));
+        if let Some(connections) = client.connections() {
+            snapshot.push_str(&format!(
+                ", {} connections opened, {} queries over open ones",
+                connections.opened, connections.reused
+            ));
+        }
+        snapshot
+    };
+    let done = AtomicBool::new(false);
+    let (total, failed) = thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                thread::sleep(verbosity::POLL_INTERVAL);
+                verbosity::poll(snapshot);
+            }
+        });
+        let results = batch::run(input, &limiter, &progress, &cli.session, |argument| {
+            let mut output = String::new();
+            let result = resolve_batch_name(
+                cli,
+                &client,
+                &output_config,
+                aggregate.as_ref(),
+                argument,
+                &mut output,
+            );
+            if let (OutputFormat::Json, Err(error)) = (format, &result) {
+                let entry = json!({ "name": argument, "error": error.to_string() });
+                output = format!("{}\n", entry);
+            }
+            (output, result)
+        });
+        done.store(true, Ordering::Relaxed);
+        results
+    });
+    if let Some((last, highest)) = limiter.limits() {
+        eprintln!(
+            "; concurrency auto: {} names at a time at the end, {} at most, {:.0} names/s",
+            last,
+            highest,
+            progress.resolved() as f64 / started.elapsed().as_secs_f64().max(0.001)
+        );
+    }
+    if let Some(aggregate) = &aggregate {
+        match format {
+            OutputFormat::Json => outln!("{}", aggregate.counts.json()),
+            _ => outln!("{}", aggregate.counts.lines().join("\n")),
+        }
+        if let Some(path) = &cli.aggregate_output {
+            aggregate.counts.write(path)?;
+        }
+    }
+    match failed.first() {
+        Some(first) => Err(AppError::NamesFailed(
+            failed.len(),
+            total,
+            first.exit_code(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Counts of `--aggregate`, with the client looking up the origins of addresses to count them
+/// by their autonomous system
+struct BatchAggregate {
+    counts: Aggregate,
+    asn_client: Option<DnsClient>,
+}
 
+/// Resolve one name of `--file`, writing its records into `output` and counting its answers
+/// into `aggregate`
+fn resolve_batch_name(
+    cli: &Cli,
+    client: &DnsClient,
+    output_config: &OutputConfig,
+    aggregate: Option<&BatchAggregate>,
+    argument: &str,
+    output: &mut String,
+) -> Result<(), AppError> {
+    let (name, record_types) = match cli.import_dnsperf {
+        true => {
+            let query = dnsperf::parse_line(argument)?;
+            (query.name, vec![query.record_type])
+        },
+        false => (
+            parse_domain_name(Some(argument))?,
+            cli.parse_record_types(Some(argument))?,
+        ),
+    };
+    // The records of a dnsperf line are printed under its name, as those of a name
+    let argument = argument.split_whitespace().next().unwrap_or(argument);
+    let class = cli.parse_class()?;
+    let mut responses: Vec<(RecordType, DnsResponse)> = Vec::with_capacity(record_types.len());
+    let mut failures: Vec<AppError> = Vec::new();
     for record_type in record_types {
-        let response: DnsResponse = client
-            .query(&name, DNSClass::IN, record_type)
-            .map_err(AppError::QueryError)?;
-        let answers: &[Record] = response.answers();
-        results.extend_from_slice(answers);
+        let started = Instant::now();
+        match client.query(&name, class, record_type) {
+            Ok(response) => responses.push((record_type, response)),
+            Err(error) => failures.push(query_failure(
+                &name,
+                record_type,
+                client.options(),
+                error,
+                started.elapsed(),
+            )),
+        }
+    }
+    if responses.is_empty() && !failures.is_empty() {
+        return Err(failures.remove(0));
     }
 
-    for result in results {
-        println!("{}", RecordFormatter::new(result, &output_config).format())
+    let answers: Vec<&Record> = responses
+        .iter()
+        .flat_map(|(_, response)| response.answers())
+        .collect();
+    if let Some(aggregate) = aggregate {
+        let origins = match &aggregate.asn_client {
+            Some(asn_client) => asn::annotate(asn_client, &answers),
+            None => HashMap::new(),
+        };
+        aggregate.counts.add(&answers, &origins);
     }
-    Ok(())
+    match cli.output_format() {
+        OutputFormat::Json => {
+            let entry = schema::batch(argument, format::json_records(&answers));
+            output.push_str(&format!("{}\n", entry));
+        },
+        OutputFormat::Short => {
+            for line in format::format_short(&answers, cli.ascii_names(), cli.txt_concat) {
+                output.push_str(&format!("{} {}\n", argument, line));
+            }
+        },
+        _ => {
+            let mut line = String::new();
+            let widths = output_config.column_widths(&answers);
+            for record in answers {
+                line.clear();
+                RecordFormatter::new(record, output_config)
+                    .with_widths(widths)
+                    .write(&mut line);
+                output.push_str(&line);
+                output.push('\n');
+            }
+        },
+    }
+    for error in &failures {
+        eprintln!("Error: {}", error);
+    }
+    response_codes(&name, &cli.server, &responses)
 }
 
-enum DnsClient {
-    Tcp(SyncClient<TcpClientConnection>),
-    Udp(SyncClient<UdpClientConnection>),
-}
+/// Resolve `name` and print what the options ask for, pushing the answers of `--json` into
+/// `json` instead when given, and naming the variables of `--output env` with `env_prefix`
+fn resolve(
+    cli: &Cli,
+    name: Option<&str>,
+    json: Option<&mut Vec<Value>>,
+    env_prefix: &str,
+) -> Result<(), AppError> {
+    // Deliberate pauses between queries, which latencies don't include
+    let pause = |index: usize| {
+        if let (true, Some(spacing)) = (index > 0, cli.spacing) {
+            thread::sleep(spacing);
+        }
+    };
+    if let Some(queries) = cli.survey {
+        // Over UDP whatever the connection, as only new source ports reach other instances
+        let options = cli
+            .resolve_options()
+            .with_connection(ConnectionType::Udp)
+            .with_timeout(survey::SURVEY_TIMEOUT)
+            .with_feature("survey");
+        let survey_client = DnsClient::new(&options)?;
+        let survey = survey::survey(
+            &survey_client,
+            &cli.server,
+            queries,
+            cli.survey_until_stable,
+            pause,
+        );
+        outln!("{}", survey);
+        return Ok(());
+    }
+    let client = DnsClient::new(&cli.resolve_options())?;
 
-impl DnsClient {
-    fn new(
-        connection_type: ConnectionType,
-        raw_addr: &str,
-    ) -> Result<Self, AppError> {
-        let socket_addr = raw_addr
-            .parse()
-            .map_err(|_| AppError::InvalidDnsServer(raw_addr.to_owned()))?;
+    if let Some(path) = &cli.pin_file {
+        let pin_file = pins::PinFile::read(path)?;
+        let only = match name {
+            Some(_) => Some(parse_domain_name(name)?),
+            None => None,
+        };
+        let verdicts = pin_file.check(&client.with_feature("pins"), only.as_ref());
 
-        Ok(match connection_type {
-            ConnectionType::Udp => {
-                Self::Udp(SyncClient::new(
-                    UdpClientConnection::new(socket_addr).map_err(|_| {
-                        AppError::DNSServerUnreachable(connection_type, raw_addr.to_owned())
-                    })?,
-                ))
+        let mut summary: BTreeMap<String, usize> = BTreeMap::new();
+        outln!("Pins of {}:", path.display());
+        for verdict in &verdicts {
+            outln!("{}", verdict);
+            *summary.entry(verdict.label()).or_default() += 1;
+        }
+        let summary: Vec<String> = summary
+            .iter()
+            .map(|(label, count)| format!("{} {}", count, label))
+            .collect();
+        outln!("  {} pins: {}", verdicts.len(), summary.join(", "));
+        return Ok(());
+    }
+    let argument = name;
+    let name = parse_domain_name(argument)?;
+    if cli.verbose && name.to_ascii() != name.to_utf8() {
+        eprintln!("Querying {} as {}", name, name.to_ascii());
+    }
+    if let Some(format) = cli.dnssec_readiness {
+        let report = match &cli.zone_file {
+            Some(path) => readiness::Report::from_zone_file(&name, path)?,
+            None => {
+                let records = client
+                    .with_feature("readiness")
+                    .zone_transfer(&name)
+                    .map_err(|error| AppError::ZoneError(cli.server.clone(), error))?;
+                readiness::Report::from_transfer(&name, &cli.server, &records)
             },
-            ConnectionType::Tcp => {
-                Self::Tcp(SyncClient::new(
-                    TcpClientConnection::new(socket_addr).map_err(|_| {
-                        AppError::DNSServerUnreachable(connection_type, raw_addr.to_owned())
-                    })?,
-                ))
+        };
+        report.print(format);
+        return Ok(());
+    }
+    if cli.axfr {
+        let records = client
+            .with_feature("axfr")
+            .zone_transfer(&name)
+            .map_err(|error| AppError::ZoneError(cli.server.clone(), error))?;
+        let records: Vec<&Record> = records.iter().collect();
+        let output_config = cli.parse_output_config();
+        match cli.output_format() {
+            OutputFormat::Json => outln!(
+                "{}",
+                serde_json::to_string_pretty(&schema::transfer(format::json_records(&records)))
+                    .unwrap_or_default()
+            ),
+            OutputFormat::Short => {
+                for line in format::format_short(&records, cli.ascii_names(), cli.txt_concat) {
+                    outln!("{}", line)
+                }
+            },
+            _ => {
+                let mut line = String::new();
+                let widths = output_config.column_widths(&records);
+                for record in &records {
+                    line.clear();
+                    RecordFormatter::new(record, &output_config)
+                        .with_widths(widths)
+                        .write(&mut line);
+                    outln!("{}", line);
+                }
             },
-        })
+        }
+        let server = match client.server() {
+            Some(_) => cli.server.as_str(),
+            None => "the mock answers",
+        };
+        eprintln!("{} records transferred from {}", records.len(), server);
+        return Ok(());
+    }
+    if cli.rollover_status {
+        let rollover_client = DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(rollover::ROLLOVER_TIMEOUT)
+                .with_feature("rollover"),
+        )?;
+        let observation =
+            rollover::observe(&rollover_client, &name).map_err(AppError::RolloverError)?;
+        outln!("{}", rollover::Status::new(observation, cli.new_key));
+        return Ok(());
+    }
+    let mut record_types = match cli.dual_stack {
+        true => vec![RecordType::A, RecordType::AAAA],
+        false => cli.parse_record_types(argument)?,
+    };
+    if cli.trace {
+        let client = client.with_feature("trace");
+        let output_config = cli.parse_output_config();
+        let tracer = trace::Tracer::new(client.options(), &client, &output_config)
+            .with_timing(cli.timing)
+            .with_all_ns(cli.trace_all_ns);
+        for (index, record_type) in record_types.into_iter().enumerate() {
+            if index > 0 {
+                outln!();
+            }
+            tracer.trace(&name, record_type, cli.trace_from)?;
+        }
+        return Ok(());
+    }
+    let class = cli.parse_class()?;
+    cli.parse_sort()?;
+    if let Some(interval) = cli.watch {
+        let client = client.with_feature("watch");
+        let output_config = cli.parse_output_config();
+        let options = client.options().clone();
+        let watcher = watch::Watcher::new(
+            &options,
+            interval,
+            cli.until_changed,
+            matches!(cli.output_format(), OutputFormat::Short),
+            &output_config,
+        );
+        return watcher.watch(client, &name, class, &record_types);
+    }
+    let our_cidrs = cli.parse_our_cidrs()?;
+    let vantages = cli.parse_vantages()?;
+    let output_config = cli.parse_output_config();
+    let auditor = match cli.audit_txt {
+        Some(_) => Some(audit::Auditor::new(cli.audit_rules.as_deref())?),
+        None => None,
+    };
+    if auditor.is_some() && !record_types.contains(&RecordType::TXT) {
+        record_types.push(RecordType::TXT);
+    }
+    let expectations = cli.parse_expectations()?;
+    for expectation in &expectations {
+        if !record_types.contains(&expectation.record_type()) {
+            record_types.push(expectation.record_type());
+        }
     }
 
-    fn query(
-        &self,
-        name: &Name,
-        query_class: DNSClass,
-        query_type: RecordType,
-    ) -> ClientResult<DnsResponse> {
-        match self {
-            Self::Tcp(client) => client.query(name, query_class, query_type),
-            Self::Udp(client) => client.query(name, query_class, query_type),
+    if let OutputFormat::Widerow = cli.output_format() {
+        // Failed queries become rows as well, so no (name, type) goes missing
+        let rows: Vec<widerow::Row> = record_types
+            .into_iter()
+            .enumerate()
+            .map(|(index, record_type)| {
+                pause(index);
+                let timestamp = SystemTime::now();
+                let started = Instant::now();
+                let result = client.query(&name, class, record_type);
+                widerow::Row {
+                    timestamp,
+                    name: &name,
+                    record_type,
+                    result,
+                    server: &cli.server,
+                    latency: started.elapsed(),
+                }
+            })
+            .collect();
+        widerow::print(&rows, &output_config);
+        return Ok(());
+    }
+
+    // All types are asked for at once rather than one after another, unless --spacing asks
+    // for pauses between them
+    let resolution = resolve::query_types(&client, &name, class, &record_types, cli.spacing);
+    let resolution = match cli.follow {
+        true => resolution.follow(
+            &client.with_feature("follow"),
+            &name,
+            class,
+            cli.follow_depth,
+        ),
+        false => resolution,
+    };
+    let resolve::Resolution {
+        responses,
+        latencies,
+        mut failures,
+        elapsed,
+    } = resolution;
+    // Failures of some types are reported after the answers of the others
+    let report_failures = |failures: &[(RecordType, AppError)]| {
+        for (record_type, error) in failures {
+            match error {
+                AppError::QueryError(error) => eprintln!(
+                    "Error: Cannot send DNS query for {} {}: {}",
+                    name, record_type, error
+                ),
+                error => eprintln!("Error: {}", error),
+            }
+        }
+    };
+    if responses.is_empty() && !failures.is_empty() {
+        let (_, error) = failures.remove(0);
+        report_failures(&failures);
+        return Err(error);
+    }
+
+    let mut lookup = Lookup::new(&name, &responses)
+        .with_verdicts(validation(cli, &client, &responses)?)
+        .with_dnssec(cli.dnssec)
+        .with_nsid(cli.nsid)
+        .with_filtering(!cli.no_filtering_detection);
+    if cli.follow {
+        lookup = lookup.with_chains();
+    }
+    if cli.verbose {
+        lookup = lookup.with_empty_groups(&record_types);
+    }
+    // The origins annotate the lines of the records, or are a field of their JSON
+    if cli.asn && matches!(cli.output_format(), OutputFormat::Text | OutputFormat::Json) {
+        let options = cli
+            .resolve_options()
+            .with_timeout(asn::ASN_TIMEOUT)
+            .with_feature("asn");
+        if let Ok(asn_client) = DnsClient::new(&options) {
+            lookup = lookup.with_origins(&asn_client);
+        }
+    }
+
+    // Only the answers and the hints make it into the output, so that it stays valid JSON
+    if let OutputFormat::Json = cli.output_format() {
+        let answers = arranged(cli, lookup.answers());
+        let records = lookup.json_records(&answers, cli.full);
+        let mut output = schema::lookup(records, hints(cli, &name, &responses));
+        if let Some(group_by) = cli.group_by {
+            output["groups"] = lookup.json_groups(&answers, group_by.into());
+        }
+        if cli.dual_stack {
+            let pairs = dual_stack::pair(&name, &responses);
+            output["dual_stack"] = pairs.iter().map(dual_stack::Pair::to_json).collect();
+        }
+        let output = with_registration(cli, &client, &name, output);
+        match json {
+            Some(json) => json.push(output),
+            None => outln!(
+                "{}",
+                serde_json::to_string_pretty(&output).unwrap_or_default()
+            ),
+        }
+        report_failures(&failures);
+        // Bogus records and unmet expectations fail the run once the name exists
+        return lookup.validated(
+            response_codes(&name, &cli.server, &responses),
+            &expectations,
+        );
+    }
+
+    // Grouped per type in the order the types were asked for, as the responses are
+    if let OutputFormat::Short = cli.output_format() {
+        let answers = arranged(cli, lookup.answers());
+        for line in format::format_short(&answers, cli.ascii_names(), cli.txt_concat) {
+            outln!("{}", line)
+        }
+        report_failures(&failures);
+        return lookup.validated(
+            response_codes(&name, &cli.server, &responses),
+            &expectations,
+        );
+    }
+
+    if let OutputFormat::Csv | OutputFormat::Tsv = cli.output_format() {
+        let table = match cli.output_format() {
+            OutputFormat::Csv => TableFormatter::Csv,
+            _ => TableFormatter::Tsv,
+        };
+        let mut records: Vec<(&Record, format::Section)> = arranged(cli, lookup.answers())
+            .into_iter()
+            .map(|record| (record, format::Section::Answer))
+            .collect();
+        if cli.full {
+            for (_, response) in &responses {
+                records.extend(format::other_sections(response));
+            }
+        }
+        for row in table.format_sections(&records, &output_config) {
+            outln!("{}", row)
+        }
+        report_failures(&failures);
+        return lookup.validated(
+            response_codes(&name, &cli.server, &responses),
+            &expectations,
+        );
+    }
+
+    if let OutputFormat::Env = cli.output_format() {
+        let answers = arranged(cli, lookup.answers());
+        env::print(env_prefix, &record_types, &answers);
+        report_failures(&failures);
+        return lookup.validated(
+            response_codes(&name, &cli.server, &responses),
+            &expectations,
+        );
+    }
+
+    // The JSONL audit stream replaces the regular output
+    let print_records = !matches!(cli.audit_txt, Some(audit::AuditFormat::Jsonl));
+    if cli.dual_stack && print_records {
+        for pair in dual_stack::pair(&name, &responses) {
+            outln!("{}", pair)
+        }
+    }
+    let paired = |record_type: &RecordType| {
+        cli.dual_stack && matches!(record_type, RecordType::A | RecordType::AAAA)
+    };
+    let printed: Vec<&Record> = responses
+        .iter()
+        .filter(|(record_type, _)| print_records && !paired(record_type))
+        .flat_map(|(_, response)| response.answers())
+        .collect();
+    let printed = arranged(cli, printed);
+    lookup.print_records(&printed, cli.group_by.map(GroupKey::from), &output_config);
+    if cli.full && print_records {
+        lookup.print_sections(&output_config);
+    }
+    if cli.nsid && print_records {
+        lookup.print_nsids();
+    }
+    if cli.legend {
+        let mut legend_types: Vec<RecordType> = Vec::new();
+        for record in &printed {
+            if !legend_types.contains(&record.record_type()) {
+                legend_types.push(record.record_type());
+            }
+        }
+        if let Some(legend) = output_config.format_legend(&legend_types) {
+            outln!("{}", legend)
+        }
+    }
+
+    // The lines of a template are what scripts read, the footer would get in their way
+    if !cli.quiet && print_records && cli.template.is_none() {
+        let server = client
+            .server()
+            .unwrap_or_else(|| String::from("the mock answers"));
+        lookup.print_footer(&server, &latencies, elapsed);
+    }
+
+    report_failures(&failures);
+    let codes = lookup.validated(
+        response_codes(&name, &cli.server, &responses),
+        &expectations,
+    );
+
+    if !vantages.is_empty() {
+        let queried: Vec<RecordType> = responses
+            .iter()
+            .map(|(record_type, _)| *record_type)
+            .collect();
+        let request = via::Request {
+            server: &cli.server,
+            connection: cli.connection,
+            name: &name,
+            record_types: &queried,
+            output_config: &output_config,
+            seconds: cli.seconds,
+        };
+        // A vantage that fails is reported and skipped, the others are still shown
+        for report in via::query_all(&vantages, &request) {
+            if cli.verbose {
+                for step in &report.strategy {
+                    eprintln!("via {}: {}", report.vantage.label, step);
+                }
+            }
+            match report.lines {
+                Ok(lines) => {
+                    outln!();
+                    outln!("Answers via {}:", report.vantage.label);
+                    for line in lines {
+                        outln!("{}", line)
+                    }
+                },
+                Err(error) => eprintln!("via {}: {}", report.vantage.label, error),
+            }
         }
     }
+
+    if let Some(path) = &cli.graph {
+        let graph_client = DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(graph::GRAPH_TIMEOUT)
+                .with_feature("graph"),
+        )?;
+        graph::Graph::build(&graph_client, &cli.server, &name, &responses).write(path)?;
+    }
+
+    if cli.explain {
+        let outcomes: Vec<Outcome> = responses
+            .iter()
+            .map(|(record_type, response)| Outcome {
+                name: &name,
+                record_type: *record_type,
+                response_code: response.response_code(),
+                answers: response.answers(),
+            })
+            .collect();
+        outln!();
+        for line in explain::explain(&outcomes) {
+            outln!("{}", line)
+        }
+    }
+
+    if let (Some(auditor), Some(format)) = (&auditor, cli.audit_txt) {
+        let findings: Vec<audit::Finding> = responses
+            .iter()
+            .flat_map(|(_, response)| response.answers())
+            .filter_map(|record| auditor.audit(record))
+            .collect();
+        audit::report(&name, &findings, format);
+    }
+
+    if cli.check_dangling {
+        let check_client = DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(dangling::CHECK_TIMEOUT)
+                .with_feature("dangling"),
+        )?;
+        let findings = dangling::check(&check_client, &name, &responses, &our_cidrs);
+        outln!();
+        if findings.is_empty() {
+            outln!("No dangling records found for {}", name);
+        } else {
+            outln!("Dangling records of {}:", name);
+            for finding in findings {
+                outln!("{}", finding)
+            }
+        }
+    }
+
+    if cli.check_filtering {
+        let canary_client = DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(filtering::FILTERING_TIMEOUT)
+                .with_feature("filtering"),
+        )?;
+        let benign =
+            Name::from_str(&cli.benign_canary).map_err(|_| invalid_name(&cli.benign_canary))?;
+        let blocked = cli
+            .blocked_canaries
+            .iter()
+            .map(|name| Name::from_str(name).map_err(|_| invalid_name(name)))
+            .collect::<Result<Vec<Name>, AppError>>()?;
+        outln!();
+        outln!("Filtering check of {}:", cli.server);
+        outln!(
+            "{}",
+            filtering::Check::run(&canary_client, &benign, &blocked)
+        );
+    }
+
+    let mut outcome = codes;
+    if cli.probe_targets {
+        let probe_client = DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(probe::PROBE_TIMEOUT)
+                .with_feature("probe"),
+        )?;
+        let probes = probe::probe(&probe_client, &responses);
+        let reachable = probes.iter().filter(|probe| probe.is_reachable()).count();
+        outln!();
+        if probes.is_empty() {
+            outln!("No SRV or HTTPS targets to probe for {}", name);
+        } else {
+            outln!("Probes of {}:", name);
+            for probe in &probes {
+                outln!("{}", probe)
+            }
+            outln!(
+                "  reachable through {} of {} endpoints",
+                reachable,
+                probes.len()
+            );
+        }
+        if reachable == 0 {
+            outcome = Err(AppError::ServiceUnreachable(name.to_string()));
+        }
+    }
+
+    #[cfg(feature = "doh")]
+    if cli.rdap && cli.session.stopped().is_none() {
+        // RDAP is an addition to the answers, so its failures don't fail the run
+        match rdap::registration(&client.with_feature("rdap"), &name) {
+            Ok(registration) => {
+                outln!();
+                outln!("{}", registration);
+            },
+            Err(error) => eprintln!("rdap: {}", error),
+        }
+    }
+
+    let nxdomain = responses
+        .iter()
+        .find(|(_, response)| response.response_code() == ResponseCode::NXDomain);
+    if let (true, Some((record_type, _))) = (cli.suggest, nxdomain) {
+        if let Ok(suggest_client) = DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(suggest::SUGGEST_TIMEOUT)
+                .with_feature("suggest"),
+        ) {
+            let suggestions = suggest::find_suggestions(&suggest_client, &name, *record_type);
+            if !suggestions.is_empty() {
+                let suggestions: Vec<String> =
+                    suggestions.iter().map(ToString::to_string).collect();
+                eprintln!(
+                    "{} does not exist, did you mean {}?",
+                    name,
+                    suggestions.join(" or ")
+                )
+            }
+        }
+    }
+
+    for hint in hints(cli, &name, &responses) {
+        eprintln!("hint: {}", hint)
+    }
+    outcome
+}
+
+/// Hints about the answers for `name`, unless `--no-hints`: the other types of names that
+/// answered NODATA, and servers that answered ANY with HINFO only
+fn hints(
+    cli: &Cli,
+    name: &Name,
+    responses: &[(RecordType, DnsResponse)],
+) -> Vec<String> {
+    if cli.no_hints {
+        return Vec::new();
+    }
+    let mut found: Vec<String> = Vec::new();
+    // Some of the common types are missing for about every name, which is no news
+    if let (false, Ok(hint_client)) = (
+        cli.asks_common_types(),
+        DnsClient::new(
+            &cli.resolve_options()
+                .with_timeout(hints::HINT_TIMEOUT)
+                .with_retries(0)
+                .with_feature("hints"),
+        ),
+    ) {
+        let hints = hints::find_hints(&hint_client, name, responses);
+        found.extend(hints.iter().map(ToString::to_string));
+    }
+    if hints::is_minimal_any(responses) {
+        found.push(String::from(
+            "the server answered ANY with HINFO only, as RFC 8482 allows, query all for the \
+             common record types instead",
+        ))
+    }
+    found
+}
+
+/// `output` of `--json` with the `rdap` object of the registration of `name`, for `--rdap`
+#[cfg(feature = "doh")]
+fn with_registration(
+    cli: &Cli,
+    client: &DnsClient,
+    name: &Name,
+    mut output: Value,
+) -> Value {
+    if cli.rdap && cli.session.stopped().is_none() {
+        // RDAP is an addition to the answers, so its failures don't fail the run
+        match rdap::registration(&client.with_feature("rdap"), name) {
+            Ok(registration) => output["rdap"] = registration.to_json(),
+            Err(error) => eprintln!("rdap: {}", error),
+        }
+    }
+    output
+}
+
+#[cfg(not(feature = "doh"))]
+fn with_registration(
+    _cli: &Cli,
+    _client: &DnsClient,
+    _name: &Name,
+    output: Value,
+) -> Value {
+    output
+}
+
+/// Verdicts of `--validate` on the answers of `responses`
+#[cfg(feature = "dnssec")]
+fn validation<'r>(
+    cli: &Cli,
+    client: &DnsClient,
+    responses: &'r [(RecordType, DnsResponse)],
+) -> Result<validate::Verdicts<'r>, AppError> {
+    if !cli.validate {
+        return Ok(validate::Verdicts::default());
+    }
+    let answers: Vec<&Record> = responses
+        .iter()
+        .flat_map(|(_, response)| response.answers())
+        .collect();
+    let client = client.with_feature("validate");
+    Ok(validate::Validator::new(&client, cli.trust_anchors.clone()).validate(&answers))
+}
+
+#[cfg(not(feature = "dnssec"))]
+fn validation<'r>(
+    _cli: &Cli,
+    _client: &DnsClient,
+    _responses: &'r [(RecordType, DnsResponse)],
+) -> Result<validate::Verdicts<'r>, AppError> {
+    Ok(validate::Verdicts::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The options of `args`, with the positional arguments sorted out as `main` does
+    fn cli(args: &[&str]) -> Cli {
+        let matches = Cli::command()
+            .try_get_matches_from(std::iter::once("resoy").chain(args.iter().copied()))
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        cli.take_positional(&matches);
+        cli
+    }
+
+    #[test]
+    fn colors_follow_the_flags_then_no_color_then_the_terminal() {
+        let no_color = || Some(OsString::from("1"));
+        let empty = || Some(OsString::new());
+        // (arguments, terminal, NO_COLOR, colored)
+        let cases: [(&[&str], bool, Option<OsString>, bool); 12] = [
+            (&[], true, None, true),
+            (&[], false, None, false),
+            (&[], true, no_color(), false),
+            // An empty NO_COLOR doesn't count
+            (&[], true, empty(), true),
+            (&["--color", "always"], false, no_color(), true),
+            (&["--color", "never"], true, None, false),
+            (&["--color", "auto"], true, no_color(), false),
+            (&["--no-ansi"], true, None, false),
+            (&["--no-ansi", "--color", "always"], true, None, false),
+            (&["--color", "always", "--no-ansi"], false, None, false),
+            // Machine formats are never colored
+            (&["--color", "always", "--json"], true, None, false),
+            (&["--color", "always", "--short"], true, None, false),
+        ];
+        for (args, terminal, no_color, colored) in cases {
+            let args: Vec<&str> = ["example.com"].iter().chain(args).copied().collect();
+            assert_eq!(
+                cli(&args).ansi(terminal, no_color.clone()),
+                colored,
+                "{:?} on a terminal: {}, NO_COLOR: {:?}",
+                args,
+                terminal,
+                no_color
+            );
+        }
+    }
+
+    #[test]
+    fn manifests_win_over_the_color_flags() {
+        let manifest: manifest::Manifest = serde_json::from_value(serde_json::json!({
+            "resoy_version": "1.0.1",
+            "config": {"color": "never", "no_ansi": false},
+            "server": {"address": "192.0.2.53:53", "connection": "udp", "resolved": []},
+            "started_at": "2026-10-14T12:00:00Z",
+            "finished_at": "2026-10-14T12:00:01Z",
+            "error": null,
+        }))
+        .unwrap();
+        let recorded = manifest
+            .apply(cli(&["example.com", "--color", "always"]))
+            .unwrap();
+        assert!(!recorded.ansi(true, None));
+    }
+
+    #[test]
+    fn only_the_recorded_settings_that_change_the_run_come_from_the_manifest() {
+        let manifest: manifest::Manifest = serde_json::from_value(serde_json::json!({
+            "resoy_version": "1.0.1",
+            "config": {"color": "never", "no_ansi": false, "no_hints": true},
+            "server": {"address": "192.0.2.53:53", "connection": "udp", "resolved": []},
+            "started_at": "2026-10-14T12:00:00Z",
+            "finished_at": "2026-10-14T12:00:01Z",
+            "error": null,
+        }))
+        .unwrap();
+        let recorded = manifest.recorded_keys(&cli(&["example.com", "--color", "never"]));
+        assert_eq!(recorded, ["no_hints"]);
+    }
+
+    #[test]
+    fn manifests_record_every_server_and_the_digest_of_the_file() {
+        let path = std::env::temp_dir().join(format!("resoy-names-{}.txt", process::id()));
+        std::fs::write(&path, "example.com\n").unwrap();
+        let file = path.to_str().unwrap();
+        let cli = cli(&["--file", file, "--server", "192.0.2.53,[2001:db8::53]:5353"]);
+        let manifest = manifest::Manifest::new(&cli, SystemTime::now(), &Ok(()));
+        let manifest = serde_json::to_value(&manifest).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(manifest["servers"][0]["resolved"], json!(["192.0.2.53:53"]));
+        assert_eq!(
+            manifest["servers"][1]["resolved"],
+            json!(["[2001:db8::53]:5353"])
+        );
+        assert_eq!(
+            manifest["input"]["sha256"],
+            "391196688aa55d3321deffa736f8d103b4813470952b748e9c2c9deb17fa60f5"
+        );
+        // Replayed against all of them
+        assert_eq!(manifest["config"]["servers"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dig_style_servers_may_come_anywhere_among_the_name_and_types() {
+        for args in [
+            ["example.com", "@192.0.2.53", "MX"],
+            ["example.com", "MX", "@192.0.2.53"],
+            ["@192.0.2.53", "example.com", "MX"],
+        ] {
+            let cli = cli(&args);
+            assert_eq!(cli.name.as_deref(), Some("example.com"), "{:?}", args);
+            assert_eq!(cli.record_types, ["MX"], "{:?}", args);
+            assert_eq!(cli.servers, ["192.0.2.53"], "{:?}", args);
+            assert_eq!(cli.server, "192.0.2.53", "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn dig_style_servers_mix_with_flags() {
+        let cli = cli(&[
+            "example.com",
+            "-c",
+            "tcp",
+            "@dns.google",
+            "--timeout",
+            "2s",
+            "A",
+            "@[2001:4860:4860::8888]:53",
+            "AAAA",
+        ]);
+        assert!(matches!(cli.connection, ConnectionType::Tcp));
+        assert_eq!(cli.name.as_deref(), Some("example.com"));
+        assert_eq!(cli.record_types, ["A", "AAAA"]);
+        assert_eq!(cli.servers, ["dns.google", "[2001:4860:4860::8888]:53"]);
+        assert_eq!(cli.server, "dns.google");
+    }
+
+    #[test]
+    fn server_flags_win_over_dig_style_servers() {
+        let cli = cli(&["example.com", "@192.0.2.53", "-s", "198.51.100.53", "MX"]);
+        assert_eq!(cli.servers, ["198.51.100.53"]);
+        assert_eq!(cli.record_types, ["MX"]);
+    }
+
+    #[test]
+    fn dig_style_servers_leave_every_argument_a_type_with_names_given_apart() {
+        let cli = cli(&["--name", "example.com", "@192.0.2.53", "MX", "TXT"]);
+        assert_eq!(cli.name, None);
+        assert_eq!(cli.record_types, ["MX", "TXT"]);
+        assert_eq!(cli.servers, ["192.0.2.53"]);
+    }
 }
@@ -0,0 +1,184 @@
+use std::{
+    fs,
+    net::{SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use resoy::{format::format_timestamp, server_addr, sha256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{AppError, Cli, ConnectionType};
+
+/// Everything needed to reproduce a run, written by `--manifest`
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    resoy_version: String,
+    /// effective configuration of the run, as serialized from `Cli`
+    config: Value,
+    /// every server of the run, the only one of the older manifests being ignored
+    #[serde(default)]
+    servers: Vec<ServerInfo>,
+    /// file of `--file`, unless read from stdin
+    #[serde(default)]
+    input: Option<InputInfo>,
+    started_at: String,
+    finished_at: String,
+    /// `None` when the run succeeded
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ServerInfo {
+    address: String,
+    connection: ConnectionType,
+    /// addresses the server resolved to when the run started
+    resolved: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct InputInfo {
+    path: PathBuf,
+    /// SHA-256 of the file when the run ended, in hex
+    sha256: String,
+}
+
+impl InputInfo {
+    fn new(path: &Path) -> Option<Self> {
+        if path == Path::new("-") {
+            return None;
+        }
+        let content = fs::read(path).ok()?;
+        Some(Self {
+            path: path.to_owned(),
+            sha256: sha256::hex_digest(&content),
+        })
+    }
+}
+
+impl ServerInfo {
+    fn new(
+        address: &str,
+        connection: ConnectionType,
+    ) -> Self {
+        let resolved: Vec<SocketAddr> = match url::Url::parse(address) {
+            // The URLs of DNS-over-HTTPS, whose hosts are looked up by the HTTP client, while
+            // `host:port` parses as a URL of the scheme `host`
+            Ok(url) if url.has_host() => url
+                .host_str()
+                .map(|host| {
+                    (
+                        host.trim_matches(['[', ']']),
+                        url.port_or_known_default().unwrap_or(443),
+                    )
+                })
+                .and_then(|host_port| host_port.to_socket_addrs().ok())
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+            _ => server_addr::candidates(connection, address).unwrap_or_default(),
+        };
+        let resolved = resolved.iter().map(ToString::to_string).collect();
+
+        Self {
+            address: address.to_owned(),
+            connection,
+            resolved,
+        }
+    }
+}
+
+impl Manifest {
+    pub fn new(
+        cli: &Cli,
+        started_at: SystemTime,
+        result: &Result<(), AppError>,
+    ) -> Self {
+        Self {
+            resoy_version: env!("CARGO_PKG_VERSION").to_owned(),
+            config: serde_json::to_value(cli).unwrap(),
+            servers: cli
+                .servers
+                .iter()
+                .map(|server| ServerInfo::new(server, cli.connection))
+                .collect(),
+            input: cli.file.as_deref().and_then(InputInfo::new),
+            started_at: format_timestamp(started_at),
+            finished_at: format_timestamp(SystemTime::now()),
+            error: result.as_ref().err().map(|error| error.to_string()),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, AppError> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| AppError::ManifestError(path.to_owned(), error.to_string()))?;
+        serde_json::from_str(&content)
+            .map_err(|error| AppError::ManifestError(path.to_owned(), error.to_string()))
+    }
+
+    pub fn write(
+        &self,
+        path: &Path,
+    ) -> Result<(), AppError> {
+        let mut content = serde_json::to_string_pretty(self).unwrap();
+        content.push('\n');
+        fs::write(path, content)
+            .map_err(|error| AppError::ManifestError(path.to_owned(), error.to_string()))
+    }
+
+    /// Warning about the file of `--file` when it changed since the recorded run
+    pub fn input_changed(&self) -> Option<String> {
+        let recorded = self.input.as_ref()?;
+        let current = InputInfo::new(&recorded.path);
+        match current {
+            Some(current) if current.sha256 == recorded.sha256 => None,
+            Some(_) => Some(format!(
+                "{} changed since the recorded run",
+                recorded.path.display()
+            )),
+            None => Some(format!(
+                "{} of the recorded run cannot be read",
+                recorded.path.display()
+            )),
+        }
+    }
+
+    /// Settings recorded in the manifest that `apply` changes in `cli`, which the defaults and
+    /// flags already holding the recorded values are not
+    pub fn recorded_keys(
+        &self,
+        cli: &Cli,
+    ) -> Vec<String> {
+        let current = serde_json::to_value(cli).unwrap();
+        match &self.config {
+            Value::Object(config) => config
+                .iter()
+                .filter(|(key, value)| current.get(key.as_str()) != Some(*value))
+                .map(|(key, _)| key.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Configuration of the recorded run on top of `cli`.
+    ///
+    /// Settings missing from older manifests keep the values of `cli`, while `--manifest`
+    /// is taken from `cli` so the repeated run can be recorded as well.
+    pub fn apply(
+        self,
+        cli: Cli,
+    ) -> Result<Cli, AppError> {
+        let mut config = serde_json::to_value(&cli).unwrap();
+        if let (Value::Object(config), Value::Object(recorded)) = (&mut config, self.config) {
+            config.extend(recorded);
+        }
+        let mut recorded: Cli = serde_json::from_value(config).map_err(|error| {
+            AppError::ManifestError(
+                cli.from_manifest.clone().unwrap_or_default(),
+                error.to_string(),
+            )
+        })?;
+        recorded.manifest = cli.manifest;
+        Ok(recorded)
+    }
+}
@@ -0,0 +1,730 @@
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use hickory_client::{
+    error::{ClientErrorKind, ClientResult},
+    op::{DnsResponse, Message, MessageType, OpCode, Query, ResponseCode},
+    proto::serialize::binary::{BinDecoder, Restrict},
+    rr::{rdata::NULL, DNSClass, Name, RData, Record, RecordType},
+    serialize::txt::RDataParser,
+};
+use serde::Deserialize;
+
+use crate::{client::DEFAULT_TIMEOUT, ttl::Ttl, AppError};
+
+/// TTL of records whose entry doesn't give one
+const DEFAULT_TTL: u32 = 300;
+
+/// Header flags of answers whose entry doesn't list any
+const DEFAULT_FLAGS: [&str; 2] = ["rd", "ra"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    /// class of the question and the records, IN by default
+    #[serde(default)]
+    class: Option<String>,
+    #[serde(default)]
+    rcode: Option<String>,
+    /// header flags, e.g. `aa` and `ad`
+    #[serde(default)]
+    flags: Option<Vec<String>>,
+    #[serde(default)]
+    ttl: Option<Ttl>,
+    /// record data in zone file syntax, or records of other names and types
+    #[serde(default)]
+    records: Vec<RecordEntry>,
+    /// records of the authority section, e.g. the SOA record of a negative answer
+    #[serde(default)]
+    authority: Vec<RecordTable>,
+    #[serde(default)]
+    additional: Vec<RecordTable>,
+    /// wait before answering, timing out when the client doesn't wait this long
+    #[serde(default)]
+    delay: Option<String>,
+    /// how the first queries of the entry fail in turn, `timeout` or an rcode, before the
+    /// answer comes
+    #[serde(default)]
+    failures: Vec<String>,
+}
+
+/// Record of an answer, as the data of a record of the name and type of the entry or as a
+/// table of its own
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecordEntry {
+    Data(String),
+    Record(RecordTable),
+}
+
+/// Record whose name, type and TTL default to those of its entry
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecordTable {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default, rename = "type")]
+    record_type: Option<String>,
+    #[serde(default)]
+    ttl: Option<Ttl>,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixtureContent {
+    #[serde(default, rename = "answer")]
+    answers: Vec<Entry>,
+}
+
+#[derive(Default)]
+struct Flags {
+    authoritative: bool,
+    truncated: bool,
+    recursion_desired: bool,
+    recursion_available: bool,
+    authentic_data: bool,
+    checking_disabled: bool,
+}
+
+/// How a query of an answer with `failures` fails
+enum Failure {
+    Timeout,
+    Code(ResponseCode),
+}
+
+struct Answer {
+    /// lower case, without the trailing dot
+    name: String,
+    record_type: RecordType,
+    class: DNSClass,
+    response_code: ResponseCode,
+    flags: Flags,
+    records: Vec<Record>,
+    authority: Vec<Record>,
+    additional: Vec<Record>,
+    delay: Duration,
+    failures: Vec<Failure>,
+    /// queries of the answer so far, which the failures are taken in turn by
+    queries: AtomicUsize,
+}
+
+/// Canned answers standing in for a DNS server
+pub struct Fixture {
+    answers: Vec<Answer>,
+}
+
+fn normalize(name: &Name) -> String {
+    name.to_lowercase()
+        .to_ascii()
+        .trim_end_matches('.')
+        .to_owned()
+}
+
+fn parse_name(value: &str) -> Result<Name, String> {
+    let mut name = Name::from_str(value).map_err(|_| format!("invalid name {:?}", value))?;
+    // Owners of records from servers are always fully qualified
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+fn parse_record_type(value: &str) -> Result<RecordType, String> {
+    RecordType::from_str(&value.to_uppercase())
+        .map_err(|_| format!("unknown record type {:?}", value))
+}
+
+/// Record data in zone file syntax, or in the generic syntax of RFC 3597, e.g. `\# 4 c0000201`,
+/// for the types that have none
+fn parse_data(
+    record_type: RecordType,
+    data: &str,
+) -> Result<RData, String> {
+    let invalid = |error: String| format!("invalid {} record {:?}: {}", record_type, data, error);
+    let Some(generic) = data.trim_start().strip_prefix("\\#") else {
+        return RData::try_from_str(record_type, data).map_err(|e| invalid(e.to_string()));
+    };
+    let mut fields = generic.split_whitespace();
+    let length: u16 = fields
+        .next()
+        .and_then(|length| length.parse().ok())
+        .ok_or_else(|| invalid(String::from("expected the length after \\#")))?;
+    let hex: String = fields.collect();
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            hex.get(index..index + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| invalid(String::from("the data is not hexadecimal")))?;
+    if bytes.len() != usize::from(length) {
+        return Err(invalid(format!(
+            "{} bytes of data instead of {}",
+            bytes.len(),
+            length
+        )));
+    }
+    // DNSSEC records are read raw, as builds without the DNSSEC types of hickory read them
+    // from servers, and the other types as if from the wire
+    if record_type.is_dnssec() {
+        return Ok(RData::Unknown {
+            code: record_type,
+            rdata: NULL::with(bytes),
+        });
+    }
+    RData::read(
+        &mut BinDecoder::new(&bytes),
+        record_type,
+        Restrict::new(length),
+    )
+    .map_err(|e| invalid(e.to_string()))
+}
+
+fn parse_flags(values: &[String]) -> Result<Flags, String> {
+    let mut flags = Flags::default();
+    for value in values {
+        let flag = match value.to_lowercase().as_str() {
+            "aa" => &mut flags.authoritative,
+            "tc" => &mut flags.truncated,
+            "rd" => &mut flags.recursion_desired,
+            "ra" => &mut flags.recursion_available,
+            "ad" => &mut flags.authentic_data,
+            "cd" => &mut flags.checking_disabled,
+            _ => {
+                return Err(format!(
+                    "unknown flag {:?}, expected aa, tc, rd, ra, ad or cd",
+                    value
+                ))
+            },
+        };
+        *flag = true;
+    }
+    Ok(flags)
+}
+
+impl RecordTable {
+    /// The record, with the `name`, `record_type`, `class` and `ttl` of its entry unless it
+    /// has its own
+    fn parse(
+        &self,
+        name: &Name,
+        record_type: RecordType,
+        class: DNSClass,
+        ttl: u32,
+    ) -> Result<Record, String> {
+        let name = match &self.name {
+            Some(name) => parse_name(name)?,
+            None => name.clone(),
+        };
+        let record_type = match &self.record_type {
+            Some(record_type) => parse_record_type(record_type)?,
+            None => record_type,
+        };
+        let ttl = match &self.ttl {
+            Some(ttl) => ttl.seconds()?,
+            None => ttl,
+        };
+        let mut record = Record::from_rdata(name, ttl, parse_data(record_type, &self.data)?);
+        record.set_dns_class(class);
+        Ok(record)
+    }
+}
+
+impl Entry {
+    fn parse(self) -> Result<Answer, String> {
+        let name = parse_name(&self.name)?;
+        let record_type = parse_record_type(&self.record_type)?;
+        let class = match &self.class {
+            Some(class) => DNSClass::from_str(&class.to_uppercase())
+                .map_err(|_| format!("unknown class {:?}, e.g. IN or CH", class))?,
+            None => DNSClass::IN,
+        };
+        let response_code = match &self.rcode {
+            Some(rcode) => crate::parse::parse_response_code(rcode)
+                .ok_or_else(|| format!("unknown rcode {:?}, e.g. NOERROR or NXDOMAIN", rcode))?,
+            None => ResponseCode::NoError,
+        };
+        let flags = match &self.flags {
+            Some(flags) => parse_flags(flags)?,
+            None => parse_flags(&DEFAULT_FLAGS.map(String::from))?,
+        };
+        let ttl = match &self.ttl {
+            Some(ttl) => ttl.seconds()?,
+            None => DEFAULT_TTL,
+        };
+        let records = self
+            .records
+            .iter()
+            .map(|entry| match entry {
+                RecordEntry::Data(data) => {
+                    let mut record =
+                        Record::from_rdata(name.clone(), ttl, parse_data(record_type, data)?);
+                    record.set_dns_class(class);
+                    Ok(record)
+                },
+                RecordEntry::Record(table) => table.parse(&name, record_type, class, ttl),
+            })
+            .collect::<Result<_, String>>()?;
+        let delay = match &self.delay {
+            Some(delay) => crate::parse::parse_duration(delay)
+                .map_err(|error| format!("invalid delay {:?}: {}", delay, error))?,
+            None => Duration::ZERO,
+        };
+        let failures = self
+            .failures
+            .iter()
+            .map(|failure| match failure.to_lowercase().as_str() {
+                "timeout" => Ok(Failure::Timeout),
+                _ => crate::parse::parse_response_code(failure)
+                    .map(Failure::Code)
+                    .ok_or_else(|| {
+                        format!("unknown failure {:?}, e.g. timeout or SERVFAIL", failure)
+                    }),
+            })
+            .collect::<Result<_, String>>()?;
+        let section = |tables: &[RecordTable]| {
+            tables
+                .iter()
+                .map(|table| table.parse(&name, record_type, class, ttl))
+                .collect::<Result<Vec<Record>, String>>()
+        };
+        Ok(Answer {
+            name: normalize(&name),
+            record_type,
+            class,
+            response_code,
+            flags,
+            records,
+            authority: section(&self.authority)?,
+            additional: section(&self.additional)?,
+            delay,
+            failures,
+            queries: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Fixture {
+    /// Read a TOML fixture of `[[answer]]` tables, or the same as JSON for `.json` files
+    pub fn read(path: &Path) -> Result<Self, AppError> {
+        let error = |message: String| AppError::MockAnswersError(path.to_owned(), message);
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content)
+                .map_err(|e| e.to_string())
+                .and_then(Self::new),
+            _ => Self::from_toml(&content),
+        }
+        .map_err(error)
+    }
+
+    /// The fixture of the `[[answer]]` tables of `content`
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        toml::from_str(content)
+            .map_err(|e| e.to_string())
+            .and_then(Self::new)
+    }
+
+    fn new(content: FixtureContent) -> Result<Self, String> {
+        let answers = content
+            .answers
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                entry
+                    .parse()
+                    .map_err(|message| format!("answer {}: {}", index + 1, message))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            answers,
+        })
+    }
+
+    /// Answer as a server holding only the fixture would: the CNAME of names that have one
+    /// for other types, NODATA for names of the fixture without an answer of the type,
+    /// NXDOMAIN for other names, negative answers with the SOA record of the closest zone of
+    /// the fixture, within the default timeout
+    pub fn query(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+    ) -> ClientResult<DnsResponse> {
+        self.query_within(name, query_class, query_type, DEFAULT_TIMEOUT)
+    }
+
+    /// Like `query`, for a client waiting `timeout` for an answer: the answers with a longer
+    /// delay and the failures that are timeouts time out after it
+    pub fn query_within(
+        &self,
+        name: &Name,
+        query_class: DNSClass,
+        query_type: RecordType,
+        timeout: Duration,
+    ) -> ClientResult<DnsResponse> {
+        let normalized = normalize(name);
+        let find = |record_type: RecordType| {
+            self.answers.iter().find(|answer| {
+                answer.name == normalized
+                    && answer.record_type == record_type
+                    && answer.class == query_class
+            })
+        };
+        let answer = find(query_type).or_else(|| find(RecordType::CNAME));
+        let known = self
+            .answers
+            .iter()
+            .any(|answer| answer.name == normalized && answer.class == query_class);
+
+        let mut query = Query::query(name.clone(), query_type);
+        query.set_query_class(query_class);
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Response)
+            .set_op_code(OpCode::Query)
+            .add_query(query);
+        let timed_out = || {
+            thread::sleep(timeout);
+            Err(ClientErrorKind::Timeout.into())
+        };
+        match answer {
+            Some(answer) => {
+                let query = answer.queries.fetch_add(1, Ordering::Relaxed);
+                match answer.failures.get(query) {
+                    Some(Failure::Timeout) => return timed_out(),
+                    Some(Failure::Code(code)) => {
+                        message.set_response_code(*code);
+                        return Ok(DnsResponse::from_message(message)?);
+                    },
+                    None if answer.delay >= timeout => return timed_out(),
+                    None => thread::sleep(answer.delay),
+                }
+                message
+                    .set_response_code(answer.response_code)
+                    .set_authoritative(answer.flags.authoritative)
+                    .set_truncated(answer.flags.truncated)
+                    .set_recursion_desired(answer.flags.recursion_desired)
+                    .set_recursion_available(answer.flags.recursion_available)
+                    .set_authentic_data(answer.flags.authentic_data)
+                    .set_checking_disabled(answer.flags.checking_disabled)
+                    .add_answers(answer.records.clone())
+                    .add_name_servers(answer.authority.clone())
+                    .add_additionals(answer.additional.clone());
+            },
+            None => {
+                message
+                    .set_response_code(match known {
+                        true => ResponseCode::NoError,
+                        false => ResponseCode::NXDomain,
+                    })
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true);
+                if let Some(soa) = self.zone_of(name, query_class) {
+                    message.add_name_servers(soa.records.clone());
+                }
+            },
+        }
+        Ok(DnsResponse::from_message(message)?)
+    }
+
+    /// The SOA answer of the closest zone of the fixture that `name` is in
+    fn zone_of(
+        &self,
+        name: &Name,
+        class: DNSClass,
+    ) -> Option<&Answer> {
+        let mut zone = name.clone();
+        loop {
+            let normalized = normalize(&zone);
+            let soa = self.answers.iter().find(|answer| {
+                answer.name == normalized
+                    && answer.record_type == RecordType::SOA
+                    && answer.class == class
+                    && !answer.records.is_empty()
+            });
+            match soa {
+                Some(soa) => return Some(soa),
+                None if zone.is_root() => return None,
+                None => zone = zone.base_name(),
+            }
+        }
+    }
+
+    /// Records of a transfer of `zone`, its SOA record first and every record of the fixture
+    /// in the zone after it, as a server holding the fixture would transfer it
+    pub fn zone_transfer(
+        &self,
+        zone: &Name,
+    ) -> Result<Vec<Record>, String> {
+        let zone = zone.to_lowercase();
+        let soa = self
+            .zone_of(&zone, DNSClass::IN)
+            .filter(|soa| soa.records[0].name().to_lowercase() == zone)
+            .ok_or_else(|| format!("the mock answers have no SOA record of {}", zone))?;
+        let mut records = soa.records[..1].to_vec();
+        for answer in &self.answers {
+            for record in answer
+                .records
+                .iter()
+                .chain(&answer.authority)
+                .chain(&answer.additional)
+            {
+                let in_zone = zone.zone_of(&record.name().to_lowercase());
+                if in_zone && record.record_type() != RecordType::SOA && !records.contains(record) {
+                    records.push(record.clone());
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"
+        [[answer]]
+        name = "example.com"
+        type = "SOA"
+        records = ["ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 600"]
+
+        [[answer]]
+        name = "example.com"
+        type = "A"
+        flags = ["aa"]
+        records = ["192.0.2.1", { type = "RRSIG", data = "\\# 3 000102" }]
+        additional = [{ name = "ns1.example.com", type = "AAAA", data = "2001:db8::53" }]
+
+        [[answer]]
+        name = "www.example.com"
+        type = "CNAME"
+        ttl = "1h"
+        records = ["example.com."]
+
+        [[answer]]
+        name = "gone.example.com"
+        type = "A"
+        rcode = "NXDOMAIN"
+        authority = [{ name = "example.com", type = "SOA", ttl = 60, data = "ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 60" }]
+
+        [[answer]]
+        name = "version.bind"
+        type = "TXT"
+        class = "CH"
+        records = ['"resoy"']
+    "#;
+
+    fn fixture() -> Fixture {
+        Fixture::from_toml(FIXTURE).unwrap()
+    }
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn types(records: &[Record]) -> Vec<(String, RecordType)> {
+        records
+            .iter()
+            .map(|record| (record.name().to_string(), record.record_type()))
+            .collect()
+    }
+
+    #[test]
+    fn answers_take_records_of_other_types_and_sections() {
+        let response = fixture()
+            .query(&name("example.com."), DNSClass::IN, RecordType::A)
+            .unwrap();
+        assert!(response.authoritative());
+        assert_eq!(
+            types(response.answers()),
+            [
+                (String::from("example.com."), RecordType::A),
+                (String::from("example.com."), RecordType::RRSIG),
+            ]
+        );
+        // Types without a syntax of their own are read from the generic one as from the wire
+        assert!(matches!(
+            response.answers()[1].data(),
+            Some(RData::Unknown { code: RecordType::RRSIG, rdata }) if rdata.anything() == [0, 1, 2]
+        ));
+        assert_eq!(
+            types(response.additionals()),
+            [(String::from("ns1.example.com."), RecordType::AAAA)]
+        );
+    }
+
+    #[test]
+    fn names_with_a_cname_answer_every_type_with_it() {
+        let response = fixture()
+            .query(&name("WWW.example.com."), DNSClass::IN, RecordType::A)
+            .unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        let records = response.answers();
+        assert_eq!(records[0].record_type(), RecordType::CNAME);
+        assert_eq!(records[0].ttl(), 3600);
+        assert_eq!(records[0].data().unwrap().to_string(), "example.com.");
+    }
+
+    #[test]
+    fn negative_answers_come_with_the_soa_record_of_their_zone() {
+        let fixture = fixture();
+        // NXDOMAIN of the fixture itself, with its own authority section
+        let response = fixture
+            .query(&name("gone.example.com."), DNSClass::IN, RecordType::A)
+            .unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NXDomain);
+        assert_eq!(response.name_servers()[0].ttl(), 60);
+        // NODATA and NXDOMAIN of names the fixture doesn't answer for
+        for (queried, code) in [
+            ("example.com.", ResponseCode::NoError),
+            ("missing.example.com.", ResponseCode::NXDomain),
+        ] {
+            let response = fixture
+                .query(&name(queried), DNSClass::IN, RecordType::MX)
+                .unwrap();
+            assert_eq!(response.response_code(), code);
+            assert!(response.answers().is_empty());
+            assert_eq!(
+                types(response.name_servers()),
+                [(String::from("example.com."), RecordType::SOA)]
+            );
+        }
+        // Outside of the zones of the fixture there is no SOA record to give
+        let response = fixture
+            .query(&name("example.net."), DNSClass::IN, RecordType::A)
+            .unwrap();
+        assert!(response.name_servers().is_empty());
+    }
+
+    #[test]
+    fn answers_are_only_given_in_their_class() {
+        let fixture = fixture();
+        let response = fixture
+            .query(&name("version.bind."), DNSClass::CH, RecordType::TXT)
+            .unwrap();
+        assert_eq!(response.queries()[0].query_class(), DNSClass::CH);
+        assert_eq!(response.answers()[0].dns_class(), DNSClass::CH);
+        assert_eq!(response.answers()[0].data().unwrap().to_string(), "resoy");
+        let response = fixture
+            .query(&name("version.bind."), DNSClass::IN, RecordType::TXT)
+            .unwrap();
+        assert_eq!(response.response_code(), ResponseCode::NXDomain);
+    }
+
+    #[test]
+    fn zones_are_transferred_from_their_soa_record() {
+        let records = fixture().zone_transfer(&name("Example.com.")).unwrap();
+        assert_eq!(
+            types(&records),
+            [
+                (String::from("example.com."), RecordType::SOA),
+                (String::from("example.com."), RecordType::A),
+                (String::from("example.com."), RecordType::RRSIG),
+                (String::from("ns1.example.com."), RecordType::AAAA),
+                (String::from("www.example.com."), RecordType::CNAME),
+            ]
+        );
+        let error = fixture()
+            .zone_transfer(&name("www.example.com."))
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "the mock answers have no SOA record of www.example.com."
+        );
+    }
+
+    #[test]
+    fn ttls_are_read_in_seconds_or_with_units() {
+        let ttl = |value: &str| {
+            let content = format!(
+                "[[answer]]\nname = \"a.example\"\ntype = \"A\"\nttl = {}\nrecords = [\"192.0.2.1\"]",
+                value
+            );
+            let response = Fixture::from_toml(&content)?
+                .query(&name("a.example."), DNSClass::IN, RecordType::A)
+                .unwrap();
+            Ok::<u32, String>(response.answers()[0].ttl())
+        };
+        for value in ["3600", "\"3600\"", "\"3600s\"", "\"60m\"", "\"1h\""] {
+            assert_eq!(ttl(value), Ok(3600), "{}", value);
+        }
+        assert_eq!(ttl("\"2d\""), Ok(172800));
+        assert_eq!(ttl("0"), Ok(0));
+        assert_eq!(
+            ttl("\"1500ms\""),
+            Err(String::from(
+                "answer 1: ttl \"1500ms\" is not a whole number of seconds"
+            ))
+        );
+        assert_eq!(
+            ttl("\"50000d\""),
+            Err(String::from("answer 1: ttl \"50000d\" is too long"))
+        );
+        assert!(ttl("\"1w\"")
+            .unwrap_err()
+            .starts_with("answer 1: invalid ttl \"1w\": unknown unit"));
+    }
+
+    #[test]
+    fn mistakes_in_fixtures_are_reported_with_their_answer() {
+        let error = |content: &str| Fixture::from_toml(content).err().unwrap();
+        assert_eq!(
+            error("[[answer]]\nname = \"a.example\"\ntype = \"A\"\nrecords = [\"\\\\# 4 c00002\"]"),
+            "answer 1: invalid A record \"\\\\# 4 c00002\": 3 bytes of data instead of 4"
+        );
+        assert_eq!(
+            error(
+                "[[answer]]\nname = \"a.example\"\ntype = \"A\"\n\
+                 authority = [{ type = \"SOAP\", data = \"x\" }]"
+            ),
+            "answer 1: unknown record type \"SOAP\""
+        );
+        assert!(error(
+            "[[answer]]\nname = \"a.example\"\ntype = \"A\"\nrecords = [\"192.0.2.300\"]"
+        )
+        .starts_with("answer 1: invalid A record \"192.0.2.300\""));
+        assert_eq!(
+            error("[[answer]]\nname = \"a.example\"\ntype = \"A\"\nfailures = [\"lost\"]"),
+            "answer 1: unknown failure \"lost\", e.g. timeout or SERVFAIL"
+        );
+        assert_eq!(
+            error("[[answer]]\nname = \"a.example\"\ntype = \"A\"\ndelay = \"soon\""),
+            "answer 1: invalid delay \"soon\": invalid duration \"soon\""
+        );
+    }
+
+    #[test]
+    fn failures_come_in_turn_before_the_answer() {
+        let fixture = Fixture::from_toml(
+            "[[answer]]\nname = \"a.example\"\ntype = \"A\"\nfailures = [\"REFUSED\", \
+             \"timeout\"]\nrecords = [\"192.0.2.1\"]",
+        )
+        .unwrap();
+        let name = Name::from_str("a.example.").unwrap();
+        let query = || {
+            fixture.query_within(
+                &name,
+                DNSClass::IN,
+                RecordType::A,
+                Duration::from_millis(10),
+            )
+        };
+        let refused = query().unwrap();
+        assert_eq!(refused.response_code(), ResponseCode::Refused);
+        assert!(refused.answers().is_empty());
+        assert!(query().is_err());
+        assert_eq!(query().unwrap().answers().len(), 1);
+        assert_eq!(query().unwrap().answers().len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! What a run prints on stdout, flushed as `--flush` says.
+//!
+//! Rust flushes stdout at the end of every line, pipes included, which costs a write for every
+//! line of a huge `--file` run, while a tool reading a stream wants every record as soon as it
+//! is printed, and none of them cut in half. Every record written here goes through one buffer
+//! under the policy of the run, so that no write to stdout can overtake another.
+
+use std::{
+    fmt,
+    io::{self, stdout, BufWriter, IsTerminal, Stdout, Write},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex, OnceLock, PoisonError,
+    },
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// When the output of a run is flushed to stdout
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Flush {
+    /// at the end of every line
+    Line,
+    /// once 64 KiB are buffered, and at the end of the run
+    Block,
+    /// at the end of every record, e.g. every name of --file and every change of --watch
+    EveryRecord,
+}
+
+impl Flush {
+    /// Policy of a run without `--flush`: line by line on a terminal, record by record for the
+    /// streaming runs of `--watch` and `--file` into a pipe, and by blocks for the others,
+    /// which print their output at once
+    pub fn default_for(streaming: bool) -> Self {
+        match (stdout().is_terminal(), streaming) {
+            (true, _) => Self::Line,
+            (false, true) => Self::EveryRecord,
+            (false, false) => Self::Block,
+        }
+    }
+}
+
+/// Size of the buffer of `Flush::Block`
+const BLOCK: usize = 64 * 1024;
+
+/// Policy of the run, as `Flush as u8`
+static POLICY: AtomicU8 = AtomicU8::new(Flush::Line as u8);
+
+static BUFFER: OnceLock<Mutex<BufWriter<Stdout>>> = OnceLock::new();
+
+/// Flush the output of the rest of the run as `policy` says
+pub fn set(policy: Flush) {
+    flush();
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub fn policy() -> Flush {
+    match POLICY.load(Ordering::Relaxed) {
+        policy if policy == Flush::Block as u8 => Flush::Block,
+        policy if policy == Flush::EveryRecord as u8 => Flush::EveryRecord,
+        _ => Flush::Line,
+    }
+}
+
+fn buffer() -> &'static Mutex<BufWriter<Stdout>> {
+    BUFFER.get_or_init(|| Mutex::new(BufWriter::with_capacity(BLOCK, stdout())))
+}
+
+/// Write one record to stdout with `write`, whole, and flush it as the policy of the run says.
+///
+/// A closed pipe fails the write, which `write` passes on with `?` to stop there: output is
+/// meant to be piped, and whoever closed the pipe wants no more of it.
+pub fn record(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    // A record that panicked halfway leaves nothing to protect
+    let mut buffer = buffer().lock().unwrap_or_else(PoisonError::into_inner);
+    match policy() {
+        Flush::Line => {
+            buffer.flush()?;
+            write(&mut stdout().lock())
+        },
+        Flush::Block => write(&mut *buffer),
+        Flush::EveryRecord => {
+            write(&mut *buffer)?;
+            buffer.flush()
+        },
+    }
+}
+
+/// Print `args` as one record, a closed pipe just ending the output, as `outln!` does
+pub fn print(args: fmt::Arguments) {
+    let _ = record(|out| out.write_fmt(args));
+}
+
+/// Write out whatever the policy of the run buffered
+pub fn flush() {
+    if let Some(buffer) = BUFFER.get() {
+        let _ = buffer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush();
+    }
+}
+
+/// Flushes the output when dropped, so that a run ending early or panicking keeps whatever it
+/// printed
+pub struct FlushGuard;
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        flush();
+    }
+}
+
+/// Print a record of the run on stdout, like `print!`, flushed as `--flush` says
+#[macro_export]
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::print(format_args!($($arg)*))
+    };
+}
+
+/// Print a line of the run on stdout, like `println!`, flushed as `--flush` says
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::output::print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_round_trip_through_their_byte() {
+        for policy in [Flush::Line, Flush::Block, Flush::EveryRecord] {
+            set(policy);
+            assert_eq!(super::policy(), policy);
+        }
+        set(Flush::Line);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{net::IpAddr, str::FromStr, time::Duration};
+
+use hickory_client::{
+    op::ResponseCode,
+    rr::{Name, RecordType},
+};
+
+use crate::AppError;
+
+/// The name, when it is an IP address rather than a domain name
+pub fn parse_address(name: Option<&str>) -> Option<IpAddr> {
+    name?.parse().ok()
+}
+
+/// Addresses stand for their name under in-addr.arpa or ip6.arpa, as with `dig -x`
+pub fn parse_domain_name(name: Option<&str>) -> Result<Name, AppError> {
+    if let Some(address) = parse_address(name) {
+        return Ok(Name::from(address));
+    }
+    let name = name.unwrap_or_default();
+    Name::from_str(name).map_err(|_| invalid_name(name))
+}
+
+/// Error of a name that doesn't parse, naming the first of its labels at fault
+pub fn invalid_name(name: &str) -> AppError {
+    let labels = name.strip_suffix('.').unwrap_or(name).split('.');
+    let hint = labels
+        .map(|label| match Name::from_utf8(label) {
+            _ if label.is_empty() => Some(String::from("it has an empty label")),
+            Ok(_) => None,
+            Err(_) if label.is_ascii() && label.len() > 63 => {
+                Some(format!("the label {:?} is longer than 63 bytes", label))
+            },
+            Err(_) if label.is_ascii() => Some(format!("the label {:?} is no valid label", label)),
+            Err(_) => Some(format!(
+                "the label {:?} is no valid internationalized label",
+                label
+            )),
+        })
+        .find_map(|hint| hint)
+        .unwrap_or_else(|| String::from("it is longer than 255 bytes"));
+    AppError::InvalidDomainName(name.to_owned(), hint)
+}
+
+/// Record types of `all`, those worth a look when investigating a domain
+pub const COMMON_RECORD_TYPES: [RecordType; 8] = [
+    RecordType::A,
+    RecordType::AAAA,
+    RecordType::CNAME,
+    RecordType::MX,
+    RecordType::TXT,
+    RecordType::NS,
+    RecordType::SOA,
+    RecordType::CAA,
+];
+
+/// Record types of `values` in the order they were given, without repetitions, `all` and
+/// `common` standing for the common types, and A, or PTR when `name` is an address, when none
+/// is given
+pub fn parse_record_types(
+    values: &[String],
+    name: Option<&str>,
+) -> Result<Vec<RecordType>, AppError> {
+    if values.is_empty() {
+        return Ok(match parse_address(name) {
+            Some(_) => vec![RecordType::PTR],
+            None => vec![RecordType::A],
+        });
+    }
+    let mut record_types: Vec<RecordType> = Vec::new();
+    for value in values {
+        let expanded = match value.to_ascii_lowercase().as_str() {
+            "all" | "common" => COMMON_RECORD_TYPES.to_vec(),
+            // hickory only takes the names of the types in upper case
+            _ => vec![RecordType::from_str(&value.to_ascii_uppercase())
+                .map_err(|_| AppError::UnknownRecordType(value.clone()))?],
+        };
+        for record_type in expanded {
+            if !record_types.contains(&record_type) {
+                record_types.push(record_type);
+            }
+        }
+    }
+    Ok(record_types)
+}
+
+/// The servers of the dig-style `@server` arguments of `arguments`, without their `@`, and the
+/// other arguments in their order
+pub fn split_servers(arguments: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let (servers, others): (Vec<String>, Vec<String>) = arguments
+        .into_iter()
+        .partition(|argument| argument.starts_with('@'));
+    let servers = servers
+        .into_iter()
+        .map(|server| server[1..].to_owned())
+        .collect();
+    (servers, others)
+}
+
+/// Parse a response code as the output spells it, e.g. NXDOMAIN, in any case
+pub fn parse_response_code(value: &str) -> Option<ResponseCode> {
+    (0..=23)
+        .map(<ResponseCode as From<u16>>::from)
+        .find(|code| format!("{:?}", code).eq_ignore_ascii_case(value))
+}
+
+/// Parse a duration such as `50ms`, `30s`, `5m`, `1h` or `2d`, plain numbers being seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}", value))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        "d" => number * 86400.0,
+        _ => return Err(format!("unknown unit {:?}, use ms, s, m, h or d", unit)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reverse(address: &str) -> String {
+        parse_domain_name(Some(address)).unwrap().to_string()
+    }
+
+    #[test]
+    fn ipv4_addresses_stand_for_their_name_under_in_addr_arpa() {
+        assert_eq!(reverse("192.0.2.1"), "1.2.0.192.in-addr.arpa.");
+        assert_eq!(reverse("10.0.0.255"), "255.0.0.10.in-addr.arpa.");
+        assert_eq!(reverse("0.0.0.0"), "0.0.0.0.in-addr.arpa.");
+    }
+
+    #[test]
+    fn ipv6_addresses_stand_for_their_nibbles_under_ip6_arpa() {
+        assert_eq!(
+            reverse("2001:db8::567:89ab"),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+        );
+        assert_eq!(
+            reverse("2001:DB8:0:0:0:0:0:1"),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+        );
+        assert_eq!(
+            reverse("::"),
+            "0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa."
+        );
+        assert_eq!(
+            reverse("::1"),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa."
+        );
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_stay_under_ip6_arpa() {
+        // As with dig -x, the address is reversed as given rather than as the IPv4 it maps
+        assert_eq!(
+            reverse("::ffff:192.0.2.1"),
+            "1.0.2.0.0.0.0.c.f.f.f.f.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa."
+        );
+    }
+
+    #[test]
+    fn names_that_only_look_like_addresses_are_left_alone() {
+        assert_eq!(reverse("192.0.2"), "192.0.2");
+        assert_eq!(reverse("1.2.3.4.in-addr.arpa."), "1.2.3.4.in-addr.arpa.");
+        assert!(parse_address(Some("2001:db8::1%eth0")).is_none());
+    }
+
+    #[test]
+    fn addresses_are_asked_for_ptr_records_by_default() {
+        assert_eq!(
+            parse_record_types(&[], Some("2001:db8::1")).unwrap(),
+            [RecordType::PTR]
+        );
+        assert_eq!(
+            parse_record_types(&[], Some("192.0.2.1")).unwrap(),
+            [RecordType::PTR]
+        );
+        assert_eq!(
+            parse_record_types(&[], Some("example.com")).unwrap(),
+            [RecordType::A]
+        );
+    }
+
+    fn types(values: &[&str]) -> Result<Vec<RecordType>, AppError> {
+        let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        parse_record_types(&values, Some("example.com"))
+    }
+
+    #[test]
+    fn all_expands_to_the_common_types_without_repeating_the_others() {
+        assert_eq!(types(&["all"]).unwrap(), COMMON_RECORD_TYPES);
+        assert_eq!(types(&["common"]).unwrap(), COMMON_RECORD_TYPES);
+        // Explicit types keep their place, those of `all` after them only once
+        let mut expected = vec![RecordType::MX, RecordType::SRV];
+        expected.extend(
+            COMMON_RECORD_TYPES
+                .iter()
+                .filter(|record_type| **record_type != RecordType::MX),
+        );
+        assert_eq!(
+            types(&["MX", "SRV", "all", "A", "common"]).unwrap(),
+            expected
+        );
+        let mut expected = COMMON_RECORD_TYPES.to_vec();
+        expected.push(RecordType::SRV);
+        assert_eq!(types(&["all", "SOA", "SRV"]).unwrap(), expected);
+    }
+
+    #[test]
+    fn record_types_are_taken_in_any_case() {
+        assert_eq!(
+            types(&["mx", "Txt", "AAAA", "aLl"]).unwrap()[..3],
+            [RecordType::MX, RecordType::TXT, RecordType::AAAA]
+        );
+        assert_eq!(types(&["ALL"]).unwrap(), COMMON_RECORD_TYPES);
+        assert_eq!(types(&["a", "A"]).unwrap(), [RecordType::A]);
+        assert!(matches!(
+            types(&["mx", "nope"]),
+            Err(AppError::UnknownRecordType(value)) if value == "nope"
+        ));
+    }
+
+    #[test]
+    fn durations_take_every_unit() {
+        for (value, expected) in [
+            ("250ms", Duration::from_millis(250)),
+            ("0ms", Duration::ZERO),
+            ("30", Duration::from_secs(30)),
+            ("30s", Duration::from_secs(30)),
+            ("1.5s", Duration::from_millis(1500)),
+            ("5m", Duration::from_secs(300)),
+            ("1h", Duration::from_secs(3600)),
+            ("0.5h", Duration::from_secs(1800)),
+            ("2d", Duration::from_secs(172800)),
+        ] {
+            assert_eq!(parse_duration(value), Ok(expected), "{}", value);
+        }
+        // The same span in every unit
+        for value in ["86400000ms", "86400", "86400s", "1440m", "24h", "1d"] {
+            assert_eq!(
+                parse_duration(value),
+                Ok(Duration::from_secs(86400)),
+                "{}",
+                value
+            );
+        }
+    }
+
+    fn ascii(name: &str) -> String {
+        parse_domain_name(Some(name)).unwrap().to_ascii()
+    }
+
+    #[test]
+    fn internationalized_names_are_sent_in_their_xn_form() {
+        assert_eq!(ascii("münchen.de"), "xn--mnchen-3ya.de");
+        assert_eq!(ascii("MÜNCHEN.de."), "xn--mnchen-3ya.de.");
+        assert_eq!(ascii("例え.テスト"), "xn--r8jz45g.xn--zckzah");
+        assert_eq!(ascii("☕.example"), "xn--53h.example");
+        // Names given in their xn-- form are left as they are
+        assert_eq!(ascii("xn--mnchen-3ya.de"), "xn--mnchen-3ya.de");
+        assert_eq!(
+            parse_domain_name(Some("XN--MNCHEN-3YA.de")).unwrap(),
+            parse_domain_name(Some("münchen.de")).unwrap()
+        );
+        assert_eq!(
+            parse_domain_name(Some("xn--mnchen-3ya.de"))
+                .unwrap()
+                .to_utf8(),
+            "münchen.de"
+        );
+    }
+
+    #[test]
+    fn invalid_names_are_refused_with_the_label_at_fault() {
+        let hint = |name: &str| match parse_domain_name(Some(name)) {
+            Err(AppError::InvalidDomainName(domain, hint)) => {
+                assert_eq!(domain, name);
+                hint
+            },
+            other => panic!("{:?} gave {:?}", name, other.map(|name| name.to_string())),
+        };
+        assert_eq!(hint("www..example.com"), "it has an empty label");
+        assert_eq!(
+            hint(&format!("{}.example.com", "a".repeat(64))),
+            format!("the label {:?} is longer than 63 bytes", "a".repeat(64))
+        );
+        assert_eq!(
+            hint("xn--⚠.example"),
+            "the label \"xn--⚠\" is no valid internationalized label"
+        );
+    }
+
+    #[test]
+    fn durations_without_a_number_or_with_other_units_are_refused() {
+        assert_eq!(
+            parse_duration("5w"),
+            Err(String::from("unknown unit \"w\", use ms, s, m, h or d"))
+        );
+        assert_eq!(
+            parse_duration("1H"),
+            Err(String::from("unknown unit \"H\", use ms, s, m, h or d"))
+        );
+        for value in ["", "s", "h1", "-1s", "1.2.3s"] {
+            assert_eq!(
+                parse_duration(value),
+                Err(format!("invalid duration {:?}", value)),
+                "{}",
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    fs::File,
+    io::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hickory_client::{
+    op::{DnsResponse, Message, MessageType, OpCode, Query},
+    rr::{DNSClass, Name, RecordType},
+    serialize::binary::BinEncodable,
+};
+
+use crate::AppError;
+
+/// File of `--pcap`, shared by every client of the run
+static CAPTURE: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Raw IPv4 or IPv6 packets without a link layer header
+const LINKTYPE_RAW: u16 = 101;
+
+/// Payload of a single synthesized TCP segment
+const TCP_SEGMENT_SIZE: usize = 1460;
+
+/// How a captured message travelled
+#[derive(Clone, Copy)]
+pub enum Transport {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    /// only the decoded message is known, carried over HTTPS from the endpoint
+    #[cfg(feature = "doh")]
+    DohJson,
+    /// only the decrypted message is known
+    #[cfg(feature = "tls")]
+    Tls(SocketAddr),
+    /// only the message is known, carried over HTTPS to the endpoint
+    #[cfg(feature = "doh")]
+    Https,
+}
+
+/// Start writing a pcapng file that every later query of the run is recorded into.
+///
+/// Blocks are written as soon as the messages are exchanged, so the file stays readable when
+/// the run is interrupted.
+pub fn start(path: &Path) -> Result<(), AppError> {
+    let error = |e: std::io::Error| AppError::PcapError(path.to_owned(), e.to_string());
+    let mut file = File::create(path).map_err(error)?;
+
+    // Section header block, then the description of the only interface
+    let mut header = Vec::new();
+    header.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&u64::MAX.to_le_bytes());
+    file.write_all(&block(0x0A0D_0D0A, &header))
+        .map_err(error)?;
+
+    let mut interface = Vec::new();
+    interface.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+    interface.extend_from_slice(&0u16.to_le_bytes());
+    interface.extend_from_slice(&0u32.to_le_bytes());
+    file.write_all(&block(1, &interface)).map_err(error)?;
+
+    CAPTURE
+        .set(Mutex::new(file))
+        .map_err(|_| AppError::PcapError(path.to_owned(), String::from("capture already started")))
+}
+
+pub fn is_capturing() -> bool {
+    CAPTURE.get().is_some()
+}
+
+/// Record a query and its response, the query being rebuilt from the response
+pub fn record(
+    transport: Transport,
+    name: &Name,
+    query_class: DNSClass,
+    query_type: RecordType,
+    sent: SystemTime,
+    received: SystemTime,
+    response: &DnsResponse,
+) {
+    let Some(capture) = CAPTURE.get() else {
+        return;
+    };
+
+    let mut query = Query::query(name.clone(), query_type);
+    query.set_query_class(query_class);
+    let mut message = Message::new();
+    message
+        .set_id(response.id())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(response.recursion_desired())
+        .add_query(query);
+    let Ok(query) = message.to_bytes() else {
+        return;
+    };
+
+    let (server, tcp, comment) = match transport {
+        Transport::Udp(server) => (server, false, None),
+        Transport::Tcp(server) => (server, true, None),
+        #[cfg(feature = "tls")]
+        Transport::Tls(server) => (
+            server,
+            true,
+            Some("transport: tls, decrypted message with synthetic TCP headers"),
+        ),
+        #[cfg(feature = "doh")]
+        Transport::DohJson => (
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 53),
+            false,
+            Some("transport: doh-json, decoded message with synthetic UDP and IP headers"),
+        ),
+        #[cfg(feature = "doh")]
+        Transport::Https => (
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 53),
+            false,
+            Some("transport: https, message with synthetic UDP and IP headers"),
+        ),
+    };
+    // The ephemeral port of the client isn't exposed, so it is derived from the message id
+    let local = SocketAddr::new(local_address(server.ip()), 49152 + response.id() % 16384);
+
+    let mut packets: Vec<(SystemTime, Vec<u8>)> = Vec::new();
+    if tcp {
+        packets.extend(
+            tcp_packets(local, server, &query)
+                .into_iter()
+                .map(|p| (sent, p)),
+        );
+        packets.extend(
+            tcp_packets(server, local, response.as_buffer())
+                .into_iter()
+                .map(|p| (received, p)),
+        );
+    } else {
+        packets.push((sent, udp_packet(local, server, &query)));
+        packets.push((received, udp_packet(server, local, response.as_buffer())));
+    }
+
+    let Ok(mut file) = capture.lock() else {
+        return;
+    };
+    for (time, packet) in packets {
+        let _ = file.write_all(&packet_block(time, &packet, comment));
+    }
+    let _ = file.flush();
+}
+
+/// Address the system would send from to reach `server`
+fn local_address(server: IpAddr) -> IpAddr {
+    let unspecified = match server {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    UdpSocket::bind(SocketAddr::new(unspecified, 0))
+        .and_then(|socket| {
+            socket.connect(SocketAddr::new(server, 53))?;
+            socket.local_addr()
+        })
+        .map(|address| address.ip())
+        .unwrap_or(unspecified)
+}
+
+/// A pcapng block of `block_type` around `body`, padded to 32 bits
+fn block(
+    block_type: u32,
+    body: &[u8],
+) -> Vec<u8> {
+    let padding = (4 - body.len() % 4) % 4;
+    let total = (12 + body.len() + padding) as u32;
+    let mut block = Vec::with_capacity(total as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&total.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend(std::iter::repeat_n(0, padding));
+    block.extend_from_slice(&total.to_le_bytes());
+    block
+}
+
+/// Enhanced packet block with microsecond timestamps and an optional comment
+fn packet_block(
+    time: SystemTime,
+    packet: &[u8],
+    comment: Option<&str>,
+) -> Vec<u8> {
+    let micros = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let mut body = Vec::new();
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(micros as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    body.extend_from_slice(packet);
+    body.extend(std::iter::repeat_n(0, (4 - packet.len() % 4) % 4));
+    if let Some(comment) = comment {
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+        body.extend_from_slice(comment.as_bytes());
+        body.extend(std::iter::repeat_n(0, (4 - comment.len() % 4) % 4));
+        body.extend_from_slice(&[0; 4]);
+    }
+    block(6, &body)
+}
+
+/// Ones' complement sum folded to 16 bits, as used by IP, UDP and TCP checksums
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in chunks {
+        for pair in chunk.chunks(2) {
+            let word = match pair {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                [high] => u16::from_be_bytes([*high, 0]),
+                _ => 0,
+            };
+            sum += u32::from(word);
+        }
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// IP packet from `source` to `destination` carrying `segment` of `protocol`
+fn ip_packet(
+    source: IpAddr,
+    destination: IpAddr,
+    protocol: u8,
+    mut segment: Vec<u8>,
+    checksum_offset: usize,
+) -> Vec<u8> {
+    let length = segment.len();
+    let pseudo_header: Vec<u8> = match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => [
+            &source.octets()[..],
+            &destination.octets(),
+            &[0, protocol],
+            &(length as u16).to_be_bytes(),
+        ]
+        .concat(),
+        (source, destination) => [
+            &to_v6(source).octets()[..],
+            &to_v6(destination).octets(),
+            &(length as u32).to_be_bytes(),
+            &[0, 0, 0, protocol],
+        ]
+        .concat(),
+    };
+    let sum = checksum(&[&pseudo_header, &segment]);
+    segment[checksum_offset..checksum_offset + 2].copy_from_slice(&sum.to_be_bytes());
+
+    let mut packet = Vec::with_capacity(40 + length);
+    match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            packet.extend_from_slice(&[0x45, 0]);
+            packet.extend_from_slice(&((20 + length) as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+            packet.extend_from_slice(&source.octets());
+            packet.extend_from_slice(&destination.octets());
+            let sum = checksum(&[&packet]);
+            packet[10..12].copy_from_slice(&sum.to_be_bytes());
+        },
+        (source, destination) => {
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&(length as u16).to_be_bytes());
+            packet.extend_from_slice(&[protocol, 64]);
+            packet.extend_from_slice(&to_v6(source).octets());
+            packet.extend_from_slice(&to_v6(destination).octets());
+        },
+    }
+    packet.extend_from_slice(&segment);
+    packet
+}
+
+fn to_v6(address: IpAddr) -> Ipv6Addr {
+    match address {
+        IpAddr::V4(address) => address.to_ipv6_mapped(),
+        IpAddr::V6(address) => address,
+    }
+}
+
+fn udp_packet(
+    source: SocketAddr,
+    destination: SocketAddr,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(8 + payload.len());
+    segment.extend_from_slice(&source.port().to_be_bytes());
+    segment.extend_from_slice(&destination.port().to_be_bytes());
+    segment.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+    segment.extend_from_slice(&[0, 0]);
+    segment.extend_from_slice(payload);
+    ip_packet(source.ip(), destination.ip(), 17, segment, 6)
+}
+
+/// Length-prefixed message split into segments, with sequence numbers starting at 1
+fn tcp_packets(
+    source: SocketAddr,
+    destination: SocketAddr,
+    message: &[u8],
+) -> Vec<Vec<u8>> {
+    let stream = [&(message.len() as u16).to_be_bytes()[..], message].concat();
+    stream
+        .chunks(TCP_SEGMENT_SIZE)
+        .enumerate()
+        .map(|(index, payload)| {
+            let sequence = 1 + (index * TCP_SEGMENT_SIZE) as u32;
+            let mut segment = Vec::with_capacity(20 + payload.len());
+            segment.extend_from_slice(&source.port().to_be_bytes());
+            segment.extend_from_slice(&destination.port().to_be_bytes());
+            segment.extend_from_slice(&sequence.to_be_bytes());
+            segment.extend_from_slice(&1u32.to_be_bytes());
+            // Header of five words, PSH and ACK set
+            segment.extend_from_slice(&[0x50, 0x18, 0xFF, 0xFF, 0, 0, 0, 0]);
+            segment.extend_from_slice(payload);
+            ip_packet(source.ip(), destination.ip(), 6, segment, 16)
+        })
+        .collect()
+}
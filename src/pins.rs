@@ -0,0 +1,203 @@
+use std::{collections::BTreeMap, fmt::Display, fs, net::IpAddr, path::Path, str::FromStr, thread};
+
+use hickory_client::rr::{DNSClass, Name, RecordType};
+use serde::Deserialize;
+
+use crate::{format, ttl, AppError, DnsClient};
+
+/// Expected answers of one (name, type) pair in every view
+#[derive(Deserialize)]
+struct Pin {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    /// view labels, e.g. `internal` and `external`, with the answers expected in them
+    #[serde(flatten)]
+    views: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct PinFileContent {
+    #[serde(default, rename = "pin")]
+    pins: Vec<Pin>,
+}
+
+/// Pins of `--pin-file`, for telling which view of a split horizon a resolver serves
+pub struct PinFile {
+    pins: Vec<(Name, RecordType, BTreeMap<String, Vec<String>>)>,
+}
+
+/// Answer data in a comparable form: lower case, without trailing dots, canonical addresses,
+/// and the timers of SOA records in seconds
+fn normalize(
+    record_type: RecordType,
+    data: &str,
+) -> String {
+    let data = match record_type {
+        RecordType::SOA => ttl::soa_in_seconds(data),
+        _ => data.to_owned(),
+    };
+    let data = data.trim().trim_end_matches('.').to_lowercase();
+    match IpAddr::from_str(&data) {
+        Ok(address) => address.to_string(),
+        Err(_) => data,
+    }
+}
+
+fn normalize_all<'a>(
+    record_type: RecordType,
+    data: impl Iterator<Item = &'a str>,
+) -> Vec<String> {
+    let mut normalized: Vec<String> = data.map(|data| normalize(record_type, data)).collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+/// Which view the answers of a pinned pair matched
+pub struct Verdict {
+    name: Name,
+    record_type: RecordType,
+    answers: Result<Vec<String>, String>,
+    /// views whose expected answers equal the actual ones
+    matched: Vec<String>,
+    views: BTreeMap<String, Vec<String>>,
+}
+
+impl Verdict {
+    /// Label counted in the summary
+    pub fn label(&self) -> String {
+        match (&self.answers, self.matched.as_slice()) {
+            (Err(_), _) => String::from("failed"),
+            (Ok(_), []) => String::from("neither"),
+            (Ok(_), matched) => matched.join("/"),
+        }
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match (&self.answers, self.matched.as_slice()) {
+            (Err(error), _) => write!(
+                f,
+                "  {} {}: query failed: {}",
+                self.name, self.record_type, error
+            ),
+            (Ok(answers), []) => {
+                let views: Vec<String> = self
+                    .views
+                    .iter()
+                    .map(|(view, expected)| format!("{}: {}", view, expected.join(", ")))
+                    .collect();
+                write!(
+                    f,
+                    "  {} {}: the answer matches neither view, got {} ({})",
+                    self.name,
+                    self.record_type,
+                    if answers.is_empty() {
+                        String::from("no records")
+                    } else {
+                        answers.join(", ")
+                    },
+                    views.join("; ")
+                )
+            },
+            (Ok(_), matched) => {
+                let matched: Vec<String> = matched.iter().map(|view| view.to_uppercase()).collect();
+                write!(
+                    f,
+                    "  {} {}: this resolver is serving the {} view",
+                    self.name,
+                    self.record_type,
+                    matched.join("/")
+                )
+            },
+        }
+    }
+}
+
+impl PinFile {
+    pub fn read(path: &Path) -> Result<Self, AppError> {
+        let error = |message: String| AppError::PinFileError(path.to_owned(), message);
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let content: PinFileContent = toml::from_str(&content).map_err(|e| error(e.to_string()))?;
+
+        let pins = content
+            .pins
+            .into_iter()
+            .map(|pin| {
+                let name = Name::from_str(&pin.name)
+                    .map_err(|_| error(format!("invalid name {:?}", pin.name)))?;
+                let record_type = RecordType::from_str(&pin.record_type.to_uppercase())
+                    .map_err(|_| error(format!("unknown record type {:?}", pin.record_type)))?;
+                let views = pin
+                    .views
+                    .into_iter()
+                    .map(|(view, expected)| {
+                        (
+                            view,
+                            normalize_all(record_type, expected.iter().map(String::as_str)),
+                        )
+                    })
+                    .collect();
+                Ok((name, record_type, views))
+            })
+            .collect::<Result<_, AppError>>()?;
+        Ok(Self {
+            pins,
+        })
+    }
+
+    /// Query every pinned pair concurrently, only those of `only` if given
+    pub fn check(
+        &self,
+        client: &DnsClient,
+        only: Option<&Name>,
+    ) -> Vec<Verdict> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .pins
+                .iter()
+                .filter(|(name, _, _)| only.is_none_or(|only| only == name))
+                .map(|(name, record_type, views)| {
+                    scope.spawn(move || {
+                        let answers = client
+                            .query(name, DNSClass::IN, *record_type)
+                            .map(|response| {
+                                let data: Vec<String> = response
+                                    .answers()
+                                    .iter()
+                                    .filter(|record| record.record_type() == *record_type)
+                                    .filter_map(|record| record.data().map(format::data_text))
+                                    .collect();
+                                normalize_all(*record_type, data.iter().map(String::as_str))
+                            })
+                            .map_err(|error| error.to_string());
+                        let matched = match &answers {
+                            Ok(answers) => views
+                                .iter()
+                                .filter(|(_, expected)| *expected == answers)
+                                .map(|(view, _)| view.clone())
+                                .collect(),
+                            Err(_) => Vec::new(),
+                        };
+                        Verdict {
+                            name: name.clone(),
+                            record_type: *record_type,
+                            answers,
+                            matched,
+                            views: views.clone(),
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        })
+    }
+}
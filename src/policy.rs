@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use hickory_client::{
+    error::ClientResult,
+    op::{DnsResponse, ResponseCode},
+};
+
+use crate::{client::DEFAULT_TIMEOUT, parse, resolve::DEFAULT_RETRIES, salvage};
+
+/// How the queries of a client are sent and sent again: how many times, how long each waits
+/// for its response and between them, which responses count as a failure of the server rather
+/// than its answer, and whether truncated responses are asked again over TCP.
+///
+/// The default is that of the command: three attempts of 5s each, sent again right away on
+/// timeouts only, and truncated responses asked again over TCP.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryPolicy {
+    /// times a query is sent at most, the first one included
+    attempts: usize,
+    /// wait for the response to every attempt
+    timeout: Duration,
+    /// wait before the second attempt, doubled before every later one
+    backoff: Duration,
+    /// response codes sent again like timeouts, e.g. SERVFAIL of a resolver that lost its
+    /// upstream for a moment
+    retry_on: Vec<ResponseCode>,
+    /// asking again over TCP when a UDP response comes truncated
+    tcp_fallback: bool,
+}
+
+impl Default for QueryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: DEFAULT_RETRIES + 1,
+            timeout: DEFAULT_TIMEOUT,
+            backoff: Duration::ZERO,
+            retry_on: Vec::new(),
+            tcp_fallback: true,
+        }
+    }
+}
+
+impl QueryPolicy {
+    /// Send every query up to `attempts` times, once at least
+    pub fn with_attempts(
+        mut self,
+        attempts: usize,
+    ) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Wait this long for the response to every attempt
+    pub fn with_timeout(
+        mut self,
+        timeout: Duration,
+    ) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait `backoff` before the second attempt, and twice as long as before every later one
+    pub fn with_backoff(
+        mut self,
+        backoff: Duration,
+    ) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Send the query again when the response comes with one of `codes`, as when it times
+    /// out, the last response being the answer when every attempt got one
+    pub fn with_retry_on(
+        mut self,
+        codes: &[ResponseCode],
+    ) -> Self {
+        self.retry_on = Vec::new();
+        for code in codes {
+            if !self.retry_on.contains(code) {
+                self.retry_on.push(*code);
+            }
+        }
+        self
+    }
+
+    /// Show truncated UDP responses as they are with `false`, instead of asking again over
+    /// TCP
+    pub fn with_tcp_fallback(
+        mut self,
+        tcp_fallback: bool,
+    ) -> Self {
+        self.tcp_fallback = tcp_fallback;
+        self
+    }
+
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    pub fn retry_on(&self) -> &[ResponseCode] {
+        &self.retry_on
+    }
+
+    pub fn tcp_fallback(&self) -> bool {
+        self.tcp_fallback
+    }
+
+    /// How long to wait before sending the query again after `response` came to attempt
+    /// `attempt`, counted from 1, or `None` when `response` is the answer
+    pub fn retry_after(
+        &self,
+        attempt: usize,
+        response: &ClientResult<DnsResponse>,
+    ) -> Option<Duration> {
+        let failed = match response {
+            Ok(response) => self.retry_on.contains(&response.response_code()),
+            Err(error) => salvage::timed_out(error),
+        };
+        if !failed || attempt >= self.attempts {
+            return None;
+        }
+        let doublings = u32::try_from(attempt - 1).unwrap_or(u32::MAX);
+        Some(
+            self.backoff
+                .checked_mul(2_u32.saturating_pow(doublings))
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+/// Parse a response code for `--retry-on`, e.g. `SERVFAIL` or `refused`
+pub fn parse_retry_code(value: &str) -> Result<ResponseCode, String> {
+    parse::parse_response_code(value)
+        .ok_or_else(|| format!("unknown rcode {:?}, e.g. SERVFAIL or REFUSED", value))
+}
+
+/// Response codes of `--retry-on` as their names, in the configuration of the run
+pub mod retry_codes {
+    use hickory_client::op::ResponseCode;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        codes: &[ResponseCode],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            codes
+                .iter()
+                .map(|code| format!("{:?}", code).to_uppercase()),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D
+    ) -> Result<Vec<ResponseCode>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|name| super::parse_retry_code(name).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_client::{error::ClientError, op::Message};
+
+    use super::*;
+
+    fn answer(code: ResponseCode) -> ClientResult<DnsResponse> {
+        let mut message = Message::new();
+        message.set_response_code(code);
+        Ok(DnsResponse::from_message(message).unwrap())
+    }
+
+    fn timeout() -> ClientResult<DnsResponse> {
+        Err(ClientError::from(
+            hickory_client::error::ClientErrorKind::Timeout,
+        ))
+    }
+
+    #[test]
+    fn the_default_sends_timeouts_again_right_away() {
+        let policy = QueryPolicy::default();
+        assert_eq!(policy.attempts(), 3);
+        assert_eq!(policy.timeout(), Duration::from_secs(5));
+        assert!(policy.tcp_fallback());
+        assert_eq!(policy.retry_after(1, &timeout()), Some(Duration::ZERO));
+        assert_eq!(policy.retry_after(2, &timeout()), Some(Duration::ZERO));
+        assert_eq!(policy.retry_after(3, &timeout()), None);
+        // Any response is the answer of the server
+        assert_eq!(policy.retry_after(1, &answer(ResponseCode::ServFail)), None);
+        assert_eq!(
+            policy.retry_after(1, &Err(ClientError::from("refused"))),
+            None
+        );
+    }
+
+    #[test]
+    fn the_backoff_doubles_after_every_attempt() {
+        let policy = QueryPolicy::default()
+            .with_attempts(5)
+            .with_backoff(Duration::from_millis(100));
+        let waits: Vec<_> = (1..=5)
+            .map(|attempt| policy.retry_after(attempt, &timeout()))
+            .collect();
+        assert_eq!(
+            waits,
+            [100, 200, 400, 800]
+                .map(|ms| Some(Duration::from_millis(ms)))
+                .into_iter()
+                .chain([None])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn response_codes_of_the_set_are_sent_again() {
+        let policy = QueryPolicy::default().with_retry_on(&[
+            ResponseCode::ServFail,
+            ResponseCode::Refused,
+            ResponseCode::ServFail,
+        ]);
+        assert_eq!(
+            policy.retry_on(),
+            [ResponseCode::ServFail, ResponseCode::Refused]
+        );
+        for code in [ResponseCode::ServFail, ResponseCode::Refused] {
+            assert_eq!(policy.retry_after(1, &answer(code)), Some(Duration::ZERO));
+            assert_eq!(policy.retry_after(3, &answer(code)), None);
+        }
+        for code in [ResponseCode::NoError, ResponseCode::NXDomain] {
+            assert_eq!(policy.retry_after(1, &answer(code)), None);
+        }
+        assert_eq!(parse_retry_code("servfail"), Ok(ResponseCode::ServFail));
+        assert_eq!(
+            parse_retry_code("broken"),
+            Err(String::from(
+                "unknown rcode \"broken\", e.g. SERVFAIL or REFUSED"
+            ))
+        );
+    }
+
+    #[test]
+    fn one_attempt_at_least_is_made() {
+        let policy = QueryPolicy::default().with_attempts(0);
+        assert_eq!(policy.attempts(), 1);
+        assert_eq!(policy.retry_after(1, &timeout()), None);
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    op::DnsResponse,
+    rr::{rdata::svcb::SvcParamValue, DNSClass, Name, RData, RecordType},
+};
+
+use crate::{asn::address_of, DnsClient};
+
+/// Timeout of every connection attempt, and of the lookups of target addresses
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Upper bound of connection attempts made at the same time
+const MAX_CONCURRENT_PROBES: usize = 8;
+
+/// Port of HTTPS records without a `port` parameter
+const HTTPS_PORT: u16 = 443;
+
+/// A target advertised by an SRV or HTTPS record
+struct Target {
+    priority: u16,
+    weight: u16,
+    name: Name,
+    port: u16,
+}
+
+/// Outcome of connecting to one address of a target
+pub struct Probe {
+    priority: u16,
+    target: Name,
+    address: Option<SocketAddr>,
+    result: Result<Duration, String>,
+}
+
+impl Probe {
+    pub fn is_reachable(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+impl Display for Probe {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let address = self
+            .address
+            .map(|address| address.to_string())
+            .unwrap_or_else(|| String::from("no address"));
+        write!(
+            f,
+            "  priority {} {} ({}): ",
+            self.priority, self.target, address
+        )?;
+        match &self.result {
+            Ok(latency) => write!(f, "reachable in {} ms", latency.as_millis()),
+            Err(error) => write!(f, "unreachable, {}", error),
+        }
+    }
+}
+
+/// Targets of the SRV and HTTPS answers, most preferred first
+fn targets(responses: &[(RecordType, DnsResponse)]) -> Vec<Target> {
+    let mut targets: Vec<Target> = responses
+        .iter()
+        .flat_map(|(_, response)| response.answers())
+        .filter_map(|record| match record.data() {
+            Some(RData::SRV(srv)) if !srv.target().is_root() => Some(Target {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                name: srv.target().clone(),
+                port: srv.port(),
+            }),
+            Some(RData::HTTPS(https)) => {
+                let svcb = &https.0;
+                let port = svcb
+                    .svc_params()
+                    .iter()
+                    .find_map(|(_, value)| match value {
+                        SvcParamValue::Port(port) => Some(*port),
+                        _ => None,
+                    })
+                    .unwrap_or(HTTPS_PORT);
+                // A target of "." stands for the owner of the record
+                let name = match svcb.target_name().is_root() {
+                    true => record.name().clone(),
+                    false => svcb.target_name().clone(),
+                };
+                Some(Target {
+                    priority: svcb.svc_priority(),
+                    weight: 0,
+                    name,
+                    port,
+                })
+            },
+            _ => None,
+        })
+        .collect();
+    // Higher weights are more likely to be picked among targets of the same priority
+    targets.sort_by_key(|target| (target.priority, u16::MAX - target.weight));
+    targets
+}
+
+/// Addresses of `name`, looked up through `client`
+fn resolve(
+    client: &DnsClient,
+    name: &Name,
+) -> Vec<IpAddr> {
+    [RecordType::A, RecordType::AAAA]
+        .iter()
+        .filter_map(|record_type| client.query(name, DNSClass::IN, *record_type).ok())
+        .flat_map(|response| {
+            response
+                .answers()
+                .iter()
+                .filter_map(address_of)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Connect to every address of every target and close right away, without sending data
+pub fn probe(
+    client: &DnsClient,
+    responses: &[(RecordType, DnsResponse)],
+) -> Vec<Probe> {
+    let targets = targets(responses);
+
+    let resolved: Vec<(Target, Vec<IpAddr>)> = thread::scope(|scope| {
+        let handles: Vec<_> = targets
+            .into_iter()
+            .map(|target| {
+                scope.spawn(move || {
+                    let addresses = resolve(client, &target.name);
+                    (target, addresses)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .collect()
+    });
+
+    let mut pending: Vec<(u16, Name, Option<SocketAddr>)> = Vec::new();
+    for (target, addresses) in resolved {
+        if addresses.is_empty() {
+            pending.push((target.priority, target.name.clone(), None));
+        }
+        for address in addresses {
+            let address = SocketAddr::new(address, target.port);
+            pending.push((target.priority, target.name.clone(), Some(address)));
+        }
+    }
+
+    let mut probes: Vec<Probe> = Vec::with_capacity(pending.len());
+    for chunk in pending.chunks(MAX_CONCURRENT_PROBES) {
+        let chunk_probes: Vec<Probe> = thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|(priority, target, address)| {
+                    scope.spawn(move || {
+                        let result = match address {
+                            Some(address) => {
+                                let started = Instant::now();
+                                TcpStream::connect_timeout(address, PROBE_TIMEOUT)
+                                    .map(|_| started.elapsed())
+                                    .map_err(|error| error.to_string())
+                            },
+                            None => Err(String::from("the target does not resolve")),
+                        };
+                        Probe {
+                            priority: *priority,
+                            target: target.clone(),
+                            address: *address,
+                            result,
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+        probes.extend(chunk_probes);
+    }
+    probes
+}
@@ -0,0 +1,878 @@
+use std::{
+    fmt::Display,
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, TrySendError},
+        Mutex,
+    },
+    thread::{self, Scope},
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use hickory_client::{
+    error::ClientResult,
+    op::{DnsResponse, Message, MessageType, ResponseCode},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    answer_cache::{AnswerCache, Negative},
+    outln, resolv_conf,
+    routes::Router,
+    schema,
+    stats::Stats,
+    stats_http,
+    ttl_rewrite::TtlBounds,
+    verbosity::{self, Verbosity},
+    AppError, ConnectionType, DnsClient, ResolveOptions,
+};
+
+/// How often the listeners stop waiting to tell whether to shut down
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// TCP connections of clients that send nothing for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set once SIGINT or SIGTERM arrives, the listeners then stop accepting queries
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Set when SIGHUP asks to read the rules again
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+#[derive(Args)]
+pub struct ProxyArgs {
+    /// address to accept plain dns queries on, over both UDP and TCP
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:5353")]
+    listen: SocketAddr,
+
+    /// dns server to forward the queries to, the first name server of the system by default,
+    /// or 1.1.1.1 with connections other than udp and tcp
+    #[arg(short, long)]
+    server: Option<String>,
+
+    /// forward the queries for some names to other servers, as the rules of the file tell
+    ///
+    /// The file is TOML with a [[rule]] table per suffix, e.g. suffix = "*.corp.example" and
+    /// server = "10.0.0.53", and optionally a connection and a timeout of its own. The rule of
+    /// the longest suffix of a name wins, and names that no rule matches go to --server. The
+    /// rules are read again on SIGHUP and whenever the file changes, the previous ones serving
+    /// until the new ones parse.
+    #[arg(long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+
+    /// connection type to the server
+    #[arg(long, short, default_value_t = ConnectionType::Udp)]
+    connection: ConnectionType,
+
+    /// name to verify the certificate of a tls server against, by default its address
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "NAME")]
+    tls_hostname: Option<String>,
+
+    /// do not verify the certificate of a tls server
+    #[cfg(feature = "tls")]
+    #[arg(long, default_value_t = false)]
+    insecure: bool,
+
+    /// wait this long for the response of the server to a query, e.g. 1s, shorter than for
+    /// lookups so that clients get SERVFAIL before they give up themselves
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = crate::parse::parse_duration)]
+    timeout: Duration,
+
+    /// forward a query that timed out this many more times before answering SERVFAIL
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    retries: usize,
+
+    /// forward this many queries at most at the same time, answering SERVFAIL right away to
+    /// the UDP queries that find as many already waiting
+    #[arg(long, value_name = "N", default_value_t = 64)]
+    max_in_flight: usize,
+
+    /// keep this many TCP connections of clients open at most, closing new ones beyond them
+    #[arg(long, value_name = "N", default_value_t = 128)]
+    max_connections: usize,
+
+    /// raise the TTLs of the relayed records to at least this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    ttl_min: Option<u32>,
+
+    /// lower the TTLs of the relayed records to at most this many seconds, winning over
+    /// --ttl-min
+    #[arg(long, value_name = "SECONDS")]
+    ttl_max: Option<u32>,
+
+    /// also rewrite SOA records, and so how long negative answers are cached
+    #[arg(long, default_value_t = false)]
+    rewrite_negative: bool,
+
+    /// print every record whose TTL is rewritten, and the rule that routed every query
+    ///
+    /// SIGUSR1 moves to the next verbosity while the proxy runs: from quiet, which leaves out
+    /// the log of the queries, to normal, verbose, and debug, which prints every forwarded
+    /// query with its server and time, and back to quiet.
+    #[arg(long, default_value_t = false)]
+    verbose: bool,
+
+    /// keep the statistics of about this many clients, and as many names, dropping those seen
+    /// least recently
+    ///
+    /// The proxy counts the queries, NXDOMAIN answers and mean latency of every client address
+    /// and every queried name, and when each was last seen. SIGUSR2 prints them on stderr as
+    /// JSON, the busiest first, with the queries forwarded and in flight, and a POST to
+    /// /stats/reset of --stats-listen starts them over.
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    stats_top: usize,
+
+    /// serve the statistics over HTTP on this address, as JSON at /stats, or as text at
+    /// /stats?format=text, a POST to /stats/reset starting them over
+    #[arg(long, value_name = "ADDR")]
+    stats_listen: Option<SocketAddr>,
+
+    /// answer repeated queries from a cache of up to this many answers, dropping those used
+    /// least recently
+    ///
+    /// Answers are kept for as long as their TTLs tell, negative ones for as long as the SOA
+    /// record of the answer tells, and served with their TTLs lowered by the time they were
+    /// kept. Truncated answers are never cached. SIGUSR2 prints the hits, misses and evictions
+    /// of the cache with the statistics.
+    #[arg(long, value_name = "N")]
+    cache_size: Option<usize>,
+
+    /// keep the cached answers but SERVFAIL, and serve their records, for at least this many
+    /// seconds
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 0,
+        requires = "cache_size"
+    )]
+    cache_min_ttl: u32,
+
+    /// do not cache these negative answers, may be repeated or separated by comma
+    #[arg(
+        long,
+        value_enum,
+        value_name = "OUTCOME",
+        value_delimiter = ',',
+        requires = "cache_size"
+    )]
+    no_cache_rcode: Vec<Negative>,
+
+    /// format of the log of the forwarded queries
+    #[arg(long, short, value_enum, default_value_t = LogFormat::Plain)]
+    output: LogFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+#[clap(rename_all = "kebab_case")]
+enum LogFormat {
+    Plain,
+    Jsonl,
+}
+
+/// One query of a client and how it was answered
+#[derive(Serialize)]
+struct Transaction {
+    client: SocketAddr,
+    transport: &'static str,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    code: String,
+    answers: usize,
+    elapsed_ms: u128,
+    /// answered from the cache
+    cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Display for Transaction {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} answers {}ms",
+            self.client,
+            self.transport,
+            self.name,
+            self.record_type,
+            self.code,
+            self.answers,
+            self.elapsed_ms
+        )?;
+        if self.cached {
+            write!(f, " cached")?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " ({})", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the listeners share
+struct Proxy {
+    client: DnsClient,
+    output: LogFormat,
+    ttls: TtlBounds,
+    router: Option<Router>,
+    stats: Stats,
+    cache: Option<AnswerCache>,
+    max_in_flight: usize,
+    in_flight: AtomicUsize,
+    max_connections: usize,
+    connections: AtomicUsize,
+    forwarded: AtomicUsize,
+}
+
+impl Proxy {
+    /// Answer of the server to the query in `bytes`, `None` for messages that are no query.
+    ///
+    /// Queries that come while the proxy is `overloaded` are answered from the cache or with
+    /// SERVFAIL, never forwarded.
+    fn answer(
+        &self,
+        bytes: &[u8],
+        client: SocketAddr,
+        transport: &'static str,
+        overloaded: bool,
+    ) -> Option<Message> {
+        let started = Instant::now();
+        let request = Message::from_bytes(bytes).ok()?;
+        if request.message_type() != MessageType::Query {
+            return None;
+        }
+        let Some(query) = request.queries().first().cloned() else {
+            return Some(reply_error(&request, ResponseCode::FormErr));
+        };
+
+        let cached = self.cache.as_ref().and_then(|cache| cache.get(&request));
+        let from_cache = cached.is_some();
+        let response = match cached {
+            Some(message) => Some(Ok(message)),
+            None if overloaded => None,
+            None => self.forward(&request).map(|response| {
+                response.map(|response| {
+                    let message = response.into_message();
+                    if let Some(cache) = &self.cache {
+                        cache.insert(&request, message.clone());
+                    }
+                    message
+                })
+            }),
+        };
+        let (reply, error) = match response {
+            Some(Ok(message)) => {
+                let mut reply = relay(&request, message);
+                self.ttls.apply(&mut reply);
+                (reply, None)
+            },
+            Some(Err(error)) => (
+                reply_error(&request, ResponseCode::ServFail),
+                Some(error.to_string()),
+            ),
+            None => (
+                reply_error(&request, ResponseCode::ServFail),
+                Some(String::from(
+                    "too many queries in flight, see --max-in-flight",
+                )),
+            ),
+        };
+        let elapsed = started.elapsed();
+        let name = query.name().to_string();
+        self.stats.count(
+            &client.ip().to_string(),
+            &name,
+            reply.response_code() == ResponseCode::NXDomain,
+            elapsed,
+        );
+        self.log(&Transaction {
+            client,
+            transport,
+            name,
+            record_type: query.query_type().to_string(),
+            code: format!("{:?}", reply.response_code()).to_uppercase(),
+            answers: reply.answers().len(),
+            elapsed_ms: elapsed.as_millis(),
+            cached: from_cache,
+            error,
+        });
+        Some(reply)
+    }
+
+    /// Response of the server to `request`, a query with a question, `None` when too many are
+    /// forwarded already
+    fn forward(
+        &self,
+        request: &Message,
+    ) -> Option<ClientResult<DnsResponse>> {
+        let query = request.queries().first()?;
+        if self.in_flight.fetch_add(1, Ordering::SeqCst) >= self.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        // Held until the query returns, so that reloads don't pull the rules from under it
+        let rules = self.router.as_ref().map(Router::rules);
+        let rule = rules.as_ref().and_then(|rules| rules.route(query.name()));
+        if verbosity::at_least(Verbosity::Verbose) {
+            match rule {
+                Some(rule) => eprintln!(
+                    "{} {} routed by the rule on {}",
+                    query.name(),
+                    query.query_type(),
+                    rule.label()
+                ),
+                None if rules.is_some() => eprintln!(
+                    "{} {} matches no rule, forwarded to the default server",
+                    query.name(),
+                    query.query_type()
+                ),
+                None => {},
+            }
+        }
+        let client = rule.map_or(&self.client, |rule| &rule.client);
+        // Every query is forwarded as the client sent it, e.g. with its DO and CD bits, the
+        // answers of the run are not shared as in other modes
+        let started = Instant::now();
+        let response = client.forward(request);
+        if verbosity::at_least(Verbosity::Debug) {
+            eprintln!(
+                "{} {} forwarded to {} in {}ms",
+                query.name(),
+                query.query_type(),
+                client
+                    .server()
+                    .unwrap_or_else(|| String::from("the mock answers")),
+                started.elapsed().as_millis()
+            );
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.forwarded.fetch_add(1, Ordering::Relaxed);
+        Some(response)
+    }
+
+    /// The counters of the clients and names, the queries, the cache and the rules, dumped on
+    /// SIGUSR2 and served at --stats-listen
+    fn stats_json(&self) -> Value {
+        let mut json = self.stats.to_json();
+        json["schema"] = Value::from(schema::SCHEMA);
+        json["forwarded"] = Value::from(self.forwarded.load(Ordering::Relaxed));
+        json["in_flight"] = Value::from(self.in_flight.load(Ordering::SeqCst));
+        if let Some(cache) = &self.cache {
+            json["cache"] = cache.to_json();
+        }
+        if let Some(router) = &self.router {
+            json["rules"] = router.to_json();
+        }
+        json
+    }
+
+    fn log(
+        &self,
+        transaction: &Transaction,
+    ) {
+        if !verbosity::at_least(Verbosity::Normal) {
+            return;
+        }
+        match self.output {
+            LogFormat::Plain => outln!("{}", transaction),
+            LogFormat::Jsonl => outln!("{}", schema::versioned(transaction)),
+        }
+    }
+
+    /// Answer the UDP queries with `--max-in-flight` workers, the queries waiting for them
+    /// in a queue as long, so that a flood of queries takes neither unbounded threads nor
+    /// memory
+    fn serve_udp(
+        &self,
+        socket: &UdpSocket,
+    ) {
+        let (queue, queued) = mpsc::sync_channel::<(Vec<u8>, SocketAddr)>(self.max_in_flight);
+        let queued = Mutex::new(queued);
+        let reply = |bytes: &[u8], client: SocketAddr, overloaded: bool| {
+            let Some(reply) = self.answer(bytes, client, "udp", overloaded) else {
+                return;
+            };
+            if let Ok(reply) = fit(reply, max_payload(bytes)).to_bytes() {
+                let _ = socket.send_to(&reply, client);
+            }
+        };
+        thread::scope(|scope| {
+            for _ in 0..self.max_in_flight.max(1) {
+                scope.spawn(|| loop {
+                    // The lock is only held while waiting, not while answering
+                    let next = queued.lock().unwrap().recv();
+                    match next {
+                        Ok((bytes, client)) => reply(&bytes, client, false),
+                        Err(_) => break,
+                    }
+                });
+            }
+
+            let mut buffer = [0; u16::MAX as usize];
+            while !SHUTDOWN.load(Ordering::Relaxed) {
+                let (length, client) = match socket.recv_from(&mut buffer) {
+                    Ok(received) => received,
+                    Err(error) if is_timeout(&error) => continue,
+                    Err(error) => {
+                        eprintln!("udp: {}", error);
+                        continue;
+                    },
+                };
+                match queue.try_send((buffer[..length].to_vec(), client)) {
+                    Ok(()) => {},
+                    Err(TrySendError::Full((bytes, client))) => reply(&bytes, client, true),
+                    Err(TrySendError::Disconnected(_)) => break,
+                }
+            }
+            // The workers stop once the queue is empty
+            drop(queue);
+        });
+    }
+
+    fn serve_tcp<'scope>(
+        &'scope self,
+        scope: &'scope Scope<'scope, '_>,
+        listener: &TcpListener,
+    ) {
+        while !SHUTDOWN.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, client)) => {
+                    if self.connections.fetch_add(1, Ordering::SeqCst) >= self.max_connections {
+                        self.connections.fetch_sub(1, Ordering::SeqCst);
+                        eprintln!(
+                            "tcp {}: too many connections, see --max-connections",
+                            client
+                        );
+                        continue;
+                    }
+                    scope.spawn(move || {
+                        if let Err(error) = self.serve_connection(stream, client) {
+                            if !matches!(error.kind(), ErrorKind::UnexpectedEof)
+                                && !is_timeout(&error)
+                            {
+                                eprintln!("tcp {}: {}", client, error);
+                            }
+                        }
+                        self.connections.fetch_sub(1, Ordering::SeqCst);
+                    });
+                },
+                Err(error) if is_timeout(&error) => thread::sleep(POLL_INTERVAL),
+                Err(error) => eprintln!("tcp: {}", error),
+            }
+        }
+    }
+
+    /// Answer the requests for the statistics, one connection after the other
+    /// Start the counters of the clients and names, the cache and the rules over, for a POST
+    /// to `/stats/reset`
+    fn reset_stats(&self) {
+        self.stats.reset();
+        if let Some(cache) = &self.cache {
+            cache.reset();
+        }
+        if let Some(router) = &self.router {
+            router.reset();
+        }
+        eprintln!("statistics reset");
+    }
+
+    fn serve_stats(
+        &self,
+        listener: &TcpListener,
+    ) {
+        while !SHUTDOWN.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((mut stream, client)) => {
+                    let served = stream
+                        .set_nonblocking(false)
+                        .and_then(|_| stream.set_read_timeout(Some(IDLE_TIMEOUT)))
+                        .and_then(|_| {
+                            stats_http::respond(
+                                &mut stream,
+                                || self.stats_json(),
+                                || self.reset_stats(),
+                            )
+                        });
+                    if let Err(error) = served {
+                        eprintln!("stats {}: {}", client, error);
+                    }
+                },
+                Err(error) if is_timeout(&error) => thread::sleep(POLL_INTERVAL),
+                Err(error) => eprintln!("stats: {}", error),
+            }
+        }
+    }
+
+    /// Answer the queries of one client in turn, until it closes the connection or goes idle
+    fn serve_connection(
+        &self,
+        mut stream: TcpStream,
+        client: SocketAddr,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        loop {
+            let mut length = [0; 2];
+            read_exactly(&mut stream, &mut length)?;
+            let mut bytes = vec![0; u16::from_be_bytes(length) as usize];
+            read_exactly(&mut stream, &mut bytes)?;
+            let Some(reply) = self.answer(&bytes, client, "tcp", false) else {
+                continue;
+            };
+            if let Ok(reply) = reply.to_bytes() {
+                stream.write_all(&(reply.len() as u16).to_be_bytes())?;
+                stream.write_all(&reply)?;
+            }
+        }
+    }
+}
+
+impl ProxyArgs {
+    pub fn run(&self) -> Result<(), AppError> {
+        // The name servers of the system rarely speak anything but plain DNS
+        let plain = matches!(self.connection, ConnectionType::Udp | ConnectionType::Tcp);
+        let server = match &self.server {
+            Some(server) => server.clone(),
+            None if plain => resolv_conf::default_server(),
+            None => String::from(resolv_conf::FALLBACK_SERVER),
+        };
+        let options = ResolveOptions::new(self.connection, &server)
+            .with_timeout(self.timeout)
+            .with_retries(self.retries);
+        #[cfg(feature = "tls")]
+        let options = options.with_tls(self.tls_hostname.clone(), self.insecure);
+        let client = DnsClient::new(&options)?;
+        let router = match &self.rules {
+            Some(path) => Some(Router::new(path, &options)?),
+            None => None,
+        };
+
+        let listen_error = |error: io::Error| AppError::ListenError(self.listen, error.to_string());
+        let socket = UdpSocket::bind(self.listen).map_err(listen_error)?;
+        socket
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(listen_error)?;
+        let listener = TcpListener::bind(self.listen).map_err(listen_error)?;
+        listener.set_nonblocking(true).map_err(listen_error)?;
+        let stats_listener = match self.stats_listen {
+            Some(addr) => {
+                let error = |error: io::Error| AppError::ListenError(addr, error.to_string());
+                let listener = TcpListener::bind(addr).map_err(error)?;
+                listener.set_nonblocking(true).map_err(error)?;
+                eprintln!("serving the statistics on http://{}/stats", addr);
+                Some(listener)
+            },
+            None => None,
+        };
+
+        verbosity::set(Verbosity::of(false, self.verbose));
+        handle_signals();
+        verbosity::listen(true);
+        eprintln!(
+            "listening on {} over udp and tcp, forwarding to {} over {}",
+            self.listen,
+            client.server().unwrap_or(server),
+            self.connection
+        );
+
+        let proxy = Proxy {
+            client,
+            output: self.output,
+            stats: Stats::new(self.stats_top),
+            router,
+            cache: self.cache_size.map(|capacity| {
+                AnswerCache::new(capacity, self.cache_min_ttl, self.no_cache_rcode.clone())
+            }),
+            ttls: TtlBounds::new(self.ttl_min, self.ttl_max, self.rewrite_negative),
+            max_in_flight: self.max_in_flight,
+            in_flight: AtomicUsize::new(0),
+            max_connections: self.max_connections,
+            connections: AtomicUsize::new(0),
+            forwarded: AtomicUsize::new(0),
+        };
+        thread::scope(|scope| {
+            scope.spawn(|| proxy.serve_udp(&socket));
+            scope.spawn(|| proxy.serve_tcp(scope, &listener));
+            if let Some(listener) = &stats_listener {
+                scope.spawn(|| proxy.serve_stats(listener));
+            }
+            // The scope ends once the listeners have stopped and every query is answered
+            while !SHUTDOWN.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                verbosity::poll(|| serde_json::to_string_pretty(&proxy.stats_json()).unwrap());
+                let reload = RELOAD.swap(false, Ordering::Relaxed);
+                if let Some(router) = &proxy.router {
+                    if reload || router.changed() {
+                        router.reload();
+                    }
+                }
+            }
+            let in_flight = proxy.in_flight.load(Ordering::SeqCst);
+            if in_flight > 0 {
+                eprintln!("shutting down, waiting for {} queries in flight", in_flight);
+            }
+        });
+        eprintln!(
+            "forwarded {} queries",
+            proxy.forwarded.load(Ordering::Relaxed)
+        );
+        Ok(())
+    }
+}
+
+/// `response` of the server, with its header bits, as a reply to `request`
+fn relay(
+    request: &Message,
+    response: Message,
+) -> Message {
+    let mut reply = response;
+    reply.set_id(request.id());
+    // The question as the client asked it, whatever the server echoed
+    reply.take_queries();
+    reply.add_queries(request.queries().to_vec());
+    // The signature of the server was checked, and is not meant for the client
+    reply.take_signature();
+    // Clients that sent no OPT record must not get one, RFC 6891
+    if request.extensions().is_none() {
+        *reply.extensions_mut() = None;
+    }
+    reply
+}
+
+fn reply_error(
+    request: &Message,
+    code: ResponseCode,
+) -> Message {
+    let mut reply = Message::error_msg(request.id(), request.op_code(), code);
+    reply
+        .set_recursion_desired(request.recursion_desired())
+        .add_queries(request.queries().to_vec());
+    reply
+}
+
+/// Largest UDP reply the client of the query in `bytes` takes, 512 bytes without EDNS
+fn max_payload(bytes: &[u8]) -> usize {
+    Message::from_bytes(bytes).map_or(512, |request| request.max_payload() as usize)
+}
+
+/// `reply` as it is when it fits into `max_payload` bytes, otherwise its header and question
+/// with the TC bit, so that the client asks again over TCP
+fn fit(
+    reply: Message,
+    max_payload: usize,
+) -> Message {
+    match reply.to_bytes() {
+        Ok(bytes) if bytes.len() <= max_payload => reply,
+        _ => {
+            let mut truncated = reply;
+            truncated.take_answers();
+            truncated.take_name_servers();
+            truncated.take_additionals();
+            truncated.set_truncated(true);
+            truncated
+        },
+    }
+}
+
+/// Whether the error only tells that nothing came within the poll interval
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Fill `buffer` from the stream, whose reads time out every poll interval, giving up when
+/// shutting down or once the client has been idle for too long
+fn read_exactly(
+    stream: &mut TcpStream,
+    buffer: &mut [u8],
+) -> io::Result<()> {
+    let started = Instant::now();
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match stream.read(&mut buffer[filled..]) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(read) => filled += read,
+            Err(error) if is_timeout(&error) => {
+                if SHUTDOWN.load(Ordering::Relaxed) || started.elapsed() > IDLE_TIMEOUT {
+                    return Err(error);
+                }
+            },
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// Shut down gracefully on the first SIGINT or SIGTERM, and right away on the second. Read
+/// the rules again on SIGHUP, SIGUSR1 and SIGUSR2 being those of `verbosity::listen`.
+#[cfg(unix)]
+fn handle_signals() {
+    extern "C" fn shut_down(signal: libc::c_int) {
+        SHUTDOWN.store(true, Ordering::Relaxed);
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+    extern "C" fn reload(_: libc::c_int) {
+        RELOAD.store(true, Ordering::Relaxed);
+    }
+    let handlers = [
+        (libc::SIGINT, shut_down as *const ()),
+        (libc::SIGTERM, shut_down as *const ()),
+        (libc::SIGHUP, reload as *const ()),
+    ];
+    for (signal, handler) in handlers {
+        unsafe {
+            libc::signal(signal, handler as libc::sighandler_t);
+        }
+    }
+}
+
+/// Other systems end the proxy abruptly
+#[cfg(not(unix))]
+fn handle_signals() {}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use hickory_client::{
+        op::{Edns, Query},
+        rr::{Name, RecordType},
+    };
+
+    use super::*;
+    use crate::mock::Fixture;
+
+    const FIXTURE: &str = r#"
+        [[answer]]
+        name = "example.com"
+        type = "A"
+        flags = ["rd", "ra", "ad"]
+        records = ["192.0.2.1"]
+    "#;
+
+    fn proxy() -> Proxy {
+        let fixture = Fixture::from_toml(FIXTURE).unwrap();
+        let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+            .with_mock(Some(Arc::new(fixture)));
+        Proxy {
+            client: DnsClient::new(&options).unwrap(),
+            output: LogFormat::Jsonl,
+            ttls: TtlBounds::new(None, None, false),
+            router: None,
+            stats: Stats::new(10),
+            cache: None,
+            max_in_flight: 4,
+            in_flight: AtomicUsize::new(0),
+            max_connections: 4,
+            connections: AtomicUsize::new(0),
+            forwarded: AtomicUsize::new(0),
+        }
+    }
+
+    fn request(name: &str) -> Message {
+        let mut message = Message::new();
+        message
+            .set_id(4321)
+            .set_message_type(MessageType::Query)
+            .set_recursion_desired(true)
+            .add_query(Query::query(Name::from_str(name).unwrap(), RecordType::A));
+        message
+    }
+
+    fn client() -> SocketAddr {
+        "127.0.0.1:50000".parse().unwrap()
+    }
+
+    #[test]
+    fn replies_keep_the_id_and_question_of_the_client_and_the_flags_of_the_server() {
+        let proxy = proxy();
+        let request = request("example.com.");
+        let reply = proxy
+            .answer(&request.to_bytes().unwrap(), client(), "udp", false)
+            .unwrap();
+        assert_eq!(reply.id(), 4321);
+        assert_eq!(reply.response_code(), ResponseCode::NoError);
+        assert!(reply.authentic_data() && reply.recursion_available());
+        assert_eq!(reply.queries(), request.queries());
+        assert_eq!(reply.answers().len(), 1);
+        assert_eq!(proxy.forwarded.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn replies_carry_edns_only_for_clients_that_sent_it() {
+        let mut relayed = request("example.com.");
+        relayed.set_message_type(MessageType::Response);
+        relayed.set_edns(Edns::new());
+        let reply = relay(&request("example.com."), relayed.clone());
+        assert!(reply.extensions().is_none());
+        let mut with_edns = request("example.com.");
+        with_edns.set_edns(Edns::new());
+        assert!(relay(&with_edns, relayed).extensions().is_some());
+    }
+
+    #[test]
+    fn overloaded_proxies_answer_servfail_without_forwarding() {
+        let proxy = proxy();
+        let bytes = request("example.com.").to_bytes().unwrap();
+        let reply = proxy.answer(&bytes, client(), "udp", true).unwrap();
+        assert_eq!(reply.response_code(), ResponseCode::ServFail);
+        assert_eq!(reply.id(), 4321);
+        assert_eq!(proxy.forwarded.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn log_lines_and_counters_match_their_schemas() {
+        let proxy = proxy();
+        proxy.answer(
+            &request("example.com.").to_bytes().unwrap(),
+            client(),
+            "udp",
+            false,
+        );
+        let transaction = Transaction {
+            client: client(),
+            transport: "tcp",
+            name: String::from("example.com."),
+            record_type: String::from("A"),
+            code: String::from("SERVFAIL"),
+            answers: 0,
+            elapsed_ms: 2000,
+            cached: false,
+            error: Some(String::from("request timed out")),
+        };
+        let line = schema::versioned(&transaction);
+        schema::validate(&schema::schema(schema::Output::ProxyLog), &line).unwrap();
+        let stats = proxy.stats_json();
+        schema::validate(&schema::schema(schema::Output::ProxyStats), &stats).unwrap();
+        assert_eq!(stats["clients"][0]["queries"], 1);
+    }
+
+    #[test]
+    fn replies_too_large_for_the_client_are_truncated() {
+        let mut reply = request("example.com.");
+        reply.set_message_type(MessageType::Response);
+        for index in 0..64 {
+            let record = hickory_client::rr::Record::from_rdata(
+                Name::from_str("example.com.").unwrap(),
+                300,
+                hickory_client::rr::RData::A(hickory_client::rr::rdata::A::new(192, 0, 2, index)),
+            );
+            reply.add_answer(record);
+        }
+        let truncated = fit(reply.clone(), 512);
+        assert!(truncated.truncated());
+        assert!(truncated.answers().is_empty());
+        assert_eq!(fit(reply, 4096).answers().len(), 64);
+    }
+}
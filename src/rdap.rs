@@ -0,0 +1,284 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hickory_client::rr::{DNSClass, Name, RData, Record, RecordType};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::DnsClient;
+
+/// IANA registry of the RDAP servers of top level domains
+const BOOTSTRAP_URL: &str = "https://data.iana.org/rdap/dns.json";
+
+/// Timeout of every RDAP request
+pub const RDAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Registrations expiring within this many days are warned about
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+struct Bootstrap {
+    /// pairs of top level domains and the base URLs of their RDAP servers
+    services: Vec<(Vec<String>, Vec<String>)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DomainResponse {
+    #[serde(default)]
+    events: Vec<Event>,
+    #[serde(default)]
+    status: Vec<String>,
+    #[serde(default)]
+    nameservers: Vec<Nameserver>,
+    #[serde(default)]
+    entities: Vec<Entity>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    event_action: String,
+    event_date: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Nameserver {
+    ldh_name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entity {
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default)]
+    vcard_array: Value,
+}
+
+impl Entity {
+    /// The `fn` property of the jCard of the entity
+    fn full_name(&self) -> Option<String> {
+        self.vcard_array
+            .get(1)?
+            .as_array()?
+            .iter()
+            .find(|property| property.get(0).and_then(Value::as_str) == Some("fn"))?
+            .get(3)?
+            .as_str()
+            .map(ToOwned::to_owned)
+    }
+}
+
+/// Registration facts of a domain, as listed by its registry
+pub struct Registration {
+    pub domain: String,
+    pub registrar: Option<String>,
+    pub created: Option<String>,
+    pub expires: Option<String>,
+    pub status: Vec<String>,
+    pub nameservers: Vec<String>,
+    /// name servers answered over DNS, compared against the registry
+    pub live_nameservers: Vec<String>,
+}
+
+fn get_json<T: for<'de> Deserialize<'de>>(
+    agent: &ureq::Agent,
+    url: &str,
+) -> Result<T, String> {
+    let response = agent
+        .get(url)
+        .set("accept", "application/rdap+json, application/json")
+        .call()
+        .map_err(|error| error.to_string())?;
+    serde_json::from_reader(response.into_reader())
+        .map_err(|error| format!("invalid response from {}: {}", url, error))
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+/// Look up the registration of the registrable domain of `name`.
+///
+/// The registrable domain is the longest top level domain known to the bootstrap registry
+/// plus one label.
+pub fn lookup(name: &Name) -> Result<Registration, String> {
+    let agent = ureq::AgentBuilder::new().timeout(RDAP_TIMEOUT).build();
+    let bootstrap: Bootstrap = get_json(&agent, BOOTSTRAP_URL)?;
+
+    let ascii = normalize(&name.to_ascii());
+    let labels: Vec<&str> = ascii.split('.').collect();
+    let (domain, base_url) = (1..labels.len())
+        .find_map(|start| {
+            let suffix = labels[start..].join(".");
+            let (_, urls) = bootstrap
+                .services
+                .iter()
+                .find(|(tlds, _)| tlds.iter().any(|tld| normalize(tld) == suffix))?;
+            Some((labels[start - 1..].join("."), urls.first()?.clone()))
+        })
+        .ok_or_else(|| format!("no RDAP server is known for {}", ascii))?;
+
+    let url = format!("{}/domain/{}", base_url.trim_end_matches('/'), domain);
+    let response: DomainResponse = get_json(&agent, &url)?;
+
+    let event = |action: &str| {
+        response
+            .events
+            .iter()
+            .find(|event| event.event_action == action)
+            .map(|event| event.event_date.clone())
+    };
+    let registrar = response
+        .entities
+        .iter()
+        .find(|entity| entity.roles.iter().any(|role| role == "registrar"))
+        .and_then(Entity::full_name);
+
+    Ok(Registration {
+        registrar,
+        created: event("registration"),
+        expires: event("expiration"),
+        status: response.status,
+        nameservers: response
+            .nameservers
+            .iter()
+            .map(|nameserver| normalize(&nameserver.ldh_name))
+            .collect(),
+        live_nameservers: Vec::new(),
+        domain,
+    })
+}
+
+/// Registration of the domain of `name`, with the name servers `client` finds for it over DNS
+pub fn registration(
+    client: &DnsClient,
+    name: &Name,
+) -> Result<Registration, String> {
+    let mut registration = lookup(name)?;
+    if let Ok(domain) = Name::from_ascii(&registration.domain) {
+        if let Ok(response) = client.query(&domain, DNSClass::IN, RecordType::NS) {
+            registration.set_live_nameservers(response.answers());
+        }
+    }
+    Ok(registration)
+}
+
+/// Days since the epoch of the date of an RFC 3339 timestamp
+pub(crate) fn days_since_epoch(timestamp: &str) -> Option<i64> {
+    let date = timestamp.get(..10)?;
+    let mut parts = date.split('-').map(str::parse::<i64>);
+    let (year, month, day) = (
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    );
+
+    // Days from civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+impl Registration {
+    /// Record the name servers answered over DNS to compare them against the registry
+    pub fn set_live_nameservers(
+        &mut self,
+        answers: &[Record],
+    ) {
+        self.live_nameservers = answers
+            .iter()
+            .filter_map(|record| match record.data() {
+                Some(RData::NS(nameserver)) => Some(normalize(&nameserver.0.to_ascii())),
+                _ => None,
+            })
+            .collect();
+    }
+
+    /// Registration as the `rdap` object of `--json`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "domain": self.domain,
+            "registrar": self.registrar,
+            "created": self.created,
+            "expires": self.expires,
+            "expires_in_days": self.expires_in_days(),
+            "status": self.status,
+            "nameservers": self.nameservers,
+            "live_nameservers": self.live_nameservers,
+        })
+    }
+
+    /// Days left until the registration expires
+    pub fn expires_in_days(&self) -> Option<i64> {
+        let expires = days_since_epoch(self.expires.as_deref()?)?;
+        let today = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs() / 86400;
+        Some(expires - today as i64)
+    }
+}
+
+impl Display for Registration {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let unknown = || String::from("unknown");
+        writeln!(f, "RDAP registration of {}:", self.domain)?;
+        writeln!(
+            f,
+            "  registrar:   {}",
+            self.registrar.clone().unwrap_or_else(unknown)
+        )?;
+        writeln!(
+            f,
+            "  created:     {}",
+            self.created.clone().unwrap_or_else(unknown)
+        )?;
+        write!(
+            f,
+            "  expires:     {}",
+            self.expires.clone().unwrap_or_else(unknown)
+        )?;
+        match self.expires_in_days() {
+            Some(days) if days < 0 => write!(f, " (expired {} days ago)", -days)?,
+            Some(days) if days <= EXPIRY_WARNING_DAYS => {
+                write!(f, " (warning: expires in {} days)", days)?
+            },
+            Some(days) => write!(f, " (in {} days)", days)?,
+            None => {},
+        }
+        writeln!(f)?;
+        writeln!(f, "  status:      {}", self.status.join(", "))?;
+        write!(f, "  nameservers: {}", self.nameservers.join(", "))?;
+
+        if !self.live_nameservers.is_empty() {
+            for nameserver in &self.nameservers {
+                if !self.live_nameservers.contains(nameserver) {
+                    write!(
+                        f,
+                        "\n  mismatch:    {} is listed by the registry but not answered over DNS",
+                        nameserver
+                    )?;
+                }
+            }
+            for nameserver in &self.live_nameservers {
+                if !self.nameservers.contains(nameserver) {
+                    write!(
+                        f,
+                        "\n  mismatch:    {} is answered over DNS but not listed by the registry",
+                        nameserver
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use hickory_client::{
+    rr::{Name, Record, RecordType},
+    serialize::{binary::BinEncodable, txt::Parser},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{format, output, schema, AppError};
+
+/// Size of responses that avoids fragmentation, as agreed for DNS flag day 2020
+const RESPONSE_BUDGET: usize = 1232;
+
+/// Signatures are estimated for ECDSA P-256 with SHA-256, the algorithm 13 most zones sign with
+const SIGNATURE_LENGTH: usize = 64;
+
+/// Fixed fields of RRSIG data before the signer name: type covered, algorithm, labels,
+/// original TTL, expiration, inception and key tag
+const RRSIG_FIXED_LENGTH: usize = 18;
+
+/// Type, class, TTL and data length of every record
+const RECORD_HEADER_LENGTH: usize = 10;
+
+/// Header of a DNS message
+const MESSAGE_HEADER_LENGTH: usize = 12;
+
+/// OPT record of a response with EDNS
+const OPT_LENGTH: usize = 11;
+
+/// Owner names of answers are compressed to a pointer to the question
+const POINTER_LENGTH: usize = 2;
+
+/// Types that a zone isn't supposed to have before it is signed
+const LEFTOVER_TYPES: [RecordType; 6] = [
+    RecordType::DNSKEY,
+    RecordType::DS,
+    RecordType::RRSIG,
+    RecordType::NSEC,
+    RecordType::NSEC3,
+    RecordType::NSEC3PARAM,
+];
+
+/// Types that the zone file parser refuses, as they are meant to be made when signing
+const SIGNING_TYPES: [&str; 9] = [
+    "DNSKEY",
+    "CDNSKEY",
+    "KEY",
+    "DS",
+    "CDS",
+    "RRSIG",
+    "NSEC",
+    "NSEC3",
+    "NSEC3PARAM",
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadinessFormat {
+    Plain,
+    Json,
+}
+
+/// An RRset whose signed answer is estimated to outgrow the budget
+#[derive(Serialize)]
+pub struct Oversized {
+    name: String,
+    record_type: String,
+    unsigned_bytes: usize,
+    signed_bytes: usize,
+}
+
+/// What will bite after signing a zone
+#[derive(Serialize)]
+pub struct Report {
+    zone: String,
+    source: String,
+    records: usize,
+    rrsets: usize,
+    unsigned_bytes: usize,
+    /// estimated with one RRSIG for every RRset and one NSEC for every name
+    signed_bytes: usize,
+    oversized: Vec<Oversized>,
+    apex_cname: bool,
+    duplicates: Vec<String>,
+    leftovers: Vec<String>,
+    notes: Vec<String>,
+}
+
+/// Length of `name` in wire format, without compression
+fn name_length(name: &Name) -> usize {
+    name.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+}
+
+fn data_length(record: &Record) -> usize {
+    record
+        .data()
+        .and_then(|data| data.to_bytes().ok())
+        .map(|bytes| bytes.len())
+        .unwrap_or_default()
+}
+
+fn rrsig_length(
+    owner_length: usize,
+    zone: &Name,
+) -> usize {
+    owner_length + RECORD_HEADER_LENGTH + RRSIG_FIXED_LENGTH + name_length(zone) + SIGNATURE_LENGTH
+}
+
+/// Length of the type bitmap of an NSEC record listing `types`, RRSIG and NSEC
+fn type_bitmap_length(types: &BTreeSet<u16>) -> usize {
+    let mut windows: BTreeMap<u16, u16> = BTreeMap::new();
+    let signed = [u16::from(RecordType::RRSIG), u16::from(RecordType::NSEC)];
+    for code in types.iter().chain(signed.iter()) {
+        let highest = windows.entry(code >> 8).or_default();
+        *highest = (*highest).max(code & 0xFF);
+    }
+    // Window number, bitmap length, and the bitmap up to the highest type of the window
+    windows
+        .values()
+        .map(|highest| 2 + usize::from(highest / 8) + 1)
+        .sum()
+}
+
+fn lowercase(name: &Name) -> String {
+    name.to_lowercase().to_ascii()
+}
+
+/// Split the DNSSEC records out of zone file content, which the parser refuses.
+///
+/// Returns the remaining content, and the owner and type of every record taken out.
+fn strip_signing_records(
+    content: &str,
+    zone: &Name,
+) -> (String, Vec<String>) {
+    let mut origin = zone.to_string();
+    if !origin.ends_with('.') {
+        origin.push('.');
+    }
+    let mut kept = String::with_capacity(content.len());
+    let mut stripped = Vec::new();
+    let mut owner = origin.clone();
+    // Owner of a stripped record, which the records after it without an owner inherit
+    let mut inherited: Option<String> = None;
+
+    let mut entry = String::new();
+    let mut depth = 0;
+    for line in content.lines() {
+        let code = line.split(';').next().unwrap_or_default();
+        depth += code.matches('(').count() as i32 - code.matches(')').count() as i32;
+        entry.push_str(code);
+        entry.push('\n');
+        if depth > 0 {
+            continue;
+        }
+        depth = 0;
+        let text = std::mem::take(&mut entry);
+        let tokens: Vec<&str> = text
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .filter(|token| !token.is_empty())
+            .collect();
+        let has_owner = !text.starts_with([' ', '\t']);
+        match (tokens.first(), tokens.get(1)) {
+            (Some(&"$ORIGIN"), Some(name)) => origin = name.to_string(),
+            (Some(token), _) if has_owner && !token.starts_with('$') => {
+                owner = match *token {
+                    "@" => origin.clone(),
+                    name if name.ends_with('.') => name.to_owned(),
+                    name => format!("{}.{}", name, origin),
+                }
+            },
+            _ => {},
+        }
+
+        // The type comes after the optional TTL and class
+        let fields = &tokens[usize::from(has_owner).min(tokens.len())..];
+        let signing_type = fields
+            .iter()
+            .take(3)
+            .find(|field| SIGNING_TYPES.contains(&field.to_uppercase().as_str()));
+        match signing_type {
+            Some(record_type) => {
+                stripped.push(format!("{} {}", owner, record_type.to_uppercase()));
+                if has_owner {
+                    inherited = Some(owner.clone());
+                }
+            },
+            None => {
+                if let (false, false, Some(owner)) = (has_owner, tokens.is_empty(), &inherited) {
+                    kept.push_str(owner);
+                }
+                if has_owner && !tokens.is_empty() {
+                    inherited = None;
+                }
+                kept.push_str(&text);
+            },
+        }
+    }
+    (kept, stripped)
+}
+
+impl Report {
+    fn new(
+        zone: &Name,
+        source: String,
+        records: &[Record],
+        mut leftovers: Vec<String>,
+        notes: Vec<String>,
+    ) -> Self {
+        let mut zone = zone.to_lowercase();
+        zone.set_fqdn(true);
+        let zone = &zone;
+        let mut rrsets: BTreeMap<(Name, u16), Vec<&Record>> = BTreeMap::new();
+        let mut seen: HashMap<(String, RecordType, Vec<u8>), usize> = HashMap::new();
+        for record in records {
+            let owner = record.name().to_lowercase();
+            rrsets
+                .entry((owner.clone(), u16::from(record.record_type())))
+                .or_default()
+                .push(record);
+            let data = record
+                .data()
+                .and_then(|data| data.to_bytes().ok())
+                .unwrap_or_default();
+            *seen
+                .entry((lowercase(&owner), record.record_type(), data))
+                .or_default() += 1;
+        }
+
+        let mut duplicates: Vec<String> = records
+            .iter()
+            .filter(|record| {
+                let data = record
+                    .data()
+                    .and_then(|data| data.to_bytes().ok())
+                    .unwrap_or_default();
+                seen.get(&(lowercase(record.name()), record.record_type(), data))
+                    .is_some_and(|count| *count > 1)
+            })
+            .map(|record| {
+                let data = record.data().map(format::data_text).unwrap_or_default();
+                format!("{} {} {}", record.name(), record.record_type(), data)
+            })
+            .collect();
+        duplicates.sort();
+        duplicates.dedup();
+
+        let unsigned_bytes: usize = records
+            .iter()
+            .map(|record| name_length(record.name()) + RECORD_HEADER_LENGTH + data_length(record))
+            .sum();
+
+        let mut types_by_name: BTreeMap<Name, BTreeSet<u16>> = BTreeMap::new();
+        for (owner, record_type) in rrsets.keys() {
+            types_by_name
+                .entry(owner.clone())
+                .or_default()
+                .insert(*record_type);
+        }
+        let rrsig_bytes: usize = rrsets
+            .keys()
+            .map(|(owner, _)| rrsig_length(name_length(owner), zone))
+            .sum();
+        // Every name gets an NSEC record pointing at the next name, the last one at the apex
+        let names: Vec<&Name> = types_by_name.keys().collect();
+        let nsec_bytes: usize = types_by_name
+            .iter()
+            .enumerate()
+            .map(|(index, (owner, types))| {
+                let next = names.get(index + 1).copied().unwrap_or(zone);
+                let rdata = name_length(next) + type_bitmap_length(types);
+                let record = name_length(owner) + RECORD_HEADER_LENGTH + rdata;
+                record + rrsig_length(name_length(owner), zone)
+            })
+            .sum();
+
+        let mut oversized: Vec<Oversized> = rrsets
+            .iter()
+            .filter_map(|((owner, record_type), records)| {
+                let record_type = RecordType::from(*record_type);
+                let answers: usize = records
+                    .iter()
+                    .map(|record| POINTER_LENGTH + RECORD_HEADER_LENGTH + data_length(record))
+                    .sum();
+                let unsigned_bytes =
+                    MESSAGE_HEADER_LENGTH + name_length(owner) + 4 + answers + OPT_LENGTH;
+                let signed_bytes = unsigned_bytes + rrsig_length(POINTER_LENGTH, zone);
+                (signed_bytes > RESPONSE_BUDGET).then(|| Oversized {
+                    name: owner.to_string(),
+                    record_type: record_type.to_string(),
+                    unsigned_bytes,
+                    signed_bytes,
+                })
+            })
+            .collect();
+        oversized.sort_by_key(|oversized| usize::MAX - oversized.signed_bytes);
+
+        let apex_cname = rrsets.contains_key(&(zone.clone(), u16::from(RecordType::CNAME)));
+        leftovers.extend(
+            rrsets
+                .keys()
+                .filter(|(_, record_type)| LEFTOVER_TYPES.contains(&RecordType::from(*record_type)))
+                .map(|(owner, record_type)| {
+                    format!("{} {}", owner, RecordType::from(*record_type))
+                }),
+        );
+        leftovers.sort();
+        leftovers.dedup();
+
+        Self {
+            zone: zone.to_string(),
+            source,
+            records: records.len(),
+            rrsets: rrsets.len(),
+            unsigned_bytes,
+            signed_bytes: unsigned_bytes + rrsig_bytes + nsec_bytes,
+            oversized,
+            apex_cname,
+            duplicates,
+            leftovers,
+            notes,
+        }
+    }
+
+    /// Report on the records of a zone file, whose origin is `zone` unless it says otherwise
+    pub fn from_zone_file(
+        zone: &Name,
+        path: &Path,
+    ) -> Result<Self, AppError> {
+        let error = |message: String| AppError::ZoneError(path.display().to_string(), message);
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let (content, leftovers) = strip_signing_records(&content, zone);
+        let (origin, rrsets) = Parser::new(content, Some(path.to_owned()), Some(zone.clone()))
+            .parse()
+            .map_err(|e| error(e.to_string()))?;
+        let records: Vec<Record> = rrsets
+            .values()
+            .flat_map(|rrset| rrset.records_without_rrsigs().cloned())
+            .collect();
+        let notes = vec![String::from(
+            "duplicates are merged when zone files are read, transfer the zone to find them",
+        )];
+        Ok(Self::new(
+            &origin,
+            format!("zone file {}", path.display()),
+            &records,
+            leftovers,
+            notes,
+        ))
+    }
+
+    /// Report on the records of a zone transfer
+    pub fn from_transfer(
+        zone: &Name,
+        server: &str,
+        records: &[Record],
+    ) -> Self {
+        Self::new(
+            zone,
+            format!("AXFR from {}", server),
+            records,
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    fn issues(&self) -> usize {
+        self.oversized.len()
+            + usize::from(self.apex_cname)
+            + self.duplicates.len()
+            + self.leftovers.len()
+    }
+
+    pub fn print(
+        &self,
+        format: ReadinessFormat,
+    ) {
+        let _ = output::record(|out| self.write(out, format));
+    }
+
+    fn write(
+        &self,
+        out: &mut dyn Write,
+        format: ReadinessFormat,
+    ) -> io::Result<()> {
+        if let ReadinessFormat::Json = format {
+            let json = schema::versioned(self);
+            return writeln!(out, "{}", serde_json::to_string_pretty(&json).unwrap());
+        }
+
+        let kilobytes = |bytes: usize| bytes as f64 / 1024.0;
+        writeln!(out, "DNSSEC readiness of {} ({}):", self.zone, self.source)?;
+        writeln!(
+            out,
+            "  records:    {} in {} RRsets",
+            self.records, self.rrsets
+        )?;
+        writeln!(
+            out,
+            "  size:       {:.1} KiB unsigned, about {:.1} KiB signed (+{:.0}%)",
+            kilobytes(self.unsigned_bytes),
+            kilobytes(self.signed_bytes),
+            (self.signed_bytes as f64 / self.unsigned_bytes.max(1) as f64 - 1.0) * 100.0
+        )?;
+        for oversized in &self.oversized {
+            writeln!(
+                out,
+                "  oversized:  {} {} answers with {} bytes, about {} signed, over the {} byte budget",
+                oversized.name,
+                oversized.record_type,
+                oversized.unsigned_bytes,
+                oversized.signed_bytes,
+                RESPONSE_BUDGET
+            )?;
+        }
+        if self.apex_cname {
+            writeln!(
+                out,
+                "  apex CNAME: {} has a CNAME, which can't coexist with the DNSKEY of a signed zone",
+                self.zone
+            )?;
+        }
+        for duplicate in &self.duplicates {
+            writeln!(out, "  duplicate:  {}", duplicate)?;
+        }
+        for leftover in &self.leftovers {
+            writeln!(
+                out,
+                "  leftover:   {} is already published, remove or reconcile it first",
+                leftover
+            )?;
+        }
+        for note in &self.notes {
+            writeln!(out, "  note:       {}", note)?;
+        }
+        match self.issues() {
+            0 => writeln!(out, "  ready to sign, no issues found"),
+            issues => writeln!(out, "  {} issues to fix before signing", issues),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::rr::{
+        rdata::{A, CNAME, TXT},
+        RData,
+    };
+
+    use super::*;
+    use crate::schema::{validate, Output};
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn txt(strings: &[usize]) -> Record {
+        let strings = strings.iter().map(|length| "x".repeat(*length)).collect();
+        Record::from_rdata(name("example.com."), 300, RData::TXT(TXT::new(strings)))
+    }
+
+    #[test]
+    fn names_and_signatures_have_their_wire_lengths() {
+        // 7 example 3 com 0
+        assert_eq!(name_length(&name("example.com.")), 13);
+        assert_eq!(name_length(&Name::root()), 1);
+        // Owner, header, fixed fields, signer example.com. and a P-256 signature
+        assert_eq!(
+            rrsig_length(2, &name("example.com.")),
+            2 + 10 + 18 + 13 + 64
+        );
+    }
+
+    #[test]
+    fn type_bitmaps_take_a_window_per_256_types() {
+        let codes = |types: &[RecordType]| types.iter().map(|t| u16::from(*t)).collect();
+        // A and MX share window 0 with RRSIG (46) and NSEC (47): 2 + 47 / 8 + 1
+        assert_eq!(
+            type_bitmap_length(&codes(&[RecordType::A, RecordType::MX])),
+            8
+        );
+        assert_eq!(type_bitmap_length(&codes(&[])), 8);
+        // CAA (257) opens window 1, whose highest type is 1
+        assert_eq!(
+            type_bitmap_length(&codes(&[RecordType::A, RecordType::CAA])),
+            8 + 3
+        );
+    }
+
+    #[test]
+    fn the_signed_size_adds_an_rrsig_per_rrset_and_an_nsec_per_name() {
+        let zone = name("example.com.");
+        let records = [Record::from_rdata(
+            zone.clone(),
+            300,
+            RData::A(A::new(192, 0, 2, 1)),
+        )];
+        let report = Report::from_transfer(&zone, "192.0.2.53", &records);
+        // Owner, header and 4 bytes of address
+        assert_eq!(report.unsigned_bytes, 13 + 10 + 4);
+        let rrsig = 13 + 10 + 18 + 13 + 64;
+        // NSEC pointing back at the apex with the bitmap of A, RRSIG and NSEC, and its RRSIG
+        let nsec = 13 + 10 + (13 + 8) + rrsig;
+        assert_eq!(report.signed_bytes, 27 + rrsig + nsec);
+        assert_eq!((report.records, report.rrsets), (1, 1));
+        assert_eq!(report.issues(), 0);
+    }
+
+    #[test]
+    fn rrsets_whose_signed_answer_outgrows_the_budget_are_oversized() {
+        let zone = name("example.com.");
+        // Header, question, four TXT records of 2 + 10 + 256 bytes, the OPT record and an
+        // RRSIG of 107 bytes make 1219
+        let fitting = [txt(&[255]), txt(&[255]), txt(&[255]), txt(&[255])];
+        let report = Report::from_transfer(&zone, "192.0.2.53", &fitting);
+        assert!(report.oversized.is_empty());
+
+        // 15 bytes more, a string of 14, and the signed answer is 2 bytes over
+        let over = [txt(&[255]), txt(&[255]), txt(&[255]), txt(&[255, 14])];
+        let report = Report::from_transfer(&zone, "192.0.2.53", &over);
+        assert_eq!(report.oversized.len(), 1);
+        let oversized = &report.oversized[0];
+        assert_eq!(
+            (oversized.name.as_str(), oversized.record_type.as_str()),
+            ("example.com.", "TXT")
+        );
+        assert_eq!(
+            (oversized.unsigned_bytes, oversized.signed_bytes),
+            (1127, 1234)
+        );
+    }
+
+    #[test]
+    fn the_json_output_matches_its_schema() {
+        let zone = Name::from_str("example.com.").unwrap();
+        let records = [
+            Record::from_rdata(zone.clone(), 300, RData::A(A::new(192, 0, 2, 1))),
+            Record::from_rdata(zone.clone(), 300, RData::A(A::new(192, 0, 2, 1))),
+            Record::from_rdata(
+                zone.clone(),
+                300,
+                RData::CNAME(CNAME(Name::from_str("other.example.").unwrap())),
+            ),
+        ];
+        let report = Report::from_transfer(&zone, "192.0.2.53", &records);
+        let json = schema::versioned(&report);
+        validate(&schema::schema(Output::Readiness), &json).unwrap();
+        assert_eq!(json["apex_cname"], true);
+    }
+}
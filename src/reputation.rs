@@ -0,0 +1,316 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::{outln, output, AppError, ConnectionType};
+
+/// How long what a run learned of a server is remembered
+pub const EXPIRY: Duration = Duration::from_secs(15 * 60);
+
+/// Servers answering slower than this go after the others, as long as they answer
+pub const SLOW: Duration = Duration::from_millis(500);
+
+/// What the last run asking a server learned of it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Health {
+    /// when it was asked, in seconds since the Unix epoch
+    pub checked: u64,
+    /// whether it timed out, could not be reached or answered SERVFAIL or REFUSED
+    pub failed: bool,
+    /// how long its answer took, when it answered
+    pub latency_ms: Option<u64>,
+}
+
+impl Health {
+    pub fn slow(&self) -> bool {
+        self.latency_ms
+            .is_some_and(|latency| latency >= SLOW.as_millis() as u64)
+    }
+}
+
+/// Servers that runs of `--failover` asked lately, kept between runs in the state file
+#[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    /// by `key`
+    servers: BTreeMap<String, Health>,
+}
+
+/// Key of `server` asked over `connection` in the state, as one server may answer over UDP
+/// and fail over TLS
+pub fn key(
+    connection: ConnectionType,
+    server: &str,
+) -> String {
+    format!("{} {}", connection, server)
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Path of the file `name` of resoy under `$XDG_CACHE_HOME`, `~/.cache` or on Windows
+/// `%LOCALAPPDATA%`, or `None` when there is no such directory
+pub fn cache_file(name: &str) -> Option<PathBuf> {
+    let directory = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(directory) => PathBuf::from(directory),
+        None if cfg!(windows) => PathBuf::from(env::var_os("LOCALAPPDATA")?),
+        None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    Some(directory.join("resoy").join(name))
+}
+
+impl State {
+    /// The state file, `resoy/servers.json` under the cache directory
+    pub fn path() -> Option<PathBuf> {
+        cache_file("servers.json")
+    }
+
+    /// Read the state of `path`, empty when there is none yet or it cannot be read, as it is
+    /// only a hint of which server to ask first
+    pub fn read(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the state into `path` at once: into a file of this process next to it first,
+    /// renamed over it then, so that runs at the same time never read half of a state
+    pub fn write(
+        &self,
+        path: &Path,
+    ) -> std::io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension(format!("json.{}", process::id()));
+        fs::write(
+            &temporary,
+            serde_json::to_string_pretty(self).unwrap() + "\n",
+        )?;
+        fs::rename(&temporary, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })
+    }
+
+    /// Read the state of `path` again, record `outcomes` into it and write it back, so that
+    /// the servers another run recorded meanwhile are kept
+    pub fn update(
+        path: &Path,
+        now: SystemTime,
+        outcomes: &[(String, Option<Duration>)],
+    ) -> std::io::Result<()> {
+        let mut state = Self::read(path);
+        state.expire(now);
+        for (key, latency) in outcomes {
+            state.record(key, now, *latency);
+        }
+        state.write(path)
+    }
+
+    /// Forget what was learned longer than `EXPIRY` before `now`
+    pub fn expire(
+        &mut self,
+        now: SystemTime,
+    ) {
+        let oldest = seconds(now).saturating_sub(EXPIRY.as_secs());
+        self.servers.retain(|_, health| health.checked > oldest);
+    }
+
+    /// Record that the server of `key` answered in `latency`, or failed with `None`
+    pub fn record(
+        &mut self,
+        key: &str,
+        now: SystemTime,
+        latency: Option<Duration>,
+    ) {
+        let health = Health {
+            checked: seconds(now),
+            failed: latency.is_none(),
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+        };
+        self.servers.insert(key.to_owned(), health);
+    }
+
+    /// What was learned of the server of `key` less than `EXPIRY` before `now`
+    pub fn health(
+        &self,
+        key: &str,
+        now: SystemTime,
+    ) -> Option<&Health> {
+        let oldest = seconds(now).saturating_sub(EXPIRY.as_secs());
+        self.servers
+            .get(key)
+            .filter(|health| health.checked > oldest)
+    }
+
+    /// `servers` in the order to ask them in: those that failed lately last, those that were
+    /// slow before them, the others first, each in the order they were given
+    pub fn order<'s>(
+        &self,
+        connection: ConnectionType,
+        servers: &'s [String],
+        now: SystemTime,
+    ) -> Vec<&'s String> {
+        let mut ordered: Vec<&String> = servers.iter().collect();
+        ordered.sort_by_key(|server| match self.health(&key(connection, server), now) {
+            Some(health) if health.failed => 2,
+            Some(health) if health.slow() => 1,
+            _ => 0,
+        });
+        ordered
+    }
+}
+
+#[derive(Args)]
+pub struct ServersArgs {
+    /// print what runs of --failover remember of the servers they asked
+    #[arg(long, required = true)]
+    status: bool,
+}
+
+impl ServersArgs {
+    /// Print the servers of the state file with their health and how long ago it was learned
+    pub fn run(&self) -> Result<(), AppError> {
+        let Some(path) = State::path() else {
+            outln!("No cache directory to keep the state of the servers in");
+            return Ok(());
+        };
+        let now = SystemTime::now();
+        let state = State::read(&path);
+        let lines = status_lines(&state, now);
+        let _ = output::record(|out| {
+            writeln!(out, "; state of {}", path.display())?;
+            if lines.is_empty() {
+                writeln!(
+                    out,
+                    "No server was asked in the last {} minutes",
+                    EXPIRY.as_secs() / 60
+                )?;
+            }
+            for line in &lines {
+                writeln!(out, "{}", line)?;
+            }
+            Ok(())
+        });
+        Ok(())
+    }
+}
+
+/// A line per server of `state` not expired at `now`, as `resoy servers --status` prints them
+fn status_lines(
+    state: &State,
+    now: SystemTime,
+) -> Vec<String> {
+    let width = state.servers.keys().map(String::len).max().unwrap_or(0);
+    state
+        .servers
+        .iter()
+        .filter(|(key, _)| state.health(key, now).is_some())
+        .map(|(key, health)| {
+            let status = match (health.failed, health.slow()) {
+                (true, _) => "failing",
+                (false, true) => "slow",
+                (false, false) => "healthy",
+            };
+            let latency = match health.latency_ms {
+                Some(latency) => format!("{}ms", latency),
+                None => String::from("-"),
+            };
+            let ago = seconds(now).saturating_sub(health.checked);
+            format!(
+                "{:<width$} {:<7} {:>7}  {}s ago, forgotten in {}s",
+                key,
+                status,
+                latency,
+                ago,
+                EXPIRY.as_secs().saturating_sub(ago),
+                width = width
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn failing_and_slow_servers_go_last() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut state = State::default();
+        let udp = |server| key(ConnectionType::Udp, server);
+        state.record(&udp("192.0.2.1"), now, None);
+        state.record(&udp("192.0.2.2"), now, Some(Duration::from_millis(900)));
+        state.record(&udp("192.0.2.3"), now, Some(Duration::from_millis(20)));
+        let given = servers(&["192.0.2.1", "192.0.2.2", "192.0.2.3", "192.0.2.4"]);
+        assert_eq!(
+            state.order(ConnectionType::Udp, &given, now),
+            ["192.0.2.3", "192.0.2.4", "192.0.2.2", "192.0.2.1"]
+        );
+        // Over another connection the servers are unknown, and keep their order
+        assert_eq!(
+            state.order(ConnectionType::Tcp, &given, now),
+            given.iter().collect::<Vec<_>>()
+        );
+        // Failures are forgotten after 15 minutes
+        let later = now + EXPIRY;
+        assert_eq!(
+            state.order(ConnectionType::Udp, &given, later),
+            given.iter().collect::<Vec<_>>()
+        );
+        state.expire(later);
+        assert_eq!(state, State::default());
+    }
+
+    #[test]
+    fn the_state_is_written_at_once_and_merged_with_other_runs() {
+        let directory = env::temp_dir().join(format!("resoy-state-{}", process::id()));
+        let path = directory.join("resoy").join("servers.json");
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        // A broken state is no state
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{ half").unwrap();
+        assert_eq!(State::read(&path), State::default());
+
+        let one = (String::from("udp 192.0.2.1"), None);
+        let two = (
+            String::from("udp 192.0.2.2"),
+            Some(Duration::from_millis(30)),
+        );
+        State::update(&path, now, &[one]).unwrap();
+        State::update(&path, now + Duration::from_secs(60), &[two]).unwrap();
+        let state = State::read(&path);
+        assert!(state.health("udp 192.0.2.1", now).unwrap().failed);
+        assert_eq!(
+            state.health("udp 192.0.2.2", now).unwrap().latency_ms,
+            Some(30)
+        );
+        // Nothing is left of the writes but the state
+        let files: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let lines = status_lines(&state, now + Duration::from_secs(120));
+        assert_eq!(
+            lines,
+            [
+                "udp 192.0.2.1 failing       -  120s ago, forgotten in 780s",
+                "udp 192.0.2.2 healthy    30ms  60s ago, forgotten in 840s",
+            ]
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
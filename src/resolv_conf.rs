@@ -0,0 +1,226 @@
+/// Server of runs without `--server` on systems without a configured name server, on the
+/// default port of the connection
+pub const FALLBACK_SERVER: &str = "1.1.1.1";
+
+/// Where unix systems list their name servers
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Where the name servers of the system are read from
+#[cfg(unix)]
+const SYSTEM_SOURCE: &str = RESOLV_CONF;
+#[cfg(windows)]
+const SYSTEM_SOURCE: &str = "the network adapters";
+#[cfg(not(any(unix, windows)))]
+const SYSTEM_SOURCE: &str = "the system";
+
+/// Addresses of the `nameserver` lines of resolv.conf `content`, in order.
+///
+/// Comments start with `#` or `;`, anywhere on a line.
+pub fn nameservers(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(['#', ';']).next()?;
+            let mut words = line.split_whitespace();
+            match words.next()? {
+                "nameserver" => words.next().map(ToOwned::to_owned),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Where the default server comes from
+pub fn default_server_source() -> Option<&'static str> {
+    system_server().map(|_| SYSTEM_SOURCE)
+}
+
+/// The first name server the system is configured with, the fallback when there is none
+pub fn default_server() -> String {
+    system_server().unwrap_or_else(|| String::from(FALLBACK_SERVER))
+}
+
+fn system_server() -> Option<String> {
+    system_servers().into_iter().next()
+}
+
+/// Name servers of the system, in the order it asks them
+#[cfg(unix)]
+pub fn system_servers() -> Vec<String> {
+    std::fs::read_to_string(RESOLV_CONF)
+        .map(|content| nameservers(&content))
+        .unwrap_or_default()
+}
+
+/// Name servers of the system, those of every network adapter that is up in the order
+/// Windows lists them
+#[cfg(windows)]
+pub fn system_servers() -> Vec<String> {
+    use windows_sys::Win32::{
+        NetworkManagement::{
+            IpHelper::{
+                GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_MULTICAST,
+                GAA_FLAG_SKIP_UNICAST, IP_ADAPTER_ADDRESSES_LH,
+            },
+            Ndis::IfOperStatusUp,
+        },
+        Networking::WinSock::AF_UNSPEC,
+    };
+    const ERROR_BUFFER_OVERFLOW: u32 = 111;
+
+    let flags = GAA_FLAG_SKIP_UNICAST | GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST;
+    // Microsoft suggests 15 KB, which nearly always suffices, and the call tells the size
+    // needed otherwise. Words rather than bytes, so that the list is aligned.
+    let mut size: u32 = 15 * 1024;
+    let mut buffer: Vec<u64> = Vec::new();
+    for _ in 0..3 {
+        buffer = vec![0; (size as usize).div_ceil(8)];
+        // SAFETY: the buffer holds `size` bytes, as the call is told
+        let result = unsafe {
+            GetAdaptersAddresses(
+                u32::from(AF_UNSPEC),
+                flags,
+                std::ptr::null(),
+                buffer.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        match result {
+            0 => break,
+            ERROR_BUFFER_OVERFLOW => buffer.clear(),
+            _ => return Vec::new(),
+        }
+    }
+
+    let mut servers: Vec<String> = Vec::new();
+    let mut adapter: *const IP_ADAPTER_ADDRESSES_LH = match buffer.is_empty() {
+        true => std::ptr::null(),
+        false => buffer.as_ptr().cast(),
+    };
+    // SAFETY: the adapters and their addresses are lists within the buffer, ending with null
+    while let Some(current) = unsafe { adapter.as_ref() } {
+        let mut server = current.FirstDnsServerAddress.cast_const();
+        while let Some(address) = unsafe { server.as_ref() } {
+            let length = usize::try_from(address.Address.iSockaddrLength).unwrap_or(0);
+            let bytes = match address.Address.lpSockaddr.is_null() {
+                true => &[][..],
+                false => unsafe {
+                    std::slice::from_raw_parts(address.Address.lpSockaddr.cast::<u8>(), length)
+                },
+            };
+            let up = current.OperStatus == IfOperStatusUp;
+            if let Some(found) = socket_address(bytes).filter(|_| up) {
+                if !servers.contains(&found) {
+                    servers.push(found);
+                }
+            }
+            server = address.Next.cast_const();
+        }
+        adapter = current.Next.cast_const();
+    }
+    servers
+}
+
+/// The address of a `SOCKADDR_IN` or `SOCKADDR_IN6` in `bytes`, IPv6 ones with their zone,
+/// leaving out the site-local servers Windows falls back to when none are configured
+#[cfg(windows)]
+fn socket_address(bytes: &[u8]) -> Option<String> {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const AF_INET: u16 = 2;
+    const AF_INET6: u16 = 23;
+    match u16::from_le_bytes(bytes.get(..2)?.try_into().ok()?) {
+        AF_INET => {
+            let octets: [u8; 4] = bytes.get(4..8)?.try_into().ok()?;
+            Some(Ipv4Addr::from(octets).to_string())
+        },
+        AF_INET6 => {
+            let octets: [u8; 16] = bytes.get(8..24)?.try_into().ok()?;
+            let ip = Ipv6Addr::from(octets);
+            if ip.segments()[..4] == [0xfec0, 0, 0, 0xffff] {
+                return None;
+            }
+            let scope_id = u32::from_le_bytes(bytes.get(24..28)?.try_into().ok()?);
+            match scope_id {
+                0 => Some(ip.to_string()),
+                scope_id => Some(format!("{}%{}", ip, scope_id)),
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Name servers of other systems aren't read yet
+#[cfg(not(any(unix, windows)))]
+pub fn system_servers() -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nameservers_are_read_in_order() {
+        let content = "\
+nameserver 192.0.2.1
+nameserver 2001:db8::53
+nameserver 192.0.2.2
+";
+        assert_eq!(
+            nameservers(content),
+            ["192.0.2.1", "2001:db8::53", "192.0.2.2"]
+        );
+    }
+
+    #[test]
+    fn comments_and_other_lines_are_skipped() {
+        let content = "\
+# Generated by NetworkManager
+; also a comment
+search example.com corp.example
+domain example.com
+options edns0 trust-ad ndots:2 timeout:1
+nameserver 192.0.2.1 # the router
+nameserver 192.0.2.2; the backup
+#nameserver 192.0.2.3
+  nameserver   192.0.2.4
+nameserver
+sortlist 130.155.160.0/255.255.240.0
+";
+        assert_eq!(
+            nameservers(content),
+            ["192.0.2.1", "192.0.2.2", "192.0.2.4"]
+        );
+    }
+
+    #[test]
+    fn link_local_servers_keep_their_zone() {
+        let content = "nameserver fe80::1%eth0\nnameserver fe80::2%3\n";
+        assert_eq!(nameservers(content), ["fe80::1%eth0", "fe80::2%3"]);
+        let addr = crate::server_addr::parse_server_addr(crate::ConnectionType::Udp, "fe80::2%3");
+        assert_eq!(addr.unwrap().to_string(), "[fe80::2%3]:53");
+    }
+
+    #[test]
+    fn empty_files_have_no_nameservers() {
+        assert!(nameservers("").is_empty());
+        assert!(nameservers("options rotate\n").is_empty());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn adapter_addresses_are_read_from_socket_addresses() {
+        let mut v4 = vec![2, 0, 0, 53, 192, 0, 2, 1];
+        v4.resize(16, 0);
+        assert_eq!(socket_address(&v4).as_deref(), Some("192.0.2.1"));
+        let mut v6 = vec![23, 0, 0, 53, 0, 0, 0, 0];
+        v6.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        v6.extend_from_slice(&7u32.to_le_bytes());
+        assert_eq!(socket_address(&v6).as_deref(), Some("fe80::1%7"));
+        let mut site_local = vec![23, 0, 0, 53, 0, 0, 0, 0];
+        site_local.extend_from_slice(&[0xfe, 0xc0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 1]);
+        site_local.extend_from_slice(&[0; 4]);
+        assert_eq!(socket_address(&site_local), None);
+    }
+}
@@ -0,0 +1,734 @@
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    op::{DnsResponse, ResponseCode},
+    rr::{DNSClass, Name, Record, RecordType},
+};
+
+use crate::{
+    client::{query_failure, Session},
+    follow,
+    mock::Fixture,
+    policy::QueryPolicy,
+    resolv_conf, tsig, AppError, ConnectionType, DnsClient,
+};
+
+/// Retries of queries that time out, by default
+pub const DEFAULT_RETRIES: usize = 2;
+
+/// Where and how `resolve` and `DnsClient` ask, built like `OutputConfig`
+#[derive(Clone)]
+pub struct ResolveOptions {
+    connection: ConnectionType,
+    server: String,
+    class: DNSClass,
+    /// attempts, timeouts and TCP fallback of the queries
+    policy: QueryPolicy,
+    /// the DO bit, asking for the signatures of the answers
+    dnssec_ok: bool,
+    /// the RD bit, asking the server to recurse
+    recursion_desired: bool,
+    /// asking the server for its NSID
+    nsid: bool,
+    /// address and port the queries over UDP and TCP leave from, the system choosing
+    /// whatever is not given
+    source: (Option<IpAddr>, Option<u16>),
+    /// failing on responses that cannot be parsed as a whole instead of salvaging them
+    strict_parsing: bool,
+    /// name to check the certificates of DNS-over-TLS servers for
+    tls_hostname: Option<String>,
+    /// accepting any certificate of DNS-over-TLS servers
+    insecure: bool,
+    /// key the queries are signed with, and the responses checked against
+    tsig: Option<tsig::Key>,
+    /// canned answers to answer from instead of the network
+    mock: Option<Arc<Fixture>>,
+    session: Arc<Session>,
+    /// feature the queries are counted under in the budget of the session
+    feature: &'static str,
+}
+
+impl ResolveOptions {
+    /// Ask `server`, an address, a hostname or the URL of a DNS-over-HTTPS resolver, over
+    /// `connection`
+    pub fn new(
+        connection: ConnectionType,
+        server: &str,
+    ) -> Self {
+        Self {
+            connection,
+            server: server.to_owned(),
+            class: DNSClass::IN,
+            policy: QueryPolicy::default(),
+            dnssec_ok: false,
+            recursion_desired: true,
+            nsid: false,
+            source: (None, None),
+            strict_parsing: false,
+            tls_hostname: None,
+            insecure: false,
+            tsig: None,
+            mock: None,
+            session: Arc::default(),
+            feature: "lookup",
+        }
+    }
+
+    /// Ask `server` instead, keeping everything else
+    pub fn with_server(
+        mut self,
+        server: &str,
+    ) -> Self {
+        self.server = server.to_owned();
+        self
+    }
+
+    /// Ask over `connection` instead, keeping everything else
+    pub fn with_connection(
+        mut self,
+        connection: ConnectionType,
+    ) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// Ask for the records of `class` instead of IN
+    pub fn with_class(
+        mut self,
+        class: DNSClass,
+    ) -> Self {
+        self.class = class;
+        self
+    }
+
+    /// Wait this long for the response to a query
+    pub fn with_timeout(
+        mut self,
+        timeout: Duration,
+    ) -> Self {
+        self.policy = self.policy.with_timeout(timeout);
+        self
+    }
+
+    /// Send every query that times out up to `retries` more times
+    pub fn with_retries(
+        mut self,
+        retries: usize,
+    ) -> Self {
+        self.policy = self.policy.with_attempts(retries.saturating_add(1));
+        self
+    }
+
+    /// Send and send again the queries as `policy` says, instead of its parts set one by one
+    pub fn with_policy(
+        mut self,
+        policy: QueryPolicy,
+    ) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set the DO bit, asking for the signatures of the answers
+    pub fn with_dnssec_ok(
+        mut self,
+        dnssec_ok: bool,
+    ) -> Self {
+        self.dnssec_ok = dnssec_ok;
+        self
+    }
+
+    /// Clear the RD bit with `false`, so that the server answers from what it knows only
+    pub fn with_recursion_desired(
+        mut self,
+        recursion_desired: bool,
+    ) -> Self {
+        self.recursion_desired = recursion_desired;
+        self
+    }
+
+    /// Ask the server for its NSID
+    pub fn with_nsid(
+        mut self,
+        nsid: bool,
+    ) -> Self {
+        self.nsid = nsid;
+        self
+    }
+
+    /// Send the queries over UDP and TCP from `ip` and `port`, the system choosing whatever
+    /// is `None`
+    pub fn with_source(
+        mut self,
+        ip: Option<IpAddr>,
+        port: Option<u16>,
+    ) -> Self {
+        self.source = (ip, port);
+        self
+    }
+
+    /// Fail on responses that cannot be parsed as a whole, instead of salvaging them
+    pub fn with_strict_parsing(
+        mut self,
+        strict_parsing: bool,
+    ) -> Self {
+        self.strict_parsing = strict_parsing;
+        self
+    }
+
+    /// Show truncated UDP responses as they are with `false`, instead of asking again over
+    /// TCP
+    pub fn with_tcp_fallback(
+        mut self,
+        tcp_fallback: bool,
+    ) -> Self {
+        self.policy = self.policy.with_tcp_fallback(tcp_fallback);
+        self
+    }
+
+    /// Verify the certificates of DNS-over-TLS servers for `hostname` instead of the host of
+    /// the server address, or not at all when `insecure`
+    pub fn with_tls(
+        mut self,
+        hostname: Option<String>,
+        insecure: bool,
+    ) -> Self {
+        self.tls_hostname = hostname;
+        self.insecure = insecure;
+        self
+    }
+
+    /// Sign the queries over UDP, TCP and TLS with `key`, and accept only the responses signed
+    /// with it
+    pub fn with_tsig(
+        mut self,
+        key: Option<tsig::Key>,
+    ) -> Self {
+        self.tsig = key;
+        self
+    }
+
+    /// Answer every query from `fixture` instead of the network
+    pub fn with_mock(
+        mut self,
+        fixture: Option<Arc<Fixture>>,
+    ) -> Self {
+        self.mock = fixture;
+        self
+    }
+
+    /// Count the queries against the budget of `session`, and share the answers with the
+    /// other clients of it
+    pub fn with_session(
+        mut self,
+        session: Arc<Session>,
+    ) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Count the queries as sent by `feature` in the budget of the session, e.g. "hints"
+    pub fn with_feature(
+        mut self,
+        feature: &'static str,
+    ) -> Self {
+        self.feature = feature;
+        self
+    }
+
+    pub fn connection(&self) -> ConnectionType {
+        self.connection
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn class(&self) -> DNSClass {
+        self.class
+    }
+
+    pub fn policy(&self) -> &QueryPolicy {
+        &self.policy
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.policy.timeout()
+    }
+
+    /// How many times a query is sent at most
+    pub fn attempts(&self) -> usize {
+        self.policy.attempts()
+    }
+
+    pub fn dnssec_ok(&self) -> bool {
+        self.dnssec_ok
+    }
+
+    pub fn recursion_desired(&self) -> bool {
+        self.recursion_desired
+    }
+
+    pub fn nsid(&self) -> bool {
+        self.nsid
+    }
+
+    pub fn source(&self) -> (Option<IpAddr>, Option<u16>) {
+        self.source
+    }
+
+    pub fn strict_parsing(&self) -> bool {
+        self.strict_parsing
+    }
+
+    pub fn tcp_fallback(&self) -> bool {
+        self.policy.tcp_fallback()
+    }
+
+    pub fn tsig(&self) -> Option<&tsig::Key> {
+        self.tsig.as_ref()
+    }
+
+    pub fn tls_hostname(&self) -> Option<&str> {
+        self.tls_hostname.as_deref()
+    }
+
+    pub fn insecure(&self) -> bool {
+        self.insecure
+    }
+
+    pub fn mock(&self) -> Option<&Arc<Fixture>> {
+        self.mock.as_ref()
+    }
+
+    pub fn session(&self) -> &Arc<Session> {
+        &self.session
+    }
+
+    pub fn feature(&self) -> &'static str {
+        self.feature
+    }
+}
+
+impl Default for ResolveOptions {
+    /// Over UDP to the first name server of the system
+    fn default() -> Self {
+        Self::new(ConnectionType::Udp, &resolv_conf::default_server())
+    }
+}
+
+/// The answers of the server to the queries of `name` for every one of `record_types`, asked
+/// at the same time, in the order the types were given.
+///
+/// Types that failed or came with an error code are left out, as long as another type got
+/// an answer; otherwise the resolution fails with the error of the first, NXDOMAIN first.
+///
+/// ```no_run
+/// use std::str::FromStr;
+///
+/// use hickory_client::rr::{Name, RecordType};
+/// use resoy::{resolve, ConnectionType, ResolveOptions};
+///
+/// let name = Name::from_str("example.com.").unwrap();
+/// let options = ResolveOptions::new(ConnectionType::Udp, "1.1.1.1");
+/// for record in resolve(&name, &[RecordType::A, RecordType::AAAA], &options).unwrap() {
+///     println!("{}", record);
+/// }
+/// ```
+pub fn resolve(
+    name: &Name,
+    record_types: &[RecordType],
+    options: &ResolveOptions,
+) -> Result<Vec<Record>, AppError> {
+    let client = DnsClient::new(options)?;
+    let mut resolution = query_types(&client, name, options.class, record_types, None);
+    if resolution.responses.is_empty() && !resolution.failures.is_empty() {
+        return Err(resolution.failures.remove(0).1);
+    }
+    if let (Some(error), _) = response_errors(name, &options.server, &resolution.responses) {
+        return Err(error);
+    }
+    Ok(resolution.answers().into_iter().cloned().collect())
+}
+
+/// Responses to the queries of a name for several types, as `query_types` sends them
+pub struct Resolution {
+    /// in the order the types were given, whatever the order the answers arrived in
+    pub responses: Vec<(RecordType, DnsResponse)>,
+    /// round trip time of every response, in the same order
+    pub latencies: Vec<Duration>,
+    /// the types whose queries failed, with their errors
+    pub failures: Vec<(RecordType, AppError)>,
+    /// time all the queries took together
+    pub elapsed: Duration,
+}
+
+impl Resolution {
+    /// Answers of every response, in the order of the types
+    pub fn answers(&self) -> Vec<&Record> {
+        self.responses
+            .iter()
+            .flat_map(|(_, response)| response.answers())
+            .collect()
+    }
+
+    /// Follow the CNAME chains of the responses to their ends with `follow::follow`, the types
+    /// whose chains fail becoming failures
+    pub fn follow(
+        self,
+        client: &DnsClient,
+        name: &Name,
+        class: DNSClass,
+        max_depth: usize,
+    ) -> Self {
+        let mut followed = Self {
+            responses: Vec::with_capacity(self.responses.len()),
+            latencies: Vec::with_capacity(self.latencies.len()),
+            failures: self.failures,
+            elapsed: self.elapsed,
+        };
+        for ((record_type, response), latency) in self.responses.into_iter().zip(self.latencies) {
+            match follow::follow(client, name, class, record_type, response, max_depth) {
+                Ok(response) => {
+                    followed.responses.push((record_type, response));
+                    followed.latencies.push(latency);
+                },
+                Err(error) => followed.failures.push((record_type, error)),
+            }
+        }
+        followed
+    }
+}
+
+/// Ask `client` for the records of `name` of every one of `record_types`, all at the same
+/// time, or one after another with pauses of `spacing` between them, which the round trip
+/// times leave out
+pub fn query_types(
+    client: &DnsClient,
+    name: &Name,
+    class: DNSClass,
+    record_types: &[RecordType],
+    spacing: Option<Duration>,
+) -> Resolution {
+    let timed_query = |record_type: RecordType| {
+        let started = Instant::now();
+        let result = client.query(name, class, record_type);
+        (record_type, result, started.elapsed())
+    };
+    let queried = Instant::now();
+    let results: Vec<_> = match spacing {
+        Some(spacing) => record_types
+            .iter()
+            .enumerate()
+            .map(|(index, record_type)| {
+                if index > 0 {
+                    thread::sleep(spacing);
+                }
+                timed_query(*record_type)
+            })
+            .collect(),
+        None => thread::scope(|scope| {
+            let handles: Vec<_> = record_types
+                .iter()
+                .map(|record_type| {
+                    let timed_query = &timed_query;
+                    scope.spawn(move || timed_query(*record_type))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        }),
+    };
+    let mut resolution = Resolution {
+        responses: Vec::with_capacity(results.len()),
+        latencies: Vec::with_capacity(results.len()),
+        failures: Vec::new(),
+        elapsed: queried.elapsed(),
+    };
+    for (record_type, result, elapsed) in results {
+        match result {
+            Ok(response) => {
+                resolution.responses.push((record_type, response));
+                resolution.latencies.push(elapsed);
+            },
+            Err(error) => {
+                let error = query_failure(name, record_type, client.options(), error, elapsed);
+                resolution.failures.push((record_type, error));
+            },
+        }
+    }
+    resolution
+}
+
+/// The errors of the responses that came with an error code: the one to fail with when no
+/// record type got an answer, as with failed queries, and the others, which are only worth
+/// reporting.
+///
+/// NXDOMAIN is the one to fail with if the name doesn't exist, the first other error
+/// otherwise.
+pub fn response_errors(
+    name: &Name,
+    server: &str,
+    responses: &[(RecordType, DnsResponse)],
+) -> (Option<AppError>, Vec<AppError>) {
+    let answered = responses
+        .iter()
+        .any(|(_, response)| !response.answers().is_empty());
+    let mut errors: Vec<AppError> = Vec::new();
+    for (record_type, response) in responses {
+        let error = match response.response_code() {
+            ResponseCode::NoError => continue,
+            ResponseCode::NXDomain => AppError::NxDomain(name.to_string()),
+            code => AppError::ResponseError(
+                format!("{:?}", code).to_uppercase(),
+                server.to_owned(),
+                format!("{}/{}", name, record_type),
+            ),
+        };
+        // A name that doesn't exist is reported once, whatever the types asked for
+        let reported = errors.iter().any(|other| {
+            matches!(
+                (other, &error),
+                (AppError::NxDomain(_), AppError::NxDomain(_))
+            )
+        });
+        if !reported {
+            errors.push(error);
+        }
+    }
+    if answered || errors.is_empty() {
+        return (None, errors);
+    }
+    let first = errors
+        .iter()
+        .position(|error| matches!(error, AppError::NxDomain(_)))
+        .unwrap_or_default();
+    let error = errors.remove(first);
+    (Some(error), errors)
+}
+
+/// Report the responses that came with an error code on stderr, failing with the one of
+/// `response_errors` to fail with
+pub fn response_codes(
+    name: &Name,
+    server: &str,
+    responses: &[(RecordType, DnsResponse)],
+) -> Result<(), AppError> {
+    let (error, others) = response_errors(name, server, responses);
+    for other in &others {
+        eprintln!("Error: {}", other);
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, str::FromStr, sync::Mutex};
+
+    use hickory_client::{
+        op::{Message, MessageType},
+        rr::{rdata::A, RData},
+    };
+
+    use super::*;
+    use crate::format::{OutputConfig, RecordFormatter};
+
+    const FIXTURE: &str = r#"
+        [[answer]]
+        name = "example.com"
+        type = "A"
+        records = ["192.0.2.1", "192.0.2.2"]
+
+        [[answer]]
+        name = "example.com"
+        type = "MX"
+        records = ["10 mail.example.com."]
+
+        [[answer]]
+        name = "broken.example.com"
+        type = "A"
+        rcode = "SERVFAIL"
+    "#;
+
+    fn mock_options() -> ResolveOptions {
+        let fixture = Fixture::from_toml(FIXTURE).unwrap();
+        ResolveOptions::new(ConnectionType::Udp, "192.0.2.53").with_mock(Some(Arc::new(fixture)))
+    }
+
+    fn data(records: &[Record]) -> Vec<String> {
+        records
+            .iter()
+            .map(|record| record.data().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn answers_come_in_the_order_of_the_types() {
+        let types = [RecordType::MX, RecordType::A];
+        let records = resolve(&name(), &types, &mock_options()).unwrap();
+        assert_eq!(
+            data(&records),
+            ["10 mail.example.com.", "192.0.2.1", "192.0.2.2"]
+        );
+        // NODATA of one type leaves the others
+        let types = [RecordType::AAAA, RecordType::A];
+        assert_eq!(resolve(&name(), &types, &mock_options()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn names_that_do_not_exist_fail_with_nxdomain() {
+        let missing = Name::from_str("missing.example.com.").unwrap();
+        let result = resolve(&missing, &[RecordType::A], &mock_options());
+        assert!(matches!(result, Err(AppError::NxDomain(_))));
+    }
+
+    #[test]
+    fn error_codes_fail_the_resolution() {
+        let broken = Name::from_str("broken.example.com.").unwrap();
+        let result = resolve(&broken, &[RecordType::A], &mock_options());
+        assert!(matches!(result, Err(AppError::ResponseError(ref code, ..)) if code == "SERVFAIL"));
+    }
+
+    #[test]
+    fn classes_other_than_in_are_asked_for() {
+        let fixture = Fixture::from_toml(
+            r#"
+            [[answer]]
+            name = "version.bind"
+            type = "TXT"
+            class = "CH"
+            records = ['"resoy 1.0"']
+        "#,
+        )
+        .unwrap();
+        let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+            .with_mock(Some(Arc::new(fixture)))
+            .with_class(DNSClass::CH);
+        let version = Name::from_str("version.bind.").unwrap();
+        let records = resolve(&version, &[RecordType::TXT], &options).unwrap();
+        assert_eq!(records[0].dns_class(), DNSClass::CH);
+        // The class is shown, as it isn't IN
+        let line = RecordFormatter::new(&records[0], &OutputConfig::new(true, false)).format();
+        assert!(line.ends_with(" CH \"resoy 1.0\""), "{}", line);
+
+        let options = options.with_class(DNSClass::IN);
+        let result = resolve(&version, &[RecordType::TXT], &options);
+        assert!(matches!(result, Err(AppError::NxDomain(_))));
+    }
+
+    #[test]
+    fn queries_that_time_out_are_sent_retries_more_times() {
+        // A server that reads every query and never answers
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+        let received = Mutex::new(0);
+        let options = ResolveOptions::new(ConnectionType::Udp, &addr)
+            .with_timeout(Duration::from_millis(100))
+            .with_retries(2)
+            .with_strict_parsing(true);
+        let result = thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut buffer = [0; 512];
+                while server.recv(&mut buffer).is_ok() {
+                    *received.lock().unwrap() += 1;
+                }
+            });
+            resolve(&name(), &[RecordType::A], &options)
+        });
+        assert!(
+            matches!(result, Err(AppError::QueryTimeout(_, _, 3, _))),
+            "{:?}",
+            result
+        );
+        assert_eq!(*received.lock().unwrap(), 3);
+    }
+
+    fn name() -> Name {
+        Name::from_str("example.com.").unwrap()
+    }
+
+    fn response(
+        code: ResponseCode,
+        answers: usize,
+    ) -> DnsResponse {
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Response)
+            .set_response_code(code);
+        for index in 0..answers {
+            let address = RData::A(A::new(192, 0, 2, index as u8 + 1));
+            message.add_answer(Record::from_rdata(name(), 300, address));
+        }
+        DnsResponse::from_message(message).unwrap()
+    }
+
+    fn exit_code(responses: &[(RecordType, DnsResponse)]) -> i32 {
+        match response_errors(&name(), "192.0.2.53", responses) {
+            (Some(error), _) => error.exit_code(),
+            (None, _) => 0,
+        }
+    }
+
+    #[test]
+    fn nxdomain_is_the_error_to_fail_with() {
+        let responses = [
+            (RecordType::A, response(ResponseCode::ServFail, 0)),
+            (RecordType::AAAA, response(ResponseCode::NXDomain, 0)),
+            (RecordType::MX, response(ResponseCode::NXDomain, 0)),
+        ];
+        let (error, others) = response_errors(&name(), "192.0.2.53", &responses);
+        assert!(matches!(error, Some(AppError::NxDomain(_))));
+        // SERVFAIL is still reported, NXDOMAIN only once
+        assert_eq!(others.len(), 1);
+        assert!(matches!(others[0], AppError::ResponseError(ref code, ..) if code == "SERVFAIL"));
+        assert_eq!(exit_code(&responses), crate::error::NXDOMAIN_EXIT_CODE);
+    }
+
+    #[test]
+    fn servfail_everywhere_fails_with_a_response_error() {
+        let responses = [
+            (RecordType::A, response(ResponseCode::ServFail, 0)),
+            (RecordType::AAAA, response(ResponseCode::Refused, 0)),
+        ];
+        let (error, others) = response_errors(&name(), "192.0.2.53", &responses);
+        assert!(matches!(error, Some(AppError::ResponseError(ref code, ..)) if code == "SERVFAIL"));
+        assert_eq!(others.len(), 1);
+        assert_eq!(exit_code(&responses), 5);
+    }
+
+    #[test]
+    fn nodata_is_no_error() {
+        let responses = [
+            (RecordType::A, response(ResponseCode::NoError, 0)),
+            (RecordType::AAAA, response(ResponseCode::NoError, 0)),
+        ];
+        let (error, others) = response_errors(&name(), "192.0.2.53", &responses);
+        assert!(error.is_none());
+        assert!(others.is_empty());
+        assert_eq!(exit_code(&responses), 0);
+    }
+
+    #[test]
+    fn an_answer_of_any_type_wins_over_the_errors_of_the_others() {
+        let responses = [
+            (RecordType::A, response(ResponseCode::NoError, 2)),
+            (RecordType::AAAA, response(ResponseCode::ServFail, 0)),
+        ];
+        let (error, others) = response_errors(&name(), "192.0.2.53", &responses);
+        assert!(error.is_none());
+        assert_eq!(others.len(), 1);
+        assert_eq!(exit_code(&responses), 0);
+    }
+}
@@ -0,0 +1,679 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hickory_client::{
+    op::{DnsResponse, ResponseCode},
+    rr::{Name, RData, Record, RecordType},
+};
+
+use crate::{
+    format::{format_timestamp, format_ttl},
+    DnsClient,
+};
+
+/// Timeout of the queries of `--rollover-status`
+pub const ROLLOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Zone key bit of the DNSKEY flags
+pub const ZONE_KEY: u16 = 0x0100;
+
+/// Revoke bit of the DNSKEY flags, set by RFC 5011 rollovers
+pub const REVOKED: u16 = 0x0080;
+
+/// Secure entry point bit of the DNSKEY flags, set on key signing keys
+const SECURE_ENTRY_POINT: u16 = 0x0001;
+
+/// Hold-down time of RFC 5011 before a revoked key may be removed
+const REMOVE_HOLD_DOWN_DAYS: u32 = 30;
+
+struct Key {
+    tag: u16,
+    algorithm: u8,
+    sep: bool,
+    revoked: bool,
+}
+
+struct Ds {
+    tag: u16,
+    algorithm: u8,
+}
+
+struct Signature {
+    algorithm: u8,
+    tag: u16,
+    expiration: u32,
+}
+
+/// Data of a record of `record_type`, which this build only knows as unparsed bytes
+pub fn raw_data(
+    record: &Record,
+    record_type: RecordType,
+) -> Option<&[u8]> {
+    match record.data() {
+        Some(RData::Unknown {
+            code,
+            rdata,
+        }) if *code == record_type => Some(rdata.anything()),
+        _ => None,
+    }
+}
+
+/// Key tag of DNSKEY data, see RFC 4034 appendix B
+pub fn key_tag(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for (index, byte) in data.iter().enumerate() {
+        sum += match index % 2 {
+            0 => u32::from(*byte) << 8,
+            _ => u32::from(*byte),
+        };
+    }
+    sum += (sum >> 16) & 0xFFFF;
+    (sum & 0xFFFF) as u16
+}
+
+fn parse_key(data: &[u8]) -> Option<Key> {
+    let flags = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+    if flags & ZONE_KEY == 0 {
+        return None;
+    }
+    Some(Key {
+        tag: key_tag(data),
+        algorithm: *data.get(3)?,
+        sep: flags & SECURE_ENTRY_POINT != 0,
+        revoked: flags & REVOKED != 0,
+    })
+}
+
+fn parse_ds(data: &[u8]) -> Option<Ds> {
+    Some(Ds {
+        tag: u16::from_be_bytes([*data.first()?, *data.get(1)?]),
+        algorithm: *data.get(2)?,
+    })
+}
+
+/// Signature of `covered`, from the fixed fields in front of the signer name
+fn parse_signature(
+    data: &[u8],
+    covered: RecordType,
+) -> Option<Signature> {
+    let field = |range: std::ops::Range<usize>| data.get(range);
+    let type_covered = u16::from_be_bytes(field(0..2)?.try_into().ok()?);
+    if type_covered != u16::from(covered) {
+        return None;
+    }
+    Some(Signature {
+        algorithm: *data.get(2)?,
+        expiration: u32::from_be_bytes(field(8..12)?.try_into().ok()?),
+        tag: u16::from_be_bytes(field(16..18)?.try_into().ok()?),
+    })
+}
+
+fn signatures(
+    response: &DnsResponse,
+    covered: RecordType,
+) -> Vec<Signature> {
+    response
+        .answers()
+        .iter()
+        .filter_map(|record| parse_signature(raw_data(record, RecordType::RRSIG)?, covered))
+        .collect()
+}
+
+/// TTL of the records of `record_type` among the answers
+fn ttl(
+    response: &DnsResponse,
+    record_type: RecordType,
+) -> Option<u32> {
+    response
+        .answers()
+        .iter()
+        .find(|record| record.record_type() == record_type)
+        .map(Record::ttl)
+}
+
+/// Keys, DS records and signatures of a zone as seen at one moment
+pub struct Observation {
+    zone: Name,
+    keys: Vec<Key>,
+    dnskey_ttl: Option<u32>,
+    ds: Vec<Ds>,
+    ds_ttl: Option<u32>,
+    dnskey_signatures: Vec<Signature>,
+    soa_signatures: Vec<Signature>,
+}
+
+/// Query the DNSKEY, DS and SOA records of `zone` with their signatures
+pub fn observe(
+    client: &DnsClient,
+    zone: &Name,
+) -> Result<Observation, String> {
+    let query = |record_type: RecordType| {
+        let response = client
+            .query_dnssec(zone, record_type)
+            .map_err(|error| format!("{} query failed: {}", record_type, error))?;
+        match response.response_code() {
+            ResponseCode::NoError => Ok(response),
+            code => Err(format!(
+                "{} query answered {}",
+                record_type,
+                format!("{:?}", code).to_uppercase()
+            )),
+        }
+    };
+    let dnskey = query(RecordType::DNSKEY)?;
+    let ds = query(RecordType::DS)?;
+    let soa = query(RecordType::SOA)?;
+
+    let keys: Vec<Key> = dnskey
+        .answers()
+        .iter()
+        .filter_map(|record| parse_key(raw_data(record, RecordType::DNSKEY)?))
+        .collect();
+    if keys.is_empty() {
+        return Err(format!(
+            "{} has no DNSKEY records, or the server strips them",
+            zone
+        ));
+    }
+    Ok(Observation {
+        zone: zone.clone(),
+        keys,
+        dnskey_ttl: ttl(&dnskey, RecordType::DNSKEY),
+        ds: ds
+            .answers()
+            .iter()
+            .filter_map(|record| parse_ds(raw_data(record, RecordType::DS)?))
+            .collect(),
+        ds_ttl: ttl(&ds, RecordType::DS),
+        dnskey_signatures: signatures(&dnskey, RecordType::DNSKEY),
+        soa_signatures: signatures(&soa, RecordType::SOA),
+    })
+}
+
+/// Stage of one kind of rollover, with what has to happen next
+pub struct Assessment {
+    /// machine-readable stage, e.g. `new-key-published`
+    pub stage: &'static str,
+    summary: String,
+    next: Option<String>,
+}
+
+impl Assessment {
+    fn new(
+        stage: &'static str,
+        summary: String,
+        next: Option<String>,
+    ) -> Self {
+        Self {
+            stage,
+            summary,
+            next,
+        }
+    }
+}
+
+/// Stages of the KSK, ZSK and algorithm rollovers of a zone
+pub struct Status {
+    observation: Observation,
+    pub ksk: Assessment,
+    pub zsk: Assessment,
+    pub algorithm: Assessment,
+}
+
+impl Observation {
+    fn has_ds(
+        &self,
+        key: &Key,
+    ) -> bool {
+        self.ds
+            .iter()
+            .any(|ds| ds.tag == key.tag && ds.algorithm == key.algorithm)
+    }
+
+    fn signs(
+        signatures: &[Signature],
+        key: &Key,
+    ) -> bool {
+        signatures
+            .iter()
+            .any(|signature| signature.tag == key.tag && signature.algorithm == key.algorithm)
+    }
+
+    fn ttl_text(ttl: Option<u32>) -> String {
+        ttl.map(format_ttl)
+            .unwrap_or_else(|| String::from("unknown"))
+    }
+
+    fn assess_algorithm(&self) -> Assessment {
+        let key_algorithms: BTreeSet<u8> = self.keys.iter().map(|key| key.algorithm).collect();
+        let ds_algorithms: BTreeSet<u8> = self.ds.iter().map(|ds| ds.algorithm).collect();
+        let signature_algorithms: BTreeSet<u8> = self
+            .dnskey_signatures
+            .iter()
+            .chain(&self.soa_signatures)
+            .map(|signature| signature.algorithm)
+            .collect();
+        let all: BTreeSet<u8> = key_algorithms
+            .iter()
+            .chain(&ds_algorithms)
+            .chain(&signature_algorithms)
+            .copied()
+            .collect();
+        let list = |algorithms: &BTreeSet<u8>| {
+            algorithms
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" and ")
+        };
+        let dnskey_ttl = Self::ttl_text(self.dnskey_ttl);
+        let ds_ttl = Self::ttl_text(self.ds_ttl);
+
+        if all.len() <= 1 {
+            return Assessment::new(
+                "stable",
+                format!("everything uses algorithm {}", list(&all)),
+                None,
+            );
+        }
+        let unsigned: BTreeSet<u8> = signature_algorithms
+            .difference(&key_algorithms)
+            .copied()
+            .collect();
+        let without_ds: BTreeSet<u8> = key_algorithms.difference(&ds_algorithms).copied().collect();
+        let new: BTreeSet<u8> = match ds_algorithms.len() {
+            1 => without_ds,
+            _ => BTreeSet::new(),
+        };
+
+        if key_algorithms.len() == 1 && !unsigned.is_empty() && ds_algorithms == key_algorithms {
+            // Either the new algorithm's signatures came first, or the old keys are gone
+            return Assessment::new(
+                "signatures-without-keys",
+                format!(
+                    "signatures of algorithm {} are published without its keys",
+                    list(&unsigned)
+                ),
+                Some(format!(
+                    "if {} is new, publish its keys once the largest TTL of the zone has passed; if it is old, remove its signatures once the DNSKEY TTL of {} has passed",
+                    list(&unsigned),
+                    dnskey_ttl
+                )),
+            );
+        }
+        if ds_algorithms.len() > 1 {
+            return Assessment::new(
+                "new-ds-at-parent",
+                format!(
+                    "the parent has DS records of algorithms {}",
+                    list(&ds_algorithms)
+                ),
+                Some(format!(
+                    "once the DS TTL of {} has passed, withdraw the DS of the old algorithm",
+                    ds_ttl
+                )),
+            );
+        }
+        if !new.is_empty() {
+            return Assessment::new(
+                "new-keys-published",
+                format!(
+                    "keys of algorithm {} are published, the parent only has DS records of algorithm {}",
+                    list(&new),
+                    list(&ds_algorithms)
+                ),
+                Some(format!(
+                    "once the DNSKEY TTL of {} has passed, submit the DS of algorithm {} to the parent; if {} is the old algorithm instead, remove its keys once the DS TTL of {} has passed",
+                    dnskey_ttl,
+                    list(&new),
+                    list(&ds_algorithms),
+                    ds_ttl
+                )),
+            );
+        }
+        Assessment::new(
+            "inconsistent",
+            format!(
+                "keys of algorithm {}, DS records of algorithm {}, signatures of algorithm {}",
+                list(&key_algorithms),
+                list(&ds_algorithms),
+                list(&signature_algorithms)
+            ),
+            Some(String::from(
+                "make sure every algorithm with a DS has keys signing the DNSKEY records",
+            )),
+        )
+    }
+
+    fn assess_ksk(
+        &self,
+        new_key: Option<u16>,
+    ) -> Assessment {
+        let revoked: Vec<&Key> = self.keys.iter().filter(|key| key.revoked).collect();
+        let sep: Vec<&Key> = self
+            .keys
+            .iter()
+            .filter(|key| key.sep && !key.revoked)
+            .collect();
+        // Zones signed with a single key often don't set the SEP bit
+        let ksks: Vec<&Key> = match sep.is_empty() {
+            true => self
+                .keys
+                .iter()
+                .filter(|key| self.has_ds(key) && !key.revoked)
+                .collect(),
+            false => sep,
+        };
+        let with_ds: Vec<&Key> = ksks
+            .iter()
+            .copied()
+            .filter(|key| self.has_ds(key))
+            .collect();
+        let dnskey_ttl = Self::ttl_text(self.dnskey_ttl);
+        let ds_ttl = Self::ttl_text(self.ds_ttl);
+
+        if let Some(key) = revoked.first() {
+            return Assessment::new(
+                "old-key-revoked",
+                format!("key {} is published with the revoke bit", key.tag),
+                Some(format!(
+                    "remove key {} once the {} day hold-down of RFC 5011 has passed",
+                    key.tag, REMOVE_HOLD_DOWN_DAYS
+                )),
+            );
+        }
+        if self.ds.is_empty() {
+            return Assessment::new(
+                "insecure",
+                format!("the parent has no DS records for {}", self.zone),
+                Some(String::from(
+                    "submit the DS of the key signing key to the parent to secure the delegation",
+                )),
+            );
+        }
+        if with_ds.is_empty() {
+            return Assessment::new(
+                "ds-mismatch",
+                String::from("no DS record at the parent matches a published key"),
+                Some(String::from(
+                    "publish the key of the DS, or submit the DS of a published key now, validation fails until then",
+                )),
+            );
+        }
+        match (ksks.len(), with_ds.len()) {
+            (1, _) => Assessment::new(
+                "stable",
+                format!("key {} has its DS at the parent", ksks[0].tag),
+                None,
+            ),
+            (_, 1) => {
+                let with = with_ds[0];
+                let without: Vec<&Key> = ksks
+                    .iter()
+                    .copied()
+                    .filter(|key| key.tag != with.tag)
+                    .collect();
+                let tags: Vec<String> = without.iter().map(|key| key.tag.to_string()).collect();
+                if new_key == Some(with.tag) {
+                    Assessment::new(
+                        "old-key-removable",
+                        format!(
+                            "only the new key {} has its DS at the parent",
+                            with.tag
+                        ),
+                        Some(format!(
+                            "remove key {} once the DS TTL of {} has passed since its DS was withdrawn",
+                            tags.join(", "),
+                            ds_ttl
+                        )),
+                    )
+                } else {
+                    let unsigned: Vec<String> = without
+                        .iter()
+                        .filter(|key| !Self::signs(&self.dnskey_signatures, key))
+                        .map(|key| key.tag.to_string())
+                        .collect();
+                    let mut next = format!(
+                        "once the DNSKEY TTL of {} has passed since key {} was published, submit its DS to the parent",
+                        dnskey_ttl,
+                        tags.join(", ")
+                    );
+                    if !unsigned.is_empty() {
+                        next.push_str(&format!(
+                            " (key {} doesn't sign the DNSKEY records yet)",
+                            unsigned.join(", ")
+                        ));
+                    }
+                    if new_key.is_none() {
+                        next.push_str(&format!(
+                            "; if {} is the new key instead, the old one is removable, pass --new-key to tell",
+                            with.tag
+                        ));
+                    }
+                    Assessment::new(
+                        "new-key-published",
+                        format!(
+                            "key {} is published, the parent only has the DS of key {}",
+                            tags.join(", "),
+                            with.tag
+                        ),
+                        Some(next),
+                    )
+                }
+            },
+            _ => Assessment::new(
+                "new-ds-at-parent",
+                String::from("the parent has the DS of both keys"),
+                Some(format!(
+                    "once the DS TTL of {} has passed, withdraw the DS of the old key",
+                    ds_ttl
+                )),
+            ),
+        }
+    }
+
+    fn assess_zsk(
+        &self,
+        new_key: Option<u16>,
+    ) -> Assessment {
+        let zsks: Vec<&Key> = self
+            .keys
+            .iter()
+            .filter(|key| !key.sep && !key.revoked && !self.has_ds(key))
+            .collect();
+        let signing: Vec<&Key> = zsks
+            .iter()
+            .copied()
+            .filter(|key| Self::signs(&self.soa_signatures, key))
+            .collect();
+        let dnskey_ttl = Self::ttl_text(self.dnskey_ttl);
+
+        match (zsks.len(), signing.len()) {
+            (0, _) => Assessment::new(
+                "none",
+                String::from("the key signing key signs the zone as well"),
+                None,
+            ),
+            (1, _) => Assessment::new(
+                "stable",
+                format!("key {} signs the zone", zsks[0].tag),
+                None,
+            ),
+            (_, 0) => Assessment::new(
+                "not-signing",
+                String::from("no zone signing key signs the SOA record"),
+                Some(String::from("check the signer, signatures may be missing")),
+            ),
+            (keys, signing) if keys == signing => Assessment::new(
+                "double-signature",
+                String::from("every zone signing key signs the zone"),
+                Some(format!(
+                    "once the DNSKEY TTL of {} has passed, stop signing with the old key and remove it",
+                    dnskey_ttl
+                )),
+            ),
+            _ => {
+                let idle: Vec<String> = zsks
+                    .iter()
+                    .filter(|key| !Self::signs(&self.soa_signatures, key))
+                    .map(|key| key.tag.to_string())
+                    .collect();
+                let active: Vec<String> = signing.iter().map(|key| key.tag.to_string()).collect();
+                if new_key.is_some_and(|tag| active.contains(&tag.to_string())) {
+                    Assessment::new(
+                        "old-key-removable",
+                        format!(
+                            "the new key {} signs the zone, key {} is published only",
+                            active.join(", "),
+                            idle.join(", ")
+                        ),
+                        Some(format!(
+                            "remove key {} once the largest TTL of the zone has passed since signing switched",
+                            idle.join(", ")
+                        )),
+                    )
+                } else {
+                    let mut next = format!(
+                        "once the DNSKEY TTL of {} has passed since key {} was published, sign with it instead",
+                        dnskey_ttl,
+                        idle.join(", ")
+                    );
+                    if new_key.is_none() {
+                        next.push_str(&format!(
+                            "; if {} is the new key instead, the old one is removable, pass --new-key to tell",
+                            active.join(", ")
+                        ));
+                    }
+                    Assessment::new(
+                        "new-key-published",
+                        format!(
+                            "key {} signs the zone, key {} is published only",
+                            active.join(", "),
+                            idle.join(", ")
+                        ),
+                        Some(next),
+                    )
+                }
+            },
+        }
+    }
+}
+
+impl Status {
+    /// Interpret an observation, `new_key` being the tag of the key being introduced if known
+    pub fn new(
+        observation: Observation,
+        new_key: Option<u16>,
+    ) -> Self {
+        let algorithm = observation.assess_algorithm();
+        let (ksk, zsk) = match algorithm.stage {
+            // Both algorithms have keys of their own, which the algorithm rollover covers
+            "stable" => (
+                observation.assess_ksk(new_key),
+                observation.assess_zsk(new_key),
+            ),
+            _ => (
+                Assessment::new(
+                    "algorithm-rollover",
+                    String::from("part of the algorithm rollover"),
+                    None,
+                ),
+                Assessment::new(
+                    "algorithm-rollover",
+                    String::from("part of the algorithm rollover"),
+                    None,
+                ),
+            ),
+        };
+        Self {
+            observation,
+            ksk,
+            zsk,
+            algorithm,
+        }
+    }
+}
+
+fn expiration_text(expiration: u32) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(u64::from(expiration));
+    let now = SystemTime::now();
+    let remaining = match time.duration_since(now) {
+        Ok(left) => format!("in {}", format_ttl(left.as_secs() as u32)),
+        Err(_) => String::from("expired"),
+    };
+    format!("{} ({})", format_timestamp(time), remaining)
+}
+
+impl Display for Status {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let observation = &self.observation;
+        writeln!(f, "Rollover status of {}:", observation.zone)?;
+        for key in &observation.keys {
+            let mut notes = vec![match (key.sep, key.revoked) {
+                (_, true) => String::from("revoked"),
+                (true, _) => String::from("KSK"),
+                (false, _) => String::from("ZSK"),
+            }];
+            if observation.has_ds(key) {
+                notes.push(String::from("DS at parent"));
+            }
+            for (signatures, covered) in [
+                (&observation.dnskey_signatures, "DNSKEY"),
+                (&observation.soa_signatures, "SOA"),
+            ] {
+                if let Some(signature) = signatures.iter().find(|signature| {
+                    signature.tag == key.tag && signature.algorithm == key.algorithm
+                }) {
+                    notes.push(format!(
+                        "signs {} until {}",
+                        covered,
+                        expiration_text(signature.expiration)
+                    ));
+                }
+            }
+            writeln!(
+                f,
+                "  key {} algorithm {}: {}",
+                key.tag,
+                key.algorithm,
+                notes.join(", ")
+            )?;
+        }
+        for ds in &observation.ds {
+            if !observation.keys.iter().any(|key| key.tag == ds.tag) {
+                writeln!(
+                    f,
+                    "  DS {} algorithm {}: no published key matches",
+                    ds.tag, ds.algorithm
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "  timers: DNSKEY TTL {}, DS TTL {}",
+            Observation::ttl_text(observation.dnskey_ttl),
+            Observation::ttl_text(observation.ds_ttl)
+        )?;
+        for (label, assessment) in [
+            ("KSK", &self.ksk),
+            ("ZSK", &self.zsk),
+            ("algorithm", &self.algorithm),
+        ] {
+            writeln!(f, "  {}: {}", label, assessment.summary)?;
+            if let Some(next) = &assessment.next {
+                writeln!(f, "    next: {}", next)?;
+            }
+        }
+        write!(
+            f,
+            "  stage: ksk={} zsk={} algorithm={}",
+            self.ksk.stage, self.zsk.stage, self.algorithm.stage
+        )
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::SystemTime,
+};
+
+use clap::ValueEnum;
+use hickory_client::rr::Name;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use toml::Spanned;
+
+use crate::{tsig, AppError, ConnectionType, DnsClient, ResolveOptions};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    suffix: Spanned<String>,
+    server: Spanned<String>,
+    connection: Option<Spanned<String>>,
+    timeout: Option<Spanned<String>>,
+    tsig: Option<Spanned<TsigEntry>>,
+}
+
+/// Key of a rule, as in the key files of BIND
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TsigEntry {
+    name: String,
+    #[serde(default = "default_algorithm")]
+    algorithm: String,
+    /// base64
+    secret: String,
+}
+
+fn default_algorithm() -> String {
+    String::from("hmac-sha256")
+}
+
+#[derive(Deserialize)]
+struct RulesFileContent {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleEntry>,
+}
+
+/// Upstream of the queries for the names under a suffix
+pub struct Rule {
+    suffix: Name,
+    /// line of the rule in the file
+    line: usize,
+    server: String,
+    connection: ConnectionType,
+    pub client: DnsClient,
+    queries: AtomicU64,
+}
+
+impl Rule {
+    /// How the rule is told apart in logs, e.g. `line 3 (corp.example.)`
+    pub fn label(&self) -> String {
+        format!("line {} ({})", self.line, self.suffix)
+    }
+}
+
+/// Rules of `resoy proxy --rules`, the rule of the longest suffix first
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    /// Parse and check the rules of `content`, connecting to the server of every rule with
+    /// `options`
+    fn parse(
+        content: &str,
+        options: &ResolveOptions,
+    ) -> Result<Self, String> {
+        let content_lines = |offset: usize| content[..offset].matches('\n').count() + 1;
+        let parsed: RulesFileContent = toml::from_str(content).map_err(|e| e.to_string())?;
+
+        let mut rules: Vec<Rule> = Vec::with_capacity(parsed.rules.len());
+        for entry in parsed.rules {
+            let line = content_lines(entry.suffix.span().start);
+            let error = |spanned_line: usize, message: String| {
+                format!("line {}: {}", spanned_line, message)
+            };
+            let suffix = parse_suffix(entry.suffix.get_ref()).ok_or_else(|| {
+                error(line, format!("invalid suffix {:?}", entry.suffix.get_ref()))
+            })?;
+            if let Some(other) = rules.iter().find(|rule| rule.suffix == suffix) {
+                return Err(error(
+                    line,
+                    format!("suffix {} is already routed on line {}", suffix, other.line),
+                ));
+            }
+            let connection = match &entry.connection {
+                Some(value) => ConnectionType::from_str(value.get_ref(), true).map_err(|_| {
+                    error(
+                        content_lines(value.span().start),
+                        format!("unknown connection {:?}", value.get_ref()),
+                    )
+                })?,
+                None => options.connection(),
+            };
+            let timeout = match &entry.timeout {
+                Some(value) => crate::parse::parse_duration(value.get_ref())
+                    .map_err(|message| error(content_lines(value.span().start), message))?,
+                None => options.timeout(),
+            };
+            let tsig = match &entry.tsig {
+                Some(value) => {
+                    let tsig_error =
+                        |message: String| error(content_lines(value.span().start), message);
+                    let key = value.get_ref();
+                    let key = tsig::Key::parse(&key.name, &key.algorithm, &key.secret)
+                        .map_err(tsig_error)?;
+                    tsig::Signer::new(&key).map_err(tsig_error)?;
+                    Some(key)
+                },
+                None => None,
+            };
+            let server = entry.server.into_inner();
+            let options = options
+                .clone()
+                .with_server(&server)
+                .with_connection(connection)
+                .with_timeout(timeout)
+                .with_tsig(tsig);
+            let client = DnsClient::new(&options).map_err(|e| error(line, format!("{:?}", e)))?;
+            rules.push(Rule {
+                suffix,
+                line,
+                server,
+                connection,
+                client,
+                queries: AtomicU64::new(0),
+            });
+        }
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.suffix.num_labels()));
+        Ok(Self {
+            rules,
+        })
+    }
+
+    /// Rule of the longest suffix of `name`, counting the query, `None` when no rule matches
+    pub fn route(
+        &self,
+        name: &Name,
+    ) -> Option<&Rule> {
+        let rule = self.rules.iter().find(|rule| rule.suffix.zone_of(name))?;
+        rule.queries.fetch_add(1, Ordering::Relaxed);
+        Some(rule)
+    }
+
+    /// Queries routed by every rule, in the order of the file
+    fn to_json(&self) -> Value {
+        let mut rules: Vec<&Rule> = self.rules.iter().collect();
+        rules.sort_by_key(|rule| rule.line);
+        let rules: Vec<Value> = rules
+            .into_iter()
+            .map(|rule| {
+                json!({
+                    "line": rule.line,
+                    "suffix": rule.suffix.to_string(),
+                    "server": rule.server,
+                    "connection": rule.connection.to_string(),
+                    "queries": rule.queries.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+        Value::Array(rules)
+    }
+}
+
+/// The name of a suffix, written as `corp.example`, `*.corp.example` or `*.` for every name
+fn parse_suffix(suffix: &str) -> Option<Name> {
+    let suffix = suffix.strip_prefix('*').unwrap_or(suffix);
+    let suffix = suffix.strip_prefix('.').unwrap_or(suffix);
+    if suffix.is_empty() {
+        return Some(Name::root());
+    }
+    let mut name = Name::from_str(suffix).ok()?;
+    name.set_fqdn(true);
+    Some(name)
+}
+
+/// Rules of a file, read again on SIGHUP or once the file changes.
+///
+/// A reload only replaces the rules once the new ones parse, and queries in flight keep the
+/// rules they were routed with.
+pub struct Router {
+    path: PathBuf,
+    options: ResolveOptions,
+    current: RwLock<Arc<Rules>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl Router {
+    /// Read the rules of `path`, those without a connection or timeout of their own taking
+    /// those of `options`
+    pub fn new(
+        path: &Path,
+        options: &ResolveOptions,
+    ) -> Result<Self, AppError> {
+        let modified = modified(path);
+        let rules = read(path, options)?;
+        Ok(Self {
+            path: path.to_owned(),
+            options: options.clone(),
+            current: RwLock::new(Arc::new(rules)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    pub fn rules(&self) -> Arc<Rules> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Whether the file changed since the rules were last read
+    pub fn changed(&self) -> bool {
+        let modified = modified(&self.path);
+        modified.is_some() && modified != *self.modified.lock().unwrap()
+    }
+
+    /// Read the rules again, keeping the current ones when the new ones don't parse
+    pub fn reload(&self) {
+        *self.modified.lock().unwrap() = modified(&self.path);
+        match read(&self.path, &self.options) {
+            Ok(rules) => {
+                eprintln!(
+                    "{} rules reloaded from {}",
+                    rules.rules.len(),
+                    self.path.display()
+                );
+                *self.current.write().unwrap() = Arc::new(rules);
+            },
+            Err(error) => eprintln!("Error: {}, keeping the previous rules", error),
+        }
+    }
+
+    /// Queries routed by every rule since they were read or reset
+    pub fn to_json(&self) -> Value {
+        self.rules().to_json()
+    }
+
+    pub fn reset(&self) {
+        for rule in &self.rules().rules {
+            rule.queries.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+fn read(
+    path: &Path,
+    options: &ResolveOptions,
+) -> Result<Rules, AppError> {
+    let error = |message: String| AppError::RulesError(path.to_owned(), message);
+    let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    Rules::parse(&content, options).map_err(error)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Rules, String> {
+        Rules::parse(content, &ResolveOptions::default())
+    }
+
+    #[test]
+    fn keys_are_checked_at_their_line() {
+        let content = "[[rule]]\nsuffix = \"corp.example.\"\nserver = \"10.0.0.53\"\n\
+                       tsig = { name = \"key.\", algorithm = \"hmac-md5\", secret = \"c2VjcmV0\" }\n";
+        let error = parse(content).err().unwrap();
+        assert!(
+            error.starts_with("line 4: unsupported TSIG algorithm"),
+            "{}",
+            error
+        );
+        let content = content
+            .replace("hmac-md5", "hmac-sha256")
+            .replace("c2VjcmV0", "!");
+        let error = parse(&content).err().unwrap();
+        assert_eq!(error, "line 4: the secret is not valid base64");
+    }
+
+    #[cfg(feature = "tsig")]
+    #[test]
+    fn keys_are_taken_for_rules_over_tcp() {
+        let content = "[[rule]]\nsuffix = \"corp.example.\"\nserver = \"10.0.0.53\"\n\
+                       connection = \"tcp\"\ntsig = { name = \"key.\", secret = \"c2VjcmV0\" }\n";
+        let rules = parse(content).unwrap();
+        assert_eq!(rules.rules[0].line, 2);
+    }
+
+    #[cfg(all(feature = "tsig", feature = "doh"))]
+    #[test]
+    fn keys_are_refused_over_https() {
+        let content = "[[rule]]\nsuffix = \"corp.example.\"\nserver = \"10.0.0.53\"\n\
+                       connection = \"doh-json\"\ntsig = { name = \"key.\", secret = \"c2VjcmV0\" }\n";
+        let error = parse(content).err().unwrap();
+        assert!(error.contains("cannot be signed"), "{}", error);
+    }
+
+    #[cfg(not(feature = "tsig"))]
+    #[test]
+    fn keys_need_the_feature() {
+        let content = "[[rule]]\nsuffix = \"corp.example.\"\nserver = \"10.0.0.53\"\n\
+                       tsig = { name = \"key.\", secret = \"c2VjcmV0\" }\n";
+        let error = parse(content).err().unwrap();
+        assert_eq!(
+            error,
+            "line 4: TSIG needs resoy built with the tsig feature"
+        );
+    }
+}
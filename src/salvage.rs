@@ -0,0 +1,329 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    error::{ClientError, ClientErrorKind, ClientResult},
+    op::{DnsResponse, Header, Message, Query},
+    proto::error::ProtoErrorKind,
+    rr::{Name, Record, RecordType},
+    serialize::binary::{BinDecodable, BinDecoder, BinEncodable},
+};
+
+use crate::{
+    client::Session,
+    tcp::failure,
+    tsig::{Signer, Verifier},
+};
+
+/// Where the parsing of a response broke off
+#[derive(Debug, PartialEq, Eq)]
+pub struct Corruption {
+    section: &'static str,
+    offset: usize,
+    error: String,
+}
+
+impl Display for Corruption {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "in its {} section at byte {} ({})",
+            self.section, self.offset, self.error
+        )
+    }
+}
+
+/// Parse as much of `bytes` as possible, keeping the sections before the one that fails.
+///
+/// The header and the question have to be intact, as without them the answers would not be
+/// known to belong to the query.
+pub fn parse_lenient(bytes: &[u8]) -> Result<(Message, Option<Corruption>), String> {
+    if let Ok(message) = Message::from_vec(bytes) {
+        return Ok((message, None));
+    }
+    let mut decoder = BinDecoder::new(bytes);
+    let header = Header::read(&mut decoder).map_err(|error| error.to_string())?;
+    let mut message = Message::new();
+    message.set_header(header);
+    for _ in 0..header.query_count() {
+        let query = Query::read(&mut decoder).map_err(|error| error.to_string())?;
+        message.add_query(query);
+    }
+
+    let sections = [
+        ("answer", header.answer_count()),
+        ("authority", header.name_server_count()),
+        ("additional", header.additional_count()),
+    ];
+    for (section, count) in sections {
+        for _ in 0..count {
+            let offset = decoder.index();
+            let record = match Record::read(&mut decoder) {
+                Ok(record) => record,
+                Err(error) => {
+                    let corruption = Corruption {
+                        section,
+                        offset,
+                        error: error.to_string(),
+                    };
+                    return Ok((message, Some(corruption)));
+                },
+            };
+            match section {
+                "answer" => message.add_answer(record),
+                "authority" => message.add_name_server(record),
+                _ => message.add_additional(record),
+            };
+        }
+    }
+    // Every record parsed, so what failed was e.g. a second OPT record or a misplaced TSIG
+    let corruption = Corruption {
+        section: "additional",
+        offset: decoder.index(),
+        error: Message::from_vec(bytes)
+            .err()
+            .map(|error| error.to_string())
+            .unwrap_or_default(),
+    };
+    Ok((message, Some(corruption)))
+}
+
+/// Response of the bytes that arrived, with where their parsing broke off when only the
+/// records before it could be kept
+pub type Lenient = (DnsResponse, Option<Corruption>);
+
+/// Read the response to the query `id` out of `bytes`, checking its signature with `verifier`.
+///
+/// With `lenient` the records before a corrupt section are kept, unless the response is
+/// signed, as the signature of a corrupt response cannot be checked.
+pub fn read_response(
+    bytes: Vec<u8>,
+    id: u16,
+    verifier: Option<Verifier>,
+    lenient: bool,
+) -> ClientResult<Lenient> {
+    let (response, corruption) = match lenient {
+        true => parse_lenient(&bytes).map_err(ClientError::from)?,
+        false => (Message::from_bytes(&bytes)?, None),
+    };
+    if response.id() != id {
+        return Err(ClientError::from("answer to another query"));
+    }
+    match (verifier, corruption) {
+        (Some(verifier), None) => Ok((verifier.verify(&bytes)?, None)),
+        (Some(_), Some(corruption)) => Err(ClientError::from(format!(
+            "the signed response is corrupt {}, so its signature cannot be checked",
+            corruption
+        ))),
+        (None, corruption) => Ok((DnsResponse::new(response, bytes), corruption)),
+    }
+}
+
+/// Send `message` over UDP from `bind_addr` on a socket of ours, which keeps the response
+/// that arrives within `timeout` even when it does not parse with `lenient`, unlike the
+/// client of hickory that drops it and waits for another until the timeout
+pub fn send_udp(
+    server: SocketAddr,
+    bind_addr: Option<SocketAddr>,
+    mut message: Message,
+    signer: Option<&Signer>,
+    timeout: Duration,
+    lenient: bool,
+) -> ClientResult<Lenient> {
+    let id = RandomState::new().build_hasher().finish() as u16;
+    message.set_id(id);
+    let verifier = signer.map(|signer| signer.sign(&mut message)).transpose()?;
+    let query = message.to_bytes()?;
+    let local = bind_addr.unwrap_or_else(|| match server {
+        SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        SocketAddr::V6(_) => SocketAddr::from(([0; 16], 0)),
+    });
+    let socket = UdpSocket::bind(local).map_err(failure)?;
+    socket.connect(server).map_err(failure)?;
+    socket.send(&query).map_err(failure)?;
+    let deadline = Instant::now() + timeout;
+    let mut buffer = vec![0; u16::MAX as usize];
+    // Responses to earlier attempts may still come, and are no answer to this one
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(ClientError::from(ClientErrorKind::Timeout));
+        }
+        socket.set_read_timeout(Some(left)).map_err(failure)?;
+        let length = socket.recv(&mut buffer).map_err(failure)?;
+        if length >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+            return read_response(buffer[..length].to_vec(), id, verifier, lenient);
+        }
+    }
+}
+
+/// Count the response of `server` to `name` and `query_type` that was salvaged in `session`,
+/// and tell on stderr what was lost of it
+pub fn warn(
+    server: SocketAddr,
+    name: &Name,
+    query_type: RecordType,
+    corruption: &Corruption,
+    session: &Session,
+) {
+    session.add_salvaged();
+    eprintln!(
+        "WARNING: the response of {} to {} {} is corrupt {}, only the records before it are \
+         shown",
+        server, name, query_type, corruption
+    );
+}
+
+/// Whether the query failed the way hickory fails on responses it cannot parse: it drops
+/// them and waits for another until the timeout
+pub fn timed_out(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Timeout => true,
+        ClientErrorKind::Proto(error) => matches!(error.kind(), ProtoErrorKind::Timeout),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{str::FromStr, thread};
+
+    use hickory_client::{
+        op::{MessageType, OpCode},
+        rr::RData,
+    };
+
+    use super::*;
+
+    fn capture(hex: &str) -> Vec<u8> {
+        hex.lines()
+            .filter(|line| !line.starts_with('#'))
+            .flat_map(str::split_whitespace)
+            .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+            .collect()
+    }
+
+    /// Bytes of `tests/fixtures/corrupt/opt-overrun.hex`, a response with a corrupt OPT record
+    pub(crate) fn opt_overrun() -> Vec<u8> {
+        capture(include_str!("../tests/fixtures/corrupt/opt-overrun.hex"))
+    }
+
+    fn addresses(message: &Message) -> Vec<String> {
+        message
+            .answers()
+            .iter()
+            .filter_map(|record| record.data().map(RData::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn answers_before_a_corrupt_additional_section_are_kept() {
+        let bytes = opt_overrun();
+        assert!(Message::from_vec(&bytes).is_err());
+        let (message, corruption) = parse_lenient(&bytes).unwrap();
+        assert_eq!(addresses(&message), ["192.0.2.1"]);
+        let corruption = corruption.unwrap();
+        assert_eq!((corruption.section, corruption.offset), ("additional", 45));
+        assert!(corruption
+            .to_string()
+            .starts_with("in its additional section at byte 45 ("));
+    }
+
+    #[test]
+    fn answers_before_a_corrupt_answer_are_kept() {
+        let bytes = capture(include_str!("../tests/fixtures/corrupt/answer-pointer.hex"));
+        let (message, corruption) = parse_lenient(&bytes).unwrap();
+        assert_eq!(addresses(&message), ["192.0.2.1"]);
+        let corruption = corruption.unwrap();
+        assert_eq!((corruption.section, corruption.offset), ("answer", 45));
+    }
+
+    #[test]
+    fn responses_cut_in_their_question_are_not_salvaged() {
+        let bytes = capture(include_str!("../tests/fixtures/corrupt/question-cut.hex"));
+        assert!(parse_lenient(&bytes).is_err());
+    }
+
+    #[test]
+    fn intact_responses_have_no_corruption() {
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Response)
+            .set_op_code(OpCode::Query);
+        let bytes = message.to_bytes().unwrap();
+        let (parsed, corruption) = parse_lenient(&bytes).unwrap();
+        assert_eq!(parsed, message);
+        assert_eq!(corruption, None);
+    }
+
+    #[test]
+    fn only_lenient_reads_keep_corrupt_responses() {
+        let (response, corruption) = read_response(opt_overrun(), 0, None, true).unwrap();
+        assert_eq!(addresses(&response), ["192.0.2.1"]);
+        assert!(corruption.is_some());
+        assert!(read_response(opt_overrun(), 0, None, false).is_err());
+        // A response with the ID of another query is no answer, however well it parses
+        assert!(read_response(opt_overrun(), 1, None, true).is_err());
+    }
+
+    /// Responder answering the first query with `stale`, a response to some earlier query,
+    /// and then with `bytes` under the ID of the query
+    fn responder(bytes: Vec<u8>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut query = [0; 512];
+            let (_, client) = socket.recv_from(&mut query).unwrap();
+            let mut stale = bytes.clone();
+            stale[..2].copy_from_slice(&[query[0] ^ 0xff, query[1]]);
+            socket.send_to(&stale, client).unwrap();
+            let mut response = bytes;
+            response[..2].copy_from_slice(&query[..2]);
+            socket.send_to(&response, client).unwrap();
+        });
+        addr
+    }
+
+    fn query() -> Message {
+        let mut message = Message::new();
+        message.add_query(Query::query(
+            Name::from_str("example.com.").unwrap(),
+            RecordType::A,
+        ));
+        message
+    }
+
+    #[test]
+    fn corrupt_udp_responses_that_arrive_are_salvaged() {
+        let server = responder(opt_overrun());
+        let (response, corruption) =
+            send_udp(server, None, query(), None, Duration::from_secs(2), true).unwrap();
+        assert_eq!(addresses(&response), ["192.0.2.1"]);
+        assert_eq!(corruption.unwrap().section, "additional");
+    }
+
+    #[test]
+    fn silent_udp_servers_time_out_within_the_timeout() {
+        // Bound, so that nothing answers with port unreachable, but never read
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let started = Instant::now();
+        let result = send_udp(
+            socket.local_addr().unwrap(),
+            None,
+            query(),
+            None,
+            Duration::from_millis(100),
+            true,
+        );
+        assert!(timed_out(&result.err().unwrap()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
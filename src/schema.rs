@@ -0,0 +1,812 @@
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{outln, AppError};
+
+/// Version of the shape of the JSON outputs, given in their `schema` field. It is only bumped
+/// when an output changes in a way that breaks its readers, new fields don't.
+pub const SCHEMA: &str = "resoy/1";
+
+/// The JSON outputs, each with a schema of its own
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+#[clap(rename_all = "kebab_case")]
+pub enum Output {
+    /// records of a name, --json
+    Lookup,
+    /// records of every name of several --name, --json
+    Names,
+    /// line of every name of --file, --json
+    Batch,
+    /// last line of --file --aggregate --json, and the file of --aggregate-output
+    /// ending with .json
+    Aggregate,
+    /// records of every server of several --server, --json, and their --diff
+    Servers,
+    /// records of a zone transfer, --axfr --json
+    Transfer,
+    /// line of every query of resoy proxy --output jsonl
+    ProxyLog,
+    /// counters that resoy proxy dumps on SIGUSR2 and serves at --stats-listen
+    ProxyStats,
+    /// checks of resoy doctor --json
+    Doctor,
+    /// report of --dnssec-readiness json
+    Readiness,
+    /// lines of --audit-txt jsonl
+    Audit,
+    /// table or single type of resoy types --json
+    Types,
+}
+
+impl Output {
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Args)]
+pub struct SchemaArgs {
+    /// output to print the schema of, every output's when not given
+    output: Option<Output>,
+}
+
+impl SchemaArgs {
+    /// Print the JSON Schema of the output, or an object of every schema under the name of
+    /// its output
+    pub fn run(&self) -> Result<(), AppError> {
+        let document = match self.output {
+            Some(output) => schema(output),
+            None => Value::Object(
+                Output::value_variants()
+                    .iter()
+                    .map(|output| (output.name(), schema(*output)))
+                    .collect(),
+            ),
+        };
+        outln!("{}", serde_json::to_string_pretty(&document).unwrap());
+        Ok(())
+    }
+}
+
+/// `value` serialized with the `schema` field among its own, for outputs of serde types
+pub fn versioned<T: Serialize>(value: &T) -> Value {
+    let mut value = serde_json::to_value(value).unwrap_or_default();
+    if let Value::Object(fields) = &mut value {
+        fields.insert(String::from("schema"), Value::from(SCHEMA));
+    }
+    value
+}
+
+/// Output of `--json` for a name, `records` being from `format::json_records`
+pub fn lookup(
+    records: Value,
+    hints: Vec<String>,
+) -> Value {
+    json!({ "schema": SCHEMA, "records": records, "hints": hints })
+}
+
+/// Output of `--json` for several names, each of `names` being `{"name", "records", "hints"}`
+/// or with the `error` of the name
+pub fn names(names: Vec<Value>) -> Value {
+    json!({ "schema": SCHEMA, "names": names })
+}
+
+/// Line of `--file` with `--json` for the name as it was written in the file
+pub fn batch(
+    name: &str,
+    records: Value,
+) -> Value {
+    json!({ "schema": SCHEMA, "name": name, "records": records })
+}
+
+/// Output of `--json` for several servers, each of `servers` being `{"server", "records"}`
+/// or with the `error` of the server, with the `differences` of `--diff` if asked
+pub fn servers(
+    servers: Vec<Value>,
+    differences: Option<Value>,
+) -> Value {
+    let mut output = json!({ "schema": SCHEMA, "servers": servers });
+    if let Some(differences) = differences {
+        output["differences"] = differences;
+    }
+    output
+}
+
+/// Output of `--axfr` with `--json`
+pub fn transfer(records: Value) -> Value {
+    json!({ "schema": SCHEMA, "records": records })
+}
+
+/// Output of `resoy types --json` for every type
+pub fn types(table: &impl Serialize) -> Value {
+    json!({ "schema": SCHEMA, "types": table })
+}
+
+/// JSON Schema of `output`
+pub fn schema(output: Output) -> Value {
+    let body = match output {
+        Output::Lookup => object(
+            &[
+                ("schema", version()),
+                ("records", array(reference("record"))),
+                ("hints", array(string())),
+                ("groups", groups()),
+                ("dual_stack", array(pair())),
+                ("rdap", registration()),
+            ],
+            &["schema", "records", "hints"],
+        ),
+        Output::Names => object(
+            &[
+                ("schema", version()),
+                (
+                    "names",
+                    array(object(
+                        &[
+                            ("name", string()),
+                            ("records", array(reference("record"))),
+                            ("hints", array(string())),
+                            ("groups", groups()),
+                            ("dual_stack", array(pair())),
+                            ("rdap", registration()),
+                            ("error", string()),
+                        ],
+                        &["name"],
+                    )),
+                ),
+            ],
+            &["schema", "names"],
+        ),
+        Output::Batch => object(
+            &[
+                ("schema", version()),
+                ("name", string()),
+                ("records", array(reference("record"))),
+            ],
+            &["schema", "name", "records"],
+        ),
+        Output::Aggregate => object(
+            &[
+                ("schema", version()),
+                (
+                    "aggregate",
+                    object(
+                        &[
+                            ("by", enumeration(&["data", "asn", "registrable-domain"])),
+                            ("names", count()),
+                            (
+                                "values",
+                                array(object(
+                                    &[("type", string()), ("value", string()), ("names", count())],
+                                    &["type", "value", "names"],
+                                )),
+                            ),
+                        ],
+                        &["by", "names", "values"],
+                    ),
+                ),
+            ],
+            &["schema", "aggregate"],
+        ),
+        Output::Servers => object(
+            &[
+                ("schema", version()),
+                (
+                    "servers",
+                    array(object(
+                        &[
+                            ("server", string()),
+                            ("records", array(reference("record"))),
+                            ("error", string()),
+                        ],
+                        &["server"],
+                    )),
+                ),
+                (
+                    "differences",
+                    array(object(
+                        &[
+                            ("name", string()),
+                            ("type", string()),
+                            ("data", nullable(string())),
+                            ("servers", array(string())),
+                        ],
+                        &["name", "type", "data", "servers"],
+                    )),
+                ),
+            ],
+            &["schema", "servers"],
+        ),
+        Output::Transfer => object(
+            &[
+                ("schema", version()),
+                ("records", array(reference("record"))),
+            ],
+            &["schema", "records"],
+        ),
+        Output::ProxyLog => object(
+            &[
+                ("schema", version()),
+                ("client", string()),
+                ("transport", enumeration(&["udp", "tcp"])),
+                ("name", string()),
+                ("type", string()),
+                ("code", string()),
+                ("answers", count()),
+                ("elapsed_ms", count()),
+                ("cached", boolean()),
+                ("error", string()),
+            ],
+            &[
+                "schema",
+                "client",
+                "transport",
+                "name",
+                "type",
+                "code",
+                "answers",
+                "elapsed_ms",
+                "cached",
+            ],
+        ),
+        Output::ProxyStats => {
+            let entry = object(
+                &[
+                    ("key", string()),
+                    ("queries", count()),
+                    ("nxdomains", count()),
+                    ("mean_latency_ms", number()),
+                    ("last_seen", count()),
+                ],
+                &[
+                    "key",
+                    "queries",
+                    "nxdomains",
+                    "mean_latency_ms",
+                    "last_seen",
+                ],
+            );
+            let cache = object(
+                &[
+                    ("entries", count()),
+                    ("hits", count()),
+                    ("misses", count()),
+                    ("evictions", count()),
+                ],
+                &["entries", "hits", "misses", "evictions"],
+            );
+            let rule = object(
+                &[
+                    ("line", count()),
+                    ("suffix", string()),
+                    ("server", string()),
+                    ("connection", string()),
+                    ("queries", count()),
+                ],
+                &["line", "suffix", "server", "connection", "queries"],
+            );
+            object(
+                &[
+                    ("schema", version()),
+                    ("clients", array(entry.clone())),
+                    ("names", array(entry)),
+                    ("cache", cache),
+                    ("rules", array(rule)),
+                    ("forwarded", count()),
+                    ("in_flight", count()),
+                ],
+                &["schema", "clients", "names"],
+            )
+        },
+        Output::Doctor => object(
+            &[
+                ("schema", version()),
+                (
+                    "checks",
+                    array(object(
+                        &[
+                            ("name", string()),
+                            ("status", enumeration(&["pass", "warn", "fail"])),
+                            ("detail", string()),
+                            ("remedy", string()),
+                        ],
+                        &["name", "status", "detail"],
+                    )),
+                ),
+                ("failures", count()),
+            ],
+            &["schema", "checks", "failures"],
+        ),
+        Output::Readiness => object(
+            &[
+                ("schema", version()),
+                ("zone", string()),
+                ("source", string()),
+                ("records", count()),
+                ("rrsets", count()),
+                ("unsigned_bytes", count()),
+                ("signed_bytes", count()),
+                (
+                    "oversized",
+                    array(object(
+                        &[
+                            ("name", string()),
+                            ("record_type", string()),
+                            ("unsigned_bytes", count()),
+                            ("signed_bytes", count()),
+                        ],
+                        &["name", "record_type", "unsigned_bytes", "signed_bytes"],
+                    )),
+                ),
+                ("apex_cname", boolean()),
+                ("duplicates", array(string())),
+                ("leftovers", array(string())),
+                ("notes", array(string())),
+            ],
+            &[
+                "schema",
+                "zone",
+                "source",
+                "records",
+                "rrsets",
+                "unsigned_bytes",
+                "signed_bytes",
+                "oversized",
+                "apex_cname",
+                "duplicates",
+                "leftovers",
+                "notes",
+            ],
+        ),
+        // A line per TXT record, then a line of the classes of the name
+        Output::Audit => json!({
+            "oneOf": [
+                object(
+                    &[
+                        ("schema", version()),
+                        ("name", string()),
+                        ("text", string()),
+                        ("length", count()),
+                        ("classes", array(string())),
+                        ("blob_length", count()),
+                    ],
+                    &["schema", "name", "text", "length", "classes"],
+                ),
+                object(
+                    &[
+                        ("schema", version()),
+                        ("summary", string()),
+                        ("records", count()),
+                        ("classes", json!({ "type": "object", "additionalProperties": count() })),
+                    ],
+                    &["schema", "summary", "records", "classes"],
+                ),
+            ],
+        }),
+        Output::Types => {
+            let info = |versioned: bool| {
+                let mut fields = vec![
+                    ("type", string()),
+                    ("value", count()),
+                    ("summary", string()),
+                    ("details", string()),
+                    ("example", string()),
+                    ("pretty", boolean()),
+                ];
+                let mut required = vec!["type", "value", "summary", "details", "example", "pretty"];
+                if versioned {
+                    fields.insert(0, ("schema", version()));
+                    required.insert(0, "schema");
+                }
+                object(&fields, &required)
+            };
+            json!({
+                "oneOf": [
+                    object(
+                        &[("schema", version()), ("types", array(info(false)))],
+                        &["schema", "types"],
+                    ),
+                    info(true),
+                ],
+            })
+        },
+    };
+
+    let mut document = Map::new();
+    document.insert(
+        String::from("$schema"),
+        Value::from("https://json-schema.org/draft/2020-12/schema"),
+    );
+    document.insert(
+        String::from("$id"),
+        Value::from(format!("{}/{}", SCHEMA, output.name())),
+    );
+    let uses_records = matches!(
+        output,
+        Output::Lookup | Output::Names | Output::Batch | Output::Servers | Output::Transfer
+    );
+    if uses_records {
+        document.insert(String::from("$defs"), json!({ "record": record() }));
+    }
+    if let Value::Object(body) = body {
+        document.extend(body);
+    }
+    Value::Object(document)
+}
+
+/// Schema of the records of `format::json_records`, tagged with their section, with the fields
+/// that `--dnssec`, `--nsid`, `--follow` and `--validate` add
+fn record() -> Value {
+    object(
+        &[
+            ("name", string()),
+            ("type", string()),
+            ("class", string()),
+            ("ttl", count()),
+            // Fields of the types that have them, the strings of TXT records, the text of the
+            // data otherwise
+            (
+                "data",
+                json!({ "type": ["object", "array", "string", "null"] }),
+            ),
+            (
+                "section",
+                enumeration(&["answer", "authority", "additional"]),
+            ),
+            ("authenticated", boolean()),
+            ("nsid", string()),
+            ("chain", count()),
+            // Origin of the address of an A or AAAA record, with --asn
+            (
+                "asn",
+                object(
+                    &[
+                        ("asn", count()),
+                        ("prefix", string()),
+                        ("country", string()),
+                        ("network", string()),
+                    ],
+                    &["asn", "prefix", "country"],
+                ),
+            ),
+            ("validation", enumeration(&["secure", "insecure", "bogus"])),
+            ("validation_anchor", string()),
+            ("validation_reason", string()),
+        ],
+        &["name", "type", "class", "ttl", "data"],
+    )
+}
+
+/// Records nested per name then per type, or per type then per name, with --group-by
+fn groups() -> Value {
+    let members = |key: &str| {
+        array(object(
+            &[(key, string()), ("records", array(reference("record")))],
+            &[key, "records"],
+        ))
+    };
+    array(json!({ "oneOf": [
+        object(&[("name", string()), ("types", members("type"))], &["name", "types"]),
+        object(&[("type", string()), ("names", members("name"))], &["type", "names"]),
+    ] }))
+}
+
+/// Addresses of both families for one owner name, with --dual-stack
+fn pair() -> Value {
+    let addresses = nullable(array(string()));
+    object(
+        &[
+            // Owner of the addresses, the last name of the CNAME chain from the one queried
+            ("name", string()),
+            ("chain", array(string())),
+            ("a", addresses.clone()),
+            ("aaaa", addresses),
+            // Families without addresses for the owner, e.g. ["AAAA"]
+            ("missing", array(enumeration(&["A", "AAAA"]))),
+        ],
+        &["name", "chain", "a", "aaaa", "missing"],
+    )
+}
+
+/// Registration of the domain of a name, with --rdap
+fn registration() -> Value {
+    object(
+        &[
+            ("domain", string()),
+            ("registrar", nullable(string())),
+            ("created", nullable(string())),
+            ("expires", nullable(string())),
+            // Negative once the registration expired
+            ("expires_in_days", nullable(json!({ "type": "integer" }))),
+            ("status", array(string())),
+            ("nameservers", array(string())),
+            // Name servers answered over DNS, which the registry should list as well
+            ("live_nameservers", array(string())),
+        ],
+        &["domain", "status", "nameservers", "live_nameservers"],
+    )
+}
+
+fn object(
+    properties: &[(&str, Value)],
+    required: &[&str],
+) -> Value {
+    let properties: Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn reference(definition: &str) -> Value {
+    json!({ "$ref": format!("#/$defs/{}", definition) })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "oneOf": [schema, { "type": "null" }] })
+}
+
+fn enumeration(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn version() -> Value {
+    json!({ "const": SCHEMA })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn count() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+/// Check `value` against `schema`, knowing the keywords that the schemas above use, for the
+/// tests of the outputs
+#[cfg(test)]
+pub fn validate(
+    schema: &Value,
+    value: &Value,
+) -> Result<(), String> {
+    check(schema, schema, value, "")
+}
+
+#[cfg(test)]
+fn check(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+) -> Result<(), String> {
+    let fail = |message: String| Err(format!("{}: {}", path, message));
+    if let Some(Value::String(reference)) = schema.get("$ref") {
+        let name = reference.trim_start_matches("#/$defs/");
+        return check(root, &root["$defs"][name], value, path);
+    }
+    if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+        let matching = schemas
+            .iter()
+            .filter(|schema| check(root, schema, value, path).is_ok())
+            .count();
+        if matching != 1 {
+            return fail(format!("{} matches {} of oneOf", value, matching));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return fail(format!("{} is not {}", value, expected));
+        }
+    }
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        if !values.contains(value) {
+            return fail(format!("{} is none of {:?}", value, values));
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let is = |name: &str| match name {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => false,
+        };
+        if !types.iter().any(|name| is(name)) {
+            return fail(format!("{} is not of type {:?}", value, types));
+        }
+    }
+    if let (Some(minimum), Some(number)) = (schema.get("minimum"), value.as_f64()) {
+        if number < minimum.as_f64().unwrap_or_default() {
+            return fail(format!("{} is below {}", number, minimum));
+        }
+    }
+    if let Value::Object(fields) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !fields.contains_key(name) {
+                    return fail(format!("{} is missing", name));
+                }
+            }
+        }
+        for (name, field) in fields {
+            let field_path = format!("{}/{}", path, name);
+            match schema
+                .get("properties")
+                .and_then(|properties| properties.get(name))
+            {
+                Some(field_schema) => check(root, field_schema, field, &field_path)?,
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => return fail(format!("{} is unknown", name)),
+                    Some(field_schema @ Value::Object(_)) => {
+                        check(root, field_schema, field, &field_path)?
+                    },
+                    _ => {},
+                },
+            }
+        }
+    }
+    if let (Value::Array(items), Some(items_schema)) = (value, schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            check(root, items_schema, item, &format!("{}/{}", path, index))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr};
+
+    use hickory_client::rr::{
+        rdata::{A, MX, TXT},
+        Name, RData, Record,
+    };
+
+    use super::*;
+    use crate::{
+        aggregate::{Aggregate, AggregateBy},
+        format, types,
+    };
+
+    fn records() -> Vec<Record> {
+        let name = Name::from_str("example.com.").unwrap();
+        vec![
+            Record::from_rdata(name.clone(), 300, RData::A(A::new(192, 0, 2, 1))),
+            Record::from_rdata(
+                name.clone(),
+                300,
+                RData::MX(MX::new(10, Name::from_str("mail.example.com.").unwrap())),
+            ),
+            Record::from_rdata(
+                name,
+                300,
+                RData::TXT(TXT::new(vec![String::from("v=spf1 -all")])),
+            ),
+        ]
+    }
+
+    fn json_records() -> Value {
+        let records = records();
+        let records: Vec<&Record> = records.iter().collect();
+        format::json_records(&records)
+    }
+
+    fn valid(
+        output: Output,
+        value: &Value,
+    ) {
+        if let Err(error) = validate(&schema(output), value) {
+            panic!("{:?} output {} is invalid: {}", output, value, error);
+        }
+    }
+
+    #[test]
+    fn lookups_validate() {
+        let hints = vec![String::from("no AAAA record for example.com, but A exists")];
+        let mut output = lookup(json_records(), hints);
+        valid(Output::Lookup, &output);
+        output["records"][0]["authenticated"] = Value::Bool(true);
+        output["records"][0]["validation"] = Value::from("secure");
+        output["records"][0]["asn"] =
+            json!({ "asn": 64496, "prefix": "192.0.2.0/24", "country": "ZZ" });
+        output["rdap"] = json!({
+            "domain": "example.com",
+            "registrar": null,
+            "created": "1995-08-14T04:00:00Z",
+            "expires": "2030-08-13T04:00:00Z",
+            "expires_in_days": 1400,
+            "status": ["client transfer prohibited"],
+            "nameservers": ["a.iana-servers.net"],
+            "live_nameservers": [],
+        });
+        output["groups"] = json!([
+            { "name": "example.com.", "types": [{ "type": "A", "records": json_records() }] },
+            { "type": "MX", "names": [] },
+        ]);
+        output["dual_stack"] = json!([{
+            "name": "example.com.",
+            "chain": ["example.com."],
+            "a": ["192.0.2.1"],
+            "aaaa": null,
+            "missing": ["AAAA"],
+        }]);
+        valid(Output::Lookup, &output);
+
+        output["records"][0]["ttl"] = Value::from(-1);
+        assert!(validate(&schema(Output::Lookup), &output).is_err());
+    }
+
+    #[test]
+    fn outputs_of_several_names_and_servers_validate() {
+        let names = names(vec![
+            json!({ "name": "example.com", "records": json_records(), "hints": [] }),
+            json!({ "name": "example.net", "error": "NXDOMAIN for example.net" }),
+        ]);
+        valid(Output::Names, &names);
+
+        let servers = servers(
+            vec![json!({ "server": "192.0.2.53", "records": json_records() })],
+            Some(json!([{
+                "name": "example.com.",
+                "type": "A",
+                "data": "192.0.2.1",
+                "servers": ["192.0.2.53"],
+            }])),
+        );
+        valid(Output::Servers, &servers);
+        valid(Output::Batch, &batch("example.com", json_records()));
+        let aggregate = Aggregate::new(AggregateBy::Data);
+        let records = records();
+        aggregate.add(&records.iter().collect::<Vec<_>>(), &HashMap::new());
+        valid(Output::Aggregate, &aggregate.json());
+        valid(Output::Transfer, &transfer(json_records()));
+    }
+
+    #[test]
+    fn outputs_without_their_schema_are_invalid() {
+        let mut output = lookup(json_records(), Vec::new());
+        output["schema"] = Value::from("resoy/0");
+        assert!(validate(&schema(Output::Lookup), &output).is_err());
+        output.as_object_mut().unwrap().remove("schema");
+        assert!(validate(&schema(Output::Lookup), &output).is_err());
+    }
+
+    #[test]
+    fn type_tables_validate() {
+        let table = types::table();
+        valid(Output::Types, &types(&table));
+        valid(Output::Types, &versioned(&table[0]));
+    }
+
+    #[test]
+    fn every_output_has_a_schema_with_an_identifier() {
+        for output in Output::value_variants() {
+            let document = schema(*output);
+            assert_eq!(document["$id"], format!("resoy/1/{}", output.name()));
+            assert!(document.get("type").is_some() || document.get("oneOf").is_some());
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpStream, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use crate::{AppError, ConnectionType};
+
+/// Port of plain DNS servers given without one
+const DNS_PORT: u16 = 53;
+
+/// Port of DNS-over-TLS servers given without one, RFC 7858
+#[cfg(feature = "tls")]
+const DOT_PORT: u16 = 853;
+
+/// Port of the servers of `connection_type` given without one
+pub fn default_port(connection_type: ConnectionType) -> u16 {
+    match connection_type {
+        #[cfg(feature = "tls")]
+        ConnectionType::Tls => DOT_PORT,
+        _ => DNS_PORT,
+    }
+}
+
+/// Parse a server address, the port being the default one of `connection_type` when missing,
+/// 853 for tls and 53 otherwise.
+///
+/// IPv6 addresses may be bracketed as in `[2606:4700:4700::1111]:53`, and only need brackets
+/// with a port. They may carry a zone, the interface being given by its name or its index, as
+/// in `[fe80::1%eth0]:53`.
+pub fn parse_server_addr(
+    connection_type: ConnectionType,
+    raw_addr: &str,
+) -> Result<SocketAddr, AppError> {
+    let invalid = || AppError::InvalidDnsServer(raw_addr.to_owned());
+    let default_port = default_port(connection_type);
+    // An address first, as the colons of IPv6 would pass for a port otherwise
+    if let Ok(ip) = raw_addr.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, default_port));
+    }
+    if let Ok(addr) = raw_addr.parse() {
+        return Ok(addr);
+    }
+    let (host, port) = match raw_addr.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']').ok_or_else(invalid)? {
+            (host, "") => (host, default_port),
+            (host, port) => {
+                let port = port.strip_prefix(':').and_then(|port| port.parse().ok());
+                (host, port.ok_or_else(invalid)?)
+            },
+        },
+        None => (raw_addr, default_port),
+    };
+    if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        return Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, 0)));
+    }
+    let (ip, zone) = host.split_once('%').ok_or_else(invalid)?;
+    let ip: Ipv6Addr = ip.parse().map_err(|_| invalid())?;
+    let scope_id = match zone.parse() {
+        Ok(index) => index,
+        Err(_) => interface_index(zone)?,
+    };
+    Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)))
+}
+
+/// Host and port of a `host[:port]` server given by name, `None` for addresses, the port
+/// being the default one of `connection_type` when missing
+pub fn split_hostname(
+    connection_type: ConnectionType,
+    raw_addr: &str,
+) -> Result<Option<(&str, u16)>, AppError> {
+    let error = match parse_server_addr(connection_type, raw_addr) {
+        Ok(_) => return Ok(None),
+        Err(error) => error,
+    };
+    let invalid = || AppError::InvalidDnsServer(raw_addr.to_owned());
+    let (host, port) = match raw_addr.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+        None => (raw_addr, default_port(connection_type)),
+    };
+    // Colons or brackets left over are those of an IPv6 address that didn't parse
+    match host.is_empty() || host.contains([':', '[', ']', '%']) {
+        true => Err(error),
+        false => Ok(Some((host, port))),
+    }
+}
+
+/// Addresses of the server at `raw_addr`, a hostname being looked up with the system resolver
+pub fn candidates(
+    connection_type: ConnectionType,
+    raw_addr: &str,
+) -> Result<Vec<SocketAddr>, AppError> {
+    let invalid = || AppError::InvalidDnsServer(raw_addr.to_owned());
+    let Some((host, port)) = split_hostname(connection_type, raw_addr)? else {
+        return Ok(vec![parse_server_addr(connection_type, raw_addr)?]);
+    };
+    let candidates: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|_| invalid())?
+        .collect();
+    match candidates.is_empty() {
+        true => Err(invalid()),
+        false => Ok(candidates),
+    }
+}
+
+/// Whether `addr` can be reached at all, such as IPv6 ones from hosts without IPv6.
+///
+/// Connecting a UDP socket sends nothing, but fails without a route to the address.
+fn is_reachable(
+    addr: SocketAddr,
+    tcp: bool,
+    timeout: Duration,
+) -> bool {
+    if tcp {
+        return TcpStream::connect_timeout(&addr, timeout).is_ok();
+    }
+    let local = match addr {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::from([0; 4]), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::from([0; 16]), 0),
+    };
+    UdpSocket::bind(local)
+        .and_then(|socket| socket.connect(addr))
+        .is_ok()
+}
+
+/// Address to send the queries for the server at `raw_addr` to.
+///
+/// Of several addresses of a hostname, the first that can be reached is taken. Otherwise the
+/// error names the addresses tried.
+pub fn resolve_server(
+    connection_type: ConnectionType,
+    raw_addr: &str,
+    timeout: Duration,
+) -> Result<SocketAddr, AppError> {
+    let candidates = candidates(connection_type, raw_addr)?;
+    if let [addr] = candidates[..] {
+        return Ok(addr);
+    }
+    let tcp = !matches!(connection_type, ConnectionType::Udp);
+    candidates
+        .iter()
+        .find(|addr| is_reachable(**addr, tcp, timeout))
+        .copied()
+        .ok_or_else(|| {
+            let tried: Vec<String> = candidates.iter().map(ToString::to_string).collect();
+            AppError::DNSServerUnreachable(
+                connection_type,
+                format!("{}: tried {}", raw_addr, tried.join(", ")),
+            )
+        })
+}
+
+#[cfg(unix)]
+fn interface_index(name: &str) -> Result<u32, AppError> {
+    let unknown = || AppError::UnknownInterface(name.to_owned(), interfaces());
+    let name = std::ffi::CString::new(name).map_err(|_| unknown())?;
+    // SAFETY: the name is a valid C string that outlives the call
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(unknown()),
+        index => Ok(index),
+    }
+}
+
+/// Names of interfaces are only resolved on unix, elsewhere the zone is the numeric index
+#[cfg(not(unix))]
+fn interface_index(name: &str) -> Result<u32, AppError> {
+    Err(AppError::UnknownInterface(name.to_owned(), Vec::new()))
+}
+
+/// Names of the network interfaces of the host, with their indexes
+#[cfg(unix)]
+fn interfaces() -> Vec<String> {
+    let mut interfaces = Vec::new();
+    // SAFETY: the list ends with an entry of index 0 and is freed once read
+    unsafe {
+        let list = libc::if_nameindex();
+        if list.is_null() {
+            return interfaces;
+        }
+        let mut entry = list;
+        while (*entry).if_index != 0 {
+            let name = std::ffi::CStr::from_ptr((*entry).if_name).to_string_lossy();
+            interfaces.push(format!("{} ({})", name, (*entry).if_index));
+            entry = entry.add(1);
+        }
+        libc::if_freenameindex(list);
+    }
+    interfaces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(
+        connection_type: ConnectionType,
+        raw_addr: &str,
+    ) -> Option<String> {
+        parse_server_addr(connection_type, raw_addr)
+            .ok()
+            .map(|addr| addr.to_string())
+    }
+
+    #[test]
+    fn addresses_take_the_port_of_plain_dns_by_default() {
+        let table = [
+            ("1.1.1.1", Some("1.1.1.1:53")),
+            ("1.1.1.1:5353", Some("1.1.1.1:5353")),
+            ("2606:4700:4700::1111", Some("[2606:4700:4700::1111]:53")),
+            ("[2606:4700:4700::1111]", Some("[2606:4700:4700::1111]:53")),
+            (
+                "[2606:4700:4700::1111]:5353",
+                Some("[2606:4700:4700::1111]:5353"),
+            ),
+            ("[fe80::1%2]:53", Some("[fe80::1%2]:53")),
+            ("fe80::1%2", Some("[fe80::1%2]:53")),
+            ("[fe80::1%2]", Some("[fe80::1%2]:53")),
+            ("[::1", None),
+            ("[::1]5353", None),
+            ("[::1]:port", None),
+            ("1.1.1.1:65536", None),
+            ("dns.google", None),
+            ("", None),
+        ];
+        for (raw_addr, expected) in table {
+            assert_eq!(
+                parsed(ConnectionType::Udp, raw_addr).as_deref(),
+                expected,
+                "{}",
+                raw_addr
+            );
+            assert_eq!(
+                parsed(ConnectionType::Tcp, raw_addr).as_deref(),
+                expected,
+                "{}",
+                raw_addr
+            );
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_addresses_take_port_853_by_default_and_keep_explicit_ports() {
+        let table = [
+            ("1.1.1.1", "1.1.1.1:853"),
+            ("1.1.1.1:53", "1.1.1.1:53"),
+            ("1.1.1.1:8853", "1.1.1.1:8853"),
+            ("2606:4700:4700::1111", "[2606:4700:4700::1111]:853"),
+            ("[2606:4700:4700::1111]", "[2606:4700:4700::1111]:853"),
+            ("[2606:4700:4700::1111]:53", "[2606:4700:4700::1111]:53"),
+            ("[fe80::1%2]", "[fe80::1%2]:853"),
+        ];
+        for (raw_addr, expected) in table {
+            assert_eq!(
+                parsed(ConnectionType::Tls, raw_addr).as_deref(),
+                Some(expected),
+                "{}",
+                raw_addr
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_interfaces_are_named() {
+        let error = parse_server_addr(ConnectionType::Udp, "[fe80::1%resoy-none0]:53");
+        assert!(
+            matches!(error, Err(AppError::UnknownInterface(ref name, _)) if name == "resoy-none0")
+        );
+    }
+
+    #[test]
+    fn hostnames_are_split_from_their_port() {
+        let udp = ConnectionType::Udp;
+        assert_eq!(
+            split_hostname(udp, "dns.google").ok(),
+            Some(Some(("dns.google", 53)))
+        );
+        assert_eq!(
+            split_hostname(udp, "dns.google:5353").ok(),
+            Some(Some(("dns.google", 5353)))
+        );
+        assert_eq!(split_hostname(udp, "1.1.1.1").ok(), Some(None));
+        assert_eq!(split_hostname(udp, "[::1]:53").ok(), Some(None));
+        assert!(split_hostname(udp, "dns.google:port").is_err());
+        assert!(split_hostname(udp, "fe80::zz").is_err());
+        assert!(split_hostname(udp, ":53").is_err());
+        #[cfg(feature = "tls")]
+        assert_eq!(
+            split_hostname(ConnectionType::Tls, "dns.google").ok(),
+            Some(Some(("dns.google", 853)))
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket};
+
+use crate::AppError;
+
+/// The address to bind the UDP sockets to that ask `server` from `ip` and `port`, as given with
+/// `--source` and `--source-port`, `None` to let the system choose
+pub fn udp_bind_addr(
+    server: SocketAddr,
+    ip: Option<IpAddr>,
+    port: Option<u16>,
+) -> Result<Option<SocketAddr>, AppError> {
+    let Some(addr) = bind_addr(server, ip, port)? else {
+        return Ok(None);
+    };
+    UdpSocket::bind(addr).map_err(|error| AppError::BindError(addr, error.to_string()))?;
+    Ok(Some(addr))
+}
+
+/// The address to bind the TCP connections to `server` to, like `udp_bind_addr`
+pub fn tcp_bind_addr(
+    server: SocketAddr,
+    ip: Option<IpAddr>,
+    port: Option<u16>,
+) -> Result<Option<SocketAddr>, AppError> {
+    let Some(addr) = bind_addr(server, ip, port)? else {
+        return Ok(None);
+    };
+    TcpListener::bind(addr).map_err(|error| AppError::BindError(addr, error.to_string()))?;
+    Ok(Some(addr))
+}
+
+/// The source of the queries to `server`, of the family of `server` when only the port is
+/// given, checked to be of that family otherwise, as the system would only refuse to send
+/// with an unhelpful error once the query is under way
+fn bind_addr(
+    server: SocketAddr,
+    ip: Option<IpAddr>,
+    port: Option<u16>,
+) -> Result<Option<SocketAddr>, AppError> {
+    let (ip, port) = match (ip, port) {
+        (None, None) => return Ok(None),
+        (ip, port) => (ip, port.unwrap_or(0)),
+    };
+    let ip = ip.unwrap_or(match server {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let mut addr = SocketAddr::new(ip, port);
+    if addr.is_ipv4() != server.is_ipv4() {
+        let family = |addr: SocketAddr| match addr.is_ipv4() {
+            true => "IPv4",
+            false => "IPv6",
+        };
+        return Err(AppError::BindError(
+            addr,
+            format!(
+                "it is an {} address and the server {} an {} one",
+                family(addr),
+                server,
+                family(server)
+            ),
+        ));
+    }
+    // Link-local sources are on the interface of the server
+    if let (SocketAddr::V6(addr), SocketAddr::V6(server)) = (&mut addr, server) {
+        if addr.ip().is_unicast_link_local() {
+            addr.set_scope_id(server.scope_id());
+        }
+    }
+    Ok(Some(addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53);
+
+    #[test]
+    fn queries_are_sent_from_where_the_system_chooses_by_default() {
+        assert!(matches!(udp_bind_addr(SERVER, None, None), Ok(None)));
+        assert!(matches!(tcp_bind_addr(SERVER, None, None), Ok(None)));
+    }
+
+    #[test]
+    fn a_port_alone_takes_the_family_of_the_server() {
+        let v6 = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 53);
+        assert_eq!(
+            bind_addr(SERVER, None, Some(5300)).unwrap(),
+            Some("0.0.0.0:5300".parse().unwrap())
+        );
+        assert_eq!(
+            bind_addr(v6, None, Some(5300)).unwrap(),
+            Some("[::]:5300".parse().unwrap())
+        );
+        assert_eq!(
+            udp_bind_addr(SERVER, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), None).unwrap(),
+            Some("127.0.0.1:0".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn sources_of_another_family_than_the_server_are_refused_up_front() {
+        let v6 = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 53);
+        match udp_bind_addr(v6, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), None) {
+            Err(error @ AppError::BindError(..)) => assert_eq!(
+                error.to_string(),
+                "Cannot send queries from 127.0.0.1:0: it is an IPv4 address and the server \
+                 [::1]:53 an IPv6 one"
+            ),
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            tcp_bind_addr(SERVER, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)), Some(5300)),
+            Err(AppError::BindError(addr, _)) if addr == "[::1]:5300".parse().unwrap()
+        ));
+    }
+
+    #[test]
+    fn sources_that_cannot_be_bound_name_their_address() {
+        let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+        assert!(matches!(
+            udp_bind_addr(SERVER, Some(addr.ip()), Some(addr.port())),
+            Err(AppError::BindError(at, _)) if at == addr
+        ));
+        // An address of no interface of this host
+        let foreign: IpAddr = "192.0.2.1".parse().unwrap();
+        match tcp_bind_addr(SERVER, Some(foreign), None) {
+            Err(error @ AppError::BindError(..)) => assert!(
+                error
+                    .to_string()
+                    .starts_with("Cannot send queries from 192.0.2.1:0: "),
+                "{}",
+                error
+            ),
+            other => panic!("{:?}", other),
+        }
+    }
+}
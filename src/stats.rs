@@ -0,0 +1,204 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+/// Tables are split into up to this many independently locked shards, so that the handlers
+/// of concurrent queries rarely wait for each other
+const SHARDS: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Counters {
+    queries: u64,
+    nxdomains: u64,
+    latency: Duration,
+    last_seen: SystemTime,
+    /// when the key was last seen, as a position in the `order` of its shard
+    seen: u64,
+}
+
+/// Counters of one key, as dumped
+#[derive(Serialize)]
+struct Entry {
+    key: String,
+    queries: u64,
+    nxdomains: u64,
+    mean_latency_ms: f64,
+    /// seconds since the Unix epoch
+    last_seen: u64,
+}
+
+/// Counters of one shard, and their keys from the least recently seen on
+#[derive(Default)]
+struct Shard {
+    counters: HashMap<String, Counters>,
+    order: BTreeMap<u64, String>,
+}
+
+/// Counters per key, keeping only the most recently seen keys once there are too many
+struct Table {
+    shards: Vec<Mutex<Shard>>,
+    per_shard: usize,
+    /// source of the positions of the keys in the order they were seen
+    seen: AtomicU64,
+}
+
+impl Table {
+    fn new(capacity: usize) -> Self {
+        // Small tables get fewer shards, so that they don't keep many more keys than asked
+        let shards = capacity.clamp(1, SHARDS);
+        Self {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            per_shard: capacity.div_ceil(shards).max(1),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(
+        &self,
+        key: &str,
+    ) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    fn count(
+        &self,
+        key: &str,
+        nxdomain: bool,
+        latency: Duration,
+    ) {
+        let now = SystemTime::now();
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        let mut shard = self.shard(key).lock().unwrap();
+        let Shard {
+            counters,
+            order,
+        } = &mut *shard;
+        if !counters.contains_key(key) && counters.len() >= self.per_shard {
+            if let Some((_, oldest)) = order.pop_first() {
+                counters.remove(&oldest);
+            }
+        }
+        let counters = counters.entry(key.to_owned()).or_insert(Counters {
+            queries: 0,
+            nxdomains: 0,
+            latency: Duration::ZERO,
+            last_seen: now,
+            seen,
+        });
+        order.remove(&counters.seen);
+        order.insert(seen, key.to_owned());
+        counters.queries += 1;
+        counters.nxdomains += u64::from(nxdomain);
+        counters.latency += latency;
+        counters.last_seen = now;
+        counters.seen = seen;
+    }
+
+    /// Entries of every shard, the busiest first
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = self
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                let shard = shard.lock().unwrap();
+                shard
+                    .counters
+                    .iter()
+                    .map(|(key, counters)| Entry {
+                        key: key.clone(),
+                        queries: counters.queries,
+                        nxdomains: counters.nxdomains,
+                        mean_latency_ms: counters.latency.as_secs_f64() * 1000.0
+                            / counters.queries as f64,
+                        last_seen: counters
+                            .last_seen
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |since| since.as_secs()),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        entries.sort_by(|a, b| b.queries.cmp(&a.queries).then_with(|| a.key.cmp(&b.key)));
+        entries
+    }
+
+    fn clear(&self) {
+        for shard in &self.shards {
+            *shard.lock().unwrap() = Shard::default();
+        }
+    }
+}
+
+/// Counters of the proxy per client address and per queried name
+pub struct Stats {
+    clients: Table,
+    names: Table,
+}
+
+impl Stats {
+    /// Keep the counters of at most `capacity` clients and as many names
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            clients: Table::new(capacity),
+            names: Table::new(capacity),
+        }
+    }
+
+    pub fn count(
+        &self,
+        client: &str,
+        name: &str,
+        nxdomain: bool,
+        latency: Duration,
+    ) {
+        self.clients.count(client, nxdomain, latency);
+        self.names.count(name, nxdomain, latency);
+    }
+
+    /// Counters as a JSON document, clients and names with the most queries first
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "clients": self.clients.entries(),
+            "names": self.names.entries(),
+        })
+    }
+
+    pub fn reset(&self) {
+        self.clients.clear();
+        self.names.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_keys_seen_least_recently_are_dropped_first() {
+        let table = Table {
+            shards: vec![Mutex::default()],
+            per_shard: 2,
+            seen: AtomicU64::new(0),
+        };
+        for key in ["a", "b", "a", "c"] {
+            table.count(key, false, Duration::from_millis(1));
+        }
+        let keys: Vec<(String, u64)> = table
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.key, entry.queries))
+            .collect();
+        assert_eq!(keys, [("a".into(), 2), ("c".into(), 1)]);
+        assert_eq!(table.shards[0].lock().unwrap().order.len(), 2);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use serde_json::Value;
+
+/// Longest request head read, the endpoint only takes bare GET requests
+const MAX_REQUEST_LENGTH: usize = 8192;
+
+/// Answer the HTTP request on `stream` with `stats`, the counters of the proxy: as JSON at
+/// `/stats`, and as text at `/stats?format=text`, starting them over with `reset` on a POST
+/// to `/stats/reset`
+pub fn respond<S: Read + Write>(
+    stream: &mut S,
+    stats: impl FnOnce() -> Value,
+    reset: impl FnOnce(),
+) -> io::Result<()> {
+    let head = read_head(stream)?;
+    let request_line = head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or("/"),
+    );
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (status, content_type, body) = match (method, path) {
+        ("GET" | "HEAD", "/" | "/stats") => {
+            let stats = stats();
+            match query.split('&').any(|pair| pair == "format=text") {
+                true => ("200 OK", "text/plain; charset=utf-8", text(&stats)),
+                false => (
+                    "200 OK",
+                    "application/json",
+                    serde_json::to_string_pretty(&stats).unwrap_or_default() + "\n",
+                ),
+            }
+        },
+        ("POST", "/stats/reset") => {
+            reset();
+            ("200 OK", "text/plain", String::from("statistics reset\n"))
+        },
+        ("GET" | "HEAD", "/stats/reset") => (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("only POST is allowed\n"),
+        ),
+        ("GET" | "HEAD", _) => ("404 Not Found", "text/plain", String::from("not found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("only GET is allowed\n"),
+        ),
+    };
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// The request line and headers, up to the blank line that ends them
+fn read_head<S: Read>(stream: &mut S) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+        if head.len() > MAX_REQUEST_LENGTH {
+            return Err(io::Error::new(ErrorKind::InvalidData, "request too long"));
+        }
+        match stream.read(&mut buffer)? {
+            0 => break,
+            read => head.extend_from_slice(&buffer[..read]),
+        }
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// The counters of the proxy as lines of text, the busiest clients and names first
+pub fn text(stats: &Value) -> String {
+    let mut out = String::new();
+    for (table, title) in [("clients", "Clients"), ("names", "Names")] {
+        let entries = stats[table]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let _ = writeln!(out, "{}: {}", title, entries.len());
+        for entry in entries {
+            let _ = writeln!(
+                out,
+                "  {} {} queries, {} NXDOMAIN, {:.1}ms mean, last seen {}",
+                entry["key"].as_str().unwrap_or_default(),
+                entry["queries"],
+                entry["nxdomains"],
+                entry["mean_latency_ms"].as_f64().unwrap_or_default(),
+                entry["last_seen"],
+            );
+        }
+    }
+    let cache = &stats["cache"];
+    if cache.is_object() {
+        let _ = writeln!(
+            out,
+            "Cache: {} entries, {} hits, {} misses, {} evictions",
+            cache["entries"], cache["hits"], cache["misses"], cache["evictions"],
+        );
+    }
+    if let Some(rules) = stats["rules"].as_array() {
+        let _ = writeln!(out, "Rules: {}", rules.len());
+        for rule in rules {
+            let _ = writeln!(
+                out,
+                "  line {} {} to {} over {}, {} queries",
+                rule["line"],
+                rule["suffix"].as_str().unwrap_or_default(),
+                rule["server"].as_str().unwrap_or_default(),
+                rule["connection"].as_str().unwrap_or_default(),
+                rule["queries"],
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Request and response of one exchange with the endpoint
+    struct Exchange {
+        request: io::Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for Exchange {
+        fn read(
+            &mut self,
+            buffer: &mut [u8],
+        ) -> io::Result<usize> {
+            self.request.read(buffer)
+        }
+    }
+
+    impl Write for Exchange {
+        fn write(
+            &mut self,
+            buffer: &[u8],
+        ) -> io::Result<usize> {
+            self.response.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stats() -> Value {
+        json!({
+            "schema": "resoy/1",
+            "clients": [{
+                "key": "127.0.0.1",
+                "queries": 3,
+                "nxdomains": 1,
+                "mean_latency_ms": 2.26,
+                "last_seen": 1700000000,
+            }],
+            "names": [],
+            "cache": {"entries": 2, "hits": 5, "misses": 3, "evictions": 0},
+        })
+    }
+
+    /// Response to `request`, and whether it reset the counters
+    fn exchange(request: &str) -> (String, bool) {
+        let mut exchange = Exchange {
+            request: io::Cursor::new(request.as_bytes().to_vec()),
+            response: Vec::new(),
+        };
+        let mut reset = false;
+        respond(&mut exchange, stats, || reset = true).unwrap();
+        (String::from_utf8(exchange.response).unwrap(), reset)
+    }
+
+    fn get(request: &str) -> String {
+        exchange(request).0
+    }
+
+    #[test]
+    fn the_counters_are_served_as_json_by_default() {
+        let response = get("GET /stats HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: application/json"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert_eq!(serde_json::from_str::<Value>(body).unwrap(), stats());
+    }
+
+    #[test]
+    fn the_counters_are_served_as_text_when_asked() {
+        let response = get("GET /stats?format=text HTTP/1.1\r\n\r\n");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Type: text/plain"));
+        assert_eq!(
+            body,
+            "Clients: 1\n  127.0.0.1 3 queries, 1 NXDOMAIN, 2.3ms mean, last seen 1700000000\n\
+             Names: 0\nCache: 2 entries, 5 hits, 3 misses, 0 evictions\n"
+        );
+    }
+
+    #[test]
+    fn the_counters_are_reset_by_a_post_only() {
+        let (response, reset) = exchange("POST /stats/reset HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 ") && reset);
+        let (response, reset) = exchange("GET /stats/reset HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 ") && !reset);
+        assert!(!exchange("GET /stats HTTP/1.1\r\n\r\n").1);
+    }
+
+    #[test]
+    fn other_paths_and_methods_are_refused() {
+        assert!(get("GET /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 "));
+        assert!(get("POST /stats HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 "));
+        let head = get("HEAD /stats HTTP/1.1\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 200 ") && head.ends_with("\r\n\r\n"));
+    }
+}
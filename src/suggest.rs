@@ -0,0 +1,165 @@
+use std::{str::FromStr, thread, time::Duration};
+
+use hickory_client::{
+    op::ResponseCode,
+    rr::{DNSClass, Name, RecordType},
+};
+
+use crate::DnsClient;
+
+/// Timeout of every query verifying a suggestion
+pub const SUGGEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Upper bound of candidates verified against the server
+const MAX_CANDIDATES: usize = 12;
+
+const COMMON_TLDS: &[&str] = &[
+    "com", "net", "org", "io", "co", "dev", "app", "de", "uk", "fr", "ru", "nl", "info",
+];
+
+const POPULAR_DOMAINS: &[&str] = &[
+    "google.com",
+    "youtube.com",
+    "facebook.com",
+    "instagram.com",
+    "twitter.com",
+    "x.com",
+    "wikipedia.org",
+    "amazon.com",
+    "apple.com",
+    "microsoft.com",
+    "linkedin.com",
+    "netflix.com",
+    "yahoo.com",
+    "reddit.com",
+    "github.com",
+    "gitlab.com",
+    "stackoverflow.com",
+    "cloudflare.com",
+    "office.com",
+    "live.com",
+    "outlook.com",
+    "gmail.com",
+    "bing.com",
+    "whatsapp.com",
+    "tiktok.com",
+    "zoom.us",
+    "paypal.com",
+    "ebay.com",
+    "dropbox.com",
+    "adobe.com",
+    "mozilla.org",
+    "rust-lang.org",
+    "crates.io",
+    "docker.com",
+    "yandex.ru",
+    "baidu.com",
+    "example.com",
+];
+
+/// Edit distance with adjacent transpositions (optimal string alignment)
+fn edit_distance(
+    a: &str,
+    b: &str,
+) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Plausible corrections of a mistyped name, most likely first
+fn candidates(name: &Name) -> Vec<String> {
+    let ascii = name.to_ascii().trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = ascii.split('.').collect();
+    let mut candidates: Vec<String> = Vec::new();
+
+    if let Some((tld, rest)) = labels.split_last() {
+        if !rest.is_empty() {
+            let rest = rest.join(".");
+
+            // Close popular domains, compared without a leading "www."
+            let bare = ascii.strip_prefix("www.").unwrap_or(&ascii);
+            for popular in POPULAR_DOMAINS {
+                if edit_distance(bare, popular) == 1 {
+                    candidates.push(popular.to_string());
+                }
+            }
+
+            // Mistyped top level domains, then the most common ones
+            for common in COMMON_TLDS {
+                if common != tld && edit_distance(tld, common) == 1 {
+                    candidates.push(format!("{}.{}", rest, common));
+                }
+            }
+            for common in COMMON_TLDS.iter().take(3) {
+                if common != tld {
+                    candidates.push(format!("{}.{}", rest, common));
+                }
+            }
+        }
+    }
+
+    match ascii.strip_prefix("www.") {
+        Some(bare) => candidates.push(bare.to_string()),
+        None => candidates.push(format!("www.{}", ascii)),
+    }
+
+    let mut unique: Vec<String> = Vec::new();
+    for candidate in candidates {
+        if candidate != ascii && !unique.contains(&candidate) {
+            unique.push(candidate);
+        }
+    }
+    unique.truncate(MAX_CANDIDATES);
+    unique
+}
+
+/// Existing names the mistyped name was likely meant to be.
+///
+/// Every candidate is verified with a query, so only names that exist are returned.
+pub fn find_suggestions(
+    client: &DnsClient,
+    name: &Name,
+    record_type: RecordType,
+) -> Vec<Name> {
+    let candidates: Vec<Name> = candidates(name)
+        .iter()
+        .filter_map(|candidate| Name::from_str(candidate).ok())
+        .collect();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .into_iter()
+            .map(|candidate| {
+                scope.spawn(move || {
+                    let exists = client
+                        .query(&candidate, DNSClass::IN, record_type)
+                        .is_ok_and(|response| response.response_code() == ResponseCode::NoError);
+                    exists.then_some(candidate)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok().flatten())
+            .collect()
+    })
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use hickory_client::rr::{DNSClass, Name, RData, RecordType};
+
+use crate::DnsClient;
+
+/// Timeout of every query of `--survey`
+pub const SURVEY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// CHAOS names that anycast instances answer with their identity, in order of preference
+const IDENTITY_NAMES: [&str; 2] = ["id.server.", "hostname.bind."];
+
+/// Answers of one anycast instance
+struct Instance {
+    identity: String,
+    latencies: Vec<Duration>,
+}
+
+/// Identities of the instances behind an anycast address, as seen from here
+pub struct Survey {
+    server: String,
+    queries: usize,
+    failures: usize,
+    /// whether the survey ended because no new identity showed up
+    stable: bool,
+    instances: Vec<Instance>,
+}
+
+/// Identity an instance answers with, `None` when it answers neither name
+fn identity(client: &DnsClient) -> Result<Option<String>, String> {
+    let mut error = None;
+    for name in IDENTITY_NAMES {
+        let name = Name::from_str(name).map_err(|e| e.to_string())?;
+        // Every query has to go out, to reach other instances
+        match client.query_uncached(&name, DNSClass::CH, RecordType::TXT) {
+            Ok(response) => {
+                let text = response
+                    .answers()
+                    .iter()
+                    .find_map(|record| match record.data() {
+                        Some(RData::TXT(txt)) => Some(
+                            txt.txt_data()
+                                .iter()
+                                .map(|data| String::from_utf8_lossy(data))
+                                .collect::<String>(),
+                        ),
+                        _ => None,
+                    });
+                if text.is_some() {
+                    return Ok(text);
+                }
+            },
+            Err(e) => error = Some(e.to_string()),
+        }
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(None),
+    }
+}
+
+/// Ask `queries` times for the identity of the server.
+///
+/// Every UDP query goes out from a new source port, so ECMP spreads them over the instances.
+/// With `until_stable`, the survey ends early once that many queries in a row found no new
+/// identity.
+pub fn survey(
+    client: &DnsClient,
+    server: &str,
+    queries: usize,
+    until_stable: Option<usize>,
+    pause: impl Fn(usize),
+) -> Survey {
+    let mut survey = Survey {
+        server: server.to_owned(),
+        queries: 0,
+        failures: 0,
+        stable: false,
+        instances: Vec::new(),
+    };
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    let mut since_new = 0;
+    for index in 0..queries {
+        pause(index);
+        let started = Instant::now();
+        let result = identity(client);
+        let latency = started.elapsed();
+        survey.queries += 1;
+        since_new += 1;
+
+        match result {
+            Ok(identity) => {
+                let identity = identity.unwrap_or_else(|| String::from("(no identity)"));
+                match indexes.get(&identity) {
+                    Some(index) => survey.instances[*index].latencies.push(latency),
+                    None => {
+                        indexes.insert(identity.clone(), survey.instances.len());
+                        survey.instances.push(Instance {
+                            identity,
+                            latencies: vec![latency],
+                        });
+                        since_new = 0;
+                    },
+                }
+            },
+            Err(_) => survey.failures += 1,
+        }
+
+        if until_stable.is_some_and(|until_stable| since_new >= until_stable) {
+            survey.stable = true;
+            break;
+        }
+    }
+    // Most answering first
+    survey
+        .instances
+        .sort_by_key(|instance| usize::MAX - instance.latencies.len());
+    survey
+}
+
+impl Display for Survey {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "Survey of {}: {} queries, {} instances",
+            self.server,
+            self.queries,
+            self.instances.len()
+        )?;
+        if self.failures > 0 {
+            write!(f, ", {} failed", self.failures)?;
+        }
+        if self.stable {
+            write!(f, ", stable")?;
+        }
+
+        let width = self
+            .instances
+            .iter()
+            .map(|instance| instance.identity.len())
+            .chain([8])
+            .max()
+            .unwrap_or_default();
+        write!(
+            f,
+            "\n  {:<width$} {:>7} {:>8} {:>8} {:>8}",
+            "INSTANCE",
+            "ANSWERS",
+            "MIN MS",
+            "AVG MS",
+            "MAX MS",
+            width = width
+        )?;
+        for instance in &self.instances {
+            let millis: Vec<f64> = instance
+                .latencies
+                .iter()
+                .map(|latency| latency.as_secs_f64() * 1000.0)
+                .collect();
+            let min = millis.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = millis.iter().copied().fold(0.0, f64::max);
+            let avg = millis.iter().sum::<f64>() / millis.len() as f64;
+            write!(
+                f,
+                "\n  {:<width$} {:>7} {:>8.1} {:>8.1} {:>8.1}",
+                instance.identity,
+                instance.latencies.len(),
+                min,
+                avg,
+                max,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
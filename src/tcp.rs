@@ -0,0 +1,304 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::Mutex,
+    time::Duration,
+};
+
+use hickory_client::{
+    error::{ClientError, ClientErrorKind, ClientResult},
+    op::{DnsResponse, Message},
+    serialize::binary::BinEncodable,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{
+    salvage::{self, Lenient},
+    tsig::Signer,
+};
+
+/// Client of plain DNS over TCP, sending every query over one connection and taking the
+/// first response to each as its answer, rather than waiting for more to come until the
+/// timeout like the client of hickory does
+pub struct TcpClient {
+    addr: SocketAddr,
+    /// local address of `--source` and `--source-port`
+    bind_addr: Option<SocketAddr>,
+    timeout: Duration,
+    signer: Option<Signer>,
+    stream: Mutex<Option<TcpStream>>,
+}
+
+impl TcpClient {
+    /// Client of `addr`, which connects once the first query is sent
+    pub fn new(
+        addr: SocketAddr,
+        bind_addr: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            addr,
+            bind_addr,
+            timeout,
+            signer: None,
+            stream: Mutex::new(None),
+        }
+    }
+
+    /// Sign the queries with `signer`, and check their responses
+    pub fn with_signer(
+        mut self,
+        signer: Option<Signer>,
+    ) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Local address the connection leaves from, `None` for any
+    pub fn bind_addr(&self) -> Option<SocketAddr> {
+        self.bind_addr
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn connect(
+        &self,
+        timeout: Duration,
+    ) -> io::Result<TcpStream> {
+        let socket = Socket::new(
+            Domain::for_address(self.addr),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        if let Some(bind_addr) = self.bind_addr {
+            socket.bind(&bind_addr.into())?;
+        }
+        socket.connect_timeout(&self.addr.into(), timeout)?;
+        let stream = TcpStream::from(socket);
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    /// Send `message`, reconnecting once when the server closed the connection meanwhile
+    pub fn send(
+        &self,
+        message: Message,
+    ) -> ClientResult<DnsResponse> {
+        self.exchange(message, false, self.timeout)
+            .map(|(response, _)| response)
+    }
+
+    /// Like `send`, keeping the records before the section of the response that is corrupt
+    pub fn send_lenient(
+        &self,
+        message: Message,
+    ) -> ClientResult<Lenient> {
+        self.exchange(message, true, self.timeout)
+    }
+
+    /// Like `send_lenient`, or `send` unless `lenient`, waiting for the connection and the
+    /// response no longer than `timeout`, e.g. until a deadline shorter than the timeout of
+    /// the client
+    pub fn send_within(
+        &self,
+        message: Message,
+        lenient: bool,
+        timeout: Duration,
+    ) -> ClientResult<Lenient> {
+        self.exchange(message, lenient, timeout.min(self.timeout))
+    }
+
+    fn exchange(
+        &self,
+        message: Message,
+        lenient: bool,
+        timeout: Duration,
+    ) -> ClientResult<Lenient> {
+        let mut stream = self.stream.lock().unwrap();
+        let mut reconnected = false;
+        loop {
+            let current = match stream.as_mut() {
+                Some(current) => current,
+                None => {
+                    reconnected = true;
+                    stream.insert(self.connect(timeout).map_err(failure)?)
+                },
+            };
+            let timeouts = current
+                .set_read_timeout(Some(timeout))
+                .and_then(|()| current.set_write_timeout(Some(timeout)));
+            if let Err(error) = timeouts {
+                *stream = None;
+                return Err(failure(error));
+            }
+            match send(current, message.clone(), self.signer.as_ref(), lenient) {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    *stream = None;
+                    let closed = matches!(
+                        error.kind(),
+                        ClientErrorKind::Io(error) if error.kind() == ErrorKind::UnexpectedEof
+                    );
+                    if reconnected || !closed {
+                        return Err(error);
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Send `message` over `stream`, which may be encrypted, with an ID of its own and signed
+/// with `signer`, and read its response, leniently with `lenient` as `salvage::read_response`
+/// does
+pub fn send<S: Read + Write>(
+    stream: &mut S,
+    mut message: Message,
+    signer: Option<&Signer>,
+    lenient: bool,
+) -> ClientResult<Lenient> {
+    message.set_id(RandomState::new().build_hasher().finish() as u16);
+    let verifier = signer.map(|signer| signer.sign(&mut message)).transpose()?;
+    let query = message.to_bytes()?;
+    let answer = exchange(stream, &query).map_err(failure)?;
+    salvage::read_response(answer, message.id(), verifier, lenient)
+}
+
+/// Write `query` with the length in front of it that messages over streams have, RFC 1035
+/// section 4.2.2, and read the response framed the same way
+fn exchange<S: Read + Write>(
+    stream: &mut S,
+    query: &[u8],
+) -> io::Result<Vec<u8>> {
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed)?;
+    stream.flush()?;
+    let mut length = [0; 2];
+    stream.read_exact(&mut length)?;
+    let mut answer = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut answer)?;
+    Ok(answer)
+}
+
+/// Error of a failed exchange, timeouts told apart so that they are retried
+pub fn failure(error: io::Error) -> ClientError {
+    match error.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => ClientError::from(ClientErrorKind::Timeout),
+        _ => ClientError::from(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, str::FromStr, thread};
+
+    use hickory_client::{
+        op::{MessageType, Query},
+        rr::{Name, RecordType},
+        serialize::binary::BinDecodable,
+    };
+
+    use super::*;
+
+    fn query() -> Message {
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Query)
+            .add_query(Query::query(
+                Name::from_str("example.com.").unwrap(),
+                RecordType::A,
+            ));
+        message
+    }
+
+    /// Server answering `count` queries on one connection, closing it afterwards
+    fn server(count: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                for _ in 0..count {
+                    let mut length = [0; 2];
+                    if stream.read_exact(&mut length).is_err() {
+                        break;
+                    }
+                    let mut query = vec![0; u16::from_be_bytes(length) as usize];
+                    stream.read_exact(&mut query).unwrap();
+                    let mut response = Message::from_bytes(&query).unwrap();
+                    response.set_message_type(MessageType::Response);
+                    let response = response.to_bytes().unwrap();
+                    let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+                    framed.extend_from_slice(&response);
+                    stream.write_all(&framed).unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn responses_are_taken_without_waiting_for_the_timeout() {
+        let client = TcpClient::new(server(2), None, Duration::from_secs(5));
+        let started = std::time::Instant::now();
+        for _ in 0..2 {
+            let response = client.send(query()).unwrap();
+            assert_eq!(response.message_type(), MessageType::Response);
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn connections_closed_by_the_server_are_opened_again() {
+        let client = TcpClient::new(server(1), None, Duration::from_secs(5));
+        client.send(query()).unwrap();
+        client.send(query()).unwrap();
+    }
+
+    #[test]
+    fn corrupt_responses_are_salvaged_only_when_lenient() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut length = [0; 2];
+                stream.read_exact(&mut length).unwrap();
+                let mut query = vec![0; u16::from_be_bytes(length) as usize];
+                stream.read_exact(&mut query).unwrap();
+                let mut response = salvage::tests::opt_overrun();
+                response[..2].copy_from_slice(&query[..2]);
+                let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+                framed.extend_from_slice(&response);
+                stream.write_all(&framed).unwrap();
+            }
+        });
+        let client = TcpClient::new(addr, None, Duration::from_secs(5));
+        let (response, corruption) = client.send_lenient(query()).unwrap();
+        assert_eq!(response.answers().len(), 1);
+        assert!(corruption.is_some());
+        let error = client.send(query()).unwrap_err();
+        assert!(!salvage::timed_out(&error), "{}", error);
+    }
+
+    #[test]
+    fn silent_servers_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpClient::new(
+            listener.local_addr().unwrap(),
+            None,
+            Duration::from_millis(100),
+        );
+        let error = client.send(query()).unwrap_err();
+        assert!(salvage::timed_out(&error));
+    }
+}
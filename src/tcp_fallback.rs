@@ -0,0 +1,70 @@
+use std::{net::SocketAddr, time::Duration};
+
+use hickory_client::{
+    error::ClientResult,
+    op::DnsResponse,
+    rr::{Name, RecordType},
+};
+
+use crate::tcp::TcpClient;
+
+/// TCP client to the server of a UDP client, only connected once a response comes truncated
+/// and then shared by every later query of the client
+pub struct TcpFallback {
+    /// whether truncated responses are asked again at all, off with `--no-tcp-fallback`
+    enabled: bool,
+    client: TcpClient,
+}
+
+impl TcpFallback {
+    /// Ask again over `client` when `enabled`
+    pub fn new(
+        client: TcpClient,
+        enabled: bool,
+    ) -> Self {
+        Self {
+            enabled,
+            client,
+        }
+    }
+
+    /// Timeout of the queries over the TCP connections to the server
+    pub fn timeout(&self) -> Duration {
+        self.client.timeout()
+    }
+
+    /// Local address the TCP connections to the server leave from, `None` for any
+    pub fn bind_addr(&self) -> Option<SocketAddr> {
+        self.client.bind_addr()
+    }
+
+    /// The client to ask the server again over, when its `response` to the question came
+    /// over UDP with the TC bit set
+    pub fn client(
+        &self,
+        response: &ClientResult<DnsResponse>,
+        name: &Name,
+        query_type: RecordType,
+    ) -> Option<&TcpClient> {
+        match response {
+            Ok(response) if response.truncated() => {},
+            _ => return None,
+        }
+        if !self.enabled {
+            eprintln!(
+                "the response of {} to {} {} was truncated, showing it as it is",
+                self.client.addr(),
+                name,
+                query_type
+            );
+            return None;
+        }
+        eprintln!(
+            "the response of {} to {} {} was truncated, asking again over TCP",
+            self.client.addr(),
+            name,
+            query_type
+        );
+        Some(&self.client)
+    }
+}
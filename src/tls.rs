@@ -0,0 +1,210 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    error::{ClientError, ClientErrorKind, ClientResult},
+    op::{DnsResponse, Message},
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, ClientConnection, DigitallySignedStruct, HandshakeKind, RootCertStore,
+    SignatureScheme, StreamOwned,
+};
+
+use crate::{
+    connections::{ConnectionCounter, Connections},
+    tcp,
+    tsig::Signer,
+    ResolveOptions,
+};
+
+/// Boxed, as the state of a connection is large
+type Stream = Box<StreamOwned<ClientConnection, TcpStream>>;
+
+/// Client of DNS-over-TLS resolvers, sending every query over one connection, and resuming
+/// the session of the last one when it has to connect again
+pub struct DotClient {
+    addr: SocketAddr,
+    server_name: ServerName<'static>,
+    /// kept for every connection, as it holds the sessions to resume
+    config: Arc<ClientConfig>,
+    timeout: Duration,
+    signer: Option<Signer>,
+    stream: Mutex<Option<Stream>>,
+    counter: ConnectionCounter,
+}
+
+/// Accepts any certificate, for `--insecure`
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    // Signatures are still checked, so that the handshake itself isn't skipped
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+impl DotClient {
+    /// Connect to `addr`, checking its certificate as `options` say, or else for `host`, the
+    /// name the server was given by, or for the address itself
+    pub fn new(
+        addr: SocketAddr,
+        host: Option<&str>,
+        options: &ResolveOptions,
+        signer: Option<Signer>,
+    ) -> Result<Self, String> {
+        let timeout = options.timeout();
+        let server_name = match options.tls_hostname().or(host) {
+            Some(name) => {
+                ServerName::try_from(name.to_owned()).map_err(|error| error.to_string())?
+            },
+            None => ServerName::IpAddress(addr.ip().into()),
+        };
+
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|error| error.to_string())?;
+        let config = match options.insecure() {
+            true => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+                .with_no_client_auth(),
+            false => {
+                let roots = RootCertStore {
+                    roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                };
+                builder.with_root_certificates(roots).with_no_client_auth()
+            },
+        };
+
+        let client = Self {
+            addr,
+            server_name,
+            config: Arc::new(config),
+            timeout,
+            signer,
+            stream: Mutex::new(None),
+            counter: ConnectionCounter::default(),
+        };
+        // Connected right away, so that certificate errors show up as such
+        let stream = client.connect()?;
+        *client.stream.lock().unwrap() = Some(stream);
+        Ok(client)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn connections(&self) -> Connections {
+        self.counter.get()
+    }
+
+    fn connect(&self) -> Result<Stream, String> {
+        let started = Instant::now();
+        let socket =
+            TcpStream::connect_timeout(&self.addr, self.timeout).map_err(|e| e.to_string())?;
+        socket
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|_| socket.set_write_timeout(Some(self.timeout)))
+            .map_err(|e| e.to_string())?;
+        let connection = ClientConnection::new(self.config.clone(), self.server_name.clone())
+            .map_err(|e| e.to_string())?;
+        let mut stream = Box::new(StreamOwned::new(connection, socket));
+        while stream.conn.is_handshaking() {
+            stream
+                .conn
+                .complete_io(&mut stream.sock)
+                .map_err(|e| e.to_string())?;
+        }
+        let resumed = stream.conn.handshake_kind() == Some(HandshakeKind::Resumed);
+        self.counter.opened(started.elapsed(), resumed);
+        Ok(stream)
+    }
+
+    /// Send `message`, reconnecting once when the server closed the connection meanwhile
+    pub fn send(
+        &self,
+        message: Message,
+    ) -> ClientResult<DnsResponse> {
+        let mut stream = self.stream.lock().unwrap();
+        let mut reconnected = false;
+        loop {
+            let current = match stream.as_mut() {
+                Some(current) => current,
+                None => {
+                    reconnected = true;
+                    stream.insert(self.connect().map_err(ClientError::from)?)
+                },
+            };
+            match tcp::send(current, message.clone(), self.signer.as_ref(), false) {
+                Ok((response, _)) => {
+                    if !reconnected {
+                        self.counter.reused();
+                    }
+                    return Ok(response);
+                },
+                Err(error) => {
+                    *stream = None;
+                    let closed = matches!(
+                        error.kind(),
+                        ClientErrorKind::Io(error) if error.kind() == ErrorKind::UnexpectedEof
+                    );
+                    if reconnected && closed {
+                        return Err(ClientError::from("the server closed the connection"));
+                    }
+                    if reconnected || !closed {
+                        return Err(error);
+                    }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,613 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    op::{DnsResponse, ResponseCode},
+    rr::{DNSClass, Name, RData, Record, RecordType},
+};
+
+use crate::{
+    format::{OutputConfig, RecordFormatter},
+    outln, AppError, ConnectionType, DnsClient, ResolveOptions,
+};
+
+/// Delegations followed at most, as every one must go a label deeper towards the name
+const MAX_DEPTH: usize = 32;
+
+/// Servers of the root zone, from the root hints of IANA
+const ROOT_SERVERS: &[(&str, Ipv4Addr)] = &[
+    ("a.root-servers.net.", Ipv4Addr::new(198, 41, 0, 4)),
+    ("b.root-servers.net.", Ipv4Addr::new(170, 247, 170, 2)),
+    ("c.root-servers.net.", Ipv4Addr::new(192, 33, 4, 12)),
+    ("d.root-servers.net.", Ipv4Addr::new(199, 7, 91, 13)),
+    ("e.root-servers.net.", Ipv4Addr::new(192, 203, 230, 10)),
+    ("f.root-servers.net.", Ipv4Addr::new(192, 5, 5, 241)),
+    ("g.root-servers.net.", Ipv4Addr::new(192, 112, 36, 4)),
+    ("h.root-servers.net.", Ipv4Addr::new(198, 97, 190, 53)),
+    ("i.root-servers.net.", Ipv4Addr::new(192, 36, 148, 17)),
+    ("j.root-servers.net.", Ipv4Addr::new(192, 58, 128, 30)),
+    ("k.root-servers.net.", Ipv4Addr::new(193, 0, 14, 129)),
+    ("l.root-servers.net.", Ipv4Addr::new(199, 7, 83, 42)),
+    ("m.root-servers.net.", Ipv4Addr::new(202, 12, 27, 33)),
+];
+
+/// Name server of a zone, with the address to ask it at once known
+struct Server {
+    name: Name,
+    addr: Option<SocketAddr>,
+}
+
+/// Response of the server of a zone that answered, and the time it took to get it
+struct Asked {
+    server: Name,
+    addr: SocketAddr,
+    response: DnsResponse,
+    /// round trip of the query the server answered
+    elapsed: Duration,
+    /// spent on the servers of the zone that did not answer before it
+    unanswered: Duration,
+    /// spent asking the resolver for the addresses of servers without glue
+    lookups: Duration,
+}
+
+/// Time of a step of a trace, as `--timing` prints it
+struct Step {
+    /// the server that answered, its address for that of --trace-from
+    server: String,
+    elapsed: Duration,
+    unanswered: Duration,
+    lookups: Duration,
+}
+
+impl Step {
+    fn total(&self) -> Duration {
+        self.elapsed + self.unanswered + self.lookups
+    }
+}
+
+/// Time of every step of a trace, each measured query by query
+#[derive(Default)]
+struct Timing {
+    steps: Vec<Step>,
+}
+
+impl Timing {
+    /// Add the step of `server`, and describe it with the time of the trace so far
+    fn step(
+        &mut self,
+        step: Step,
+    ) -> String {
+        let mut line = format!(
+            "step {}: {} {}ms",
+            self.steps.len() + 1,
+            step.server,
+            step.elapsed.as_millis()
+        );
+        if !step.unanswered.is_zero() {
+            line += &format!(
+                ", after {}ms on servers that did not answer",
+                step.unanswered.as_millis()
+            );
+        }
+        if !step.lookups.is_zero() {
+            line += &format!(", {}ms looking up addresses", step.lookups.as_millis());
+        }
+        self.steps.push(step);
+        line + &format!(", {}ms in all", self.total().as_millis())
+    }
+
+    fn total(&self) -> Duration {
+        self.steps.iter().map(Step::total).sum()
+    }
+
+    /// The total time and what took the most of it: the slowest server, or the servers that
+    /// did not answer when they took longer
+    fn summary(&self) -> String {
+        let total = format!("total resolution {}ms", self.total().as_millis());
+        let Some(slowest) = self.steps.iter().max_by_key(|step| step.elapsed) else {
+            return total;
+        };
+        let unanswered: Duration = self.steps.iter().map(|step| step.unanswered).sum();
+        match unanswered > slowest.elapsed {
+            true => format!(
+                "{}, dominated by servers that did not answer ({}ms)",
+                total,
+                unanswered.as_millis()
+            ),
+            false => format!(
+                "{}, dominated by {} ({}ms)",
+                total,
+                slowest.server,
+                slowest.elapsed.as_millis()
+            ),
+        }
+    }
+}
+
+/// The fastest and the slowest of the servers of a zone, and the spread between them
+fn spread(times: &[(String, Duration)]) -> Option<String> {
+    let fastest = times.iter().min_by_key(|(_, time)| *time)?;
+    let slowest = times.iter().max_by_key(|(_, time)| *time)?;
+    Some(format!(
+        "fastest {} ({}ms), slowest {} ({}ms), a spread of {}ms",
+        fastest.0,
+        fastest.1.as_millis(),
+        slowest.0,
+        slowest.1.as_millis(),
+        (slowest.1 - fastest.1).as_millis()
+    ))
+}
+
+/// Queries of a trace and where their answers are printed
+pub struct Tracer<'a> {
+    /// how the name servers are asked, over UDP or TCP
+    options: ResolveOptions,
+    /// the server of the command line, asked for the addresses of name servers without glue
+    resolver: &'a DnsClient,
+    output_config: &'a OutputConfig,
+    /// print the time of every step, and what took the most of the total
+    timing: bool,
+    /// ask every server of every zone, to compare their times
+    all_ns: bool,
+}
+
+impl<'a> Tracer<'a> {
+    /// Trace with `options`, over TCP with their connection tcp, over UDP otherwise
+    pub fn new(
+        options: &ResolveOptions,
+        resolver: &'a DnsClient,
+        output_config: &'a OutputConfig,
+    ) -> Self {
+        let connection = match options.connection() {
+            ConnectionType::Tcp => ConnectionType::Tcp,
+            _ => ConnectionType::Udp,
+        };
+        Self {
+            options: options.clone().with_connection(connection),
+            resolver,
+            output_config,
+            timing: false,
+            all_ns: false,
+        }
+    }
+
+    /// Print the time of every step, and what took the most of the total
+    pub fn with_timing(
+        mut self,
+        timing: bool,
+    ) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Ask every server of every zone, printing the time of each
+    pub fn with_all_ns(
+        mut self,
+        all_ns: bool,
+    ) -> Self {
+        self.all_ns = all_ns;
+        self
+    }
+
+    /// Resolve `name` from the root servers down, or from `start` when given, printing every
+    /// delegation on the way and the final answer
+    pub fn trace(
+        &self,
+        name: &Name,
+        record_type: RecordType,
+        start: Option<SocketAddr>,
+    ) -> Result<(), AppError> {
+        let error =
+            |message: String| AppError::TraceError(format!("{} {}", name, record_type), message);
+        let mut zone = Name::root();
+        let mut servers: Vec<Server> = match start {
+            Some(addr) => vec![Server {
+                name: Name::root(),
+                addr: Some(addr),
+            }],
+            None => ROOT_SERVERS
+                .iter()
+                .map(|(server, address)| Server {
+                    name: Name::from_str(server).unwrap(),
+                    addr: Some(SocketAddr::new(IpAddr::V4(*address), 53)),
+                })
+                .collect(),
+        };
+        let mut timing = Timing::default();
+        for _ in 0..MAX_DEPTH {
+            let asked = self
+                .ask(&servers, name, record_type)
+                .ok_or_else(|| error(format!("no server of {} answered", zone)))?;
+            let (server, addr, response, elapsed) =
+                (&asked.server, asked.addr, &asked.response, asked.elapsed);
+            let header = |what: &str| {
+                // The server of --trace-from has no name
+                let from = match server.is_root() {
+                    true => addr.to_string(),
+                    false => format!("{} ({})", server, addr),
+                };
+                let header = format!("{} from {} in {}ms", what, from, elapsed.as_millis());
+                outln!("{}", self.output_config.format_header(&header));
+            };
+
+            // Authoritative responses and answers end the trace, whatever authority comes along
+            let delegation = match response.authoritative() || !response.answers().is_empty() {
+                true => None,
+                false => referral(response),
+            };
+            let Some((child, next)) = delegation else {
+                let code = response.response_code();
+                match response.answers().is_empty() {
+                    false => {
+                        header("Answer");
+                        self.print(response.answers());
+                    },
+                    true if code == ResponseCode::NoError => {
+                        header("No records");
+                        self.print(response.name_servers());
+                    },
+                    true => {
+                        header(&format!("{:?}", code).to_uppercase());
+                        self.print(response.name_servers());
+                    },
+                }
+                self.overlay(&mut timing, &asked, &servers, name, record_type);
+                if self.timing {
+                    let summary = format!(";; {}", timing.summary());
+                    outln!("{}", self.output_config.format_header(&summary));
+                }
+                return match code {
+                    ResponseCode::NoError => Ok(()),
+                    ResponseCode::NXDomain => Err(AppError::NxDomain(name.to_string())),
+                    code => Err(AppError::ResponseError(
+                        format!("{:?}", code).to_uppercase(),
+                        addr.to_string(),
+                        format!("{}/{}", name, record_type),
+                    )),
+                };
+            };
+            header(&format!("Referral to {}", child));
+            self.print(response.name_servers());
+            self.overlay(&mut timing, &asked, &servers, name, record_type);
+            // Every step must go down towards the name, or the delegations would go around
+            if !child.zone_of(name) || child.num_labels() <= zone.num_labels() {
+                return Err(error(format!(
+                    "{} of {} referred to {}, which is no zone below it towards the name, so the \
+                     delegations would loop",
+                    server, zone, child
+                )));
+            }
+            outln!();
+            servers = next;
+            zone = child;
+        }
+        Err(error(format!("more than {} delegations", MAX_DEPTH)))
+    }
+
+    /// Print the time of the step that `asked` answered with `--timing`, and that of every
+    /// other of `servers` with `--trace-all-ns`
+    fn overlay(
+        &self,
+        timing: &mut Timing,
+        asked: &Asked,
+        servers: &[Server],
+        name: &Name,
+        record_type: RecordType,
+    ) {
+        if self.all_ns {
+            let mut times = Vec::new();
+            for server in servers {
+                if server.name == asked.server {
+                    // Asked already, and not again so that its time is that of the trace
+                    let elapsed = asked.elapsed;
+                    times.push((server.name.to_string(), elapsed));
+                    outln!(
+                        ";;   {} ({}) {}ms",
+                        server.name,
+                        asked.addr,
+                        elapsed.as_millis()
+                    );
+                    continue;
+                }
+                let Some(addr) = server.addr.or_else(|| self.address_of(&server.name)) else {
+                    outln!(";;   {} has no address", server.name);
+                    continue;
+                };
+                match self.query(addr, name, record_type) {
+                    Ok((_, elapsed)) => {
+                        times.push((server.name.to_string(), elapsed));
+                        outln!(";;   {} ({}) {}ms", server.name, addr, elapsed.as_millis());
+                    },
+                    Err(error) => {
+                        outln!(";;   {} ({}) did not answer: {}", server.name, addr, error)
+                    },
+                }
+            }
+            if let Some(spread) = spread(&times) {
+                outln!(
+                    "{}",
+                    self.output_config.format_header(&format!(";; {}", spread))
+                );
+            }
+        }
+        if self.timing {
+            let line = timing.step(Step {
+                // The server of --trace-from has no name
+                server: match asked.server.is_root() {
+                    true => asked.addr.to_string(),
+                    false => asked.server.to_string(),
+                },
+                elapsed: asked.elapsed,
+                unanswered: asked.unanswered,
+                lookups: asked.lookups,
+            });
+            outln!(
+                "{}",
+                self.output_config.format_header(&format!(";; {}", line))
+            );
+        }
+    }
+
+    /// Response of the first of `servers` that answers, with its name, address and the round
+    /// trip time
+    fn ask(
+        &self,
+        servers: &[Server],
+        name: &Name,
+        record_type: RecordType,
+    ) -> Option<Asked> {
+        let (mut unanswered, mut lookups) = (Duration::ZERO, Duration::ZERO);
+        for server in servers {
+            let looked_up = Instant::now();
+            let addr = server.addr.or_else(|| {
+                let addr = self.address_of(&server.name);
+                lookups += looked_up.elapsed();
+                addr
+            });
+            let Some(addr) = addr else {
+                eprintln!("no address of {}", server.name);
+                continue;
+            };
+            let started = Instant::now();
+            match self.query(addr, name, record_type) {
+                Ok((response, elapsed)) => {
+                    return Some(Asked {
+                        server: server.name.clone(),
+                        addr,
+                        response,
+                        elapsed,
+                        unanswered,
+                        lookups,
+                    });
+                },
+                Err(error) => {
+                    unanswered += started.elapsed();
+                    eprintln!("{} ({}) did not answer: {}", server.name, addr, error);
+                },
+            }
+        }
+        None
+    }
+
+    /// Response of the server at `addr` to the query, and its round trip time
+    fn query(
+        &self,
+        addr: SocketAddr,
+        name: &Name,
+        record_type: RecordType,
+    ) -> Result<(DnsResponse, Duration), String> {
+        let options = self.options.clone().with_server(&addr.to_string());
+        let client = DnsClient::new(&options).map_err(|error| error.to_string())?;
+        let started = Instant::now();
+        let response = client
+            .query_uncached(name, DNSClass::IN, record_type)
+            .map_err(|error| error.to_string())?;
+        Ok((response, started.elapsed()))
+    }
+
+    /// Address of a name server that came without glue, asked of the resolver
+    fn address_of(
+        &self,
+        server: &Name,
+    ) -> Option<SocketAddr> {
+        let response = self
+            .resolver
+            .query(server, DNSClass::IN, RecordType::A)
+            .ok()?;
+        response
+            .answers()
+            .iter()
+            .find_map(|record| match record.data() {
+                Some(RData::A(address)) => Some(SocketAddr::new(IpAddr::V4(address.0), 53)),
+                _ => None,
+            })
+    }
+
+    fn print(
+        &self,
+        records: &[Record],
+    ) {
+        let mut line = String::new();
+        let widths = self
+            .output_config
+            .column_widths(&records.iter().collect::<Vec<_>>());
+        for record in records {
+            line.clear();
+            RecordFormatter::new(record, self.output_config)
+                .with_widths(widths)
+                .write(&mut line);
+            outln!("{}", line);
+        }
+    }
+}
+
+/// Zone that `response` delegates to and its name servers, with the addresses of the
+/// glue records, IPv4 first
+fn referral(response: &DnsResponse) -> Option<(Name, Vec<Server>)> {
+    let ns: Vec<&Record> = response
+        .name_servers()
+        .iter()
+        .filter(|record| record.record_type() == RecordType::NS)
+        .collect();
+    let child = ns.first()?.name().clone();
+    let glue = |server: &Name, v4: bool| {
+        response
+            .additionals()
+            .iter()
+            .find_map(|record| match record.data() {
+                Some(RData::A(address)) if v4 && record.name() == server => {
+                    Some(SocketAddr::new(IpAddr::V4(address.0), 53))
+                },
+                Some(RData::AAAA(address)) if !v4 && record.name() == server => {
+                    Some(SocketAddr::new(IpAddr::V6(address.0), 53))
+                },
+                _ => None,
+            })
+    };
+    let mut servers: Vec<Server> = Vec::new();
+    for v4 in [true, false] {
+        for record in &ns {
+            if let Some(RData::NS(server)) = record.data() {
+                if let Some(addr) = glue(&server.0, v4) {
+                    servers.push(Server {
+                        name: server.0.clone(),
+                        addr: Some(addr),
+                    });
+                }
+            }
+        }
+    }
+    // Servers without glue are looked up last, only when those with glue don't answer
+    for record in &ns {
+        if let Some(RData::NS(server)) = record.data() {
+            if !servers.iter().any(|known| known.name == server.0) {
+                servers.push(Server {
+                    name: server.0.clone(),
+                    addr: None,
+                });
+            }
+        }
+    }
+    Some((child, servers))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mock::Fixture;
+
+    fn step(
+        server: &str,
+        elapsed: u64,
+        unanswered: u64,
+        lookups: u64,
+    ) -> Step {
+        Step {
+            server: server.to_owned(),
+            elapsed: Duration::from_millis(elapsed),
+            unanswered: Duration::from_millis(unanswered),
+            lookups: Duration::from_millis(lookups),
+        }
+    }
+
+    #[test]
+    fn steps_add_up_to_the_slowest_server() {
+        let mut timing = Timing::default();
+        assert_eq!(timing.summary(), "total resolution 0ms");
+        assert_eq!(
+            timing.step(step("a.root-servers.net.", 22, 0, 0)),
+            "step 1: a.root-servers.net. 22ms, 22ms in all"
+        );
+        assert_eq!(
+            timing.step(step("ns1.slow-tld-server.example.", 240, 0, 0)),
+            "step 2: ns1.slow-tld-server.example. 240ms, 262ms in all"
+        );
+        assert_eq!(
+            timing.step(step("ns1.example.com.", 38, 0, 12)),
+            "step 3: ns1.example.com. 38ms, 12ms looking up addresses, 312ms in all"
+        );
+        assert_eq!(
+            timing.summary(),
+            "total resolution 312ms, dominated by ns1.slow-tld-server.example. (240ms)"
+        );
+    }
+
+    #[test]
+    fn servers_that_did_not_answer_dominate_when_they_took_longer() {
+        let mut timing = Timing::default();
+        assert_eq!(
+            timing.step(step("b.root-servers.net.", 20, 2000, 0)),
+            "step 1: b.root-servers.net. 20ms, after 2000ms on servers that did not answer, \
+             2020ms in all"
+        );
+        timing.step(step("ns2.example.com.", 30, 0, 0));
+        assert_eq!(
+            timing.summary(),
+            "total resolution 2050ms, dominated by servers that did not answer (2000ms)"
+        );
+    }
+
+    #[test]
+    fn the_spread_is_between_the_fastest_and_the_slowest() {
+        assert_eq!(spread(&[]), None);
+        let times = [
+            (
+                String::from("a.gtld-servers.net."),
+                Duration::from_millis(31),
+            ),
+            (
+                String::from("b.gtld-servers.net."),
+                Duration::from_millis(12),
+            ),
+            (
+                String::from("c.gtld-servers.net."),
+                Duration::from_millis(240),
+            ),
+        ];
+        assert_eq!(
+            spread(&times).unwrap(),
+            "fastest b.gtld-servers.net. (12ms), slowest c.gtld-servers.net. (240ms), a spread \
+             of 228ms"
+        );
+    }
+
+    #[test]
+    fn every_query_is_timed_on_its_own() {
+        // The first root server times out, the second answers after the delay
+        let fixture = Fixture::from_toml(
+            "[[answer]]\nname = \"example.com\"\ntype = \"A\"\ndelay = \"40ms\"\n\
+             failures = [\"timeout\"]\nflags = [\"aa\"]\nrecords = [\"192.0.2.1\"]",
+        )
+        .unwrap();
+        let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+            .with_timeout(Duration::from_millis(100))
+            .with_retries(0)
+            .with_mock(Some(Arc::new(fixture)));
+        let resolver = DnsClient::new(&options).unwrap();
+        let output_config = OutputConfig::new(true, false);
+        let tracer = Tracer::new(&options, &resolver, &output_config);
+        let servers: Vec<Server> = ROOT_SERVERS[..2]
+            .iter()
+            .map(|(server, address)| Server {
+                name: Name::from_str(server).unwrap(),
+                addr: Some(SocketAddr::new(IpAddr::V4(*address), 53)),
+            })
+            .collect();
+        let name = Name::from_str("example.com.").unwrap();
+        let asked = tracer.ask(&servers, &name, RecordType::A).unwrap();
+        assert_eq!(asked.server, servers[1].name);
+        assert!(asked.unanswered >= Duration::from_millis(100));
+        assert!(asked.elapsed >= Duration::from_millis(40));
+        assert!(
+            asked.elapsed < Duration::from_millis(100),
+            "{:?}",
+            asked.elapsed
+        );
+        assert_eq!(asked.lookups, Duration::ZERO);
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hickory_client::rr::Name;
+use regex::Regex;
+
+use crate::{
+    format::format_timestamp,
+    rdap::days_since_epoch,
+    rollover::ZONE_KEY,
+    tsig::decode_base64,
+    validate::{parse_trust_anchor, TrustAnchor},
+};
+
+/// Statements of named.conf that hold trust anchors
+const BLOCKS: &[&str] = &["trust-anchors", "managed-keys", "trusted-keys"];
+
+/// Trust anchors of a file, and why some keys of it were left out
+#[derive(Default)]
+pub struct AnchorFile {
+    pub anchors: Vec<TrustAnchor>,
+    /// a warning per revoked or expired key, with its line
+    pub warnings: Vec<String>,
+}
+
+/// Read the trust anchors of the file at `path`, see `parse`
+pub fn read(
+    path: &Path,
+    now: SystemTime,
+) -> Result<AnchorFile, String> {
+    let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    parse(&content, now)
+}
+
+/// Parse the trust anchors of a file, which is one of:
+/// - DS or DNSKEY records as in a zone file or the output of dig, one per line, or DS records
+///   without their class and type like `. 20326 8 2 <digest>`
+/// - a `trust-anchors`, `managed-keys` or `trusted-keys` statement of BIND
+/// - the `root-anchors.xml` of IANA, whose digests are only taken while they are valid
+///
+/// Revoked keys and digests expired by `now` are left out with a warning, and errors name
+/// the line they are on.
+pub fn parse(
+    content: &str,
+    now: SystemTime,
+) -> Result<AnchorFile, String> {
+    let file = if content.contains("<TrustAnchor") {
+        parse_xml(content, now)?
+    } else {
+        match tokens(content) {
+            Ok(tokens)
+                if tokens
+                    .first()
+                    .is_some_and(|(_, token)| BLOCKS.contains(&token.as_str())) =>
+            {
+                parse_bind(tokens)?
+            },
+            Err(error) if BLOCKS.iter().any(|block| content.contains(block)) => return Err(error),
+            _ => parse_records(content)?,
+        }
+    };
+    if file.anchors.is_empty() {
+        return Err(match file.warnings.is_empty() {
+            true => String::from("no trust anchor in the file"),
+            false => format!("no trust anchor left: {}", file.warnings.join(", ")),
+        });
+    }
+    Ok(file)
+}
+
+enum Kind {
+    Ds,
+    Key,
+}
+
+/// Add the anchor of `zone` written as the fields of a DS or DNSKEY record to `file`, or a
+/// warning when it is a revoked key
+fn add(
+    file: &mut AnchorFile,
+    line: usize,
+    zone: &str,
+    kind: Kind,
+    fields: &[&str],
+) -> Result<(), String> {
+    let anchor = match kind {
+        Kind::Ds => parse_trust_anchor(&format!("{} {}", zone, fields.join(" "))),
+        Kind::Key => parse_key(zone, fields),
+    }
+    .map_err(|error| format!("line {}: {}", line, error))?;
+    match anchor.revoked() {
+        true => file.warnings.push(format!(
+            "line {}: key {} of {} is revoked, left out",
+            line,
+            anchor.tag(),
+            anchor.zone()
+        )),
+        false => file.anchors.push(anchor),
+    }
+    Ok(())
+}
+
+/// A trust anchor written like a DNSKEY record, `flags protocol algorithm key`
+fn parse_key(
+    zone: &str,
+    fields: &[&str],
+) -> Result<TrustAnchor, String> {
+    let [flags, protocol, algorithm, key @ ..] = fields else {
+        return Err(String::from(
+            "expected a DNSKEY record like \"example. DNSKEY 257 3 13 <key>\"",
+        ));
+    };
+    let flags = flags
+        .parse::<u16>()
+        .map_err(|_| format!("invalid flags {:?}", flags))?;
+    if flags & ZONE_KEY == 0 {
+        return Err(format!("flags {} lack the zone key bit", flags));
+    }
+    if *protocol != "3" {
+        return Err(format!("invalid protocol {:?}, expected 3", protocol));
+    }
+    let algorithm = algorithm
+        .parse::<u8>()
+        .map_err(|_| format!("invalid algorithm {:?}", algorithm))?;
+    let key = decode_base64(&key.concat())
+        .filter(|key| !key.is_empty())
+        .ok_or("the key is not valid base64")?;
+    let zone = Name::from_str(zone).map_err(|_| format!("invalid zone {:?}", zone))?;
+    let data = [&flags.to_be_bytes()[..], &[3, algorithm], &key].concat();
+    Ok(TrustAnchor::from_key(&zone, data))
+}
+
+/// Records of a file like a zone file, `;` and `#` starting comments
+fn parse_records(content: &str) -> Result<AnchorFile, String> {
+    let mut file = AnchorFile::default();
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let record = line.split([';', '#']).next().unwrap_or_default();
+        let fields: Vec<&str> = record.split_whitespace().collect();
+        let Some(zone) = fields.first() else {
+            continue;
+        };
+        // The TTL and class may come between the owner and the type
+        let record_type = fields
+            .iter()
+            .enumerate()
+            .skip(1)
+            .take(3)
+            .find(|(_, field)| {
+                field.eq_ignore_ascii_case("DS") || field.eq_ignore_ascii_case("DNSKEY")
+            });
+        match record_type {
+            Some((at, record_type)) => {
+                let kind = match record_type.eq_ignore_ascii_case("DS") {
+                    true => Kind::Ds,
+                    false => Kind::Key,
+                };
+                add(&mut file, line_number, zone, kind, &fields[at + 1..])?
+            },
+            None => add(&mut file, line_number, zone, Kind::Ds, &fields[1..])?,
+        }
+    }
+    Ok(file)
+}
+
+/// Words of a configuration of BIND with the line each is on, without the comments, braces
+/// and semicolons being words of their own
+fn tokens(content: &str) -> Result<Vec<(usize, String)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '#' | '/' if c == '#' || chars.peek() == Some(&'/') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                let start = line;
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('*') if chars.next_if_eq(&'/').is_some() => break,
+                        Some('\n') => line += 1,
+                        Some(_) => {},
+                        None => return Err(format!("line {}: the comment is not closed", start)),
+                    }
+                }
+            },
+            '{' | '}' | ';' => tokens.push((line, c.to_string())),
+            '"' => {
+                let start = line;
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            token.push(c);
+                        },
+                        None => return Err(format!("line {}: the string is not closed", start)),
+                    }
+                }
+                tokens.push((start, token));
+            },
+            c if c.is_whitespace() => {},
+            c => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{};\"".contains(*c)) {
+                    token.push(c);
+                }
+                tokens.push((line, token));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+/// Anchors of the statements of BIND, e.g.
+/// `trust-anchors { . initial-key 257 3 8 "<key>"; example. static-ds 12345 13 2 "<digest>"; };`
+fn parse_bind(tokens: Vec<(usize, String)>) -> Result<AnchorFile, String> {
+    let mut file = AnchorFile::default();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some((line, block)) = tokens.next() {
+        if !BLOCKS.contains(&block.as_str()) {
+            return Err(format!(
+                "line {}: unexpected {:?}, expected trust-anchors",
+                line, block
+            ));
+        }
+        if tokens.next_if(|(_, token)| token == "{").is_none() {
+            return Err(format!("line {}: expected {{ after {}", line, block));
+        }
+        loop {
+            let Some((line, first)) = tokens.next() else {
+                return Err(format!(
+                    "line {}: the {} statement is not closed",
+                    line, block
+                ));
+            };
+            if first == "}" {
+                tokens.next_if(|(_, token)| token == ";");
+                break;
+            }
+            let mut fields = vec![first];
+            loop {
+                match tokens.next() {
+                    Some((_, token)) if token == ";" => break,
+                    Some((at, token)) if token == "{" || token == "}" => {
+                        return Err(format!("line {}: unexpected {}, expected ;", at, token))
+                    },
+                    Some((_, token)) => fields.push(token),
+                    None => return Err(format!("line {}: the anchor does not end with ;", line)),
+                }
+            }
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            let (kind, rest) = match fields.as_slice() {
+                [_, "initial-key" | "static-key", rest @ ..] => (Kind::Key, rest),
+                [_, "initial-ds" | "static-ds", rest @ ..] => (Kind::Ds, rest),
+                [_, rest @ ..] if block == "trusted-keys" => (Kind::Key, rest),
+                [_, kind, ..] => {
+                    return Err(format!(
+                        "line {}: unknown anchor type {:?}, expected initial-key, static-key, \
+                         initial-ds or static-ds",
+                        line, kind
+                    ))
+                },
+                _ => return Err(format!("line {}: expected an anchor after the zone", line)),
+            };
+            add(&mut file, line, fields[0], kind, rest)?;
+        }
+    }
+    Ok(file)
+}
+
+/// Line of the byte at `offset` of `content`, counted from 1
+fn line_of(
+    content: &str,
+    offset: usize,
+) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+/// Time of an XML timestamp like `2017-02-02T00:00:00+00:00`, taken in UTC as IANA writes them
+fn parse_time(timestamp: &str) -> Option<SystemTime> {
+    let days = u64::try_from(days_since_epoch(timestamp)?).ok()?;
+    let time = match timestamp.get(11..19) {
+        Some(time) => {
+            let mut parts = time.split(':').map(str::parse::<u64>);
+            let (hour, minute, second) = (
+                parts.next()?.ok()?,
+                parts.next()?.ok()?,
+                parts.next()?.ok()?,
+            );
+            hour * 3600 + minute * 60 + second
+        },
+        None => 0,
+    };
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + time))
+}
+
+/// The digests of the `root-anchors.xml` of IANA valid at `now`, see RFC 9718
+fn parse_xml(
+    content: &str,
+    now: SystemTime,
+) -> Result<AnchorFile, String> {
+    let element = |name: &str| Regex::new(&format!(r"<{0}>\s*([^<]*?)\s*</{0}>", name)).unwrap();
+    let zone = element("Zone")
+        .captures(content)
+        .map(|captures| captures[1].to_owned())
+        .ok_or("line 1: the trust anchor has no <Zone>")?;
+    let digests = Regex::new(r"(?s)<KeyDigest([^>]*)>(.*?)</KeyDigest>").unwrap();
+    let attribute = |attributes: &str, name: &str| {
+        Regex::new(&format!(r#"\b{}="([^"]*)""#, name))
+            .unwrap()
+            .captures(attributes)
+            .map(|captures| captures[1].to_owned())
+    };
+    let mut file = AnchorFile::default();
+    for captures in digests.captures_iter(content) {
+        let line = line_of(content, captures.get(0).unwrap().start());
+        let (attributes, body) = (&captures[1], &captures[2]);
+        let mut fields = Vec::new();
+        for name in ["KeyTag", "Algorithm", "DigestType", "Digest"] {
+            let field = element(name)
+                .captures(body)
+                .ok_or_else(|| format!("line {}: the KeyDigest has no <{}>", line, name))?;
+            fields.push(field[1].to_owned());
+        }
+        let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+        let time = |name: &str| match attribute(attributes, name) {
+            Some(timestamp) => parse_time(&timestamp)
+                .map(Some)
+                .ok_or_else(|| format!("line {}: invalid {} {:?}", line, name, timestamp)),
+            None => Ok(None),
+        };
+        let (valid_from, valid_until) = (time("validFrom")?, time("validUntil")?);
+        let tag = fields[0];
+        if let Some(until) = valid_until.filter(|until| *until <= now) {
+            file.warnings.push(format!(
+                "line {}: the DS of key {} of {} expired on {}, left out",
+                line,
+                tag,
+                zone,
+                format_timestamp(until)
+            ));
+        } else if let Some(from) = valid_from.filter(|from| *from > now) {
+            file.warnings.push(format!(
+                "line {}: the DS of key {} of {} is only valid from {}, left out",
+                line,
+                tag,
+                zone,
+                format_timestamp(from)
+            ));
+        } else {
+            add(&mut file, line, &zone, Kind::Ds, &fields)?;
+        }
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT_DS: &str =
+        "20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D";
+
+    /// A key of algorithm 13 with the flags `flags`, whose bytes are not a real key
+    fn key(flags: u16) -> String {
+        format!(
+            "{} 3 13 AwEAAbOFAxl+Lkt0UMglZizKEC1AxUu8zlj65KYatR5wBWMrh18TYzK/",
+            flags
+        )
+    }
+
+    fn anchors(content: &str) -> Vec<String> {
+        parse(content, SystemTime::now())
+            .unwrap()
+            .anchors
+            .iter()
+            .map(TrustAnchor::to_string)
+            .collect()
+    }
+
+    fn error(content: &str) -> String {
+        parse(content, SystemTime::now()).err().unwrap()
+    }
+
+    #[test]
+    fn records_are_read_like_a_zone_file() {
+        let content = format!(
+            "; the root and an island\n.\t172800 IN DS {}\ncorp.example. DNSKEY {} # internal\n\n\
+             example.net {}\n",
+            ROOT_DS,
+            key(257),
+            ROOT_DS
+        );
+        let got = anchors(&content);
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[0], ". DS 20326");
+        assert!(got[1].starts_with("corp.example. DNSKEY "), "{}", got[1]);
+        assert_eq!(got[2], "example.net. DS 20326");
+    }
+
+    #[test]
+    fn statements_of_bind_are_read() {
+        let content = format!(
+            "/* anchors of\n   the resolver */\ntrust-anchors {{\n  // the root\n  \".\" initial-ds {};\n  \
+             corp.example. static-key {} \"AAAA\";\n}};\ntrusted-keys {{ example.org. {}; }};\n",
+            ROOT_DS.replacen(" E06D", " \"E06D", 1) + "\"",
+            key(257),
+            key(257)
+        );
+        let got = anchors(&content);
+        assert_eq!(got.len(), 3);
+        assert_eq!(got[0], ". DS 20326");
+        assert!(got[2].starts_with("example.org. DNSKEY "), "{}", got[2]);
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        assert_eq!(
+            error(&format!(". DS {}\nexample. DS 1 2 3\n", ROOT_DS)),
+            "line 2: invalid digest \"\""
+        );
+        assert_eq!(
+            error("trust-anchors {\n  . initial-key 257 3 8 \"AwEAAa\";\n  . trusted 1;\n};\n"),
+            "line 3: unknown anchor type \"trusted\", expected initial-key, static-key, initial-ds \
+             or static-ds"
+        );
+        assert_eq!(
+            error("trust-anchors {\n  . initial-key 257 3 8 \"AwEAAa\"\n}\n"),
+            "line 3: unexpected }, expected ;"
+        );
+        assert_eq!(
+            error(&format!(
+                "example. DNSKEY {}\n",
+                key(256).replacen("256", "0", 1)
+            )),
+            "line 1: flags 0 lack the zone key bit"
+        );
+        assert_eq!(error("; nothing\n"), "no trust anchor in the file");
+    }
+
+    #[test]
+    fn revoked_and_expired_keys_are_left_out_with_a_warning() {
+        let content = format!(". DS {}\nexample. DNSKEY {}\n", ROOT_DS, key(257 | 0x0080));
+        let file = parse(&content, SystemTime::now()).unwrap();
+        assert_eq!(file.anchors.len(), 1);
+        assert_eq!(file.warnings.len(), 1);
+        assert!(
+            file.warnings[0].starts_with("line 2: key ")
+                && file.warnings[0].ends_with(" of example. is revoked, left out"),
+            "{}",
+            file.warnings[0]
+        );
+
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrustAnchor id="E9724F53-1851-4F86-85E5-F1392102940B" source="http://data.iana.org/root-anchors/root-anchors.xml">
+<Zone>.</Zone>
+<KeyDigest id="Kjqmt7v" validFrom="2010-07-15T00:00:00+00:00" validUntil="2019-01-11T00:00:00+00:00">
+<KeyTag>19036</KeyTag>
+<Algorithm>8</Algorithm>
+<DigestType>2</DigestType>
+<Digest>49AAC11D7B6F6446702E54A1607371607A1A41855200FD2CE1CDDE32F24E8FB5</Digest>
+</KeyDigest>
+<KeyDigest id="Klajeyz" validFrom="2017-02-02T00:00:00+00:00">
+<KeyTag>20326</KeyTag>
+<Algorithm>8</Algorithm>
+<DigestType>2</DigestType>
+<Digest>E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D</Digest>
+</KeyDigest>
+</TrustAnchor>
+"#;
+        let file = parse(xml, SystemTime::now()).unwrap();
+        let got: Vec<String> = file.anchors.iter().map(TrustAnchor::to_string).collect();
+        assert_eq!(got, [". DS 20326"]);
+        assert_eq!(
+            file.warnings,
+            ["line 4: the DS of key 19036 of . expired on 2019-01-11T00:00:00.000Z, left out"]
+        );
+        // Before 2017, the new root key was not valid yet
+        let before = parse_time("2015-01-01T00:00:00+00:00").unwrap();
+        assert_eq!(parse(xml, before).unwrap().warnings.len(), 1);
+        assert_eq!(
+            parse(xml, before).unwrap().anchors[0].to_string(),
+            ". DS 19036"
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::{fmt::Display, str::FromStr, time::SystemTime};
+
+use hickory_client::{
+    error::{ClientError, ClientResult},
+    op::{DnsResponse, Message},
+    rr::Name,
+};
+#[cfg(feature = "tsig")]
+use hickory_client::{
+    op::MessageVerifier,
+    proto::rr::dnssec::{rdata::tsig::TsigAlgorithm, tsig::TSigner},
+    rr::{rdata::NULL, RData, Record},
+    serialize::binary::{BinEncodable, BinEncoder},
+};
+
+/// Seconds the clocks of the server and ours may differ by, that of BIND and nsupdate
+#[cfg_attr(not(feature = "tsig"), allow(dead_code))]
+const FUDGE: u16 = 300;
+
+/// MAC algorithms of the keys, those that hickory signs with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Ok(Self::HmacSha256),
+            "hmac-sha384" => Ok(Self::HmacSha384),
+            "hmac-sha512" => Ok(Self::HmacSha512),
+            _ => Err(format!(
+                "unsupported TSIG algorithm {:?}, use hmac-sha256, hmac-sha384 or hmac-sha512",
+                value
+            )),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::HmacSha256 => "hmac-sha256",
+            Self::HmacSha384 => "hmac-sha384",
+            Self::HmacSha512 => "hmac-sha512",
+        })
+    }
+}
+
+/// Shared secret that the queries to a server are signed with, RFC 8945
+#[derive(Clone)]
+pub struct Key {
+    name: Name,
+    algorithm: Algorithm,
+    #[cfg_attr(not(feature = "tsig"), allow(dead_code))]
+    secret: Vec<u8>,
+}
+
+impl Key {
+    /// Key called `name`, whose `secret` is in base64 as in the key files of BIND
+    pub fn parse(
+        name: &str,
+        algorithm: &str,
+        secret: &str,
+    ) -> Result<Self, String> {
+        let name = Name::from_str(name).map_err(|_| format!("invalid key name {:?}", name))?;
+        let secret = decode_base64(secret).ok_or("the secret is not valid base64")?;
+        if secret.is_empty() {
+            return Err(String::from("the secret is empty"));
+        }
+        Ok(Self {
+            name: name.to_lowercase(),
+            algorithm: algorithm.parse()?,
+            secret,
+        })
+    }
+
+    pub fn name(&self) -> &Name {
+        &self.name
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+}
+
+/// Signs queries with a key and checks that the responses are signed with it as well
+#[derive(Clone)]
+pub struct Signer {
+    #[cfg(feature = "tsig")]
+    signer: TSigner,
+}
+
+impl Signer {
+    #[cfg(feature = "tsig")]
+    pub fn new(key: &Key) -> Result<Self, String> {
+        let algorithm = match key.algorithm {
+            Algorithm::HmacSha256 => TsigAlgorithm::HmacSha256,
+            Algorithm::HmacSha384 => TsigAlgorithm::HmacSha384,
+            Algorithm::HmacSha512 => TsigAlgorithm::HmacSha512,
+        };
+        let signer = TSigner::new(key.secret.clone(), algorithm, key.name.clone(), FUDGE)
+            .map_err(|error| error.to_string())?;
+        Ok(Self {
+            signer,
+        })
+    }
+
+    #[cfg(not(feature = "tsig"))]
+    pub fn new(_: &Key) -> Result<Self, String> {
+        Err(String::from("TSIG needs resoy built with the tsig feature"))
+    }
+
+    /// The signer of hickory, for the clients that sign on their own
+    #[cfg(feature = "tsig")]
+    pub fn tsigner(&self) -> &TSigner {
+        &self.signer
+    }
+
+    /// Sign `message`, whose ID must not change anymore, and return the check of its response
+    pub fn sign(
+        &self,
+        message: &mut Message,
+    ) -> ClientResult<Verifier> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as u32);
+        #[cfg(feature = "tsig")]
+        {
+            let verifier = message.finalize(&self.signer, now)?;
+            verifier
+                .map(|verifier| Verifier {
+                    verifier,
+                })
+                .ok_or_else(|| ClientError::from("TSIG signing gave no verifier"))
+        }
+        #[cfg(not(feature = "tsig"))]
+        {
+            let _ = (message, now);
+            Err(ClientError::from(
+                "TSIG needs resoy built with the tsig feature",
+            ))
+        }
+    }
+}
+
+/// Check of the response to a signed query
+pub struct Verifier {
+    #[cfg(feature = "tsig")]
+    verifier: MessageVerifier,
+}
+
+impl Verifier {
+    /// The response in `bytes`, once its signature and time are found right
+    pub fn verify(
+        self,
+        bytes: &[u8],
+    ) -> ClientResult<DnsResponse> {
+        #[cfg(feature = "tsig")]
+        {
+            let mut verifier = self.verifier;
+            verifier(bytes).map_err(ClientError::from)
+        }
+        #[cfg(not(feature = "tsig"))]
+        {
+            let _ = bytes;
+            Err(ClientError::from(
+                "TSIG needs resoy built with the tsig feature",
+            ))
+        }
+    }
+}
+
+/// `response` with its DNSSEC records as raw data again. TSIG comes with the DNSSEC support
+/// of hickory, which parses them into types of its own, while resoy reads them as builds
+/// without the feature parse them.
+#[cfg(feature = "tsig")]
+pub fn raw_dnssec(response: DnsResponse) -> DnsResponse {
+    let (mut message, buffer) = response.into_parts();
+    raw_dnssec_records(message.answers_mut());
+    raw_dnssec_records(message.name_servers_mut());
+    raw_dnssec_records(message.additionals_mut());
+    DnsResponse::new(message, buffer)
+}
+
+/// `records` with the data of the DNSSEC ones as it was on the wire, their names lowercased
+#[cfg(feature = "tsig")]
+pub fn raw_dnssec_records(records: &mut [Record]) {
+    for record in records {
+        let Some(data @ RData::DNSSEC(_)) = record.data() else {
+            continue;
+        };
+        let mut bytes = Vec::new();
+        if data.emit(&mut BinEncoder::new(&mut bytes)).is_err() {
+            continue;
+        }
+        let code = record.record_type();
+        record.set_data(Some(RData::Unknown {
+            code,
+            rdata: NULL::with(bytes),
+        }));
+    }
+}
+
+/// Bytes of base64 `text`, padded or not, with whitespace ignored as in key files
+pub(crate) fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let text = text.trim_end_matches(|c: char| c == '=' || c.is_whitespace());
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    // A single leftover character holds less than a byte
+    (bits < 6).then_some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_read_as_base64_padded_or_not() {
+        assert_eq!(decode_base64("c2VjcmV0").unwrap(), b"secret");
+        assert_eq!(decode_base64("c2VjcmV0IQ==").unwrap(), b"secret!");
+        assert_eq!(decode_base64("c2VjcmV0IQ").unwrap(), b"secret!");
+        assert_eq!(decode_base64("c2Vj\n cmV0").unwrap(), b"secret");
+        assert_eq!(decode_base64("c2VjcmV0I"), None);
+        assert_eq!(decode_base64("c2Vj*mV0"), None);
+    }
+
+    #[test]
+    fn keys_take_the_algorithms_by_their_names() {
+        let key = Key::parse("Transfer.Example.", "HMAC-SHA512.", "c2VjcmV0").unwrap();
+        assert_eq!(key.name(), &Name::from_str("transfer.example.").unwrap());
+        assert_eq!(key.algorithm(), Algorithm::HmacSha512);
+        assert_eq!(key.algorithm().to_string(), "hmac-sha512");
+        let error = Key::parse("key.", "hmac-md5", "c2VjcmV0").err().unwrap();
+        assert!(error.contains("unsupported TSIG algorithm \"hmac-md5\""));
+        let error = Key::parse("key.", "hmac-sha256", "not base64!")
+            .err()
+            .unwrap();
+        assert_eq!(error, "the secret is not valid base64");
+        let error = Key::parse("key.", "hmac-sha256", "").err().unwrap();
+        assert_eq!(error, "the secret is empty");
+    }
+
+    #[cfg(not(feature = "tsig"))]
+    #[test]
+    fn signing_needs_the_feature() {
+        let key = Key::parse("key.", "hmac-sha256", "c2VjcmV0").unwrap();
+        let error = Signer::new(&key).err().unwrap();
+        assert_eq!(error, "TSIG needs resoy built with the tsig feature");
+    }
+
+    #[cfg(feature = "tsig")]
+    mod signed {
+        use std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener},
+            thread,
+            time::Duration,
+        };
+
+        use hickory_client::{
+            op::{MessageType, Query},
+            proto::rr::dnssec::rdata::tsig::{make_tsig_record, message_tbs, TSIG},
+            proto::serialize::binary::{BinDecoder, Restrict},
+            rr::RecordType,
+        };
+
+        use super::*;
+        use crate::tcp::TcpClient;
+
+        fn key(secret: &str) -> Key {
+            Key::parse("transfer.example.", "hmac-sha256", secret).unwrap()
+        }
+
+        fn query() -> Message {
+            let mut message = Message::new();
+            message
+                .set_message_type(MessageType::Query)
+                .add_query(Query::query(
+                    Name::from_str("example.com.").unwrap(),
+                    RecordType::SOA,
+                ));
+            message
+        }
+
+        /// Server checking the signature of one query with `key`, and answering it signed with
+        /// `answer_key`, or unsigned without one
+        fn server(
+            key: Key,
+            answer_key: Option<Key>,
+        ) -> SocketAddr {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut length = [0; 2];
+                stream.read_exact(&mut length).unwrap();
+                let mut query = vec![0; u16::from_be_bytes(length) as usize];
+                stream.read_exact(&mut query).unwrap();
+                let checker = Signer::new(&key).unwrap();
+                let (request_mac, _, _) = checker
+                    .tsigner()
+                    .verify_message_byte(None, &query, true)
+                    .unwrap();
+                let mut response = Message::from_vec(&query).unwrap();
+                response.set_message_type(MessageType::Response);
+                response.take_signature();
+                if let Some(answer_key) = answer_key {
+                    let signer = Signer::new(&answer_key).unwrap();
+                    let now = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs();
+                    let pre_tsig = TSIG::new(
+                        TsigAlgorithm::HmacSha256,
+                        now,
+                        FUDGE,
+                        Vec::new(),
+                        response.id(),
+                        0,
+                        Vec::new(),
+                    );
+                    let tbs =
+                        message_tbs(Some(&request_mac), &response, &pre_tsig, answer_key.name())
+                            .unwrap();
+                    let mac = signer.tsigner().sign(&tbs).unwrap();
+                    response.add_tsig(make_tsig_record(
+                        answer_key.name().clone(),
+                        pre_tsig.set_mac(mac),
+                    ));
+                }
+                let response = response.to_vec().unwrap();
+                let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+                framed.extend_from_slice(&response);
+                stream.write_all(&framed).unwrap();
+            });
+            addr
+        }
+
+        fn client(addr: SocketAddr) -> TcpClient {
+            TcpClient::new(addr, None, Duration::from_secs(5))
+                .with_signer(Some(Signer::new(&key("c2VjcmV0")).unwrap()))
+        }
+
+        #[test]
+        fn signed_responses_are_taken() {
+            let addr = server(key("c2VjcmV0"), Some(key("c2VjcmV0")));
+            let response = client(addr).send(query()).unwrap();
+            assert_eq!(response.message_type(), MessageType::Response);
+        }
+
+        #[test]
+        fn unsigned_responses_are_refused() {
+            let addr = server(key("c2VjcmV0"), None);
+            assert!(client(addr).send(query()).is_err());
+        }
+
+        #[test]
+        fn responses_signed_with_another_secret_are_refused() {
+            let addr = server(key("c2VjcmV0"), Some(key("b3RoZXI=")));
+            assert!(client(addr).send(query()).is_err());
+        }
+
+        #[test]
+        fn dnssec_records_are_kept_as_on_the_wire() {
+            let wire = [1, 1, 3, 8, 3, 1, 0, 1];
+            let mut decoder = BinDecoder::new(&wire);
+            let data = RData::read(
+                &mut decoder,
+                RecordType::DNSKEY,
+                Restrict::new(wire.len() as u16),
+            )
+            .unwrap();
+            assert!(matches!(data, RData::DNSSEC(_)));
+            let mut records = [Record::from_rdata(
+                Name::from_str("example.com.").unwrap(),
+                3600,
+                data,
+            )];
+            raw_dnssec_records(&mut records);
+            let Some(RData::Unknown {
+                code,
+                rdata,
+            }) = records[0].data()
+            else {
+                panic!("DNSKEY left parsed");
+            };
+            assert_eq!(*code, RecordType::DNSKEY);
+            assert_eq!(rdata.anything(), wire);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! TTLs as people write them, e.g. `3600`, `60m` or `1h`, for every input that takes one:
+//! the answers of `--mock-answers`, the timers of SOA records in pin files and of `--expect`,
+//! and the TTLs that `--ttl-units` prints back in the same form.
+//!
+//! A TTL is a whole number of seconds however it is written, so that an expected `1h` and the
+//! `3600` of a live record compare equal.
+
+use serde::Deserialize;
+
+use crate::parse::parse_duration;
+
+/// Units of the TTLs that `format_units` prints, largest first
+const UNITS: [(&str, u32); 3] = [("d", 86400), ("h", 3600), ("m", 60)];
+
+/// TTL given as seconds, or with a unit like `1h` or `2d`, as TOML files hold them
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Ttl {
+    Seconds(u32),
+    Duration(String),
+}
+
+impl Ttl {
+    pub fn seconds(&self) -> Result<u32, String> {
+        match self {
+            Self::Seconds(seconds) => Ok(*seconds),
+            Self::Duration(value) => parse(value),
+        }
+    }
+}
+
+/// Parse a TTL in seconds, e.g. `300`, or with a unit of `parse_duration`, e.g. `5m`
+pub fn parse(value: &str) -> Result<u32, String> {
+    let duration =
+        parse_duration(value).map_err(|error| format!("invalid ttl {:?}: {}", value, error))?;
+    match duration.subsec_nanos() {
+        0 => u32::try_from(duration.as_secs()).map_err(|_| format!("ttl {:?} is too long", value)),
+        _ => Err(format!("ttl {:?} is not a whole number of seconds", value)),
+    }
+}
+
+/// `seconds` in the largest unit that divides them, e.g. `2h` for 7200 and `90m` for 5400,
+/// as `parse` reads it back
+pub fn format_units(seconds: u32) -> String {
+    UNITS
+        .iter()
+        .find(|(_, length)| seconds != 0 && seconds.is_multiple_of(*length))
+        .map(|(unit, length)| format!("{}{}", seconds / length, unit))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
+
+/// The data of an SOA record as resoy prints it, its refresh, retry, expire and minimum given
+/// in seconds whichever unit they were written with, and its other words left alone
+pub fn soa_in_seconds(data: &str) -> String {
+    data.split_whitespace()
+        .enumerate()
+        .map(|(index, word)| match (index, parse(word)) {
+            (3..=6, Ok(seconds)) => seconds.to_string(),
+            _ => word.to_owned(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttls_are_read_in_seconds_or_with_any_unit() {
+        let cases = [
+            ("3600", 3600),
+            ("3600s", 3600),
+            ("60m", 3600),
+            ("1h", 3600),
+            ("3600000ms", 3600),
+            ("1.5h", 5400),
+            ("2d", 172800),
+            ("0.25d", 21600),
+            ("0", 0),
+        ];
+        for (value, seconds) in cases {
+            assert_eq!(parse(value), Ok(seconds), "{}", value);
+        }
+        assert_eq!(
+            parse("1500ms"),
+            Err(String::from(
+                "ttl \"1500ms\" is not a whole number of seconds"
+            ))
+        );
+        assert_eq!(
+            parse("50000d"),
+            Err(String::from("ttl \"50000d\" is too long"))
+        );
+        assert!(parse("1w")
+            .unwrap_err()
+            .starts_with("invalid ttl \"1w\": unknown unit"));
+    }
+
+    #[test]
+    fn ttls_are_printed_in_the_largest_unit_dividing_them() {
+        let cases = [
+            (0, "0s"),
+            (59, "59s"),
+            (60, "1m"),
+            (5400, "90m"),
+            (3600, "1h"),
+            (86400, "1d"),
+            (90000, "25h"),
+            (3601, "3601s"),
+        ];
+        for (seconds, formatted) in cases {
+            assert_eq!(format_units(seconds), formatted);
+            assert_eq!(parse(formatted), Ok(seconds), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn timers_of_soa_records_compare_in_seconds() {
+        assert_eq!(
+            soa_in_seconds("ns1.example.com. hostmaster.example.com. 1 2h 1h 14d 10m"),
+            "ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 600"
+        );
+        // The serial is no TTL, and words that are none are left alone
+        assert_eq!(
+            soa_in_seconds("ns1.example.com. hostmaster 1d 2h x  1h"),
+            "ns1.example.com. hostmaster 1d 7200 x 3600"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use hickory_client::{
+    op::Message,
+    rr::{rdata::SOA, RData, Record, RecordType},
+};
+
+use crate::verbosity::{self, Verbosity};
+
+/// Bounds that `--ttl-min` and `--ttl-max` of the proxy put on the TTLs it relays
+pub struct TtlBounds {
+    min: Option<u32>,
+    max: Option<u32>,
+    /// also bound SOA records, whose TTL and minimum tell for how long to cache negative answers
+    negative: bool,
+    /// whether the warning about signed answers was printed, it is only worth telling once
+    warned_signed: AtomicBool,
+}
+
+impl TtlBounds {
+    pub fn new(
+        min: Option<u32>,
+        max: Option<u32>,
+        negative: bool,
+    ) -> Self {
+        Self {
+            min,
+            max,
+            negative,
+            warned_signed: AtomicBool::new(false),
+        }
+    }
+
+    /// `ttl` within the bounds, the maximum winning when the minimum is above it
+    fn bound(
+        &self,
+        ttl: u32,
+    ) -> u32 {
+        let ttl = self.min.map_or(ttl, |min| ttl.max(min));
+        self.max.map_or(ttl, |max| ttl.min(max))
+    }
+
+    /// Rewrite the TTLs of the records of every section of `message` in place, keeping their
+    /// order
+    pub fn apply(
+        &self,
+        message: &mut Message,
+    ) {
+        if self.min.is_none() && self.max.is_none() {
+            return;
+        }
+        let rewritten = self.rewrite_all(message.answers_mut())
+            + self.rewrite_all(message.name_servers_mut())
+            + self.rewrite_all(message.additionals_mut());
+
+        let signed = message
+            .answers()
+            .iter()
+            .chain(message.name_servers())
+            .chain(message.additionals())
+            .any(|record| record.record_type() == RecordType::RRSIG);
+        if rewritten > 0 && signed && !self.warned_signed.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "WARNING: rewriting the TTLs of signed answers breaks their validation \
+                 downstream, as the signatures cover the original TTLs"
+            );
+        }
+    }
+
+    /// How many of the records were rewritten
+    fn rewrite_all(
+        &self,
+        records: &mut [Record],
+    ) -> usize {
+        records
+            .iter_mut()
+            .map(|record| usize::from(self.rewrite(record)))
+            .sum()
+    }
+
+    /// Whether the TTL of the record, or the minimum of an SOA record, had to change
+    fn rewrite(
+        &self,
+        record: &mut Record,
+    ) -> bool {
+        let soa = match record.data() {
+            Some(RData::SOA(soa)) if self.negative => Some(soa.clone()),
+            Some(RData::SOA(_)) => return false,
+            _ => None,
+        };
+        let ttl = record.ttl();
+        let bounded = self.bound(ttl);
+        let mut changed = bounded != ttl;
+        if changed {
+            if verbosity::at_least(Verbosity::Verbose) {
+                eprintln!(
+                    "ttl of {} {} rewritten from {} to {}",
+                    record.name(),
+                    record.record_type(),
+                    ttl,
+                    bounded
+                );
+            }
+            record.set_ttl(bounded);
+        }
+
+        if let Some(soa) = soa {
+            let minimum = self.bound(soa.minimum());
+            if minimum != soa.minimum() {
+                if verbosity::at_least(Verbosity::Verbose) {
+                    eprintln!(
+                        "minimum of {} SOA rewritten from {} to {}",
+                        record.name(),
+                        soa.minimum(),
+                        minimum
+                    );
+                }
+                record.set_data(Some(RData::SOA(SOA::new(
+                    soa.mname().clone(),
+                    soa.rname().clone(),
+                    soa.serial(),
+                    soa.refresh(),
+                    soa.retry(),
+                    soa.expire(),
+                    minimum,
+                ))));
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::rr::{
+        rdata::{A, NULL},
+        Name,
+    };
+
+    use super::*;
+
+    fn name() -> Name {
+        Name::from_str("example.com.").unwrap()
+    }
+
+    fn a(ttl: u32) -> Record {
+        Record::from_rdata(name(), ttl, RData::A(A::new(192, 0, 2, 1)))
+    }
+
+    fn soa(
+        ttl: u32,
+        minimum: u32,
+    ) -> Record {
+        let soa = SOA::new(name(), name(), 1, 7200, 3600, 1209600, minimum);
+        Record::from_rdata(name(), ttl, RData::SOA(soa))
+    }
+
+    fn rrsig() -> Record {
+        let data = RData::Unknown {
+            code: RecordType::RRSIG,
+            rdata: NULL::with(vec![0; 18]),
+        };
+        Record::from_rdata(name(), 300, data)
+    }
+
+    fn ttls(records: &[Record]) -> Vec<u32> {
+        records.iter().map(Record::ttl).collect()
+    }
+
+    fn minimum(record: &Record) -> u32 {
+        match record.data() {
+            Some(RData::SOA(soa)) => soa.minimum(),
+            _ => panic!("not an SOA record"),
+        }
+    }
+
+    #[test]
+    fn ttls_of_every_section_are_clamped_to_the_bounds() {
+        let bounds = TtlBounds::new(Some(60), Some(3600), false);
+        let mut message = Message::new();
+        message
+            .add_answers([a(5), a(300), a(86400)])
+            .add_additional(a(10));
+        bounds.apply(&mut message);
+        assert_eq!(ttls(message.answers()), [60, 300, 3600]);
+        assert_eq!(ttls(message.additionals()), [60]);
+    }
+
+    #[test]
+    fn the_maximum_wins_over_a_minimum_above_it() {
+        let bounds = TtlBounds::new(Some(600), Some(60), false);
+        let mut message = Message::new();
+        message.add_answers([a(5), a(300)]);
+        bounds.apply(&mut message);
+        assert_eq!(ttls(message.answers()), [60, 60]);
+    }
+
+    #[test]
+    fn soa_records_are_left_alone_unless_negative_answers_are_rewritten_too() {
+        let mut message = Message::new();
+        message.add_name_server(soa(86400, 5));
+        TtlBounds::new(Some(60), Some(3600), false).apply(&mut message);
+        assert_eq!(message.name_servers()[0].ttl(), 86400);
+        assert_eq!(minimum(&message.name_servers()[0]), 5);
+
+        TtlBounds::new(Some(60), Some(3600), true).apply(&mut message);
+        assert_eq!(message.name_servers()[0].ttl(), 3600);
+        assert_eq!(minimum(&message.name_servers()[0]), 60);
+    }
+
+    #[test]
+    fn rewriting_signed_answers_warns_once() {
+        let bounds = TtlBounds::new(Some(60), None, false);
+        let mut unsigned = Message::new();
+        unsigned.add_answer(a(5));
+        bounds.apply(&mut unsigned);
+        assert!(!bounds.warned_signed.load(Ordering::Relaxed));
+
+        let mut signed = Message::new();
+        signed.add_answers([a(300), rrsig()]);
+        bounds.apply(&mut signed);
+        assert!(!bounds.warned_signed.load(Ordering::Relaxed));
+
+        signed.add_answer(a(5));
+        bounds.apply(&mut signed);
+        assert!(bounds.warned_signed.load(Ordering::Relaxed));
+    }
+}
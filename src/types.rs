@@ -0,0 +1,398 @@
+use std::ffi::OsStr;
+
+use clap::{
+    builder::{PossibleValue, StringValueParser, TypedValueParser},
+    Arg, Args, Command,
+};
+use hickory_client::rr::RecordType;
+use serde::Serialize;
+
+use crate::{outln, output, schema, AppError};
+
+/// Row of the record type table printed by `resoy types`
+#[derive(Serialize)]
+pub struct TypeInfo {
+    #[serde(rename = "type", serialize_with = "serialize_record_type")]
+    pub record_type: RecordType,
+    pub value: u16,
+    pub summary: &'static str,
+    pub details: &'static str,
+    pub example: &'static str,
+    /// resoy renders this type with a type-aware formatter
+    pub pretty: bool,
+}
+
+fn serialize_record_type<S: serde::Serializer>(
+    record_type: &RecordType,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(record_type)
+}
+
+macro_rules! type_info {
+    ($record_type:ident, $summary:expr, $details:expr, $example:expr, $pretty:expr) => {
+        TypeInfo {
+            record_type: RecordType::$record_type,
+            value: u16::from(RecordType::$record_type),
+            summary: $summary,
+            details: $details,
+            example: $example,
+            pretty: $pretty,
+        }
+    };
+}
+
+/// Every record type resoy can query
+pub fn table() -> Vec<TypeInfo> {
+    vec![
+        type_info!(
+            A,
+            "IPv4 address",
+            "Maps a name to a 32-bit IPv4 address. The most common record type; a name may \
+             have several A records for load balancing.",
+            "resoy example.com A",
+            false
+        ),
+        type_info!(
+            AAAA,
+            "IPv6 address",
+            "Maps a name to a 128-bit IPv6 address. Hosts reachable over IPv6 publish AAAA \
+             records next to their A records.",
+            "resoy example.com AAAA",
+            false
+        ),
+        type_info!(
+            ANAME,
+            "Apex alias (draft)",
+            "A CNAME-like alias that is allowed at the zone apex. Servers supporting it answer \
+             with the addresses of the target instead.",
+            "resoy example.com ANAME",
+            false
+        ),
+        type_info!(
+            ANY,
+            "All records (RFC 8482 limited)",
+            "Asks for all records of a name. Most servers now answer with a minimal response, \
+             often a single HINFO record, as allowed by RFC 8482.",
+            "resoy example.com ANY",
+            false
+        ),
+        type_info!(
+            CAA,
+            "Certification authority authorization",
+            "Lists the certificate authorities allowed to issue certificates for the domain, \
+             and where to report violations (iodef).",
+            "resoy example.com CAA",
+            false
+        ),
+        type_info!(
+            CDNSKEY,
+            "Child copy of DNSKEY",
+            "Published by a child zone to signal the parent which DNSKEY should be \
+             referenced by the DS record (RFC 7344).",
+            "resoy example.com CDNSKEY",
+            false
+        ),
+        type_info!(
+            CDS,
+            "Child copy of DS",
+            "Published by a child zone to signal the parent which DS record it should \
+             publish, enabling automated key rollovers (RFC 7344).",
+            "resoy example.com CDS",
+            false
+        ),
+        type_info!(
+            CNAME,
+            "Canonical name (alias)",
+            "Declares the name an alias of another name. Resolvers restart the lookup at the \
+             target; a CNAME cannot coexist with other records of the same name.",
+            "resoy www.example.com CNAME",
+            false
+        ),
+        type_info!(
+            CSYNC,
+            "Child-to-parent synchronization",
+            "Lets a child zone ask the parent to copy its NS and glue records (RFC 7477).",
+            "resoy example.com CSYNC",
+            false
+        ),
+        type_info!(
+            DNSKEY,
+            "DNSSEC public key",
+            "Holds the public keys used to verify RRSIG signatures of the zone. The key \
+             signing key is referenced by the DS record at the parent.",
+            "resoy example.com DNSKEY",
+            false
+        ),
+        type_info!(
+            DS,
+            "Delegation signer",
+            "Published in the parent zone, holds the digest of the child's key signing key \
+             and links the DNSSEC chain of trust.",
+            "resoy example.com DS",
+            false
+        ),
+        type_info!(
+            HINFO,
+            "Host information",
+            "Describes CPU and operating system of a host. Rarely used today, except as the \
+             minimal answer to ANY queries.",
+            "resoy example.com HINFO",
+            false
+        ),
+        type_info!(
+            HTTPS,
+            "HTTPS service binding",
+            "Tells HTTPS clients about alternative endpoints, supported protocols (ALPN), \
+             address hints and ECH configuration of a service (RFC 9460).",
+            "resoy example.com HTTPS",
+            false
+        ),
+        type_info!(
+            KEY,
+            "Security key (legacy)",
+            "Legacy public key record, today used for SIG(0) transaction signatures.",
+            "resoy example.com KEY",
+            false
+        ),
+        type_info!(
+            MX,
+            "Mail exchange",
+            "Names the servers accepting email for the domain, each with a preference; lower \
+             values are tried first. A single MX with the root as target (null MX) means the \
+             domain accepts no email.",
+            "resoy example.com MX",
+            false
+        ),
+        type_info!(
+            NAPTR,
+            "Naming authority pointer",
+            "Rewrites names with regular expressions, used by ENUM and SIP to discover \
+             services for phone numbers and URIs.",
+            "resoy 4.3.2.1.5.5.5.e164.arpa NAPTR",
+            false
+        ),
+        type_info!(
+            NS,
+            "Name server",
+            "Names the authoritative servers of a zone. NS records at the parent delegate the \
+             zone; NS records at the apex should match them.",
+            "resoy example.com NS",
+            false
+        ),
+        type_info!(
+            NSEC,
+            "Next secure record",
+            "Proves the non-existence of names and types in a DNSSEC signed zone by linking \
+             to the next existing name.",
+            "resoy example.com NSEC",
+            false
+        ),
+        type_info!(
+            NSEC3,
+            "Hashed next secure record",
+            "Like NSEC but with hashed owner names, which makes zone walking harder.",
+            "resoy example.com NSEC3",
+            false
+        ),
+        type_info!(
+            NSEC3PARAM,
+            "NSEC3 parameters",
+            "Holds the hash algorithm, iterations and salt used for the NSEC3 chain of the \
+             zone.",
+            "resoy example.com NSEC3PARAM",
+            false
+        ),
+        type_info!(
+            NULL,
+            "Null record (experimental)",
+            "Holds arbitrary binary data of up to 65535 bytes. Experimental, not used in \
+             zone files.",
+            "resoy example.com NULL",
+            false
+        ),
+        type_info!(
+            OPENPGPKEY,
+            "OpenPGP public key",
+            "Publishes the OpenPGP key of an email address under a hashed local part (RFC \
+             7929).",
+            "resoy example.com OPENPGPKEY",
+            false
+        ),
+        type_info!(
+            PTR,
+            "Pointer (reverse lookup)",
+            "Maps a name to another name, mostly used below in-addr.arpa and ip6.arpa to map \
+             addresses back to host names.",
+            "resoy 1.1.1.1.in-addr.arpa PTR",
+            false
+        ),
+        type_info!(
+            RRSIG,
+            "DNSSEC signature",
+            "Holds the signature over an RRset, with its validity period and the key tag of \
+             the signing DNSKEY.",
+            "resoy example.com RRSIG",
+            false
+        ),
+        type_info!(
+            SIG,
+            "Signature (legacy)",
+            "Legacy signature record, today used for SIG(0) transaction signatures.",
+            "resoy example.com SIG",
+            false
+        ),
+        type_info!(
+            SOA,
+            "Start of authority",
+            "Marks the apex of a zone and holds its primary server, responsible mailbox, \
+             serial and timers; the minimum field is the negative caching TTL.",
+            "resoy example.com SOA",
+            false
+        ),
+        type_info!(
+            SRV,
+            "Service locator",
+            "Names the host and port of a service, with priority and weight for failover and \
+             load balancing. Queried at _service._proto names.",
+            "resoy _xmpp-server._tcp.example.com SRV",
+            false
+        ),
+        type_info!(
+            SSHFP,
+            "SSH host key fingerprint",
+            "Publishes fingerprints of SSH host keys so clients can verify hosts through \
+             DNSSEC (RFC 4255).",
+            "resoy example.com SSHFP",
+            false
+        ),
+        type_info!(
+            SVCB,
+            "Service binding",
+            "Generic form of HTTPS records for other protocols, describing endpoints and \
+             their parameters (RFC 9460).",
+            "resoy _dns.example.com SVCB",
+            false
+        ),
+        type_info!(
+            TLSA,
+            "TLS certificate association",
+            "Pins the certificate or public key of a TLS service for DANE, queried at \
+             _port._proto names (RFC 6698).",
+            "resoy _443._tcp.example.com TLSA",
+            false
+        ),
+        type_info!(
+            TXT,
+            "Text",
+            "Holds one or more character strings. Used for SPF, DKIM and DMARC email \
+             policies and for domain ownership verification tokens.",
+            "resoy example.com TXT",
+            false
+        ),
+    ]
+}
+
+/// Parser of record type arguments, which takes any name and leaves it to `RecordType` to
+/// tell whether it is known, offering the types of the table for shell completion
+#[derive(Clone)]
+pub struct RecordTypeParser {
+    /// whether `all` and `common` are offered as well
+    common: bool,
+}
+
+impl RecordTypeParser {
+    /// Offering the types of the table, and with `common` also `all` and `common` for the
+    /// queries of the common types
+    pub fn new(common: bool) -> Self {
+        Self {
+            common,
+        }
+    }
+}
+
+impl TypedValueParser for RecordTypeParser {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        let common = [
+            PossibleValue::new("all").help("the common record types"),
+            PossibleValue::new("common").help("the common record types"),
+        ];
+        let types = table()
+            .into_iter()
+            .map(|info| PossibleValue::new(<&str>::from(info.record_type)).help(info.summary));
+        Some(Box::new(
+            common.into_iter().filter(|_| self.common).chain(types),
+        ))
+    }
+}
+
+#[derive(Args)]
+pub struct TypesArgs {
+    /// record type to describe in detail
+    #[arg(value_parser = RecordTypeParser::new(false), hide_possible_values = true)]
+    record_type: Option<String>,
+
+    /// print the table as JSON
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+impl TypesArgs {
+    /// Print the record type table or the details of a single type
+    pub fn run(&self) -> Result<(), AppError> {
+        let table = table();
+
+        let Some(requested) = &self.record_type else {
+            if self.json {
+                let json = schema::types(&table);
+                outln!("{}", serde_json::to_string_pretty(&json).unwrap());
+                return Ok(());
+            }
+            let _ = output::record(|out| {
+                for info in &table {
+                    writeln!(
+                        out,
+                        "{:<10} {:>5} {:<6} {}",
+                        info.record_type.to_string(),
+                        info.value,
+                        if info.pretty { "pretty" } else { "-" },
+                        info.summary
+                    )?;
+                }
+                Ok(())
+            });
+            return Ok(());
+        };
+
+        let info = table
+            .iter()
+            .find(|info| info.record_type.to_string().eq_ignore_ascii_case(requested))
+            .ok_or_else(|| AppError::UnknownRecordType(requested.clone()))?;
+
+        if self.json {
+            let json = schema::versioned(info);
+            outln!("{}", serde_json::to_string_pretty(&json).unwrap());
+        } else {
+            outln!(
+                "{} ({}): {}\n\n{}\n\nExample: {}",
+                info.record_type,
+                info.value,
+                info.summary,
+                info.details,
+                info.example
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    env, fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::{outln, reputation};
+
+/// Version of the running binary
+const RUNNING: &str = env!("CARGO_PKG_VERSION");
+
+/// Latest release of resoy on GitHub
+const RELEASES_URL: &str = "https://api.github.com/repos/1ort/resoy/releases/latest";
+
+/// Timeout of the release lookup, short as it is never worth waiting for
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Environment variable that enables the passive check
+const PASSIVE_VARIABLE: &str = "RESOY_UPDATE_CHECK";
+
+/// Time between two passive checks
+const PASSIVE_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+/// Major, minor and patch of a version such as `v1.2.3`, without any pre-release suffix
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse().ok());
+    Some((
+        parts.next()??,
+        parts.next()??,
+        parts.next().unwrap_or(Some(0))?,
+    ))
+}
+
+/// Latest release on GitHub
+fn latest() -> Result<Release, String> {
+    let agent = ureq::AgentBuilder::new().timeout(UPDATE_TIMEOUT).build();
+    agent
+        .get(RELEASES_URL)
+        .set("user-agent", concat!("resoy/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .map_err(|error| error.to_string())
+        .and_then(|response| {
+            serde_json::from_reader::<_, Release>(response.into_reader())
+                .map_err(|error| error.to_string())
+        })
+}
+
+/// The latest release when it is newer than the running one, `Ok(None)` when it is not
+fn newer() -> Result<Option<Release>, String> {
+    let release = latest()?;
+    match (parse_version(&release.tag_name), parse_version(RUNNING)) {
+        (Some(latest), Some(current)) => Ok((latest > current).then_some(release)),
+        _ => Err(format!("cannot compare {:?}", release.tag_name)),
+    }
+}
+
+/// Notice of `release`, newer than the running one
+fn notice(release: &Release) -> String {
+    format!(
+        "resoy {} is available, this is {}: {}",
+        release.tag_name.trim_start_matches('v'),
+        RUNNING,
+        release.html_url
+    )
+}
+
+/// Tell whether a newer release than the running one is out, for `--check-update`.
+///
+/// Nothing is printed when the release can't be looked up, e.g. without network; `verbose`
+/// tells why instead. Asked for explicitly, the check runs in CI as well.
+pub fn check(verbose: bool) {
+    match newer() {
+        Ok(Some(release)) => outln!("{}", notice(&release)),
+        Ok(None) => outln!("resoy {} is up to date", RUNNING),
+        Err(error) => {
+            if verbose {
+                eprintln!("update check failed: {}", error);
+            }
+        },
+    }
+}
+
+/// Whether the passive check is enabled by the user, with `RESOY_UPDATE_CHECK=weekly`
+pub fn passive_enabled() -> bool {
+    env::var_os(PASSIVE_VARIABLE).is_some_and(|value| value == "weekly")
+}
+
+/// Tell on stderr when a newer release is out, at most once a week, for the runs where
+/// `passive_enabled`.
+///
+/// The time of the last check is kept in `resoy/update-check` under the cache directory,
+/// written before the lookup so that a run without network doesn't try again on the next.
+/// Nothing but the notice is ever printed, `verbose` aside, and never in CI.
+pub fn check_weekly(verbose: bool) {
+    let skipped = |reason: &str| {
+        if verbose {
+            eprintln!("update check skipped: {}", reason);
+        }
+    };
+    if env::var_os("CI").is_some() {
+        return skipped("in CI");
+    }
+    let Some(path) = reputation::cache_file("update-check") else {
+        return skipped("no cache directory to remember it in");
+    };
+    let now = SystemTime::now();
+    let checked = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| content.trim().parse().ok())
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds));
+    let since = checked.and_then(|checked| now.duration_since(checked).ok());
+    if since.is_some_and(|since| since < PASSIVE_INTERVAL) {
+        return skipped("checked less than a week ago");
+    }
+    let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, format!("{}\n", seconds)));
+    if let Err(error) = written {
+        return skipped(&format!("cannot write {}: {}", path.display(), error));
+    }
+    match newer() {
+        Ok(Some(release)) => eprintln!("{}", notice(&release)),
+        Ok(None) => {},
+        Err(error) => {
+            if verbose {
+                eprintln!("update check failed: {}", error);
+            }
+        },
+    }
+}
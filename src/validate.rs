@@ -0,0 +1,1183 @@
+#[cfg(feature = "dnssec")]
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    rc::Rc,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hickory_client::rr::Record;
+#[cfg(feature = "dnssec")]
+use hickory_client::{
+    op::DnsResponse,
+    rr::{Name, RecordType},
+    serialize::binary::{BinDecodable, BinDecoder, BinEncodable, BinEncoder, EncodeMode},
+};
+#[cfg(feature = "dnssec")]
+use ring::{digest, signature};
+
+use crate::format::OutputConfig;
+#[cfg(feature = "dnssec")]
+use crate::{
+    format::format_timestamp,
+    rollover::{key_tag, raw_data, REVOKED, ZONE_KEY},
+    DnsClient,
+};
+
+#[cfg(feature = "dnssec")]
+/// Keys of the root zone, as the DS records IANA publishes
+const ROOT_ANCHORS: &[&str] = &[
+    ". 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    ". 38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+#[cfg(feature = "dnssec")]
+/// Algorithms whose signatures are checked, see RFC 8624
+const ALGORITHMS: &[u8] = &[5, 7, 8, 10, 13, 14, 15];
+
+/// Outcome of the validation of a record
+#[derive(Clone)]
+#[cfg_attr(not(feature = "dnssec"), allow(dead_code))]
+pub enum Verdict {
+    /// signed all the way down from a trust anchor, the one given
+    Secure(String),
+    /// in a zone that is not signed, or signed in a way that cannot be checked
+    Insecure(String),
+    /// in a signed zone, without a signature that holds
+    Bogus(String),
+}
+
+impl Verdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Secure(_) => "secure",
+            Self::Insecure(_) => "insecure",
+            Self::Bogus(_) => "bogus",
+        }
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Secure(_) => None,
+            Self::Insecure(reason) | Self::Bogus(reason) => Some(reason),
+        }
+    }
+
+    /// Trust anchor the chain of a secure record starts from, e.g. `. DS 20326`
+    pub fn anchor(&self) -> Option<&str> {
+        match self {
+            Self::Secure(anchor) => Some(anchor),
+            Self::Insecure(_) | Self::Bogus(_) => None,
+        }
+    }
+
+    /// Note after the record, red for bogus ones when colors are enabled
+    pub fn annotation(
+        &self,
+        output_config: &OutputConfig,
+    ) -> String {
+        match self {
+            Self::Secure(anchor) => format!("  [secure from {}]", anchor),
+            Self::Insecure(reason) => format!("  [insecure: {}]", reason),
+            Self::Bogus(reason) => {
+                format!(
+                    "  {}",
+                    output_config.format_alert(&format!("[bogus: {}]", reason))
+                )
+            },
+        }
+    }
+}
+
+/// Verdicts of the answers of a run, none without `--validate`
+#[derive(Default)]
+pub struct Verdicts<'r> {
+    verdicts: Vec<(&'r Record, Verdict)>,
+}
+
+impl Verdicts<'_> {
+    /// Verdict of a record of the answers
+    pub fn get(
+        &self,
+        record: &Record,
+    ) -> Option<&Verdict> {
+        self.verdicts
+            .iter()
+            .find(|(answer, _)| std::ptr::eq(*answer, record))
+            .map(|(_, verdict)| verdict)
+    }
+
+    /// How many records are bogus
+    pub fn bogus(&self) -> usize {
+        self.verdicts
+            .iter()
+            .filter(|(_, verdict)| matches!(verdict, Verdict::Bogus(_)))
+            .count()
+    }
+}
+
+#[cfg(feature = "dnssec")]
+#[derive(Clone)]
+struct Ds {
+    tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: Vec<u8>,
+}
+
+#[cfg(feature = "dnssec")]
+impl Ds {
+    fn parse(data: &[u8]) -> Option<Self> {
+        Some(Self {
+            tag: u16::from_be_bytes([*data.first()?, *data.get(1)?]),
+            algorithm: *data.get(2)?,
+            digest_type: *data.get(3)?,
+            digest: data.get(4..)?.to_vec(),
+        })
+    }
+
+    fn digest_algorithm(&self) -> Option<&'static digest::Algorithm> {
+        match self.digest_type {
+            1 => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+            2 => Some(&digest::SHA256),
+            4 => Some(&digest::SHA384),
+            _ => None,
+        }
+    }
+
+    fn is_supported(&self) -> bool {
+        ALGORITHMS.contains(&self.algorithm) && self.digest_algorithm().is_some()
+    }
+
+    /// Whether `key` of `zone` is the one of the DS
+    fn matches(
+        &self,
+        zone: &Name,
+        key: &Key,
+    ) -> bool {
+        let Some(algorithm) = self.digest_algorithm() else {
+            return false;
+        };
+        if key.tag != self.tag || key.algorithm != self.algorithm {
+            return false;
+        }
+        let mut context = digest::Context::new(algorithm);
+        context.update(&canonical_name(zone));
+        context.update(&key.data);
+        context.finish().as_ref() == self.digest.as_slice()
+    }
+}
+
+/// How the key of a trust anchor is known: by its digest, or as the whole key
+#[cfg(feature = "dnssec")]
+#[derive(Clone)]
+enum Proof {
+    Ds(Ds),
+    /// the DNSKEY data of the key
+    Key(Vec<u8>),
+}
+
+#[cfg(feature = "dnssec")]
+impl Proof {
+    fn tag(&self) -> u16 {
+        match self {
+            Self::Ds(ds) => ds.tag,
+            Self::Key(data) => key_tag(data),
+        }
+    }
+
+    fn is_supported(&self) -> bool {
+        match self {
+            Self::Ds(ds) => ds.is_supported(),
+            Self::Key(data) => data
+                .get(3)
+                .is_some_and(|algorithm| ALGORITHMS.contains(algorithm)),
+        }
+    }
+
+    /// Whether `key` of `zone` is the one proven
+    fn matches(
+        &self,
+        zone: &Name,
+        key: &Key,
+    ) -> bool {
+        match self {
+            Self::Ds(ds) => ds.matches(zone, key),
+            Self::Key(data) => *data == key.data,
+        }
+    }
+
+    /// The proof as verdicts name it, e.g. `. DS 20326`
+    fn describe(
+        &self,
+        zone: &Name,
+    ) -> String {
+        let kind = match self {
+            Self::Ds(_) => "DS",
+            Self::Key(_) => "DNSKEY",
+        };
+        format!("{} {} {}", zone, kind, self.tag())
+    }
+}
+
+/// Key the chain of trust of `--validate` starts from, given as a DS or DNSKEY record
+#[cfg(feature = "dnssec")]
+#[derive(Clone)]
+pub struct TrustAnchor {
+    zone: Name,
+    proof: Proof,
+}
+
+#[cfg(feature = "dnssec")]
+impl TrustAnchor {
+    /// Anchor of the key of `zone` whose DNSKEY data is `data`
+    pub fn from_key(
+        zone: &Name,
+        data: Vec<u8>,
+    ) -> Self {
+        let mut zone = zone.to_lowercase();
+        zone.set_fqdn(true);
+        Self {
+            zone,
+            proof: Proof::Key(data),
+        }
+    }
+
+    pub fn zone(&self) -> &Name {
+        &self.zone
+    }
+
+    pub fn tag(&self) -> u16 {
+        self.proof.tag()
+    }
+
+    /// Whether the anchor is a key whose revoke bit is set, which RFC 5011 says not to trust
+    pub fn revoked(&self) -> bool {
+        match &self.proof {
+            Proof::Ds(_) => false,
+            Proof::Key(data) => {
+                data.len() >= 2 && u16::from_be_bytes([data[0], data[1]]) & REVOKED != 0
+            },
+        }
+    }
+}
+
+#[cfg(feature = "dnssec")]
+impl Display for TrustAnchor {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(&self.proof.describe(&self.zone))
+    }
+}
+
+/// A trust anchor written like a DS record, `zone tag algorithm digest-type digest`
+#[cfg(feature = "dnssec")]
+pub fn parse_trust_anchor(text: &str) -> Result<TrustAnchor, String> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let [zone, tag, algorithm, digest_type, digest @ ..] = fields.as_slice() else {
+        return Err(String::from(
+            "expected a DS record like \"example. 12345 13 2 <digest>\"",
+        ));
+    };
+    let number = |field: &str, what: &str| {
+        field
+            .parse::<u16>()
+            .map_err(|_| format!("invalid {} {:?}", what, field))
+    };
+    let byte = |field: &str, what: &str| {
+        u8::try_from(number(field, what)?).map_err(|_| format!("invalid {} {:?}", what, field))
+    };
+    let mut zone = Name::from_str(zone).map_err(|_| format!("invalid zone {:?}", zone))?;
+    zone.set_fqdn(true);
+    let digest: String = digest.concat();
+    let digest = (0..digest.len())
+        .step_by(2)
+        .map(|index| {
+            digest
+                .get(index..index + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .filter(|digest| !digest.is_empty())
+        .ok_or_else(|| format!("invalid digest {:?}", digest))?;
+    Ok(TrustAnchor {
+        zone: zone.to_lowercase(),
+        proof: Proof::Ds(Ds {
+            tag: number(tag, "key tag")?,
+            algorithm: byte(algorithm, "algorithm")?,
+            digest_type: byte(digest_type, "digest type")?,
+            digest,
+        }),
+    })
+}
+
+/// The keys of the root zone
+#[cfg(feature = "dnssec")]
+pub fn root_anchors() -> Vec<TrustAnchor> {
+    ROOT_ANCHORS
+        .iter()
+        .map(|anchor| parse_trust_anchor(anchor).unwrap())
+        .collect()
+}
+
+#[cfg(feature = "dnssec")]
+#[derive(Clone)]
+struct Key {
+    tag: u16,
+    algorithm: u8,
+    public_key: Vec<u8>,
+    /// the whole DNSKEY data, which DS digests cover
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "dnssec")]
+impl Key {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let flags = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+        if flags & ZONE_KEY == 0 {
+            return None;
+        }
+        Some(Self {
+            tag: key_tag(data),
+            algorithm: *data.get(3)?,
+            public_key: data.get(4..)?.to_vec(),
+            data: data.to_vec(),
+        })
+    }
+
+    fn verify(
+        &self,
+        message: &[u8],
+        signature: &[u8],
+    ) -> bool {
+        let key = self.public_key.as_slice();
+        let ecdsa = |algorithm: &'static signature::EcdsaVerificationAlgorithm| {
+            // Keys are the bare coordinates, without the point format of SEC 1
+            let key = [&[0x04], key].concat();
+            signature::UnparsedPublicKey::new(algorithm, key)
+                .verify(message, signature)
+                .is_ok()
+        };
+        match self.algorithm {
+            5 | 7 => rsa(
+                &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+                key,
+                message,
+                signature,
+            ),
+            8 => rsa(
+                &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                key,
+                message,
+                signature,
+            ),
+            10 => rsa(
+                &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+                key,
+                message,
+                signature,
+            ),
+            13 => ecdsa(&signature::ECDSA_P256_SHA256_FIXED),
+            14 => ecdsa(&signature::ECDSA_P384_SHA384_FIXED),
+            15 => signature::UnparsedPublicKey::new(&signature::ED25519, key)
+                .verify(message, signature)
+                .is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Check an RSA signature with a key in the format of RFC 3110
+#[cfg(feature = "dnssec")]
+fn rsa(
+    parameters: &'static signature::RsaParameters,
+    key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> bool {
+    let (exponent_length, key) = match key {
+        [0, high, low, key @ ..] => (usize::from(u16::from_be_bytes([*high, *low])), key),
+        [length, key @ ..] => (usize::from(*length), key),
+        [] => return false,
+    };
+    if key.len() <= exponent_length {
+        return false;
+    }
+    let (e, n) = key.split_at(exponent_length);
+    signature::RsaPublicKeyComponents {
+        n,
+        e,
+    }
+    .verify(parameters, message, signature)
+    .is_ok()
+}
+
+#[cfg(feature = "dnssec")]
+struct Signature<'a> {
+    covered: RecordType,
+    algorithm: u8,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    tag: u16,
+    signer: Name,
+    /// the fixed fields in front of the signer name, the start of the signed data
+    fields: &'a [u8],
+    signature: &'a [u8],
+}
+
+#[cfg(feature = "dnssec")]
+impl<'a> Signature<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let fields = data.get(..18)?;
+        let number = |range: std::ops::Range<usize>| {
+            Some(u32::from_be_bytes(fields.get(range)?.try_into().ok()?))
+        };
+        let mut decoder = BinDecoder::new(&data[18..]);
+        let signer = Name::read(&mut decoder).ok()?;
+        let signature = &data[18 + decoder.index()..];
+        Some(Self {
+            covered: RecordType::from(u16::from_be_bytes([fields[0], fields[1]])),
+            algorithm: fields[2],
+            labels: fields[3],
+            original_ttl: number(4..8)?,
+            expiration: number(8..12)?,
+            inception: number(12..16)?,
+            tag: u16::from_be_bytes([fields[16], fields[17]]),
+            signer,
+            fields,
+            signature,
+        })
+    }
+
+    /// What the signature covers for `records` of `owner`, see RFC 4034 section 3.1.8.1
+    fn signed_data(
+        &self,
+        owner: &Name,
+        records: &[&Record],
+    ) -> Result<Vec<u8>, String> {
+        // Answers synthesized from a wildcard are signed as the wildcard
+        let owner = match self.labels.cmp(&owner.num_labels()) {
+            std::cmp::Ordering::Equal => owner.clone(),
+            std::cmp::Ordering::Less => Name::from_ascii("*")
+                .and_then(|wildcard| wildcard.append_name(&owner.trim_to(usize::from(self.labels))))
+                .map_err(|error| error.to_string())?,
+            std::cmp::Ordering::Greater => {
+                return Err(format!(
+                    "the signature by key {} counts more labels than {}",
+                    self.tag, owner
+                ))
+            },
+        };
+        let owner = canonical_name(&owner);
+        let mut rdatas = records
+            .iter()
+            .map(|record| canonical_rdata(record))
+            .collect::<Option<Vec<Vec<u8>>>>()
+            .ok_or_else(|| format!("cannot encode the records of {}", self.covered))?;
+        rdatas.sort();
+        rdatas.dedup();
+
+        let mut data = self.fields.to_vec();
+        data.extend(canonical_name(&self.signer));
+        for rdata in rdatas {
+            data.extend(&owner);
+            data.extend(u16::from(self.covered).to_be_bytes());
+            data.extend(u16::from(records[0].dns_class()).to_be_bytes());
+            data.extend(self.original_ttl.to_be_bytes());
+            data.extend((rdata.len() as u16).to_be_bytes());
+            data.extend(rdata);
+        }
+        Ok(data)
+    }
+
+    /// Why the signature is not valid at `now`, if it is not
+    fn check_window(
+        &self,
+        now: u32,
+    ) -> Result<(), String> {
+        let time =
+            |seconds: u32| format_timestamp(UNIX_EPOCH + Duration::from_secs(u64::from(seconds)));
+        // Serial number arithmetic of RFC 1982, the fields wrap around in 2106
+        let before = |a: u32, b: u32| (b.wrapping_sub(a) as i32) > 0;
+        if before(self.expiration, now) {
+            return Err(format!(
+                "signature by key {} expired on {}",
+                self.tag,
+                time(self.expiration)
+            ));
+        }
+        if before(now, self.inception) {
+            return Err(format!(
+                "signature by key {} is not valid before {}",
+                self.tag,
+                time(self.inception)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `name` in lowercase and uncompressed, as signatures and digests cover it
+#[cfg(feature = "dnssec")]
+fn canonical_name(name: &Name) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut encoder = BinEncoder::new(&mut buffer);
+    let _ = name.to_lowercase().emit_as_canonical(&mut encoder, true);
+    buffer
+}
+
+/// Data of `record` in the canonical form of RFC 4034 section 6.2
+#[cfg(feature = "dnssec")]
+fn canonical_rdata(record: &Record) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut encoder = BinEncoder::with_mode(&mut buffer, EncodeMode::Signing);
+    encoder.set_canonical_names(true);
+    record.data()?.emit(&mut encoder).ok()?;
+    Some(buffer)
+}
+
+/// Records of `owner` and `record_type` among the answers of `response`, with their
+/// signatures
+#[cfg(feature = "dnssec")]
+fn rrset<'r>(
+    response: &'r DnsResponse,
+    owner: &Name,
+    record_type: RecordType,
+) -> (Vec<&'r Record>, Vec<&'r Record>) {
+    let records = response
+        .answers()
+        .iter()
+        .filter(|record| record.name() == owner && record.record_type() == record_type)
+        .collect();
+    let signatures = response
+        .answers()
+        .iter()
+        .filter(|record| record.name() == owner && covered(record) == Some(record_type))
+        .collect();
+    (records, signatures)
+}
+
+/// Type a signature record covers
+#[cfg(feature = "dnssec")]
+fn covered(record: &Record) -> Option<RecordType> {
+    let data = raw_data(record, RecordType::RRSIG)?;
+    Some(RecordType::from(u16::from_be_bytes([
+        *data.first()?,
+        *data.get(1)?,
+    ])))
+}
+
+#[cfg(feature = "dnssec")]
+enum Trust {
+    /// the keys of the zone, and the trust anchor its chain starts from
+    Secure(Vec<Key>, String),
+    Insecure(String),
+    Bogus(String),
+}
+
+/// Zone of the names looked at, with the keys its records are signed with once proven
+#[cfg(feature = "dnssec")]
+struct Zone {
+    name: Name,
+    trust: Trust,
+}
+
+/// Checks the signatures of answers, following the DS and DNSKEY records of every zone cut
+/// from a trust anchor down
+#[cfg(feature = "dnssec")]
+pub struct Validator<'a> {
+    client: &'a DnsClient,
+    anchors: Vec<TrustAnchor>,
+    /// zone of every name looked at, so that the chain is only fetched once per run
+    zones: RefCell<HashMap<Name, Rc<Zone>>>,
+    /// seconds since the Unix epoch, modulo 2^32 like the validity of signatures
+    now: u32,
+}
+
+#[cfg(feature = "dnssec")]
+impl<'a> Validator<'a> {
+    pub fn new(
+        client: &'a DnsClient,
+        anchors: Vec<TrustAnchor>,
+    ) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as u32;
+        Self {
+            client,
+            anchors,
+            zones: RefCell::new(HashMap::new()),
+            now,
+        }
+    }
+
+    /// Verdict of every record of `answers`, signatures taking the verdict of the records
+    /// they cover
+    pub fn validate<'r>(
+        &self,
+        answers: &[&'r Record],
+    ) -> Verdicts<'r> {
+        let mut rrsets: Vec<(&Name, RecordType, Vec<&'r Record>)> = Vec::new();
+        for record in answers {
+            if record.record_type() == RecordType::RRSIG {
+                continue;
+            }
+            let (name, record_type) = (record.name(), record.record_type());
+            match rrsets
+                .iter_mut()
+                .find(|(owner, rrset_type, _)| *owner == name && *rrset_type == record_type)
+            {
+                Some((_, _, records)) => records.push(record),
+                None => rrsets.push((name, record_type, vec![record])),
+            }
+        }
+
+        let mut verdicts: Vec<(&'r Record, Verdict)> = Vec::new();
+        for (owner, record_type, records) in rrsets {
+            let signatures: Vec<&'r Record> = answers
+                .iter()
+                .filter(|record| record.name() == owner && covered(record) == Some(record_type))
+                .copied()
+                .collect();
+            // DS records are signed by the parent of their zone
+            let zone = match record_type {
+                RecordType::DS if !owner.is_root() => self.zone_of(&owner.base_name()),
+                _ => self.zone_of(owner),
+            };
+            let verdict = match &zone.trust {
+                Trust::Secure(keys, anchor) => {
+                    self.check(owner, &records, &signatures, &zone.name, keys, anchor)
+                },
+                Trust::Insecure(reason) => Verdict::Insecure(reason.clone()),
+                Trust::Bogus(reason) => Verdict::Bogus(reason.clone()),
+            };
+            for record in records.into_iter().chain(signatures) {
+                verdicts.push((record, verdict.clone()));
+            }
+        }
+        Verdicts {
+            verdicts,
+        }
+    }
+
+    /// Whether one of `signatures` by `zone` holds for `records`, with one of `keys` proven
+    /// from `anchor`
+    fn check(
+        &self,
+        owner: &Name,
+        records: &[&Record],
+        signatures: &[&Record],
+        zone: &Name,
+        keys: &[Key],
+        anchor: &str,
+    ) -> Verdict {
+        let signatures: Vec<Signature> = signatures
+            .iter()
+            .filter_map(|record| Signature::parse(raw_data(record, RecordType::RRSIG)?))
+            .filter(|signature| signature.signer == *zone)
+            .collect();
+        if signatures.is_empty() {
+            return Verdict::Bogus(format!("no signatures while {} is signed", zone));
+        }
+        if signatures
+            .iter()
+            .all(|signature| !ALGORITHMS.contains(&signature.algorithm))
+        {
+            return Verdict::Insecure(String::from("signed with unsupported algorithms only"));
+        }
+
+        // The most telling failure is reported, expired signatures before bad ones
+        let mut failures: Vec<(u8, String)> = Vec::new();
+        for signature in &signatures {
+            let candidates: Vec<&Key> = keys
+                .iter()
+                .filter(|key| key.tag == signature.tag && key.algorithm == signature.algorithm)
+                .collect();
+            if candidates.is_empty() {
+                failures.push((
+                    0,
+                    format!(
+                        "signed with key {}, which {} does not publish",
+                        signature.tag, zone
+                    ),
+                ));
+                continue;
+            }
+            let data = match signature.signed_data(owner, records) {
+                Ok(data) => data,
+                Err(reason) => {
+                    failures.push((1, reason));
+                    continue;
+                },
+            };
+            if !candidates
+                .iter()
+                .any(|key| key.verify(&data, signature.signature))
+            {
+                failures.push((
+                    2,
+                    format!("signature by key {} does not verify", signature.tag),
+                ));
+                continue;
+            }
+            match signature.check_window(self.now) {
+                Ok(()) => return Verdict::Secure(anchor.to_owned()),
+                Err(reason) => failures.push((3, reason)),
+            }
+        }
+        let (_, reason) = failures.into_iter().max_by_key(|(rank, _)| *rank).unwrap();
+        Verdict::Bogus(reason)
+    }
+
+    /// Zone that `name` belongs to, finding the zone cuts above it first
+    fn zone_of(
+        &self,
+        name: &Name,
+    ) -> Rc<Zone> {
+        let name = name.to_lowercase();
+        if let Some(zone) = self.zones.borrow().get(&name) {
+            return zone.clone();
+        }
+        // Anchors below others are islands of trust, which their names are checked from
+        let anchors: Vec<Proof> = self
+            .anchors
+            .iter()
+            .filter(|anchor| anchor.zone == name)
+            .map(|anchor| anchor.proof.clone())
+            .collect();
+        let zone = match (anchors.is_empty(), name.is_root()) {
+            (false, _) => Rc::new(Zone {
+                trust: self.keys(&name, &anchors, "trust anchor", None),
+                name: name.clone(),
+            }),
+            (true, true) => Rc::new(Zone {
+                name: name.clone(),
+                trust: Trust::Insecure(String::from("no trust anchor covers the name")),
+            }),
+            (true, false) => {
+                let parent = self.zone_of(&name.base_name());
+                match &parent.trust {
+                    Trust::Secure(keys, anchor) => self
+                        .cut(&name, &parent.name, keys, anchor)
+                        .map(Rc::new)
+                        .unwrap_or(parent),
+                    _ => parent,
+                }
+            },
+        };
+        self.zones.borrow_mut().insert(name, zone.clone());
+        zone
+    }
+
+    /// The zone starting at `name` below the signed `parent`, whose chain starts from
+    /// `anchor`, `None` when there is no zone cut at the name
+    fn cut(
+        &self,
+        name: &Name,
+        parent: &Name,
+        parent_keys: &[Key],
+        anchor: &str,
+    ) -> Option<Zone> {
+        let zone = |trust: Trust| {
+            Some(Zone {
+                name: name.clone(),
+                trust,
+            })
+        };
+        let response = match self.client.query_dnssec(name, RecordType::DS) {
+            Ok(response) => response,
+            Err(error) => {
+                return zone(Trust::Bogus(format!(
+                    "cannot query DS of {}: {}",
+                    name, error
+                )))
+            },
+        };
+        let (records, signatures) = rrset(&response, name, RecordType::DS);
+        if records.is_empty() {
+            // Without DS, the name is either inside the zone of its parent or the apex of an
+            // unsigned zone
+            let apex = self
+                .client
+                .query_dnssec(name, RecordType::SOA)
+                .is_ok_and(|response| {
+                    response.answers().iter().any(|record| {
+                        record.record_type() == RecordType::SOA && record.name() == name
+                    })
+                });
+            return match apex {
+                true => zone(Trust::Insecure(format!(
+                    "no DS for {} at its parent {}",
+                    name, parent
+                ))),
+                false => None,
+            };
+        }
+        match self.check(name, &records, &signatures, parent, parent_keys, anchor) {
+            Verdict::Secure(_) => {},
+            Verdict::Insecure(reason) => {
+                return zone(Trust::Insecure(format!("DS of {}: {}", name, reason)))
+            },
+            Verdict::Bogus(reason) => {
+                return zone(Trust::Bogus(format!("DS of {}: {}", name, reason)))
+            },
+        }
+        let ds: Vec<Proof> = records
+            .iter()
+            .filter_map(|record| Ds::parse(raw_data(record, RecordType::DS)?))
+            .map(Proof::Ds)
+            .collect();
+        zone(self.keys(name, &ds, "DS", Some(anchor)))
+    }
+
+    /// Keys of `zone`, proven by one of `proofs` from `source`, in a chain starting from
+    /// `anchor`, or from the proof itself when it is a trust anchor
+    fn keys(
+        &self,
+        zone: &Name,
+        proofs: &[Proof],
+        source: &str,
+        anchor: Option<&str>,
+    ) -> Trust {
+        let proofs: Vec<&Proof> = proofs.iter().filter(|proof| proof.is_supported()).collect();
+        if proofs.is_empty() {
+            return Trust::Insecure(format!(
+                "the {} of {} only has unsupported algorithms",
+                source, zone
+            ));
+        }
+        let response = match self.client.query_dnssec(zone, RecordType::DNSKEY) {
+            Ok(response) => response,
+            Err(error) => {
+                return Trust::Bogus(format!("cannot query DNSKEY of {}: {}", zone, error))
+            },
+        };
+        let (records, signatures) = rrset(&response, zone, RecordType::DNSKEY);
+        let keys: Vec<Key> = records
+            .iter()
+            .filter_map(|record| Key::parse(raw_data(record, RecordType::DNSKEY)?))
+            .collect();
+        let entry_keys: Vec<Key> = keys
+            .iter()
+            .filter(|key| proofs.iter().any(|proof| proof.matches(zone, key)))
+            .cloned()
+            .collect();
+        let Some(proof) = proofs
+            .iter()
+            .find(|proof| entry_keys.iter().any(|key| proof.matches(zone, key)))
+        else {
+            return Trust::Bogus(format!("no DNSKEY of {} matches its {}", zone, source));
+        };
+        let anchor = match anchor {
+            Some(anchor) => anchor.to_owned(),
+            None => proof.describe(zone),
+        };
+        match self.check(zone, &records, &signatures, zone, &entry_keys, &anchor) {
+            Verdict::Secure(_) => Trust::Secure(keys, anchor),
+            Verdict::Insecure(reason) | Verdict::Bogus(reason) => {
+                Trust::Bogus(format!("DNSKEY of {}: {}", zone, reason))
+            },
+        }
+    }
+}
+
+#[cfg(all(test, feature = "dnssec"))]
+mod tests {
+    use std::{fmt::Write, sync::Arc};
+
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+    use crate::{mock::Fixture, ConnectionType, ResolveOptions};
+
+    /// The generic syntax of RFC 3597 for `data`, which the fixtures take for every type
+    fn generic(data: &[u8]) -> String {
+        data.iter()
+            .fold(format!("\\\\# {} ", data.len()), |mut out, byte| {
+                let _ = write!(out, "{:02x}", byte);
+                out
+            })
+    }
+
+    fn now() -> u32 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+    }
+
+    /// Zone `example.` signed with an Ed25519 key
+    struct SignedZone {
+        pair: Ed25519KeyPair,
+        dnskey: Vec<u8>,
+    }
+
+    impl SignedZone {
+        fn new() -> Self {
+            let pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+            // A key signing entry point of the zone, protocol 3, algorithm 15
+            let dnskey = [&[1, 1, 3, 15], pair.public_key().as_ref()].concat();
+            Self {
+                pair,
+                dnskey,
+            }
+        }
+
+        fn tag(&self) -> u16 {
+            key_tag(&self.dnskey)
+        }
+
+        fn anchor(&self) -> TrustAnchor {
+            let mut context = digest::Context::new(&digest::SHA256);
+            context.update(&canonical_name(&Name::from_str("example.").unwrap()));
+            context.update(&self.dnskey);
+            let digest: String = context
+                .finish()
+                .as_ref()
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            parse_trust_anchor(&format!("example. {} 15 2 {}", self.tag(), digest)).unwrap()
+        }
+
+        /// RRSIG data over `rdatas` of `owner`, valid from `inception` to `expiration`
+        fn sign(
+            &self,
+            owner: &str,
+            covered: RecordType,
+            rdatas: &[&[u8]],
+            (inception, expiration): (u32, u32),
+        ) -> String {
+            let owner = Name::from_str(owner).unwrap();
+            let mut data = Vec::new();
+            data.extend(u16::from(covered).to_be_bytes());
+            data.extend([15, owner.num_labels()]);
+            data.extend(300u32.to_be_bytes());
+            data.extend(expiration.to_be_bytes());
+            data.extend(inception.to_be_bytes());
+            data.extend(self.tag().to_be_bytes());
+            data.extend(canonical_name(&Name::from_str("example.").unwrap()));
+            let mut signed = data.clone();
+            let mut rdatas = rdatas.to_vec();
+            rdatas.sort();
+            for rdata in rdatas {
+                signed.extend(canonical_name(&owner));
+                signed.extend(u16::from(covered).to_be_bytes());
+                signed.extend(1u16.to_be_bytes());
+                signed.extend(300u32.to_be_bytes());
+                signed.extend((rdata.len() as u16).to_be_bytes());
+                signed.extend(rdata);
+            }
+            data.extend(self.pair.sign(&signed).as_ref());
+            generic(&data)
+        }
+
+        /// The mock answers of the zone, `www.example.` pointing at `address` with a
+        /// signature valid for `window`
+        fn fixture(
+            &self,
+            address: [u8; 4],
+            window: (u32, u32),
+        ) -> Fixture {
+            let valid = (now() - 3600, now() + 86400);
+            let dnskey_rrsig = self.sign("example.", RecordType::DNSKEY, &[&self.dnskey], valid);
+            let a_rrsig = self.sign("www.example.", RecordType::A, &[&[192, 0, 2, 1]], window);
+            Fixture::from_toml(&format!(
+                r#"
+                [[answer]]
+                name = "example."
+                type = "DNSKEY"
+                records = ["{}", {{ type = "RRSIG", data = "{}" }}]
+
+                [[answer]]
+                name = "www.example."
+                type = "A"
+                records = ["{}", {{ type = "RRSIG", data = "{}" }}]
+
+                [[answer]]
+                name = "nosig.example."
+                type = "A"
+                records = ["192.0.2.3"]
+
+                [[answer]]
+                name = "unsigned.example."
+                type = "SOA"
+                records = ["ns.unsigned.example. hostmaster.unsigned.example. 1 7200 3600 1209600 600"]
+
+                [[answer]]
+                name = "host.unsigned.example."
+                type = "A"
+                records = ["192.0.2.9"]
+                "#,
+                generic(&self.dnskey),
+                dnskey_rrsig,
+                std::net::Ipv4Addr::from(address),
+                a_rrsig,
+            ))
+            .unwrap()
+        }
+
+        /// Verdicts of the answers for `name`, one per record, the signatures last
+        fn verdicts(
+            &self,
+            fixture: Fixture,
+            name: &str,
+        ) -> Vec<(RecordType, Verdict)> {
+            self.verdicts_from(fixture, name, vec![self.anchor()])
+        }
+
+        /// Verdicts of the answers for `name` checked from `anchors`
+        fn verdicts_from(
+            &self,
+            fixture: Fixture,
+            name: &str,
+            anchors: Vec<TrustAnchor>,
+        ) -> Vec<(RecordType, Verdict)> {
+            let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+                .with_mock(Some(Arc::new(fixture)));
+            let client = DnsClient::new(&options).unwrap();
+            let response = client
+                .query_dnssec(&Name::from_str(name).unwrap(), RecordType::A)
+                .unwrap();
+            let answers: Vec<&Record> = response.answers().iter().collect();
+            let validator = Validator::new(&client, anchors);
+            let verdicts = validator.validate(&answers);
+            answers
+                .iter()
+                .map(|record| (record.record_type(), verdicts.get(record).unwrap().clone()))
+                .collect()
+        }
+    }
+
+    fn labels(verdicts: &[(RecordType, Verdict)]) -> Vec<(RecordType, &str, Option<&str>)> {
+        verdicts
+            .iter()
+            .map(|(record_type, verdict)| (*record_type, verdict.label(), verdict.reason()))
+            .collect()
+    }
+
+    fn valid() -> (u32, u32) {
+        (now() - 3600, now() + 86400)
+    }
+
+    #[test]
+    fn records_signed_from_the_anchor_down_are_secure() {
+        let zone = SignedZone::new();
+        let verdicts = zone.verdicts(zone.fixture([192, 0, 2, 1], valid()), "www.example.");
+        assert_eq!(
+            labels(&verdicts),
+            [
+                (RecordType::A, "secure", None),
+                (RecordType::RRSIG, "secure", None),
+            ]
+        );
+        let anchor = format!("example. DS {}", zone.tag());
+        assert_eq!(verdicts[0].1.anchor(), Some(anchor.as_str()));
+        assert_eq!(
+            verdicts[0].1.annotation(&OutputConfig::new(true, false)),
+            format!("  [secure from {}]", anchor)
+        );
+    }
+
+    #[test]
+    fn islands_of_trust_are_named_in_the_verdicts() {
+        let zone = SignedZone::new();
+        // The root keys cannot be followed down to example., its own key being the island
+        let mut anchors = root_anchors();
+        anchors.push(TrustAnchor::from_key(
+            &Name::from_str("EXAMPLE").unwrap(),
+            zone.dnskey.clone(),
+        ));
+        let fixture = zone.fixture([192, 0, 2, 1], valid());
+        let verdicts = zone.verdicts_from(fixture, "www.example.", anchors);
+        let anchor = format!("example. DNSKEY {}", zone.tag());
+        assert_eq!(verdicts[0].1.label(), "secure");
+        assert_eq!(verdicts[0].1.anchor(), Some(anchor.as_str()));
+        // Names below a zone cut inherit the anchor of their parent
+        let unsigned = zone.verdicts_from(
+            zone.fixture([192, 0, 2, 1], valid()),
+            "host.unsigned.example.",
+            vec![zone.anchor()],
+        );
+        assert_eq!(unsigned[0].1.anchor(), None);
+    }
+
+    #[test]
+    fn records_that_do_not_match_their_signature_are_bogus() {
+        let zone = SignedZone::new();
+        let verdicts = zone.verdicts(zone.fixture([192, 0, 2, 66], valid()), "www.example.");
+        let reason = format!("signature by key {} does not verify", zone.tag());
+        assert_eq!(
+            labels(&verdicts)[0],
+            (RecordType::A, "bogus", Some(reason.as_str()))
+        );
+    }
+
+    #[test]
+    fn expired_signatures_are_bogus() {
+        let zone = SignedZone::new();
+        let expiration = now() - 60;
+        let fixture = zone.fixture([192, 0, 2, 1], (expiration - 86400, expiration));
+        let verdicts = zone.verdicts(fixture, "www.example.");
+        let (_, verdict) = &verdicts[0];
+        assert_eq!(verdict.label(), "bogus");
+        let reason = verdict.reason().unwrap();
+        assert!(
+            reason.starts_with(&format!("signature by key {} expired on ", zone.tag())),
+            "{}",
+            reason
+        );
+    }
+
+    #[test]
+    fn unsigned_records_of_signed_zones_are_bogus() {
+        let zone = SignedZone::new();
+        let verdicts = zone.verdicts(zone.fixture([192, 0, 2, 1], valid()), "nosig.example.");
+        assert_eq!(
+            labels(&verdicts),
+            [(
+                RecordType::A,
+                "bogus",
+                Some("no signatures while example. is signed")
+            )]
+        );
+    }
+
+    #[test]
+    fn zones_without_ds_are_insecure() {
+        let zone = SignedZone::new();
+        let fixture = zone.fixture([192, 0, 2, 1], valid());
+        let verdicts = zone.verdicts(fixture, "host.unsigned.example.");
+        assert_eq!(
+            labels(&verdicts),
+            [(
+                RecordType::A,
+                "insecure",
+                Some("no DS for unsigned.example. at its parent example.")
+            )]
+        );
+    }
+
+    #[test]
+    fn names_no_anchor_covers_are_insecure() {
+        let zone = SignedZone::new();
+        let options = ResolveOptions::new(ConnectionType::Udp, "192.0.2.53")
+            .with_mock(Some(Arc::new(zone.fixture([192, 0, 2, 1], valid()))));
+        let client = DnsClient::new(&options).unwrap();
+        let response = client
+            .query_dnssec(&Name::from_str("www.example.").unwrap(), RecordType::A)
+            .unwrap();
+        let answers: Vec<&Record> = response.answers().iter().collect();
+        let validator = Validator::new(&client, Vec::new());
+        let verdicts = validator.validate(&answers);
+        let verdict = verdicts.get(answers[0]).unwrap();
+        assert_eq!(verdict.reason(), Some("no trust anchor covers the name"));
+    }
+}
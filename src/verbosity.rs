@@ -0,0 +1,168 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::Duration,
+};
+
+/// How often the loops of long runs look whether a signal came
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How much a long run, of `--file` or of `resoy proxy`, prints on stderr, which SIGUSR1
+/// moves to the next level while it runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// nothing but what the run is for and its outcome
+    Quiet,
+    Normal,
+    /// what --verbose prints
+    Verbose,
+    /// every name or query with its time too
+    Debug,
+}
+
+const LEVELS: [Verbosity; 4] = [
+    Verbosity::Quiet,
+    Verbosity::Normal,
+    Verbosity::Verbose,
+    Verbosity::Debug,
+];
+
+/// Index of the level of the run in `LEVELS`
+static LEVEL: AtomicU8 = AtomicU8::new(1);
+
+/// Set by SIGUSR1 when the level changed, until the change is announced
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Set by SIGUSR2 until the snapshot is printed
+static SNAPSHOT: AtomicBool = AtomicBool::new(false);
+
+impl Verbosity {
+    /// Level of the flags of the run, `--verbose` winning over `--quiet`
+    pub fn of(
+        quiet: bool,
+        verbose: bool,
+    ) -> Self {
+        match (quiet, verbose) {
+            (_, true) => Self::Verbose,
+            (true, false) => Self::Quiet,
+            (false, false) => Self::Normal,
+        }
+    }
+}
+
+impl Display for Verbosity {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let name = match self {
+            Self::Quiet => "quiet",
+            Self::Normal => "normal",
+            Self::Verbose => "verbose",
+            Self::Debug => "debug",
+        };
+        f.write_str(name)
+    }
+}
+
+pub fn set(level: Verbosity) {
+    let index = LEVELS.iter().position(|known| *known == level).unwrap();
+    LEVEL.store(index as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Verbosity {
+    LEVELS[usize::from(LEVEL.load(Ordering::Relaxed)) % LEVELS.len()]
+}
+
+/// Whether the run prints what `wanted` does
+pub fn at_least(wanted: Verbosity) -> bool {
+    level() >= wanted
+}
+
+/// Move to the next level, from debug back to quiet, and return it. Safe in a signal handler.
+pub fn cycle() -> Verbosity {
+    let count = LEVELS.len() as u8;
+    let previous = LEVEL
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |index| {
+            Some((index + 1) % count)
+        })
+        .unwrap_or_default();
+    CHANGED.store(true, Ordering::Relaxed);
+    LEVELS[usize::from((previous + 1) % count)]
+}
+
+/// Ask for a snapshot at the next `poll`. Safe in a signal handler.
+pub fn request_snapshot() {
+    SNAPSHOT.store(true, Ordering::Relaxed);
+}
+
+/// Announce a change of the level, and print `snapshot()` on stderr when one was asked
+/// for, from the loop of the run rather than from the signal handlers
+pub fn poll(snapshot: impl FnOnce() -> String) {
+    if CHANGED.swap(false, Ordering::Relaxed) {
+        eprintln!("; verbosity {}", level());
+    }
+    if SNAPSHOT.swap(false, Ordering::Relaxed) {
+        eprintln!("{}", snapshot());
+    }
+}
+
+/// Cycle the level on SIGUSR1 and ask for a snapshot on SIGUSR2
+#[cfg(unix)]
+pub fn listen(_keys: bool) {
+    extern "C" fn next_level(_: libc::c_int) {
+        cycle();
+    }
+    extern "C" fn snapshot(_: libc::c_int) {
+        request_snapshot();
+    }
+    unsafe {
+        libc::signal(libc::SIGUSR1, next_level as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGUSR2, snapshot as *const () as libc::sighandler_t);
+    }
+}
+
+/// Systems without SIGUSR1 and SIGUSR2 take `v` and `s` typed into the terminal instead,
+/// unless `keys` is false as stdin is the input of the run
+#[cfg(not(unix))]
+pub fn listen(keys: bool) {
+    use std::io::{stdin, BufRead, IsTerminal};
+
+    if !keys || !stdin().is_terminal() {
+        return;
+    }
+    eprintln!("; type v and enter to change the verbosity, s and enter for a snapshot");
+    std::thread::spawn(|| {
+        for line in stdin().lock().lines() {
+            match line.as_deref().map(str::trim) {
+                Ok("v") => {
+                    cycle();
+                },
+                Ok("s") => request_snapshot(),
+                Ok(_) => {},
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_cycle_from_debug_back_to_quiet() {
+        set(Verbosity::of(true, false));
+        assert_eq!(level(), Verbosity::Quiet);
+        assert!(!at_least(Verbosity::Normal));
+        assert_eq!(cycle(), Verbosity::Normal);
+        assert_eq!(cycle(), Verbosity::Verbose);
+        assert!(at_least(Verbosity::Normal));
+        assert_eq!(cycle(), Verbosity::Debug);
+        assert_eq!(cycle(), Verbosity::Quiet);
+        assert_eq!(level(), Verbosity::Quiet);
+        // The change is announced once, by the loop of the run
+        assert!(CHANGED.swap(false, Ordering::Relaxed));
+        set(Verbosity::Normal);
+    }
+}
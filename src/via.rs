@@ -0,0 +1,274 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io::Write,
+    process::{Command, Output, Stdio},
+    str::FromStr,
+    thread,
+};
+
+use hickory_client::{
+    op::{Message, MessageType, OpCode, Query},
+    rr::{Name, RecordType},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+use url::Url;
+
+use crate::{
+    format::{OutputConfig, RecordFormatter},
+    ConnectionType,
+};
+
+/// Exit status of a shell that could not find the command
+const COMMAND_NOT_FOUND: i32 = 127;
+
+/// Exit status of ssh itself failing, e.g. to connect or to authenticate
+const SSH_FAILED: i32 = 255;
+
+/// Seconds nc waits for the answer after sending the query
+const NC_WAIT: &str = "3";
+
+/// Host to run queries from, given as `ssh://[user@]host[:port]`
+#[derive(Clone)]
+pub struct Vantage {
+    pub label: String,
+    destination: String,
+    port: Option<u16>,
+}
+
+impl FromStr for Vantage {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(value).map_err(|error| error.to_string())?;
+        if url.scheme() != "ssh" {
+            return Err(String::from("only ssh:// vantages are supported"));
+        }
+        let host = url
+            .host_str()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| String::from("missing host"))?;
+        let destination = match url.username() {
+            "" => host.to_owned(),
+            user => format!("{}@{}", user, host),
+        };
+        Ok(Self {
+            label: destination.clone(),
+            destination,
+            port: url.port(),
+        })
+    }
+}
+
+/// What the server answered when asked from a vantage
+pub struct Report {
+    pub vantage: Vantage,
+    /// how the queries were run, for `--verbose`
+    pub strategy: Vec<String>,
+    pub lines: Result<Vec<String>, String>,
+}
+
+/// Quote `arg` for the remote shell, which ssh hands the command line to
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+impl Vantage {
+    /// Run `command` on the vantage with `input` on its standard input.
+    ///
+    /// Host keys are checked as configured for ssh, and prompts are disabled so that an
+    /// unknown host fails instead of blocking the run.
+    fn run(
+        &self,
+        command: &[String],
+        input: &[u8],
+    ) -> Result<Output, String> {
+        let mut ssh = Command::new("ssh");
+        ssh.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            ssh.args(["-p", &port.to_string()]);
+        }
+        let remote: Vec<String> = command.iter().map(|arg| quote(arg)).collect();
+        let mut child = ssh
+            .arg(&self.destination)
+            .arg("--")
+            .arg(remote.join(" "))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("cannot run ssh: {}", error))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(input);
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|error| format!("cannot run ssh: {}", error))?;
+        if output.status.code() == Some(SSH_FAILED) {
+            return Err(format!(
+                "ssh failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output)
+    }
+}
+
+/// Parameters of the local run that the remote queries repeat
+pub struct Request<'a> {
+    pub server: &'a str,
+    pub connection: ConnectionType,
+    pub name: &'a Name,
+    pub record_types: &'a [RecordType],
+    pub output_config: &'a OutputConfig,
+    pub seconds: bool,
+}
+
+/// Run the query with the resoy of the vantage, `None` when it has none
+fn remote_resoy(
+    vantage: &Vantage,
+    request: &Request,
+) -> Option<Result<Vec<String>, String>> {
+    let mut command: Vec<String> = [
+        "resoy",
+        "--no-ansi",
+        "--no-hints",
+        "--connection",
+        &request.connection.to_string(),
+        "--server",
+        request.server,
+    ]
+    .iter()
+    .map(ToString::to_string)
+    .collect();
+    if request.seconds {
+        command.push(String::from("--seconds"));
+    }
+    command.push(request.name.to_string());
+    command.extend(request.record_types.iter().map(ToString::to_string));
+
+    let output = match vantage.run(&command, &[]) {
+        Ok(output) => output,
+        Err(error) => return Some(Err(error)),
+    };
+    match output.status.code() {
+        Some(COMMAND_NOT_FOUND) => None,
+        Some(0) => Some(Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect())),
+        _ => Some(Err(format!(
+            "remote resoy failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+/// Send wire-format queries to the server through nc on the vantage
+fn netcat(
+    vantage: &Vantage,
+    request: &Request,
+) -> Result<Vec<String>, String> {
+    let tcp = match request.connection {
+        ConnectionType::Udp => false,
+        ConnectionType::Tcp => true,
+        #[allow(unreachable_patterns)]
+        _ => {
+            return Err(format!(
+                "resoy is not installed there, and nc cannot speak {}",
+                request.connection
+            ))
+        },
+    };
+    let server = crate::server_addr::parse_server_addr(request.connection, request.server)
+        .map_err(|_| format!("cannot parse DNS server address {:?}", request.server))?;
+    let mut command = vec![String::from("nc")];
+    if !tcp {
+        command.push(String::from("-u"));
+    }
+    command.extend([
+        String::from("-w"),
+        String::from(NC_WAIT),
+        server.ip().to_string(),
+        server.port().to_string(),
+    ]);
+
+    let mut lines = Vec::new();
+    for record_type in request.record_types {
+        let id = RandomState::new().build_hasher().finish() as u16;
+        let mut message = Message::new();
+        message
+            .set_id(id)
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .set_recursion_desired(true)
+            .add_query(Query::query(request.name.clone(), *record_type));
+        let mut query = message.to_bytes().map_err(|error| error.to_string())?;
+        if tcp {
+            query.splice(0..0, (query.len() as u16).to_be_bytes());
+        }
+
+        let output = vantage.run(&command, &query)?;
+        let answer = match tcp {
+            true => output.stdout.get(2..).unwrap_or_default(),
+            false => &output.stdout[..],
+        };
+        if answer.is_empty() {
+            return Err(format!(
+                "no answer from {} through nc{}",
+                server,
+                match output.status.code() {
+                    Some(COMMAND_NOT_FOUND) => ", which is not installed either",
+                    _ => "",
+                }
+            ));
+        }
+        let response = Message::from_bytes(answer)
+            .map_err(|error| format!("invalid answer from {}: {}", server, error))?;
+        lines.extend(
+            response
+                .answers()
+                .iter()
+                .map(|record| RecordFormatter::new(record, request.output_config).format()),
+        );
+    }
+    Ok(lines)
+}
+
+fn query(
+    vantage: &Vantage,
+    request: &Request,
+) -> Report {
+    let mut strategy = vec![format!("running resoy on {} over ssh", vantage.label)];
+    let lines = match remote_resoy(vantage, request) {
+        Some(lines) => lines,
+        None => {
+            strategy.push(String::from(
+                "resoy is not installed there, sending wire-format queries through nc",
+            ));
+            netcat(vantage, request)
+        },
+    };
+    Report {
+        vantage: vantage.clone(),
+        strategy,
+        lines,
+    }
+}
+
+/// Query from every vantage concurrently, each failing on its own
+pub fn query_all(
+    vantages: &[Vantage],
+    request: &Request,
+) -> Vec<Report> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = vantages
+            .iter()
+            .map(|vantage| scope.spawn(move || query(vantage, request)))
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .collect()
+    })
+}
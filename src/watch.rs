@@ -0,0 +1,416 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use hickory_client::{
+    op::ResponseCode,
+    rr::{DNSClass, Name, Record, RecordType},
+};
+
+use crate::{
+    format::{self, same_record, OutputConfig, RecordFormatter},
+    outln, AppError, DnsClient, ResolveOptions,
+};
+
+/// Set once SIGINT arrives, the watch then ends after the round under way
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// How often the pause between two rounds checks whether the watch was stopped
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// What the TTL of a record tells about the cache of the resolver, from one round to the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TtlTrend {
+    /// counting down, or held by a resolver that caps or rounds it
+    Cached,
+    /// up again, or down from a copy that would have expired meanwhile
+    Refreshed,
+}
+
+impl Display for TtlTrend {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Cached => write!(f, "ttl ↓ (cached)"),
+            Self::Refreshed => write!(f, "ttl ↑ refreshed from authority"),
+        }
+    }
+}
+
+/// What `current`, the TTL of a record `elapsed` after it was `previous`, tells about the
+/// cache. Resolvers that round the TTLs they count down may give one a little higher than
+/// before, up to a tenth of it or a second, which is no refresh.
+fn ttl_trend(
+    previous: u32,
+    current: u32,
+    elapsed: Duration,
+) -> TtlTrend {
+    let rounding = (previous / 10).max(1);
+    let elapsed = u32::try_from(elapsed.as_secs()).unwrap_or(u32::MAX);
+    let expired = elapsed > previous.saturating_add(rounding);
+    match current > previous.saturating_add(rounding) || expired {
+        true => TtlTrend::Refreshed,
+        false => TtlTrend::Cached,
+    }
+}
+
+/// The trend of an answer: refreshed when one of its records was, or when they changed as
+/// only the authority changes them, and `None` for the first answer or an empty one
+fn answer_trend(
+    previous: &[Record],
+    current: &[Record],
+    elapsed: Duration,
+) -> Option<TtlTrend> {
+    if current.is_empty() || previous.is_empty() {
+        return None;
+    }
+    if !same_records(previous, current) {
+        return Some(TtlTrend::Refreshed);
+    }
+    let refreshed = current.iter().any(|record| {
+        previous
+            .iter()
+            .filter(|known| same_record(known, record))
+            .any(|known| ttl_trend(known.ttl(), record.ttl(), elapsed) == TtlTrend::Refreshed)
+    });
+    match refreshed {
+        true => Some(TtlTrend::Refreshed),
+        false => Some(TtlTrend::Cached),
+    }
+}
+
+/// Queries of `--watch` and how their answers are printed
+pub struct Watcher<'a> {
+    /// how a client connects again after it failed
+    options: &'a ResolveOptions,
+    interval: Duration,
+    /// end once the records of a type differ from those of the first round that answered
+    until_changed: bool,
+    /// print the data of the records only, as with `--short`
+    short: bool,
+    output_config: &'a OutputConfig,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(
+        options: &'a ResolveOptions,
+        interval: Duration,
+        until_changed: bool,
+        short: bool,
+        output_config: &'a OutputConfig,
+    ) -> Self {
+        Self {
+            options,
+            interval,
+            until_changed,
+            short,
+            output_config,
+        }
+    }
+
+    /// Ask `client` for the records of `name` every interval until Ctrl-C, or until they
+    /// changed with `until_changed`, printing the records of every round under a timestamp.
+    ///
+    /// A client that fails is dropped and a new one connects in the next query, so that a
+    /// server that goes away for a while doesn't end the watch. Clients over TLS and HTTPS are
+    /// kept instead, as they connect again by themselves, resuming the TLS session, and the
+    /// time that takes is reported apart from that of the queries.
+    pub fn watch(
+        &self,
+        client: DnsClient,
+        name: &Name,
+        class: DNSClass,
+        record_types: &[RecordType],
+    ) -> Result<(), AppError> {
+        handle_signals();
+        let mut client = Some(client);
+        // Per type, the records of the first round that answered and of the last one
+        let mut first: Vec<Option<Vec<Record>>> = vec![None; record_types.len()];
+        let mut previous: Vec<Option<Vec<Record>>> = vec![None; record_types.len()];
+        // Per type, when the last answer came, and when the records came fresh from authority
+        let mut answered: Vec<Option<Instant>> = vec![None; record_types.len()];
+        let mut refreshes: Vec<(RecordType, SystemTime)> = Vec::new();
+        let mut round = 0;
+        let mut changes = 0;
+        let (mut queries, mut query_time) = (0, Duration::ZERO);
+        while !self.stopped() {
+            let started = Instant::now();
+            round += 1;
+            if round > 1 {
+                outln!();
+            }
+            let header = format!(
+                ";; {} round {}",
+                format::format_timestamp(SystemTime::now()),
+                round
+            );
+            outln!("{}", self.output_config.format_header(&header));
+
+            let mut changed_since_first = Vec::new();
+            let mut changed = false;
+            for (index, record_type) in record_types.iter().enumerate() {
+                let connected = match client.take() {
+                    Some(client) => Ok(client),
+                    None => DnsClient::new(self.options),
+                };
+                let current = match connected {
+                    Ok(connected) => client.insert(connected),
+                    Err(error) => {
+                        outln!("; {}: {:?}", record_type, error);
+                        continue;
+                    },
+                };
+                let before = current.connections();
+                let asked = Instant::now();
+                let response = current.query_uncached(name, class, *record_type);
+                let mut elapsed = asked.elapsed();
+                if let (Some(before), Some(after)) = (before, current.connections()) {
+                    let connections = after - before;
+                    elapsed = elapsed.saturating_sub(connections.handshakes);
+                    if connections.opened > 0 {
+                        outln!(
+                            "; {}: connected again in {}ms{}",
+                            record_type,
+                            connections.handshakes.as_millis(),
+                            match connections.resumed > 0 {
+                                true => ", session resumed",
+                                false => "",
+                            }
+                        );
+                    }
+                }
+                let response = match response {
+                    Ok(response) => response,
+                    Err(error) => {
+                        outln!("; {}: {}, connecting again", record_type, error);
+                        if current.connections().is_none() {
+                            client = None;
+                        }
+                        continue;
+                    },
+                };
+                queries += 1;
+                query_time += elapsed;
+                let code = response.response_code();
+                if code != ResponseCode::NoError {
+                    outln!(
+                        "; {}: {}",
+                        record_type,
+                        format!("{:?}", code).to_uppercase()
+                    );
+                }
+                let records = response.answers().to_vec();
+                if let Some(last) = &previous[index] {
+                    changed |= !same_records(last, &records);
+                }
+                for record in &records {
+                    let line = self.line(record);
+                    match &previous[index] {
+                        Some(last) if !last.iter().any(|known| same_record(known, record)) => {
+                            outln!("{}", self.output_config.format_changed(&line))
+                        },
+                        _ => outln!("{}", line),
+                    }
+                }
+                if let Some(last) = &previous[index] {
+                    for gone in last
+                        .iter()
+                        .filter(|known| !records.iter().any(|record| same_record(known, record)))
+                    {
+                        let removed = self.output_config.format_alert("  [removed]");
+                        outln!("{}{}", self.line(gone), removed);
+                    }
+                }
+                if let (Some(last), Some(before)) = (&previous[index], answered[index]) {
+                    if let Some(trend) = answer_trend(last, &records, asked - before) {
+                        outln!("; {}: {}", record_type, trend);
+                        if trend == TtlTrend::Refreshed {
+                            refreshes.push((*record_type, SystemTime::now()));
+                        }
+                    }
+                }
+                answered[index] = Some(asked);
+                let initial = first[index].get_or_insert_with(|| records.clone());
+                if !same_records(initial, &records) {
+                    changed_since_first.push(record_type.to_string());
+                }
+                previous[index] = Some(records);
+            }
+            if changed {
+                changes += 1;
+            }
+            if self.until_changed && !changed_since_first.is_empty() {
+                outln!(
+                    "; records of {} changed since they were first seen",
+                    changed_since_first.join(", ")
+                );
+                return Ok(());
+            }
+            self.pause(started);
+        }
+        eprint!("Watched {} rounds, {} with changes", round, changes);
+        if queries > 0 {
+            eprint!(", {}ms a query", (query_time / queries).as_millis());
+        }
+        eprintln!();
+        if !refreshes.is_empty() {
+            let refreshes: Vec<String> = refreshes
+                .iter()
+                .map(|(record_type, at)| {
+                    format!("{} at {}", record_type, format::format_timestamp(*at))
+                })
+                .collect();
+            eprintln!("Refreshed from authority: {}", refreshes.join(", "));
+        }
+        if let Some(connections) = client.as_ref().and_then(DnsClient::connections) {
+            eprintln!("{}", connections);
+        }
+        Ok(())
+    }
+
+    /// Whether the watch was stopped, by SIGINT or by the end of the budget of its queries
+    fn stopped(&self) -> bool {
+        STOPPED.load(Ordering::Relaxed) || self.options.session().stopped().is_some()
+    }
+
+    /// Wait until the next round is due, or until the watch is stopped
+    fn pause(
+        &self,
+        started: Instant,
+    ) {
+        while !self.stopped() {
+            let left = self.interval.saturating_sub(started.elapsed());
+            if left.is_zero() {
+                break;
+            }
+            thread::sleep(left.min(STOP_CHECK));
+        }
+    }
+
+    fn line(
+        &self,
+        record: &Record,
+    ) -> String {
+        match self.short {
+            true => format::format_short(
+                &[record],
+                self.output_config.ascii_names(),
+                self.output_config.txt_concat(),
+            )
+            .concat(),
+            false => {
+                let mut line = String::new();
+                RecordFormatter::new(record, self.output_config).write(&mut line);
+                line
+            },
+        }
+    }
+}
+
+/// Whether two answers hold the same records, in whatever order
+fn same_records(
+    a: &[Record],
+    b: &[Record],
+) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|x| b.iter().any(|y| same_record(x, y)))
+        && b.iter().all(|y| a.iter().any(|x| same_record(x, y)))
+}
+
+/// End the watch after the round under way on the first SIGINT, and right away on the second
+#[cfg(unix)]
+fn handle_signals() {
+    extern "C" fn stop(signal: libc::c_int) {
+        STOPPED.store(true, Ordering::Relaxed);
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, stop as *const () as libc::sighandler_t);
+    }
+}
+
+/// Other systems end the watch abruptly
+#[cfg(not(unix))]
+fn handle_signals() {}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::{rr::RData, serialize::txt::RDataParser};
+
+    use super::{
+        TtlTrend::{Cached, Refreshed},
+        *,
+    };
+
+    /// Trends along `ttls`, polled every `interval` seconds
+    fn trends(
+        ttls: &[u32],
+        interval: u64,
+    ) -> Vec<TtlTrend> {
+        ttls.windows(2)
+            .map(|pair| ttl_trend(pair[0], pair[1], Duration::from_secs(interval)))
+            .collect()
+    }
+
+    fn record(
+        ttl: u32,
+        address: &str,
+    ) -> Record {
+        let data = RData::try_from_str(RecordType::A, address).unwrap();
+        Record::from_rdata(Name::from_str("example.com.").unwrap(), ttl, data)
+    }
+
+    #[test]
+    fn ttls_counting_down_are_cached_until_they_jump_back_up() {
+        assert_eq!(
+            trends(&[300, 270, 240, 210, 300, 270], 30),
+            [Cached, Cached, Cached, Refreshed, Cached]
+        );
+        // Fetched again as the cached copy expired, to the same TTL a poll later
+        assert_eq!(trends(&[20, 290], 30), [Refreshed]);
+    }
+
+    #[test]
+    fn ttls_shorter_than_the_interval_are_refreshed_even_when_lower() {
+        // 50s left, and 120s later the copy had expired whatever the TTL says
+        assert_eq!(trends(&[50, 10], 120), [Refreshed]);
+        assert_eq!(trends(&[0, 0, 0], 5), [Refreshed, Refreshed]);
+    }
+
+    #[test]
+    fn capped_and_rounded_ttls_are_no_refresh() {
+        // Capped at an hour, and never counted down
+        assert_eq!(trends(&[3600, 3600, 3600], 60), [Cached, Cached]);
+        // Rounded to ten seconds, sometimes up
+        assert_eq!(trends(&[300, 300, 290, 300, 280], 4), [Cached; 4]);
+        // Off by a second around the boundary of one
+        assert_eq!(trends(&[5, 6, 4], 1), [Cached, Cached]);
+        assert_eq!(trends(&[5, 7], 1), [Refreshed]);
+    }
+
+    #[test]
+    fn answers_are_refreshed_when_one_record_is_or_when_they_change() {
+        let interval = Duration::from_secs(30);
+        let before = [record(300, "192.0.2.1"), record(300, "192.0.2.2")];
+        let cached = [record(270, "192.0.2.2"), record(270, "192.0.2.1")];
+        assert_eq!(answer_trend(&before, &cached, interval), Some(Cached));
+        let one_refreshed = [record(270, "192.0.2.1"), record(600, "192.0.2.2")];
+        assert_eq!(
+            answer_trend(&before, &one_refreshed, interval),
+            Some(Refreshed)
+        );
+        let changed = [record(270, "192.0.2.1"), record(270, "192.0.2.3")];
+        assert_eq!(answer_trend(&before, &changed, interval), Some(Refreshed));
+        assert_eq!(answer_trend(&before, &[], interval), None);
+        assert_eq!(answer_trend(&[], &cached, interval), None);
+    }
+}
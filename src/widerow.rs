@@ -0,0 +1,228 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, SystemTime},
+};
+
+use hickory_client::{
+    error::ClientResult,
+    op::DnsResponse,
+    rr::{Name, RecordType},
+};
+
+use crate::{
+    format::{data_text, escape_tsv, format_timestamp, OutputConfig},
+    output,
+};
+
+/// Columns of `--output widerow`, in order
+const COLUMNS: [&str; 10] = [
+    "timestamp",
+    "name",
+    "type",
+    "rcode",
+    "answer_count",
+    "answers",
+    "min_ttl",
+    "server",
+    "latency_ms",
+    "error",
+];
+
+/// One row per queried (name, type), whatever the outcome of the query
+pub struct Row<'a> {
+    pub timestamp: SystemTime,
+    pub name: &'a Name,
+    pub record_type: RecordType,
+    pub result: ClientResult<DnsResponse>,
+    pub server: &'a str,
+    pub latency: Duration,
+}
+
+impl Row<'_> {
+    /// Fields of the row in the order of the columns, unescaped
+    pub fn fields(&self) -> [String; 10] {
+        let (rcode, answers, min_ttl, error) = match &self.result {
+            Ok(response) => (
+                format!("{:?}", response.response_code()).to_uppercase(),
+                response
+                    .answers()
+                    .iter()
+                    .map(|record| record.data().map(data_text).unwrap_or_default())
+                    .collect(),
+                response.answers().iter().map(|record| record.ttl()).min(),
+                String::new(),
+            ),
+            Err(error) => (String::new(), Vec::new(), None, error.to_string()),
+        };
+        [
+            format_timestamp(self.timestamp),
+            self.name.to_ascii(),
+            self.record_type.to_string(),
+            rcode,
+            answers.len().to_string(),
+            serde_json::to_string(&answers).unwrap(),
+            min_ttl.map(|ttl| ttl.to_string()).unwrap_or_default(),
+            self.server.to_owned(),
+            self.latency.as_millis().to_string(),
+            error,
+        ]
+    }
+}
+
+/// Print the rows as TSV, under the header unless `config` printed it already, so that the
+/// rows of several names make one table
+pub fn print(
+    rows: &[Row],
+    config: &OutputConfig,
+) {
+    let _ = output::record(|out| write(out, rows, config));
+}
+
+/// Write the rows to `out` as `print` does
+pub fn write(
+    out: &mut dyn Write,
+    rows: &[Row],
+    config: &OutputConfig,
+) -> io::Result<()> {
+    if config.starts_table() {
+        writeln!(out, "{}", COLUMNS.join("\t"))?;
+    }
+    for row in rows {
+        let fields: Vec<String> = row.fields().iter().map(|field| escape_tsv(field)).collect();
+        writeln!(out, "{}", fields.join("\t"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use hickory_client::{
+        error::ClientError,
+        op::{Message, ResponseCode},
+        proto::error::{ProtoError, ProtoErrorKind},
+        rr::{
+            rdata::{A, TXT},
+            RData, Record,
+        },
+    };
+
+    use super::*;
+
+    fn response(
+        response_code: ResponseCode,
+        answers: Vec<RData>,
+    ) -> ClientResult<DnsResponse> {
+        let mut message = Message::new();
+        message.set_response_code(response_code);
+        // Every answer a second older than the one before, for min_ttl to pick the last
+        for (index, data) in answers.into_iter().enumerate() {
+            let name = Name::from_str("example.com.").unwrap();
+            message.add_answer(Record::from_rdata(name, 300 - index as u32, data));
+        }
+        Ok(DnsResponse::from_message(message).unwrap())
+    }
+
+    fn txt(strings: &[&str]) -> RData {
+        RData::TXT(TXT::new(strings.iter().map(|s| s.to_string()).collect()))
+    }
+
+    #[test]
+    fn rows_match_the_golden_file() {
+        // Written again by running the tests with `UPDATE_SNAPSHOTS=1`
+        const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/widerow.tsv");
+        let name = Name::from_str("example.com.").unwrap();
+        let idn = Name::from_str("xn--mnchen-3ya.de.").unwrap();
+        let results = [
+            (
+                &name,
+                RecordType::A,
+                response(
+                    ResponseCode::NoError,
+                    vec![
+                        RData::A(A::new(192, 0, 2, 1)),
+                        RData::A(A::new(192, 0, 2, 2)),
+                    ],
+                ),
+            ),
+            (
+                &name,
+                RecordType::TXT,
+                response(
+                    ResponseCode::NoError,
+                    vec![
+                        txt(&["tab\there", "new\nline", "carriage\rreturn"]),
+                        txt(&["back\\slash \"quoted\"", "caf\u{e9}"]),
+                    ],
+                ),
+            ),
+            (
+                &idn,
+                RecordType::AAAA,
+                response(ResponseCode::NoError, Vec::new()),
+            ),
+            (
+                &name,
+                RecordType::MX,
+                response(ResponseCode::NXDomain, Vec::new()),
+            ),
+            (
+                &name,
+                RecordType::NS,
+                response(ResponseCode::ServFail, Vec::new()),
+            ),
+            (
+                &name,
+                RecordType::SOA,
+                Err(ClientError::from(ProtoError::from(ProtoErrorKind::Timeout))),
+            ),
+            (
+                &name,
+                RecordType::CAA,
+                Err(ClientError::from(String::from(
+                    "first line\nsecond\tcolumn \\ end",
+                ))),
+            ),
+        ];
+        let rows: Vec<Row> = results
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, record_type, result))| Row {
+                timestamp: SystemTime::UNIX_EPOCH
+                    + Duration::from_secs(1_700_000_000 + index as u64),
+                name,
+                record_type,
+                result,
+                server: "192.0.2.53:53",
+                latency: Duration::from_millis(12 * index as u64),
+            })
+            .collect();
+        let mut out = Vec::new();
+        write(&mut out, &rows, &OutputConfig::new(true, false)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(GOLDEN, &out).unwrap();
+        }
+        let expected = std::fs::read_to_string(GOLDEN).unwrap();
+        assert!(
+            out == expected,
+            "the widerow rows changed, check them and run the tests with UPDATE_SNAPSHOTS=1"
+        );
+        // Escaping leaves exactly the ten columns on one line per (name, type)
+        assert_eq!(out.lines().count(), rows.len() + 1);
+        for line in out.lines() {
+            assert_eq!(line.split('\t').count(), COLUMNS.len(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn the_header_is_written_once_per_table() {
+        let config = OutputConfig::new(true, false);
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        write(&mut first, &[], &config).unwrap();
+        write(&mut second, &[], &config).unwrap();
+        assert_eq!(first, b"timestamp\tname\ttype\trcode\tanswer_count\tanswers\tmin_ttl\tserver\tlatency_ms\terror\n");
+        assert!(second.is_empty());
+    }
+}
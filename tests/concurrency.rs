@@ -0,0 +1,189 @@
+//! One client queried from many threads at once, as library consumers share it, through the
+//! mock answers and through local servers over UDP and TCP
+
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, UdpSocket},
+    str::FromStr,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use hickory_client::{
+    op::{Message, MessageType, ResponseCode},
+    rr::{rdata::TXT, DNSClass, Name, RData, Record, RecordType},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+use resoy::{mock::Fixture, ConnectionType, DnsClient, ResolveOptions};
+
+const THREADS: usize = 32;
+const QUERIES: usize = 100;
+
+const FIXTURE: &str = r#"
+[[answer]]
+name = "a.example.com"
+type = "A"
+records = ["192.0.2.1"]
+
+[[answer]]
+name = "b.example.com"
+type = "A"
+records = ["192.0.2.2"]
+
+[[answer]]
+name = "b.example.com"
+type = "TXT"
+records = ['"b"']
+"#;
+
+fn assert_shareable<T: Clone + Send + Sync>() {}
+
+/// Resident memory of the process in pages, on Linux
+fn resident_pages() -> Option<usize> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    statm.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Send `QUERIES` queries from each of `THREADS` threads through clones of `client`, checking
+/// that every one got the answer to its own question
+fn wave(client: &DnsClient) {
+    thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let client = client.clone();
+            scope.spawn(move || {
+                for query in 0..QUERIES {
+                    let (name, record_type, expected) = match (thread + query) % 4 {
+                        0 => ("a.example.com.", RecordType::A, Some("192.0.2.1")),
+                        1 => ("b.example.com.", RecordType::A, Some("192.0.2.2")),
+                        2 => ("b.example.com.", RecordType::TXT, Some("b")),
+                        _ => ("missing.example.com.", RecordType::A, None),
+                    };
+                    let name = Name::from_str(name).unwrap();
+                    let response = match query % 2 {
+                        0 => client.query(&name, DNSClass::IN, record_type),
+                        _ => client.query_uncached(&name, DNSClass::IN, record_type),
+                    }
+                    .unwrap();
+                    assert_eq!(response.queries()[0].name(), &name);
+                    assert_eq!(response.queries()[0].query_type(), record_type);
+                    let data: Vec<String> = response
+                        .answers()
+                        .iter()
+                        .filter_map(|record| record.data())
+                        .map(|data| match data {
+                            RData::TXT(txt) => txt.to_string(),
+                            data => data.to_string(),
+                        })
+                        .collect();
+                    match expected {
+                        Some(expected) => assert_eq!(data, [expected]),
+                        None => {
+                            assert_eq!(response.response_code(), ResponseCode::NXDomain);
+                            assert!(data.is_empty());
+                        },
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Response of the local servers to `query`, answering as `FIXTURE` does
+fn respond(query: &[u8]) -> Vec<u8> {
+    let query = Message::from_bytes(query).unwrap();
+    let mut response = query.clone();
+    response.set_message_type(MessageType::Response);
+    for question in query.queries() {
+        let data = match (question.name().to_ascii().as_str(), question.query_type()) {
+            ("a.example.com.", RecordType::A) => RData::A("192.0.2.1".parse().unwrap()),
+            ("b.example.com.", RecordType::A) => RData::A("192.0.2.2".parse().unwrap()),
+            ("b.example.com.", RecordType::TXT) => RData::TXT(TXT::new(vec![String::from("b")])),
+            _ => {
+                response.set_response_code(ResponseCode::NXDomain);
+                continue;
+            },
+        };
+        response.add_answer(Record::from_rdata(question.name().clone(), 60, data));
+    }
+    response.to_bytes().unwrap()
+}
+
+/// Server answering over UDP on a port of its own
+fn udp_server() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buffer = [0; 512];
+        while let Ok((length, client)) = socket.recv_from(&mut buffer) {
+            socket.send_to(&respond(&buffer[..length]), client).unwrap();
+        }
+    });
+    addr
+}
+
+/// Server answering over TCP on a port of its own, every connection on a thread of its own
+fn tcp_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || loop {
+                let mut length = [0; 2];
+                if stream.read_exact(&mut length).is_err() {
+                    break;
+                }
+                let mut query = vec![0; u16::from_be_bytes(length) as usize];
+                stream.read_exact(&mut query).unwrap();
+                let response = respond(&query);
+                let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+                framed.extend_from_slice(&response);
+                if stream.write_all(&framed).is_err() {
+                    break;
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Send waves of queries through `client`, checking that memory stops growing once the first
+/// one warmed it up, and that no question is left in flight
+fn stress(client: &DnsClient) {
+    wave(client);
+    let warmed_up = resident_pages();
+    for _ in 0..4 {
+        wave(client);
+    }
+    // Queries leave nothing behind in the client once answered
+    if let (Some(before), Some(after)) = (warmed_up, resident_pages()) {
+        let grown = after.saturating_sub(before);
+        assert!(grown < 4096, "{} pages more after 4 waves", grown);
+    }
+    assert_eq!(client.options().session().questions().in_flight(), 0);
+}
+
+#[test]
+fn thousands_of_concurrent_queries_share_one_client() {
+    assert_shareable::<DnsClient>();
+    let fixture = Fixture::from_toml(FIXTURE).unwrap();
+    let options =
+        ResolveOptions::new(ConnectionType::Udp, "192.0.2.53").with_mock(Some(Arc::new(fixture)));
+    stress(&DnsClient::new(&options).unwrap());
+}
+
+#[test]
+fn thousands_of_concurrent_queries_share_one_client_over_udp() {
+    let options = ResolveOptions::new(ConnectionType::Udp, &udp_server().to_string())
+        .with_timeout(Duration::from_secs(5));
+    stress(&DnsClient::new(&options).unwrap());
+}
+
+#[test]
+fn thousands_of_concurrent_queries_share_one_client_over_tcp() {
+    let options = ResolveOptions::new(ConnectionType::Tcp, &tcp_server().to_string())
+        .with_timeout(Duration::from_secs(5));
+    stress(&DnsClient::new(&options).unwrap());
+}
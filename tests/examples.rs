@@ -0,0 +1,206 @@
+//! The cookbook of `resoy examples`, every command of it run against mock answers so that the
+//! examples keep working as the options change
+
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+/// Answers to the questions that the examples ask
+const FIXTURE: &str = r#"
+[[answer]]
+name = "example.com"
+type = "SOA"
+records = ["ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 600"]
+
+[[answer]]
+name = "example.com"
+type = "A"
+records = ["93.184.215.14"]
+
+[[answer]]
+name = "example.com"
+type = "AAAA"
+records = ["2606:2800:21f:cb07:6820:80da:af6b:8b2c"]
+
+[[answer]]
+name = "example.com"
+type = "MX"
+records = ["10 mail.example.com."]
+
+[[answer]]
+name = "example.com"
+type = "NS"
+records = ["ns1.example.com."]
+
+[[answer]]
+name = "example.com"
+type = "TXT"
+records = ['"v=spf1 -all"', '"google-site-verification=abc"']
+
+[[answer]]
+name = "example.com"
+type = "CAA"
+records = ['0 issue "letsencrypt.org"']
+
+[[answer]]
+name = "mail.example.com"
+type = "A"
+records = ["93.184.215.25"]
+
+[[answer]]
+name = "www.example.com"
+type = "CNAME"
+records = ["example.com."]
+
+[[answer]]
+name = "web1.example.com"
+type = "A"
+records = ["192.0.2.1"]
+
+[[answer]]
+name = "web2.example.com"
+type = "A"
+records = ["192.0.2.2"]
+
+[[answer]]
+name = "_dmarc.example.com"
+type = "TXT"
+records = ['"v=DMARC1; p=reject; rua=mailto:dmarc@example.com"']
+
+[[answer]]
+name = "1.1.1.1.in-addr.arpa"
+type = "PTR"
+records = ["one.one.one.one."]
+
+[[answer]]
+name = "1.1.1.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.7.4.0.0.7.4.6.0.6.2.ip6.arpa"
+type = "PTR"
+records = ["one.one.one.one."]
+"#;
+
+/// Arguments of the examples that need the network or keep running, which mock answers
+/// can't stand in for
+const NOT_MOCKED: [&str; 7] = [
+    "--trace",
+    "--validate",
+    "--watch",
+    "--probe-targets",
+    "--check-filtering",
+    "doctor",
+    "proxy",
+];
+
+/// Subcommands, which take no --mock-answers
+const SUBCOMMANDS: [&str; 2] = ["types", "export-queries"];
+
+fn resoy(
+    directory: &Path,
+    args: &[String],
+) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_resoy"))
+        .args(args)
+        .current_dir(directory)
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap()
+}
+
+/// Arguments of a command line of the cookbook, unquoting the single quotes it puts around
+/// arguments with special characters and the escaped quotes in them
+fn split(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let (mut quoted, mut started) = (false, false);
+    let mut chars = command_line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => (quoted, started) = (!quoted, true),
+            '\\' if !quoted => arg.extend(chars.next()),
+            ' ' if !quoted => {
+                if started {
+                    args.push(std::mem::take(&mut arg));
+                }
+                started = false;
+            },
+            c => {
+                arg.push(c);
+                started = true;
+            },
+        }
+    }
+    if started {
+        args.push(arg);
+    }
+    args
+}
+
+/// Commands of the cookbook, each under its `# title` line
+fn cookbook(directory: &Path) -> Vec<(String, Vec<String>)> {
+    let output = resoy(directory, &[String::from("examples")]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines().filter(|line| !line.is_empty());
+    let mut examples = Vec::new();
+    while let (Some(title), Some(command_line)) = (lines.next(), lines.next()) {
+        let title = title.strip_prefix("# ").expect(title);
+        let args = split(command_line.strip_prefix("resoy ").expect(command_line));
+        examples.push((title.to_owned(), args));
+    }
+    examples
+}
+
+#[test]
+fn command_lines_are_split_as_a_shell_would() {
+    assert_eq!(
+        split("--format '{name} {data}' a 'it'\\''s' ''"),
+        ["--format", "{name} {data}", "a", "it's", ""]
+    );
+}
+
+#[test]
+fn every_example_runs_against_mock_answers() {
+    let directory = env::temp_dir().join(format!("resoy-examples-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("fixture.toml"), FIXTURE).unwrap();
+    fs::write(
+        directory.join("hosts.txt"),
+        "web1.example.com\nweb2.example.com\n",
+    )
+    .unwrap();
+
+    let examples = cookbook(&directory);
+    assert!(examples.len() > 20, "{:?}", examples);
+    let mut ran = 0;
+    for (title, args) in &examples {
+        if args.iter().any(|arg| NOT_MOCKED.contains(&arg.as_str())) {
+            continue;
+        }
+        let mut command: Vec<String> = Vec::new();
+        let subcommand = SUBCOMMANDS.contains(&args[0].as_str());
+        if !subcommand && !args.contains(&String::from("--mock-answers")) {
+            command.extend([String::from("--mock-answers"), String::from("fixture.toml")]);
+        }
+        command.extend(args.iter().cloned());
+        let output = resoy(&directory, &command);
+        assert!(
+            output.status.success() && !output.stdout.is_empty(),
+            "{}: resoy {:?} exited with {}\n{}{}",
+            title,
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        ran += 1;
+    }
+    fs::remove_dir_all(&directory).unwrap();
+    // Most of the cookbook doesn't need the network
+    assert!(
+        ran * 3 > examples.len() * 2,
+        "{} of {}",
+        ran,
+        examples.len()
+    );
+}
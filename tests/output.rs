@@ -0,0 +1,169 @@
+//! Output of the records in every format, compared with the snapshots under tests/snapshots
+
+use std::{env, fs, str::FromStr};
+
+use hickory_client::{
+    rr::{Name, RData, Record, RecordType},
+    serialize::txt::RDataParser,
+};
+use resoy::format::{self, OutputConfig, RecordFormatter, TableFormatter};
+
+fn record(
+    name: &str,
+    record_type: RecordType,
+    ttl: u32,
+    data: &str,
+) -> Record {
+    let data = RData::try_from_str(record_type, data).unwrap();
+    Record::from_rdata(Name::from_str(name).unwrap(), ttl, data)
+}
+
+/// Records of the types worth a look, with names and strings that need escaping
+fn records() -> Vec<Record> {
+    vec![
+        record("example.com.", RecordType::A, 300, "192.0.2.1"),
+        record("example.com.", RecordType::A, 300, "192.0.2.10"),
+        record("example.com.", RecordType::AAAA, 300, "2001:db8::1"),
+        record("www.example.com.", RecordType::CNAME, 86400, "example.com."),
+        record("example.com.", RecordType::MX, 3600, "10 mail.example.com."),
+        record(
+            "example.com.",
+            RecordType::MX,
+            3600,
+            "20 xn--mnchen-3ya.de.",
+        ),
+        record("example.com.", RecordType::TXT, 90061, "\"v=spf1 -all\""),
+        record(
+            "example.com.",
+            RecordType::TXT,
+            60,
+            "\"a, \\\"quoted\\\"\" \"second\"",
+        ),
+        record(
+            "example.com.",
+            RecordType::TXT,
+            60,
+            "\"back\\\\slash\\009tab\"",
+        ),
+        record(
+            "example.com.",
+            RecordType::SOA,
+            600,
+            "ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 600",
+        ),
+        record("example.com.", RecordType::NS, 172800, "ns1.example.com."),
+        record(
+            "example.com.",
+            RecordType::CAA,
+            300,
+            "0 issue \"letsencrypt.org\"",
+        ),
+        record(
+            "_sip._tcp.example.com.",
+            RecordType::SRV,
+            300,
+            "10 60 5060 sip.example.com.",
+        ),
+        record(
+            "1.2.0.192.in-addr.arpa.",
+            RecordType::PTR,
+            0,
+            "example.com.",
+        ),
+        record("xn--mnchen-3ya.de.", RecordType::A, 5, "192.0.2.99"),
+    ]
+}
+
+/// Lines of the text output, padded to the widths of all of `records` as resoy prints them
+fn text(
+    config: &OutputConfig,
+    records: &[&Record],
+) -> String {
+    let widths = config.column_widths(records);
+    let mut out = String::new();
+    let mut line = String::new();
+    for record in records {
+        line.clear();
+        RecordFormatter::new(record, config)
+            .with_widths(widths)
+            .write(&mut line);
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn table(
+    formatter: TableFormatter,
+    records: &[&Record],
+) -> String {
+    let rows = formatter.format(records, &OutputConfig::new(true, false));
+    rows.iter().map(|row| format!("{}\n", row)).collect()
+}
+
+/// Compare `output` with the snapshot `file`, written again by running the tests with
+/// `UPDATE_SNAPSHOTS=1`
+fn assert_snapshot(
+    file: &str,
+    output: &str,
+) {
+    let path = format!("{}/tests/snapshots/{}", env!("CARGO_MANIFEST_DIR"), file);
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, output).unwrap();
+    }
+    let expected = fs::read_to_string(&path).unwrap();
+    assert!(
+        output == expected,
+        "the output of {} changed, check it and run the tests with UPDATE_SNAPSHOTS=1",
+        file
+    );
+}
+
+#[test]
+fn text_output_matches_the_snapshot() {
+    let records = records();
+    let records: Vec<&Record> = records.iter().collect();
+    assert_snapshot(
+        "records.txt",
+        &text(&OutputConfig::new(true, false), &records),
+    );
+    assert_snapshot(
+        "records-seconds.txt",
+        &text(&OutputConfig::new(false, false), &records),
+    );
+    assert_snapshot(
+        "records-ascii.txt",
+        &text(
+            &OutputConfig::new(true, false)
+                .with_ascii_names(true)
+                .with_txt_concat(true),
+            &records,
+        ),
+    );
+}
+
+#[test]
+fn colored_output_matches_the_snapshot() {
+    let records = records();
+    let records: Vec<&Record> = records.iter().collect();
+    assert_snapshot(
+        "records.ansi",
+        &text(&OutputConfig::new(true, true), &records),
+    );
+}
+
+#[test]
+fn json_output_matches_the_snapshot() {
+    let records = records();
+    let records: Vec<&Record> = records.iter().collect();
+    let json = serde_json::to_string_pretty(&format::json_records(&records)).unwrap();
+    assert_snapshot("records.json", &(json + "\n"));
+}
+
+#[test]
+fn csv_and_tsv_output_match_the_snapshots() {
+    let records = records();
+    let records: Vec<&Record> = records.iter().collect();
+    assert_snapshot("records.csv", &table(TableFormatter::Csv, &records));
+    assert_snapshot("records.tsv", &table(TableFormatter::Tsv, &records));
+}
@@ -0,0 +1,219 @@
+//! Queries over UDP against a responder on the loopback interface
+
+use std::{
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use hickory_client::{
+    op::{Message, MessageType, ResponseCode},
+    rr::{rdata::A, Name, RData, Record, RecordType},
+};
+use resoy::{resolve, AppError, ConnectionType, ResolveOptions};
+
+/// Responder bound to `addr`, answering every A query with 192.0.2.1
+fn responder(addr: &str) -> SocketAddr {
+    let socket = UdpSocket::bind(addr).unwrap();
+    let addr = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut buffer = [0; 512];
+        while let Ok((length, client)) = socket.recv_from(&mut buffer) {
+            let query = Message::from_vec(&buffer[..length]).unwrap();
+            let mut response = query.clone();
+            response
+                .set_message_type(MessageType::Response)
+                .set_response_code(ResponseCode::NoError);
+            for question in query.queries() {
+                response.add_answer(Record::from_rdata(
+                    question.name().clone(),
+                    60,
+                    RData::A(A::new(192, 0, 2, 1)),
+                ));
+            }
+            socket.send_to(&response.to_vec().unwrap(), client).unwrap();
+        }
+    });
+    addr
+}
+
+fn query(server: SocketAddr) -> Result<Vec<Record>, AppError> {
+    let options = ResolveOptions::new(ConnectionType::Udp, &server.to_string())
+        .with_timeout(Duration::from_secs(2));
+    resolve(
+        &Name::from_str("example.com.").unwrap(),
+        &[RecordType::A],
+        &options,
+    )
+}
+
+fn addresses(records: &[Record]) -> Vec<String> {
+    records
+        .iter()
+        .map(|record| record.data().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn queries_round_trip_over_ipv4() {
+    let records = query(responder("127.0.0.1:0")).unwrap();
+    assert_eq!(addresses(&records), ["192.0.2.1"]);
+    assert_eq!(records[0].name().to_string(), "example.com.");
+}
+
+#[test]
+fn queries_round_trip_over_ipv6() {
+    let records = query(responder("[::1]:0")).unwrap();
+    assert_eq!(addresses(&records), ["192.0.2.1"]);
+}
+
+#[test]
+fn silent_servers_time_out() {
+    // Bound, so that nothing answers with port unreachable, but never read
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let server = socket.local_addr().unwrap().to_string();
+    for retries in [0, 1] {
+        let options = ResolveOptions::new(ConnectionType::Udp, &server)
+            .with_timeout(Duration::from_millis(200))
+            .with_retries(retries);
+        let started = Instant::now();
+        let result = resolve(
+            &Name::from_str("example.com.").unwrap(),
+            &[RecordType::A],
+            &options,
+        );
+        // Every attempt waits the timeout, and nothing else does
+        let elapsed = started.elapsed();
+        let attempts = retries + 1;
+        assert!(
+            matches!(result, Err(AppError::QueryTimeout(_, _, count, _)) if count == attempts),
+            "{:?}",
+            result
+        );
+        assert!(
+            elapsed < Duration::from_millis(200) * attempts as u32 + Duration::from_millis(300)
+        );
+    }
+}
+
+#[test]
+fn queries_in_flight_end_at_the_deadline() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let options = ResolveOptions::new(
+        ConnectionType::Udp,
+        &socket.local_addr().unwrap().to_string(),
+    )
+    .with_timeout(Duration::from_secs(5));
+    let started = Instant::now();
+    options
+        .session()
+        .set_deadline(started, Duration::from_millis(300));
+    let name = Name::from_str("example.com.").unwrap();
+    let result = resolve(&name, &[RecordType::A], &options);
+    assert!(
+        matches!(result, Err(AppError::DeadlineExceeded(deadline)) if deadline == Duration::from_millis(300)),
+        "{:?}",
+        result
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+    // And the queries after it are refused at once
+    let result = resolve(&name, &[RecordType::A], &options);
+    assert!(matches!(result, Err(AppError::DeadlineExceeded(_))));
+    assert_eq!(
+        options.session().budget().summary(),
+        "1 queries sent (lookup 1)"
+    );
+}
+
+/// Bytes of a capture of `tests/fixtures/corrupt`, hex with `#` comments
+fn capture(hex: &str) -> Vec<u8> {
+    hex.lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+        .collect()
+}
+
+/// Responder answering every query with `bytes` under its ID, recording the ports the
+/// queries came from
+fn corrupt_responder(bytes: Vec<u8>) -> (SocketAddr, Arc<Mutex<Vec<u16>>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let ports = Arc::new(Mutex::new(Vec::new()));
+    let recorded = ports.clone();
+    thread::spawn(move || {
+        let mut query = [0; 512];
+        while let Ok((_, client)) = socket.recv_from(&mut query) {
+            recorded.lock().unwrap().push(client.port());
+            let mut response = bytes.clone();
+            response[..2].copy_from_slice(&query[..2]);
+            socket.send_to(&response, client).unwrap();
+        }
+    });
+    (addr, ports)
+}
+
+#[test]
+fn corrupt_responses_are_salvaged_from_the_source_port() {
+    let bytes = capture(include_str!("fixtures/corrupt/opt-overrun.hex"));
+    let (server, ports) = corrupt_responder(bytes);
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let options = || {
+        ResolveOptions::new(ConnectionType::Udp, &server.to_string())
+            .with_timeout(Duration::from_millis(300))
+            .with_retries(1)
+            .with_source(Some(Ipv4Addr::LOCALHOST.into()), Some(port))
+    };
+    let lenient = options();
+    let records = resolve(
+        &Name::from_str("example.com.").unwrap(),
+        &[RecordType::A],
+        &lenient,
+    )
+    .unwrap();
+    assert_eq!(addresses(&records), ["192.0.2.1"]);
+    assert_eq!(lenient.session().salvaged(), 1);
+    // hickory dropped the first response, and the second attempt kept it
+    assert_eq!(*ports.lock().unwrap(), [port, port]);
+
+    // Strict parsing leaves it at the timeouts
+    let strict = options().with_strict_parsing(true);
+    let result = resolve(
+        &Name::from_str("example.com.").unwrap(),
+        &[RecordType::A],
+        &strict,
+    );
+    assert!(
+        matches!(result, Err(AppError::QueryTimeout(..))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn queries_past_the_budget_are_refused() {
+    let options = ResolveOptions::new(ConnectionType::Udp, &responder("127.0.0.1:0").to_string())
+        .with_feature("test");
+    options.session().budget().limit(1);
+    let name = Name::from_str("example.com.").unwrap();
+    let records = resolve(&name, &[RecordType::A], &options).unwrap();
+    assert_eq!(addresses(&records), ["192.0.2.1"]);
+    let result = resolve(&name, &[RecordType::A], &options);
+    match result {
+        Err(AppError::BudgetExhausted(exhausted)) => {
+            assert_eq!(exhausted.feature, "test");
+            assert_eq!(exhausted.breakdown, "test 1");
+        },
+        result => panic!("{:?}", result),
+    }
+    assert_eq!(
+        options.session().budget().summary(),
+        "1 queries sent (test 1)"
+    );
+}